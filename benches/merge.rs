@@ -0,0 +1,41 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use loginus::{
+    generate::EntryGenerator,
+    journald::{Entry, JournalExportRead},
+    merge::{MultiRead, Order},
+};
+
+const ENTRIES: usize = 10_000;
+
+fn merge_all(streams: &[Vec<u8>]) -> usize {
+    let readers = streams
+        .iter()
+        .map(|s| JournalExportRead::new(&s[..]))
+        .collect();
+    let mut multi_read = MultiRead::new(readers, Order::Timestamp);
+    let mut bytes = 0;
+    while multi_read.parse_next().unwrap().is_some() {
+        bytes += multi_read.get_entry().as_bytes().len();
+    }
+    bytes
+}
+
+fn merge_throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group("merge");
+    for sources in [2, 8, 32] {
+        let streams: Vec<_> = (0..sources)
+            .map(|seed| EntryGenerator::new(seed).generate(ENTRIES / sources as usize))
+            .collect();
+        let len: usize = streams.iter().map(|s| s.len()).sum();
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(sources),
+            &streams,
+            |b, streams| b.iter(|| merge_all(streams)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, merge_throughput);
+criterion_main!(benches);
@@ -0,0 +1,40 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use loginus::{
+    generate::{EntryGenerator, Workload},
+    journald::{Entry, JournalExportRead},
+};
+
+const ENTRIES: usize = 10_000;
+
+fn parse_all(stream: &[u8]) -> usize {
+    let mut export_read = JournalExportRead::new(stream);
+    let mut fields = 0;
+    while export_read.parse_next().unwrap().is_some() {
+        fields += export_read.get_entry().iter().count();
+    }
+    fields
+}
+
+fn parse_throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for workload in [
+        Workload::Realistic,
+        Workload::StringHeavy,
+        Workload::BinaryHeavy,
+        Workload::ManySmallFields,
+    ] {
+        let stream = EntryGenerator::new(0)
+            .with_workload(workload)
+            .generate(ENTRIES);
+        group.throughput(Throughput::Bytes(stream.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{:?}", workload)),
+            &stream,
+            |b, stream| b.iter(|| parse_all(stream)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse_throughput);
+criterion_main!(benches);
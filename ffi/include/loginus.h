@@ -0,0 +1,111 @@
+#ifndef LOGINUS_H
+#define LOGINUS_H
+
+/* Generated by cbindgen from src/lib.rs; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/*
+ A reader of export entries; opaque to C.
+ */
+typedef struct LoginusReader LoginusReader;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ Opens the export file at the NUL-terminated `path`, decompressing it if
+ its extension is `.gz` or `.zst`. Returns NULL on error.
+
+ # Safety
+
+ `path` must point to a NUL-terminated string.
+ */
+struct LoginusReader *loginus_open(const char *path);
+
+/*
+ Reads the entries of the `len` bytes at `data`, which are copied.
+
+ # Safety
+
+ `data` must point to `len` readable bytes.
+ */
+struct LoginusReader *loginus_open_buffer(const uint8_t *data, size_t len);
+
+/*
+ Parses the next entry. Returns 1 if there is one, 0 at the end of the
+ input and -1 on error.
+
+ # Safety
+
+ `reader` must have been returned by one of the `loginus_open` functions
+ and not been freed.
+ */
+int loginus_parse_next(struct LoginusReader *reader);
+
+/*
+ Looks up the first field called `name` (NUL-terminated) in the current
+ entry and stores its value in `value` and `value_len`. Returns 1 if the
+ field exists, 0 otherwise.
+
+ # Safety
+
+ `reader` must be valid as for [loginus_parse_next], `name` must point to a
+ NUL-terminated string and `value` and `value_len` must be writable.
+ */
+int loginus_get_field(const struct LoginusReader *reader,
+                      const char *name,
+                      const uint8_t **value,
+                      size_t *value_len);
+
+/*
+ The number of fields of the current entry; 0 if there is none.
+
+ # Safety
+
+ `reader` must be valid as for [loginus_parse_next].
+ */
+size_t loginus_field_count(const struct LoginusReader *reader);
+
+/*
+ Stores the name and value of the field at `index` of the current entry.
+ Returns 1 if there is such a field, 0 otherwise.
+
+ # Safety
+
+ `reader` must be valid as for [loginus_parse_next] and the other pointers
+ must be writable.
+ */
+int loginus_field_at(const struct LoginusReader *reader,
+                     size_t index,
+                     const uint8_t **name,
+                     size_t *name_len,
+                     const uint8_t **value,
+                     size_t *value_len);
+
+/*
+ Closes the input and frees `reader`; NULL is ignored.
+
+ # Safety
+
+ `reader` must be NULL or valid as for [loginus_parse_next]; it must not
+ be used afterwards.
+ */
+void loginus_free(struct LoginusReader *reader);
+
+/*
+ The message of the last error on this thread, or NULL. The string is
+ valid until the next failing call on this thread.
+ */
+const char *loginus_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* LOGINUS_H */
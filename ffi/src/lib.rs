@@ -0,0 +1,279 @@
+//! C bindings for the export parser of loginus.
+//!
+//! The library is built as `libloginus_ffi.so` (and `.a`); its declarations
+//! are in `include/loginus.h`, which is generated from this file by
+//! `cbindgen`. A reader is used as follows:
+//!
+//! ```c
+//! LoginusReader *r = loginus_open("system.export.zst");
+//! if (!r) { fprintf(stderr, "%s\n", loginus_last_error()); return 1; }
+//! int res;
+//! while ((res = loginus_parse_next(r)) == 1) {
+//!     const uint8_t *msg; size_t len;
+//!     if (loginus_get_field(r, "MESSAGE", &msg, &len))
+//!         printf("%.*s\n", (int)len, msg);
+//! }
+//! loginus_free(r);
+//! ```
+//!
+//! Values are borrowed from the reader and are valid until the next call of
+//! `loginus_parse_next()` or `loginus_free()` on it; they are not
+//! NUL-terminated and may contain any bytes. A reader must not be used by
+//! several threads at the same time. Errors are reported by the return value
+//! and a message that `loginus_last_error()` returns on the same thread.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, CStr, CString},
+    io::{Cursor, Read},
+    path::Path,
+    ptr, slice,
+};
+
+use loginus::{
+    journald::{Entry, JournalExportRead},
+    source,
+};
+
+/// A reader of export entries; opaque to C.
+pub struct LoginusReader {
+    reader: JournalExportRead<Box<dyn Read>>,
+    has_entry: bool,
+}
+
+impl LoginusReader {
+    fn new(read: Box<dyn Read>) -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            reader: JournalExportRead::new(read),
+            has_entry: false,
+        }))
+    }
+
+    fn field(&self, index: usize) -> Option<(&[u8], &[u8])> {
+        if !self.has_entry {
+            return None;
+        }
+        let (name, value, _) = self.reader.get_entry().fields().nth(index)?;
+        Some((name, value))
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: impl ToString) {
+    let message = message.to_string().replace('\0', "");
+    LAST_ERROR.with(|e| *e.borrow_mut() = CString::new(message).ok());
+}
+
+/// Opens the export file at the NUL-terminated `path`, decompressing it if
+/// its extension is `.gz` or `.zst`. Returns NULL on error.
+///
+/// # Safety
+///
+/// `path` must point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn loginus_open(path: *const c_char) -> *mut LoginusReader {
+    let path = match CStr::from_ptr(path).to_str() {
+        Ok(path) => path,
+        Err(e) => {
+            set_last_error(e);
+            return ptr::null_mut();
+        }
+    };
+    match source::open(Path::new(path)) {
+        Ok(read) => LoginusReader::new(read),
+        Err(e) => {
+            set_last_error(format!("{}: {}", path, e));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Reads the entries of the `len` bytes at `data`, which are copied.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn loginus_open_buffer(data: *const u8, len: usize) -> *mut LoginusReader {
+    let data = if len == 0 {
+        vec![]
+    } else {
+        slice::from_raw_parts(data, len).to_vec()
+    };
+    LoginusReader::new(Box::new(Cursor::new(data)))
+}
+
+/// Parses the next entry. Returns 1 if there is one, 0 at the end of the
+/// input and -1 on error.
+///
+/// # Safety
+///
+/// `reader` must have been returned by one of the `loginus_open` functions
+/// and not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn loginus_parse_next(reader: *mut LoginusReader) -> c_int {
+    let reader = &mut *reader;
+    reader.has_entry = false;
+    match reader.reader.parse_next() {
+        Ok(Some(())) => {
+            reader.has_entry = true;
+            1
+        }
+        Ok(None) => 0,
+        Err(e) => {
+            set_last_error(e);
+            -1
+        }
+    }
+}
+
+/// Looks up the first field called `name` (NUL-terminated) in the current
+/// entry and stores its value in `value` and `value_len`. Returns 1 if the
+/// field exists, 0 otherwise.
+///
+/// # Safety
+///
+/// `reader` must be valid as for [loginus_parse_next], `name` must point to a
+/// NUL-terminated string and `value` and `value_len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn loginus_get_field(
+    reader: *const LoginusReader,
+    name: *const c_char,
+    value: *mut *const u8,
+    value_len: *mut usize,
+) -> c_int {
+    let reader = &*reader;
+    let name = CStr::from_ptr(name).to_bytes();
+    if !reader.has_entry {
+        return 0;
+    }
+    let found = reader
+        .reader
+        .get_entry()
+        .fields()
+        .find(|(n, _, _)| *n == name);
+    match found {
+        Some((_, v, _)) => {
+            *value = v.as_ptr();
+            *value_len = v.len();
+            1
+        }
+        None => 0,
+    }
+}
+
+/// The number of fields of the current entry; 0 if there is none.
+///
+/// # Safety
+///
+/// `reader` must be valid as for [loginus_parse_next].
+#[no_mangle]
+pub unsafe extern "C" fn loginus_field_count(reader: *const LoginusReader) -> usize {
+    let reader = &*reader;
+    if !reader.has_entry {
+        return 0;
+    }
+    reader.reader.get_entry().iter().count()
+}
+
+/// Stores the name and value of the field at `index` of the current entry.
+/// Returns 1 if there is such a field, 0 otherwise.
+///
+/// # Safety
+///
+/// `reader` must be valid as for [loginus_parse_next] and the other pointers
+/// must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn loginus_field_at(
+    reader: *const LoginusReader,
+    index: usize,
+    name: *mut *const u8,
+    name_len: *mut usize,
+    value: *mut *const u8,
+    value_len: *mut usize,
+) -> c_int {
+    match (*reader).field(index) {
+        Some((n, v)) => {
+            *name = n.as_ptr();
+            *name_len = n.len();
+            *value = v.as_ptr();
+            *value_len = v.len();
+            1
+        }
+        None => 0,
+    }
+}
+
+/// Closes the input and frees `reader`; NULL is ignored.
+///
+/// # Safety
+///
+/// `reader` must be NULL or valid as for [loginus_parse_next]; it must not
+/// be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn loginus_free(reader: *mut LoginusReader) {
+    if !reader.is_null() {
+        drop(Box::from_raw(reader));
+    }
+}
+
+/// The message of the last error on this thread, or NULL. The string is
+/// valid until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn loginus_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::CStr, ptr, slice};
+
+    use super::{
+        loginus_field_at, loginus_field_count, loginus_free, loginus_get_field, loginus_last_error,
+        loginus_open, loginus_open_buffer, loginus_parse_next,
+    };
+
+    #[test]
+    fn reads_entries_through_the_c_abi() {
+        let input = b"MESSAGE=hello\nDATA\n\x03\0\0\0\0\0\0\0a\nb\n\nMESSAGE=world\n\nBROKEN\n";
+        unsafe {
+            let r = loginus_open_buffer(input.as_ptr(), input.len());
+            assert_eq!(loginus_parse_next(r), 1);
+            let (mut value, mut len) = (ptr::null(), 0);
+            assert_eq!(
+                loginus_get_field(r, c"DATA".as_ptr(), &mut value, &mut len),
+                1
+            );
+            assert_eq!(slice::from_raw_parts(value, len), b"a\nb");
+            assert_eq!(
+                loginus_get_field(r, c"PID".as_ptr(), &mut value, &mut len),
+                0
+            );
+            assert_eq!(loginus_field_count(r), 2);
+            let (mut name, mut name_len) = (ptr::null(), 0);
+            assert_eq!(
+                loginus_field_at(r, 0, &mut name, &mut name_len, &mut value, &mut len),
+                1
+            );
+            assert_eq!(slice::from_raw_parts(name, name_len), b"MESSAGE");
+            assert_eq!(slice::from_raw_parts(value, len), b"hello");
+
+            assert_eq!(loginus_parse_next(r), 1);
+            assert_eq!(loginus_parse_next(r), -1);
+            assert_eq!(loginus_field_count(r), 0);
+            assert_eq!(
+                loginus_get_field(r, c"MESSAGE".as_ptr(), &mut value, &mut len),
+                0
+            );
+            assert!(!loginus_last_error().is_null());
+            loginus_free(r);
+
+            assert!(loginus_open(c"/nonexistent.export".as_ptr()).is_null());
+            let error = CStr::from_ptr(loginus_last_error()).to_str().unwrap();
+            assert!(error.starts_with("/nonexistent.export: "));
+        }
+    }
+}
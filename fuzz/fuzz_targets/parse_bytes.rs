@@ -0,0 +1,36 @@
+//! Feeds arbitrary bytes in chunks of arbitrary size into the sync reader. The
+//! reader must neither panic nor loop forever.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use loginus::{
+    config::JournalExportLimitsBuilder,
+    journald::{Entry, JournalExportRead},
+    testutil::ChunkedRead,
+};
+
+fuzz_target!(|input: (u8, &[u8])| {
+    let (chunk_size, data) = input;
+    let limits = JournalExportLimitsBuilder::new()
+        .with_initial_buf_size(16)
+        .with_max_buf_size(1 << 12)
+        .build();
+    let mut export_read =
+        JournalExportRead::new_with_limits(limits, ChunkedRead::new(data, chunk_size as usize + 1));
+
+    // Every entry consumes at least one byte of input.
+    for _ in 0..=data.len() {
+        match export_read.parse_next() {
+            Ok(Some(())) => {
+                let e = export_read.get_entry();
+                assert!(!e.as_bytes().is_empty());
+                for (name, _value, _typ) in e.iter() {
+                    assert!(!name.is_empty());
+                }
+            }
+            Ok(None) | Err(_) => return,
+        }
+    }
+    panic!("reader did not terminate");
+});
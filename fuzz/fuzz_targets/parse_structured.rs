@@ -0,0 +1,93 @@
+//! Serializes structurally valid entries, optionally mutates the resulting
+//! stream and feeds it into the sync reader. Unmutated streams must round-trip.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use loginus::{
+    journald::{Entry, JournalExportRead},
+    testutil::{write_binary, write_string, ChunkedRead},
+};
+
+#[derive(Arbitrary, Debug)]
+struct Field {
+    name: Vec<u8>,
+    value: Vec<u8>,
+    binary: bool,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Mutation {
+    pos: usize,
+    byte: u8,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    entries: Vec<Vec<Field>>,
+    mutations: Vec<Mutation>,
+    chunk_size: u8,
+}
+
+const NAME_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ_0123456789";
+
+fn field_name(raw: &[u8]) -> String {
+    let mut name = String::from("F");
+    for b in raw.iter().take(63) {
+        name.push(NAME_CHARS[*b as usize % NAME_CHARS.len()] as char);
+    }
+    name
+}
+
+fuzz_target!(|input: Input| {
+    let mut expected = vec![];
+    let mut stream = vec![];
+    for fields in input.entries.iter().filter(|f| !f.is_empty()) {
+        let mut entry = vec![];
+        for f in fields {
+            let name = field_name(&f.name);
+            let value = &f.value[..f.value.len().min(1 << 12)];
+            if f.binary || value.contains(&b'\n') {
+                write_binary(&mut stream, &name, value);
+            } else {
+                write_string(&mut stream, &name, value);
+            }
+            entry.push((name.into_bytes(), value.to_vec()));
+        }
+        stream.push(b'\n');
+        expected.push(entry);
+    }
+
+    let mutated = !input.mutations.is_empty() && !stream.is_empty();
+    for m in input.mutations.iter() {
+        let len = stream.len();
+        if len > 0 {
+            stream[m.pos % len] = m.byte;
+        }
+    }
+
+    let chunked = ChunkedRead::new(&stream, input.chunk_size as usize + 1);
+    let mut export_read = JournalExportRead::new(chunked);
+    let mut parsed = vec![];
+    for _ in 0..=stream.len() {
+        match export_read.parse_next() {
+            Ok(Some(())) => parsed.push(
+                export_read
+                    .get_entry()
+                    .iter()
+                    .map(|(name, value, _)| (name.to_vec(), value.to_vec()))
+                    .collect::<Vec<_>>(),
+            ),
+            Ok(None) => break,
+            Err(e) => {
+                assert!(mutated, "unmutated stream failed to parse: {:?}", e);
+                return;
+            }
+        }
+    }
+
+    if !mutated {
+        assert_eq!(parsed, expected);
+    }
+});
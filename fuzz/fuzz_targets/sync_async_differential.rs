@@ -0,0 +1,49 @@
+//! Parses the same bytes with the sync and the async reader and checks that
+//! both yield the same sequence of entries and errors.
+
+#![no_main]
+
+use futures::executor::block_on;
+use libfuzzer_sys::fuzz_target;
+use loginus::{
+    config::JournalExportLimitsBuilder,
+    journald::{Entry, JournalExportAsyncRead, JournalExportRead},
+    testutil::ChunkedRead,
+};
+
+fuzz_target!(|input: (u8, u8, &[u8])| {
+    let (sync_chunk, async_chunk, data) = input;
+    let limits = || {
+        JournalExportLimitsBuilder::new()
+            .with_initial_buf_size(16)
+            .with_max_buf_size(1 << 12)
+            .build()
+    };
+
+    let mut sync_read = JournalExportRead::new_with_limits(
+        limits(),
+        ChunkedRead::new(data, sync_chunk as usize + 1),
+    );
+    let mut async_read =
+        JournalExportAsyncRead::new(limits(), ChunkedRead::new(data, async_chunk as usize + 1));
+
+    for _ in 0..=data.len() {
+        let s = sync_read.parse_next();
+        let a = block_on(async_read.parse_next());
+        match (s, a) {
+            (Ok(Some(())), Ok(Some(()))) => {
+                assert_eq!(
+                    sync_read.get_entry().as_bytes(),
+                    async_read.get_entry().as_bytes()
+                );
+            }
+            (Ok(None), Ok(None)) => return,
+            (Err(s), Err(a)) => {
+                assert_eq!(format!("{:?}", s.kind()), format!("{:?}", a.kind()));
+                return;
+            }
+            (s, a) => panic!("sync: {:?}, async: {:?}", s, a),
+        }
+    }
+    panic!("readers did not terminate");
+});
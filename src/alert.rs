@@ -0,0 +1,376 @@
+//! Raise alerts when entries matching a rule exceed a threshold.
+//!
+//! [AlertRules] are described in TOML. A rule selects entries by a field
+//! regex and, with the `expr` feature, an expression; if more than
+//! `threshold` selected entries fall within `window_ms`, it raises an
+//! [Alert]. With `group_by`, every value of the field is counted separately:
+//!
+//! ```toml
+//! [[rules]]
+//! name = "unit-errors"
+//! where = "PRIORITY <= 3"
+//! group_by = "_SYSTEMD_UNIT"
+//! threshold = 10
+//! window_ms = 300000
+//! ```
+//!
+//! Time is measured by the `__REALTIME_TIMESTAMP` of the entries, so rules
+//! give the same alerts for an export file as for the live journal. Once a
+//! rule raised an alert, it counts anew.
+//!
+//! A [Webhook] posts alerts as JSON to an HTTP endpoint.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, BufRead, BufReader, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[cfg(feature = "expr")]
+use crate::expr::Predicate;
+use crate::{
+    journald::Entry,
+    transform::{FieldPattern, Filter},
+};
+
+#[derive(Error, Debug)]
+pub enum AlertError {
+    #[error("invalid alert rules: {0}")]
+    Config(#[from] toml::de::Error),
+    #[error("invalid regular expression: {0}")]
+    Regex(#[from] regex::Error),
+    #[cfg(feature = "expr")]
+    #[error("invalid expression: {0}")]
+    Expr(#[from] crate::expr::ExprError),
+    #[error("rule {0} needs both `field` and `regex`")]
+    IncompleteFilter(String),
+    #[error("invalid webhook URL, expected http://HOST[:PORT][/PATH]: {0}")]
+    Url(String),
+}
+
+impl From<AlertError> for io::Error {
+    fn from(value: AlertError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, value)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AlertConfig {
+    pub rules: Vec<RuleConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    pub name: String,
+    /// Select entries with a field matching `regex`.
+    pub field: Option<String>,
+    pub regex: Option<String>,
+    #[serde(default)]
+    pub invert: bool,
+    /// Select entries by an expression; see [crate::expr].
+    #[cfg(feature = "expr")]
+    #[serde(rename = "where")]
+    pub expr: Option<String>,
+    /// Count every value of this field separately.
+    pub group_by: Option<String>,
+    /// Raise an alert once more than this many entries were selected within
+    /// the window.
+    #[serde(default)]
+    pub threshold: u64,
+    /// Defaults to one minute.
+    #[serde(default = "default_window")]
+    pub window_ms: u64,
+}
+
+fn default_window() -> u64 {
+    60_000
+}
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct Alert {
+    pub rule: String,
+    /// The value of the rule's `group_by` field.
+    pub key: Option<String>,
+    /// The number of entries selected within the window.
+    pub matches: u64,
+    /// The time of the first and the last of these entries.
+    pub first_timestamp: u64,
+    pub timestamp: u64,
+    /// The message of the last entry.
+    pub message: Option<String>,
+}
+
+struct Rule {
+    name: String,
+    filter: Option<Filter>,
+    #[cfg(feature = "expr")]
+    predicate: Option<Predicate>,
+    group_by: Option<Vec<u8>>,
+    threshold: u64,
+    /// The window in microseconds.
+    window: u64,
+    /// The times of the selected entries within the window, per key.
+    matches: HashMap<Vec<u8>, VecDeque<u64>>,
+}
+
+impl Rule {
+    fn new(config: &RuleConfig) -> Result<Self, AlertError> {
+        let filter = match (&config.field, &config.regex) {
+            (Some(field), Some(regex)) => Some(Filter {
+                field: FieldPattern::new(field.as_str()),
+                regex: Regex::new(regex)?,
+                invert: config.invert,
+            }),
+            (None, None) => None,
+            _ => return Err(AlertError::IncompleteFilter(config.name.clone())),
+        };
+        Ok(Self {
+            name: config.name.clone(),
+            filter,
+            #[cfg(feature = "expr")]
+            predicate: config.expr.as_deref().map(Predicate::new).transpose()?,
+            group_by: config.group_by.as_ref().map(|f| f.clone().into_bytes()),
+            threshold: config.threshold,
+            window: config.window_ms.saturating_mul(1000),
+            matches: HashMap::new(),
+        })
+    }
+
+    fn selects(&self, entry: &impl Entry) -> bool {
+        if self.filter.as_ref().is_some_and(|f| !f.selects(entry)) {
+            return false;
+        }
+        #[cfg(feature = "expr")]
+        if self.predicate.as_ref().is_some_and(|p| !p.selects(entry)) {
+            return false;
+        }
+        true
+    }
+
+    fn check(&mut self, entry: &impl Entry, now: u64) -> Option<Alert> {
+        if !self.selects(entry) {
+            return None;
+        }
+        let key = self.group_by.as_ref().and_then(|f| entry.get(f));
+        let times = self
+            .matches
+            .entry(key.unwrap_or_default().to_vec())
+            .or_default();
+        times.push_back(now);
+        while times
+            .front()
+            .is_some_and(|&t| now.saturating_sub(t) > self.window)
+        {
+            times.pop_front();
+        }
+        if times.len() as u64 <= self.threshold {
+            return None;
+        }
+        let string = |v: &[u8]| String::from_utf8_lossy(v).into_owned();
+        let alert = Alert {
+            rule: self.name.clone(),
+            key: key.map(string),
+            matches: times.len() as u64,
+            first_timestamp: times.front().copied().unwrap_or(now),
+            timestamp: now,
+            message: entry.get(b"MESSAGE").map(string),
+        };
+        times.clear();
+        Some(alert)
+    }
+}
+
+pub struct AlertRules {
+    rules: Vec<Rule>,
+    now: u64,
+}
+
+impl AlertRules {
+    pub fn new(config: &AlertConfig) -> Result<Self, AlertError> {
+        Ok(Self {
+            rules: config
+                .rules
+                .iter()
+                .map(Rule::new)
+                .collect::<Result<_, _>>()?,
+            now: 0,
+        })
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self, AlertError> {
+        Self::new(&toml::from_str(s)?)
+    }
+
+    /// Counts `entry` towards the rules that select it and returns the alerts
+    /// it raises. Entries without timestamp count at the time of the
+    /// previous entry.
+    pub fn check(&mut self, entry: &impl Entry) -> Vec<Alert> {
+        self.now = entry.realtime_timestamp().unwrap_or(self.now);
+        let now = self.now;
+        self.rules
+            .iter_mut()
+            .filter_map(|rule| rule.check(entry, now))
+            .collect()
+    }
+}
+
+/// Posts alerts as JSON to an HTTP endpoint.
+pub struct Webhook {
+    host: String,
+    port: u16,
+    path: String,
+    timeout: Duration,
+}
+
+impl Webhook {
+    pub fn new(url: &str) -> Result<Self, AlertError> {
+        let invalid = || AlertError::Url(url.to_string());
+        let rest = url.strip_prefix("http://").ok_or_else(invalid)?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+            timeout: Duration::from_secs(10),
+        })
+    }
+
+    /// Gives up on requests that take longer than `timeout`; defaults to ten
+    /// seconds.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    pub fn send(&self, alert: &Alert) -> io::Result<()> {
+        let body = serde_json::to_vec(alert)?;
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port))?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n",
+            self.path,
+            self.host,
+            self.port,
+            body.len()
+        )?;
+        stream.write_all(&body)?;
+        let mut status = String::new();
+        BufReader::new(stream).read_line(&mut status)?;
+        match status.split_whitespace().nth(1) {
+            Some(code) if code.starts_with('2') => Ok(()),
+            _ => Err(io::Error::other(format!(
+                "webhook failed: {}",
+                status.trim_end()
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    use crate::{journald::JournalExportRead, testutil::write_string};
+
+    use super::{Alert, AlertRules, Webhook};
+
+    const RULES: &str = r#"
+        [[rules]]
+        name = "unit-errors"
+        field = "PRIORITY"
+        regex = "^[0-3]$"
+        group_by = "_SYSTEMD_UNIT"
+        threshold = 2
+        window_ms = 1000
+    "#;
+
+    #[test]
+    fn raises_alerts_per_key() {
+        let mut stream = vec![];
+        for (ms, unit, priority) in [
+            (0, "a", 3),
+            (100, "b", 3),
+            (200, "a", 6),
+            (300, "a", 2),
+            (2000, "a", 3),
+            (2100, "a", 3),
+            (2200, "b", 3),
+            (2300, "a", 3),
+        ] {
+            write_string(&mut stream, "__REALTIME_TIMESTAMP", (ms * 1000).to_string());
+            write_string(&mut stream, "_SYSTEMD_UNIT", unit);
+            write_string(&mut stream, "PRIORITY", priority.to_string());
+            write_string(&mut stream, "MESSAGE", format!("{} at {}", unit, ms));
+            stream.push(b'\n');
+        }
+
+        let mut rules = AlertRules::from_toml(RULES).unwrap();
+        let alerts: Vec<Alert> = JournalExportRead::new(&stream[..])
+            .flat_map(|e| rules.check(&e))
+            .collect();
+        assert_eq!(
+            alerts,
+            [Alert {
+                rule: "unit-errors".to_string(),
+                key: Some("a".to_string()),
+                matches: 3,
+                first_timestamp: 2_000_000,
+                timestamp: 2_300_000,
+                message: Some("a at 2300".to_string()),
+            }]
+        );
+        assert!(AlertRules::from_toml("[[rules]]\nname = \"x\"\nfield = \"A\"").is_err());
+    }
+
+    #[test]
+    fn posts_to_webhooks() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buf = [0; 4096];
+            while !request.ends_with(b"}") {
+                let n = conn.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            conn.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        let alert = Alert {
+            rule: "r".to_string(),
+            key: None,
+            matches: 1,
+            first_timestamp: 1,
+            timestamp: 1,
+            message: None,
+        };
+        Webhook::new(&url).unwrap().send(&alert).unwrap();
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /hook HTTP/1.1\r\n"));
+        assert!(request.ends_with(r#"{"rule":"r","key":null,"matches":1,"first_timestamp":1,"timestamp":1,"message":null}"#));
+        assert!(Webhook::new("https://example.com").is_err());
+    }
+}
@@ -0,0 +1,403 @@
+//! Summarize authentication events.
+//!
+//! [AuthAnalyzer] reads the messages of sshd, sudo, PAM and the shadow
+//! utilities and collects them into an [AuthReport]: the failed and
+//! successful logins per user and remote address, brute-force [Burst]s of
+//! failures from one address, the commands run with sudo and the users that
+//! were created or deleted. As an [EntrySink], it can be fed the output of a
+//! pipeline that selects the entries to analyze.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io,
+    time::Duration,
+};
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{
+    journald::{Entry, JournalExportRead},
+    sink::EntrySink,
+};
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct LoginStats {
+    pub user: String,
+    /// The remote address, if the login was over the network.
+    pub address: Option<String>,
+    pub failed: u64,
+    pub succeeded: u64,
+    pub first_timestamp: Option<u64>,
+    pub last_timestamp: Option<u64>,
+}
+
+/// A run of failed logins from one address.
+#[derive(Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct Burst {
+    pub address: String,
+    pub start: u64,
+    pub end: u64,
+    pub failures: u64,
+    /// The distinct users tried.
+    pub users: BTreeSet<String>,
+}
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct SudoEvent {
+    pub timestamp: Option<u64>,
+    pub user: String,
+    /// The user the command was run as.
+    pub target: Option<String>,
+    pub command: Option<String>,
+    /// Whether sudo refused to run the command, e.g. after wrong passwords.
+    pub denied: bool,
+}
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum UserEventKind {
+    Added,
+    Deleted,
+}
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct UserEvent {
+    pub kind: UserEventKind,
+    pub timestamp: Option<u64>,
+    pub user: String,
+    pub uid: Option<u64>,
+}
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct AuthReport {
+    /// Ordered by the number of failures, most first.
+    pub logins: Vec<LoginStats>,
+    /// Ordered by start.
+    pub bursts: Vec<Burst>,
+    pub sudo: Vec<SudoEvent>,
+    pub users: Vec<UserEvent>,
+}
+
+/// The outcome of a login attempt.
+enum Login {
+    Failed,
+    Succeeded,
+}
+
+pub struct AuthAnalyzer {
+    ssh_failed: Regex,
+    ssh_invalid: Regex,
+    ssh_accepted: Regex,
+    pam_failure: Regex,
+    sudo: Regex,
+    user_added: Regex,
+    user_deleted: Regex,
+    burst_threshold: u64,
+    burst_window: u64,
+    logins: BTreeMap<(String, Option<String>), LoginStats>,
+    /// The run of failures of each address that is still open.
+    runs: HashMap<String, Burst>,
+    report: AuthReport,
+}
+
+impl Default for AuthAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AuthAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            ssh_failed: Regex::new(
+                r"^Failed \S+ for (?:invalid user )?(\S*) from (\S+) port \d+",
+            )
+            .unwrap(),
+            ssh_invalid: Regex::new(r"^Invalid user (\S*) from (\S+)").unwrap(),
+            ssh_accepted: Regex::new(r"^Accepted \S+ for (\S+) from (\S+) port \d+").unwrap(),
+            pam_failure: Regex::new(
+                r"^pam_unix\(([^:)]+):auth\): authentication failure;.*?(?:rhost=(\S*))?\s+user=(\S+)",
+            )
+            .unwrap(),
+            sudo: Regex::new(r"^\s*(\S+) : (?:(.*?) ; )?TTY=.*?(?:; USER=(\S+) )?(?:; COMMAND=(.*))?$")
+                .unwrap(),
+            user_added: Regex::new(r"^new user: name=([^,]+), UID=(\d+)").unwrap(),
+            user_deleted: Regex::new(r"^delete user '([^']+)'").unwrap(),
+            burst_threshold: 10,
+            burst_window: 60_000_000,
+            logins: BTreeMap::new(),
+            runs: HashMap::new(),
+            report: AuthReport::default(),
+        }
+    }
+
+    /// Reports runs of at least `threshold` failed logins from one address,
+    /// with less than `window` between consecutive failures. By default, 10
+    /// failures with less than a minute between them.
+    pub fn with_burst(self, threshold: u64, window: Duration) -> Self {
+        Self {
+            burst_threshold: threshold,
+            burst_window: window.as_micros() as u64,
+            ..self
+        }
+    }
+
+    pub fn push(&mut self, entry: &impl Entry) {
+        let (Some(identifier), Some(message)) =
+            (entry.get(b"SYSLOG_IDENTIFIER"), entry.get(b"MESSAGE"))
+        else {
+            return;
+        };
+        let identifier = String::from_utf8_lossy(identifier);
+        let message = String::from_utf8_lossy(message);
+        let timestamp = entry.realtime_timestamp();
+
+        if identifier.starts_with("sshd") {
+            let login = if let Some(c) = self.ssh_failed.captures(&message) {
+                Some((Login::Failed, c))
+            } else if let Some(c) = self.ssh_invalid.captures(&message) {
+                Some((Login::Failed, c))
+            } else {
+                self.ssh_accepted
+                    .captures(&message)
+                    .map(|c| (Login::Succeeded, c))
+            };
+            if let Some((login, c)) = login {
+                let (user, address) = (c[1].to_string(), c[2].to_string());
+                self.login(login, user, Some(address), timestamp);
+            }
+        } else if let Some(c) = self.pam_failure.captures(&message) {
+            // sshd reports its failures itself.
+            if !c[1].starts_with("sshd") {
+                let address = c.get(2).map(|a| a.as_str().to_string());
+                let address = address.filter(|a| !a.is_empty());
+                self.login(Login::Failed, c[3].to_string(), address, timestamp);
+            }
+        } else if identifier == "sudo" {
+            if let Some(c) = self.sudo.captures(&message) {
+                let denied = c.get(2).is_some_and(|reason| {
+                    let reason = reason.as_str();
+                    reason.contains("incorrect password")
+                        || reason.contains("NOT in sudoers")
+                        || reason.contains("not allowed")
+                });
+                self.report.sudo.push(SudoEvent {
+                    timestamp,
+                    user: c[1].to_string(),
+                    target: c.get(3).map(|t| t.as_str().to_string()),
+                    command: c.get(4).map(|t| t.as_str().to_string()),
+                    denied,
+                });
+            }
+        } else if let Some(c) = self.user_added.captures(&message) {
+            self.report.users.push(UserEvent {
+                kind: UserEventKind::Added,
+                timestamp,
+                user: c[1].to_string(),
+                uid: c[2].parse().ok(),
+            });
+        } else if let Some(c) = self.user_deleted.captures(&message) {
+            self.report.users.push(UserEvent {
+                kind: UserEventKind::Deleted,
+                timestamp,
+                user: c[1].to_string(),
+                uid: None,
+            });
+        }
+    }
+
+    fn login(&mut self, login: Login, user: String, address: Option<String>, ts: Option<u64>) {
+        let stats = self
+            .logins
+            .entry((user.clone(), address.clone()))
+            .or_insert_with(|| LoginStats {
+                user: user.clone(),
+                address: address.clone(),
+                ..LoginStats::default()
+            });
+        if let Some(ts) = ts {
+            stats.first_timestamp = Some(stats.first_timestamp.map_or(ts, |t| t.min(ts)));
+            stats.last_timestamp = Some(stats.last_timestamp.map_or(ts, |t| t.max(ts)));
+        }
+        match login {
+            Login::Succeeded => stats.succeeded += 1,
+            Login::Failed => stats.failed += 1,
+        }
+        if let (Login::Failed, Some(address), Some(ts)) = (login, address, ts) {
+            let run = self.runs.remove(&address).and_then(|run| {
+                match ts.abs_diff(run.end) < self.burst_window {
+                    true => Some(run),
+                    false => {
+                        self.finish_run(run);
+                        None
+                    }
+                }
+            });
+            let mut run = run.unwrap_or_else(|| Burst {
+                address: address.clone(),
+                start: ts,
+                end: ts,
+                failures: 0,
+                users: BTreeSet::new(),
+            });
+            run.start = run.start.min(ts);
+            run.end = run.end.max(ts);
+            run.failures += 1;
+            run.users.insert(user);
+            self.runs.insert(address, run);
+        }
+    }
+
+    fn finish_run(&mut self, run: Burst) {
+        if run.failures >= self.burst_threshold {
+            self.report.bursts.push(run);
+        }
+    }
+
+    pub fn into_report(mut self) -> AuthReport {
+        for (_, run) in std::mem::take(&mut self.runs) {
+            self.finish_run(run);
+        }
+        let mut report = self.report;
+        report.logins = self.logins.into_values().collect();
+        report.logins.sort_by_key(|l| std::cmp::Reverse(l.failed));
+        report.bursts.sort_by_key(|b| b.start);
+        report
+    }
+}
+
+impl EntrySink for AuthAnalyzer {
+    fn write_entry(&mut self, entry: &[u8]) -> io::Result<()> {
+        let mut reader = JournalExportRead::new(entry);
+        if reader.parse_next().map_err(io::Error::other)?.is_some() {
+            self.push(&reader.get_entry());
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{journald::JournalExportRead, testutil::write_string};
+
+    use super::{AuthAnalyzer, UserEventKind};
+
+    #[test]
+    fn summarizes_logins() {
+        let mut messages = vec![];
+        for i in 0..4 {
+            messages.push((
+                100 + i,
+                "sshd",
+                format!(
+                    "Failed password for invalid user admin{} from 203.0.113.5 port 4{} ssh2",
+                    i, i
+                ),
+            ));
+        }
+        messages.extend([
+            (
+                200,
+                "sshd",
+                "Failed password for root from 203.0.113.5 port 5 ssh2".to_string(),
+            ),
+            (
+                300,
+                "sshd-session",
+                "Accepted publickey for alice from 192.0.2.1 port 22 ssh2: ED25519 SHA256:x"
+                    .to_string(),
+            ),
+            (
+                310,
+                "sshd",
+                "pam_unix(sshd:auth): authentication failure; logname= uid=0 euid=0 tty=ssh ruser= rhost=203.0.113.5  user=root".to_string(),
+            ),
+            (
+                320,
+                "su",
+                "pam_unix(su:auth): authentication failure; logname=alice uid=1000 euid=0 tty=pts/0 ruser=alice rhost=  user=root".to_string(),
+            ),
+            (
+                330,
+                "sudo",
+                "   alice : TTY=pts/0 ; PWD=/home/alice ; USER=root ; COMMAND=/usr/bin/apt update"
+                    .to_string(),
+            ),
+            (
+                340,
+                "sudo",
+                "     bob : 3 incorrect password attempts ; TTY=pts/1 ; PWD=/home/bob ; USER=root ; COMMAND=/bin/sh".to_string(),
+            ),
+            (
+                350,
+                "useradd",
+                "new user: name=mallory, UID=1002, GID=1002, home=/home/mallory, shell=/bin/bash, from=/dev/pts/0".to_string(),
+            ),
+        ]);
+        let mut stream = vec![];
+        for (ts, identifier, message) in messages {
+            write_string(&mut stream, "__REALTIME_TIMESTAMP", ts.to_string());
+            write_string(&mut stream, "SYSLOG_IDENTIFIER", identifier);
+            write_string(&mut stream, "MESSAGE", message);
+            stream.push(b'\n');
+        }
+
+        let mut analyzer = AuthAnalyzer::new().with_burst(4, Duration::from_micros(50));
+        for e in JournalExportRead::new(&stream[..]) {
+            analyzer.push(&e);
+        }
+        let report = analyzer.into_report();
+
+        let logins: Vec<_> = report
+            .logins
+            .iter()
+            .map(|l| (l.user.as_str(), l.address.as_deref(), l.failed, l.succeeded))
+            .collect();
+        assert_eq!(logins.len(), 7);
+        assert!(logins.contains(&("root", Some("203.0.113.5"), 1, 0)));
+        assert!(logins.contains(&("alice", Some("192.0.2.1"), 0, 1)));
+        assert!(logins.contains(&("root", None, 1, 0)));
+
+        // The failure at 200 is too late to extend the run.
+        assert_eq!(report.bursts.len(), 1);
+        let burst = &report.bursts[0];
+        assert_eq!((burst.start, burst.end, burst.failures), (100, 103, 4));
+        assert_eq!(burst.users.len(), 4);
+
+        let sudo: Vec<_> = report
+            .sudo
+            .iter()
+            .map(|s| {
+                (
+                    s.user.as_str(),
+                    s.target.as_deref(),
+                    s.command.as_deref(),
+                    s.denied,
+                )
+            })
+            .collect();
+        assert_eq!(
+            sudo,
+            [
+                ("alice", Some("root"), Some("/usr/bin/apt update"), false),
+                ("bob", Some("root"), Some("/bin/sh"), true),
+            ]
+        );
+        assert_eq!(report.users.len(), 1);
+        assert_eq!(
+            (
+                report.users[0].kind,
+                report.users[0].user.as_str(),
+                report.users[0].uid
+            ),
+            (UserEventKind::Added, "mallory", Some(1002))
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! List the boots contained in a stream.
+//!
+//! [BootList] collects the distinct `_BOOT_ID`s of a stream with their time
+//! ranges and entry counts, like `journalctl --list-boots`. A
+//! [BootSelector] picks one of them by ID or by offset: `0` is the last boot,
+//! `-1` the one before, and positive offsets count from the first boot,
+//! which is `1`.
+
+use std::{collections::HashMap, str::FromStr};
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::journald::Entry;
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct Boot {
+    pub id: String,
+    pub entries: u64,
+    pub first_timestamp: Option<u64>,
+    pub last_timestamp: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct BootList {
+    boots: Vec<Boot>,
+    index: HashMap<Vec<u8>, usize>,
+}
+
+impl BootList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts `entry` towards its boot; entries without `_BOOT_ID` are
+    /// ignored.
+    pub fn push(&mut self, entry: &impl Entry) {
+        let Some(id) = entry.get(b"_BOOT_ID") else {
+            return;
+        };
+        let i = match self.index.get(id) {
+            Some(&i) => i,
+            None => {
+                self.index.insert(id.to_vec(), self.boots.len());
+                self.boots.push(Boot {
+                    id: String::from_utf8_lossy(id).into_owned(),
+                    entries: 0,
+                    first_timestamp: None,
+                    last_timestamp: None,
+                });
+                self.boots.len() - 1
+            }
+        };
+        let boot = &mut self.boots[i];
+        boot.entries += 1;
+        if let Some(ts) = entry.realtime_timestamp() {
+            boot.first_timestamp = Some(boot.first_timestamp.map_or(ts, |t| t.min(ts)));
+            boot.last_timestamp = Some(boot.last_timestamp.map_or(ts, |t| t.max(ts)));
+        }
+    }
+
+    /// The boots ordered by their first entry; boots without timestamps come
+    /// last.
+    pub fn into_boots(self) -> Vec<Boot> {
+        let mut boots = self.boots;
+        boots.sort_by_key(|b| (b.first_timestamp.is_none(), b.first_timestamp));
+        boots
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum BootSelector {
+    Id(String),
+    Offset(i64),
+}
+
+#[derive(Error, Debug)]
+#[error("expected a boot ID or offset: {0}")]
+pub struct InvalidBootSelector(String);
+
+impl FromStr for BootSelector {
+    type Err = InvalidBootSelector;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() == 32 && s.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return Ok(BootSelector::Id(s.to_ascii_lowercase()));
+        }
+        s.parse()
+            .map(BootSelector::Offset)
+            .map_err(|_| InvalidBootSelector(s.to_string()))
+    }
+}
+
+impl BootSelector {
+    /// The selected boot among `boots`, which are ordered as by
+    /// [BootList::into_boots].
+    pub fn resolve<'a>(&self, boots: &'a [Boot]) -> Option<&'a Boot> {
+        match *self {
+            BootSelector::Id(ref id) => boots.iter().find(|b| b.id == *id),
+            BootSelector::Offset(n) if n <= 0 => boots
+                .len()
+                .checked_sub(1 + n.unsigned_abs() as usize)
+                .map(|i| &boots[i]),
+            BootSelector::Offset(n) => boots.get(n as usize - 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{journald::JournalExportRead, testutil::write_string};
+
+    use super::{BootList, BootSelector};
+
+    #[test]
+    fn lists_and_selects_boots() {
+        let a = "a".repeat(32);
+        let b = "b".repeat(32);
+        let mut stream = vec![];
+        for (ts, boot) in [(30, &b), (10, &a), (20, &a), (40, &b), (50, &b)] {
+            write_string(&mut stream, "__REALTIME_TIMESTAMP", ts.to_string());
+            write_string(&mut stream, "_BOOT_ID", boot);
+            stream.push(b'\n');
+        }
+        write_string(&mut stream, "MESSAGE", "no boot");
+        stream.push(b'\n');
+
+        let mut list = BootList::new();
+        for e in JournalExportRead::new(&stream[..]) {
+            list.push(&e);
+        }
+        let boots = list.into_boots();
+        let summary: Vec<_> = boots
+            .iter()
+            .map(|b| {
+                (
+                    b.id.as_str(),
+                    b.entries,
+                    b.first_timestamp,
+                    b.last_timestamp,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (a.as_str(), 2, Some(10), Some(20)),
+                (b.as_str(), 3, Some(30), Some(50))
+            ]
+        );
+
+        let select = |s: &str| s.parse::<BootSelector>().unwrap().resolve(&boots);
+        assert_eq!(select("0").unwrap().id, b);
+        assert_eq!(select("-1").unwrap().id, a);
+        assert_eq!(select("1").unwrap().id, a);
+        assert_eq!(select(&b.to_uppercase()).unwrap().id, b);
+        assert!(select("-2").is_none());
+        assert!(select("3").is_none());
+        assert!("latest".parse::<BootSelector>().is_err());
+    }
+}
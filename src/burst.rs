@@ -0,0 +1,272 @@
+//! Find the times when a unit flooded the journal.
+//!
+//! [BurstDetector] counts the entries of every key, by default the unit or
+//! else the syslog identifier, per second. Its baseline is the average rate
+//! of the key over the whole input. A token bucket per key, refilled at
+//! `factor` times the baseline and holding `window` seconds of that rate,
+//! then marks the seconds in which the bucket overflows; overflowing seconds
+//! less than `window` apart form a [KeyBurst]. The bursts with the most
+//! entries above the baseline are reported first.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::{
+    journald::{Entry, JournalExportRead},
+    sink::EntrySink,
+};
+
+/// The number of messages kept per key and second, and reported per burst.
+const SAMPLES: usize = 3;
+/// The length up to which sample messages are kept.
+const SAMPLE_LEN: usize = 200;
+
+#[derive(Serialize, PartialEq, Debug, Clone)]
+pub struct KeyBurst {
+    pub key: String,
+    /// In microseconds since the epoch.
+    pub start: u64,
+    /// Exclusive.
+    pub end: u64,
+    pub entries: u64,
+    /// Entries per second during the burst.
+    pub rate: f64,
+    /// Entries per second of the key over the whole input.
+    pub baseline: f64,
+    /// Distinct messages logged during the burst.
+    pub samples: Vec<String>,
+}
+
+impl KeyBurst {
+    /// The number of entries above the baseline.
+    pub fn excess(&self) -> f64 {
+        self.entries as f64 - self.baseline * (self.end - self.start) as f64 / 1e6
+    }
+}
+
+#[derive(Default)]
+struct Second {
+    entries: u64,
+    samples: Vec<String>,
+}
+
+pub struct BurstDetector {
+    keys: Vec<String>,
+    factor: f64,
+    window: u64,
+    min_entries: u64,
+    /// The entries of every key per second since the epoch.
+    seconds: HashMap<String, BTreeMap<u64, Second>>,
+    first_timestamp: Option<u64>,
+    last_timestamp: Option<u64>,
+}
+
+impl Default for BurstDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BurstDetector {
+    pub fn new() -> Self {
+        Self {
+            keys: vec!["_SYSTEMD_UNIT".to_string(), "SYSLOG_IDENTIFIER".to_string()],
+            factor: 5.0,
+            window: 10,
+            min_entries: 100,
+            seconds: HashMap::new(),
+            first_timestamp: None,
+            last_timestamp: None,
+        }
+    }
+
+    /// Groups entries by the value of `field` instead of their unit.
+    pub fn with_key(self, field: impl Into<String>) -> Self {
+        Self {
+            keys: vec![field.into()],
+            ..self
+        }
+    }
+
+    /// Reports rates above `factor` times the baseline. 5 by default.
+    pub fn with_factor(self, factor: f64) -> Self {
+        Self { factor, ..self }
+    }
+
+    /// How long a key may exceed its rate before it bursts, and the maximum
+    /// gap within a burst. 10 seconds by default.
+    pub fn with_window(self, window: Duration) -> Self {
+        Self {
+            window: window.as_secs().max(1),
+            ..self
+        }
+    }
+
+    /// Leaves out bursts of less than `min_entries` entries. 100 by default.
+    pub fn with_min_entries(self, min_entries: u64) -> Self {
+        Self {
+            min_entries,
+            ..self
+        }
+    }
+
+    pub fn push(&mut self, entry: &impl Entry) {
+        let Some(ts) = entry.realtime_timestamp() else {
+            return;
+        };
+        let Some(key) = self.keys.iter().find_map(|k| entry.get(k.as_bytes())) else {
+            return;
+        };
+        self.first_timestamp = Some(self.first_timestamp.map_or(ts, |t| t.min(ts)));
+        self.last_timestamp = Some(self.last_timestamp.map_or(ts, |t| t.max(ts)));
+        let key = String::from_utf8_lossy(key);
+        let seconds = match self.seconds.get_mut(key.as_ref()) {
+            Some(seconds) => seconds,
+            None => self.seconds.entry(key.into_owned()).or_default(),
+        };
+        let second = seconds.entry(ts / 1_000_000).or_default();
+        second.entries += 1;
+        if second.samples.len() < SAMPLES {
+            if let Some(message) = entry.get(b"MESSAGE") {
+                let message = String::from_utf8_lossy(message);
+                let end = message.floor_char_boundary(SAMPLE_LEN);
+                second.samples.push(message[..end].to_string());
+            }
+        }
+    }
+
+    /// The `top` bursts with the most entries above the baseline.
+    pub fn into_bursts(self, top: usize) -> Vec<KeyBurst> {
+        let (Some(first), Some(last)) = (self.first_timestamp, self.last_timestamp) else {
+            return vec![];
+        };
+        let span = ((last - first) as f64 / 1e6).max(1.0);
+        let mut bursts = vec![];
+        for (key, seconds) in self.seconds.iter() {
+            let total: u64 = seconds.values().map(|s| s.entries).sum();
+            let baseline = total as f64 / span;
+            let refill = self.factor * baseline;
+            let capacity = refill * self.window as f64;
+            let mut tokens = capacity;
+            let mut previous = None;
+            // The first and last overflowing second of the current run.
+            let mut run: Option<(u64, u64)> = None;
+            let mut runs = vec![];
+            for (&second, s) in seconds.iter() {
+                let elapsed = previous.map_or(0, |p| second - p);
+                previous = Some(second);
+                tokens = (tokens + elapsed as f64 * refill).min(capacity) - s.entries as f64;
+                if tokens >= 0.0 {
+                    continue;
+                }
+                tokens = 0.0;
+                run = match run {
+                    Some((start, end)) if second - end < self.window => Some((start, second)),
+                    _ => {
+                        runs.extend(run);
+                        Some((second, second))
+                    }
+                };
+            }
+            runs.extend(run);
+
+            for (start, end) in runs {
+                let mut entries = 0;
+                let mut samples: Vec<String> = vec![];
+                for (_, s) in seconds.range(start..=end) {
+                    entries += s.entries;
+                    for sample in s.samples.iter() {
+                        if samples.len() < SAMPLES && !samples.contains(sample) {
+                            samples.push(sample.clone());
+                        }
+                    }
+                }
+                if entries < self.min_entries {
+                    continue;
+                }
+                let duration = (end + 1 - start) as f64;
+                bursts.push(KeyBurst {
+                    key: key.clone(),
+                    start: start * 1_000_000,
+                    end: (end + 1) * 1_000_000,
+                    entries,
+                    rate: entries as f64 / duration,
+                    baseline,
+                    samples,
+                });
+            }
+        }
+        bursts.sort_by(|a, b| b.excess().total_cmp(&a.excess()));
+        bursts.truncate(top);
+        bursts
+    }
+}
+
+impl EntrySink for BurstDetector {
+    fn write_entry(&mut self, entry: &[u8]) -> io::Result<()> {
+        let mut reader = JournalExportRead::new(entry);
+        if reader.parse_next().map_err(io::Error::other)?.is_some() {
+            self.push(&reader.get_entry());
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{journald::JournalExportRead, testutil::write_string};
+
+    use super::BurstDetector;
+
+    #[test]
+    fn finds_floods() {
+        let mut stream = vec![];
+        let mut entry = |ts: u64, unit: &str, message: &str| {
+            write_string(&mut stream, "__REALTIME_TIMESTAMP", ts.to_string());
+            write_string(&mut stream, "_SYSTEMD_UNIT", unit);
+            write_string(&mut stream, "MESSAGE", message);
+            stream.push(b'\n');
+        };
+        // One entry per second for an hour, with 500 entries within 5
+        // seconds after 30 minutes.
+        for s in 0..3600u64 {
+            entry(s * 1_000_000, "steady.service", "tick");
+            entry(s * 1_000_000 + 1, "noisy.service", "ok");
+            if (1800..1805).contains(&s) {
+                for i in 0..100 {
+                    entry(
+                        s * 1_000_000 + 2 + i,
+                        "noisy.service",
+                        &format!("retry {}", i % 2),
+                    );
+                }
+            }
+        }
+
+        let mut detector = BurstDetector::new()
+            .with_window(Duration::from_secs(2))
+            .with_min_entries(50);
+        for e in JournalExportRead::new(&stream[..]) {
+            detector.push(&e);
+        }
+        let bursts = detector.into_bursts(10);
+        assert_eq!(bursts.len(), 1);
+        let b = &bursts[0];
+        assert_eq!(b.key, "noisy.service");
+        assert_eq!((b.start, b.end), (1_800_000_000, 1_805_000_000));
+        assert_eq!(b.entries, 505);
+        assert_eq!(b.rate, 101.0);
+        assert_eq!(b.samples, ["ok", "retry 0", "retry 1"]);
+    }
+}
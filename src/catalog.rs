@@ -0,0 +1,294 @@
+//! Explain entries using systemd's message catalog.
+//!
+//! Catalog files, such as those in `/usr/lib/systemd/catalog`, describe the
+//! messages of a `MESSAGE_ID`, optionally in several languages:
+//!
+//! ```text
+//! -- fc2e22bc6ee647b6b90729ab34a250b1
+//! Subject: Process @COREDUMP_PID@ (@COREDUMP_COMM@) dumped core
+//! Defined-By: systemd
+//!
+//! Process @COREDUMP_PID@ (@COREDUMP_COMM@) crashed and dumped core.
+//! ```
+//!
+//! `@FIELD@` in the text refers to the fields of the entry that is explained.
+//! See: [catalog](https://www.freedesktop.org/wiki/Software/systemd/catalog/)
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::{journald::Entry, message_ids::MessageId};
+
+/// Where systemd installs its catalog files.
+pub const DEFAULT_DIR: &str = "/usr/lib/systemd/catalog";
+
+#[derive(Error, Debug)]
+#[error("line {line}: {reason}")]
+pub struct InvalidCatalog {
+    pub line: usize,
+    pub reason: &'static str,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct CatalogEntry {
+    pub id: MessageId,
+    pub language: Option<String>,
+    /// The header lines, e.g. `Subject` and `Defined-By`.
+    pub headers: Vec<(String, String)>,
+    pub text: String,
+}
+
+impl CatalogEntry {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn subject(&self) -> Option<&str> {
+        self.header("Subject")
+    }
+}
+
+#[derive(Default)]
+pub struct Catalog {
+    entries: HashMap<(MessageId, Option<String>), CatalogEntry>,
+    language: Option<String>,
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Prefers the entries in `language`, e.g. `de`, falling back to those
+    /// without language.
+    pub fn with_language(self, language: impl Into<String>) -> Self {
+        Self {
+            language: Some(language.into()),
+            ..self
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Adds the entries of a catalog file; they replace earlier entries with
+    /// the same ID and language.
+    pub fn parse(&mut self, catalog: &str) -> Result<(), InvalidCatalog> {
+        self.parse_in(catalog, None)
+    }
+
+    /// Parses a catalog whose entries are in `language` unless they say
+    /// otherwise.
+    fn parse_in(&mut self, catalog: &str, language: Option<&str>) -> Result<(), InvalidCatalog> {
+        let mut current: Option<CatalogEntry> = None;
+        let mut in_headers = false;
+        for (i, line) in catalog.lines().enumerate() {
+            let invalid = |reason| InvalidCatalog {
+                line: i + 1,
+                reason,
+            };
+            if line.starts_with('#') {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix("-- ") {
+                self.insert(current.take());
+                let mut words = header.split_whitespace();
+                let id = words
+                    .next()
+                    .and_then(|id| id.parse().ok())
+                    .ok_or_else(|| invalid("expected a 128-bit message ID"))?;
+                current = Some(CatalogEntry {
+                    id,
+                    language: words.next().or(language).map(str::to_string),
+                    headers: vec![],
+                    text: String::new(),
+                });
+                in_headers = true;
+                continue;
+            }
+            let Some(entry) = current.as_mut() else {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                return Err(invalid("text outside of an entry"));
+            };
+            if in_headers {
+                if line.is_empty() {
+                    in_headers = false;
+                } else {
+                    let (name, value) = line
+                        .split_once(':')
+                        .ok_or_else(|| invalid("expected a header"))?;
+                    entry
+                        .headers
+                        .push((name.trim().to_string(), value.trim().to_string()));
+                }
+                continue;
+            }
+            entry.text.push_str(line);
+            entry.text.push('\n');
+        }
+        self.insert(current);
+        Ok(())
+    }
+
+    fn insert(&mut self, entry: Option<CatalogEntry>) {
+        if let Some(mut entry) = entry {
+            entry.text.truncate(entry.text.trim_end().len());
+            let key = (entry.id, entry.language.clone());
+            self.entries.insert(key, entry);
+        }
+    }
+
+    /// Loads a catalog file. Like systemd, a file named `NAME.LANG.catalog`
+    /// holds entries in the language `LANG`.
+    pub fn load(&mut self, path: &Path) -> io::Result<()> {
+        let language = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.split_once('.'))
+            .map(|(_, language)| language);
+        self.parse_in(&fs::read_to_string(path)?, language)
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), e),
+                )
+            })
+    }
+
+    /// Loads the `*.catalog` files in `dir`.
+    pub fn load_dir(&mut self, dir: &Path) -> io::Result<()> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<io::Result<_>>()?;
+        paths.retain(|p| p.extension().is_some_and(|e| e == "catalog"));
+        paths.sort();
+        for path in paths {
+            self.load(&path)?;
+        }
+        Ok(())
+    }
+
+    /// The entry for the message `id`.
+    pub fn get(&self, id: MessageId) -> Option<&CatalogEntry> {
+        self.language
+            .as_ref()
+            .and_then(|l| self.entries.get(&(id, Some(l.clone()))))
+            .or_else(|| self.entries.get(&(id, None)))
+    }
+
+    /// The catalog entry for the `MESSAGE_ID` of `entry`, with its headers and
+    /// text, and `@FIELD@` replaced by the fields of `entry`.
+    pub fn explain(&self, entry: &impl Entry) -> Option<String> {
+        let catalog_entry = self.get(MessageId::of(entry)?)?;
+        let mut explanation = String::new();
+        for (name, value) in &catalog_entry.headers {
+            explanation.push_str(&format!("{}: {}\n", name, substitute(value, entry)));
+        }
+        if !catalog_entry.text.is_empty() {
+            explanation.push('\n');
+            explanation.push_str(&substitute(&catalog_entry.text, entry));
+        }
+        Some(explanation.trim_end().to_string())
+    }
+}
+
+/// Replaces `@FIELD@` in `text` by the value of the field in `entry`;
+/// references to missing fields are kept.
+fn substitute(text: &str, entry: &impl Entry) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('@') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after
+            .find('@')
+            .map(|end| &after[..end])
+            .filter(|name| !name.is_empty() && !name.contains(char::is_whitespace))
+            .and_then(|name| Some((name, entry.get(name.as_bytes())?)));
+        match value {
+            Some((name, value)) => {
+                out.push_str(&String::from_utf8_lossy(value));
+                rest = &after[name.len() + 1..];
+            }
+            None => {
+                out.push('@');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{journald::JournalExportRead, message_ids::COREDUMP};
+
+    use super::Catalog;
+
+    const CATALOG: &str = "\
+# A comment
+-- fc2e22bc6ee647b6b90729ab34a250b1
+Subject: Process @COREDUMP_PID@ (@COREDUMP_COMM@) dumped core
+Defined-By: systemd
+
+Process @COREDUMP_PID@ (@COREDUMP_COMM@) crashed.
+Mail @NOBODY@ at root@localhost.
+
+-- fc2e22bc6ee647b6b90729ab34a250b1 de
+Subject: Prozess @COREDUMP_PID@ hat einen Speicherabzug erzeugt
+";
+
+    #[test]
+    fn explains_entries() {
+        let mut catalog = Catalog::new();
+        catalog.parse(CATALOG).unwrap();
+        assert_eq!(catalog.len(), 2);
+        let input = b"MESSAGE_ID=fc2e22bc6ee647b6b90729ab34a250b1\nCOREDUMP_PID=42\nCOREDUMP_COMM=crashy\n\n";
+        let entry = JournalExportRead::new(&input[..]).next().unwrap();
+        assert_eq!(
+            catalog.explain(&entry).unwrap(),
+            "Subject: Process 42 (crashy) dumped core\nDefined-By: systemd\n\n\
+             Process 42 (crashy) crashed.\nMail @NOBODY@ at root@localhost."
+        );
+
+        let catalog = {
+            let mut c = Catalog::new().with_language("de");
+            c.parse(CATALOG).unwrap();
+            c
+        };
+        let subject = catalog.get(COREDUMP).unwrap().subject();
+        assert_eq!(
+            subject,
+            Some("Prozess @COREDUMP_PID@ hat einen Speicherabzug erzeugt")
+        );
+
+        // Files named NAME.LANG.catalog are in LANG.
+        let dir = tempfile::tempdir().unwrap();
+        let english = CATALOG
+            .split("-- fc2e22bc6ee647b6b90729ab34a250b1 de")
+            .next();
+        std::fs::write(dir.path().join("a.de.catalog"), english.unwrap()).unwrap();
+        let mut catalog = Catalog::new();
+        catalog.load_dir(dir.path()).unwrap();
+        assert!(catalog.get(COREDUMP).is_none());
+
+        assert!(Catalog::new().parse("Subject: orphan").is_err());
+        assert!(Catalog::new().parse("-- coredump\n").is_err());
+    }
+}
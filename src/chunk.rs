@@ -0,0 +1,212 @@
+//! Parse a single large export file on several threads.
+//!
+//! [split] cuts an uncompressed file into chunks at entry boundaries: near
+//! every cut, it looks for an empty line from which the following bytes parse
+//! as entries. Binary values may contain such bytes as well, so [fold], which
+//! parses the chunks in parallel into one accumulator each, checks that every
+//! chunk starts where the entries of the chunk before it end, and parses a
+//! chunk again if not. The accumulators are returned in the order of the
+//! chunks such that the caller can combine them in order.
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+    path::Path,
+};
+
+use crate::journald::{ErrorKind, JournalExportRead, RefEntry};
+
+/// The size of the window searched for an entry boundary, which grows if
+/// it holds none.
+const WINDOW: u64 = 64 * 1024;
+/// Files smaller than this are not split.
+const MIN_CHUNK: u64 = 1 << 20;
+
+/// The offset of the first entry boundary at or after `offset`, or `len`.
+fn boundary(file: &mut File, offset: u64, len: u64) -> io::Result<u64> {
+    let mut window = WINDOW;
+    loop {
+        let end = (offset + window).min(len);
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buf = Vec::with_capacity((end - offset) as usize);
+        file.by_ref().take(end - offset).read_to_end(&mut buf)?;
+        let candidates = buf
+            .windows(2)
+            .enumerate()
+            .filter(|(_, w)| *w == b"\n\n")
+            .map(|(i, _)| i + 2);
+        for c in candidates {
+            if c == buf.len() {
+                return Ok(end);
+            }
+            let mut reader = JournalExportRead::new(&buf[c..]);
+            // A complete entry, or one cut off by the end of the window.
+            let parses = loop {
+                match reader.parse_next() {
+                    Ok(Some(())) => continue,
+                    Ok(None) => break true,
+                    Err(e) if matches!(e.kind(), ErrorKind::UnexpectedEof) => {
+                        break reader.position() > 0 || end < len
+                    }
+                    Err(_) => break false,
+                }
+            };
+            if parses {
+                return Ok(offset + c as u64);
+            }
+        }
+        if end == len {
+            return Ok(len);
+        }
+        window *= 4;
+    }
+}
+
+/// Cuts the uncompressed export file at `path` into at most `n` ranges of
+/// about the same size that start and end at entry boundaries.
+pub fn split(path: &Path, n: usize) -> io::Result<Vec<Range<u64>>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let n = (n as u64).clamp(1, (len / MIN_CHUNK).max(1));
+    let mut cuts = vec![0];
+    for i in 1..n {
+        let cut = boundary(&mut file, i * len / n, len)?;
+        if cut > *cuts.last().unwrap() && cut < len {
+            cuts.push(cut);
+        }
+    }
+    cuts.push(len);
+    Ok(cuts.windows(2).map(|w| w[0]..w[1]).collect())
+}
+
+/// Folds the entries of `reader` until the first one that starts at or after
+/// `range.end`; the reader starts at `range.start`.
+macro_rules! fold_entries {
+    ($reader:expr, $range:expr, $init:expr, $f:expr) => {{
+        let (mut reader, range) = ($reader, $range);
+        let mut acc = $init();
+        while range.start + (reader.position() as u64) < range.end {
+            let parsed = reader.parse_next().map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("at byte {}: {}", range.start + reader.position() as u64, e),
+                )
+            })?;
+            match parsed {
+                Some(()) => $f(&mut acc, &reader.get_entry()),
+                None => break,
+            }
+        }
+        Ok((acc, range.start + reader.position() as u64))
+    }};
+}
+
+/// Folds the entries that start in `start..end` into a new accumulator and
+/// returns it with the offset right after the last of them. Reads with
+/// io_uring if built with the `uring` feature and the kernel allows it.
+fn fold_range<A>(
+    path: &Path,
+    range: Range<u64>,
+    init: impl Fn() -> A,
+    f: impl Fn(&mut A, &RefEntry<'_>),
+) -> io::Result<(A, u64)> {
+    let mut file = File::open(path)?;
+    #[cfg(all(target_os = "linux", feature = "uring"))]
+    if let Ok(reader) = crate::uring::JournalExportUringRead::new(file.try_clone()?, range.start) {
+        return fold_entries!(reader, range, init, f);
+    }
+    file.seek(SeekFrom::Start(range.start))?;
+    fold_entries!(JournalExportRead::new(file), range, init, f)
+}
+
+/// Parses the chunks of the file at `path` on a thread each, folding the
+/// entries of every chunk into an accumulator created by `init`.
+///
+/// The entry across the end of a chunk belongs to that chunk. If it ends
+/// after the end of the chunk because the cut was not at an entry boundary,
+/// the next chunk is parsed again from where the entry ends.
+pub fn fold<A: Send>(
+    path: &Path,
+    chunks: &[Range<u64>],
+    init: impl Fn() -> A + Sync,
+    f: impl Fn(&mut A, &RefEntry<'_>) + Sync,
+) -> io::Result<Vec<A>> {
+    let (init, f) = (&init, &f);
+    let results: Vec<_> = std::thread::scope(|scope| {
+        let threads: Vec<_> = chunks
+            .iter()
+            .map(|chunk| scope.spawn(move || fold_range(path, chunk.clone(), init, f)))
+            .collect();
+        threads
+            .into_iter()
+            .map(|t| t.join().expect("parser thread panicked"))
+            .collect()
+    });
+    let mut at = 0;
+    let mut accs = Vec::with_capacity(chunks.len());
+    for (chunk, result) in chunks.iter().zip(results) {
+        let (acc, end) = match result {
+            _ if at >= chunk.end => (init(), at),
+            result if chunk.start == at => result?,
+            _ => fold_range(path, at..chunk.end, init, f)?,
+        };
+        accs.push(acc);
+        at = end;
+    }
+    Ok(accs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::{
+        journald::Entry,
+        testutil::{write_binary, write_string},
+    };
+
+    use super::{fold, split};
+
+    #[test]
+    fn splits_at_entry_boundaries() {
+        let mut stream = vec![];
+        for i in 0..40_000u64 {
+            write_string(&mut stream, "__REALTIME_TIMESTAMP", i.to_string());
+            // Empty lines within binary values are not boundaries.
+            write_binary(&mut stream, "DATA", "x\n\nMESSAGE=fake\n\n");
+            write_string(&mut stream, "MESSAGE", "y".repeat((i % 50) as usize));
+            stream.push(b'\n');
+        }
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&stream).unwrap();
+
+        let chunks = split(file.path(), 4).unwrap();
+        // About 3.5 MiB, cut into 3 chunks of at least `MIN_CHUNK`.
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].start, 0);
+        assert_eq!(chunks[2].end, stream.len() as u64);
+        for w in chunks.windows(2) {
+            let cut = w[0].end as usize;
+            assert_eq!(w[1].start as usize, cut);
+            assert_eq!(&stream[cut - 2..cut], b"\n\n");
+        }
+        // Some cut is within a binary value, which `fold` corrects.
+        assert!(chunks[1..]
+            .iter()
+            .any(|c| !stream[c.start as usize..].starts_with(b"__REALTIME_TIMESTAMP=")));
+
+        let counts = fold(
+            file.path(),
+            &chunks,
+            || (0u64, 0u64),
+            |(n, sum), e| {
+                *n += 1;
+                *sum += e.realtime_timestamp().unwrap_or(0);
+            },
+        )
+        .unwrap();
+        let (n, sum) = counts.iter().fold((0, 0), |(n, s), (m, t)| (n + m, s + t));
+        assert_eq!((n, sum), (40_000, 40_000 * 39_999 / 2));
+    }
+}
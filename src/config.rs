@@ -1,16 +1,104 @@
+use alloc::vec::Vec;
+
+use crate::shiftbuffer::GrowthStrategy;
+
+/// What the parser does when a field name, a field value or an entry exceeds
+/// its limit.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LimitPolicy {
+    /// Fail the stream.
+    #[default]
+    Error,
+    /// Skip the entry and continue with the next one.
+    Skip,
+    /// Cut the value down to its limit, ending it with
+    /// [TRUNCATION_MARKER](crate::config::TRUNCATION_MARKER). Entries with a
+    /// field name that is too long or that are too large are skipped.
+    Truncate,
+}
+
+/// Which of the fields with the same name an entry keeps; the Journal Export
+/// Format allows repeated names.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    #[default]
+    KeepAll,
+    KeepFirst,
+    KeepLast,
+}
+
+/// The end of truncated values, an ellipsis like `journalctl` uses.
+pub const TRUNCATION_MARKER: &[u8] = "\u{2026}".as_bytes();
+
 #[derive(Debug)]
 pub struct JournalExportLimits {
     pub max_field_value_size: usize,
     pub max_field_name_len: usize,
+    /// Not enforced by the parser; entries are limited by `max_buf_size`.
+    #[deprecated(note = "not enforced; entries are limited by `max_buf_size`")]
     pub max_entry_size: usize,
+    pub initial_buf_size: usize,
+    /// The limit of the size of an entry: the buffer never grows beyond it.
+    /// Streamed and truncated values do not count. An entry that does not fit
+    /// fails with `EntryTooLarge` or is skipped, according to `on_limit`.
+    pub max_buf_size: usize,
+    pub buf_growth: GrowthStrategy,
+    /// Binary field values longer than this are not buffered but passed to
+    /// the value handler of the reader in chunks, regardless of
+    /// `max_field_value_size`; `None` buffers all values.
+    pub max_buffered_value_size: Option<usize>,
+    /// Overrides of `max_field_value_size` for single fields, consulted in
+    /// order when a field name is complete. A name ending in `*` matches all
+    /// names with the preceding prefix. Values above `max_buf_size` still
+    /// fail unless they are streamed.
+    pub field_value_sizes: Vec<(Vec<u8>, usize)>,
+    pub on_limit: LimitPolicy,
+    /// Applied to every parsed entry, such that its fields and its bytes
+    /// only contain the kept fields.
+    pub duplicates: DuplicatePolicy,
+}
+
+impl JournalExportLimits {
+    /// The maximum value size of the field `name`, i.e. that of the first
+    /// matching entry of `field_value_sizes` or `max_field_value_size`.
+    pub fn max_field_value_size_of(&self, name: &[u8]) -> usize {
+        self.field_value_sizes
+            .iter()
+            .find(|(pattern, _)| match pattern.strip_suffix(b"*") {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == &pattern[..],
+            })
+            .map_or(self.max_field_value_size, |(_, size)| *size)
+    }
+
+    /// Limits that admit every well-formed stream of at most `len` bytes,
+    /// e.g. to parse again entries that are complete in memory.
+    pub fn bounded_by(len: usize) -> Self {
+        let limit = len.max(1);
+        JournalExportLimitsBuilder::new()
+            .with_max_field_value_size(limit)
+            .with_max_field_name_len(limit)
+            .with_max_buf_size(limit)
+            .build()
+    }
 }
 
 impl Default for JournalExportLimits {
+    #[allow(deprecated)]
     fn default() -> Self {
         Self {
             max_field_value_size: 12 * 1024, // 12 KiB,
             max_field_name_len: 128,
             max_entry_size: 1 << 14,
+            // We assume that 16KiB (half the L1 cache on modern CPUs) is
+            // enough to hold at least one Journal Entry.
+            initial_buf_size: 1 << 14,
+            max_buf_size: 1 << 24, // 16 MiB
+            buf_growth: GrowthStrategy::Double,
+            max_buffered_value_size: None,
+            field_value_sizes: Vec::new(),
+            on_limit: LimitPolicy::Error,
+            duplicates: DuplicatePolicy::KeepAll,
         }
     }
 }
@@ -20,6 +108,13 @@ pub struct JournalExportLimitsBuilder {
     max_field_value_size: Option<usize>,
     max_field_name_len: Option<usize>,
     max_entry_size: Option<usize>,
+    initial_buf_size: Option<usize>,
+    max_buf_size: Option<usize>,
+    buf_growth: Option<GrowthStrategy>,
+    max_buffered_value_size: Option<usize>,
+    field_value_sizes: Vec<(Vec<u8>, usize)>,
+    on_limit: Option<LimitPolicy>,
+    duplicates: Option<DuplicatePolicy>,
 }
 
 impl JournalExportLimitsBuilder {
@@ -43,6 +138,7 @@ impl JournalExportLimitsBuilder {
         }
     }
 
+    #[deprecated(note = "not enforced; use `with_max_buf_size` to limit entries")]
     pub fn with_max_entry_size(self, size: usize) -> Self {
         assert!(size > 0);
         Self {
@@ -51,6 +147,60 @@ impl JournalExportLimitsBuilder {
         }
     }
 
+    pub fn with_initial_buf_size(self, size: usize) -> Self {
+        assert!(size > 0);
+        Self {
+            initial_buf_size: Some(size),
+            ..self
+        }
+    }
+
+    pub fn with_max_buf_size(self, size: usize) -> Self {
+        assert!(size > 0);
+        Self {
+            max_buf_size: Some(size),
+            ..self
+        }
+    }
+
+    pub fn with_buf_growth(self, growth: GrowthStrategy) -> Self {
+        Self {
+            buf_growth: Some(growth),
+            ..self
+        }
+    }
+
+    pub fn with_max_buffered_value_size(self, size: usize) -> Self {
+        Self {
+            max_buffered_value_size: Some(size),
+            ..self
+        }
+    }
+
+    /// Limits the values of the field `name`, or of all fields starting with
+    /// a prefix if `name` is the prefix followed by `*`, to `size` bytes.
+    /// Earlier overrides take precedence.
+    pub fn with_field_value_size(mut self, name: impl Into<Vec<u8>>, size: usize) -> Self {
+        assert!(size > 0);
+        self.field_value_sizes.push((name.into(), size));
+        self
+    }
+
+    pub fn with_limit_policy(self, policy: LimitPolicy) -> Self {
+        Self {
+            on_limit: Some(policy),
+            ..self
+        }
+    }
+
+    pub fn with_duplicate_policy(self, policy: DuplicatePolicy) -> Self {
+        Self {
+            duplicates: Some(policy),
+            ..self
+        }
+    }
+
+    #[allow(deprecated)]
     pub fn build(self) -> JournalExportLimits {
         let defaults = JournalExportLimits::default();
         JournalExportLimits {
@@ -61,6 +211,15 @@ impl JournalExportLimitsBuilder {
                 .max_field_name_len
                 .unwrap_or(defaults.max_field_name_len),
             max_entry_size: self.max_entry_size.unwrap_or(defaults.max_entry_size),
+            initial_buf_size: self.initial_buf_size.unwrap_or(defaults.initial_buf_size),
+            max_buf_size: self.max_buf_size.unwrap_or(defaults.max_buf_size),
+            buf_growth: self.buf_growth.unwrap_or(defaults.buf_growth),
+            max_buffered_value_size: self
+                .max_buffered_value_size
+                .or(defaults.max_buffered_value_size),
+            field_value_sizes: self.field_value_sizes,
+            on_limit: self.on_limit.unwrap_or(defaults.on_limit),
+            duplicates: self.duplicates.unwrap_or(defaults.duplicates),
         }
     }
 }
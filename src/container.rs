@@ -0,0 +1,464 @@
+//! Import the log files of container runtimes.
+//!
+//! [ContainerLogRead] converts the log files that Docker's `json-file` driver
+//! writes (`/var/lib/docker/containers/ID/ID-json.log`), with one JSON object
+//! per line:
+//!
+//! ```text
+//! {"log":"listening on :80\n","stream":"stdout","time":"2024-05-01T10:00:00.123456789Z"}
+//! ```
+//!
+//! and those of containerd and other CRI runtimes (`/var/log/pods/...`), with
+//! the time, the stream and a tag that is `P` for partial lines and `F` for
+//! the final part of a line:
+//!
+//! ```text
+//! 2024-05-01T10:00:00.123456789Z stderr F connection refused
+//! ```
+//!
+//! to entries in the Journal Export Format. Long lines that the runtime split
+//! are reassembled per stream; the entry has the time of the first part. Like
+//! journald, lines longer than the maximum value size of the parser's default
+//! limits are split again into entries of that size, all but the last one
+//! marked with `_LINE_BREAK=line-max`, which [crate::reassemble] understands.
+//! Entries have the fields of journald's `journald` logging driver, i.e.
+//! `CONTAINER_ID`, `CONTAINER_ID_FULL`, `CONTAINER_NAME` and `IMAGE_NAME` as
+//! far as [ContainerInfo] knows them, `PRIORITY` 3 for `stderr` and 6 for
+//! `stdout`, and `CONTAINER_STREAM`.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead, Read},
+    path::Path,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    config::JournalExportLimits,
+    journald::{parser::FieldType, write_field},
+};
+
+/// What is known about the container of a log file.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct ContainerInfo {
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub image: Option<String>,
+    pub pod: Option<String>,
+    pub namespace: Option<String>,
+}
+
+impl ContainerInfo {
+    /// Determines the container from the path of its log file:
+    ///
+    /// - Docker: `containers/ID/ID-json.log`, with the name and image read
+    ///   from `config.v2.json` next to it if possible;
+    /// - Kubernetes: `pods/NAMESPACE_POD_UID/CONTAINER/N.log`;
+    /// - Kubernetes: `containers/POD_NAMESPACE_CONTAINER-ID.log`.
+    pub fn from_path(path: &Path) -> Self {
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned());
+        let name = name.as_deref().unwrap_or_default();
+        let parent = |n: usize| {
+            path.ancestors()
+                .nth(n)
+                .and_then(|p| p.file_name())
+                .map(|n| n.to_string_lossy().into_owned())
+        };
+        if let Some(id) = name.strip_suffix("-json.log") {
+            let mut info = Self {
+                id: Some(id.to_string()),
+                ..Self::default()
+            };
+            let config = path.with_file_name("config.v2.json");
+            if let Some(config) = fs::read(config)
+                .ok()
+                .and_then(|c| serde_json::from_slice::<DockerConfig>(&c).ok())
+            {
+                info.name = config.name.map(|n| n.trim_start_matches('/').to_string());
+                info.image = config.config.and_then(|c| c.image);
+            }
+            return info;
+        }
+        if parent(3).as_deref() == Some("pods") {
+            if let Some(pod) = parent(2) {
+                let mut parts = pod.splitn(3, '_');
+                return Self {
+                    namespace: parts.next().map(str::to_string),
+                    pod: parts.next().map(str::to_string),
+                    name: parent(1),
+                    ..Self::default()
+                };
+            }
+        }
+        if let Some((rest, id)) = name.strip_suffix(".log").and_then(|n| n.rsplit_once('-')) {
+            let parts: Vec<_> = rest.splitn(3, '_').collect();
+            if let [pod, namespace, container] = parts[..] {
+                return Self {
+                    id: Some(id.to_string()),
+                    name: Some(container.to_string()),
+                    pod: Some(pod.to_string()),
+                    namespace: Some(namespace.to_string()),
+                    ..Self::default()
+                };
+            }
+        }
+        Self::default()
+    }
+}
+
+#[derive(Deserialize)]
+struct DockerConfig {
+    #[serde(rename = "Name")]
+    name: Option<String>,
+    #[serde(rename = "Config")]
+    config: Option<DockerContainerConfig>,
+}
+
+#[derive(Deserialize)]
+struct DockerContainerConfig {
+    #[serde(rename = "Image")]
+    image: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DockerLine {
+    log: String,
+    stream: String,
+    time: String,
+    /// Docker's `--log-opt labels=...` and `env=...`.
+    #[serde(default)]
+    attrs: BTreeMap<String, String>,
+}
+
+/// Whether `head`, the start of a file, looks like a container log.
+pub fn is_container_log(head: &[u8]) -> bool {
+    let line = head.split(|&b| b == b'\n').next().unwrap_or_default();
+    let Ok(line) = std::str::from_utf8(line) else {
+        return false;
+    };
+    if line.starts_with('{') {
+        return line.contains("\"log\":") && line.contains("\"stream\":");
+    }
+    let mut parts = line.splitn(4, ' ');
+    matches!(
+        (parts.next().map(parse_time), parts.next(), parts.next()),
+        (Some(Some(_)), Some("stdout" | "stderr"), Some("F" | "P"))
+    )
+}
+
+fn parse_time(s: &str) -> Option<u64> {
+    let time = chrono::DateTime::parse_from_rfc3339(s).ok()?;
+    u64::try_from(time.timestamp_micros()).ok()
+}
+
+/// A line that is being reassembled.
+struct Partial {
+    timestamp: u64,
+    message: Vec<u8>,
+}
+
+/// Converts a Docker or CRI container log to entries in the Journal Export
+/// Format; the format is detected per line.
+pub struct ContainerLogRead<R> {
+    read: R,
+    info: ContainerInfo,
+    line: Vec<u8>,
+    lines: usize,
+    /// The partial lines of `stdout` and `stderr`.
+    partial: [Option<Partial>; 2],
+    max_message_size: usize,
+    out: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: BufRead> ContainerLogRead<R> {
+    pub fn new(read: R) -> Self {
+        Self {
+            read,
+            info: ContainerInfo::default(),
+            line: vec![],
+            lines: 0,
+            partial: [None, None],
+            max_message_size: JournalExportLimits::default().max_field_value_size,
+            out: vec![],
+            pos: 0,
+        }
+    }
+
+    /// The maximum size of the `MESSAGE` of an entry; longer lines are split.
+    /// Defaults to the maximum value size of [JournalExportLimits::default].
+    pub fn with_max_message_size(self, max_message_size: usize) -> Self {
+        assert!(max_message_size > 0);
+        Self {
+            max_message_size,
+            ..self
+        }
+    }
+
+    /// Adds the fields describing the container to every entry.
+    pub fn with_info(self, info: ContainerInfo) -> Self {
+        Self { info, ..self }
+    }
+
+    fn invalid(&self, reason: impl std::fmt::Display) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("line {}: {}", self.lines, reason),
+        )
+    }
+
+    /// Converts lines until an entry is complete. Returns `false` at the end
+    /// of the input, after writing the partial lines that were left.
+    fn convert_next(&mut self) -> io::Result<bool> {
+        loop {
+            self.line.clear();
+            if self.read.read_until(b'\n', &mut self.line)? == 0 {
+                for stream in 0..2 {
+                    if let Some(p) = self.partial[stream].take() {
+                        self.write(p, stream, &BTreeMap::new(), false);
+                    }
+                }
+                return Ok(!self.out.is_empty());
+            }
+            self.lines += 1;
+            let line = std::mem::take(&mut self.line);
+            let complete = self.convert_line(&line);
+            self.line = line;
+            if complete? {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Adds a line to the partial line of its stream. Returns whether that
+    /// completed an entry.
+    fn convert_line(&mut self, line: &[u8]) -> io::Result<bool> {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            return Ok(false);
+        }
+        let (time, stream, message, last, attrs) = if line.starts_with(b"{") {
+            let l: DockerLine = serde_json::from_slice(line).map_err(|e| self.invalid(e))?;
+            let last = l.log.ends_with('\n');
+            let log = l.log.strip_suffix('\n').unwrap_or(&l.log);
+            let log = log.strip_suffix('\r').unwrap_or(log);
+            (l.time, l.stream, log.as_bytes().to_vec(), last, l.attrs)
+        } else {
+            let mut parts = line.splitn(4, |&b| b == b' ');
+            let mut part = || {
+                parts
+                    .next()
+                    .map(|p| String::from_utf8_lossy(p).into_owned())
+            };
+            let (Some(time), Some(stream), Some(tag)) = (part(), part(), part()) else {
+                return Err(self.invalid("expected TIME STREAM TAG MESSAGE"));
+            };
+            let message = parts.next().unwrap_or_default().to_vec();
+            (time, stream, message, tag != "P", BTreeMap::new())
+        };
+        let timestamp = parse_time(&time).ok_or_else(|| self.invalid("invalid time"))?;
+        let index = match stream.as_str() {
+            "stdout" => 0,
+            "stderr" => 1,
+            _ => return Err(self.invalid(format!("unknown stream: {}", stream))),
+        };
+        let partial = self.partial[index].get_or_insert(Partial {
+            timestamp,
+            message: vec![],
+        });
+        partial.message.extend_from_slice(&message);
+        let mut split = false;
+        loop {
+            let partial = self.partial[index].as_mut().unwrap();
+            if partial.message.len() <= self.max_message_size {
+                break;
+            }
+            let rest = partial.message.split_off(self.max_message_size);
+            let head = Partial {
+                timestamp: partial.timestamp,
+                message: std::mem::replace(&mut partial.message, rest),
+            };
+            self.write(head, index, &attrs, true);
+            split = true;
+        }
+        if !last {
+            return Ok(split);
+        }
+        let partial = self.partial[index].take().unwrap();
+        self.write(partial, index, &attrs, false);
+        Ok(true)
+    }
+
+    /// Appends the entry of `partial` to the output; `line_max` marks it as
+    /// the first part of a line that is continued by the next entry.
+    fn write(
+        &mut self,
+        partial: Partial,
+        stream: usize,
+        attrs: &BTreeMap<String, String>,
+        line_max: bool,
+    ) {
+        let out = &mut self.out;
+        let mut field =
+            |name: &str, value: &[u8]| write_field(out, name.as_bytes(), value, &FieldType::String);
+        field(
+            "__REALTIME_TIMESTAMP",
+            partial.timestamp.to_string().as_bytes(),
+        );
+        field("PRIORITY", if stream == 0 { b"6" } else { b"3" });
+        let info = &self.info;
+        let short_id = info.id.as_deref().map(|id| &id[..id.len().min(12)]);
+        if let Some(identifier) = info.name.as_deref().or(short_id) {
+            field("SYSLOG_IDENTIFIER", identifier.as_bytes());
+        }
+        field("MESSAGE", &partial.message);
+        if line_max {
+            field("_LINE_BREAK", b"line-max");
+        }
+        if let (Some(short), Some(id)) = (short_id, &info.id) {
+            field("CONTAINER_ID", short.as_bytes());
+            field("CONTAINER_ID_FULL", id.as_bytes());
+        }
+        let described = [
+            ("CONTAINER_NAME", &info.name),
+            ("IMAGE_NAME", &info.image),
+            ("CONTAINER_POD", &info.pod),
+            ("CONTAINER_NAMESPACE", &info.namespace),
+        ];
+        for (name, value) in described {
+            if let Some(value) = value {
+                field(name, value.as_bytes());
+            }
+        }
+        let stream: &[u8] = if stream == 0 { b"stdout" } else { b"stderr" };
+        field("CONTAINER_STREAM", stream);
+        for (name, value) in attrs {
+            let name = format!("CONTAINER_ATTR_{}", field_suffix(name));
+            field(&name, value.as_bytes());
+        }
+        out.push(b'\n');
+    }
+}
+
+fn field_suffix(name: &str) -> String {
+    name.chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_uppercase(),
+            false => '_',
+        })
+        .collect()
+}
+
+impl<R: BufRead> Read for ContainerLogRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.out.len() {
+            self.out.clear();
+            self.pos = 0;
+            if !self.convert_next()? {
+                return Ok(0);
+            }
+        }
+        let n = buf.len().min(self.out.len() - self.pos);
+        buf[..n].copy_from_slice(&self.out[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::journald::{Entry, JournalExportRead};
+
+    use super::{is_container_log, ContainerInfo, ContainerLogRead};
+
+    fn convert(log: &str, info: ContainerInfo) -> Vec<Vec<(String, String)>> {
+        let mut export = vec![];
+        let mut read = ContainerLogRead::new(log.as_bytes()).with_info(info);
+        std::io::copy(&mut read, &mut export).unwrap();
+        JournalExportRead::new(&export[..])
+            .map(|e| {
+                e.iter()
+                    .map(|(n, v, _)| {
+                        let s = |b: &[u8]| String::from_utf8_lossy(b).into_owned();
+                        (s(n), s(v))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn get<'a>(entry: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        entry
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn converts_docker_and_cri_logs() {
+        let docker = concat!(
+            r#"{"log":"first part, ","stream":"stdout","time":"2024-05-01T10:00:00.000001Z"}"#,
+            "\n",
+            r#"{"log":"oops\n","stream":"stderr","time":"2024-05-01T10:00:00.5Z","attrs":{"team":"web"}}"#,
+            "\n",
+            r#"{"log":"second part\n","stream":"stdout","time":"2024-05-01T10:00:01Z"}"#,
+            "\n",
+        );
+        assert!(is_container_log(docker.as_bytes()));
+        let path =
+            Path::new("/var/lib/docker/containers/0123456789abcdef/0123456789abcdef-json.log");
+        let entries = convert(docker, ContainerInfo::from_path(path));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(get(&entries[0], "MESSAGE"), Some("oops"));
+        assert_eq!(get(&entries[0], "PRIORITY"), Some("3"));
+        assert_eq!(get(&entries[0], "CONTAINER_ATTR_TEAM"), Some("web"));
+        assert_eq!(get(&entries[1], "MESSAGE"), Some("first part, second part"));
+        assert_eq!(
+            get(&entries[1], "__REALTIME_TIMESTAMP"),
+            Some("1714557600000001")
+        );
+        assert_eq!(get(&entries[1], "CONTAINER_ID"), Some("0123456789ab"));
+        assert_eq!(get(&entries[1], "CONTAINER_STREAM"), Some("stdout"));
+
+        let cri = "2024-05-01T10:00:00Z stdout P abc\n\
+                   2024-05-01T10:00:00Z stdout F def\n\
+                   2024-05-01T10:00:01Z stderr P unterminated";
+        assert!(is_container_log(cri.as_bytes()));
+        assert!(!is_container_log(b"__CURSOR=s=1\n"));
+        let path = Path::new("/var/log/pods/default_web-1_4f2a/nginx/0.log");
+        let entries = convert(cri, ContainerInfo::from_path(path));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(get(&entries[0], "MESSAGE"), Some("abcdef"));
+        assert_eq!(get(&entries[0], "CONTAINER_NAME"), Some("nginx"));
+        assert_eq!(get(&entries[0], "CONTAINER_POD"), Some("web-1"));
+        assert_eq!(get(&entries[0], "CONTAINER_NAMESPACE"), Some("default"));
+        assert_eq!(get(&entries[1], "MESSAGE"), Some("unterminated"));
+    }
+
+    #[test]
+    fn splits_lines_longer_than_the_value_limit() {
+        // Docker splits lines at 16 KiB, more than the parser admits.
+        let part: String = (0..16384)
+            .map(|i| (b'a' + (i % 26) as u8) as char)
+            .collect();
+        let first = r#"{"log":"PART","stream":"stdout","time":"2024-05-01T10:00:00Z"}"#;
+        let last = r#"{"log":"tail\n","stream":"stdout","time":"2024-05-01T10:00:01Z"}"#;
+        let docker = format!("{}\n{}\n", first.replace("PART", &part), last);
+        let entries = convert(&docker, ContainerInfo::default());
+        assert_eq!(entries.len(), 2);
+        let message = |e: &[(String, String)]| get(e, "MESSAGE").unwrap().to_owned();
+        assert_eq!(message(&entries[0]).len(), 12 * 1024);
+        assert_eq!(get(&entries[0], "_LINE_BREAK"), Some("line-max"));
+        assert_eq!(get(&entries[1], "_LINE_BREAK"), None);
+        assert_eq!(
+            get(&entries[1], "__REALTIME_TIMESTAMP"),
+            Some("1714557600000000")
+        );
+        assert_eq!(message(&entries[0]) + &message(&entries[1]), part + "tail");
+    }
+}
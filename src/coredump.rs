@@ -0,0 +1,172 @@
+//! Summarize the coredumps recorded by systemd-coredump.
+//!
+//! systemd-coredump logs every crash as an entry with `COREDUMP_*` fields
+//! describing the crashed process. Depending on its `Storage=` setting, the
+//! core itself is either embedded in the `COREDUMP` field of the entry or
+//! stored in a file referenced by `COREDUMP_FILENAME`.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{journald::Entry, message_ids};
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct Coredump {
+    pub timestamp: Option<u64>,
+    pub pid: Option<u64>,
+    pub exe: Option<String>,
+    pub comm: Option<String>,
+    pub signal: Option<u64>,
+    pub signal_name: Option<String>,
+    /// The unit of the crashed process; user units take precedence.
+    pub unit: Option<String>,
+    /// The file the core was stored in, if it was stored externally.
+    pub filename: Option<String>,
+    /// The size of the core embedded in the entry.
+    pub core_size: Option<usize>,
+}
+
+impl Coredump {
+    /// The coredump described by `entry`, or `None` if it is not logged by
+    /// systemd-coredump.
+    pub fn from_entry(entry: &impl Entry) -> Option<Self> {
+        let is_coredump = message_ids::COREDUMP.matches(entry)
+            || entry
+                .iter()
+                .any(|(name, _, _)| name.starts_with(b"COREDUMP_"));
+        if !is_coredump {
+            return None;
+        }
+        let string = |name: &[u8]| {
+            entry
+                .get(name)
+                .map(|v| String::from_utf8_lossy(v).into_owned())
+        };
+        let signal = entry.get_u64(b"COREDUMP_SIGNAL");
+        Some(Self {
+            timestamp: entry
+                .get_u64(b"COREDUMP_TIMESTAMP")
+                .or_else(|| entry.realtime_timestamp()),
+            pid: entry.get_u64(b"COREDUMP_PID"),
+            exe: string(b"COREDUMP_EXE"),
+            comm: string(b"COREDUMP_COMM"),
+            signal,
+            signal_name: string(b"COREDUMP_SIGNAL_NAME")
+                .or_else(|| signal.and_then(signal_name).map(str::to_string)),
+            unit: string(b"COREDUMP_USER_UNIT").or_else(|| string(b"COREDUMP_UNIT")),
+            filename: string(b"COREDUMP_FILENAME"),
+            core_size: core(entry).map(<[u8]>::len),
+        })
+    }
+
+    /// Writes the core embedded in `entry`, the entry of the coredump, to
+    /// `dir`, or else copies the file the coredump references there. Returns
+    /// the path written to, or `None` if the core is neither embedded nor
+    /// available on this machine.
+    pub fn extract(&self, entry: &impl Entry, dir: &Path) -> io::Result<Option<PathBuf>> {
+        if let Some(core) = core(entry) {
+            let name = format!(
+                "core.{}.{}.{}",
+                sanitize(self.comm.as_deref().unwrap_or("unknown")),
+                self.pid.unwrap_or(0),
+                self.timestamp.unwrap_or(0)
+            );
+            let path = dir.join(name);
+            fs::write(&path, core)?;
+            return Ok(Some(path));
+        }
+        let Some(src) = self.filename.as_deref().map(Path::new) else {
+            return Ok(None);
+        };
+        let Some(name) = src.file_name() else {
+            return Ok(None);
+        };
+        let path = dir.join(name);
+        match fs::copy(src, &path) {
+            Ok(_) => Ok(Some(path)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// The core embedded in `entry`.
+pub fn core(entry: &impl Entry) -> Option<&[u8]> {
+    entry.get(b"COREDUMP").filter(|core| !core.is_empty())
+}
+
+/// The names of the signals that dump core on Linux.
+fn signal_name(signal: u64) -> Option<&'static str> {
+    Some(match signal {
+        3 => "SIGQUIT",
+        4 => "SIGILL",
+        5 => "SIGTRAP",
+        6 => "SIGABRT",
+        7 => "SIGBUS",
+        8 => "SIGFPE",
+        11 => "SIGSEGV",
+        24 => "SIGXCPU",
+        25 => "SIGXFSZ",
+        31 => "SIGSYS",
+        _ => return None,
+    })
+}
+
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\0' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        journald::JournalExportRead,
+        testutil::{write_binary, write_string},
+    };
+
+    use super::Coredump;
+
+    #[test]
+    fn summarizes_and_extracts_coredumps() {
+        let mut stream = vec![];
+        write_string(&mut stream, "__REALTIME_TIMESTAMP", "100");
+        write_string(&mut stream, "MESSAGE", "not a crash");
+        stream.push(b'\n');
+        write_string(&mut stream, "__REALTIME_TIMESTAMP", "200");
+        write_string(&mut stream, "COREDUMP_PID", "42");
+        write_string(&mut stream, "COREDUMP_COMM", "crashy");
+        write_string(&mut stream, "COREDUMP_EXE", "/usr/bin/crashy");
+        write_string(&mut stream, "COREDUMP_SIGNAL", "11");
+        write_string(&mut stream, "COREDUMP_UNIT", "crashy.service");
+        write_binary(&mut stream, "COREDUMP", b"\x7fELF\ncore");
+        stream.push(b'\n');
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut dumps = vec![];
+        for e in JournalExportRead::new(&stream[..]) {
+            if let Some(dump) = Coredump::from_entry(&e) {
+                let path = dump.extract(&e, dir.path()).unwrap().unwrap();
+                assert_eq!(std::fs::read(path).unwrap(), b"\x7fELF\ncore");
+                dumps.push(dump);
+            }
+        }
+        assert_eq!(dumps.len(), 1);
+        let dump = &dumps[0];
+        assert_eq!(
+            (dump.timestamp, dump.pid, dump.signal_name.as_deref()),
+            (Some(200), Some(42), Some("SIGSEGV"))
+        );
+        assert_eq!(
+            (dump.exe.as_deref(), dump.unit.as_deref(), dump.core_size),
+            (Some("/usr/bin/crashy"), Some("crashy.service"), Some(9))
+        );
+    }
+}
@@ -0,0 +1,258 @@
+//! Remove duplicate entries from a stream.
+//!
+//! [Dedup] reduces every entry to a 128bit digest of its [DedupKey] and
+//! remembers the digests in a [KeySet]. Two sets are provided:
+//! [WindowSet] keeps the most recent digests in memory and thus only finds
+//! duplicates that are close to each other, which is the common case for
+//! re-uploaded batches. [DiskHashSet] is exact; it stores all digests in an
+//! open addressing hash table in a temporary file, such that the memory used
+//! does not depend on the size of the input.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::FileExt;
+
+use sha2::Digest;
+
+#[cfg(not(unix))]
+use crate::fileext::FileExt;
+use crate::journald::Entry;
+
+pub type Key = [u8; 16];
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum DedupKey {
+    /// The `__CURSOR` field; entries without cursor are compared by content.
+    #[default]
+    Cursor,
+    /// The complete entry, see [Entry::canonical_bytes].
+    Content,
+}
+
+impl DedupKey {
+    pub fn digest(&self, entry: &impl Entry) -> Key {
+        let digest = match self.cursor(entry) {
+            Some(cursor) => sha2::Sha256::digest(cursor),
+            None => sha2::Sha256::digest(entry.canonical_bytes()),
+        };
+        digest[..16].try_into().unwrap()
+    }
+
+    fn cursor<'a>(&self, entry: &'a impl Entry) -> Option<&'a [u8]> {
+        match self {
+            DedupKey::Cursor => entry.get(b"__CURSOR"),
+            DedupKey::Content => None,
+        }
+    }
+}
+
+/// A set of entry digests.
+pub trait KeySet {
+    /// Adds `key` to the set. Returns whether it was not present.
+    fn insert(&mut self, key: Key) -> io::Result<bool>;
+}
+
+/// Remembers the last `capacity` distinct keys.
+pub struct WindowSet {
+    capacity: usize,
+    keys: HashSet<Key>,
+    order: VecDeque<Key>,
+}
+
+impl WindowSet {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0);
+        Self {
+            capacity,
+            keys: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+}
+
+impl KeySet for WindowSet {
+    fn insert(&mut self, key: Key) -> io::Result<bool> {
+        if !self.keys.insert(key) {
+            return Ok(false);
+        }
+        self.order.push_back(key);
+        if self.order.len() > self.capacity {
+            let oldest = self.order.pop_front().unwrap();
+            self.keys.remove(&oldest);
+        }
+        Ok(true)
+    }
+}
+
+const SLOT_SIZE: u64 = 16;
+const EMPTY: Key = [0; 16];
+
+/// An exact set of keys, stored in a temporary file using linear probing.
+/// The table is doubled (and rewritten) whenever it is half full.
+pub struct DiskHashSet {
+    tmp_dir: PathBuf,
+    file: File,
+    slots: u64,
+    len: u64,
+}
+
+impl DiskHashSet {
+    pub fn new(tmp_dir: &Path) -> io::Result<Self> {
+        Self::with_slots(tmp_dir, 1 << 16)
+    }
+
+    /// Creates a table with `slots` slots initially; `slots` must be a power
+    /// of two.
+    pub fn with_slots(tmp_dir: &Path, slots: u64) -> io::Result<Self> {
+        assert!(slots.is_power_of_two());
+        let file = tempfile::tempfile_in(tmp_dir)?;
+        file.set_len(slots * SLOT_SIZE)?;
+        Ok(Self {
+            tmp_dir: tmp_dir.to_path_buf(),
+            file,
+            slots,
+            len: 0,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn insert_slot(&mut self, key: Key) -> io::Result<bool> {
+        // The all-zero digest marks empty slots; mapping the (unlikely) zero
+        // digest to another value merely merges it with that one.
+        let key = if key == EMPTY { [0xff; 16] } else { key };
+        let mut slot = u64::from_le_bytes(key[..8].try_into().unwrap()) & (self.slots - 1);
+        let mut stored = EMPTY;
+        loop {
+            self.file.read_exact_at(&mut stored, slot * SLOT_SIZE)?;
+            if stored == EMPTY {
+                self.file.write_all_at(&key, slot * SLOT_SIZE)?;
+                self.len += 1;
+                return Ok(true);
+            }
+            if stored == key {
+                return Ok(false);
+            }
+            slot = (slot + 1) & (self.slots - 1);
+        }
+    }
+
+    fn grow(&mut self) -> io::Result<()> {
+        let mut grown = Self::with_slots(&self.tmp_dir, self.slots * 2)?;
+        let mut old = BufReader::new(&self.file);
+        let mut key = EMPTY;
+        for _ in 0..self.slots {
+            old.read_exact(&mut key)?;
+            if key != EMPTY {
+                grown.insert_slot(key)?;
+            }
+        }
+        *self = grown;
+        Ok(())
+    }
+}
+
+impl KeySet for DiskHashSet {
+    fn insert(&mut self, key: Key) -> io::Result<bool> {
+        if (self.len + 1) * 2 > self.slots {
+            self.grow()?;
+        }
+        self.insert_slot(key)
+    }
+}
+
+pub struct Dedup<S> {
+    key: DedupKey,
+    set: S,
+    duplicates: usize,
+}
+
+impl<S: KeySet> Dedup<S> {
+    pub fn new(key: DedupKey, set: S) -> Self {
+        Self {
+            key,
+            set,
+            duplicates: 0,
+        }
+    }
+
+    /// Whether an entry with the same key has been seen before.
+    pub fn is_duplicate(&mut self, entry: &impl Entry) -> io::Result<bool> {
+        let new = self.set.insert(self.key.digest(entry))?;
+        if !new {
+            self.duplicates += 1;
+        }
+        Ok(!new)
+    }
+
+    /// The number of duplicates found so far.
+    pub fn duplicates(&self) -> usize {
+        self.duplicates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{generate::EntryGenerator, journald::JournalExportRead};
+
+    use super::{Dedup, DedupKey, DiskHashSet, KeySet, WindowSet};
+
+    fn count_duplicates<S: KeySet>(stream: &[u8], key: DedupKey, set: S) -> usize {
+        let mut dedup = Dedup::new(key, set);
+        let mut jreader = JournalExportRead::new(stream);
+        while jreader.parse_next().unwrap().is_some() {
+            dedup.is_duplicate(&jreader.get_entry()).unwrap();
+        }
+        dedup.duplicates()
+    }
+
+    #[test]
+    fn window_finds_nearby_duplicates_only() {
+        let batch = EntryGenerator::new(1).generate(10);
+        let stream = [
+            batch.clone(),
+            batch.clone(),
+            EntryGenerator::new(2).generate(20),
+            batch,
+        ]
+        .concat();
+        assert_eq!(
+            count_duplicates(&stream, DedupKey::Cursor, WindowSet::new(15)),
+            10
+        );
+        assert_eq!(
+            count_duplicates(&stream, DedupKey::Content, WindowSet::new(100)),
+            20
+        );
+    }
+
+    #[test]
+    fn disk_set_is_exact_across_growth() {
+        let dir = tempfile::tempdir().unwrap();
+        let batch = EntryGenerator::new(1).generate(100);
+        let stream = [batch.clone(), EntryGenerator::new(2).generate(100), batch].concat();
+        let set = DiskHashSet::with_slots(dir.path(), 4).unwrap();
+        assert_eq!(count_duplicates(&stream, DedupKey::Content, set), 100);
+
+        let mut set = DiskHashSet::with_slots(dir.path(), 4).unwrap();
+        for i in 0..1000u32 {
+            let mut key = [0; 16];
+            key[..4].copy_from_slice(&i.to_le_bytes());
+            assert!(set.insert(key).unwrap());
+        }
+        assert!(!set.insert([0; 16]).unwrap());
+        assert_eq!(set.len(), 1000);
+    }
+}
@@ -0,0 +1,247 @@
+//! Compare two journal export streams.
+//!
+//! [ExportDiff] walks through two streams that are ordered by their
+//! `__REALTIME_TIMESTAMP` and aligns their entries: entries with the same
+//! timestamp are matched by their `__CURSOR` or, if they lack a cursor, by
+//! their content (see [Entry::canonical_bytes]; the order of fields does not
+//! matter). Entries that cannot be matched are only contained in one of the
+//! streams; matched entries whose content differs are reported as
+//! [Difference::Changed]. [field_changes] breaks such a change down into
+//! fields.
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    io::Read,
+};
+
+use crate::journald::{Entry, JournalExportRead, JournalExportReadError, OwnedEntry};
+
+pub enum Difference {
+    /// The entry is only contained in the left stream.
+    Left(OwnedEntry),
+    /// The entry is only contained in the right stream.
+    Right(OwnedEntry),
+    /// The entries are matched but their content differs.
+    Changed(OwnedEntry, OwnedEntry),
+}
+
+/// The values of a field in two matched entries. Fields may occur multiple
+/// times in an entry, hence the lists of values.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct FieldChange {
+    pub name: Vec<u8>,
+    pub left: Vec<Vec<u8>>,
+    pub right: Vec<Vec<u8>>,
+}
+
+/// Lists the fields whose values differ between `left` and `right`, ordered
+/// by name.
+pub fn field_changes(left: &impl Entry, right: &impl Entry) -> Vec<FieldChange> {
+    fn fields(e: &impl Entry) -> BTreeMap<Vec<u8>, Vec<Vec<u8>>> {
+        let mut fields: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for (name, value, _) in e.iter() {
+            fields
+                .entry(name.to_vec())
+                .or_default()
+                .push(value.to_vec());
+        }
+        fields
+    }
+    let (mut left, mut right) = (fields(left), fields(right));
+    let mut names: Vec<_> = left.keys().chain(right.keys()).cloned().collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let l = left.remove(&name).unwrap_or_default();
+            let r = right.remove(&name).unwrap_or_default();
+            (l != r).then_some(FieldChange {
+                name,
+                left: l,
+                right: r,
+            })
+        })
+        .collect()
+}
+
+struct Side<R> {
+    reader: JournalExportRead<R>,
+    head: Option<OwnedEntry>,
+}
+
+impl<R: Read> Side<R> {
+    fn advance(&mut self) -> Result<(), JournalExportReadError> {
+        self.head = self
+            .reader
+            .parse_next()?
+            .map(|()| self.reader.get_entry().to_owned());
+        Ok(())
+    }
+
+    fn head_timestamp(&self) -> Option<Option<u64>> {
+        self.head.as_ref().map(|e| e.realtime_timestamp())
+    }
+
+    /// Takes all consecutive entries with timestamp `ts` from the stream.
+    fn take_group(&mut self, ts: Option<u64>) -> Result<Vec<OwnedEntry>, JournalExportReadError> {
+        let mut group = vec![];
+        while self.head_timestamp() == Some(ts) {
+            group.extend(self.head.take());
+            self.advance()?;
+        }
+        Ok(group)
+    }
+}
+
+pub struct ExportDiff<A, B> {
+    left: Side<A>,
+    right: Side<B>,
+    primed: bool,
+    pending: VecDeque<Difference>,
+    matched: usize,
+}
+
+impl<A: Read, B: Read> ExportDiff<A, B> {
+    pub fn new(left: JournalExportRead<A>, right: JournalExportRead<B>) -> Self {
+        Self {
+            left: Side {
+                reader: left,
+                head: None,
+            },
+            right: Side {
+                reader: right,
+                head: None,
+            },
+            primed: false,
+            pending: VecDeque::new(),
+            matched: 0,
+        }
+    }
+
+    /// Returns the next difference or `None` once both streams are exhausted.
+    pub fn next_difference(&mut self) -> Result<Option<Difference>, JournalExportReadError> {
+        if !self.primed {
+            self.left.advance()?;
+            self.right.advance()?;
+            self.primed = true;
+        }
+        loop {
+            if let Some(d) = self.pending.pop_front() {
+                return Ok(Some(d));
+            }
+            let ts = match (self.left.head_timestamp(), self.right.head_timestamp()) {
+                (None, None) => return Ok(None),
+                (Some(l), Some(r)) => l.min(r),
+                (Some(ts), None) | (None, Some(ts)) => ts,
+            };
+            let left = self.left.take_group(ts)?;
+            let right = self.right.take_group(ts)?;
+            self.align(left, right);
+        }
+    }
+
+    /// The number of entries that were contained in both streams so far
+    /// (including changed entries).
+    pub fn matched(&self) -> usize {
+        self.matched
+    }
+
+    fn align(&mut self, left: Vec<OwnedEntry>, right: Vec<OwnedEntry>) {
+        fn key(e: &OwnedEntry) -> Vec<u8> {
+            match e.get(b"__CURSOR") {
+                Some(cursor) => cursor.to_vec(),
+                None => e.canonical_bytes(),
+            }
+        }
+        let mut candidates: HashMap<Vec<u8>, VecDeque<usize>> = HashMap::new();
+        for (i, e) in right.iter().enumerate() {
+            candidates.entry(key(e)).or_default().push_back(i);
+        }
+        let mut right: Vec<_> = right.into_iter().map(Some).collect();
+        for l in left {
+            let matching = candidates.get_mut(&key(&l)).and_then(|c| c.pop_front());
+            match matching.and_then(|i| right[i].take()) {
+                Some(r) => {
+                    self.matched += 1;
+                    if l != r {
+                        self.pending.push_back(Difference::Changed(l, r));
+                    }
+                }
+                None => self.pending.push_back(Difference::Left(l)),
+            }
+        }
+        self.pending
+            .extend(right.into_iter().flatten().map(Difference::Right));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        generate::EntryGenerator,
+        journald::{Entry, JournalExportRead},
+        testutil::write_string,
+    };
+
+    use super::{field_changes, Difference, ExportDiff};
+
+    fn entries(n: usize) -> Vec<Vec<u8>> {
+        let mut generator = EntryGenerator::new(4);
+        (0..n)
+            .map(|_| {
+                let mut e = vec![];
+                generator.write_entry(&mut e);
+                e
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reports_missing_and_changed_entries() {
+        let entries = entries(20);
+        let left = entries.concat();
+        let mut right = entries.clone();
+        right.remove(3);
+        right.remove(10);
+        let mut extra = vec![];
+        write_string(&mut extra, "__REALTIME_TIMESTAMP", "1");
+        write_string(&mut extra, "MESSAGE", "only right");
+        extra.push(b'\n');
+        right.insert(0, extra);
+        let changed = String::from_utf8(right[5].clone())
+            .unwrap()
+            .replace("_HOSTNAME=localhost", "_HOSTNAME=remote");
+        right[5] = changed.into_bytes();
+        let right = right.concat();
+
+        let mut diff = ExportDiff::new(
+            JournalExportRead::new(&left[..]),
+            JournalExportRead::new(&right[..]),
+        );
+        let mut found = vec![];
+        while let Some(d) = diff.next_difference().unwrap() {
+            found.push(match d {
+                Difference::Left(e) => ('<', e.realtime_timestamp()),
+                Difference::Right(e) => ('>', e.realtime_timestamp()),
+                Difference::Changed(l, r) => {
+                    let changes = field_changes(&l, &r);
+                    assert_eq!(changes.len(), 1);
+                    assert_eq!(changes[0].name, b"_HOSTNAME");
+                    assert_eq!(changes[0].right, vec![b"remote".to_vec()]);
+                    ('~', l.realtime_timestamp())
+                }
+            });
+        }
+        let ts = |i: usize| {
+            let mut jreader = JournalExportRead::new(&entries[i][..]);
+            jreader.parse_next().unwrap();
+            jreader.get_entry().realtime_timestamp()
+        };
+        assert_eq!(
+            found,
+            vec![('>', Some(1)), ('<', ts(3)), ('~', ts(5)), ('<', ts(11))]
+        );
+        assert_eq!(diff.matched(), 18);
+    }
+}
@@ -0,0 +1,527 @@
+//! A small expression language to select and rewrite entries.
+//!
+//! A [Predicate] selects entries, e.g.
+//! `PRIORITY <= 3 && MESSAGE =~ "oom" && !_SYSTEMD_UNIT`, and a [Map] assigns
+//! fields, e.g. `SEVERITY = "prio-" + PRIORITY; _CMDLINE = null`. Expressions
+//! are compiled once; fields are looked up in the entry only when an
+//! expression needs them.
+//!
+//! Identifiers refer to fields and evaluate to `null` if the entry lacks the
+//! field. Literals are strings (`"..."` with the escapes `\"`, `\\`, `\n` and
+//! `\t`), integers, `true`, `false` and `null`. The operators, by increasing
+//! precedence, are:
+//!
+//! - `||`, `&&` and `!`, which treat `null`, `false`, `0` and empty strings as
+//!   false,
+//! - `==`, `!=`, `<`, `<=`, `>` and `>=`, which compare numerically if both
+//!   sides are integers and bytewise otherwise; ordering comparisons with
+//!   `null` are false,
+//! - `=~` and `!~`, which match against a regular expression given as string
+//!   literal,
+//! - `+`, which adds integers and concatenates other values.
+//!
+//! Assigning `null` removes a field. All assignments of a [Map] see the
+//! original entry.
+
+use std::{borrow::Cow, cmp::Ordering, io};
+
+use regex::bytes::Regex;
+use thiserror::Error;
+
+use crate::{
+    journald::{parser::FieldType, write_field, Entry},
+    transform::{EntryView, Transform, TransformResult},
+};
+
+#[derive(Error, Debug)]
+pub enum ExprError {
+    #[error("{message} at position {position}")]
+    Syntax { position: usize, message: String },
+    #[error("invalid regular expression: {0}")]
+    Regex(#[from] regex::Error),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+enum Token {
+    Ident(Vec<u8>),
+    Str(Vec<u8>),
+    Int(i64),
+    Op(&'static str),
+}
+
+const OPERATORS: [&str; 16] = [
+    "||", "&&", "==", "!=", "<=", ">=", "=~", "!~", "!", "<", ">", "+", "(", ")", "=", ";",
+];
+
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>, ExprError> {
+    let bytes = s.as_bytes();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        let start = i;
+        if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c == b'"' {
+            let mut value = vec![];
+            i += 1;
+            loop {
+                match bytes.get(i) {
+                    None => return Err(syntax(start, "unterminated string")),
+                    Some(b'"') => break,
+                    Some(b'\\') => {
+                        value.push(match bytes.get(i + 1) {
+                            Some(b'n') => b'\n',
+                            Some(b't') => b'\t',
+                            Some(c @ (b'"' | b'\\')) => *c,
+                            _ => return Err(syntax(i, "invalid escape")),
+                        });
+                        i += 2;
+                    }
+                    Some(c) => {
+                        value.push(*c);
+                        i += 1;
+                    }
+                }
+            }
+            i += 1;
+            tokens.push((start, Token::Str(value)));
+        } else if c.is_ascii_digit() {
+            while bytes.get(i).is_some_and(u8::is_ascii_digit) {
+                i += 1;
+            }
+            let n = s[start..i]
+                .parse()
+                .map_err(|_| syntax(start, "integer out of range"))?;
+            tokens.push((start, Token::Int(n)));
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            while bytes
+                .get(i)
+                .is_some_and(|c| c.is_ascii_alphanumeric() || *c == b'_')
+            {
+                i += 1;
+            }
+            tokens.push((start, Token::Ident(bytes[start..i].to_vec())));
+        } else {
+            let op = OPERATORS
+                .iter()
+                .find(|op| bytes[i..].starts_with(op.as_bytes()))
+                .ok_or_else(|| syntax(start, "unexpected character"))?;
+            i += op.len();
+            tokens.push((start, Token::Op(op)));
+        }
+    }
+    Ok(tokens)
+}
+
+fn syntax(position: usize, message: &str) -> ExprError {
+    ExprError::Syntax {
+        position,
+        message: message.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Compare {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Field(Vec<u8>),
+    Literal(Value<'static>),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(Compare, Box<Expr>, Box<Expr>),
+    Matches(Box<Expr>, Regex),
+    Add(Box<Expr>, Box<Expr>),
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+enum Value<'a> {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Bytes(Cow<'a, [u8]>),
+}
+
+impl Value<'_> {
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Bool(b) => *b,
+            Value::Int(n) => *n != 0,
+            Value::Bytes(b) => !b.is_empty(),
+        }
+    }
+
+    fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(*n),
+            Value::Bytes(b) => std::str::from_utf8(b).ok()?.parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn to_bytes(&self) -> Option<Cow<'_, [u8]>> {
+        match self {
+            Value::Null => None,
+            Value::Bool(b) => Some(Cow::Owned(b.to_string().into_bytes())),
+            Value::Int(n) => Some(Cow::Owned(n.to_string().into_bytes())),
+            Value::Bytes(b) => Some(Cow::Borrowed(b)),
+        }
+    }
+
+    fn compare(&self, other: &Value) -> Option<Ordering> {
+        if let (Some(a), Some(b)) = (self.as_int(), other.as_int()) {
+            return Some(a.cmp(&b));
+        }
+        Some(self.to_bytes()?.cmp(&other.to_bytes()?))
+    }
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn new(s: &str) -> Result<Self, ExprError> {
+        Ok(Self {
+            tokens: tokenize(s)?,
+            pos: 0,
+            end: s.len(),
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |(p, _)| *p)
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Op(o)) if *o == op) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), ExprError> {
+        match self.eat(op) {
+            true => Ok(()),
+            false => Err(syntax(self.position(), &format!("expected `{}`", op))),
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos == self.tokens.len()
+    }
+
+    fn expr(&mut self) -> Result<Expr, ExprError> {
+        let mut lhs = self.and()?;
+        while self.eat("||") {
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Expr, ExprError> {
+        let mut lhs = self.unary()?;
+        while self.eat("&&") {
+            lhs = Expr::And(Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ExprError> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ExprError> {
+        let lhs = self.sum()?;
+        for (op, negated) in [("=~", false), ("!~", true)] {
+            if !self.eat(op) {
+                continue;
+            }
+            let position = self.position();
+            let Some(Token::Str(pattern)) = self.peek().cloned() else {
+                return Err(syntax(position, "expected a regular expression string"));
+            };
+            self.pos += 1;
+            let pattern = String::from_utf8(pattern)
+                .map_err(|_| syntax(position, "regular expression is not UTF-8"))?;
+            let matches = Expr::Matches(Box::new(lhs), Regex::new(&pattern)?);
+            return Ok(match negated {
+                true => Expr::Not(Box::new(matches)),
+                false => matches,
+            });
+        }
+        for (op, cmp) in [
+            ("==", Compare::Eq),
+            ("!=", Compare::Ne),
+            ("<=", Compare::Le),
+            (">=", Compare::Ge),
+            ("<", Compare::Lt),
+            (">", Compare::Gt),
+        ] {
+            if self.eat(op) {
+                return Ok(Expr::Compare(cmp, Box::new(lhs), Box::new(self.sum()?)));
+            }
+        }
+        Ok(lhs)
+    }
+
+    fn sum(&mut self) -> Result<Expr, ExprError> {
+        let mut lhs = self.atom()?;
+        while self.eat("+") {
+            lhs = Expr::Add(Box::new(lhs), Box::new(self.atom()?));
+        }
+        Ok(lhs)
+    }
+
+    fn atom(&mut self) -> Result<Expr, ExprError> {
+        let position = self.position();
+        let Some(token) = self.peek().cloned() else {
+            return Err(syntax(position, "unexpected end of expression"));
+        };
+        self.pos += 1;
+        Ok(match token {
+            Token::Str(s) => Expr::Literal(Value::Bytes(Cow::Owned(s))),
+            Token::Int(n) => Expr::Literal(Value::Int(n)),
+            Token::Ident(name) => match &name[..] {
+                b"true" => Expr::Literal(Value::Bool(true)),
+                b"false" => Expr::Literal(Value::Bool(false)),
+                b"null" => Expr::Literal(Value::Null),
+                _ => Expr::Field(name),
+            },
+            Token::Op("(") => {
+                let e = self.expr()?;
+                self.expect(")")?;
+                e
+            }
+            Token::Op(_) => return Err(syntax(position, "expected a value")),
+        })
+    }
+}
+
+impl Expr {
+    fn eval<'a>(&'a self, entry: &'a dyn Entry) -> Value<'a> {
+        match self {
+            Expr::Field(name) => entry
+                .get(name)
+                .map_or(Value::Null, |v| Value::Bytes(Cow::Borrowed(v))),
+            Expr::Literal(v) => v.clone(),
+            Expr::Not(e) => Value::Bool(!e.eval(entry).truthy()),
+            Expr::And(a, b) => Value::Bool(a.eval(entry).truthy() && b.eval(entry).truthy()),
+            Expr::Or(a, b) => Value::Bool(a.eval(entry).truthy() || b.eval(entry).truthy()),
+            Expr::Compare(cmp, a, b) => {
+                let (a, b) = (a.eval(entry), b.eval(entry));
+                let ord = a.compare(&b);
+                Value::Bool(match cmp {
+                    Compare::Eq => a == b || ord == Some(Ordering::Equal),
+                    Compare::Ne => a != b && ord != Some(Ordering::Equal),
+                    Compare::Lt => ord == Some(Ordering::Less),
+                    Compare::Le => matches!(ord, Some(Ordering::Less | Ordering::Equal)),
+                    Compare::Gt => ord == Some(Ordering::Greater),
+                    Compare::Ge => matches!(ord, Some(Ordering::Greater | Ordering::Equal)),
+                })
+            }
+            Expr::Matches(e, regex) => {
+                let v = e.eval(entry);
+                Value::Bool(v.to_bytes().is_some_and(|b| regex.is_match(&b)))
+            }
+            Expr::Add(a, b) => {
+                let (a, b) = (a.eval(entry), b.eval(entry));
+                if let (Some(x), Some(y)) = (a.as_int(), b.as_int()) {
+                    if let Some(sum) = x.checked_add(y) {
+                        return Value::Int(sum);
+                    }
+                }
+                match (a.to_bytes(), b.to_bytes()) {
+                    (Some(x), Some(y)) => Value::Bytes(Cow::Owned([x, y].concat())),
+                    _ => Value::Null,
+                }
+            }
+        }
+    }
+}
+
+/// Selects entries for which an expression is true.
+#[derive(Debug, Clone)]
+pub struct Predicate(Expr);
+
+impl Predicate {
+    pub fn new(s: &str) -> Result<Self, ExprError> {
+        let mut parser = Parser::new(s)?;
+        let expr = parser.expr()?;
+        if !parser.at_end() {
+            return Err(syntax(parser.position(), "unexpected token"));
+        }
+        Ok(Self(expr))
+    }
+
+    pub fn selects(&self, entry: &dyn Entry) -> bool {
+        self.0.eval(entry).truthy()
+    }
+}
+
+impl Transform for Predicate {
+    fn apply(&mut self, entry: EntryView<'_>) -> io::Result<TransformResult> {
+        Ok(match self.selects(entry.entry) {
+            true => TransformResult::Keep,
+            false => TransformResult::Drop,
+        })
+    }
+}
+
+/// Assigns fields, given as `NAME = EXPR` separated by `;`.
+#[derive(Debug, Clone)]
+pub struct Map(Vec<(Vec<u8>, Expr)>);
+
+impl Map {
+    pub fn new(s: &str) -> Result<Self, ExprError> {
+        let mut parser = Parser::new(s)?;
+        let mut assignments = vec![];
+        while !parser.at_end() {
+            let position = parser.position();
+            let Some(Token::Ident(name)) = parser.peek().cloned() else {
+                return Err(syntax(position, "expected a field name"));
+            };
+            parser.pos += 1;
+            parser.expect("=")?;
+            assignments.push((name, parser.expr()?));
+            if !parser.at_end() {
+                parser.expect(";")?;
+            }
+        }
+        Ok(Self(assignments))
+    }
+
+    /// Appends the entry with the assigned fields to `out`. Fields of the
+    /// entry that are assigned are replaced. Returns `false` and leaves `out`
+    /// untouched if the result has no fields.
+    pub fn apply(&self, entry: &dyn Entry, out: &mut Vec<u8>) -> bool {
+        let values: Vec<_> = self.0.iter().map(|(_, e)| e.eval(entry)).collect();
+        let start = out.len();
+        for (name, value, typ) in entry.iter() {
+            if !self.0.iter().any(|(n, _)| n == name) {
+                write_field(out, name, value, &typ);
+            }
+        }
+        for ((name, _), value) in self.0.iter().zip(values.iter()) {
+            if let Some(value) = value.to_bytes() {
+                write_field(out, name, &value, &FieldType::String);
+            }
+        }
+        if out.len() == start {
+            return false;
+        }
+        out.push(b'\n');
+        true
+    }
+}
+
+impl Transform for Map {
+    fn apply(&mut self, entry: EntryView<'_>) -> io::Result<TransformResult> {
+        Ok(match Map::apply(self, entry.entry, entry.out) {
+            true => TransformResult::Replace,
+            false => TransformResult::Drop,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        journald::{Entry, JournalExportRead},
+        testutil::write_string,
+    };
+
+    use super::{ExprError, Map, Predicate};
+
+    fn entry(fields: &[(&str, &str)]) -> Vec<u8> {
+        let mut out = vec![];
+        for (name, value) in fields {
+            write_string(&mut out, name, value);
+        }
+        out.push(b'\n');
+        out
+    }
+
+    fn selects(expr: &str, e: &[u8]) -> bool {
+        let mut jreader = JournalExportRead::new(e);
+        jreader.parse_next().unwrap();
+        Predicate::new(expr).unwrap().selects(&jreader.get_entry())
+    }
+
+    #[test]
+    fn evaluates_predicates() {
+        let e = entry(&[("PRIORITY", "3"), ("MESSAGE", "Out of memory: Killed")]);
+        assert!(selects(r#"PRIORITY <= 3 && MESSAGE =~ "(?i)memory""#, &e));
+        assert!(selects("PRIORITY == \"3\" && PRIORITY != 10", &e));
+        assert!(!selects("PRIORITY > 10 || _PID", &e));
+        assert!(selects("!_PID && (MESSAGE !~ \"^x\")", &e));
+        assert!(selects(
+            "PRIORITY + 1 == 4 && MESSAGE + \"!\" =~ \"Killed!$\"",
+            &e
+        ));
+        assert!(selects("_PID == null && !(_PID < 5)", &e));
+        assert!(selects("MESSAGE > \"A\"", &e));
+
+        for (expr, position) in [
+            ("PRIORITY <=", 11),
+            ("(PRIORITY", 9),
+            ("MESSAGE =~ PRIORITY", 11),
+            ("PRIORITY 3", 9),
+            ("\"open", 0),
+        ] {
+            match Predicate::new(expr) {
+                Err(ExprError::Syntax { position: p, .. }) => assert_eq!(p, position, "{}", expr),
+                _ => panic!("{} should not compile", expr),
+            }
+        }
+        assert!(matches!(
+            Predicate::new("MESSAGE =~ \"(\""),
+            Err(ExprError::Regex(_))
+        ));
+    }
+
+    #[test]
+    fn assigns_fields() {
+        let e = entry(&[("PRIORITY", "3"), ("MESSAGE", "a"), ("_CMDLINE", "x")]);
+        let map = Map::new(
+            r#"MESSAGE = "[" + PRIORITY + "] " + MESSAGE; LEVEL = PRIORITY + 1; _CMDLINE = null"#,
+        )
+        .unwrap();
+        let mut jreader = JournalExportRead::new(&e[..]);
+        jreader.parse_next().unwrap();
+        let mut out = vec![];
+        assert!(map.apply(&jreader.get_entry(), &mut out));
+        assert_eq!(
+            out,
+            entry(&[("PRIORITY", "3"), ("MESSAGE", "[3] a"), ("LEVEL", "4")])
+        );
+        let mut jreader = JournalExportRead::new(&out[..]);
+        jreader.parse_next().unwrap();
+        assert_eq!(jreader.get_entry().get(b"LEVEL"), Some(&b"4"[..]));
+
+        assert!(Map::new("MESSAGE == 1").is_err());
+        assert!(Map::new("A = 1 B = 2").is_err());
+    }
+}
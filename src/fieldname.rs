@@ -2,9 +2,11 @@
 //!
 //! See: [systemd.journal-fields](https://www.freedesktop.org/software/systemd/man/254/systemd.journal-fields.html)
 
-use std::borrow::Cow;
+use std::{borrow::Cow, fmt, str::FromStr};
 
 use phf::phf_map;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
 
 static KNOWN_NAMES: phf::Map<&'static [u8], Known> = phf_map! {
     // User Fields
@@ -86,7 +88,81 @@ static KNOWN_NAMES: phf::Map<&'static [u8], Known> = phf_map! {
     b"__SEQNUM_ID" => Known::__SeqnumId
 };
 
-#[derive(Clone, PartialEq, Eq)]
+/// All known fields, in the order of their documentation.
+pub const ALL_KNOWN: &[Known] = &[
+    Known::Message,
+    Known::MessageId,
+    Known::Priority,
+    Known::CodeFile,
+    Known::CodeLine,
+    Known::CodeFunc,
+    Known::Errno,
+    Known::InvocationId,
+    Known::UserInvocationId,
+    Known::SyslogFacility,
+    Known::SyslogIdentifier,
+    Known::SyslogPid,
+    Known::SyslogTimestamp,
+    Known::SyslogRaw,
+    Known::Documentation,
+    Known::Tid,
+    Known::Unit,
+    Known::UserUnit,
+    Known::_Pid,
+    Known::_Uid,
+    Known::_Gid,
+    Known::_Comm,
+    Known::_Exe,
+    Known::_Cmdline,
+    Known::_CapEffective,
+    Known::_AuditSession,
+    Known::_AuditLoginuid,
+    Known::_SystemdCgroup,
+    Known::_SystemdSlice,
+    Known::_SystemdUnit,
+    Known::_SystemdUserUnit,
+    Known::_SystemdUserSlice,
+    Known::_SystemdSession,
+    Known::_SystemdOwnerUid,
+    Known::_SelinuxContext,
+    Known::_SourceRealtimeTimestamp,
+    Known::_BootId,
+    Known::_MachineId,
+    Known::_SystemdInvocationId,
+    Known::_Hostname,
+    Known::_Transport,
+    Known::_StreamId,
+    Known::_LineBreak,
+    Known::_Namespace,
+    Known::_RuntimeScope,
+    Known::_KernelDevice,
+    Known::_KernelSubsystem,
+    Known::_UdevSysname,
+    Known::_UdevDevnode,
+    Known::_UdevDevlink,
+    Known::CoredumpUnit,
+    Known::CoredumpUserUnit,
+    Known::ObjectPid,
+    Known::ObjectUid,
+    Known::ObjectGid,
+    Known::ObjectComm,
+    Known::ObjectExe,
+    Known::ObjectCmdline,
+    Known::ObjectAuditSession,
+    Known::ObjectAuditLoginuid,
+    Known::ObjectSystemdCgroup,
+    Known::ObjectSystemdSession,
+    Known::ObjectSystemdOwnerUid,
+    Known::ObjectSystemdUnit,
+    Known::ObjectSystemdUserUnit,
+    Known::__Cursor,
+    Known::__RealtimeTimestamp,
+    Known::__MonotonicTimestamp,
+    Known::__Seqnum,
+    Known::__SeqnumId,
+];
+
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Known {
     Message,
     MessageId,
@@ -162,6 +238,14 @@ pub enum Known {
 }
 
 impl Known {
+    /// The known fields whose names start with `prefix`, e.g. `OBJECT_` for
+    /// the fields describing the process a message is about.
+    pub fn with_prefix(prefix: &[u8]) -> impl Iterator<Item = &'static Known> + '_ {
+        ALL_KNOWN
+            .iter()
+            .filter(move |k| k.as_bytes().starts_with(prefix))
+    }
+
     pub fn as_bytes(&self) -> &'static [u8] {
         match self {
             // User Fields
@@ -245,7 +329,56 @@ impl Known {
     }
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Error, PartialEq, Eq, Debug, Clone)]
+pub enum FieldnameError {
+    #[error("{0} is not a field documented by systemd")]
+    Unknown(String),
+    /// Field names consist of uppercase letters, digits and underscores, do
+    /// not start with a digit and are at most 64 bytes long.
+    #[error("{0:?} is not a valid field name")]
+    Invalid(String),
+}
+
+impl fmt::Display for Known {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // All known names are ASCII.
+        f.write_str(std::str::from_utf8(self.as_bytes()).unwrap())
+    }
+}
+
+impl FromStr for Known {
+    type Err = FieldnameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        KNOWN_NAMES
+            .get(s.as_bytes())
+            .cloned()
+            .ok_or_else(|| FieldnameError::Unknown(s.to_string()))
+    }
+}
+
+impl TryFrom<&str> for Known {
+    type Error = FieldnameError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Serialize for Known {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Known {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = Cow::<str>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Fieldname<'a> {
     Known(Known),
     Unknown(Cow<'a, [u8]>),
@@ -260,7 +393,68 @@ impl<'a> From<&'a [u8]> for Fieldname<'a> {
     }
 }
 
+impl<'a> TryFrom<&'a str> for Fieldname<'a> {
+    type Error = FieldnameError;
+
+    /// Accepts valid field names only, unlike `From<&[u8]>`.
+    fn try_from(s: &'a str) -> Result<Self, Self::Error> {
+        let b = s.as_bytes();
+        let valid = !b.is_empty()
+            && b.len() <= 64
+            && !b[0].is_ascii_digit()
+            && b.iter()
+                .all(|&c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == b'_');
+        match valid {
+            true => Ok(Self::from(b)),
+            false => Err(FieldnameError::Invalid(s.to_string())),
+        }
+    }
+}
+
+impl FromStr for Fieldname<'static> {
+    type Err = FieldnameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Fieldname::try_from(s).map(|f| f.to_owned())
+    }
+}
+
+impl fmt::Display for Fieldname<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fieldname::Known(k) => k.fmt(f),
+            Fieldname::Unknown(name) => f.write_str(&String::from_utf8_lossy(name)),
+        }
+    }
+}
+
+impl Serialize for Fieldname<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Fieldname<'static> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = Cow::<str>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 impl<'a> Fieldname<'a> {
+    /// Looks up `name` ignoring ASCII case, e.g. `message` as
+    /// [Known::Message]. Unknown names are uppercased, as journald requires.
+    pub fn from_ascii_uppercase(name: &'a [u8]) -> Self {
+        if !name.iter().any(u8::is_ascii_lowercase) {
+            return Self::from(name);
+        }
+        let upper = name.to_ascii_uppercase();
+        match KNOWN_NAMES.get(&upper[..]) {
+            Some(v) => Fieldname::Known(v.clone()),
+            None => Fieldname::Unknown(Cow::Owned(upper)),
+        }
+    }
+
     pub fn to_owned(&self) -> Fieldname<'static> {
         match self {
             Self::Unknown(Cow::Borrowed(s)) => Fieldname::Unknown(Cow::Owned(s.to_vec())),
@@ -274,7 +468,7 @@ impl<'a> Fieldname<'a> {
 mod tests {
     use std::borrow::Cow;
 
-    use super::{Fieldname, Known};
+    use super::{Fieldname, FieldnameError, Known, ALL_KNOWN, KNOWN_NAMES};
 
     #[test]
     fn simple_lookup_succceeds() {
@@ -289,4 +483,68 @@ mod tests {
         let f = Fieldname::from(s.as_bytes()).to_owned();
         assert!(matches!(f, Fieldname::Unknown(Cow::Owned(x)) if x == b"__CURSORS"));
     }
+
+    #[test]
+    fn lookup_by_case_and_prefix() {
+        assert!(
+            Fieldname::from_ascii_uppercase(b"_systemd_Unit")
+                == Fieldname::Known(Known::_SystemdUnit)
+        );
+        assert!(matches!(
+            Fieldname::from_ascii_uppercase(b"my_field"),
+            Fieldname::Unknown(Cow::Owned(x)) if x == b"MY_FIELD"
+        ));
+        assert!(matches!(
+            Fieldname::from_ascii_uppercase(b"MY_FIELD"),
+            Fieldname::Unknown(Cow::Borrowed(b"MY_FIELD"))
+        ));
+
+        let syslog: Vec<_> = Known::with_prefix(b"SYSLOG_")
+            .map(Known::as_bytes)
+            .collect();
+        assert_eq!(
+            syslog,
+            [
+                &b"SYSLOG_FACILITY"[..],
+                b"SYSLOG_IDENTIFIER",
+                b"SYSLOG_PID",
+                b"SYSLOG_TIMESTAMP",
+                b"SYSLOG_RAW"
+            ]
+        );
+
+        assert_eq!(ALL_KNOWN.len(), KNOWN_NAMES.len());
+        for known in ALL_KNOWN {
+            assert!(KNOWN_NAMES.get(known.as_bytes()) == Some(known));
+        }
+    }
+
+    #[test]
+    fn names_are_displayed_and_parsed() {
+        assert_eq!(Known::_SystemdUnit.to_string(), "_SYSTEMD_UNIT");
+        assert_eq!("MESSAGE_ID".parse(), Ok(Known::MessageId));
+        assert_eq!(
+            Known::try_from("MY_FIELD"),
+            Err(FieldnameError::Unknown("MY_FIELD".to_string()))
+        );
+
+        let f: Fieldname = "MY_FIELD".parse().unwrap();
+        assert_eq!(f.to_string(), "MY_FIELD");
+        assert_eq!(
+            Fieldname::try_from("PRIORITY"),
+            Ok(Fieldname::Known(Known::Priority))
+        );
+        for invalid in ["", "message", "1ST", "A-B", &"A".repeat(65)] {
+            assert!(matches!(
+                invalid.parse::<Fieldname>(),
+                Err(FieldnameError::Invalid(_))
+            ));
+        }
+
+        let json = serde_json::to_string(&[Fieldname::Known(Known::Message), f]).unwrap();
+        assert_eq!(json, r#"["MESSAGE","MY_FIELD"]"#);
+        let parsed: Vec<Fieldname> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed[0], Fieldname::Known(Known::Message));
+        assert!(serde_json::from_str::<Known>(r#""NOPE""#).is_err());
+    }
 }
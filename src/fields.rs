@@ -0,0 +1,129 @@
+//! Discover the fields of entries.
+//!
+//! [FieldCatalog] lists every field name of its input with the number of
+//! entries and values, the bytes of the values and an example value. Names
+//! that are not among the fields documented by systemd are marked, which
+//! shows what applications add before selecting fields.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{fieldname::Fieldname, format::BinaryRendering, journald::Entry};
+
+/// The length up to which example values are kept.
+const EXAMPLE_LEN: usize = 80;
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct FieldInfo {
+    pub name: String,
+    /// Whether systemd documents the field.
+    pub known: bool,
+    /// The number of entries with the field.
+    pub entries: u64,
+    /// The number of values, more than `entries` if the field is repeated.
+    pub values: u64,
+    /// The bytes of all values.
+    pub bytes: u64,
+    /// The first non-empty value, shortened, with bytes that are not valid
+    /// UTF-8 as `\xNN`.
+    pub example: Option<String>,
+}
+
+#[derive(Default)]
+pub struct FieldCatalog {
+    fields: BTreeMap<Vec<u8>, FieldInfo>,
+    entries: u64,
+}
+
+impl FieldCatalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, entry: &impl Entry) {
+        self.entries += 1;
+        // The names of the entry so far, to count repeated fields once.
+        let mut seen: Vec<&[u8]> = vec![];
+        for (name, value, _) in entry.iter() {
+            let field = match self.fields.get_mut(name) {
+                Some(field) => field,
+                None => self
+                    .fields
+                    .entry(name.to_vec())
+                    .or_insert_with(|| FieldInfo {
+                        name: String::from_utf8_lossy(name).into_owned(),
+                        known: matches!(Fieldname::from(name), Fieldname::Known(_)),
+                        ..FieldInfo::default()
+                    }),
+            };
+            field.values += 1;
+            field.bytes += value.len() as u64;
+            if field.example.is_none() && !value.is_empty() {
+                let example = BinaryRendering::HexEscape.render(value).unwrap_or_default();
+                let end = example.floor_char_boundary(EXAMPLE_LEN);
+                field.example = Some(match end < example.len() {
+                    true => format!("{}…", &example[..end]),
+                    false => example.into_owned(),
+                });
+            }
+            if !seen.contains(&name) {
+                seen.push(name);
+                field.entries += 1;
+            }
+        }
+    }
+
+    /// The number of entries pushed.
+    pub fn entries(&self) -> u64 {
+        self.entries
+    }
+
+    /// The fields ordered by name.
+    pub fn into_fields(self) -> Vec<FieldInfo> {
+        self.fields.into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{journald::JournalExportRead, testutil::write_string};
+
+    use super::FieldCatalog;
+
+    #[test]
+    fn lists_fields() {
+        let mut stream = vec![];
+        write_string(&mut stream, "MESSAGE", "hello");
+        write_string(&mut stream, "TAG", "");
+        write_string(&mut stream, "TAG", "b");
+        stream.push(b'\n');
+        write_string(&mut stream, "MESSAGE", "x".repeat(100));
+        stream.push(b'\n');
+
+        let mut catalog = FieldCatalog::new();
+        for e in JournalExportRead::new(&stream[..]) {
+            catalog.push(&e);
+        }
+        assert_eq!(catalog.entries(), 2);
+        let fields: Vec<_> = catalog
+            .into_fields()
+            .into_iter()
+            .map(|f| (f.name, f.known, f.entries, f.values, f.bytes, f.example))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                (
+                    "MESSAGE".to_string(),
+                    true,
+                    2,
+                    2,
+                    105,
+                    Some("hello".to_string())
+                ),
+                ("TAG".to_string(), false, 1, 2, 1, Some("b".to_string())),
+            ]
+        );
+    }
+}
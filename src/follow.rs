@@ -0,0 +1,212 @@
+//! Follow export files as they grow.
+//!
+//! [Follow] reads an export file like `tail -f`: at its end, it waits for
+//! more data instead of reporting the end of the input. If the file is
+//! truncated or replaced, e.g. by log rotation, the new file is read from the
+//! start. Following a directory reads its most recently modified export file
+//! and switches to a newer one once the current file was read to its end.
+//!
+//! Compressed files cannot be followed. The end of the input is only reported
+//! once the [Shutdown] set with [Follow::with_shutdown] is requested.
+
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{shutdown::Shutdown, source};
+
+pub struct Follow {
+    /// The followed directory, if any.
+    dir: Option<PathBuf>,
+    path: PathBuf,
+    file: File,
+    pos: u64,
+    interval: Duration,
+    shutdown: Option<Shutdown>,
+}
+
+impl Follow {
+    /// Follows the export file `path` from its start or, if `path` is a
+    /// directory, its newest export file.
+    pub fn new(path: &Path) -> io::Result<Self> {
+        let (dir, path) = if path.is_dir() {
+            let newest = newest_export(path)?.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no export files in {}", path.display()),
+                )
+            })?;
+            (Some(path.to_path_buf()), newest)
+        } else {
+            (None, path.to_path_buf())
+        };
+        Ok(Self {
+            file: File::open(&path)?,
+            dir,
+            path,
+            pos: 0,
+            interval: Duration::from_millis(250),
+            shutdown: None,
+        })
+    }
+
+    /// Continues at byte `offset` of the current file.
+    pub fn with_offset(mut self, offset: u64) -> io::Result<Self> {
+        self.pos = self.file.seek(SeekFrom::Start(offset))?;
+        Ok(self)
+    }
+
+    /// How long to wait before checking for new data; defaults to 250ms.
+    pub fn with_interval(self, interval: Duration) -> Self {
+        Self { interval, ..self }
+    }
+
+    /// Ends the input once `shutdown` is requested.
+    pub fn with_shutdown(self, shutdown: Shutdown) -> Self {
+        Self {
+            shutdown: Some(shutdown),
+            ..self
+        }
+    }
+
+    /// The file that is currently read.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Switches to a new file if the current one was replaced, truncated or,
+    /// when following a directory, superseded by a newer one.
+    fn reopen(&mut self) -> io::Result<bool> {
+        if let Some(dir) = &self.dir {
+            if let Some(newest) = newest_export(dir)? {
+                if newest != self.path {
+                    self.open(newest)?;
+                    return Ok(true);
+                }
+            }
+        }
+        let meta = match fs::metadata(&self.path) {
+            Ok(meta) => meta,
+            // Rotated away; the new file has not been created yet.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        if meta.len() < self.pos || !same_file(&meta, &self.file.metadata()?) {
+            self.open(self.path.clone())?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn open(&mut self, path: PathBuf) -> io::Result<()> {
+        self.file = File::open(&path)?;
+        self.path = path;
+        self.pos = 0;
+        Ok(())
+    }
+}
+
+impl Read for Follow {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.file.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                self.pos += n as u64;
+                return Ok(n);
+            }
+            if self.shutdown.as_ref().is_some_and(Shutdown::is_requested) {
+                return Ok(0);
+            }
+            if !self.reopen()? {
+                std::thread::sleep(self.interval);
+            }
+        }
+    }
+}
+
+/// The uncompressed export file in `dir` that was modified last; ties are
+/// broken by the file name.
+fn newest_export(dir: &Path) -> io::Result<Option<PathBuf>> {
+    let mut newest: Option<(SystemTime, PathBuf)> = None;
+    for dirent in fs::read_dir(dir)? {
+        let path = dirent?.path();
+        if !path.is_file()
+            || !source::is_export_file(&path)
+            || path.extension().is_none_or(|e| e != "export")
+        {
+            continue;
+        }
+        let modified = fs::metadata(&path)?.modified()?;
+        let candidate = (modified, path);
+        if newest.as_ref().is_none_or(|n| candidate > *n) {
+            newest = Some(candidate);
+        }
+    }
+    Ok(newest.map(|(_, path)| path))
+}
+
+#[cfg(unix)]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    (a.dev(), a.ino()) == (b.dev(), b.ino())
+}
+
+#[cfg(not(unix))]
+fn same_file(_: &fs::Metadata, _: &fs::Metadata) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::OpenOptions, io::Write, thread, time::Duration};
+
+    use crate::{
+        journald::{Entry, JournalExportRead},
+        shutdown::Shutdown,
+        testutil::write_string,
+    };
+
+    use super::Follow;
+
+    fn entry(message: &str) -> Vec<u8> {
+        let mut e = vec![];
+        write_string(&mut e, "MESSAGE", message);
+        e.push(b'\n');
+        e
+    }
+
+    #[test]
+    fn follows_growing_and_replaced_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.export");
+        std::fs::write(&path, entry("one")).unwrap();
+
+        let shutdown = Shutdown::new();
+        let follow = Follow::new(&path)
+            .unwrap()
+            .with_interval(Duration::from_millis(5))
+            .with_shutdown(shutdown.clone());
+        let writer = {
+            let path = path.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                let mut f = OpenOptions::new().append(true).open(&path).unwrap();
+                f.write_all(&entry("two")).unwrap();
+                thread::sleep(Duration::from_millis(50));
+                // Rotated: a new file takes the place of the old one.
+                std::fs::remove_file(&path).unwrap();
+                std::fs::write(&path, entry("three")).unwrap();
+                thread::sleep(Duration::from_millis(50));
+                shutdown.request();
+            })
+        };
+        let messages: Vec<_> = JournalExportRead::new(follow)
+            .map(|e| e.get(b"MESSAGE").unwrap().to_vec())
+            .collect();
+        writer.join().unwrap();
+        assert_eq!(messages, [&b"one"[..], b"two", b"three"]);
+    }
+}
@@ -0,0 +1,898 @@
+//! Render entries as text.
+//!
+//! [EntryFormatter] writes entries as JSON objects, logfmt lines or in the
+//! short format of `journalctl`. Field values are arbitrary bytes; values that
+//! are not valid UTF-8 are rendered according to a [BinaryRendering], which
+//! is lossless unless it is [BinaryRendering::Lossy] or
+//! [BinaryRendering::Skip]:
+//!
+//! - JSON: a base64 value is written as an object `{"base64": "..."}`, such
+//!   that it can be told apart from strings. Repeated fields become arrays.
+//! - logfmt: `KEY=VALUE` pairs separated by spaces; values are quoted and
+//!   escaped if necessary.
+//! - short: `TIME HOST IDENTIFIER[PID]: MESSAGE` in local time.
+//! - table: the [Column]s of a [TableLayout], aligned under a header. Every
+//!   column but the last has a fixed width, such that entries can be written
+//!   as they come; longer values are truncated, and the last column is
+//!   truncated to the maximum width of the line.
+//!
+//! With a [Theme], lines of the text formats other than JSON are highlighted
+//! according to the priority of their entry, like `journalctl` does.
+//!
+//! Times are rendered by a [TimeRendering] in a [Zone] and with a strftime
+//! format. Given one with [EntryFormatter::with_time], JSON and logfmt also
+//! get a field `__TIME` with the rendered realtime timestamp.
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fmt::{self, Display, Write as _},
+    io::{self, Write},
+    str::FromStr,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{format::StrftimeItems, DateTime, FixedOffset, Local, SecondsFormat, TimeZone};
+use chrono_tz::Tz;
+use console::{measure_text_width, pad_str, Alignment, Style};
+use serde_json::{Map, Value};
+
+use crate::{
+    journald::{Entry, JournalExportRead},
+    sink::EntrySink,
+};
+
+/// How values that are not valid UTF-8 are rendered.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum BinaryRendering {
+    Base64,
+    /// Invalid bytes are written as `\xNN` and backslashes as `\\`.
+    #[default]
+    HexEscape,
+    /// Invalid bytes are replaced by U+FFFD.
+    Lossy,
+    /// The field is left out.
+    Skip,
+}
+
+impl BinaryRendering {
+    /// Renders `value`; valid UTF-8 is returned as is.
+    pub fn render(self, value: &[u8]) -> Option<Cow<'_, str>> {
+        if let Ok(s) = std::str::from_utf8(value) {
+            return Some(Cow::Borrowed(s));
+        }
+        Some(Cow::Owned(match self {
+            BinaryRendering::Base64 => STANDARD.encode(value),
+            BinaryRendering::HexEscape => hex_escape(value),
+            BinaryRendering::Lossy => String::from_utf8_lossy(value).into_owned(),
+            BinaryRendering::Skip => return None,
+        }))
+    }
+
+    /// Restores a value rendered by [Self::render] that was not valid UTF-8;
+    /// `None` if the rendering loses data or `rendered` is malformed.
+    pub fn decode(self, rendered: &str) -> Option<Vec<u8>> {
+        match self {
+            BinaryRendering::Base64 => STANDARD.decode(rendered).ok(),
+            BinaryRendering::HexEscape => hex_unescape(rendered),
+            BinaryRendering::Lossy | BinaryRendering::Skip => None,
+        }
+    }
+}
+
+fn hex_escape(value: &[u8]) -> String {
+    let mut out = String::with_capacity(value.len());
+    for chunk in value.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if c == '\\' {
+                out.push_str("\\\\");
+            } else {
+                out.push(c);
+            }
+        }
+        for b in chunk.invalid() {
+            let _ = write!(out, "\\x{:02x}", b);
+        }
+    }
+    out
+}
+
+fn hex_unescape(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&c, tail)) = rest.split_first() {
+        rest = tail;
+        if c != b'\\' {
+            out.push(c);
+            continue;
+        }
+        match rest {
+            [b'\\', tail @ ..] => {
+                out.push(b'\\');
+                rest = tail;
+            }
+            [b'x', h, l, tail @ ..] => {
+                let hex = std::str::from_utf8(&[*h, *l]).ok()?.to_string();
+                out.push(u8::from_str_radix(&hex, 16).ok()?);
+                rest = tail;
+            }
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+/// The time zone in which times are shown.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum Zone {
+    #[default]
+    Local,
+    Utc,
+    Fixed(FixedOffset),
+    /// A zone of the IANA database such as `Europe/Berlin`.
+    Named(Tz),
+}
+
+impl FromStr for Zone {
+    type Err = String;
+
+    /// Parses `local`, `UTC`, an offset such as `+02:00` or an IANA name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "local" => return Ok(Zone::Local),
+            "UTC" | "utc" | "Z" => return Ok(Zone::Utc),
+            _ => {}
+        }
+        if s.starts_with(['+', '-']) {
+            return s
+                .parse()
+                .map(Zone::Fixed)
+                .map_err(|_| format!("invalid offset: {}", s));
+        }
+        s.parse()
+            .map(Zone::Named)
+            .map_err(|_| format!("unknown time zone: {}", s))
+    }
+}
+
+impl Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Zone::Local => f.write_str("local"),
+            Zone::Utc => f.write_str("UTC"),
+            Zone::Fixed(offset) => write!(f, "{}", offset),
+            Zone::Named(tz) => f.write_str(tz.name()),
+        }
+    }
+}
+
+/// How timestamps are rendered, shared by all text formats.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct TimeRendering {
+    zone: Zone,
+    /// A strftime format; each format has its own default.
+    format: Option<String>,
+}
+
+impl TimeRendering {
+    /// Renders times in `zone` instead of local time.
+    pub fn with_zone(self, zone: Zone) -> Self {
+        Self { zone, ..self }
+    }
+
+    /// Renders times with the strftime `format`, e.g. `%Y-%m-%d %H:%M:%S%.3f`.
+    /// Fails for unknown specifiers, which `chrono` would only report while
+    /// rendering.
+    pub fn with_format(self, format: impl Into<String>) -> Result<Self, String> {
+        let format = format.into();
+        StrftimeItems::new(&format)
+            .parse()
+            .map_err(|_| format!("invalid time format: {}", format))?;
+        Ok(Self {
+            format: Some(format),
+            ..self
+        })
+    }
+
+    /// Renders `us` microseconds since the epoch with the format, or else
+    /// with `default`; `None` if the time is out of range. `default` may be
+    /// `None` for RFC 3339 with microseconds.
+    pub fn render(&self, us: u64, default: Option<&str>) -> Option<String> {
+        let time = DateTime::from_timestamp_micros(i64::try_from(us).ok()?)?;
+        match self.zone {
+            Zone::Local => self.render_in(time.with_timezone(&Local), default),
+            Zone::Utc => self.render_in(time, default),
+            Zone::Fixed(offset) => self.render_in(time.with_timezone(&offset), default),
+            Zone::Named(tz) => self.render_in(time.with_timezone(&tz), default),
+        }
+    }
+
+    fn render_in<Z: TimeZone>(&self, time: DateTime<Z>, default: Option<&str>) -> Option<String>
+    where
+        Z::Offset: Display,
+    {
+        match self.format.as_deref().or(default) {
+            Some(format) => Some(time.format(format).to_string()),
+            None => Some(time.to_rfc3339_opts(SecondsFormat::Micros, self.zone == Zone::Utc)),
+        }
+    }
+}
+
+/// The names of the syslog priorities, from 0 to 7.
+pub const PRIORITIES: [&str; 8] = [
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+
+/// Styles of lines by the priority of their entry.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Theme {
+    styles: [Option<Style>; 8],
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::journalctl()
+    }
+}
+
+impl Theme {
+    /// Styles are applied whether or not the output is a terminal; the
+    /// caller decides whether to use a theme at all.
+    fn new(styles: [Option<Style>; 8]) -> Self {
+        Self {
+            styles: styles.map(|style| style.map(|style| style.force_styling(true))),
+        }
+    }
+
+    /// Errors and worse in bold red, warnings in bold yellow, notices in
+    /// bold and debug messages dimmed.
+    pub fn journalctl() -> Self {
+        let red = Style::new().red().bold();
+        Self::new([
+            Some(red.clone()),
+            Some(red.clone()),
+            Some(red.clone()),
+            Some(red),
+            Some(Style::new().yellow().bold()),
+            Some(Style::new().bold()),
+            None,
+            Some(Style::new().dim()),
+        ])
+    }
+
+    /// Without colors: errors and worse bold and underlined, warnings and
+    /// notices bold and debug messages dimmed.
+    pub fn mono() -> Self {
+        let error = Style::new().bold().underlined();
+        Self::new([
+            Some(error.clone()),
+            Some(error.clone()),
+            Some(error.clone()),
+            Some(error),
+            Some(Style::new().bold()),
+            Some(Style::new().bold()),
+            None,
+            Some(Style::new().dim()),
+        ])
+    }
+
+    /// Styles entries of `priority` with `style`, or not at all.
+    pub fn with_style(mut self, priority: u8, style: Option<Style>) -> Self {
+        if let Some(s) = self.styles.get_mut(priority as usize) {
+            *s = style.map(|style| style.force_styling(true));
+        }
+        self
+    }
+
+    fn style(&self, priority: Option<u64>) -> Option<&Style> {
+        self.styles.get(priority? as usize)?.as_ref()
+    }
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    /// Parses a comma-separated list of a theme, `journalctl` or `mono`, and
+    /// `PRIORITY=STYLE` to change it, where the style is a list of colors and
+    /// attributes separated by dots such as `red.bold` or `on_blue`, or
+    /// empty.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut theme = Theme::default();
+        for item in s.split(',') {
+            let Some((priority, style)) = item.split_once('=') else {
+                theme = match item {
+                    "journalctl" => Theme::journalctl(),
+                    "mono" => Theme::mono(),
+                    _ => return Err(format!("unknown theme: {}", item)),
+                };
+                continue;
+            };
+            let priority = PRIORITIES
+                .iter()
+                .position(|p| *p == priority)
+                .or_else(|| priority.parse().ok().filter(|p| *p < 8))
+                .ok_or_else(|| format!("unknown priority: {}", priority))?;
+            if let Some(part) = style
+                .split('.')
+                .find(|part| !style.is_empty() && Style::from_dotted_str(part) == Style::new())
+            {
+                return Err(format!("unknown color or attribute: {}", part));
+            }
+            let style = (!style.is_empty()).then(|| Style::from_dotted_str(style));
+            theme = theme.with_style(priority as u8, style);
+        }
+        Ok(theme)
+    }
+}
+
+/// A column of [EntryFormat::Table].
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Column {
+    Time,
+    Host,
+    /// The unit, or else the syslog identifier.
+    Unit,
+    Identifier,
+    Pid,
+    /// The name of the priority.
+    Priority,
+    Message,
+    /// The first value of a field.
+    Field(String),
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "time" => Column::Time,
+            "host" => Column::Host,
+            "unit" => Column::Unit,
+            "identifier" => Column::Identifier,
+            "pid" => Column::Pid,
+            "priority" => Column::Priority,
+            "message" => Column::Message,
+            _ if !s.is_empty() && !s.contains(['=', '\n']) => Column::Field(s.to_string()),
+            _ => return Err(format!("invalid column: {:?}", s)),
+        })
+    }
+}
+
+impl Column {
+    fn header(&self) -> &str {
+        match self {
+            Column::Time => "TIME",
+            Column::Host => "HOST",
+            Column::Unit => "UNIT",
+            Column::Identifier => "IDENTIFIER",
+            Column::Pid => "PID",
+            Column::Priority => "PRIORITY",
+            Column::Message => "MESSAGE",
+            Column::Field(name) => name,
+        }
+    }
+
+    /// The width of the column unless it is the last one.
+    fn width(&self, time: &TimeRendering) -> usize {
+        let width = match self {
+            Column::Time => time
+                .render(977_003_999_999_999, Some(SHORT_TIME))
+                .map_or(15, |t| measure_text_width(&t)),
+            Column::Host | Column::Identifier | Column::Field(_) => 16,
+            Column::Unit => 28,
+            Column::Pid => 7,
+            Column::Priority => 8,
+            Column::Message => 60,
+        };
+        width.max(self.header().len())
+    }
+}
+
+/// The columns of [EntryFormat::Table] and how wide its lines may be.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct TableLayout {
+    columns: Vec<Column>,
+    max_width: Option<usize>,
+    truncate: bool,
+}
+
+impl Default for TableLayout {
+    /// Time, unit, priority and message, without a maximum width.
+    fn default() -> Self {
+        Self::new(vec![
+            Column::Time,
+            Column::Unit,
+            Column::Priority,
+            Column::Message,
+        ])
+    }
+}
+
+impl TableLayout {
+    pub fn new(columns: Vec<Column>) -> Self {
+        Self {
+            columns,
+            max_width: None,
+            truncate: true,
+        }
+    }
+
+    /// Truncates lines to `width` columns of the terminal.
+    pub fn with_max_width(self, width: usize) -> Self {
+        Self {
+            max_width: Some(width),
+            ..self
+        }
+    }
+
+    /// Whether long values are truncated; if not, they shift the following
+    /// columns. `true` by default.
+    pub fn with_truncate(self, truncate: bool) -> Self {
+        Self { truncate, ..self }
+    }
+}
+
+/// The default time format of the short and the table format.
+const SHORT_TIME: &str = "%b %d %H:%M:%S";
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum EntryFormat {
+    /// The Journal Export Format, unchanged.
+    #[default]
+    Export,
+    Json,
+    Logfmt,
+    Short,
+    Table,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EntryFormatter {
+    format: EntryFormat,
+    binary: BinaryRendering,
+    /// `None` for local time in the short format and no `__TIME` otherwise.
+    time: Option<TimeRendering>,
+    table: TableLayout,
+    theme: Option<Theme>,
+}
+
+impl EntryFormatter {
+    pub fn new(format: EntryFormat) -> Self {
+        Self {
+            format,
+            binary: BinaryRendering::default(),
+            time: None,
+            table: TableLayout::default(),
+            theme: None,
+        }
+    }
+
+    /// Highlights lines by their priority. JSON and the export format stay
+    /// plain.
+    pub fn with_theme(self, theme: Theme) -> Self {
+        Self {
+            theme: Some(theme),
+            ..self
+        }
+    }
+
+    /// The columns of [EntryFormat::Table].
+    pub fn with_table(self, table: TableLayout) -> Self {
+        Self { table, ..self }
+    }
+
+    /// The line to write before the first entry, for tables.
+    pub fn header(&self) -> Option<String> {
+        if self.format != EntryFormat::Table {
+            return None;
+        }
+        let headers: Vec<_> = self.table.columns.iter().map(|c| c.header()).collect();
+        Some(self.row(&headers))
+    }
+
+    pub fn with_binary(self, binary: BinaryRendering) -> Self {
+        Self { binary, ..self }
+    }
+
+    /// Renders times with `time`, and adds them as `__TIME` to JSON and
+    /// logfmt.
+    pub fn with_time(self, time: TimeRendering) -> Self {
+        Self {
+            time: Some(time),
+            ..self
+        }
+    }
+
+    /// The realtime timestamp of `entry` as `__TIME`, if requested.
+    fn time_field(&self, entry: &impl Entry) -> Option<String> {
+        let time = self.time.as_ref()?;
+        time.render(entry.realtime_timestamp()?, None)
+    }
+
+    /// Writes `entry`, terminated by a newline.
+    pub fn write(&self, out: &mut impl Write, entry: &impl Entry) -> io::Result<()> {
+        match self.format {
+            EntryFormat::Export => out.write_all(entry.as_bytes()),
+            EntryFormat::Json => {
+                serde_json::to_writer(&mut *out, &self.json(entry))?;
+                out.write_all(b"\n")
+            }
+            EntryFormat::Logfmt => self.write_line(out, entry, self.logfmt(entry)),
+            EntryFormat::Short => self.write_line(out, entry, self.short(entry)),
+            EntryFormat::Table => self.write_line(out, entry, self.table(entry)),
+        }
+    }
+
+    fn write_line(&self, out: &mut impl Write, entry: &impl Entry, line: String) -> io::Result<()> {
+        let style = (self.theme.as_ref()).and_then(|theme| theme.style(entry.get_u64(b"PRIORITY")));
+        match style {
+            Some(style) => writeln!(out, "{}", style.apply_to(line)),
+            None => writeln!(out, "{}", line),
+        }
+    }
+
+    fn time_rendering(&self) -> Cow<'_, TimeRendering> {
+        match &self.time {
+            Some(time) => Cow::Borrowed(time),
+            None => Cow::Owned(TimeRendering::default()),
+        }
+    }
+
+    /// The fields of `entry` as a JSON object, sorted by name. A `BTreeMap`
+    /// keeps them sorted even if dependencies enable `preserve_order` of
+    /// `serde_json`.
+    fn json(&self, entry: &impl Entry) -> BTreeMap<String, Value> {
+        let mut object = BTreeMap::new();
+        if let Some(time) = self.time_field(entry) {
+            object.insert("__TIME".to_string(), Value::String(time));
+        }
+        for (name, value, _) in entry.iter() {
+            let Some(rendered) = self.binary.render(value) else {
+                continue;
+            };
+            let value = match (self.binary, rendered) {
+                (BinaryRendering::Base64, Cow::Owned(s)) => {
+                    Value::Object(Map::from_iter([("base64".to_string(), Value::String(s))]))
+                }
+                (_, s) => Value::String(s.into_owned()),
+            };
+            let name = String::from_utf8_lossy(name).into_owned();
+            match object.get_mut(&name) {
+                Some(Value::Array(values)) => values.push(value),
+                Some(first) => *first = Value::Array(vec![first.take(), value]),
+                None => {
+                    object.insert(name, value);
+                }
+            }
+        }
+        object
+    }
+
+    fn logfmt(&self, entry: &impl Entry) -> String {
+        let mut line = String::new();
+        if let Some(time) = self.time_field(entry) {
+            logfmt_pair(&mut line, "__TIME", &time);
+        }
+        for (name, value, _) in entry.iter() {
+            let Some(value) = self.binary.render(value) else {
+                continue;
+            };
+            logfmt_pair(&mut line, &String::from_utf8_lossy(name), &value);
+        }
+        line
+    }
+
+    fn short(&self, entry: &impl Entry) -> String {
+        let field = |name: &[u8]| entry.get(name).and_then(|v| self.binary.render(v));
+        let mut line = String::new();
+        let time = self.time_rendering();
+        if let Some(time) = entry
+            .realtime_timestamp()
+            .and_then(|us| time.render(us, Some(SHORT_TIME)))
+        {
+            line.push_str(&time);
+            line.push(' ');
+        }
+        if let Some(host) = field(b"_HOSTNAME") {
+            line.push_str(&host);
+            line.push(' ');
+        }
+        let identifier = field(b"SYSLOG_IDENTIFIER").or_else(|| field(b"_COMM"));
+        line.push_str(identifier.as_deref().unwrap_or("unknown"));
+        if let Some(pid) = field(b"_PID").or_else(|| field(b"SYSLOG_PID")) {
+            let _ = write!(line, "[{}]", pid);
+        }
+        line.push_str(": ");
+        if let Some(message) = field(b"MESSAGE") {
+            line.push_str(&message);
+        }
+        line
+    }
+
+    fn table(&self, entry: &impl Entry) -> String {
+        let field = |name: &[u8]| entry.get(name).and_then(|v| self.binary.render(v));
+        let time = self.time_rendering();
+        let cells: Vec<Cow<'_, str>> = self
+            .table
+            .columns
+            .iter()
+            .map(|column| {
+                let cell = match column {
+                    Column::Time => entry
+                        .realtime_timestamp()
+                        .and_then(|us| time.render(us, Some(SHORT_TIME)))
+                        .map(Cow::Owned),
+                    Column::Host => field(b"_HOSTNAME"),
+                    Column::Unit => field(b"_SYSTEMD_UNIT")
+                        .or_else(|| field(b"USER_UNIT"))
+                        .or_else(|| field(b"SYSLOG_IDENTIFIER")),
+                    Column::Identifier => field(b"SYSLOG_IDENTIFIER").or_else(|| field(b"_COMM")),
+                    Column::Pid => field(b"_PID").or_else(|| field(b"SYSLOG_PID")),
+                    Column::Priority => field(b"PRIORITY").map(|p| {
+                        match p.parse::<usize>().ok().and_then(|p| PRIORITIES.get(p)) {
+                            Some(name) => Cow::Borrowed(*name),
+                            None => p,
+                        }
+                    }),
+                    Column::Message => field(b"MESSAGE"),
+                    Column::Field(name) => field(name.as_bytes()),
+                };
+                match cell {
+                    // Line breaks and tabs would break the alignment.
+                    Some(cell) if cell.contains(|c: char| c.is_control()) => Cow::Owned(
+                        cell.chars()
+                            .map(|c| if c.is_control() { ' ' } else { c })
+                            .collect(),
+                    ),
+                    Some(cell) => cell,
+                    None => Cow::Borrowed("-"),
+                }
+            })
+            .collect();
+        let cells: Vec<&str> = cells.iter().map(|c| c.as_ref()).collect();
+        self.row(&cells)
+    }
+
+    /// Aligns `cells` in the columns of the table.
+    fn row(&self, cells: &[&str]) -> String {
+        let time = self.time_rendering();
+        let mut line = String::new();
+        for (i, (column, cell)) in self.table.columns.iter().zip(cells).enumerate() {
+            if i > 0 {
+                line.push(' ');
+            }
+            if i + 1 == cells.len() {
+                let used = measure_text_width(&line);
+                match self.table.max_width {
+                    Some(max) if self.table.truncate => {
+                        line.push_str(&truncate(cell, max.saturating_sub(used)));
+                    }
+                    _ => line.push_str(cell),
+                }
+                break;
+            }
+            let width = column.width(&time);
+            let cell = match self.table.truncate {
+                true => truncate(cell, width),
+                false => Cow::Borrowed(*cell),
+            };
+            line.push_str(&pad_str(&cell, width, Alignment::Left, None));
+        }
+        line
+    }
+}
+
+/// Shortens `s` to `width` columns of the terminal, marking the cut with `…`.
+fn truncate(s: &str, width: usize) -> Cow<'_, str> {
+    if measure_text_width(s) <= width {
+        return Cow::Borrowed(s);
+    }
+    let mut out = String::new();
+    let mut used = 0;
+    let mut buf = [0; 4];
+    for c in s.chars() {
+        let w = measure_text_width(c.encode_utf8(&mut buf));
+        if used + w + 1 > width {
+            break;
+        }
+        out.push(c);
+        used += w;
+    }
+    if width > 0 {
+        out.push('…');
+    }
+    Cow::Owned(out)
+}
+
+/// Appends `name=value` to `line`, quoting and escaping `value` if necessary.
+fn logfmt_pair(line: &mut String, name: &str, value: &str) {
+    if !line.is_empty() {
+        line.push(' ');
+    }
+    line.push_str(name);
+    line.push('=');
+    let plain = !value.is_empty()
+        && !value
+            .chars()
+            .any(|c| c == ' ' || c == '"' || c == '=' || c.is_control());
+    if plain {
+        line.push_str(value);
+    } else {
+        let _ = write!(line, "{:?}", value);
+    }
+}
+
+/// Writes the entries it receives in a text format.
+pub struct FormattingSink<W> {
+    formatter: EntryFormatter,
+    out: W,
+    /// The header of a table, until the first entry.
+    header: Option<String>,
+}
+
+impl<W: Write> FormattingSink<W> {
+    pub fn new(formatter: EntryFormatter, out: W) -> Self {
+        Self {
+            header: formatter.header(),
+            formatter,
+            out,
+        }
+    }
+}
+
+impl<W: Write> EntrySink for FormattingSink<W> {
+    fn write_entry(&mut self, entry: &[u8]) -> io::Result<()> {
+        if self.formatter.format == EntryFormat::Export {
+            return self.out.write_all(entry);
+        }
+        let mut reader = JournalExportRead::new(entry);
+        if reader.parse_next().map_err(io::Error::other)?.is_some() {
+            if let Some(header) = self.header.take() {
+                writeln!(self.out, "{}", header)?;
+            }
+            self.formatter.write(&mut self.out, &reader.get_entry())?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        journald::JournalExportRead,
+        testutil::{write_binary, write_string},
+    };
+
+    use super::{
+        BinaryRendering, Column, EntryFormat, EntryFormatter, TableLayout, Theme, TimeRendering,
+        Zone,
+    };
+
+    #[test]
+    fn binary_values_survive_rendering() {
+        let value = b"ok \\ \xff\xfe end";
+        for binary in [BinaryRendering::Base64, BinaryRendering::HexEscape] {
+            let rendered = binary.render(value).unwrap();
+            assert_eq!(binary.decode(&rendered).unwrap(), value);
+        }
+        assert_eq!(
+            BinaryRendering::HexEscape.render(value).unwrap(),
+            "ok \\\\ \\xff\\xfe end"
+        );
+        assert_eq!(BinaryRendering::Skip.render(value), None);
+        assert_eq!(BinaryRendering::Skip.render(b"text").unwrap(), "text");
+        assert!(BinaryRendering::Lossy.decode("x").is_none());
+    }
+
+    #[test]
+    fn formats_entries() {
+        let mut stream = vec![];
+        write_string(&mut stream, "__REALTIME_TIMESTAMP", "1700000000000000");
+        write_string(&mut stream, "_HOSTNAME", "host");
+        write_string(&mut stream, "SYSLOG_IDENTIFIER", "app");
+        write_string(&mut stream, "_PID", "42");
+        write_string(&mut stream, "MESSAGE", "hello world");
+        write_binary(&mut stream, "DATA", b"\xff\xfe");
+        write_string(&mut stream, "TAG", "a");
+        write_string(&mut stream, "TAG", "b");
+        stream.push(b'\n');
+        let entry = JournalExportRead::new(&stream[..]).next().unwrap();
+
+        let format = |format, binary| {
+            let mut out = vec![];
+            EntryFormatter::new(format)
+                .with_binary(binary)
+                .write(&mut out, &entry)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            format(EntryFormat::Json, BinaryRendering::Base64),
+            r#"{"DATA":{"base64":"//4="},"MESSAGE":"hello world","SYSLOG_IDENTIFIER":"app","TAG":["a","b"],"_HOSTNAME":"host","_PID":"42","__REALTIME_TIMESTAMP":"1700000000000000"}"#
+                .to_string()
+                + "\n"
+        );
+        assert_eq!(
+            format(EntryFormat::Logfmt, BinaryRendering::HexEscape),
+            "__REALTIME_TIMESTAMP=1700000000000000 _HOSTNAME=host SYSLOG_IDENTIFIER=app \
+             _PID=42 MESSAGE=\"hello world\" DATA=\\xff\\xfe TAG=a TAG=b\n"
+        );
+        assert!(format(EntryFormat::Short, BinaryRendering::Skip)
+            .ends_with(" host app[42]: hello world\n"));
+
+        let time = |zone: &str, format: Option<&str>, entry_format| {
+            let mut time = TimeRendering::default().with_zone(zone.parse().unwrap());
+            if let Some(format) = format {
+                time = time.with_format(format).unwrap();
+            }
+            let mut out = vec![];
+            EntryFormatter::new(entry_format)
+                .with_time(time)
+                .write(&mut out, &entry)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert!(time("UTC", None, EntryFormat::Short).starts_with("Nov 14 22:13:20 host"));
+        assert!(time("Asia/Tokyo", Some("%F %T%.3f"), EntryFormat::Logfmt)
+            .starts_with("__TIME=\"2023-11-15 07:13:20.000\" __REALTIME_TIMESTAMP="));
+        assert!(time("+01:00", None, EntryFormat::Json)
+            .ends_with("\"__TIME\":\"2023-11-14T23:13:20.000000+01:00\"}\n"));
+        assert!("Mars/Base".parse::<Zone>().is_err());
+
+        let columns: Vec<Column> = ["time", "priority", "TAG", "message"]
+            .iter()
+            .map(|c| c.parse().unwrap())
+            .collect();
+        let table = EntryFormatter::new(EntryFormat::Table)
+            .with_time(TimeRendering::default().with_zone(Zone::Utc))
+            .with_table(TableLayout::new(columns).with_max_width(50));
+        let mut out = vec![];
+        table.write(&mut out, &entry).unwrap();
+        assert_eq!(
+            table.header().unwrap(),
+            "TIME            PRIORITY TAG              MESSAGE"
+        );
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Nov 14 22:13:20 -        a                hello w…\n"
+        );
+        assert!(TimeRendering::default().with_format("%Q").is_err());
+
+        let highlight = |theme: &str, priority: &str| {
+            let mut stream = vec![];
+            write_string(&mut stream, "PRIORITY", priority);
+            write_string(&mut stream, "MESSAGE", "m");
+            stream.push(b'\n');
+            let entry = JournalExportRead::new(&stream[..]).next().unwrap();
+            let mut out = vec![];
+            EntryFormatter::new(EntryFormat::Short)
+                .with_theme(theme.parse().unwrap())
+                .write(&mut out, &entry)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            highlight("journalctl", "3"),
+            "\x1b[31m\x1b[1munknown: m\x1b[0m\n"
+        );
+        assert_eq!(highlight("journalctl", "6"), "unknown: m\n");
+        assert_eq!(
+            highlight("mono,info=blue", "6"),
+            "\x1b[34munknown: m\x1b[0m\n"
+        );
+        assert_eq!(highlight("journalctl,err=", "3"), "unknown: m\n");
+        assert!("err=purple".parse::<Theme>().is_err());
+        assert!("dark".parse::<Theme>().is_err());
+
+        let mut export = vec![];
+        EntryFormatter::new(EntryFormat::Export)
+            .write(&mut export, &entry)
+            .unwrap();
+        assert_eq!(export, stream);
+    }
+}
@@ -0,0 +1,291 @@
+//! Synthesize journal export streams, e.g. for benchmarks and `loginus
+//! generate`.
+//!
+//! [EntryGenerator] produces a deterministic (seeded) sequence of journal
+//! entries in the Journal Export Format. The shape of the entries is determined
+//! by a [Workload]; [Workload::Realistic] mimics the fields that journald
+//! attaches to typical log messages of system services.
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::journald::{parser::FieldType, write_field};
+
+/// The kind of entries produced by an [EntryGenerator].
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum Workload {
+    /// Trusted and user fields as written by journald for service logs.
+    #[default]
+    Realistic,
+    /// Few fields with long string values.
+    StringHeavy,
+    /// Most of the payload is contained in binary fields.
+    BinaryHeavy,
+    /// Many fields with short names and values.
+    ManySmallFields,
+}
+
+/// The distribution of the time between two consecutive entries.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum RateProfile {
+    /// Entries arrive at a roughly constant rate.
+    #[default]
+    Steady,
+    /// Bursts of entries that are microseconds apart, separated by quiet
+    /// periods of up to several seconds.
+    Bursty,
+}
+
+const WORDS: &[&str] = &[
+    "connection",
+    "established",
+    "failed",
+    "request",
+    "from",
+    "user",
+    "session",
+    "opened",
+    "closed",
+    "timeout",
+    "after",
+    "retrying",
+    "started",
+    "stopped",
+    "unit",
+    "service",
+    "listening",
+    "on",
+    "port",
+    "error",
+    "warning",
+    "reading",
+    "config",
+    "file",
+    "for",
+    "with",
+    "status",
+    "code",
+    "accepted",
+    "reload",
+];
+
+pub struct EntryGenerator {
+    rng: StdRng,
+    workload: Workload,
+    rate_profile: RateProfile,
+    units: usize,
+    burst_remaining: usize,
+    seqnum_id: [u8; 16],
+    boot_id: [u8; 16],
+    seqnum: u64,
+    realtime: u64,
+    monotonic: u64,
+}
+
+impl EntryGenerator {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let seqnum_id = rng.gen();
+        let boot_id = rng.gen();
+        Self {
+            rng,
+            workload: Workload::default(),
+            rate_profile: RateProfile::default(),
+            units: 8,
+            burst_remaining: 0,
+            seqnum_id,
+            boot_id,
+            seqnum: 0,
+            realtime: 1_700_000_000_000_000,
+            monotonic: 1_000_000,
+        }
+    }
+
+    pub fn with_workload(self, workload: Workload) -> Self {
+        Self { workload, ..self }
+    }
+
+    pub fn with_rate_profile(self, rate_profile: RateProfile) -> Self {
+        Self {
+            rate_profile,
+            ..self
+        }
+    }
+
+    /// Sets the number of distinct units the generated entries are
+    /// attributed to.
+    pub fn with_units(self, units: usize) -> Self {
+        assert!(units > 0);
+        Self { units, ..self }
+    }
+
+    /// Generates `n` entries and returns them as one export stream.
+    pub fn generate(&mut self, n: usize) -> Vec<u8> {
+        let mut out = vec![];
+        for _ in 0..n {
+            self.write_entry(&mut out);
+        }
+        out
+    }
+
+    /// Appends the next entry (including the terminating empty line) to
+    /// `out`.
+    pub fn write_entry(&mut self, out: &mut Vec<u8>) {
+        self.seqnum += 1;
+        let delay = self.next_delay();
+        self.realtime += delay;
+        self.monotonic += delay;
+
+        match self.workload {
+            Workload::Realistic => self.write_realistic(out),
+            Workload::StringHeavy => self.write_string_heavy(out),
+            Workload::BinaryHeavy => self.write_binary_heavy(out),
+            Workload::ManySmallFields => self.write_many_small_fields(out),
+        }
+        out.push(b'\n');
+    }
+
+    /// Returns the time in microseconds between the previous and the next
+    /// entry.
+    fn next_delay(&mut self) -> u64 {
+        match self.rate_profile {
+            RateProfile::Steady => self.rng.gen_range(1..50_000),
+            RateProfile::Bursty => {
+                if self.burst_remaining > 0 {
+                    self.burst_remaining -= 1;
+                    self.rng.gen_range(1..100)
+                } else {
+                    self.burst_remaining = self.rng.gen_range(10..500);
+                    self.rng.gen_range(100_000..5_000_000)
+                }
+            }
+        }
+    }
+
+    fn write_realistic(&mut self, out: &mut Vec<u8>) {
+        self.write_metadata(out);
+        let unit = self.rng.gen_range(0..self.units);
+        let pid = 1000 + unit as u64;
+        write_string(out, "_BOOT_ID", hex(&self.boot_id));
+        write_string(out, "_TRANSPORT", "stdout");
+        write_string(out, "PRIORITY", self.rng.gen_range(2..8).to_string());
+        write_string(out, "SYSLOG_FACILITY", "3");
+        write_string(out, "SYSLOG_IDENTIFIER", format!("service{}", unit));
+        write_string(out, "_PID", pid.to_string());
+        write_string(out, "_UID", "0");
+        write_string(out, "_GID", "0");
+        write_string(out, "_COMM", format!("service{}", unit));
+        write_string(out, "_EXE", format!("/usr/bin/service{}", unit));
+        write_string(out, "_SYSTEMD_UNIT", format!("service{}.service", unit));
+        write_string(out, "_HOSTNAME", "localhost");
+        // Some messages span multiple lines (e.g. stack traces) and thus have
+        // to be written as binary fields.
+        if self.rng.gen_bool(0.05) {
+            let lines: Vec<_> = (0..self.rng.gen_range(2..6))
+                .map(|_| self.sentence(4, 16))
+                .collect();
+            write_binary(out, "MESSAGE", lines.join("\n"));
+        } else {
+            let message = self.sentence(4, 16);
+            write_string(out, "MESSAGE", &message);
+        }
+    }
+
+    fn write_string_heavy(&mut self, out: &mut Vec<u8>) {
+        self.write_metadata(out);
+        let message = self.sentence(100, 400);
+        write_string(out, "MESSAGE", &message);
+        let stack = self.sentence(100, 400);
+        write_string(out, "STACKTRACE", &stack);
+    }
+
+    fn write_binary_heavy(&mut self, out: &mut Vec<u8>) {
+        self.write_metadata(out);
+        let len = self.rng.gen_range(256..4096);
+        let mut value: Vec<u8> = (0..len).map(|_| self.rng.gen()).collect();
+        write_binary(out, "MESSAGE", &value);
+        value.truncate(len / 4);
+        write_binary(out, "COREDUMP", &value);
+    }
+
+    fn write_many_small_fields(&mut self, out: &mut Vec<u8>) {
+        self.write_metadata(out);
+        for i in 0..self.rng.gen_range(32..96) {
+            let value = self.rng.gen_range(0..1000u32).to_string();
+            write_string(out, &format!("F{}", i), &value);
+        }
+    }
+
+    fn write_metadata(&mut self, out: &mut Vec<u8>) {
+        let cursor = format!(
+            "s={};i={:x};b={};m={:x};t={:x};x={:016x}",
+            hex(&self.seqnum_id),
+            self.seqnum,
+            hex(&self.boot_id),
+            self.monotonic,
+            self.realtime,
+            self.rng.gen::<u64>()
+        );
+        write_string(out, "__CURSOR", &cursor);
+        write_string(out, "__REALTIME_TIMESTAMP", self.realtime.to_string());
+        write_string(out, "__MONOTONIC_TIMESTAMP", self.monotonic.to_string());
+        write_string(out, "__SEQNUM", self.seqnum.to_string());
+        write_string(out, "__SEQNUM_ID", hex(&self.seqnum_id));
+    }
+
+    fn sentence(&mut self, min_words: usize, max_words: usize) -> String {
+        let n = self.rng.gen_range(min_words..max_words);
+        let words: Vec<_> = (0..n)
+            .map(|_| WORDS[self.rng.gen_range(0..WORDS.len())])
+            .collect();
+        words.join(" ")
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, name: &str, value: impl AsRef<[u8]>) {
+    write_field(out, name.as_bytes(), value.as_ref(), &FieldType::String);
+}
+
+fn write_binary(out: &mut Vec<u8>, name: &str, value: impl AsRef<[u8]>) {
+    write_field(out, name.as_bytes(), value.as_ref(), &FieldType::Binary);
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        s.push_str(&format!("{:02x}", b));
+        s
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::journald::{Entry, JournalExportRead};
+
+    use super::{EntryGenerator, Workload};
+
+    #[test]
+    fn generated_streams_are_parsable() {
+        for workload in [
+            Workload::Realistic,
+            Workload::StringHeavy,
+            Workload::BinaryHeavy,
+            Workload::ManySmallFields,
+        ] {
+            let stream = EntryGenerator::new(7).with_workload(workload).generate(100);
+            let mut export_read = JournalExportRead::new(&stream[..]);
+            let mut count = 0;
+            while export_read.parse_next().unwrap().is_some() {
+                let e = export_read.get_entry();
+                assert!(e.iter().any(|(name, _, _)| name == b"__CURSOR"));
+                count += 1;
+            }
+            assert_eq!(count, 100);
+        }
+    }
+
+    #[test]
+    fn generator_is_deterministic() {
+        let a = EntryGenerator::new(42).generate(10);
+        let b = EntryGenerator::new(42).generate(10);
+        assert_eq!(a, b);
+    }
+}
@@ -0,0 +1,351 @@
+//! Annotate IP addresses in entries with their location and network.
+//!
+//! [GeoIp] finds IPv4 and IPv6 addresses in the values of some fields,
+//! `MESSAGE` by default, and looks them up in databases in the MaxMind DB
+//! format, such as GeoLite2-Country and GeoLite2-ASN. For every address that
+//! a database knows, it appends `GEOIP_IP` followed by `GEOIP_COUNTRY` (the
+//! ISO code), `GEOIP_ASN` and `GEOIP_ASN_ORG`, as far as they are known; an
+//! entry with several such addresses thus has several groups of these fields.
+//! `GEOIP_*` fields that the entry already has are replaced.
+
+use std::{collections::HashMap, io, net::IpAddr, path::Path, sync::OnceLock};
+
+use maxminddb::{MaxMindDbError, Reader};
+use regex::bytes::Regex;
+use serde::Deserialize;
+
+use crate::{
+    journald::{parser::FieldType, write_field, Entry},
+    transform::{EntryView, FieldPattern, Transform, TransformResult},
+};
+
+/// The number of addresses whose lookups are remembered.
+const CACHE_SIZE: usize = 65536;
+
+#[derive(Deserialize)]
+struct Record {
+    country: Option<Country>,
+    registered_country: Option<Country>,
+    autonomous_system_number: Option<u32>,
+    autonomous_system_organization: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Country {
+    iso_code: Option<String>,
+}
+
+/// What the databases know about an address.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Location {
+    pub country: Option<String>,
+    pub asn: Option<u32>,
+    pub asn_org: Option<String>,
+}
+
+impl Location {
+    fn is_empty(&self) -> bool {
+        self.country.is_none() && self.asn.is_none() && self.asn_org.is_none()
+    }
+}
+
+fn address_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?i)\b(?:\d{1,3}\.){3}\d{1,3}\b|[0-9a-f]*:[0-9a-f]*:[0-9a-f:.]*").unwrap()
+    })
+}
+
+/// The IP addresses in `value`, in order and without duplicates.
+pub fn find_addresses(value: &[u8]) -> Vec<IpAddr> {
+    let mut addresses = vec![];
+    for m in address_regex().find_iter(value) {
+        let parsed = std::str::from_utf8(m.as_bytes())
+            .ok()
+            .and_then(|s| s.parse().ok());
+        if let Some(address) = parsed {
+            if !addresses.contains(&address) {
+                addresses.push(address);
+            }
+        }
+    }
+    addresses
+}
+
+#[derive(Default)]
+pub struct GeoIp {
+    databases: Vec<Reader<Vec<u8>>>,
+    fields: Vec<FieldPattern>,
+    cache: HashMap<IpAddr, Location>,
+}
+
+impl GeoIp {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens the database at `path`.
+    pub fn open(path: &Path) -> Result<Reader<Vec<u8>>, MaxMindDbError> {
+        Reader::open_readfile(path)
+    }
+
+    /// Looks addresses up in `database` as well. Of several databases that
+    /// know an address, the first one that was added wins.
+    pub fn with_database(mut self, database: Reader<Vec<u8>>) -> Self {
+        self.databases.push(database);
+        self
+    }
+
+    /// Searches the fields matching `field` for addresses instead of
+    /// `MESSAGE`; can be called several times.
+    pub fn with_field(mut self, field: FieldPattern) -> Self {
+        self.fields.push(field);
+        self
+    }
+
+    fn searches(&self, name: &[u8]) -> bool {
+        match self.fields.is_empty() {
+            true => name == b"MESSAGE",
+            false => self.fields.iter().any(|f| f.matches(name)),
+        }
+    }
+
+    /// What the databases know about `address`.
+    pub fn lookup(&mut self, address: IpAddr) -> Location {
+        if let Some(location) = self.cache.get(&address) {
+            return location.clone();
+        }
+        let mut location = Location::default();
+        for database in self.databases.iter() {
+            // IPv6 addresses cannot be looked up in IPv4 databases.
+            let Ok(Some(record)) = database.lookup(address).and_then(|r| r.decode::<Record>())
+            else {
+                continue;
+            };
+            let country = record.country.or(record.registered_country);
+            location.country = location.country.or(country.and_then(|c| c.iso_code));
+            location.asn = location.asn.or(record.autonomous_system_number);
+            location.asn_org = location.asn_org.or(record.autonomous_system_organization);
+        }
+        if self.cache.len() >= CACHE_SIZE {
+            self.cache.clear();
+        }
+        self.cache.insert(address, location.clone());
+        location
+    }
+
+    /// Appends `entry` with the locations of its addresses to `out`. Returns
+    /// `false` and leaves `out` untouched if no address is known.
+    pub fn apply(&mut self, entry: &impl Entry, out: &mut Vec<u8>) -> bool {
+        let mut addresses = vec![];
+        for (name, value, _) in entry.iter() {
+            if self.searches(name) {
+                for address in find_addresses(value) {
+                    if !addresses.contains(&address) {
+                        addresses.push(address);
+                    }
+                }
+            }
+        }
+        let located: Vec<_> = addresses
+            .into_iter()
+            .map(|a| (a, self.lookup(a)))
+            .filter(|(_, l)| !l.is_empty())
+            .collect();
+        if located.is_empty() {
+            return false;
+        }
+        for (name, value, typ) in entry.iter() {
+            if !name.starts_with(b"GEOIP_") {
+                write_field(out, name, value, &typ);
+            }
+        }
+        let mut field = |name: &str, value: &str| {
+            write_field(out, name.as_bytes(), value.as_bytes(), &FieldType::String)
+        };
+        for (address, location) in located {
+            field("GEOIP_IP", &address.to_string());
+            if let Some(country) = &location.country {
+                field("GEOIP_COUNTRY", country);
+            }
+            if let Some(asn) = location.asn {
+                field("GEOIP_ASN", &asn.to_string());
+            }
+            if let Some(org) = &location.asn_org {
+                field("GEOIP_ASN_ORG", org);
+            }
+        }
+        out.push(b'\n');
+        true
+    }
+}
+
+impl Transform for GeoIp {
+    fn apply(&mut self, entry: EntryView<'_>) -> io::Result<TransformResult> {
+        Ok(match GeoIp::apply(self, &entry.entry, entry.out) {
+            true => TransformResult::Replace,
+            false => TransformResult::Keep,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use maxminddb::Reader;
+
+    use crate::{
+        journald::{Entry, JournalExportRead},
+        testutil::write_string,
+    };
+
+    use super::{find_addresses, GeoIp};
+
+    /// Encodes a value of the MaxMind DB data section.
+    enum Value<'a> {
+        Str(&'a str),
+        U16(u16),
+        U32(u32),
+        U64(u64),
+        Map(Vec<(&'a str, Value<'a>)>),
+        Array(Vec<Value<'a>>),
+    }
+
+    impl Value<'_> {
+        fn encode(&self, out: &mut Vec<u8>) {
+            // Sizes from 29 to 284 take another byte.
+            let control = |out: &mut Vec<u8>, typ: u8, size: usize| {
+                let short = size.min(29) as u8;
+                match typ {
+                    0..=7 => out.push(typ << 5 | short),
+                    _ => out.extend([short, typ - 7]),
+                }
+                if size >= 29 {
+                    out.push((size - 29) as u8);
+                }
+            };
+            let uint = |out: &mut Vec<u8>, typ: u8, value: u64| {
+                let bytes = value.to_be_bytes();
+                let start = bytes.iter().position(|&b| b != 0).unwrap_or(8);
+                control(out, typ, 8 - start);
+                out.extend(&bytes[start..]);
+            };
+            match self {
+                Value::Str(s) => {
+                    control(out, 2, s.len());
+                    out.extend(s.as_bytes());
+                }
+                Value::U16(v) => uint(out, 5, *v as u64),
+                Value::U32(v) => uint(out, 6, *v as u64),
+                Value::U64(v) => uint(out, 9, *v),
+                Value::Map(entries) => {
+                    control(out, 7, entries.len());
+                    for (key, value) in entries {
+                        Value::Str(key).encode(out);
+                        value.encode(out);
+                    }
+                }
+                Value::Array(values) => {
+                    control(out, 11, values.len());
+                    for value in values {
+                        value.encode(out);
+                    }
+                }
+            }
+        }
+    }
+
+    /// An IPv4 database that only knows `network`/24.
+    fn database(network: [u8; 3], record: Value) -> Reader<Vec<u8>> {
+        let node_count = 24u32;
+        let bits = u32::from_be_bytes([network[0], network[1], network[2], 0]);
+        let mut db = vec![];
+        for depth in 0..24 {
+            let next = match depth {
+                23 => node_count + 16,
+                _ => depth + 1,
+            };
+            let mut records = [node_count, node_count];
+            records[(bits >> (31 - depth) & 1) as usize] = next;
+            for r in records {
+                db.extend(&r.to_be_bytes()[1..]);
+            }
+        }
+        db.extend([0; 16]);
+        record.encode(&mut db);
+        db.extend(b"\xab\xcd\xefMaxMind.com");
+        Value::Map(vec![
+            ("binary_format_major_version", Value::U16(2)),
+            ("binary_format_minor_version", Value::U16(0)),
+            ("build_epoch", Value::U64(1_700_000_000)),
+            ("database_type", Value::Str("Test")),
+            ("description", Value::Map(vec![])),
+            ("ip_version", Value::U16(4)),
+            ("languages", Value::Array(vec![])),
+            ("node_count", Value::U32(node_count)),
+            ("record_size", Value::U16(24)),
+        ])
+        .encode(&mut db);
+        Reader::from_source(db).unwrap()
+    }
+
+    #[test]
+    fn annotates_known_addresses() {
+        assert_eq!(
+            find_addresses(b"from 10.0.0.1 and 2001:db8::1, at 12:34:56, again 10.0.0.1"),
+            ["10.0.0.1".parse().unwrap(), "2001:db8::1".parse().unwrap()] as [std::net::IpAddr; 2]
+        );
+
+        let country = database(
+            [192, 0, 2],
+            Value::Map(vec![(
+                "country",
+                Value::Map(vec![("iso_code", Value::Str("DE"))]),
+            )]),
+        );
+        let asn = database(
+            [192, 0, 2],
+            Value::Map(vec![
+                ("autonomous_system_number", Value::U32(64500)),
+                ("autonomous_system_organization", Value::Str("Example")),
+            ]),
+        );
+        let mut geoip = GeoIp::new().with_database(country).with_database(asn);
+
+        let mut input = vec![];
+        write_string(
+            &mut input,
+            "MESSAGE",
+            "Failed password for root from 192.0.2.7 port 22 (via 10.0.0.1)",
+        );
+        write_string(&mut input, "GEOIP_COUNTRY", "stale");
+        input.push(b'\n');
+        let entry = JournalExportRead::new(&input[..]).next().unwrap();
+        let mut out = vec![];
+        assert!(geoip.apply(&entry, &mut out));
+        let annotated = JournalExportRead::new(&out[..]).next().unwrap();
+        let fields: Vec<_> = annotated
+            .iter()
+            .skip(1)
+            .map(|(n, v, _)| {
+                let s = |b: &[u8]| String::from_utf8_lossy(b).into_owned();
+                (s(n), s(v))
+            })
+            .collect();
+        let expected = [
+            ("GEOIP_IP", "192.0.2.7"),
+            ("GEOIP_COUNTRY", "DE"),
+            ("GEOIP_ASN", "64500"),
+            ("GEOIP_ASN_ORG", "Example"),
+        ];
+        assert_eq!(
+            fields,
+            expected.map(|(n, v)| (n.to_string(), v.to_string()))
+        );
+
+        let mut input = vec![];
+        write_string(&mut input, "MESSAGE", "from 198.51.100.1");
+        input.push(b'\n');
+        let entry = JournalExportRead::new(&input[..]).next().unwrap();
+        assert!(!geoip.apply(&entry, &mut vec![]));
+    }
+}
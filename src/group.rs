@@ -0,0 +1,176 @@
+//! Group entries by the value of a field.
+//!
+//! [GroupBy] assigns every entry to the group of its key, e.g. its unit
+//! ([UNIT]) or the invocation of its unit ([INVOCATION]), and feeds it to an
+//! [Aggregate] per group. Aggregates may summarize their group, like
+//! [GroupStats], or keep a sub-stream of it: `Vec<u8>` collects the entries
+//! in memory and [GroupSink] writes them to an [EntrySink] per group.
+
+use std::{
+    collections::{btree_map, BTreeMap},
+    io,
+};
+
+use serde::Serialize;
+
+use crate::{journald::Entry, sink::EntrySink};
+
+/// The unit that logged an entry.
+pub const UNIT: &str = "_SYSTEMD_UNIT";
+/// The invocation of a unit that logged an entry.
+pub const INVOCATION: &str = "_SYSTEMD_INVOCATION_ID";
+
+/// Accumulates the entries of one group.
+pub trait Aggregate {
+    fn push(&mut self, entry: &impl Entry) -> io::Result<()>;
+}
+
+/// Collects the entries of a group in the Journal Export Format.
+impl Aggregate for Vec<u8> {
+    fn push(&mut self, entry: &impl Entry) -> io::Result<()> {
+        self.extend_from_slice(entry.as_bytes());
+        Ok(())
+    }
+}
+
+/// The number of entries of a group, how many of them are errors (`PRIORITY`
+/// 3 or less) and their time range.
+#[derive(Serialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct GroupStats {
+    pub entries: u64,
+    pub errors: u64,
+    pub first_timestamp: Option<u64>,
+    pub last_timestamp: Option<u64>,
+}
+
+impl GroupStats {
+    /// The fraction of entries that are errors.
+    pub fn error_rate(&self) -> f64 {
+        match self.entries {
+            0 => 0.0,
+            n => self.errors as f64 / n as f64,
+        }
+    }
+}
+
+impl Aggregate for GroupStats {
+    fn push(&mut self, entry: &impl Entry) -> io::Result<()> {
+        self.entries += 1;
+        if entry.get_u64(b"PRIORITY").is_some_and(|p| p <= 3) {
+            self.errors += 1;
+        }
+        if let Some(ts) = entry.realtime_timestamp() {
+            self.first_timestamp = Some(self.first_timestamp.map_or(ts, |t| t.min(ts)));
+            self.last_timestamp = Some(self.last_timestamp.map_or(ts, |t| t.max(ts)));
+        }
+        Ok(())
+    }
+}
+
+/// Writes the entries of a group to a sink.
+pub struct GroupSink<S>(pub S);
+
+impl<S: EntrySink> Aggregate for GroupSink<S> {
+    fn push(&mut self, entry: &impl Entry) -> io::Result<()> {
+        self.0.write_entry(entry.as_bytes())
+    }
+}
+
+/// Groups entries by the value of a field. Entries without the field form
+/// the group with the key `None`.
+pub struct GroupBy<A, F> {
+    fields: Vec<Vec<u8>>,
+    create: F,
+    groups: BTreeMap<Option<Vec<u8>>, A>,
+}
+
+impl<A, F> GroupBy<A, F>
+where
+    A: Aggregate,
+    F: FnMut(Option<&[u8]>) -> io::Result<A>,
+{
+    /// Groups by the field `name`. `create` is called with the key of every
+    /// new group.
+    pub fn new(name: impl Into<Vec<u8>>, create: F) -> Self {
+        Self {
+            fields: vec![name.into()],
+            create,
+            groups: BTreeMap::new(),
+        }
+    }
+
+    /// Uses the field `name` as key for entries that lack the fields given
+    /// before, e.g. `UNIT` for the messages of systemd about a unit.
+    pub fn with_fallback(mut self, name: impl Into<Vec<u8>>) -> Self {
+        self.fields.push(name.into());
+        self
+    }
+
+    pub fn push(&mut self, entry: &impl Entry) -> io::Result<()> {
+        let key = self.fields.iter().find_map(|f| entry.get(f));
+        let group = match self.groups.entry(key.map(<[u8]>::to_vec)) {
+            btree_map::Entry::Occupied(e) => e.into_mut(),
+            btree_map::Entry::Vacant(e) => {
+                let group = (self.create)(key)?;
+                e.insert(group)
+            }
+        };
+        group.push(entry)
+    }
+
+    pub fn groups(&self) -> &BTreeMap<Option<Vec<u8>>, A> {
+        &self.groups
+    }
+
+    /// The groups ordered by key.
+    pub fn into_groups(self) -> BTreeMap<Option<Vec<u8>>, A> {
+        self.groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{journald::JournalExportRead, testutil::write_string};
+
+    use super::{GroupBy, GroupStats, UNIT};
+
+    #[test]
+    fn groups_by_unit() {
+        let mut stream = vec![];
+        for (ts, unit, priority) in [
+            (1, Some("a.service"), 6),
+            (2, Some("b.service"), 3),
+            (3, Some("a.service"), 2),
+            (4, None, 6),
+            (5, Some("a.service"), 6),
+        ] {
+            write_string(&mut stream, "__REALTIME_TIMESTAMP", ts.to_string());
+            if let Some(unit) = unit {
+                write_string(&mut stream, UNIT, unit);
+            }
+            write_string(&mut stream, "PRIORITY", priority.to_string());
+            stream.push(b'\n');
+        }
+
+        let mut stats = GroupBy::new(UNIT, |_| Ok(GroupStats::default()));
+        let mut streams = GroupBy::new(UNIT, |_| io::Result::Ok(vec![]));
+        for e in JournalExportRead::new(&stream[..]) {
+            stats.push(&e).unwrap();
+            streams.push(&e).unwrap();
+        }
+        let stats = stats.into_groups();
+        let a = &stats[&Some(b"a.service".to_vec())];
+        assert_eq!(
+            (a.entries, a.errors, a.first_timestamp, a.last_timestamp),
+            (3, 1, Some(1), Some(5))
+        );
+        assert_eq!(stats[&Some(b"b.service".to_vec())].error_rate(), 1.0);
+        assert_eq!(stats[&None].entries, 1);
+
+        let streams = streams.into_groups();
+        let a = &streams[&Some(b"a.service".to_vec())];
+        assert_eq!(JournalExportRead::new(&a[..]).count(), 3);
+    }
+}
@@ -0,0 +1,433 @@
+//! Learn about an export file without reading all of it.
+//!
+//! [peek_bounds] reads the first entries and the last entry of an
+//! uncompressed file, which takes two small reads however large the file is,
+//! and estimates the number of entries from the average size of the first
+//! ones. Compressed files and streams cannot be read from their end; they are
+//! read completely and their entries are counted exactly.
+//!
+//! [inspect] adds what an unknown file is: its [FileFormat] by its first
+//! bytes, its hosts and boots and anomalies such as a truncated end or time
+//! going backwards. Unless it scans all entries, the hosts and boots are
+//! those of the first and last entry.
+
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use crate::{
+    container,
+    journald::{Entry, JournalExportRead, JournalExportReverseRead},
+    source,
+};
+
+/// The number of entries whose size the estimate is based on.
+const SAMPLE_ENTRIES: u64 = 256;
+
+/// What identifies an entry in time.
+#[derive(Serialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct EntryMeta {
+    pub realtime: Option<u64>,
+    pub monotonic: Option<u64>,
+    pub boot_id: Option<String>,
+    pub seqnum: Option<u64>,
+    pub cursor: Option<String>,
+    pub hostname: Option<String>,
+}
+
+impl EntryMeta {
+    pub fn of(entry: &impl Entry) -> Self {
+        let string = |name: &[u8]| {
+            entry
+                .get(name)
+                .map(|v| String::from_utf8_lossy(v).into_owned())
+        };
+        Self {
+            realtime: entry.realtime_timestamp(),
+            monotonic: entry.get_u64(b"__MONOTONIC_TIMESTAMP"),
+            boot_id: string(b"_BOOT_ID"),
+            seqnum: entry.get_u64(b"__SEQNUM"),
+            cursor: string(b"__CURSOR"),
+            hostname: string(b"_HOSTNAME"),
+        }
+    }
+}
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct Bounds {
+    pub first: EntryMeta,
+    pub last: EntryMeta,
+    /// The size of the file; compressed, if it is.
+    pub bytes: u64,
+    pub entries: u64,
+    /// Whether `entries` was counted rather than estimated.
+    pub exact: bool,
+}
+
+impl Bounds {
+    /// The time from the first to the last entry in microseconds, if both
+    /// have timestamps.
+    pub fn span(&self) -> Option<u64> {
+        Some(self.last.realtime?.saturating_sub(self.first.realtime?))
+    }
+}
+
+/// The first and last entry of the export file at `path` and the number of
+/// its entries; `None` if it has no entries.
+pub fn peek_bounds(path: &Path) -> io::Result<Option<Bounds>> {
+    let bytes = std::fs::metadata(path)?.len();
+    if !source::is_verbatim(path) {
+        return scan_bounds(source::open(path)?, bytes);
+    }
+    let mut head = JournalExportRead::new(File::open(path)?);
+    let Some(()) = head.parse_next()? else {
+        return Ok(None);
+    };
+    let first = EntryMeta::of(&head.get_entry());
+    let mut entries = 1;
+    while entries < SAMPLE_ENTRIES && head.parse_next()?.is_some() {
+        entries += 1;
+    }
+    let sampled = head.position() as u64;
+    let exact = head.parse_next()?.is_none();
+    let mut tail = JournalExportReverseRead::new(File::open(path)?)?;
+    tail.parse_next()?;
+    let last = EntryMeta::of(tail.get_entry());
+    if !exact {
+        entries = (bytes as f64 / sampled as f64 * entries as f64).round() as u64;
+    }
+    Ok(Some(Bounds {
+        first,
+        last,
+        bytes,
+        entries,
+        exact,
+    }))
+}
+
+/// [peek_bounds] for streams that are read completely.
+fn scan_bounds(read: impl Read, bytes: u64) -> io::Result<Option<Bounds>> {
+    let mut scan = Scan::default();
+    scan.read(read)?;
+    Ok(scan.bounds.map(|bounds| Bounds { bytes, ..bounds }))
+}
+
+/// What the first bytes of a file say it is.
+#[derive(Serialize, PartialEq, Eq, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileFormat {
+    Export,
+    GzipExport,
+    ZstdExport,
+    /// The binary format of journald, which is not read.
+    BinaryJournal,
+    /// A Docker or CRI log.
+    ContainerLog,
+    WindowsEventLog,
+    Unknown,
+}
+
+impl FileFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileFormat::Export => "journal export",
+            FileFormat::GzipExport => "journal export, gzip-compressed",
+            FileFormat::ZstdExport => "journal export, zstd-compressed",
+            FileFormat::BinaryJournal => "binary journal",
+            FileFormat::ContainerLog => "container log",
+            FileFormat::WindowsEventLog => "Windows event log",
+            FileFormat::Unknown => "unknown",
+        }
+    }
+
+    /// Recognizes a format by `head`, the start of a file.
+    pub fn detect(head: &[u8]) -> Self {
+        if head.starts_with(b"LPKSHHRH") {
+            return FileFormat::BinaryJournal;
+        }
+        if head.starts_with(b"ElfFile\0") {
+            return FileFormat::WindowsEventLog;
+        }
+        if head.starts_with(&[0x1f, 0x8b]) {
+            return FileFormat::GzipExport;
+        }
+        if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            return FileFormat::ZstdExport;
+        }
+        if container::is_container_log(head) {
+            return FileFormat::ContainerLog;
+        }
+        // Export files start with a field, `NAME=value` or a binary `NAME`.
+        let line = head.split(|&b| b == b'\n').next().unwrap_or_default();
+        let name = line.split(|&b| b == b'=').next().unwrap_or_default();
+        let valid = !name.is_empty()
+            && name
+                .iter()
+                .all(|&b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'_');
+        match valid {
+            true => FileFormat::Export,
+            false => FileFormat::Unknown,
+        }
+    }
+}
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct FileInfo {
+    pub format: FileFormat,
+    pub bytes: u64,
+    /// `None` if the file has no entries or cannot be read.
+    pub bounds: Option<Bounds>,
+    /// In the order they were found.
+    pub hosts: Vec<String>,
+    /// In the order they were found.
+    pub boots: Vec<String>,
+    /// Whether all entries were read, such that `hosts` and `boots` are
+    /// complete.
+    pub scanned: bool,
+    pub anomalies: Vec<String>,
+}
+
+/// Describes the file at `path`, reading all its entries if `scan`.
+pub fn inspect(path: &Path, scan: bool) -> io::Result<FileInfo> {
+    let bytes = std::fs::metadata(path)?.len();
+    let mut head = vec![];
+    File::open(path)?.take(64 * 1024).read_to_end(&mut head)?;
+    let format = FileFormat::detect(&head);
+    let mut info = FileInfo {
+        format,
+        bytes,
+        bounds: None,
+        hosts: vec![],
+        boots: vec![],
+        scanned: false,
+        anomalies: vec![],
+    };
+    let extension = path.extension().and_then(|e| e.to_str());
+    let expected = match extension {
+        Some("gz") => FileFormat::GzipExport,
+        Some("zst") => FileFormat::ZstdExport,
+        Some("journal" | "journal~") => FileFormat::BinaryJournal,
+        Some("evtx") => FileFormat::WindowsEventLog,
+        Some("log") if format == FileFormat::ContainerLog => FileFormat::ContainerLog,
+        _ => FileFormat::Export,
+    };
+    if format == FileFormat::BinaryJournal {
+        info.anomalies.push(
+            "binary journals cannot be read; export them with `journalctl -o export`".to_string(),
+        );
+        return Ok(info);
+    }
+    if head.is_empty() {
+        return Ok(info);
+    }
+    if format != expected {
+        // The file would be read according to its name.
+        info.anomalies.push(format!(
+            "the content is a {} but the name suggests a {}",
+            format.name(),
+            expected.name()
+        ));
+        return Ok(info);
+    }
+
+    let mut scan = scan || !source::is_verbatim(path);
+    if !scan {
+        match peek_bounds(path) {
+            Ok(bounds) => info.bounds = bounds,
+            // Find out how much is readable.
+            Err(_) => scan = true,
+        }
+        for meta in info.bounds.iter().flat_map(|b| [&b.first, &b.last]) {
+            push_new(&mut info.hosts, meta.hostname.as_deref());
+            push_new(&mut info.boots, meta.boot_id.as_deref());
+        }
+    }
+    if scan {
+        let mut scan = Scan::default();
+        if let Err(e) = scan.read(source::open(path)?) {
+            info.anomalies.push(format!(
+                "unreadable after {} entries: {}",
+                scan.entries(),
+                e
+            ));
+        }
+        info.bounds = scan.bounds.map(|bounds| Bounds { bytes, ..bounds });
+        info.scanned = true;
+        info.hosts = scan.hosts;
+        info.boots = scan.boots;
+        if scan.backwards > 0 {
+            info.anomalies.push(format!(
+                "time goes backwards {} times, by up to {} s",
+                scan.backwards,
+                scan.max_backwards / 1_000_000
+            ));
+        }
+        if scan.untimed > 0 {
+            info.anomalies.push(format!(
+                "{} entries without __REALTIME_TIMESTAMP",
+                scan.untimed
+            ));
+        }
+    }
+
+    if let Some(b) = &info.bounds {
+        if let (Some(first), Some(last)) = (b.first.realtime, b.last.realtime) {
+            if last < first {
+                info.anomalies
+                    .push("the last entry is older than the first".to_string());
+            }
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_micros() as u64);
+        let future = [b.first.realtime, b.last.realtime]
+            .into_iter()
+            .flatten()
+            .any(|t| t > now + 86_400_000_000);
+        if future {
+            info.anomalies
+                .push("timestamps more than a day in the future".to_string());
+        }
+    }
+    Ok(info)
+}
+
+fn push_new(values: &mut Vec<String>, value: Option<&str>) {
+    if let Some(value) = value.filter(|v| !values.iter().any(|x| x == v)) {
+        values.push(value.to_string());
+    }
+}
+
+/// The bounds, hosts, boots and anomalies of all entries.
+#[derive(Default)]
+struct Scan {
+    bounds: Option<Bounds>,
+    hosts: Vec<String>,
+    boots: Vec<String>,
+    /// The number of entries older than the one before.
+    backwards: u64,
+    /// The largest step back in microseconds.
+    max_backwards: u64,
+    untimed: u64,
+    previous: Option<u64>,
+}
+
+impl Scan {
+    fn entries(&self) -> u64 {
+        self.bounds.as_ref().map_or(0, |b| b.entries)
+    }
+
+    fn read(&mut self, read: impl Read) -> io::Result<()> {
+        let mut reader = JournalExportRead::new(read);
+        while reader.parse_next()?.is_some() {
+            let entry = reader.get_entry();
+            let meta = EntryMeta::of(&entry);
+            push_new(&mut self.hosts, meta.hostname.as_deref());
+            push_new(&mut self.boots, meta.boot_id.as_deref());
+            match (self.previous, meta.realtime) {
+                (_, None) => self.untimed += 1,
+                (Some(previous), Some(t)) if t < previous => {
+                    self.backwards += 1;
+                    self.max_backwards = self.max_backwards.max(previous - t);
+                }
+                _ => {}
+            }
+            self.previous = meta.realtime.or(self.previous);
+            match &mut self.bounds {
+                Some(bounds) => {
+                    bounds.entries += 1;
+                    bounds.last = meta;
+                }
+                None => {
+                    self.bounds = Some(Bounds {
+                        first: meta.clone(),
+                        last: meta,
+                        bytes: 0,
+                        entries: 1,
+                        exact: true,
+                    })
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::testutil::write_string;
+
+    use super::{inspect, peek_bounds, FileFormat};
+
+    #[test]
+    fn peeks_at_head_and_tail() {
+        let mut stream = vec![];
+        for i in 0..1000u64 {
+            write_string(&mut stream, "__REALTIME_TIMESTAMP", (1000 + i).to_string());
+            write_string(&mut stream, "_BOOT_ID", ["a", "b"][(i / 500) as usize]);
+            write_string(&mut stream, "MESSAGE", format!("entry {:04}", i));
+            stream.push(b'\n');
+        }
+        let mut file = tempfile::Builder::new()
+            .suffix(".export")
+            .tempfile()
+            .unwrap();
+        file.write_all(&stream).unwrap();
+
+        let bounds = peek_bounds(file.path()).unwrap().unwrap();
+        assert_eq!(bounds.first.realtime, Some(1000));
+        assert_eq!(bounds.last.realtime, Some(1999));
+        assert_eq!(bounds.first.boot_id.as_deref(), Some("a"));
+        assert_eq!(bounds.last.boot_id.as_deref(), Some("b"));
+        assert_eq!(bounds.span(), Some(999));
+        assert_eq!((bounds.entries, bounds.exact), (1000, false));
+
+        let mut file = tempfile::Builder::new()
+            .suffix(".export")
+            .tempfile()
+            .unwrap();
+        file.write_all(&stream[..stream.len() / 100]).unwrap();
+        let bounds = peek_bounds(file.path()).unwrap().unwrap();
+        assert!(bounds.exact);
+
+        let file = tempfile::Builder::new()
+            .suffix(".export")
+            .tempfile()
+            .unwrap();
+        assert_eq!(peek_bounds(file.path()).unwrap(), None);
+
+        // Truncated in the middle of an entry and time going backwards.
+        let mut file = tempfile::Builder::new()
+            .suffix(".export")
+            .tempfile()
+            .unwrap();
+        let mut early = vec![];
+        write_string(&mut early, "__REALTIME_TIMESTAMP", "5000000");
+        early.push(b'\n');
+        file.write_all(&early).unwrap();
+        file.write_all(&stream[..stream.len() - 5]).unwrap();
+        let info = inspect(file.path(), false).unwrap();
+        assert_eq!(info.format, FileFormat::Export);
+        assert!(info.scanned);
+        assert_eq!(info.bounds.unwrap().entries, 1000);
+        assert_eq!(info.boots, ["a", "b"]);
+        assert_eq!(info.anomalies.len(), 3, "{:?}", info.anomalies);
+        assert!(info.anomalies[0].starts_with("unreadable after 1000 entries"));
+        assert_eq!(
+            info.anomalies[1],
+            "time goes backwards 1 times, by up to 4 s"
+        );
+        assert_eq!(info.anomalies[2], "the last entry is older than the first");
+
+        assert_eq!(FileFormat::detect(b"LPKSHHRH\0"), FileFormat::BinaryJournal);
+        assert_eq!(FileFormat::detect(b"\x1f\x8b\x08"), FileFormat::GzipExport);
+        assert_eq!(FileFormat::detect(b"hello world\n"), FileFormat::Unknown);
+    }
+}
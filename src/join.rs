@@ -0,0 +1,377 @@
+//! Annotate entries with the events of an external timeline.
+//!
+//! A [Join] holds a list of [Event]s, e.g. deployments or alert windows, that
+//! each cover a range of time and carry fields such as `DEPLOY_ID`. As a
+//! [Transform], it adds the fields of every event whose range contains the
+//! `__REALTIME_TIMESTAMP` of an entry to the entry, which helps to relate the
+//! logs of an incident to what happened around it.
+//!
+//! Events are read from CSV with a header row, or from JSON, either an array
+//! of objects or one object per line:
+//!
+//! ```text
+//! start,end,deploy_id
+//! 2024-05-01T10:00:00Z,2024-05-01T10:30:00Z,42
+//! ```
+//!
+//! The column or key `start` (or `time`) gives the start of an event and the
+//! optional `end` its end, either in microseconds since the epoch or in RFC
+//! 3339. All other columns become fields, named in upper case. An event
+//! without an end lasts for the window given with [Join::with_window], or
+//! else until the next event without an end starts.
+
+use std::{fs, io, path::Path, time::Duration};
+
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::{
+    journald::{parser::FieldType, write_field, Entry},
+    transform::{EntryView, Transform, TransformResult},
+};
+
+#[derive(Error, Debug)]
+#[error("line {line}: {reason}")]
+pub struct InvalidEvents {
+    pub line: usize,
+    pub reason: String,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Event {
+    /// In microseconds since the epoch.
+    pub start: u64,
+    /// The end of the event, exclusive.
+    pub end: Option<u64>,
+    pub fields: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Join {
+    /// Ordered by start.
+    events: Vec<Event>,
+    /// The end of each event, with those without an end resolved.
+    ends: Vec<u64>,
+    window: Option<u64>,
+}
+
+impl Join {
+    pub fn new(mut events: Vec<Event>) -> Self {
+        events.sort_by_key(|e| e.start);
+        Self {
+            events,
+            ends: vec![],
+            window: None,
+        }
+        .resolve_ends()
+    }
+
+    /// Lets events without an end last for `window`.
+    pub fn with_window(self, window: Duration) -> Self {
+        Self {
+            window: Some(window.as_micros() as u64),
+            ..self
+        }
+        .resolve_ends()
+    }
+
+    fn resolve_ends(mut self) -> Self {
+        let mut next = u64::MAX;
+        self.ends = vec![0; self.events.len()];
+        for (i, event) in self.events.iter().enumerate().rev() {
+            self.ends[i] = match (event.end, self.window) {
+                (Some(end), _) => end,
+                (None, Some(window)) => event.start.saturating_add(window),
+                (None, None) => next,
+            };
+            if event.end.is_none() {
+                next = event.start;
+            }
+        }
+        self
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Parses events in CSV with a header row.
+    pub fn parse_csv(csv: &str) -> Result<Self, InvalidEvents> {
+        let mut lines = csv
+            .lines()
+            .enumerate()
+            .map(|(i, l)| (i + 1, l))
+            .filter(|(_, l)| !l.trim().is_empty());
+        let Some((line, header)) = lines.next() else {
+            return Ok(Self::default());
+        };
+        let header = split_csv(header).map_err(|reason| InvalidEvents { line, reason })?;
+        let mut events = vec![];
+        for (line, row) in lines {
+            let invalid = |reason| InvalidEvents { line, reason };
+            let row = split_csv(row).map_err(invalid)?;
+            if row.len() != header.len() {
+                return Err(invalid(format!(
+                    "expected {} columns, found {}",
+                    header.len(),
+                    row.len()
+                )));
+            }
+            let columns = header.iter().cloned().zip(row);
+            events.push(event(columns).map_err(invalid)?);
+        }
+        Ok(Self::new(events))
+    }
+
+    /// Parses events in JSON, either an array of objects or one object per
+    /// line.
+    pub fn parse_json(json: &str) -> Result<Self, InvalidEvents> {
+        let objects: Vec<(usize, Value)> = if json.trim_start().starts_with('[') {
+            let values: Vec<Value> = serde_json::from_str(json).map_err(|e| InvalidEvents {
+                line: e.line(),
+                reason: e.to_string(),
+            })?;
+            values.into_iter().map(|v| (1, v)).collect()
+        } else {
+            json.lines()
+                .enumerate()
+                .filter(|(_, l)| !l.trim().is_empty())
+                .map(|(i, l)| {
+                    serde_json::from_str(l)
+                        .map(|v| (i + 1, v))
+                        .map_err(|e| InvalidEvents {
+                            line: i + 1,
+                            reason: e.to_string(),
+                        })
+                })
+                .collect::<Result<_, _>>()?
+        };
+        let mut events = vec![];
+        for (line, object) in objects {
+            let invalid = |reason| InvalidEvents { line, reason };
+            let Value::Object(object) = object else {
+                return Err(invalid("expected an object".to_string()));
+            };
+            let columns = object.into_iter().map(|(key, value)| {
+                let value = match value {
+                    Value::String(s) => s,
+                    Value::Null => String::new(),
+                    v => v.to_string(),
+                };
+                (key, value)
+            });
+            events.push(event(columns).map_err(invalid)?);
+        }
+        Ok(Self::new(events))
+    }
+
+    /// Loads events from a file, which is read as JSON if its name ends in
+    /// `.json` or `.jsonl` and as CSV otherwise.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        let json = path
+            .extension()
+            .is_some_and(|e| e == "json" || e == "jsonl");
+        match json {
+            true => Self::parse_json(&content),
+            false => Self::parse_csv(&content),
+        }
+        .map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })
+    }
+
+    /// The events whose range contains `timestamp`, by start.
+    pub fn events_at(&self, timestamp: u64) -> impl Iterator<Item = &Event> {
+        let started = self.events.partition_point(|e| e.start <= timestamp);
+        self.events[..started]
+            .iter()
+            .zip(self.ends.iter())
+            .filter(move |(_, &end)| timestamp < end)
+            .map(|(e, _)| e)
+    }
+
+    /// Appends `entry` with the fields of the events at its timestamp to
+    /// `out`, replacing fields of the same name; of several events with a
+    /// field, the one that started last wins. Returns `false` and leaves
+    /// `out` untouched if no event matches.
+    pub fn apply(&self, entry: &impl Entry, out: &mut Vec<u8>) -> bool {
+        let Some(timestamp) = entry.realtime_timestamp() else {
+            return false;
+        };
+        let mut fields: Vec<(&[u8], &[u8])> = vec![];
+        for event in self.events_at(timestamp) {
+            for (name, value) in event.fields.iter() {
+                match fields.iter_mut().find(|(n, _)| n == name) {
+                    Some(field) => field.1 = value,
+                    None => fields.push((name, value)),
+                }
+            }
+        }
+        if fields.is_empty() {
+            return false;
+        }
+        for (name, value, typ) in entry.iter() {
+            if !fields.iter().any(|(n, _)| *n == name) {
+                write_field(out, name, value, &typ);
+            }
+        }
+        for (name, value) in fields {
+            write_field(out, name, value, &FieldType::String);
+        }
+        out.push(b'\n');
+        true
+    }
+}
+
+impl Transform for Join {
+    fn apply(&mut self, entry: EntryView<'_>) -> io::Result<TransformResult> {
+        Ok(match Join::apply(self, &entry.entry, entry.out) {
+            true => TransformResult::Replace,
+            false => TransformResult::Keep,
+        })
+    }
+}
+
+/// Builds an event from its columns.
+fn event(columns: impl Iterator<Item = (String, String)>) -> Result<Event, String> {
+    let mut start = None;
+    let mut end = None;
+    let mut fields = vec![];
+    for (name, value) in columns {
+        match name.to_ascii_lowercase().as_str() {
+            "start" | "time" => start = Some(parse_timestamp(&value)?),
+            "end" if value.is_empty() => {}
+            "end" => end = Some(parse_timestamp(&value)?),
+            _ => {
+                let name = name.trim().to_ascii_uppercase().replace([' ', '-'], "_");
+                fields.push((name.into_bytes(), value.into_bytes()));
+            }
+        }
+    }
+    let start = start.ok_or("missing start")?;
+    if end.is_some_and(|end| end < start) {
+        return Err("end before start".to_string());
+    }
+    Ok(Event { start, end, fields })
+}
+
+/// Parses microseconds since the epoch or RFC 3339.
+fn parse_timestamp(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    if let Ok(us) = s.parse() {
+        return Ok(us);
+    }
+    chrono::DateTime::parse_from_rfc3339(s)
+        .ok()
+        .and_then(|t| u64::try_from(t.timestamp_micros()).ok())
+        .ok_or_else(|| format!("invalid timestamp: {}", s))
+}
+
+/// Splits a line of CSV into its values. Values may be quoted with `"`, in
+/// which `""` stands for a quote.
+fn split_csv(line: &str) -> Result<Vec<String>, String> {
+    let mut values = vec![];
+    let mut value = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                value.push('"');
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if value.trim().is_empty() => {
+                value.clear();
+                quoted = true;
+            }
+            (',', false) => values.push(std::mem::take(&mut value).trim().to_string()),
+            (c, _) => value.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quote".to_string());
+    }
+    values.push(value.trim().to_string());
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        journald::{Entry, JournalExportRead},
+        testutil::write_string,
+    };
+
+    use super::Join;
+
+    fn annotate(join: &Join, timestamp: u64) -> Vec<(String, String)> {
+        let mut input = vec![];
+        write_string(&mut input, "__REALTIME_TIMESTAMP", timestamp.to_string());
+        write_string(&mut input, "DEPLOY_ID", "old");
+        input.push(b'\n');
+        let mut reader = JournalExportRead::new(&input[..]);
+        reader.parse_next().unwrap();
+        let mut out = vec![];
+        if !join.apply(&reader.get_entry(), &mut out) {
+            return vec![];
+        }
+        let entry = JournalExportRead::new(&out[..]).next().unwrap();
+        entry
+            .iter()
+            .skip(1)
+            .map(|(n, v, _)| {
+                let s = |b: &[u8]| String::from_utf8_lossy(b).into_owned();
+                (s(n), s(v))
+            })
+            .collect()
+    }
+
+    fn fields(fields: &[(&str, &str)]) -> Vec<(String, String)> {
+        fields
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn annotates_entries_within_events() {
+        let join = Join::parse_csv(
+            "time,deploy_id\n\
+             1970-01-01T00:00:00.000100Z,a\n\
+             \"200\",\"b, \"\"second\"\"\"\n",
+        )
+        .unwrap();
+        assert_eq!(annotate(&join, 50), []);
+        assert_eq!(annotate(&join, 150), fields(&[("DEPLOY_ID", "a")]));
+        assert_eq!(
+            annotate(&join, 1_000),
+            fields(&[("DEPLOY_ID", "b, \"second\"")])
+        );
+
+        let join = join.with_window(Duration::from_micros(50));
+        assert_eq!(annotate(&join, 180), []);
+
+        let join = Join::parse_json(
+            "{\"start\": 100, \"end\": 300, \"alert\": \"disk\"}\n\
+             {\"start\": 200, \"end\": 250, \"alert\": \"cpu\", \"severity\": 2}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            annotate(&join, 220),
+            fields(&[("DEPLOY_ID", "old"), ("ALERT", "cpu"), ("SEVERITY", "2")])
+        );
+        assert_eq!(
+            annotate(&join, 250),
+            fields(&[("DEPLOY_ID", "old"), ("ALERT", "disk")])
+        );
+        assert!(Join::parse_csv("deploy_id\n42\n").is_err());
+    }
+}
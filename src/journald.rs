@@ -11,11 +11,29 @@
 //! wasteful to create heap-allocated objects for each entry or even field
 //! upfront containing the raw representation of the field.
 //!
-//! The journal entries are read into a buffer whose size is only increased (by
-//! the initial buffer size) if a single entry is larger than the current buffer
-//! size. Currently, there is no mechanism to decrease the buffer size again;
-//! such an extensions might be of interest in networking applications that
-//! consume data from potentially untrustworthy sources.
+//! The journal entries are read into a buffer whose size is only increased if a
+//! single entry is larger than the current buffer size. The buffer grows
+//! according to [crate::config::JournalExportLimits::buf_growth] and never
+//! beyond [crate::config::JournalExportLimits::max_buf_size], which thus limits
+//! the size of entries; an entry that does not fit into a buffer of maximum
+//! size results in [ErrorKind::EntryTooLarge] or is skipped, according to
+//! [crate::config::JournalExportLimits::on_limit]. Currently, there is no
+//! mechanism to decrease the buffer size again.
+//!
+//! Binary values longer than
+//! [crate::config::JournalExportLimits::max_buffered_value_size], such as the
+//! `COREDUMP` of large processes, need not fit into the buffer: they are passed
+//! in chunks to the handler set with `with_value_handler()` and appear empty in
+//! the parsed entry.
+//!
+//! Fields may be repeated within an entry. [Entry::get] returns the first
+//! value and [Entry::get_all] all of them; with
+//! [crate::config::JournalExportLimits::duplicates], the parser drops all but
+//! the first or the last of them.
+//!
+//! Without the `std` feature, the readers are not available; the parser only
+//! needs `alloc` and is driven by passing the input into the buffers it asks
+//! for with [parser::ParseResult::Underfilled].
 //!
 //! ## Implementation notes
 //!
@@ -24,30 +42,201 @@
 //! accessed using the `get_entry()`-method which returns a [parser::RefEntry]
 //! object.
 
-use thiserror::Error;
+use alloc::{boxed::Box, vec::Vec};
+use core::fmt;
 
+#[cfg(feature = "std")]
 use crate::config::JournalExportLimits;
 
+#[cfg(feature = "std")]
 use self::parser::{JournalExportParser, ParseResult};
-pub use self::{parser::RefEntry, sync::JournalExportRead};
+pub use self::parser::{OwnedEntry, RefEntry, ValueChunk};
+#[cfg(feature = "std")]
+pub use self::sync::{JournalExportRead, JournalExportReverseRead};
+#[cfg(feature = "std")]
 use futures::{AsyncRead, AsyncReadExt};
 
-// We assume that 16KiB (half the L1 cache on modern CPUs) is enough to hold at
-// least one Journal Entry.
-const DEFAULT_BUF_SIZE: usize = 1 << 14;
-
 pub trait Entry {
     fn as_bytes(&self) -> &[u8];
     fn iter(&self) -> parser::FieldIter<'_>;
+
+    /// The size of the entry in the Journal Export Format, including the
+    /// empty line that ends it.
+    fn len_bytes(&self) -> usize {
+        self.as_bytes().len()
+    }
+
+    /// The number of fields, counting repeated ones, without walking them.
+    fn field_count(&self) -> usize {
+        self.iter().len()
+    }
+
+    /// The names of the fields in order, with repeated ones repeated.
+    fn names(&self) -> parser::Names<'_> {
+        parser::Names(self.iter())
+    }
+
+    /// Returns the value of the first field called `name`; see
+    /// [Entry::get_all] for repeated fields.
+    fn get(&self, name: &[u8]) -> Option<&[u8]> {
+        self.iter().find(|(n, _, _)| *n == name).map(|(_, v, _)| v)
+    }
+
+    /// Returns the values of all fields called `name` in order.
+    fn get_all<'n>(&self, name: &'n [u8]) -> parser::Values<'_, 'n> {
+        parser::Values {
+            fields: self.iter(),
+            name,
+        }
+    }
+
+    /// Returns the value of the first field called `name`, parsed as a
+    /// decimal integer.
+    fn get_u64(&self, name: &[u8]) -> Option<u64> {
+        core::str::from_utf8(self.get(name)?).ok()?.parse().ok()
+    }
+
+    /// Returns the value of the `__REALTIME_TIMESTAMP` field, i.e. the
+    /// microseconds since the epoch at which the entry was received.
+    fn realtime_timestamp(&self) -> Option<u64> {
+        self.get_u64(b"__REALTIME_TIMESTAMP")
+    }
+
+    /// Serializes the fields in `order` in the Journal Export Format, where
+    /// every value is written as a string field unless it contains a newline.
+    /// Entries with the same canonical form carry the same information.
+    fn canonicalize(&self, order: FieldOrder) -> Vec<u8> {
+        let mut fields: Vec<_> = self.iter().collect();
+        if order == FieldOrder::Sorted {
+            fields.sort_by_key(|(name, _, _)| *name);
+        }
+        let mut out = Vec::with_capacity(self.as_bytes().len());
+        for (name, value, _) in fields {
+            write_field(&mut out, name, value, &parser::FieldType::String);
+        }
+        out.push(b'\n');
+        out
+    }
+
+    /// The canonical form of the entry with sorted fields; this defines the
+    /// identity of entries, e.g. for [OwnedEntry]'s `Eq` and `Hash`.
+    fn canonical_bytes(&self) -> Vec<u8> {
+        self.canonicalize(FieldOrder::Sorted)
+    }
+}
+
+/// The order of fields in [Entry::canonicalize].
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum FieldOrder {
+    /// The order of the entry.
+    Preserve,
+    /// Sorted by name; fields with the same name keep their order.
+    #[default]
+    Sorted,
+}
+
+impl<E: Entry + ?Sized> Entry for &E {
+    fn as_bytes(&self) -> &[u8] {
+        (**self).as_bytes()
+    }
+
+    fn iter(&self) -> parser::FieldIter<'_> {
+        (**self).iter()
+    }
+}
+
+/// Appends the field `name` with `value` to `out` in the Journal Export
+/// Format. The field is serialized in binary form if `typ` says so or if the
+/// value cannot be represented as a string field (i.e. contains a newline).
+pub fn write_field(out: &mut Vec<u8>, name: &[u8], value: &[u8], typ: &parser::FieldType) {
+    out.extend_from_slice(name);
+    if matches!(typ, parser::FieldType::Binary) || value.contains(&b'\n') {
+        out.push(b'\n');
+        out.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        out.push(b'=');
+    }
+    out.extend_from_slice(value);
+    out.push(b'\n');
+}
+
+/// The input around the position of an error; see
+/// [parser::JournalExportParser::error_context].
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ErrorContext {
+    /// The offset in the stream of the first byte of `bytes`.
+    pub offset: u64,
+    /// The bytes around the position, as far as they were still buffered.
+    pub bytes: Vec<u8>,
+}
+
+impl ErrorContext {
+    /// Formats `bytes` like `hexdump -C`, i.e. in lines of 16 bytes prefixed
+    /// with their offset.
+    pub fn hexdump(&self) -> alloc::string::String {
+        use core::fmt::Write;
+
+        let mut out = alloc::string::String::new();
+        for (i, line) in self.bytes.chunks(16).enumerate() {
+            let _ = write!(out, "{:08x} ", self.offset + 16 * i as u64);
+            for (j, b) in line.iter().enumerate() {
+                let gap = if j == 8 { "  " } else { " " };
+                let _ = write!(out, "{}{:02x}", gap, b);
+            }
+            let padding = 3 * (16 - line.len()) + usize::from(line.len() <= 8);
+            let text: alloc::string::String = line
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            let _ = writeln!(out, "{:padding$}  |{}|", "", text, padding = padding);
+        }
+        out
+    }
 }
 
 pub mod parser {
+    use alloc::{boxed::Box, vec, vec::Vec};
+
     use crate::{
-        config::JournalExportLimits,
+        config::{DuplicatePolicy, JournalExportLimits, LimitPolicy, TRUNCATION_MARKER},
         shiftbuffer::{Pointer, ShiftBuffer},
     };
 
-    use super::{Entry, JournalExportReadError};
+    use super::{Entry, ErrorContext, ErrorKind, JournalExportReadError};
+
+    /// A part of a binary value that is streamed rather than buffered; see
+    /// [JournalExportLimits::max_buffered_value_size].
+    pub struct ValueChunk<'a> {
+        pub name: &'a [u8],
+        /// The position of `data` within the value.
+        pub offset: u64,
+        pub data: &'a [u8],
+        /// The length of the entire value.
+        pub len: u64,
+    }
+
+    impl ValueChunk<'_> {
+        pub fn is_last(&self) -> bool {
+            self.offset + self.data.len() as u64 == self.len
+        }
+    }
+
+    /// The error a [ValueHandler] may fail with; without the `std` feature,
+    /// handlers cannot fail.
+    #[cfg(feature = "std")]
+    pub type ValueHandlerError = std::io::Error;
+    #[cfg(not(feature = "std"))]
+    pub type ValueHandlerError = core::convert::Infallible;
+
+    /// Receives the chunks of streamed values.
+    pub type ValueHandler =
+        Box<dyn FnMut(ValueChunk<'_>) -> Result<(), ValueHandlerError> + Send + Sync>;
 
     pub struct JournalExportParser {
         buf: ShiftBuffer<u8>,
@@ -56,15 +245,35 @@ pub mod parser {
         cursor: Pointer,
         namelen: usize,
         remaining: u64,
+        /// The maximum value size of the current field.
+        value_limit: usize,
         parse_state: ParserState,
         buffer_state: BufferState,
         field_offsets: Vec<FieldOffset>,
         limits: JournalExportLimits,
+        value_handler: Option<ValueHandler>,
+        /// The name and length of the value being streamed.
+        streamed_name: Vec<u8>,
+        streamed_len: u64,
+        /// Whether the current entry exceeded a limit and is skipped.
+        discarding: bool,
+        /// The number of bytes to cut from the end of the truncated value.
+        dropped: u64,
+        /// The number of entries parsed since the last reset.
+        entries: usize,
+        skipped: usize,
+        truncated: usize,
     }
 
+    /// The number of bytes before and after the cursor that
+    /// [JournalExportParser::error_context] includes.
+    const ERROR_CONTEXT: usize = 32;
+
     impl JournalExportParser {
-        pub fn new(limits: JournalExportLimits, buf_size: usize) -> Self {
-            let buf = ShiftBuffer::new(buf_size);
+        pub fn new(limits: JournalExportLimits) -> Self {
+            let buf = ShiftBuffer::new(limits.initial_buf_size.min(limits.max_buf_size))
+                .with_max_size(limits.max_buf_size)
+                .with_growth(limits.buf_growth);
             let entry_start = buf.lower();
             let field_start = entry_start;
             let cursor = entry_start;
@@ -75,19 +284,135 @@ pub mod parser {
                 cursor,
                 namelen: 0,
                 remaining: 0,
-                parse_state: ParserState::FieldStart,
+                value_limit: limits.max_field_value_size,
+                parse_state: ParserState::EntryStart,
                 buffer_state: BufferState::Underfilled,
                 field_offsets: vec![],
                 limits,
+                value_handler: None,
+                streamed_name: vec![],
+                streamed_len: 0,
+                discarding: false,
+                dropped: 0,
+                entries: 0,
+                skipped: 0,
+                truncated: 0,
             }
         }
 
+        /// Passes binary values longer than
+        /// [JournalExportLimits::max_buffered_value_size] to `handler` in
+        /// chunks. Streamed values are empty in the parsed entry. Without a
+        /// handler, these values are skipped.
+        pub fn set_value_handler(&mut self, handler: ValueHandler) {
+            self.value_handler = Some(handler);
+        }
+
+        /// Resets the parser to its initial state such that it can be used
+        /// to parse a new stream. The (possibly grown) buffer is retained.
+        pub fn reset(&mut self) {
+            self.buf.reset();
+            self.entry_start = self.buf.lower();
+            self.field_start = self.entry_start;
+            self.cursor = self.entry_start;
+            self.namelen = 0;
+            self.remaining = 0;
+            self.parse_state = ParserState::EntryStart;
+            self.buffer_state = BufferState::Underfilled;
+            self.field_offsets.clear();
+            self.discarding = false;
+            self.dropped = 0;
+            self.entries = 0;
+            self.skipped = 0;
+            self.truncated = 0;
+        }
+
         pub fn extend(&mut self, n: usize) {
             self.buf.extend(n);
         }
 
+        /// Takes the input that was not parsed into an entry yet, i.e. the
+        /// start of a partial entry, and resets the parser. Parsing the
+        /// remainder followed by the rest of the stream, e.g. after a restart
+        /// of the process, yields the entries that parsing the stream would
+        /// have yielded. Values of the partial entry that were streamed to
+        /// the value handler already are empty in the remainder.
+        ///
+        /// Returns `None` while a value is being streamed or truncated, within
+        /// a skipped entry or after an error, when the stream cannot be
+        /// resumed.
+        pub fn take_remainder(&mut self) -> Option<Vec<u8>> {
+            let start = match self.parse_state {
+                _ if self.discarding => return None,
+                ParserState::StreamedValue
+                | ParserState::DroppedValue
+                | ParserState::TruncatedString
+                | ParserState::Eof => return None,
+                ParserState::EntryStart => self.cursor,
+                _ => self.entry_start,
+            };
+            let remainder = self.buf[start..self.buf.upper()].to_vec();
+            self.reset();
+            Some(remainder)
+        }
+
+        /// Forgets that [ParseResult::Underfilled] handed out the free part of
+        /// the buffer, for when reading into it was cancelled such that
+        /// [Self::parse] asks for it again rather than taking the missing
+        /// input for the end of the stream.
+        pub fn unfill(&mut self) {
+            self.buffer_state = BufferState::Underfilled;
+        }
+
+        /// The entire buffer that [ParseResult::Underfilled] hands out parts
+        /// of, e.g. to register it with the kernel. It moves when it grows.
+        pub fn buffer_mut(&mut self) -> &mut [u8] {
+            self.buf.allocation_mut()
+        }
+
+        /// The total number of bytes that were read into the buffer since the
+        /// parser was created or reset.
+        pub fn bytes_read(&self) -> usize {
+            self.buf.upper().abs()
+        }
+
+        /// The position in the stream up to which the input was consumed.
+        /// After an entry was parsed successfully, this is the position right
+        /// after that entry.
+        pub fn position(&self) -> usize {
+            self.cursor.abs()
+        }
+
+        /// The number of entries parsed since the parser was created or reset.
+        pub fn entries(&self) -> usize {
+            self.entries
+        }
+
+        /// The number of entries skipped because of [LimitPolicy::Skip] or
+        /// [LimitPolicy::Truncate] since the parser was created or reset.
+        pub fn skipped_entries(&self) -> usize {
+            self.skipped
+        }
+
+        /// The number of values truncated because of [LimitPolicy::Truncate]
+        /// since the parser was created or reset.
+        pub fn truncated_values(&self) -> usize {
+            self.truncated
+        }
+
+        /// The input around the current position; after an error, this is
+        /// where it was detected.
+        pub fn error_context(&self) -> ErrorContext {
+            let start = self.cursor - ERROR_CONTEXT.min(self.cursor - self.buf.lower());
+            let end = self.cursor + ERROR_CONTEXT.min(self.buf.upper() - self.cursor);
+            ErrorContext {
+                offset: start.abs() as u64,
+                bytes: self.buf[start..end].to_vec(),
+            }
+        }
+
         #[inline]
-        pub fn parse(&mut self) -> ParseResult<()> {
+        pub fn parse(&mut self) -> ParseResult<'_, ()> {
             loop {
                 // If the cursor reached the upper end of the window, ask for
                 // more byte from the user.
@@ -96,10 +421,33 @@ pub mod parser {
                         if self.parse_state == ParserState::EntryStart {
                             return ParseResult::Eof;
                         }
-                        return ParseResult::Err(JournalExportReadError::UnexpectedEof);
+                        return ParseResult::Err(self.locate(ErrorKind::UnexpectedEof.into()));
                     }
                     self.buffer_state = BufferState::Filled;
-                    return ParseResult::Underfilled(self.buf.make_room());
+                    // Release everything prior to the entry that is currently
+                    // being parsed, such that the buffer only grows if a
+                    // single entry does not fit.
+                    // Of an entry that is skipped, only the current field is
+                    // kept.
+                    let keep = if self.parse_state == ParserState::EntryStart {
+                        self.cursor
+                    } else if self.discarding {
+                        self.field_start
+                    } else {
+                        self.entry_start
+                    };
+                    self.buf.shrink(keep - self.buf.lower());
+                    if self.buf.make_room().is_err() {
+                        match self.skip_field() {
+                            Some(state) => {
+                                self.parse_state = state;
+                                self.buffer_state = BufferState::Underfilled;
+                                continue;
+                            }
+                            None => return self.eof_and_return(ErrorKind::EntryTooLarge),
+                        }
+                    }
+                    return ParseResult::Underfilled(self.buf.free());
                 }
                 debug_assert!(self.cursor < self.buf.upper());
                 self.buffer_state = BufferState::Underfilled;
@@ -114,20 +462,25 @@ pub mod parser {
                             self.cursor += 1;
                             ParserState::Fieldname
                         } else {
-                            return self
-                                .eof_and_return(JournalExportReadError::UnexpectedCharacter(c));
+                            return self.eof_and_return(ErrorKind::UnexpectedCharacter(c));
                         }
                     }
                     FieldStart => match c {
                         b'\n' => {
-                            if !self.field_offsets.is_empty() {
+                            if self.discarding {
+                                self.cursor += 1;
+                                self.discarding = false;
+                                self.field_offsets.clear();
+                                self.skipped += 1;
+                                ParserState::EntryStart
+                            } else if !self.field_offsets.is_empty() {
                                 self.cursor += 1;
+                                self.drop_duplicates();
                                 self.parse_state = ParserState::EntryStart;
+                                self.entries += 1;
                                 return ParseResult::Ok(());
                             } else {
-                                return self.eof_and_return(
-                                    JournalExportReadError::UnexpectedCharacter(c),
-                                );
+                                return self.eof_and_return(ErrorKind::UnexpectedCharacter(c));
                             }
                         }
                         c if (c.is_ascii_alphanumeric() || c == b'_') => {
@@ -136,27 +489,37 @@ pub mod parser {
                             ParserState::Fieldname
                         }
                         c => {
-                            return self
-                                .eof_and_return(JournalExportReadError::UnexpectedCharacter(c));
+                            return self.eof_and_return(ErrorKind::UnexpectedCharacter(c));
                         }
                     },
                     Fieldname => {
                         self.namelen = self.cursor - self.field_start;
                         if self.namelen > self.limits.max_field_name_len {
-                            return self.eof_and_return(JournalExportReadError::FieldNameTooLong);
+                            match self.skip_field() {
+                                Some(state) => {
+                                    self.parse_state = state;
+                                    continue;
+                                }
+                                None => return self.eof_and_return(ErrorKind::FieldNameTooLong),
+                            }
                         }
                         self.cursor += 1;
                         match c {
                             c_ if c_.is_ascii_alphanumeric() || c_ == b'_' => {
                                 ParserState::Fieldname
                             }
-                            b'=' => ParserState::StringField,
-                            b'\n' => ParserState::BinaryValueLen,
+                            b'=' | b'\n' => {
+                                let name = &self.buf[self.field_start..self.cursor - 1];
+                                self.value_limit = self.limits.max_field_value_size_of(name);
+                                if c == b'=' {
+                                    ParserState::StringField
+                                } else {
+                                    ParserState::BinaryValueLen
+                                }
+                            }
                             _ => {
                                 self.cursor -= 1;
-                                return self.eof_and_return(
-                                    JournalExportReadError::UnexpectedCharacter(c),
-                                );
+                                return self.eof_and_return(ErrorKind::UnexpectedCharacter(c));
                             }
                         }
                     }
@@ -172,24 +535,89 @@ pub mod parser {
                             let len_start = len_stop - 8;
                             le_bytes.copy_from_slice(&self.buf[len_start..len_stop]);
                             self.remaining = u64::from_le_bytes(le_bytes);
-                            if self.remaining > self.limits.max_field_value_size as u64 {
-                                return self
-                                    .eof_and_return(JournalExportReadError::FieldValueTooLong);
+                            let streamed = self
+                                .limits
+                                .max_buffered_value_size
+                                .is_some_and(|max| self.remaining > max as u64);
+                            if self.discarding {
+                                ParserState::SkippedValue
+                            } else if streamed {
+                                let name_stop = self.field_start + self.namelen;
+                                self.streamed_name.clear();
+                                self.streamed_name
+                                    .extend_from_slice(&self.buf[self.field_start..name_stop]);
+                                self.streamed_len = self.remaining;
+                                // The value is cut out of the entry.
+                                for i in 0..8 {
+                                    self.buf[len_start + i] = 0;
+                                }
+                                ParserState::StreamedValue
+                            } else if self.remaining > self.value_limit as u64 {
+                                match self.limits.on_limit {
+                                    LimitPolicy::Error => {
+                                        return self.eof_and_return(ErrorKind::FieldValueTooLong)
+                                    }
+                                    LimitPolicy::Skip => {
+                                        self.discarding = true;
+                                        ParserState::SkippedValue
+                                    }
+                                    LimitPolicy::Truncate => {
+                                        self.dropped = self.remaining - self.value_limit as u64;
+                                        self.remaining = self.value_limit as u64;
+                                        let len = self.remaining.to_le_bytes();
+                                        for (i, b) in len.into_iter().enumerate() {
+                                            self.buf[len_start + i] = b;
+                                        }
+                                        ParserState::BinaryValue
+                                    }
+                                }
+                            } else {
+                                ParserState::BinaryValue
                             }
-                            ParserState::BinaryValue
                         }
                     }
+                    StreamedValue if self.remaining == 0 => {
+                        if c != b'\n' {
+                            return self.eof_and_return(ErrorKind::UnexpectedCharacter(c));
+                        }
+                        self.cursor += 1;
+                        self.field_offsets.push(FieldOffset {
+                            start: self.field_start,
+                            namelen: self.namelen,
+                            typ: FieldType::Binary,
+                        });
+                        ParserState::FieldStart
+                    }
+                    StreamedValue => {
+                        let n = (self.buf.upper() - self.cursor).min(self.remaining as usize);
+                        let stop = self.cursor + n;
+                        if let Some(handler) = self.value_handler.as_mut() {
+                            let chunk = ValueChunk {
+                                name: &self.streamed_name,
+                                offset: self.streamed_len - self.remaining,
+                                data: &self.buf[self.cursor..stop],
+                                len: self.streamed_len,
+                            };
+                            if let Err(e) = handler(chunk) {
+                                return self.eof_and_return(e);
+                            }
+                        }
+                        self.remaining -= n as u64;
+                        self.cursor = stop;
+                        self.cut_streamed(n);
+                        ParserState::StreamedValue
+                    }
                     BinaryValue => {
                         let stop_pos =
                             self.field_start + self.namelen + 9 + self.remaining as usize;
                         if self.cursor < stop_pos {
                             self.cursor = self.buf.upper().min(stop_pos);
                             ParserState::BinaryValue
+                        } else if self.dropped > 0 {
+                            ParserState::DroppedValue
                         } else {
                             if c != b'\n' {
-                                return self.eof_and_return(
-                                    JournalExportReadError::UnexpectedCharacter(c),
-                                );
+                                return self.eof_and_return(ErrorKind::UnexpectedCharacter(c));
                             }
                             self.cursor += 1;
                             self.field_offsets.push(FieldOffset {
@@ -210,14 +638,105 @@ pub mod parser {
                             });
                             ParserState::FieldStart
                         } else {
-                            if self.cursor - self.field_start - self.namelen - 1
-                                > self.limits.max_field_value_size
+                            if self.cursor - self.field_start - self.namelen - 1 > self.value_limit
                             {
                                 self.cursor -= 1;
-                                return self
-                                    .eof_and_return(JournalExportReadError::FieldValueTooLong);
+                                match self.limits.on_limit {
+                                    LimitPolicy::Error => {
+                                        return self.eof_and_return(ErrorKind::FieldValueTooLong)
+                                    }
+                                    LimitPolicy::Skip => {
+                                        self.discarding = true;
+                                        ParserState::SkippedString
+                                    }
+                                    LimitPolicy::Truncate => ParserState::TruncatedString,
+                                }
+                            } else {
+                                ParserState::StringField
                             }
+                        }
+                    }
+                    DroppedValue if self.dropped == 0 => {
+                        self.mark_truncated();
+                        ParserState::BinaryValue
+                    }
+                    DroppedValue => {
+                        let n = (self.buf.upper() - self.cursor).min(self.dropped as usize);
+                        self.cursor += n;
+                        self.dropped -= n as u64;
+                        self.cut_streamed(n);
+                        ParserState::DroppedValue
+                    }
+                    TruncatedString => {
+                        let rest = &self.buf[self.cursor..self.buf.upper()];
+                        let newline = rest.iter().position(|&b| b == b'\n');
+                        let n = newline.unwrap_or(rest.len());
+                        self.cursor += n;
+                        self.cut_streamed(n);
+                        if newline.is_some() {
+                            self.mark_truncated();
                             ParserState::StringField
+                        } else {
+                            ParserState::TruncatedString
+                        }
+                    }
+                    SkippedName => {
+                        self.cursor += 1;
+                        let state = match c {
+                            c_ if c_.is_ascii_alphanumeric() || c_ == b'_' => {
+                                ParserState::SkippedName
+                            }
+                            b'=' => ParserState::SkippedString,
+                            b'\n' => ParserState::SkippedValueLen,
+                            _ => {
+                                self.cursor -= 1;
+                                return self.eof_and_return(ErrorKind::UnexpectedCharacter(c));
+                            }
+                        };
+                        self.field_start = self.cursor;
+                        state
+                    }
+                    SkippedValueLen => {
+                        let len_stop = self.field_start + 8;
+                        self.cursor = self.buf.upper().min(len_stop);
+                        if self.cursor < len_stop || self.cursor == self.buf.upper() {
+                            ParserState::SkippedValueLen
+                        } else {
+                            let mut le_bytes = [0u8; 8];
+                            le_bytes.copy_from_slice(&self.buf[self.field_start..len_stop]);
+                            self.remaining = u64::from_le_bytes(le_bytes);
+                            self.field_start = self.cursor;
+                            ParserState::SkippedValue
+                        }
+                    }
+                    SkippedValue if self.remaining == 0 => {
+                        if c != b'\n' {
+                            return self.eof_and_return(ErrorKind::UnexpectedCharacter(c));
+                        }
+                        self.cursor += 1;
+                        self.field_start = self.cursor;
+                        ParserState::FieldStart
+                    }
+                    SkippedValue => {
+                        let n = (self.buf.upper() - self.cursor).min(self.remaining as usize);
+                        self.cursor += n;
+                        self.remaining -= n as u64;
+                        self.field_start = self.cursor;
+                        ParserState::SkippedValue
+                    }
+                    SkippedString => {
+                        let rest = &self.buf[self.cursor..self.buf.upper()];
+                        match rest.iter().position(|&b| b == b'\n') {
+                            Some(i) => {
+                                self.cursor += i + 1;
+                                self.field_start = self.cursor;
+                                ParserState::FieldStart
+                            }
+                            None => {
+                                self.cursor = self.buf.upper();
+                                self.field_start = self.cursor;
+                                ParserState::SkippedString
+                            }
                         }
                     }
                     Eof => return ParseResult::Eof,
@@ -235,10 +754,142 @@ pub mod parser {
             self.field_offsets.clear();
         }
 
+        /// Removes the `n` bytes of a streamed value before the cursor from
+        /// the window by moving the preceding part of the entry up, such that
+        /// the buffer does not have to hold the value.
+        fn cut_streamed(&mut self, n: usize) {
+            let value_start = self.cursor - n;
+            self.buf
+                .copy_within(self.entry_start..value_start, self.entry_start + n);
+            self.entry_start += n;
+            self.field_start += n;
+            for f in self.field_offsets.iter_mut() {
+                f.start += n;
+            }
+        }
+
+        /// Unless the policy is [LimitPolicy::Error], marks the current entry
+        /// as skipped and returns the state that skips the rest of the
+        /// current field.
+        fn skip_field(&mut self) -> Option<ParserState> {
+            if self.limits.on_limit == LimitPolicy::Error {
+                return None;
+            }
+            let state = match self.parse_state {
+                ParserState::FieldStart => {
+                    self.field_start = self.cursor;
+                    ParserState::FieldStart
+                }
+                ParserState::Fieldname => ParserState::SkippedName,
+                ParserState::BinaryValueLen => {
+                    self.field_start += self.namelen + 1;
+                    ParserState::SkippedValueLen
+                }
+                ParserState::BinaryValue => {
+                    let stop_pos = self.field_start + self.namelen + 9 + self.remaining as usize;
+                    self.remaining = (stop_pos - self.cursor) as u64;
+                    ParserState::SkippedValue
+                }
+                ParserState::DroppedValue => {
+                    self.remaining = core::mem::take(&mut self.dropped);
+                    ParserState::SkippedValue
+                }
+                ParserState::StreamedValue => ParserState::SkippedValue,
+                ParserState::StringField | ParserState::TruncatedString => {
+                    ParserState::SkippedString
+                }
+                _ => return None,
+            };
+            self.discarding = true;
+            Some(state)
+        }
+
+        /// Removes the fields of the complete entry that
+        /// [JournalExportLimits::duplicates] does not keep by moving the kept
+        /// ones up to the end of the entry, like [Self::cut_streamed].
+        fn drop_duplicates(&mut self) {
+            let policy = self.limits.duplicates;
+            if policy == DuplicatePolicy::KeepAll {
+                return;
+            }
+            let same = |buf: &ShiftBuffer<u8>, a: &FieldOffset, b: &FieldOffset| {
+                a.namelen == b.namelen
+                    && buf[a.start..a.start + a.namelen] == buf[b.start..b.start + b.namelen]
+            };
+            let (buf, offsets) = (&self.buf, &self.field_offsets);
+            let n = offsets.len();
+            if !(1..n).any(|i| offsets[..i].iter().any(|f| same(buf, f, &offsets[i]))) {
+                return;
+            }
+            let mut kept = vec![true; n];
+            // The empty line that ends the entry stays in place.
+            let mut end = self.cursor - 1;
+            let mut dest = end;
+            for i in (0..n).rev() {
+                let f = self.field_offsets[i].clone();
+                let offsets = &self.field_offsets;
+                kept[i] = match policy {
+                    DuplicatePolicy::KeepFirst => {
+                        !offsets[..i].iter().any(|g| same(&self.buf, g, &f))
+                    }
+                    // Dropped later fields may be overwritten already, but
+                    // each of them repeats a kept one.
+                    _ => !(i + 1..n).any(|j| kept[j] && same(&self.buf, &offsets[j], &f)),
+                };
+                if kept[i] {
+                    dest -= end - f.start;
+                    self.buf.copy_within(f.start..end, dest);
+                    self.field_offsets[i].start = dest;
+                }
+                end = f.start;
+            }
+            let mut i = 0;
+            self.field_offsets.retain(|_| {
+                i += 1;
+                kept[i - 1]
+            });
+            self.entry_start = self.field_offsets[0].start;
+        }
+
+        /// Ends the truncated value before the cursor with the marker.
+        fn mark_truncated(&mut self) {
+            let m = TRUNCATION_MARKER.len();
+            if self.value_limit >= m {
+                for (i, b) in TRUNCATION_MARKER.iter().enumerate() {
+                    self.buf[self.cursor - m + i] = *b;
+                }
+            }
+            self.truncated += 1;
+        }
+
         #[inline]
-        fn eof_and_return<T>(&mut self, r: JournalExportReadError) -> ParseResult<T> {
+        fn eof_and_return<T>(
+            &mut self,
+            e: impl Into<JournalExportReadError>,
+        ) -> ParseResult<'_, T> {
+            let e = self.locate(e.into());
             self.parse_state = ParserState::Eof;
-            ParseResult::Err(r)
+            ParseResult::Err(e)
+        }
+
+        /// Adds the current position and, within a value, the name of its
+        /// field to `e`.
+        pub(crate) fn locate(&self, e: JournalExportReadError) -> JournalExportReadError {
+            let e = e.with_position(self.cursor.abs() as u64, self.entries);
+            match self.parse_state {
+                ParserState::BinaryValueLen
+                | ParserState::BinaryValue
+                | ParserState::DroppedValue
+                | ParserState::StringField
+                | ParserState::TruncatedString => {
+                    e.with_field_name(&self.buf[self.field_start..self.field_start + self.namelen])
+                }
+                ParserState::StreamedValue => {
+                    let name = self.streamed_name.clone();
+                    e.with_field_name(&name)
+                }
+                _ => e,
+            }
         }
     }
 
@@ -256,7 +907,16 @@ pub mod parser {
         Fieldname,
         BinaryValueLen,
         BinaryValue,
+        StreamedValue,
         StringField,
+        /// The part of a truncated value after the limit.
+        DroppedValue,
+        TruncatedString,
+        /// The rest of a field of a skipped entry.
+        SkippedName,
+        SkippedValueLen,
+        SkippedValue,
+        SkippedString,
         Eof,
     }
 
@@ -273,11 +933,32 @@ pub mod parser {
     impl<'a> RefEntry<'a> {
         pub fn to_owned(&self) -> OwnedEntry {
             OwnedEntry {
-                cursor: self.reader.cursor,
-                buf: self.reader.buf.clone_window(),
+                start: self.reader.field_offsets[0].start,
+                buf: self.as_bytes().to_vec(),
                 offsets: self.reader.field_offsets.to_vec(),
             }
         }
+
+        /// Copies the entry into `target`, reusing its allocations.
+        pub fn clone_into(&self, target: &mut OwnedEntry) {
+            target.start = self.reader.field_offsets[0].start;
+            target.buf.clear();
+            target.buf.extend_from_slice(self.as_bytes());
+            target.offsets.clear();
+            target.offsets.extend_from_slice(&self.reader.field_offsets);
+        }
+
+        /// Like [Entry::iter], but the fields borrow from the parser rather
+        /// than from `self`.
+        pub fn fields(&self) -> FieldIter<'a> {
+            let start = self.reader.field_offsets[0].start;
+            FieldIter {
+                index: 0,
+                start,
+                buf: &self.reader.buf[start..self.reader.cursor],
+                offsets: &self.reader.field_offsets,
+            }
+        }
     }
 
     impl<'a> Entry for RefEntry<'a> {
@@ -287,32 +968,53 @@ pub mod parser {
         }
 
         fn iter(&self) -> FieldIter<'_> {
-            FieldIter {
-                index: 0,
-                buf: &self.reader.buf,
-                cursor: self.reader.cursor,
-                offsets: &self.reader.field_offsets,
-            }
+            self.fields()
         }
     }
 
+    /// An entry that owns its data; it is `Send` and `Sync`. The default
+    /// entry has no fields.
+    #[derive(Clone, Default)]
     pub struct OwnedEntry {
-        cursor: Pointer,
-        buf: ShiftBuffer<u8>,
+        /// The position of the entry in its stream, to which the field
+        /// offsets refer.
+        start: Pointer,
+        buf: Vec<u8>,
         offsets: Vec<FieldOffset>,
     }
 
+    impl OwnedEntry {
+        /// The memory allocated by the entry, in bytes.
+        pub fn capacity(&self) -> usize {
+            self.buf.capacity() + self.offsets.capacity() * core::mem::size_of::<FieldOffset>()
+        }
+    }
+
+    /// Entries are equal if their [Entry::canonical_bytes] are.
+    impl PartialEq for OwnedEntry {
+        fn eq(&self, other: &Self) -> bool {
+            self.canonical_bytes() == other.canonical_bytes()
+        }
+    }
+
+    impl Eq for OwnedEntry {}
+
+    impl core::hash::Hash for OwnedEntry {
+        fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+            self.canonical_bytes().hash(state);
+        }
+    }
+
     impl Entry for OwnedEntry {
         fn as_bytes(&self) -> &[u8] {
-            let start = self.offsets[0].start;
-            &self.buf[start..self.cursor]
+            &self.buf
         }
 
         fn iter(&self) -> FieldIter<'_> {
             FieldIter {
                 index: 0,
+                start: self.start,
                 buf: &self.buf,
-                cursor: self.cursor,
                 offsets: &self.offsets,
             }
         }
@@ -320,8 +1022,10 @@ pub mod parser {
 
     pub struct FieldIter<'a> {
         index: usize,
-        cursor: Pointer,
-        buf: &'a ShiftBuffer<u8>,
+        /// The position of `buf` in the stream.
+        start: Pointer,
+        /// The entry, including the empty line that terminates it.
+        buf: &'a [u8],
         offsets: &'a [FieldOffset],
     }
 
@@ -329,15 +1033,60 @@ pub mod parser {
         type Item = (&'a [u8], &'a [u8], FieldType);
 
         fn next(&mut self) -> Option<Self::Item> {
-            let res = next(self.buf, self.cursor, self.offsets, self.index);
+            let res = next(self.buf, self.start, self.offsets, self.index);
             self.index += 1;
             res
         }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            let n = self.offsets.len().saturating_sub(self.index);
+            (n, Some(n))
+        }
+    }
+
+    impl ExactSizeIterator for FieldIter<'_> {}
+
+    /// The names of the fields of an entry; see [Entry::names].
+    pub struct Names<'a>(pub(super) FieldIter<'a>);
+
+    impl<'a> Iterator for Names<'a> {
+        type Item = &'a [u8];
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let it = &mut self.0;
+            let f = it.offsets.get(it.index)?;
+            it.index += 1;
+            let name_start = f.start - it.start;
+            Some(&it.buf[name_start..name_start + f.namelen])
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            self.0.size_hint()
+        }
+    }
+
+    impl ExactSizeIterator for Names<'_> {}
+
+    /// The values of the fields with the same name; see [Entry::get_all].
+    pub struct Values<'a, 'n> {
+        pub(super) fields: FieldIter<'a>,
+        pub(super) name: &'n [u8],
+    }
+
+    impl<'a> Iterator for Values<'a, '_> {
+        type Item = &'a [u8];
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let name = self.name;
+            self.fields
+                .find(|(n, _, _)| *n == name)
+                .map(|(_, value, _)| value)
+        }
     }
 
     fn next<'a>(
-        buf: &'a ShiftBuffer<u8>,
-        stop: Pointer,
+        buf: &'a [u8],
+        start: Pointer,
         offsets: &'a [FieldOffset],
         index: usize,
     ) -> Option<(&'a [u8], &'a [u8], FieldType)> {
@@ -345,24 +1094,24 @@ pub mod parser {
             return None;
         }
         let field_stop = if index == offsets.len() - 1 {
-            // The cursor points to the first byte of the next entry. Thus,
-            // cursor-2 points to the first NL after the last field of this
-            // entry.
-            stop - 2
+            // The entry ends with two NL characters, the first of which
+            // terminates the last field.
+            buf.len() - 2
         } else {
             // The fields are separated by one NL character, therefore
             // .start-1 of the next field points to the NL character that
             // terminates this field.
-            offsets[index + 1].start - 1
+            offsets[index + 1].start - 1 - start
         };
         let res = offsets.get(index).map(|f| {
             let bin_offset = match &f.typ {
                 FieldType::Binary => 9,
                 FieldType::String => 1,
             };
+            let name_start = f.start - start;
             (
-                &buf[f.start..(f.start + f.namelen)],
-                &buf[(f.start + f.namelen + bin_offset)..field_stop],
+                &buf[name_start..(name_start + f.namelen)],
+                &buf[(name_start + f.namelen + bin_offset)..field_stop],
                 f.typ.clone(),
             )
         });
@@ -383,14 +1132,18 @@ pub mod parser {
     }
 }
 
+#[cfg(feature = "std")]
 pub mod sync {
-    use crate::config::JournalExportLimits;
+    use crate::{
+        config::{JournalExportLimits, JournalExportLimitsBuilder},
+        trace,
+    };
 
     use super::{
-        parser::{JournalExportParser, OwnedEntry, ParseResult, RefEntry},
-        JournalExportReadError, DEFAULT_BUF_SIZE,
+        parser::{JournalExportParser, OwnedEntry, ParseResult, RefEntry, ValueChunk},
+        ErrorContext, ErrorKind, JournalExportReadError,
     };
-    use std::io::Read;
+    use std::io::{Read, Seek, SeekFrom};
 
     pub struct JournalExportRead<R> {
         buf_read: R,
@@ -405,23 +1158,59 @@ pub mod sync {
         pub fn new_with_limits(limits: JournalExportLimits, buf_read: R) -> Self {
             Self {
                 buf_read,
-                parse_state: JournalExportParser::new(limits, DEFAULT_BUF_SIZE),
+                parse_state: JournalExportParser::new(limits),
             }
         }
 
+        /// Creates a reader whose buffer initially holds `capacity` bytes. A
+        /// larger capacity allows filling the buffer with fewer calls to
+        /// [Read::read]. The capacity is capped by
+        /// [JournalExportLimits::max_buf_size].
+        pub fn with_capacity(capacity: usize, buf_read: R) -> Self {
+            let limits = JournalExportLimitsBuilder::new()
+                .with_initial_buf_size(capacity)
+                .build();
+            Self::new_with_limits(limits, buf_read)
+        }
+
+        /// See [JournalExportParser::set_value_handler].
+        pub fn with_value_handler(
+            mut self,
+            handler: impl FnMut(ValueChunk<'_>) -> std::io::Result<()> + Send + Sync + 'static,
+        ) -> Self {
+            self.parse_state.set_value_handler(Box::new(handler));
+            self
+        }
+
         pub fn parse_next(&mut self) -> Result<Option<()>, JournalExportReadError> {
+            let _span = trace::trace_span!("parse_next");
             self.parse_state.clear_entry();
             loop {
                 match self.parse_state.parse() {
-                    ParseResult::Ok(()) => return Ok(Some(())),
-                    ParseResult::Eof => return Ok(None),
+                    ParseResult::Ok(()) => {
+                        trace::trace!(
+                            entries = self.parse_state.entries(),
+                            bytes = self.parse_state.bytes_read(),
+                            "entry"
+                        );
+                        return Ok(Some(()));
+                    }
+                    ParseResult::Eof => {
+                        trace::debug!(
+                            entries = self.parse_state.entries(),
+                            bytes = self.parse_state.bytes_read(),
+                            "end of stream"
+                        );
+                        return Ok(None);
+                    }
                     ParseResult::Err(e) => {
+                        trace::debug!(error = %e, "parse error");
                         return Err::<_, JournalExportReadError>(e);
                     }
-                    ParseResult::Underfilled(b) => {
-                        let n = self.buf_read.read(b)?;
-                        self.parse_state.extend(n);
-                    }
+                    ParseResult::Underfilled(b) => match self.buf_read.read(b) {
+                        Ok(n) => self.parse_state.extend(n),
+                        Err(e) => return Err(self.parse_state.locate(e.into())),
+                    },
                 }
             }
         }
@@ -429,6 +1218,56 @@ pub mod sync {
         pub fn get_entry(&self) -> RefEntry<'_> {
             self.parse_state.get_entry()
         }
+
+        /// The total number of bytes that were read from the underlying
+        /// reader.
+        pub fn bytes_read(&self) -> usize {
+            self.parse_state.bytes_read()
+        }
+
+        /// See [JournalExportParser::position].
+        pub fn position(&self) -> usize {
+            self.parse_state.position()
+        }
+
+        /// See [JournalExportParser::error_context].
+        pub fn error_context(&self) -> ErrorContext {
+            self.parse_state.error_context()
+        }
+
+        /// See [JournalExportParser::skipped_entries].
+        pub fn skipped_entries(&self) -> usize {
+            self.parse_state.skipped_entries()
+        }
+
+        /// See [JournalExportParser::truncated_values].
+        pub fn truncated_values(&self) -> usize {
+            self.parse_state.truncated_values()
+        }
+
+        /// See [JournalExportParser::take_remainder].
+        pub fn take_remainder(&mut self) -> Option<Vec<u8>> {
+            self.parse_state.take_remainder()
+        }
+
+        /// Replaces the underlying reader and resets the parser, retaining its
+        /// buffer. Returns the previous reader.
+        pub fn replace_reader(&mut self, buf_read: R) -> R {
+            self.parse_state.reset();
+            std::mem::replace(&mut self.buf_read, buf_read)
+        }
+
+        pub fn into_inner(self) -> R {
+            self.buf_read
+        }
+    }
+
+    impl<R: Read + Seek> JournalExportRead<R> {
+        /// Reads the entries of the underlying stream from its end to its
+        /// start; see [JournalExportReverseRead].
+        pub fn read_backwards(self) -> std::io::Result<JournalExportReverseRead<R>> {
+            JournalExportReverseRead::new(self.buf_read)
+        }
     }
 
     impl<R: Read> Iterator for JournalExportRead<R> {
@@ -439,63 +1278,886 @@ pub mod sync {
             Some(self.get_entry().to_owned())
         }
     }
+
+    /// The size of the first block read by [JournalExportReverseRead].
+    const REVERSE_BLOCK_SIZE: u64 = 64 * 1024;
+    /// Blocks do not grow beyond this; entries are smaller according to
+    /// the default [JournalExportLimits::max_buf_size].
+    const MAX_REVERSE_BLOCK_SIZE: u64 = 64 << 20;
+
+    /// Reads the entries of a seekable stream newest first, i.e. from its end
+    /// to its start.
+    ///
+    /// The stream is read backwards in blocks, which grow until they hold at
+    /// least one complete entry. Entries end with an empty line, but binary
+    /// values may contain `\n\n` as well; the entries of a block therefore
+    /// start at the first offset following `\n\n` from which the rest of the
+    /// block parses as entries. Such an offset may still be within a binary
+    /// value whose entry starts before the block, hence the entries of a
+    /// block are only taken once the preceding block is found to end right
+    /// where they start; otherwise, the next offset is tried.
+    pub struct JournalExportReverseRead<R> {
+        inner: R,
+        /// The start of the entries that were read so far.
+        end: u64,
+        /// The block that ends at `end` and the offset of its first entry,
+        /// found when the entries read last were checked.
+        next: Option<(u64, Vec<u8>, usize)>,
+        /// The entries of the current block that were not yielded yet.
+        entries: Vec<OwnedEntry>,
+        current: Option<OwnedEntry>,
+    }
+
+    impl<R: Read + Seek> JournalExportReverseRead<R> {
+        pub fn new(mut inner: R) -> std::io::Result<Self> {
+            let end = inner.seek(SeekFrom::End(0))?;
+            Ok(Self {
+                inner,
+                end,
+                next: None,
+                entries: vec![],
+                current: None,
+            })
+        }
+
+        pub fn parse_next(&mut self) -> Result<Option<()>, JournalExportReadError> {
+            let mut block = REVERSE_BLOCK_SIZE;
+            while self.entries.is_empty() {
+                if self.end == 0 {
+                    self.current = None;
+                    return Ok(None);
+                }
+                let (start, buf, offset) = match self.next.take() {
+                    Some((start, buf, offset)) => (start, buf, Some(offset)),
+                    None => self.locate(self.end, block)?,
+                };
+                let Some(mut offset) = offset else {
+                    return Err(tail_error(&buf));
+                };
+                let checked = loop {
+                    let cut = start + offset as u64;
+                    if cut == 0 {
+                        break true;
+                    }
+                    if let (start, buf, Some(offset)) = self.locate(cut, REVERSE_BLOCK_SIZE)? {
+                        self.next = Some((start, buf, offset));
+                        break true;
+                    }
+                    match first_entry_from(&buf, false, offset + 1) {
+                        Some((o, _)) => offset = o,
+                        None => break false,
+                    }
+                };
+                if checked {
+                    self.entries = JournalExportRead::new(&buf[offset..]).collect();
+                    self.end = start + offset as u64;
+                } else if start == 0 || buf.len() as u64 >= MAX_REVERSE_BLOCK_SIZE {
+                    return Err(tail_error(&buf));
+                } else {
+                    // The entries start before the block.
+                    block = buf.len() as u64 * 4;
+                }
+            }
+            self.current = self.entries.pop();
+            Ok(Some(()))
+        }
+
+        /// Reads blocks of growing size, starting with `block` bytes, that end
+        /// at `end` until the entries of one end there. Returns the start of
+        /// the last block read, its bytes and the offset of its first entry,
+        /// if any.
+        fn locate(
+            &mut self,
+            end: u64,
+            mut block: u64,
+        ) -> std::io::Result<(u64, Vec<u8>, Option<usize>)> {
+            loop {
+                let start = end.saturating_sub(block);
+                self.inner.seek(SeekFrom::Start(start))?;
+                let mut buf = Vec::with_capacity((end - start) as usize);
+                self.inner
+                    .by_ref()
+                    .take(end - start)
+                    .read_to_end(&mut buf)?;
+                let offset = first_entry(&buf, start == 0).map(|(offset, _)| offset);
+                if offset.is_some() || start == 0 || block >= MAX_REVERSE_BLOCK_SIZE {
+                    return Ok((start, buf, offset));
+                }
+                block *= 4;
+            }
+        }
+
+        /// The entry that was parsed last.
+        ///
+        /// # Panics
+        ///
+        /// Panics if the last call to [Self::parse_next] did not yield an
+        /// entry.
+        pub fn get_entry(&self) -> &OwnedEntry {
+            self.current.as_ref().expect("no current entry")
+        }
+
+        pub fn into_inner(self) -> R {
+            self.inner
+        }
+    }
+
+    impl<R: Read + Seek> Iterator for JournalExportReverseRead<R> {
+        type Item = OwnedEntry;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.parse_next().ok()??;
+            self.current.take()
+        }
+    }
+
+    /// Reports why the end of `buf` does not parse.
+    fn tail_error(buf: &[u8]) -> JournalExportReadError {
+        let last = buf.windows(2).rposition(|w| w == b"\n\n");
+        let from = last.map_or(0, |i| i + 2);
+        let mut reader = JournalExportRead::new(&buf[from..]);
+        loop {
+            match reader.parse_next() {
+                Ok(Some(())) => continue,
+                Ok(None) => return ErrorKind::UnexpectedEof.into(),
+                Err(e) => return e,
+            }
+        }
+    }
+
+    /// Finds the first offset in `buf` from which the rest of `buf` consists
+    /// of complete entries: 0 if `buf` is the start of the stream, or an
+    /// offset following `\n\n`. Returns the offset and the end positions of
+    /// the entries relative to it.
+    pub(crate) fn first_entry(buf: &[u8], at_start: bool) -> Option<(usize, Vec<usize>)> {
+        first_entry_from(buf, at_start, 0)
+    }
+
+    /// Like [first_entry], but only considers offsets from `from` on.
+    fn first_entry_from(buf: &[u8], at_start: bool, from: usize) -> Option<(usize, Vec<usize>)> {
+        let candidates = at_start.then_some(0).into_iter().chain(
+            buf.windows(2)
+                .enumerate()
+                .filter(|(_, w)| *w == b"\n\n")
+                .map(|(i, _)| i + 2),
+        );
+        // Entries parse the same from every offset at which a parse that
+        // failed found an entry to end, hence parses stop there.
+        let mut failed = std::collections::HashSet::new();
+        for c in candidates.filter(|&c| c >= from && c < buf.len()) {
+            if failed.contains(&c) {
+                continue;
+            }
+            let mut reader = JournalExportRead::new(&buf[c..]);
+            let mut ends = vec![];
+            let complete = loop {
+                match reader.parse_next() {
+                    Ok(Some(())) if failed.contains(&(c + reader.position())) => break false,
+                    Ok(Some(())) => ends.push(reader.position()),
+                    Ok(None) => break reader.position() == buf.len() - c,
+                    Err(_) => break false,
+                }
+            };
+            if complete {
+                return Some((c, ends));
+            }
+            failed.extend(ends.into_iter().map(|e| c + e));
+        }
+        None
+    }
 }
 
+#[cfg(feature = "std")]
 pub struct JournalExportAsyncRead<R> {
     buf_read: R,
     parse_state: JournalExportParser,
+    /// Whether [Self::parse_next] is waiting for the reader, or was dropped
+    /// while waiting.
+    reading: bool,
 }
 
 /// Read journal entries into a memory buffer which has at most
+#[cfg(feature = "std")]
 impl<R: AsyncRead + Unpin> JournalExportAsyncRead<R> {
     pub fn new(limits: JournalExportLimits, buf_read: R) -> Self {
         Self {
             buf_read,
-            parse_state: JournalExportParser::new(limits, DEFAULT_BUF_SIZE),
+            parse_state: JournalExportParser::new(limits),
+            reading: false,
         }
     }
 
+    /// See [JournalExportParser::set_value_handler].
+    pub fn with_value_handler(
+        mut self,
+        handler: impl FnMut(ValueChunk<'_>) -> std::io::Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.parse_state.set_value_handler(Box::new(handler));
+        self
+    }
+
+    /// Parses the next entry.
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancellation safe: if the future is dropped before it
+    /// completes, e.g. by `select` or a timeout, no input is lost, provided
+    /// that the reader does not lose any when its `poll_read` is not called
+    /// again. The next call continues with the entry that was being parsed.
     pub async fn parse_next(&mut self) -> Result<Option<()>, JournalExportReadError> {
-        self.parse_state.clear_entry();
+        // Input is only awaited while reading, where a previous call may have
+        // been dropped in the middle of an entry.
+        match self.reading {
+            true => self.parse_state.unfill(),
+            false => self.parse_state.clear_entry(),
+        }
         loop {
             match self.parse_state.parse() {
                 ParseResult::Ok(()) => return Ok(Some(())),
                 ParseResult::Eof => return Ok(None),
                 ParseResult::Err(e) => return Err::<_, JournalExportReadError>(e),
                 ParseResult::Underfilled(b) => {
-                    let n = self.buf_read.read(b).await?;
-                    self.parse_state.extend(n);
+                    self.reading = true;
+                    let read = self.buf_read.read(b).await;
+                    self.reading = false;
+                    match read {
+                        Ok(n) => self.parse_state.extend(n),
+                        Err(e) => return Err(self.parse_state.locate(e.into())),
+                    }
                 }
             }
         }
     }
 
+    /// Like [Self::parse_next], but fails with [ErrorKind::Timeout] if the
+    /// next entry is not complete within `timeout`, e.g. because a network
+    /// peer stalls. The reader remains usable after a timeout.
+    #[cfg(feature = "listen")]
+    pub async fn parse_next_timeout(
+        &mut self,
+        timeout: std::time::Duration,
+    ) -> Result<Option<()>, JournalExportReadError> {
+        smol::future::or(self.parse_next(), async {
+            smol::Timer::after(timeout).await;
+            Err(ErrorKind::Timeout(timeout).into())
+        })
+        .await
+    }
+
     pub fn get_entry(&self) -> RefEntry<'_> {
         self.parse_state.get_entry()
     }
+
+    /// The total number of bytes that were read from the underlying reader.
+    pub fn bytes_read(&self) -> usize {
+        self.parse_state.bytes_read()
+    }
+
+    /// See [JournalExportParser::position].
+    pub fn position(&self) -> usize {
+        self.parse_state.position()
+    }
+
+    /// See [JournalExportParser::error_context].
+    pub fn error_context(&self) -> ErrorContext {
+        self.parse_state.error_context()
+    }
+
+    /// See [JournalExportParser::take_remainder]. The remainder includes the
+    /// input of a call to [Self::parse_next] that was dropped.
+    pub fn take_remainder(&mut self) -> Option<Vec<u8>> {
+        self.reading = false;
+        self.parse_state.take_remainder()
+    }
+
+    /// Replaces the underlying reader and resets the parser, retaining its
+    /// buffer. Returns the previous reader.
+    pub fn replace_reader(&mut self, buf_read: R) -> R {
+        self.reading = false;
+        self.parse_state.reset();
+        std::mem::replace(&mut self.buf_read, buf_read)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.buf_read
+    }
 }
 
-#[derive(Error, Debug)]
-pub enum JournalExportReadError {
-    #[error("IO error occured.")]
-    IoError(#[from] std::io::Error),
-    #[error("Unexpected character")]
+/// What went wrong when parsing failed; see [JournalExportReadError::kind].
+#[derive(Debug)]
+pub enum ErrorKind {
+    #[cfg(feature = "std")]
+    IoError(std::io::Error),
     UnexpectedCharacter(u8),
-    #[error("Unexpected Eof while parsing.")]
     UnexpectedEof,
-    #[error("Field name exceeds maximum allowed length.")]
     FieldNameTooLong,
-    #[error("Field value maximum allowed length.")]
     FieldValueTooLong,
-    #[error("Total size of journal entry exceeds maximum allowed size.")]
     EntryTooLarge,
+    /// See `JournalExportAsyncRead::parse_next_timeout`.
+    Timeout(core::time::Duration),
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            ErrorKind::IoError(e) => write!(f, "IO error: {}", e),
+            ErrorKind::UnexpectedCharacter(c) => {
+                write!(f, "Unexpected character {:?}", char::from(*c))
+            }
+            ErrorKind::UnexpectedEof => f.write_str("Unexpected Eof while parsing"),
+            ErrorKind::FieldNameTooLong => f.write_str("Field name exceeds maximum allowed length"),
+            ErrorKind::FieldValueTooLong => {
+                f.write_str("Field value exceeds maximum allowed length")
+            }
+            ErrorKind::EntryTooLarge => {
+                f.write_str("Total size of journal entry exceeds maximum allowed size")
+            }
+            ErrorKind::Timeout(timeout) => write!(f, "No entry within {:?}", timeout),
+        }
+    }
+}
+
+/// An error of the parser, with where in the stream it occurred as far as
+/// that is known.
+#[derive(Debug)]
+pub struct JournalExportReadError {
+    kind: ErrorKind,
+    /// The offset and the index of the entry.
+    position: Option<(u64, usize)>,
+    field_name: Option<Box<[u8]>>,
+}
+
+impl JournalExportReadError {
+    pub fn new(kind: ErrorKind) -> Self {
+        Self {
+            kind,
+            position: None,
+            field_name: None,
+        }
+    }
+
+    /// Sets the position of the error, i.e. the offset in the stream at
+    /// which it was detected and the index of the entry being parsed.
+    pub fn with_position(self, offset: u64, entry: usize) -> Self {
+        Self {
+            position: Some((offset, entry)),
+            ..self
+        }
+    }
+
+    /// Sets the name of the field whose value failed to parse.
+    pub fn with_field_name(self, name: &[u8]) -> Self {
+        Self {
+            field_name: Some(name.into()),
+            ..self
+        }
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    pub fn into_kind(self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The offset in the stream at which the error was detected.
+    pub fn offset(&self) -> Option<u64> {
+        self.position.map(|(offset, _)| offset)
+    }
+
+    /// The index of the entry that failed to parse, counted from the start
+    /// of the stream or the last reset of the parser.
+    pub fn entry(&self) -> Option<usize> {
+        self.position.map(|(_, entry)| entry)
+    }
+
+    /// The name of the field whose value failed to parse.
+    pub fn field_name(&self) -> Option<&[u8]> {
+        self.field_name.as_deref()
+    }
+}
+
+impl fmt::Display for JournalExportReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(name) = &self.field_name {
+            write!(
+                f,
+                " in field {}",
+                alloc::string::String::from_utf8_lossy(name)
+            )?;
+        }
+        match self.position {
+            Some((offset, entry)) => write!(f, " (entry {}, byte {})", entry, offset),
+            None => Ok(()),
+        }
+    }
+}
+
+impl From<ErrorKind> for JournalExportReadError {
+    fn from(kind: ErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for JournalExportReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::IoError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for JournalExportReadError {
+    fn from(e: std::io::Error) -> Self {
+        Self::new(ErrorKind::IoError(e))
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl From<core::convert::Infallible> for JournalExportReadError {
+    fn from(e: core::convert::Infallible) -> Self {
+        match e {}
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<JournalExportReadError> for std::io::Error {
+    fn from(e: JournalExportReadError) -> Self {
+        let kind = match &e.kind {
+            ErrorKind::IoError(e) => e.kind(),
+            ErrorKind::Timeout(_) => std::io::ErrorKind::TimedOut,
+            _ => std::io::ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, e)
+    }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
-    use std::fs::OpenOptions;
+    use std::{
+        collections::HashSet,
+        fs::OpenOptions,
+        sync::{Arc, Mutex},
+    };
+
+    use crate::{
+        config::{DuplicatePolicy, JournalExportLimitsBuilder, LimitPolicy},
+        testutil::{write_binary, write_string},
+    };
+
+    use super::{Entry, ErrorKind, FieldOrder, JournalExportRead, OwnedEntry, RefEntry};
+
+    fn export_stream(n: usize) -> Vec<u8> {
+        let mut v = vec![];
+        for i in 0..n {
+            v.extend_from_slice(format!("__CURSOR=c{}\nMESSAGE=message {}\n\n", i, i).as_bytes());
+        }
+        v
+    }
+
+    #[test]
+    fn empty_stream_has_no_entries() {
+        let mut export_read = JournalExportRead::new(&b""[..]);
+        assert!(matches!(export_read.parse_next(), Ok(None)));
+    }
+
+    #[test]
+    fn entries_know_their_size_and_fields() {
+        let mut stream = vec![];
+        write_string(&mut stream, "MESSAGE", "a");
+        write_binary(&mut stream, "DATA", b"x\ny");
+        write_string(&mut stream, "MESSAGE", "b");
+        stream.push(b'\n');
+        let mut reader = JournalExportRead::new(&stream[..]);
+        reader.parse_next().unwrap();
+        let e = reader.get_entry();
+        assert_eq!(e.len_bytes(), stream.len());
+        assert_eq!(e.field_count(), 3);
+        let names: Vec<_> = e.names().collect();
+        assert_eq!(names, [&b"MESSAGE"[..], b"DATA", b"MESSAGE"]);
+        let owned = e.to_owned();
+        assert_eq!(owned.field_count(), 3);
+        assert!(owned.names().eq(e.names()));
+    }
+
+    #[test]
+    fn errors_are_located_in_the_stream() {
+        let mut stream = export_stream(2);
+        stream.extend_from_slice(b"MESSAGE=x\nBAD\x01=y\n\n");
+        let mut export_read = JournalExportRead::new(&stream[..]);
+        let e = loop {
+            if let Err(e) = export_read.parse_next() {
+                break e;
+            }
+        };
+        assert_eq!(e.entry(), Some(2));
+        assert_eq!(e.offset(), Some(stream.len() as u64 - 5));
+        assert_eq!(e.field_name(), None);
+        let context = export_read.error_context();
+        assert_eq!(context.offset as usize + context.bytes.len(), stream.len());
+        assert!(context
+            .hexdump()
+            .ends_with("0000004b  01 3d 79 0a 0a                                    |.=y..|\n"));
+    }
+
+    #[test]
+    fn entries_are_identified_by_canonical_content() {
+        let mut a = vec![];
+        write_string(&mut a, "MESSAGE", "hello");
+        write_string(&mut a, "TAG", "1");
+        write_string(&mut a, "TAG", "2");
+        a.push(b'\n');
+        let mut b = vec![];
+        write_string(&mut b, "TAG", "1");
+        write_binary(&mut b, "MESSAGE", "hello");
+        write_string(&mut b, "TAG", "2");
+        b.push(b'\n');
+        let mut c = vec![];
+        write_string(&mut c, "TAG", "2");
+        write_string(&mut c, "MESSAGE", "hello");
+        write_string(&mut c, "TAG", "1");
+        c.push(b'\n');
+        let parse = |s: &[u8]| JournalExportRead::new(s).next().unwrap();
+        let (a, b, c) = (parse(&a), parse(&b), parse(&c));
+
+        assert_eq!(a.canonical_bytes(), b"MESSAGE=hello\nTAG=1\nTAG=2\n\n");
+        assert_eq!(
+            b.canonicalize(FieldOrder::Preserve),
+            b"TAG=1\nMESSAGE=hello\nTAG=2\n\n"
+        );
+        assert!(a == b);
+        assert!(a != c);
+        let set: HashSet<_> = [a, b, c].into_iter().collect();
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn entries_and_readers_can_be_sent_to_other_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<OwnedEntry>();
+        assert_send_sync::<RefEntry<'_>>();
+        assert_send_sync::<JournalExportRead<&[u8]>>();
+
+        let stream = export_stream(10);
+        let entries: Vec<OwnedEntry> = JournalExportRead::new(&stream[..]).collect();
+        let messages = std::thread::spawn(move || {
+            entries
+                .iter()
+                .map(|e| e.get(b"MESSAGE").unwrap().to_vec())
+                .collect::<Vec<_>>()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(messages[9], b"message 9");
+    }
+
+    #[test]
+    fn reader_can_be_reused_after_failure() {
+        let truncated = b"__CURSOR=c0\nMESSAGE=trunc";
+        let stream = export_stream(3);
+        let mut export_read = JournalExportRead::new(&truncated[..]);
+        let e = export_read.parse_next().unwrap_err();
+        assert!(matches!(e.kind(), ErrorKind::UnexpectedEof));
+        assert_eq!(
+            (e.entry(), e.offset()),
+            (Some(0), Some(truncated.len() as u64))
+        );
+        assert_eq!(e.field_name(), Some(&b"MESSAGE"[..]));
+
+        let old = export_read.replace_reader(&stream[..]);
+        assert!(old.is_empty());
+        let mut count = 0;
+        while export_read.parse_next().unwrap().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn small_initial_capacity_grows() {
+        let stream = export_stream(10);
+        let mut export_read = JournalExportRead::with_capacity(4, &stream[..]);
+        let mut count = 0;
+        while export_read.parse_next().unwrap().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 10);
+        assert_eq!(export_read.bytes_read(), stream.len());
+    }
+
+    #[test]
+    fn stream_larger_than_max_buf_size_is_parsed() {
+        let stream = export_stream(1000);
+        let limits = JournalExportLimitsBuilder::new()
+            .with_max_buf_size(256)
+            .build();
+        let mut export_read = JournalExportRead::new_with_limits(limits, &stream[..]);
+
+        let mut count = 0;
+        while export_read.parse_next().unwrap().is_some() {
+            let e = export_read.get_entry();
+            let expected = format!("__CURSOR=c{}\nMESSAGE=message {}\n\n", count, count);
+            assert_eq!(e.as_bytes(), expected.as_bytes());
+            count += 1;
+        }
+        assert_eq!(count, 1000);
+    }
+
+    #[test]
+    fn entry_larger_than_max_buf_size_fails() {
+        let mut stream = b"MESSAGE=".to_vec();
+        stream.extend(std::iter::repeat_n(b'x', 1024));
+        stream.extend_from_slice(b"\n\n");
+        let limits = JournalExportLimitsBuilder::new()
+            .with_max_buf_size(256)
+            .build();
+        let mut export_read = JournalExportRead::new_with_limits(limits, &stream[..]);
+
+        assert!(matches!(
+            export_read.parse_next().map_err(|e| e.into_kind()),
+            Err(ErrorKind::EntryTooLarge)
+        ));
+    }
+
+    #[test]
+    fn field_value_sizes_override_the_limit() {
+        let parse = |stream: &[u8]| {
+            let limits = JournalExportLimitsBuilder::new()
+                .with_max_field_value_size(16)
+                .with_field_value_size("MESSAGE", 4)
+                .with_field_value_size("COREDUMP*", 1024)
+                .build();
+            let mut export_read = JournalExportRead::new_with_limits(limits, stream);
+            let parsed = export_read.parse_next();
+            parsed.map(|_| export_read.get_entry().get(b"COREDUMP").map(<[u8]>::len))
+        };
+
+        let mut stream = vec![];
+        write_binary(&mut stream, "COREDUMP", vec![7; 1000]);
+        write_string(&mut stream, "COREDUMP_EXE", "x".repeat(500));
+        write_string(&mut stream, "MESSAGE", "abcd");
+        write_string(&mut stream, "OTHER", "y".repeat(16));
+        stream.push(b'\n');
+        assert_eq!(parse(&stream).unwrap(), Some(1000));
+
+        for (name, len) in [("MESSAGE", 5), ("OTHER", 17)] {
+            let mut stream = vec![];
+            write_string(&mut stream, name, "z".repeat(len));
+            stream.push(b'\n');
+            let e = parse(&stream).unwrap_err();
+            assert!(matches!(e.kind(), ErrorKind::FieldValueTooLong), "{}", name);
+        }
+        let mut stream = vec![];
+        write_binary(&mut stream, "COREDUMP", vec![7; 1025]);
+        stream.push(b'\n');
+        let e = parse(&stream).unwrap_err();
+        assert!(matches!(e.kind(), ErrorKind::FieldValueTooLong));
+    }
+
+    #[test]
+    fn limit_violations_skip_or_truncate() {
+        let parse = |policy, stream: &[u8]| {
+            let limits = JournalExportLimitsBuilder::new()
+                .with_max_field_value_size(16)
+                .with_max_field_name_len(16)
+                .with_initial_buf_size(64)
+                .with_max_buf_size(256)
+                .with_limit_policy(policy)
+                .build();
+            let mut export_read = JournalExportRead::new_with_limits(limits, stream);
+            let mut entries = vec![];
+            while let Some(()) = export_read.parse_next().unwrap() {
+                entries.push(export_read.get_entry().to_owned());
+            }
+            let counts = (
+                export_read.skipped_entries(),
+                export_read.truncated_values(),
+            );
+            (entries, counts)
+        };
+        let mut stream = vec![];
+        let mut entry = |fields: &[(&str, &[u8])], binary: bool| {
+            for (name, value) in fields {
+                if binary {
+                    write_binary(&mut stream, name, value);
+                } else {
+                    write_string(&mut stream, name, value);
+                }
+            }
+            stream.push(b'\n');
+        };
+        let long = &[b'x'; 600][..];
+        entry(&[("MESSAGE", b"1")], false);
+        entry(&[("A", b"a"), ("MESSAGE", long), ("B", b"b")], false);
+        entry(&[("MESSAGE", b"2")], false);
+        entry(&[("DATA", long), ("MESSAGE", b"x\n")], true);
+        entry(&[("A_VERY_LONG_FIELD_NAME", b"a")], false);
+        entry(&[("A_VERY_LONG_FIELD_NAME", b"a\nb")], true);
+        entry(&[("MESSAGE", b"3")], false);
+        entry(&[("FIELD", &b"0123456789"[..]); 30], false);
+        entry(&[("MESSAGE", b"4")], false);
+
+        let (entries, counts) = parse(LimitPolicy::Skip, &stream);
+        let messages: Vec<_> = entries.iter().map(|e| e.get(b"MESSAGE").unwrap()).collect();
+        assert_eq!(messages, [b"1", b"2", b"3", b"4"]);
+        assert_eq!(counts, (5, 0));
 
-    use super::{Entry, JournalExportRead};
+        let (entries, counts) = parse(LimitPolicy::Truncate, &stream);
+        assert_eq!(entries.len(), 6);
+        assert_eq!(counts, (3, 2));
+        let truncated = [&long[..16 - 3], "\u{2026}".as_bytes()].concat();
+        assert_eq!(entries[1].get(b"MESSAGE"), Some(&truncated[..]));
+        assert_eq!(entries[1].get(b"B"), Some(&b"b"[..]));
+        assert_eq!(entries[3].get(b"DATA"), Some(&truncated[..]));
+        assert_eq!(entries[3].get(b"MESSAGE"), Some(&b"x\n"[..]));
+        // Truncated entries are valid export entries.
+        let rewritten: Vec<u8> = entries.iter().flat_map(|e| e.as_bytes().to_vec()).collect();
+        assert_eq!(parse(LimitPolicy::Error, &rewritten).0.len(), 6);
+    }
+
+    #[test]
+    fn repeated_fields_are_kept_by_policy() {
+        let mut stream = vec![];
+        write_string(&mut stream, "TAG", "a");
+        write_string(&mut stream, "MESSAGE", "m");
+        write_binary(&mut stream, "TAG", b"b\n");
+        write_string(&mut stream, "TAGS", "x");
+        write_string(&mut stream, "TAG", "c");
+        write_string(&mut stream, "MESSAGE", "n");
+        stream.push(b'\n');
+        write_string(&mut stream, "MESSAGE", "single");
+        stream.push(b'\n');
+
+        let entries = |policy| {
+            let limits = JournalExportLimitsBuilder::new()
+                .with_duplicate_policy(policy)
+                .build();
+            let reader = JournalExportRead::new_with_limits(limits, &stream[..]);
+            reader.collect::<Vec<_>>()
+        };
+        let all = entries(DuplicatePolicy::KeepAll);
+        assert_eq!(all[0].as_bytes(), &stream[..all[0].len_bytes()]);
+        let tags: Vec<_> = all[0].get_all(b"TAG").collect();
+        assert_eq!(tags, [&b"a"[..], b"b\n", b"c"]);
+        assert_eq!(all[0].get(b"TAG"), Some(&b"a"[..]));
+        assert_eq!(all[1].get_all(b"TAG").count(), 0);
+
+        let expected = |fields: &[(&str, &[u8])]| {
+            let mut entry = vec![];
+            for (name, value) in fields {
+                write_string(&mut entry, name, value);
+            }
+            entry.push(b'\n');
+            entry
+        };
+        let first = entries(DuplicatePolicy::KeepFirst);
+        let fields = [("TAG", &b"a"[..]), ("MESSAGE", b"m"), ("TAGS", b"x")];
+        assert_eq!(first[0].as_bytes(), expected(&fields));
+        let last = entries(DuplicatePolicy::KeepLast);
+        let fields = [("TAGS", &b"x"[..]), ("TAG", b"c"), ("MESSAGE", b"n")];
+        assert_eq!(last[0].as_bytes(), expected(&fields));
+        assert_eq!(last[0].field_count(), 3);
+        assert_eq!(last[1].get(b"MESSAGE"), Some(&b"single"[..]));
+    }
+
+    #[test]
+    fn entries_are_read_backwards() {
+        let mut stream = export_stream(5000);
+        write_binary(&mut stream, "DATA", b"x\n\nMESSAGE=fake\n\n");
+        stream.push(b'\n');
+        write_string(&mut stream, "MESSAGE", "last");
+        stream.push(b'\n');
+
+        let forward: Vec<_> = JournalExportRead::new(&stream[..]).collect();
+        let mut backward: Vec<_> = JournalExportRead::new(std::io::Cursor::new(&stream))
+            .read_backwards()
+            .unwrap()
+            .collect();
+        backward.reverse();
+        assert_eq!(backward.len(), 5002);
+        assert!(forward == backward);
+
+        let mut reader = JournalExportRead::new(std::io::Cursor::new(b"A=1\n\nB"))
+            .read_backwards()
+            .unwrap();
+        assert!(reader.parse_next().is_err());
+    }
+
+    #[test]
+    fn empty_lines_in_binary_values_are_not_read_as_entries_backwards() {
+        let mut entries = vec![];
+        // A bit more than the first block.
+        for i in 0..1100 {
+            write_string(&mut entries, "__CURSOR", format!("i={}", i));
+            write_binary(&mut entries, "MESSAGE", "Config:\n\nfoo=1\nbar=2");
+            write_string(&mut entries, "PRIORITY", "6");
+            entries.push(b'\n');
+        }
+        // Shifts the blocks such that they start within every part of an
+        // entry.
+        for padding in 0..64 {
+            let mut stream = entries.clone();
+            write_string(&mut stream, "PADDING", "x".repeat(padding));
+            stream.push(b'\n');
+
+            let forward: Vec<_> = JournalExportRead::new(&stream[..]).collect();
+            let mut reader = JournalExportRead::new(std::io::Cursor::new(&stream))
+                .read_backwards()
+                .unwrap();
+            let mut backward = vec![];
+            while reader.parse_next().unwrap().is_some() {
+                backward.push(reader.get_entry().clone());
+            }
+            backward.reverse();
+            assert!(forward == backward);
+        }
+    }
+
+    #[test]
+    fn large_binary_values_are_streamed() {
+        let core: Vec<u8> = (0..1_000_000u32).map(|i| (i % 251) as u8).collect();
+        let mut stream = vec![];
+        write_string(&mut stream, "MESSAGE", "crashed");
+        write_binary(&mut stream, "COREDUMP", &core);
+        write_binary(&mut stream, "SMALL", [0, 1]);
+        stream.push(b'\n');
+        stream.extend(export_stream(1));
+        let limits = JournalExportLimitsBuilder::new()
+            .with_max_buf_size(256)
+            .with_max_buffered_value_size(64)
+            .build();
+
+        let chunks = Arc::new(Mutex::new(vec![]));
+        let received = chunks.clone();
+        let mut export_read = JournalExportRead::new_with_limits(limits, &stream[..])
+            .with_value_handler(move |chunk| {
+                assert_eq!(chunk.name, b"COREDUMP");
+                assert_eq!(chunk.len, 1_000_000);
+                let mut received = received.lock().unwrap();
+                assert_eq!(chunk.offset, received.len() as u64);
+                received.extend_from_slice(chunk.data);
+                Ok(())
+            });
+
+        assert!(export_read.parse_next().unwrap().is_some());
+        let e = export_read.get_entry();
+        assert_eq!(e.get(b"MESSAGE"), Some(&b"crashed"[..]));
+        assert_eq!(e.get(b"COREDUMP"), Some(&b""[..]));
+        assert_eq!(e.get(b"SMALL"), Some(&[0, 1][..]));
+        assert!(*chunks.lock().unwrap() == core);
+        assert!(export_read.parse_next().unwrap().is_some());
+        assert_eq!(
+            export_read.get_entry().get(b"MESSAGE"),
+            Some(&b"message 0"[..])
+        );
+        assert_eq!(export_read.bytes_read(), stream.len());
+    }
 
     #[test]
     fn can_parse_host_files() -> Result<(), Box<dyn std::error::Error + 'static>> {
@@ -540,4 +2202,114 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn remainder_resumes_the_stream() {
+        use super::parser::{JournalExportParser, ParseResult};
+
+        let mut stream = export_stream(10);
+        write_binary(&mut stream, "DATA", b"binary\n\nvalue");
+        stream.push(b'\n');
+        let expected: Vec<_> = JournalExportRead::new(&stream[..])
+            .map(|e| e.as_bytes().to_vec())
+            .collect();
+        for cut in [0, 1, 30, 31, 33, stream.len() - 5, stream.len()] {
+            // The parser sees the input up to `cut` before it is stopped.
+            let mut parser = JournalExportParser::new(Default::default());
+            let mut entries = vec![];
+            let mut input = &stream[..cut];
+            loop {
+                match parser.parse() {
+                    ParseResult::Ok(()) => {
+                        entries.push(parser.get_entry().as_bytes().to_vec());
+                        parser.clear_entry();
+                    }
+                    ParseResult::Underfilled(b) if !input.is_empty() => {
+                        let n = b.len().min(input.len()).min(7);
+                        b[..n].copy_from_slice(&input[..n]);
+                        input = &input[n..];
+                        parser.extend(n);
+                    }
+                    ParseResult::Underfilled(_) => break,
+                    ParseResult::Eof | ParseResult::Err(_) => unreachable!(),
+                }
+            }
+            let remainder = parser.take_remainder().unwrap();
+            let mut resumed =
+                JournalExportRead::new(std::io::Read::chain(&remainder[..], &stream[cut..]));
+            while resumed.parse_next().unwrap().is_some() {
+                entries.push(resumed.get_entry().as_bytes().to_vec());
+            }
+            assert_eq!(entries, expected, "cut at {}", cut);
+        }
+    }
+
+    /// Yields its input a few bytes at a time, every other poll.
+    struct Trickle {
+        input: Vec<u8>,
+        pos: usize,
+        ready: bool,
+    }
+
+    impl futures::AsyncRead for Trickle {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+            buf: &mut [u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            self.ready = !self.ready;
+            if !self.ready {
+                cx.waker().wake_by_ref();
+                return std::task::Poll::Pending;
+            }
+            let n = buf.len().min(3).min(self.input.len() - self.pos);
+            buf[..n].copy_from_slice(&self.input[self.pos..self.pos + n]);
+            self.pos += n;
+            std::task::Poll::Ready(Ok(n))
+        }
+    }
+
+    #[test]
+    fn async_parse_next_is_cancellation_safe() {
+        use futures::{FutureExt, TryStreamExt};
+
+        let stream = export_stream(20);
+        let trickle = Trickle {
+            input: stream.clone(),
+            pos: 0,
+            ready: false,
+        };
+        let mut reader = super::JournalExportAsyncRead::new(Default::default(), trickle);
+        let mut parsed = vec![];
+        // Polls once and drops the future if it is pending.
+        loop {
+            match reader.parse_next().now_or_never() {
+                None => continue,
+                Some(Ok(Some(()))) => parsed.push(reader.get_entry().as_bytes().to_vec()),
+                Some(Ok(None)) => break,
+                Some(Err(e)) => panic!("{}", e),
+            }
+        }
+        let expected: Vec<_> = JournalExportRead::new(&stream[..])
+            .map(|e| e.as_bytes().to_vec())
+            .collect();
+        assert_eq!(parsed, expected);
+
+        #[cfg(feature = "listen")]
+        {
+            let (tx, rx) = futures::channel::mpsc::unbounded::<std::io::Result<Vec<u8>>>();
+            let mut reader =
+                super::JournalExportAsyncRead::new(Default::default(), rx.into_async_read());
+            tx.unbounded_send(Ok(b"MESSAGE=a\n".to_vec())).unwrap();
+            let timeout = std::time::Duration::from_millis(20);
+            let e = smol::block_on(reader.parse_next_timeout(timeout)).unwrap_err();
+            assert!(matches!(e.kind(), ErrorKind::Timeout(_)));
+            tx.unbounded_send(Ok(b"\n".to_vec())).unwrap();
+            assert_eq!(
+                smol::block_on(reader.parse_next_timeout(timeout)).unwrap(),
+                Some(())
+            );
+            assert_eq!(reader.get_entry().get(b"MESSAGE"), Some(&b"a"[..]));
+        }
+    }
 }
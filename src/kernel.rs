@@ -0,0 +1,191 @@
+//! Detect incidents in kernel messages.
+//!
+//! [KernelDetector] scans the entries with `_TRANSPORT=kernel` for
+//! out-of-memory kills, segmentation faults and hardware errors. The report
+//! the OOM killer prints spans many messages, from `invoked oom-killer` to
+//! `Killed process`; they are combined into a single [Incident].
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::journald::Entry;
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum IncidentKind {
+    Oom,
+    Segfault,
+    HardwareError,
+}
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct Incident {
+    pub kind: IncidentKind,
+    pub timestamp: Option<u64>,
+    pub boot_id: Option<String>,
+    /// The process that was killed or crashed.
+    pub process: Option<String>,
+    pub pid: Option<u64>,
+    /// The message that describes the incident best.
+    pub message: String,
+    /// For OOM kills, the process whose allocation invoked the OOM killer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger: Option<String>,
+    /// All messages of the incident.
+    pub messages: Vec<String>,
+}
+
+impl Incident {
+    fn new(kind: IncidentKind, entry: &impl Entry, message: String) -> Self {
+        Self {
+            kind,
+            timestamp: entry.realtime_timestamp(),
+            boot_id: entry
+                .get(b"_BOOT_ID")
+                .map(|id| String::from_utf8_lossy(id).into_owned()),
+            process: None,
+            pid: None,
+            messages: vec![message.clone()],
+            message,
+            trigger: None,
+        }
+    }
+}
+
+pub struct KernelDetector {
+    oom_start: Regex,
+    oom_kill: Regex,
+    segfault: Regex,
+    hardware: Regex,
+    /// The OOM report being read.
+    oom: Option<Incident>,
+    incidents: Vec<Incident>,
+}
+
+impl Default for KernelDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KernelDetector {
+    pub fn new() -> Self {
+        Self {
+            oom_start: Regex::new(r"^(.+?) invoked oom-killer:").unwrap(),
+            oom_kill: Regex::new(r"[Oo]ut of memory.*: Kill(?:ed)? process (\d+) \((.*?)\)")
+                .unwrap(),
+            segfault: Regex::new(
+                r"^(.+?)\[(\d+)\]: (?:segfault at|general protection fault|trap )",
+            )
+            .unwrap(),
+            hardware: Regex::new(
+                r"(?i)hardware error|machine check|EDAC .*\b(?:CE|UE)\b|I/O error, dev |PCIe Bus Error",
+            )
+            .unwrap(),
+            oom: None,
+            incidents: vec![],
+        }
+    }
+
+    pub fn push(&mut self, entry: &impl Entry) {
+        if entry.get(b"_TRANSPORT") != Some(b"kernel") {
+            return;
+        }
+        let Some(message) = entry.get(b"MESSAGE") else {
+            return;
+        };
+        let message = String::from_utf8_lossy(message).into_owned();
+
+        if let Some(c) = self.oom_start.captures(&message) {
+            let trigger = c[1].to_string();
+            self.finish_oom();
+            let mut oom = Incident::new(IncidentKind::Oom, entry, message);
+            oom.trigger = Some(trigger);
+            self.oom = Some(oom);
+        } else if let Some(c) = self.oom_kill.captures(&message) {
+            let (pid, process) = (c[1].parse().ok(), c[2].to_string());
+            let mut oom = self
+                .oom
+                .take()
+                .unwrap_or_else(|| Incident::new(IncidentKind::Oom, entry, message.clone()));
+            if oom.messages.last() != Some(&message) {
+                oom.messages.push(message.clone());
+            }
+            oom.message = message;
+            oom.process = Some(process);
+            oom.pid = pid;
+            self.incidents.push(oom);
+        } else if let Some(c) = self.segfault.captures(&message) {
+            let (process, pid) = (c[1].to_string(), c[2].parse().ok());
+            let mut incident = Incident::new(IncidentKind::Segfault, entry, message);
+            incident.process = Some(process);
+            incident.pid = pid;
+            self.incidents.push(incident);
+        } else if self.hardware.is_match(&message) {
+            let incident = Incident::new(IncidentKind::HardwareError, entry, message);
+            self.incidents.push(incident);
+        } else if let Some(oom) = &mut self.oom {
+            oom.messages.push(message);
+        }
+    }
+
+    /// Records an OOM report that ended without naming the killed process.
+    fn finish_oom(&mut self) {
+        self.incidents.extend(self.oom.take());
+    }
+
+    /// The incidents found, in the order of their first message.
+    pub fn into_incidents(mut self) -> Vec<Incident> {
+        self.finish_oom();
+        self.incidents
+            .sort_by_key(|i| (i.timestamp.is_none(), i.timestamp));
+        self.incidents
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{journald::JournalExportRead, testutil::write_string};
+
+    use super::{IncidentKind, KernelDetector};
+
+    #[test]
+    fn detects_kernel_incidents() {
+        let messages = [
+            (1, "kernel", "stress invoked oom-killer: gfp_mask=0x140cca(GFP_HIGHUSER_MOVABLE|__GFP_COMP), order=0, oom_score_adj=0"),
+            (2, "kernel", "Mem-Info:"),
+            (3, "kernel", "Out of memory: Killed process 4242 (stress) total-vm:8388608kB, anon-rss:7340032kB"),
+            (4, "kernel", "app[77]: segfault at 0 ip 000055d5 sp 00007ffc error 4 in app[55d5+1000]"),
+            (5, "stdout", "app[78]: segfault at 0 ip 0 sp 0 error 4"),
+            (6, "kernel", "mce: [Hardware Error]: Machine check events logged"),
+            (7, "kernel", "usb 1-1: new high-speed USB device number 2"),
+        ];
+        let mut stream = vec![];
+        for (ts, transport, message) in messages {
+            write_string(&mut stream, "__REALTIME_TIMESTAMP", ts.to_string());
+            write_string(&mut stream, "_TRANSPORT", transport);
+            write_string(&mut stream, "MESSAGE", message);
+            stream.push(b'\n');
+        }
+
+        let mut detector = KernelDetector::new();
+        for e in JournalExportRead::new(&stream[..]) {
+            detector.push(&e);
+        }
+        let incidents = detector.into_incidents();
+        let summary: Vec<_> = incidents
+            .iter()
+            .map(|i| (i.kind, i.timestamp, i.process.as_deref(), i.pid))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (IncidentKind::Oom, Some(1), Some("stress"), Some(4242)),
+                (IncidentKind::Segfault, Some(4), Some("app"), Some(77)),
+                (IncidentKind::HardwareError, Some(6), None, None),
+            ]
+        );
+        assert_eq!(incidents[0].trigger.as_deref(), Some("stress"));
+        assert_eq!(incidents[0].messages.len(), 3);
+    }
+}
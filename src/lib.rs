@@ -1,4 +1,129 @@
+//! The parser core, i.e. [journald::parser], [shiftbuffer] and [config], only
+//! needs `alloc`; everything else, including the readers of [journald],
+//! requires the `std` feature.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod alert;
+#[cfg(feature = "std")]
+pub mod auth;
+#[cfg(feature = "std")]
+pub mod boots;
+#[cfg(feature = "std")]
+pub mod burst;
+#[cfg(feature = "std")]
+pub mod catalog;
+#[cfg(feature = "std")]
+pub mod chunk;
 pub mod config;
+#[cfg(feature = "std")]
+pub mod container;
+#[cfg(feature = "std")]
+pub mod coredump;
+#[cfg(feature = "std")]
+pub mod dedup;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "expr")]
+pub mod expr;
+#[cfg(feature = "std")]
 pub mod fieldname;
+#[cfg(feature = "std")]
+pub mod fields;
+#[cfg(all(feature = "std", not(unix)))]
+mod fileext;
+#[cfg(feature = "std")]
+pub mod follow;
+#[cfg(feature = "std")]
+pub mod format;
+#[cfg(feature = "std")]
+pub mod generate;
+#[cfg(feature = "geoip")]
+pub mod geoip;
+#[cfg(feature = "std")]
+pub mod group;
+#[cfg(feature = "std")]
+pub mod inspect;
+#[cfg(feature = "std")]
+pub mod join;
 pub mod journald;
+#[cfg(feature = "std")]
+pub mod kernel;
+#[cfg(feature = "listen")]
+pub mod listen;
+#[cfg(feature = "std")]
+pub mod live;
+#[cfg(all(target_os = "linux", feature = "local"))]
+pub mod local;
+#[cfg(feature = "std")]
+pub mod merge;
+#[cfg(feature = "std")]
+pub mod message_ids;
+#[cfg(feature = "std")]
+pub mod order;
+#[cfg(feature = "std")]
+pub mod pipeline;
+#[cfg(feature = "std")]
+pub mod pool;
+#[cfg(feature = "std")]
+pub mod provenance;
+#[cfg(feature = "std")]
+pub mod queue;
+#[cfg(feature = "std")]
+pub mod ratelimit;
+#[cfg(feature = "std")]
+pub mod reassemble;
+#[cfg(feature = "std")]
+pub mod rebase;
+#[cfg(feature = "std")]
+pub mod redact;
+#[cfg(feature = "std")]
+mod reorder;
+#[cfg(feature = "std")]
+pub mod reservoir;
+#[cfg(feature = "std")]
+pub mod retention;
+#[cfg(feature = "std")]
+pub mod schema;
+#[cfg(feature = "std")]
+pub mod secrets;
+#[cfg(feature = "std")]
+pub mod session;
 pub mod shiftbuffer;
+#[cfg(feature = "std")]
+pub mod shutdown;
+#[cfg(feature = "std")]
+pub mod sink;
+#[cfg(feature = "std")]
+pub mod skew;
+#[cfg(feature = "std")]
+pub mod sort;
+#[cfg(feature = "std")]
+pub mod source;
+#[cfg(feature = "std")]
+pub mod spool;
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
+#[cfg(feature = "std")]
+pub mod timeline;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(feature = "std")]
+mod trace;
+#[cfg(feature = "std")]
+pub mod transform;
+#[cfg(all(target_os = "linux", feature = "uring"))]
+pub mod uring;
+#[cfg(feature = "std")]
+pub mod usage;
+#[cfg(feature = "std")]
+pub mod values;
+#[cfg(feature = "tui")]
+pub mod view;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "evtx")]
+pub mod winevt;
@@ -0,0 +1,549 @@
+//! Receive export streams over TCP and Unix sockets.
+//!
+//! A [Listener] accepts any number of concurrent connections, each of which
+//! carries a stream in the Journal Export Format, e.g. as written by
+//! `journalctl -o export | nc host 19531`. The entries of all connections are
+//! written to a single [EntrySink], tagged with the fields `LOGINUS_PEER`
+//! (the address of the peer) and `LOGINUS_CONNECTION` (a number that is unique
+//! per listener), and with the provenance fields of [crate::provenance].
+//!
+//! Entries of one connection are written in the order they are received.
+//! Across connections, the entries are held back for a reorder window and
+//! written in the order of their `__REALTIME_TIMESTAMP`, such that streams
+//! that are slightly out of step are interleaved correctly.
+//!
+//! With the `tls` feature, TCP connections can be secured with
+//! [Listener::with_tls]. [Listener::with_shutdown] stops a listener
+//! gracefully: it stops accepting and reading, and writes all entries that
+//! were received before it returns.
+
+use std::{
+    future::Future,
+    io,
+    net::SocketAddr,
+    path::PathBuf,
+    pin::Pin,
+    time::{Duration, Instant},
+};
+
+use futures::{AsyncRead, AsyncWrite};
+use smol::{
+    channel::{Receiver, Sender},
+    net::{unix::UnixListener, TcpListener},
+    LocalExecutor, Timer,
+};
+
+use crate::{
+    config::JournalExportLimits,
+    journald::{Entry, JournalExportAsyncRead},
+    provenance::{self, SOURCE_FIELD},
+    reorder::Reorder,
+    shutdown::Shutdown,
+    sink::EntrySink,
+    trace,
+    transform::Rewrite,
+};
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+/// Summarizes a closed connection.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ConnectionReport {
+    pub id: u64,
+    pub peer: String,
+    pub entries: usize,
+    /// Why the stream could not be read to its end, if applicable.
+    pub error: Option<String>,
+}
+
+pub struct Listener {
+    addrs: Vec<ListenAddr>,
+    window: Duration,
+    max_connections: Option<u64>,
+    idle_timeout: Option<Duration>,
+    capacity: usize,
+    #[cfg(feature = "tls")]
+    tls: Option<std::sync::Arc<rustls::ServerConfig>>,
+    shutdown: Option<Shutdown>,
+}
+
+trait Connection: AsyncRead + AsyncWrite + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> Connection for T {}
+
+type Stream = Box<dyn Connection>;
+type Accept<'a> = Pin<Box<dyn Future<Output = io::Result<Accepted>> + 'a>>;
+
+struct Accepted {
+    stream: Stream,
+    peer: String,
+    tcp: bool,
+}
+
+enum Bound {
+    Tcp(TcpListener),
+    Unix(UnixListener, String),
+}
+
+impl Bound {
+    fn accept(&self) -> Accept<'_> {
+        match self {
+            Bound::Tcp(l) => Box::pin(async move {
+                let (stream, peer) = l.accept().await?;
+                Ok(Accepted {
+                    stream: Box::new(stream),
+                    peer: peer.to_string(),
+                    tcp: true,
+                })
+            }),
+            Bound::Unix(l, path) => Box::pin(async move {
+                // Peers of Unix sockets are usually unnamed.
+                let (stream, _) = l.accept().await?;
+                Ok(Accepted {
+                    stream: Box::new(stream),
+                    peer: path.clone(),
+                    tcp: false,
+                })
+            }),
+        }
+    }
+}
+
+enum Message {
+    Entry(Option<u64>, Vec<u8>),
+    Closed(ConnectionReport),
+}
+
+impl Listener {
+    pub fn new(addrs: Vec<ListenAddr>) -> Self {
+        Self {
+            addrs,
+            window: Duration::from_secs(1),
+            max_connections: None,
+            idle_timeout: None,
+            capacity: 1024,
+            #[cfg(feature = "tls")]
+            tls: None,
+            shutdown: None,
+        }
+    }
+
+    /// Returns once `shutdown` is requested, closing all connections.
+    pub fn with_shutdown(self, shutdown: Shutdown) -> Self {
+        Self {
+            shutdown: Some(shutdown),
+            ..self
+        }
+    }
+
+    /// Requires TLS on TCP connections; see [crate::tls::server_config].
+    #[cfg(feature = "tls")]
+    pub fn with_tls(self, config: std::sync::Arc<rustls::ServerConfig>) -> Self {
+        Self {
+            tls: Some(config),
+            ..self
+        }
+    }
+
+    /// How long entries are held back to order them across connections;
+    /// defaults to one second. With a zero window, entries are written as
+    /// they arrive.
+    pub fn with_reorder_window(self, window: Duration) -> Self {
+        Self { window, ..self }
+    }
+
+    /// Stops accepting connections after `n` connections and returns once
+    /// they are closed.
+    pub fn with_max_connections(self, n: u64) -> Self {
+        Self {
+            max_connections: Some(n),
+            ..self
+        }
+    }
+
+    /// Closes connections on which no complete entry arrives for `timeout`.
+    pub fn with_idle_timeout(self, timeout: Duration) -> Self {
+        Self {
+            idle_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Binds the addresses and writes the received entries to `sink` until
+    /// [Listener::with_max_connections] connections were closed, a shutdown
+    /// is requested or accepting fails. `on_close` is called for every closed
+    /// connection.
+    pub fn run(
+        self,
+        sink: &mut dyn EntrySink,
+        mut on_close: impl FnMut(&ConnectionReport),
+    ) -> io::Result<()> {
+        let mut bound = vec![];
+        for addr in self.addrs.iter() {
+            bound.push(match addr {
+                ListenAddr::Tcp(a) => Bound::Tcp(smol::block_on(TcpListener::bind(*a))?),
+                ListenAddr::Unix(p) => {
+                    Bound::Unix(UnixListener::bind(p)?, format!("unix:{}", p.display()))
+                }
+            });
+        }
+        let ex = LocalExecutor::new();
+        let (tx, rx) = smol::channel::bounded(self.capacity);
+        // Closing the sender wakes all tasks waiting for the receiver.
+        let (stop_tx, stop) = smol::channel::bounded::<()>(1);
+        if let Some(shutdown) = &self.shutdown {
+            let stop_tx = stop_tx.clone();
+            shutdown.on_request(move || {
+                stop_tx.close();
+            });
+        }
+        let result = smol::block_on(ex.run(async {
+            #[cfg(feature = "tls")]
+            let tls = self.tls.clone().map(futures_rustls::TlsAcceptor::from);
+            #[cfg(not(feature = "tls"))]
+            let tls = None;
+            let accepting = accept(
+                &ex,
+                bound,
+                tls,
+                tx,
+                stop,
+                self.max_connections,
+                self.idle_timeout,
+            );
+            let writing = write(rx, self.window, sink, &mut on_close);
+            futures::future::try_join(accepting, writing).await?;
+            Ok(())
+        }));
+        drop(stop_tx);
+        result
+    }
+}
+
+#[cfg(feature = "tls")]
+type TlsAcceptor = futures_rustls::TlsAcceptor;
+#[cfg(not(feature = "tls"))]
+type TlsAcceptor = std::convert::Infallible;
+
+async fn accept(
+    ex: &LocalExecutor<'_>,
+    bound: Vec<Bound>,
+    tls: Option<TlsAcceptor>,
+    tx: Sender<Message>,
+    stop: Receiver<()>,
+    max_connections: Option<u64>,
+    idle_timeout: Option<Duration>,
+) -> io::Result<()> {
+    let mut id = 0;
+    while max_connections.is_none_or(|max| id < max) {
+        let accepting = async {
+            let (result, _, _) = futures::future::select_all(bound.iter().map(Bound::accept)).await;
+            result
+        };
+        let Some(result) = until_stopped(accepting, &stop).await else {
+            break;
+        };
+        let accepted = result?;
+        id += 1;
+        trace::debug!(id, peer = %accepted.peer, "connection accepted");
+        let tls = tls.clone().filter(|_| accepted.tcp);
+        ex.spawn(receive(
+            id,
+            accepted,
+            tls,
+            tx.clone(),
+            stop.clone(),
+            idle_timeout,
+        ))
+        .detach();
+    }
+    Ok(())
+}
+
+/// Runs `future` unless `stop` is closed first.
+async fn until_stopped<T>(future: impl Future<Output = T>, stop: &Receiver<()>) -> Option<T> {
+    smol::future::or(async { Some(future.await) }, async {
+        let _ = stop.recv().await;
+        None
+    })
+    .await
+}
+
+const STOPPED: &str = "closed by shutdown";
+
+async fn receive(
+    id: u64,
+    accepted: Accepted,
+    tls: Option<TlsAcceptor>,
+    tx: Sender<Message>,
+    stop: Receiver<()>,
+    idle_timeout: Option<Duration>,
+) {
+    let Accepted { stream, peer, .. } = accepted;
+    let stream: Stream = match tls {
+        #[cfg(feature = "tls")]
+        Some(acceptor) => match until_stopped(acceptor.accept(stream), &stop).await {
+            Some(Ok(stream)) => Box::new(stream),
+            handshake => {
+                let error = match handshake {
+                    Some(Err(e)) => format!("TLS handshake failed: {}", e),
+                    _ => STOPPED.to_string(),
+                };
+                let report = ConnectionReport {
+                    id,
+                    peer,
+                    entries: 0,
+                    error: Some(error),
+                };
+                let _ = tx.send(Message::Closed(report)).await;
+                return;
+            }
+        },
+        #[cfg(not(feature = "tls"))]
+        Some(never) => match never {},
+        None => stream,
+    };
+    let rewrite = Rewrite::new()
+        .with_field("LOGINUS_PEER", peer.as_str())
+        .with_field("LOGINUS_CONNECTION", id.to_string());
+    let mut jreader = JournalExportAsyncRead::new(JournalExportLimits::default(), stream);
+    let mut entries = 0;
+    let error = loop {
+        let parsing = async {
+            match idle_timeout {
+                Some(timeout) => jreader.parse_next_timeout(timeout).await,
+                None => jreader.parse_next().await,
+            }
+        };
+        let Some(parsed) = until_stopped(parsing, &stop).await else {
+            break Some(STOPPED.to_string());
+        };
+        match parsed {
+            Ok(Some(())) => {
+                let e = jreader.get_entry();
+                let mut buf = vec![];
+                if rewrite.apply(&e, &mut buf) && e.get(SOURCE_FIELD.as_bytes()).is_none() {
+                    // Before the empty line that ends the entry.
+                    buf.pop();
+                    provenance::write_fields(&mut buf, peer.as_bytes(), provenance::now());
+                    buf.push(b'\n');
+                }
+                if tx
+                    .send(Message::Entry(e.realtime_timestamp(), buf))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+                entries += 1;
+            }
+            Ok(None) => break None,
+            Err(e) => break Some(e.to_string()),
+        }
+    };
+    trace::debug!(id, %peer, entries, bytes = jreader.bytes_read(), ?error, "connection closed");
+    let report = ConnectionReport {
+        id,
+        peer,
+        entries,
+        error,
+    };
+    let _ = tx.send(Message::Closed(report)).await;
+}
+
+async fn write(
+    rx: Receiver<Message>,
+    window: Duration,
+    sink: &mut dyn EntrySink,
+    on_close: &mut impl FnMut(&ConnectionReport),
+) -> io::Result<()> {
+    let mut reorder = Reorder::new(window);
+    loop {
+        let received = match reorder.deadline() {
+            Some(deadline) => {
+                smol::future::or(async { Some(rx.recv().await) }, async {
+                    Timer::at(deadline).await;
+                    None
+                })
+                .await
+            }
+            None => Some(rx.recv().await),
+        };
+        match received {
+            Some(Ok(Message::Entry(ts, entry))) => reorder.push(ts, entry, Instant::now()),
+            Some(Ok(Message::Closed(report))) => on_close(&report),
+            // All connections are closed and no more are accepted.
+            Some(Err(_)) => break,
+            None => {}
+        }
+        while let Some(entry) = reorder.pop(Instant::now()) {
+            sink.write_entry(&entry)?;
+        }
+    }
+    while let Some(entry) = reorder.pop_any() {
+        sink.write_entry(&entry)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, os::unix::net::UnixStream, thread, time::Duration};
+
+    use crate::{
+        generate::EntryGenerator,
+        journald::{Entry, JournalExportRead},
+        shutdown::Shutdown,
+    };
+
+    use super::{ListenAddr, Listener, STOPPED};
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn authenticates_tls_clients() {
+        use crate::{
+            sink::EntrySink,
+            tls::{client_config, server_config, testutil::write_pki, TlsSender},
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        write_pki(dir.path());
+        let root = dir.path().to_path_buf();
+        let path = move |name: &str| root.join(name);
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let server = server_config(
+            &path("server.pem"),
+            &path("server.key"),
+            Some(&path("ca.pem")),
+        )
+        .unwrap();
+        let listener = Listener::new(vec![ListenAddr::Tcp(([127, 0, 0, 1], port).into())])
+            .with_reorder_window(Duration::ZERO)
+            .with_tls(server)
+            .with_max_connections(2);
+        let addr = format!("localhost:{}", port);
+        let stream = EntryGenerator::new(1).generate(20);
+        let clients = thread::spawn(move || {
+            let connect = |config| loop {
+                match TlsSender::connect(&addr, std::sync::Arc::clone(&config)) {
+                    Ok(sender) => return sender,
+                    Err(_) => thread::sleep(Duration::from_millis(10)),
+                }
+            };
+            let identity = (path("client.pem"), path("client.key"));
+            let mut sender =
+                connect(client_config(&path("ca.pem"), Some((&identity.0, &identity.1))).unwrap());
+            sender.write_entry(&stream).unwrap();
+            sender.finish().unwrap();
+            let mut anonymous = connect(client_config(&path("ca.pem"), None).unwrap());
+            // The server rejects the handshake, which may only surface when
+            // the client writes.
+            let _ = anonymous
+                .write_entry(&stream)
+                .and_then(|_| anonymous.finish());
+        });
+
+        let mut out = vec![];
+        let mut reports = vec![];
+        listener.run(&mut out, |r| reports.push(r.clone())).unwrap();
+        clients.join().unwrap();
+        reports.sort_by_key(|r| r.id);
+        assert_eq!(reports[0].entries, 20);
+        assert_eq!(reports[0].error, None);
+        assert!(reports[1]
+            .error
+            .as_ref()
+            .is_some_and(|e| e.contains("TLS handshake failed")));
+    }
+
+    #[test]
+    fn flushes_on_shutdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("socket");
+        let shutdown = Shutdown::new();
+        // Entries would be held back for a minute without the shutdown.
+        let listener = Listener::new(vec![ListenAddr::Unix(path.clone())])
+            .with_reorder_window(Duration::from_secs(60))
+            .with_shutdown(shutdown.clone());
+        let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+        let client = thread::spawn(move || {
+            while !path.exists() {
+                thread::sleep(Duration::from_millis(10));
+            }
+            let mut conn = UnixStream::connect(&path).unwrap();
+            conn.write_all(&EntryGenerator::new(1).generate(20))
+                .unwrap();
+            thread::sleep(Duration::from_millis(200));
+            shutdown.request();
+            // The connection stays open until the listener returned.
+            let _ = done_rx.recv();
+        });
+
+        let mut out = vec![];
+        let mut reports = vec![];
+        listener.run(&mut out, |r| reports.push(r.clone())).unwrap();
+        drop(done_tx);
+        client.join().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].entries, 20);
+        assert_eq!(reports[0].error.as_deref(), Some(STOPPED));
+        assert_eq!(JournalExportRead::new(&out[..]).count(), 20);
+    }
+
+    #[test]
+    fn merges_concurrent_connections() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("socket");
+        let listener = Listener::new(vec![ListenAddr::Unix(path.clone())])
+            .with_reorder_window(Duration::from_millis(200))
+            .with_max_connections(3);
+        let clients = thread::spawn(move || {
+            while !path.exists() {
+                thread::sleep(Duration::from_millis(10));
+            }
+            let streams: Vec<_> = (0..3)
+                .map(|seed| EntryGenerator::new(seed).generate(100))
+                .collect();
+            let mut conns: Vec<_> = (0..3)
+                .map(|_| UnixStream::connect(&path).unwrap())
+                .collect();
+            // Interleave the writes so that all connections are open at once.
+            for chunk in 0..4 {
+                for (conn, stream) in conns.iter_mut().zip(streams.iter()) {
+                    let len = stream.len().div_ceil(4);
+                    let end = (len * (chunk + 1)).min(stream.len());
+                    conn.write_all(&stream[len * chunk..end]).unwrap();
+                }
+            }
+            conns[2].write_all(b"garbage\n").unwrap();
+        });
+
+        let mut out = vec![];
+        let mut reports = vec![];
+        listener.run(&mut out, |r| reports.push(r.clone())).unwrap();
+        clients.join().unwrap();
+
+        reports.sort_by_key(|r| r.id);
+        assert_eq!(reports.len(), 3);
+        assert!(reports.iter().all(|r| r.entries == 100));
+        assert_eq!(reports.iter().filter(|r| r.error.is_some()).count(), 1);
+        let mut jreader = JournalExportRead::new(&out[..]);
+        let mut timestamps = vec![];
+        while jreader.parse_next().unwrap().is_some() {
+            let e = jreader.get_entry();
+            assert!(e.get(b"LOGINUS_PEER").unwrap().starts_with(b"unix:"));
+            assert!(e.get(b"LOGINUS_CONNECTION").is_some());
+            timestamps.push(e.realtime_timestamp().unwrap());
+        }
+        assert_eq!(timestamps.len(), 300);
+        assert!(timestamps.windows(2).all(|w| w[0] <= w[1]));
+    }
+}
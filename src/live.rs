@@ -0,0 +1,357 @@
+//! Merge sources that are still being written.
+//!
+//! [LiveMerge] reads each of its sources on a thread of its own, e.g. a
+//! [Follow](crate::follow::Follow)ed file or a socket, and interleaves their
+//! entries by `__REALTIME_TIMESTAMP` as they arrive. An entry is released as
+//! soon as every source that has not ended has sent an entry at least as
+//! recent (the watermark), or once it was held back for the watermark delay.
+//! A source that is idle or lags behind thus delays the others by no more than
+//! the delay; entries that it sends later than that are released out of order.
+//!
+//! Like [MultiRead](crate::merge::MultiRead), a [LiveRead] is a stateful
+//! object: [LiveRead::parse_next] waits for the next entry, which
+//! [LiveRead::get_entry] returns. It ends once all sources ended or the
+//! [Shutdown] set with [LiveMerge::with_shutdown] is requested; then, all
+//! entries that are held back are released at once.
+
+use std::{
+    io::{self, Read},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    journald::{Entry, ErrorContext, JournalExportRead, OwnedEntry},
+    merge::{MergeReport, SourceError, SourceReport},
+    reorder::Reorder,
+    shutdown::Shutdown,
+    trace,
+};
+
+type Open = Box<dyn FnOnce() -> io::Result<Box<dyn Read>> + Send>;
+type ErrorHandler = Box<dyn FnMut(&SourceError)>;
+
+pub struct LiveMerge {
+    sources: Vec<(String, Open)>,
+    delay: Duration,
+    capacity: usize,
+    shutdown: Option<Shutdown>,
+}
+
+impl Default for LiveMerge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LiveMerge {
+    pub fn new() -> Self {
+        Self {
+            sources: vec![],
+            delay: Duration::from_secs(1),
+            capacity: 1024,
+            shutdown: None,
+        }
+    }
+
+    /// Adds a source called `name`, which `open` opens on the thread that
+    /// reads it.
+    pub fn with_source(
+        mut self,
+        name: impl Into<String>,
+        open: impl FnOnce() -> io::Result<Box<dyn Read>> + Send + 'static,
+    ) -> Self {
+        self.sources.push((name.into(), Box::new(open)));
+        self
+    }
+
+    /// How long an entry is held back at most to wait for earlier entries of
+    /// other sources; defaults to one second.
+    pub fn with_delay(self, delay: Duration) -> Self {
+        Self { delay, ..self }
+    }
+
+    /// Ends the merged stream once `shutdown` is requested.
+    pub fn with_shutdown(self, shutdown: Shutdown) -> Self {
+        Self {
+            shutdown: Some(shutdown),
+            ..self
+        }
+    }
+
+    /// Starts reading the sources.
+    pub fn start(self) -> LiveRead {
+        let (tx, rx) = mpsc::sync_channel(self.capacity);
+        if let Some(shutdown) = &self.shutdown {
+            let tx = tx.clone();
+            shutdown.on_request(move || {
+                // If the channel is full, the stream checks for the shutdown
+                // anyway before waiting again.
+                let _ = tx.try_send(Message::Stop);
+            });
+        }
+        let mut names = vec![];
+        for (index, (name, open)) in self.sources.into_iter().enumerate() {
+            let tx = tx.clone();
+            let thread_name = name.clone();
+            thread::spawn(move || read_source(index, thread_name, open, tx));
+            names.push(name);
+        }
+        let sources = names.len();
+        LiveRead {
+            rx,
+            reorder: Reorder::new(self.delay),
+            latest: vec![None; sources],
+            ended: vec![false; sources],
+            reports: vec![SourceReport::default(); sources],
+            names,
+            current: None,
+            shutdown: self.shutdown,
+            on_error: None,
+        }
+    }
+}
+
+enum Message {
+    Entry(usize, OwnedEntry, usize),
+    End(usize, Option<SourceError>),
+    Stop,
+}
+
+fn unopened(index: usize, name: &str, error: io::Error) -> SourceError {
+    SourceError {
+        index,
+        source_name: Some(name.to_string()),
+        context: ErrorContext {
+            offset: 0,
+            bytes: vec![],
+        },
+        error: error.into(),
+    }
+}
+
+fn read_source(index: usize, name: String, open: Open, tx: SyncSender<Message>) {
+    let read = match open() {
+        Ok(read) => read,
+        Err(e) => {
+            let _ = tx.send(Message::End(index, Some(unopened(index, &name, e))));
+            return;
+        }
+    };
+    let mut jreader = JournalExportRead::new(read);
+    let error = loop {
+        match jreader.parse_next() {
+            Ok(Some(())) => {
+                let entry = jreader.get_entry().to_owned();
+                if tx
+                    .send(Message::Entry(index, entry, jreader.bytes_read()))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            Ok(None) => break None,
+            Err(error) => {
+                break Some(SourceError {
+                    index,
+                    source_name: Some(name),
+                    context: jreader.error_context(),
+                    error,
+                })
+            }
+        }
+    };
+    trace::debug!(
+        source = index,
+        bytes = jreader.bytes_read(),
+        "live source ended"
+    );
+    let _ = tx.send(Message::End(index, error));
+}
+
+/// The merged stream of a [LiveMerge].
+pub struct LiveRead {
+    rx: Receiver<Message>,
+    reorder: Reorder<(usize, OwnedEntry)>,
+    /// The timestamp of the latest entry of each source.
+    latest: Vec<Option<u64>>,
+    ended: Vec<bool>,
+    reports: Vec<SourceReport>,
+    names: Vec<String>,
+    current: Option<(usize, OwnedEntry)>,
+    shutdown: Option<Shutdown>,
+    on_error: Option<ErrorHandler>,
+}
+
+impl LiveRead {
+    /// Continues with the other sources if one fails instead of returning
+    /// the error, like [MultiRead::with_skip_errors](crate::merge::MultiRead::with_skip_errors).
+    pub fn with_skip_errors(self, handler: impl FnMut(&SourceError) + 'static) -> Self {
+        Self {
+            on_error: Some(Box::new(handler)),
+            ..self
+        }
+    }
+
+    /// Waits for the next entry to be released.
+    pub fn parse_next(&mut self) -> Result<Option<()>, SourceError> {
+        loop {
+            let stopped = self.shutdown.as_ref().is_some_and(Shutdown::is_requested);
+            let released = if stopped || self.ended.iter().all(|&e| e) {
+                self.reorder.pop_any()
+            } else {
+                self.release(Instant::now())
+            };
+            if let Some(released) = released {
+                self.current = Some(released);
+                return Ok(Some(()));
+            }
+            if stopped || self.ended.iter().all(|&e| e) {
+                self.current = None;
+                return Ok(None);
+            }
+            let received = match self.reorder.deadline() {
+                Some(deadline) => self
+                    .rx
+                    .recv_timeout(deadline.saturating_duration_since(Instant::now())),
+                None => self.rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match received {
+                Ok(Message::Entry(i, entry, bytes)) => {
+                    let ts = entry.realtime_timestamp();
+                    let report = &mut self.reports[i];
+                    report.record(ts, self.latest[i]);
+                    report.bytes = bytes;
+                    if ts.is_some() {
+                        self.latest[i] = self.latest[i].max(ts);
+                    }
+                    self.reorder.push(ts, (i, entry), Instant::now());
+                }
+                Ok(Message::End(i, error)) => {
+                    self.ended[i] = true;
+                    if let Some(e) = error {
+                        match &mut self.on_error {
+                            Some(handler) => {
+                                self.reports[i].errors += 1;
+                                self.reports[i].error = Some(e.error.to_string());
+                                handler(&e);
+                            }
+                            None => return Err(e),
+                        }
+                    }
+                }
+                Ok(Message::Stop) | Err(RecvTimeoutError::Timeout) => {}
+                // All sources ended, which was reported before.
+                Err(RecvTimeoutError::Disconnected) => self.ended.fill(true),
+            }
+        }
+    }
+
+    /// Releases the next entry if its delay has passed or no source that has
+    /// not ended can still send an earlier one.
+    fn release(&mut self, now: Instant) -> Option<(usize, OwnedEntry)> {
+        let watermark = self
+            .latest
+            .iter()
+            .zip(self.ended.iter())
+            .filter(|(_, &ended)| !ended)
+            .map(|(&latest, _)| latest)
+            .min()
+            .flatten();
+        watermark
+            .and_then(|w| self.reorder.pop_until(w))
+            .or_else(|| self.reorder.pop(now))
+    }
+
+    /// Returns the entry that was released last.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the last call to [LiveRead::parse_next] did not yield an
+    /// entry.
+    pub fn get_entry(&self) -> &OwnedEntry {
+        &self.current.as_ref().expect("no current entry").1
+    }
+
+    /// The index of the source the current entry originates from.
+    pub fn source_index(&self) -> Option<usize> {
+        self.current.as_ref().map(|(i, _)| *i)
+    }
+
+    /// The names of the sources, in the order they were added.
+    pub fn source_names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Statistics about the entries received so far, including those that
+    /// are held back.
+    pub fn report(&self) -> MergeReport {
+        MergeReport {
+            entries: self.reports.iter().map(|r| r.entries).sum(),
+            sources: self.reports.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, Read},
+        sync::mpsc,
+        time::Duration,
+    };
+
+    use crate::{generate::EntryGenerator, journald::Entry};
+
+    use super::LiveMerge;
+
+    /// Reads the chunks sent to it, like a socket.
+    struct Chunks(mpsc::Receiver<Vec<u8>>, Vec<u8>);
+
+    impl Read for Chunks {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.1.is_empty() {
+                self.1 = self.0.recv().unwrap_or_default();
+            }
+            let n = buf.len().min(self.1.len());
+            buf[..n].copy_from_slice(&self.1[..n]);
+            self.1.drain(..n);
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn merges_by_watermark() {
+        let streams: Vec<_> = (0..2)
+            .map(|s| EntryGenerator::new(s).generate(50))
+            .collect();
+        let (tx, rx) = mpsc::channel();
+        let slow = Chunks(rx, vec![]);
+        let fast = streams[0].clone();
+        let mut merged = LiveMerge::new()
+            .with_delay(Duration::from_secs(60))
+            .with_source("fast", move || Ok(Box::new(io::Cursor::new(fast))))
+            .with_source("slow", move || Ok(Box::new(slow)))
+            .start();
+        tx.send(streams[1].clone()).unwrap();
+        drop(tx);
+
+        let mut timestamps = vec![];
+        while merged.parse_next().unwrap().is_some() {
+            timestamps.push(merged.get_entry().realtime_timestamp().unwrap());
+        }
+        assert_eq!(timestamps.len(), 100);
+        assert!(timestamps.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(merged.report().sources[1].entries, 50);
+    }
+
+    #[test]
+    fn reports_failed_sources() {
+        let mut merged = LiveMerge::new()
+            .with_source("broken", || Ok(Box::new(&b"MESSAGE\n"[..])))
+            .start();
+        let error = merged.parse_next().unwrap_err();
+        assert_eq!(error.name(), "broken");
+    }
+}
@@ -0,0 +1,444 @@
+//! Read the journal of the local system.
+//!
+//! journald's binary journal files cannot be parsed directly (see
+//! [crate::source]); [LocalJournal] instead runs `journalctl -o export` as a
+//! child process and reads its output, optionally following new entries as
+//! they are written. The child is terminated when the [JournalctlRead] is
+//! dropped.
+//!
+//! [JournalSend] submits entries to the local journald using its native
+//! protocol, e.g. to re-inject filtered or converted entries.
+
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{self, Read, Write},
+    os::unix::process::ExitStatusExt,
+    os::{
+        fd::{AsRawFd, FromRawFd},
+        unix::net::UnixDatagram,
+    },
+    path::{Path, PathBuf},
+    process::{Child, ChildStdout, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::{
+    config::JournalExportLimits,
+    journald::{write_field, Entry, JournalExportRead},
+    sink::EntrySink,
+};
+
+#[derive(Debug, Clone)]
+pub struct LocalJournal {
+    program: OsString,
+    follow: bool,
+    directory: Option<PathBuf>,
+    after_cursor: Option<String>,
+    units: Vec<String>,
+}
+
+impl Default for LocalJournal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LocalJournal {
+    pub fn new() -> Self {
+        Self {
+            program: "journalctl".into(),
+            follow: false,
+            directory: None,
+            after_cursor: None,
+            units: vec![],
+        }
+    }
+
+    /// Keeps reading new entries once all existing entries have been read.
+    pub fn with_follow(self, follow: bool) -> Self {
+        Self { follow, ..self }
+    }
+
+    /// Reads the journal files in `directory` instead of the system journal.
+    pub fn with_directory(self, directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: Some(directory.into()),
+            ..self
+        }
+    }
+
+    /// Starts reading after the entry with the `__CURSOR` `cursor`.
+    pub fn with_after_cursor(self, cursor: impl Into<String>) -> Self {
+        Self {
+            after_cursor: Some(cursor.into()),
+            ..self
+        }
+    }
+
+    /// Only reads the entries of `unit`; can be given multiple times.
+    pub fn with_unit(mut self, unit: impl Into<String>) -> Self {
+        self.units.push(unit.into());
+        self
+    }
+
+    /// Runs `program` instead of `journalctl`.
+    pub fn with_program(self, program: impl Into<OsString>) -> Self {
+        Self {
+            program: program.into(),
+            ..self
+        }
+    }
+
+    /// The arguments passed to `journalctl`.
+    pub fn args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec!["--output=export".into(), "--no-pager".into()];
+        if self.follow {
+            args.push("--follow".into());
+            // Without this, following starts with the last ten entries.
+            args.push("--lines=all".into());
+        }
+        if let Some(dir) = &self.directory {
+            args.push("--directory".into());
+            args.push(dir.into());
+        }
+        if let Some(cursor) = &self.after_cursor {
+            args.push(format!("--after-cursor={}", cursor).into());
+        }
+        for unit in self.units.iter() {
+            args.push(format!("--unit={}", unit).into());
+        }
+        args
+    }
+
+    /// Starts `journalctl`.
+    pub fn spawn(&self) -> io::Result<JournalctlRead> {
+        let mut child = Command::new(&self.program)
+            .args(self.args())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdout = child.stdout.take().unwrap();
+        Ok(JournalctlRead {
+            child: Arc::new(Mutex::new(Some(child))),
+            stdout,
+            stopped: Arc::default(),
+        })
+    }
+}
+
+/// The export stream written by a `journalctl` child process. Reading fails
+/// if `journalctl` exits unsuccessfully, unless it was stopped by
+/// [JournalctlStop::stop] or interrupted by SIGINT or SIGTERM.
+pub struct JournalctlRead {
+    /// `None` once the child was waited for.
+    child: Arc<Mutex<Option<Child>>>,
+    stdout: ChildStdout,
+    stopped: Arc<AtomicBool>,
+}
+
+impl JournalctlRead {
+    /// A handle that terminates `journalctl`, e.g. to end a followed stream
+    /// from another thread.
+    pub fn stopper(&self) -> JournalctlStop {
+        JournalctlStop {
+            child: Arc::clone(&self.child),
+            stopped: Arc::clone(&self.stopped),
+        }
+    }
+}
+
+impl Read for JournalctlRead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.stdout.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            let mut child = self.child.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(mut c) = child.take() {
+                let status = c.wait()?;
+                let interrupted = matches!(status.signal(), Some(libc::SIGINT | libc::SIGTERM));
+                if !status.success() && !interrupted && !self.stopped.load(Ordering::SeqCst) {
+                    return Err(io::Error::other(format!("journalctl failed: {}", status)));
+                }
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl Drop for JournalctlRead {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            // The child may have exited already; there is nothing to do about
+            // errors here.
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Terminates the `journalctl` of a [JournalctlRead], which then reaches the
+/// end of its stream.
+#[derive(Clone)]
+pub struct JournalctlStop {
+    child: Arc<Mutex<Option<Child>>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl JournalctlStop {
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        let child = self.child.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(child) = child.as_ref() {
+            // SAFETY: kill has no memory safety preconditions, and the child
+            // was not waited for, i.e. its pid was not reused.
+            unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+        }
+    }
+}
+
+pub const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Submits entries to journald. Each entry is sent as one datagram; entries
+/// exceeding the maximum datagram size are passed as a sealed memfd.
+///
+/// Address fields (`__CURSOR`, `__REALTIME_TIMESTAMP` etc.) and trusted fields
+/// (`_PID`, `_HOSTNAME` etc.) are not sent: journald assigns them to the
+/// submitted entry itself and ignores them in the input.
+pub struct JournalSend {
+    socket: UnixDatagram,
+    path: PathBuf,
+    buf: Vec<u8>,
+}
+
+impl JournalSend {
+    pub fn new() -> io::Result<Self> {
+        Self::with_socket(JOURNAL_SOCKET)
+    }
+
+    /// Submits entries to the socket at `path` instead of journald's.
+    pub fn with_socket(path: impl Into<PathBuf>) -> io::Result<Self> {
+        Ok(Self {
+            socket: UnixDatagram::unbound()?,
+            path: path.into(),
+            buf: vec![],
+        })
+    }
+
+    /// Submits `entry`. Entries without fields to send are skipped.
+    pub fn send(&mut self, entry: &impl Entry) -> io::Result<()> {
+        self.buf.clear();
+        for (name, value, typ) in entry.iter() {
+            if !name.starts_with(b"_") {
+                write_field(&mut self.buf, name, value, &typ);
+            }
+        }
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        match self.socket.send_to(&self.buf, &self.path) {
+            Ok(_) => Ok(()),
+            Err(e) if matches!(e.raw_os_error(), Some(libc::EMSGSIZE | libc::ENOBUFS)) => {
+                send_memfd(&self.socket, &self.path, &self.buf)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl EntrySink for JournalSend {
+    fn write_entry(&mut self, entry: &[u8]) -> io::Result<()> {
+        // The entry is complete, hence its size bounds all limits.
+        let limits = JournalExportLimits::bounded_by(entry.len());
+        let mut jreader = JournalExportRead::new_with_limits(limits, entry);
+        if jreader.parse_next()?.is_some() {
+            self.send(&jreader.get_entry())?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes `payload` to a sealed memfd and passes it to the socket at `path`,
+/// as journald expects for large entries.
+fn send_memfd(socket: &UnixDatagram, path: &Path, payload: &[u8]) -> io::Result<()> {
+    let fd = unsafe {
+        libc::memfd_create(
+            c"loginus".as_ptr(),
+            libc::MFD_ALLOW_SEALING | libc::MFD_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut memfd = unsafe { File::from_raw_fd(fd) };
+    memfd.write_all(payload)?;
+    let seals = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE | libc::F_SEAL_SEAL;
+    if unsafe { libc::fcntl(fd, libc::F_ADD_SEALS, seals) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let path = path.as_os_str().as_encoded_bytes();
+    let mut addr: libc::sockaddr_un = unsafe { std::mem::zeroed() };
+    if path.len() >= addr.sun_path.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "socket path too long",
+        ));
+    }
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    for (dst, src) in addr.sun_path.iter_mut().zip(path) {
+        *dst = *src as libc::c_char;
+    }
+    let space = unsafe { libc::CMSG_SPACE(std::mem::size_of::<libc::c_int>() as u32) } as usize;
+    let mut control = vec![0u8; space];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_name = &mut addr as *mut libc::sockaddr_un as *mut libc::c_void;
+    msg.msg_namelen = std::mem::size_of::<libc::sockaddr_un>() as libc::socklen_t;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = space as _;
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<libc::c_int>() as u32) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut libc::c_int, memfd.as_raw_fd());
+    }
+    if unsafe { libc::sendmsg(socket.as_raw_fd(), &msg, 0) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        io::{Read, Seek},
+        os::{
+            fd::{AsRawFd, FromRawFd},
+            unix::{fs::PermissionsExt, net::UnixDatagram},
+        },
+    };
+
+    use crate::{
+        generate::EntryGenerator,
+        journald::JournalExportRead,
+        sink::EntrySink,
+        testutil::{write_binary, write_string},
+    };
+
+    use super::{JournalSend, LocalJournal};
+
+    /// Receives a datagram that carries a file descriptor and returns the
+    /// file.
+    fn recv_fd(socket: &UnixDatagram) -> File {
+        let mut byte = [0u8; 1];
+        let mut iov = libc::iovec {
+            iov_base: byte.as_mut_ptr() as *mut libc::c_void,
+            iov_len: 1,
+        };
+        let mut control = [0u8; 64];
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = control.len() as _;
+        unsafe {
+            assert!(libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) >= 0);
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            assert_eq!((*cmsg).cmsg_type, libc::SCM_RIGHTS);
+            File::from_raw_fd(std::ptr::read_unaligned(
+                libc::CMSG_DATA(cmsg) as *const libc::c_int
+            ))
+        }
+    }
+
+    #[test]
+    fn sends_entries_to_journald_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("socket");
+        let socket = UnixDatagram::bind(&path).unwrap();
+        let mut sink = JournalSend::with_socket(&path).unwrap();
+
+        let mut entry = vec![];
+        write_string(&mut entry, "__CURSOR", "s=1");
+        write_string(&mut entry, "_PID", "1");
+        write_binary(&mut entry, "MESSAGE", "hello\nworld");
+        write_string(&mut entry, "PRIORITY", "6");
+        entry.push(b'\n');
+        sink.write_entry(&entry).unwrap();
+        let mut buf = vec![0; 1024];
+        let n = socket.recv(&mut buf).unwrap();
+        assert_eq!(
+            &buf[..n],
+            b"MESSAGE\n\x0b\0\0\0\0\0\0\0hello\nworld\nPRIORITY=6\n"
+        );
+
+        let mut large = vec![];
+        let message = "x".repeat(4 << 20);
+        write_string(&mut large, "MESSAGE", &message);
+        large.push(b'\n');
+        sink.write_entry(&large).unwrap();
+        let mut memfd = recv_fd(&socket);
+        memfd.rewind().unwrap();
+        let mut payload = vec![];
+        memfd.read_to_end(&mut payload).unwrap();
+        assert_eq!(payload, format!("MESSAGE={}\n", message).into_bytes());
+    }
+
+    #[test]
+    fn reads_output_of_journalctl() {
+        let dir = tempfile::tempdir().unwrap();
+        let stream = EntryGenerator::new(1).generate(50);
+        std::fs::write(dir.path().join("export"), &stream).unwrap();
+        let script = dir.path().join("journalctl");
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\n[ \"$3\" = --follow ] || exit 3\ncat {}\n",
+                dir.path().join("export").display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let journal = LocalJournal::new().with_program(&script);
+        let mut out = vec![];
+        let err = journal.spawn().unwrap().read_to_end(&mut out).unwrap_err();
+        assert!(err.to_string().contains("exit status: 3"));
+
+        let journal = journal.with_follow(true).with_unit("sshd.service");
+        let mut jreader = JournalExportRead::new(journal.spawn().unwrap());
+        let mut entries = 0;
+        while jreader.parse_next().unwrap().is_some() {
+            entries += 1;
+        }
+        assert_eq!(entries, 50);
+    }
+
+    #[test]
+    fn stops_following() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("journalctl");
+        std::fs::write(&script, "#!/bin/sh\nprintf 'A=1\\n\\n'\nexec sleep 60\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let read = LocalJournal::new()
+            .with_program(&script)
+            .with_follow(true)
+            .spawn()
+            .unwrap();
+        let stopper = read.stopper();
+        let mut jreader = JournalExportRead::new(read);
+        assert!(jreader.parse_next().unwrap().is_some());
+        let stop = std::thread::spawn(move || stopper.stop());
+        assert!(jreader.parse_next().unwrap().is_none());
+        stop.join().unwrap();
+    }
+}
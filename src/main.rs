@@ -1,201 +1,5504 @@
-use loginus::journald::{Entry, JournalExportRead, JournalExportReadError, RefEntry};
+use indicatif::{ProgressBar, ProgressStyle};
+#[cfg(feature = "expr")]
+use loginus::expr::{Map, Predicate};
+#[cfg(feature = "listen")]
+use loginus::listen::{ListenAddr, Listener};
+#[cfg(all(target_os = "linux", feature = "local"))]
+use loginus::local::{JournalSend, LocalJournal};
+#[cfg(feature = "tui")]
+use loginus::view;
+use loginus::{
+    alert::{AlertRules, Webhook},
+    auth::{AuthAnalyzer, AuthReport, UserEventKind},
+    boots::{Boot, BootList, BootSelector},
+    burst::{BurstDetector, KeyBurst},
+    catalog::{self, Catalog},
+    chunk,
+    config::JournalExportLimits,
+    coredump::Coredump,
+    dedup::{Dedup, DedupKey, DiskHashSet, KeySet, WindowSet},
+    diff::{field_changes, Difference, ExportDiff},
+    fields::{FieldCatalog, FieldInfo},
+    follow::Follow,
+    format::{
+        BinaryRendering, Column, EntryFormat, EntryFormatter, FormattingSink, TableLayout, Theme,
+        TimeRendering, Zone, PRIORITIES,
+    },
+    generate::{EntryGenerator, RateProfile},
+    group::{self, GroupBy, GroupStats},
+    inspect::{inspect, FileInfo},
+    join::Join,
+    journald::{
+        Entry, ErrorKind, JournalExportRead, JournalExportReadError, JournalExportReverseRead,
+        RefEntry,
+    },
+    kernel::{Incident, IncidentKind, KernelDetector},
+    live::LiveMerge,
+    merge::{MultiRead, Order, SourceError, SourceReport},
+    message_ids::{self, MessageId},
+    order::{OrderChecker, OrderViolation},
+    pipeline::{
+        CompressionConfig, OutputConfig, OverflowConfig, Pipeline, PipelineConfig,
+        TransformRegistry,
+    },
+    provenance::TaggedRead,
+    queue::{OverflowPolicy, QueueSink},
+    ratelimit::RateLimit,
+    reassemble::Reassemble,
+    rebase::Rebase,
+    redact::Redaction,
+    reservoir::Reservoir,
+    retention::{self, RetentionPolicy},
+    schema::{Schema, Violation},
+    secrets::{self, Finding, SecretScanner},
+    session::{Session, Sessions},
+    shutdown::{Checkpoint, Shutdown},
+    sink::{Compression, EntrySink, ResumePoint, RotatingExportWriter},
+    skew::ClockSkew,
+    sort::{ExternalSort, SortKey},
+    source,
+    timeline::{Timeline, UnitEvent},
+    transform::{FieldPattern, Filter, PerSource, Projection, Rewrite, Substitute, Substitution},
+    usage::{DiskUsage, UsageReport},
+    values::{ValueCounter, ValuesReport},
+};
+#[cfg(feature = "geoip")]
+use loginus::{geoip::GeoIp, transform::Transform};
+#[cfg(feature = "tls")]
+use loginus::{
+    spool::{Spool, SpoolSink},
+    tls::{self, TlsSender},
+};
 use rand::Rng;
+use regex::bytes::Regex;
+use serde::Serialize;
 use sha2::Digest;
 use std::{
-    fs::OpenOptions,
-    io::{self, Write},
-    path::PathBuf,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt::{self, Display},
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    iter, ops,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::Duration,
 };
 
-use clap::{Parser, Subcommand};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum, ValueHint};
+use clap_complete::Shell;
 
+/// Parse and manipulate log streams in the Journal Export Format of journald.
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, after_long_help = DEFAULTS_HELP)]
 struct Cli {
     #[command(subcommand)]
     command: Command,
+    /// Render a progress bar with throughput and ETA on stderr.
+    #[arg(long, global = true)]
+    progress: bool,
+    /// Format of results and summaries printed on stdout.
+    #[arg(long, global = true, value_enum, default_value_t = Output::Text)]
+    output: Output,
+    #[command(flatten)]
+    range: Range,
+    #[command(flatten)]
+    errors: Errors,
+}
+
+const DEFAULTS_HELP: &str = "\
+Defaults of options are read from ~/.config/loginus/config.toml, e.g. \
+`format = \"json\"` or, for one subcommand, `[cat]` followed by \
+`limit = 100`, and from environment variables such as LOGINUS_FORMAT=json.";
+
+/// Selects entries of the sources by time and position, before any other
+/// option applies.
+#[derive(Args, Default)]
+struct Range {
+    /// Only read entries received at or after TIME: `YYYY-MM-DD[ HH:MM[:SS]]`
+    /// in local time, RFC 3339 or `@SECONDS` since the epoch. Uncompressed
+    /// files are expected to be ordered by time and are searched for it
+    /// instead of being read from their start.
+    #[arg(long, global = true, value_name = "TIME", value_parser = parse_time)]
+    since: Option<u64>,
+    /// Only read the last N entries of the sources. Uncompressed files are
+    /// scanned backwards from their end instead of being read completely.
+    #[arg(long, global = true, value_name = "N")]
+    tail: Option<usize>,
+    /// Skip the first N entries (of the last ones with `--tail`).
+    #[arg(long, global = true, value_name = "N")]
+    skip: Option<usize>,
+    /// Stop after N entries (following the skipped ones).
+    #[arg(long, visible_alias = "head", global = true, value_name = "N")]
+    limit: Option<usize>,
+}
+
+/// What to do if one of several sources cannot be opened or parsed.
+#[derive(Args, Default)]
+struct Errors {
+    /// Stop at the first source that cannot be read; the default.
+    #[arg(long, global = true, overrides_with = "skip_errors")]
+    fail_fast: bool,
+    /// Warn about sources that cannot be opened or parsed, skip the rest of
+    /// them and read the others to their end. Exits with status 2 if a
+    /// source was skipped.
+    #[arg(long, global = true, overrides_with = "fail_fast")]
+    skip_errors: bool,
+}
+
+impl Errors {
+    fn skip(&self) -> bool {
+        self.skip_errors && !self.fail_fast
+    }
+}
+
+/// An input that could not be opened; reading it fails with the error.
+struct Unopened(Option<io::Error>);
+
+impl Read for Unopened {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        match self.0.take() {
+            Some(e) => Err(e),
+            None => Ok(0),
+        }
+    }
+}
+
+#[derive(Args)]
+struct Sources {
+    /// Journal export files, directories, glob patterns or `-` for stdin.
+    /// `journal:` reads the journal of the local system, optionally followed
+    /// by comma-separated options: `follow`, `unit=UNIT`, `dir=DIR` and
+    /// `after=CURSOR`, e.g. `journal:follow,unit=sshd.service`. Docker and
+    /// CRI container logs (`.log`) are converted, as are Windows event logs
+    /// (`.evtx`, `.xml`) in builds with the `evtx` feature.
+    #[arg(required = true, value_hint = ValueHint::AnyPath)]
+    srcs: Vec<PathBuf>,
+}
+
+impl Sources {
+    fn expand(self) -> io::Result<Vec<PathBuf>> {
+        expand_sources(self.srcs)
+    }
+}
+
+#[derive(Args)]
+struct Destination {
+    /// Output file, `-` for stdout, `journal:` to submit the entries to the
+    /// local journald or `tls://HOST:PORT` to send them to a `listen --tls-cert`
+    /// receiver. If output files are rotated, the prefix of their names.
+    #[arg(short, long, value_hint = ValueHint::FilePath)]
+    out: PathBuf,
+    /// CA that certifies the receiver of a `tls://` output.
+    #[cfg(feature = "tls")]
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    out_tls_ca: Option<PathBuf>,
+    /// Certificate chain to authenticate with at the receiver of a `tls://`
+    /// output.
+    #[cfg(feature = "tls")]
+    #[arg(long, requires = "out_tls_key", value_hint = ValueHint::FilePath)]
+    out_tls_cert: Option<PathBuf>,
+    /// Private key of `--out-tls-cert`.
+    #[cfg(feature = "tls")]
+    #[arg(long, requires = "out_tls_cert", value_hint = ValueHint::FilePath)]
+    out_tls_key: Option<PathBuf>,
+    /// Spool entries in this directory while a `tls://` output is
+    /// unreachable and send them once it is back, also in a later run.
+    #[cfg(feature = "tls")]
+    #[arg(long, value_hint = ValueHint::DirPath)]
+    spool: Option<PathBuf>,
+    /// Start a new output file before it exceeds this size (e.g. 100M).
+    #[arg(long, value_parser = parse_size)]
+    rotate_size: Option<u64>,
+    /// Start a new output file after this interval (e.g. 1h).
+    #[arg(long, value_parser = parse_duration)]
+    rotate_interval: Option<Duration>,
+    /// Compress output files once they are rotated.
+    #[arg(long, value_enum)]
+    rotate_compress: Option<Compress>,
+    /// Append to the output file instead of truncating it.
+    #[arg(long, conflicts_with_all = ["rotate_size", "rotate_interval", "rotate_compress"])]
+    append: bool,
+    /// Skip input entries that the output file already contains, such that
+    /// an interrupted run can be restarted without duplicates. A trailing
+    /// incomplete entry of the output file is removed.
+    #[arg(long, requires = "append")]
+    resume: bool,
+    /// Write the output on a separate thread, holding up to this many
+    /// entries in memory while the output is slower than the input.
+    #[arg(long)]
+    queue: Option<usize>,
+    /// What to do with entries while the queue is full.
+    #[arg(long, value_enum, default_value_t = Overflow::Block, requires = "queue")]
+    overflow: Overflow,
+    /// Directory of the temporary file of `--overflow spill`.
+    #[arg(long, requires = "queue", value_hint = ValueHint::DirPath)]
+    spill_dir: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct FieldSelection {
+    /// Only write the entries of this boot, given by its ID or an offset: `0`
+    /// is the last boot, `-1` the one before, and `1` the first boot.
+    #[arg(long, allow_hyphen_values = true)]
+    boot: Option<BootSelector>,
+    /// Only write entries with one of these MESSAGE_IDs, given in hex or by
+    /// name, e.g. `unit-failed,coredump`.
+    #[arg(long, value_delimiter = ',', value_parser = parse_message_id)]
+    message_id: Vec<MessageId>,
+    /// Only write entries of these units (`_SYSTEMD_UNIT`); `.service` is
+    /// appended to names without a suffix.
+    #[arg(short, long, value_delimiter = ',')]
+    unit: Vec<String>,
+    /// Only write entries of this priority or a more important one, given
+    /// as a number or name, e.g. `warning`.
+    #[arg(short, long, value_parser = parse_priority)]
+    priority: Option<u8>,
+    /// Only write entries for which this expression is true, e.g.
+    /// `PRIORITY <= 3 && MESSAGE =~ "oom"`. Can be given multiple times.
+    #[cfg(feature = "expr")]
+    #[arg(long = "where", value_parser = Predicate::new)]
+    predicates: Vec<Predicate>,
+    /// Assign fields using expressions, e.g. `LEVEL = PRIORITY + 0; _CMDLINE =
+    /// null`. Can be given multiple times.
+    #[cfg(feature = "expr")]
+    #[arg(long = "map", value_parser = Map::new)]
+    maps: Vec<Map>,
+    /// Only write these fields (and the address fields such as `__CURSOR`);
+    /// a trailing `*` matches all fields with that prefix.
+    #[arg(long, value_delimiter = ',')]
+    fields: Vec<String>,
+    /// Do not write these fields; a trailing `*` matches all fields with that
+    /// prefix.
+    #[arg(long, value_delimiter = ',', conflicts_with = "fields")]
+    drop_fields: Vec<String>,
+    /// Shift the times of the entries by OFFSET, e.g. `-90s`, or those of one
+    /// source with `SOURCE=OFFSET`. Rewrites `__REALTIME_TIMESTAMP`,
+    /// `_SOURCE_REALTIME_TIMESTAMP`, `SYSLOG_TIMESTAMP` and the time in
+    /// `__CURSOR`. Can be given multiple times.
+    #[arg(long, value_name = "[SOURCE=]OFFSET", value_parser = parse_shift, allow_hyphen_values = true)]
+    shift_time: Vec<(Option<PathBuf>, i64)>,
+    /// Shift the times of the entries such that the first one is at TIME,
+    /// e.g. `@0`, to hide when they were logged.
+    #[arg(long, value_name = "TIME", value_parser = parse_time, conflicts_with = "shift_time")]
+    rebase_to: Option<u64>,
+    /// Rename a field, e.g. `HOST=_HOSTNAME`; can be given multiple times.
+    #[arg(long, value_parser = parse_assignment)]
+    rename: Vec<(String, String)>,
+    /// Add a field to every entry, replacing fields of the same name, e.g.
+    /// `ENVIRONMENT=prod`. `{source}` in the value is replaced by the path of
+    /// the source. Can be given multiple times.
+    #[arg(long, value_parser = parse_assignment)]
+    inject: Vec<(String, String)>,
+    /// Add the fields of the events in this CSV or JSON file, e.g. deployments
+    /// or alerts, to the entries within their time range. Events have a
+    /// `start` (or `time`) and optionally an `end`, in microseconds or RFC
+    /// 3339; the other columns become fields, e.g. `deploy_id` adds
+    /// `DEPLOY_ID`. Can be given multiple times.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    join: Vec<PathBuf>,
+    /// How long events of `--join` without an end last; by default, until
+    /// the next such event of the file starts.
+    #[arg(long, value_parser = parse_duration, requires = "join")]
+    join_window: Option<Duration>,
+    /// Add the country and autonomous system of the IP addresses in messages
+    /// as `GEOIP_COUNTRY`, `GEOIP_ASN` and `GEOIP_ASN_ORG`, after the address
+    /// in `GEOIP_IP`, using this MaxMind database, e.g. GeoLite2-Country.mmdb.
+    /// Can be given multiple times, e.g. for a country and an ASN database.
+    #[cfg(feature = "geoip")]
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    geoip: Vec<PathBuf>,
+    /// Search this field for IP addresses instead of `MESSAGE`; a trailing
+    /// `*` matches all fields with that prefix. Can be given multiple times.
+    #[cfg(feature = "geoip")]
+    #[arg(long, requires = "geoip")]
+    geoip_field: Vec<String>,
+    /// Replace matches of a regular expression in the values of a field, e.g.
+    /// `MESSAGE=s/\x1b\[[0-9;]*m//`. The character following `s` delimits
+    /// the expression and the replacement, which may refer to capture groups
+    /// (`$1`). A trailing `*` in the field name matches all fields with that
+    /// prefix. Can be given multiple times.
+    #[arg(long, value_parser = parse_substitution)]
+    substitute: Vec<Substitution>,
+    /// Merge consecutive entries of one stream that form a multi-line
+    /// message, e.g. stack traces, into one entry.
+    #[arg(long)]
+    reassemble: bool,
+    /// The maximum time between two lines of a multi-line message.
+    #[arg(long, value_parser = parse_duration, default_value = "1s", requires = "reassemble")]
+    reassemble_window: Duration,
+    /// Treat messages matching this expression as continuation lines, in
+    /// addition to lines starting with whitespace, `Caused by:` or `...`.
+    #[arg(long, value_parser = Regex::new, requires = "reassemble")]
+    continuation: Option<Regex>,
+    /// Let through at most this many entries per interval, e.g. `1000/30s`,
+    /// and replace the others by a summary of how many were suppressed.
+    #[arg(long, value_parser = parse_rate)]
+    rate_limit: Option<(u64, Duration)>,
+    /// Apply `--rate-limit` to every value of this field separately, e.g.
+    /// `_SYSTEMD_UNIT`.
+    #[arg(long, requires = "rate_limit")]
+    rate_limit_key: Option<String>,
+}
+
+impl FieldSelection {
+    fn projection(&self) -> Option<Projection> {
+        let patterns = |names: &[String]| {
+            names
+                .iter()
+                .map(|n| FieldPattern::new(n.as_str()))
+                .collect()
+        };
+        if !self.fields.is_empty() {
+            Some(Projection::Keep(patterns(&self.fields)))
+        } else if !self.drop_fields.is_empty() {
+            Some(Projection::Drop(patterns(&self.drop_fields)))
+        } else {
+            None
+        }
+    }
+
+    /// Builds the transformations for the entries of `srcs`: entries are
+    /// selected by boot, MESSAGE_ID, unit and priority, selected and mapped
+    /// by expressions, fields are renamed, injected and joined from events,
+    /// multi-line messages are reassembled and rate limited, then values are
+    /// substituted and finally the projection applies.
+    fn pipeline(&self, srcs: &[PathBuf]) -> io::Result<Pipeline> {
+        let mut pipeline = Pipeline::new();
+        if let Some(boot) = &self.boot {
+            let id = match boot {
+                BootSelector::Id(id) => id.clone(),
+                // Offsets are relative to the boots of all sources.
+                BootSelector::Offset(_) => {
+                    if let Some(src) = srcs.iter().find(|s| !rereadable(s)) {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("--boot with an offset cannot read {} twice", src.display()),
+                        ));
+                    }
+                    let (boots, _) =
+                        list_boots(srcs.to_vec(), &Range::default(), &Errors::default(), false)?;
+                    match boot.resolve(&boots) {
+                        Some(b) => b.id.clone(),
+                        None => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::NotFound,
+                                "no such boot in the sources",
+                            ))
+                        }
+                    }
+                }
+            };
+            pipeline = pipeline.with_transform(Filter {
+                field: FieldPattern::new("_BOOT_ID"),
+                regex: Regex::new(&format!("^(?i){}$", regex::escape(&id))).unwrap(),
+                invert: false,
+            });
+        }
+        if !self.message_id.is_empty() {
+            let ids: Vec<_> = self.message_id.iter().map(MessageId::to_string).collect();
+            pipeline = pipeline.with_transform(Filter {
+                field: FieldPattern::new("MESSAGE_ID"),
+                regex: Regex::new(&format!("^(?i)(?:{})$", ids.join("|"))).unwrap(),
+                invert: false,
+            });
+        }
+        if !self.unit.is_empty() {
+            let units: Vec<_> = self
+                .unit
+                .iter()
+                .map(|u| match u.contains('.') {
+                    true => regex::escape(u),
+                    false => regex::escape(&format!("{}.service", u)),
+                })
+                .collect();
+            pipeline = pipeline.with_transform(Filter {
+                field: FieldPattern::new("_SYSTEMD_UNIT"),
+                regex: Regex::new(&format!("^(?:{})$", units.join("|"))).unwrap(),
+                invert: false,
+            });
+        }
+        if let Some(priority) = self.priority {
+            pipeline = pipeline.with_transform(Filter {
+                field: FieldPattern::new("PRIORITY"),
+                regex: Regex::new(&format!("^[0-{}]$", priority)).unwrap(),
+                invert: false,
+            });
+        }
+        #[cfg(feature = "expr")]
+        {
+            for p in self.predicates.iter() {
+                pipeline = pipeline.with_transform(p.clone());
+            }
+            for m in self.maps.iter() {
+                pipeline = pipeline.with_transform(m.clone());
+            }
+        }
+        if let Some(start) = self.rebase_to {
+            pipeline = pipeline.with_transform(Rebase::starting_at(start));
+        } else if !self.shift_time.is_empty() {
+            let all = self.shift_time.iter().filter(|(s, _)| s.is_none());
+            let mut rebase = Rebase::new(all.map(|(_, o)| *o).next_back().unwrap_or(0));
+            for (source, offset) in self.shift_time.iter() {
+                let Some(source) = source else {
+                    continue;
+                };
+                let i = srcs.iter().position(|s| s == source).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("--shift-time: {} is not a source", source.display()),
+                    )
+                })?;
+                rebase = rebase.with_source_offset(i, *offset);
+            }
+            pipeline = pipeline.with_transform(rebase);
+        }
+        if !self.rename.is_empty() || !self.inject.is_empty() {
+            // One rewrite per source, since injected values may refer to the
+            // source.
+            let rewrites = srcs
+                .iter()
+                .map(|src| {
+                    let mut rewrite = Rewrite::new();
+                    for (from, to) in self.rename.iter() {
+                        rewrite = rewrite.with_rename(from.as_str(), to.as_str());
+                    }
+                    for (name, value) in self.inject.iter() {
+                        let value = value.replace("{source}", &src.to_string_lossy());
+                        rewrite = rewrite.with_field(name.as_str(), value);
+                    }
+                    rewrite
+                })
+                .collect();
+            pipeline = pipeline.with_transform(PerSource(rewrites));
+        }
+        for path in self.join.iter() {
+            let join = Join::load(path)?;
+            pipeline = pipeline.with_transform(match self.join_window {
+                Some(window) => join.with_window(window),
+                None => join,
+            });
+        }
+        #[cfg(feature = "geoip")]
+        if !self.geoip.is_empty() {
+            let mut geoip = GeoIp::new();
+            for path in self.geoip.iter() {
+                let database = GeoIp::open(path).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}: {}", path.display(), e),
+                    )
+                })?;
+                geoip = geoip.with_database(database);
+            }
+            for field in self.geoip_field.iter() {
+                geoip = geoip.with_field(FieldPattern::new(field.as_str()));
+            }
+            pipeline = pipeline.with_transform(geoip);
+        }
+        if self.reassemble {
+            let r = Reassemble::new().with_window(self.reassemble_window.as_micros() as u64);
+            pipeline = pipeline.with_transform(match &self.continuation {
+                Some(c) => r.with_continuation(c.clone()),
+                None => r,
+            });
+        }
+        if let Some((burst, interval)) = self.rate_limit {
+            let limit = RateLimit::new(burst, interval.as_micros() as u64);
+            pipeline = pipeline.with_transform(match &self.rate_limit_key {
+                Some(key) => limit.with_key(key.as_str()),
+                None => limit,
+            });
+        }
+        if !self.substitute.is_empty() {
+            let substitute = self
+                .substitute
+                .iter()
+                .cloned()
+                .fold(Substitute::new(), Substitute::with);
+            pipeline = pipeline.with_transform(substitute);
+        }
+        if let Some(projection) = self.projection() {
+            pipeline = pipeline.with_transform(projection);
+        }
+        Ok(pipeline)
+    }
+}
+
+/// Parses `FIELD=s/REGEX/TEMPLATE/`.
+fn parse_substitution(s: &str) -> Result<Substitution, String> {
+    let invalid = || format!("expected FIELD=s/REGEX/TEMPLATE/: {}", s);
+    let (field, expr) = s.split_once('=').ok_or_else(invalid)?;
+    let expr = expr.strip_prefix('s').ok_or_else(invalid)?;
+    let delim = expr.chars().next().ok_or_else(invalid)?;
+    let parts: Vec<_> = expr[delim.len_utf8()..].split(delim).collect();
+    let [regex, template, ""] = parts[..] else {
+        return Err(invalid());
+    };
+    Ok(Substitution {
+        field: FieldPattern::new(field),
+        regex: Regex::new(regex).map_err(|e| e.to_string())?,
+        template: template.as_bytes().to_vec(),
+    })
+}
+
+/// Parses `NAME=VALUE`.
+fn parse_assignment(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() && !name.contains('\n') => {
+            Ok((name.to_string(), value.to_string()))
+        }
+        _ => Err(format!("expected NAME=VALUE: {}", s)),
+    }
+}
+
+impl Destination {
+    fn rotates(&self) -> bool {
+        self.rotate_size.is_some()
+            || self.rotate_interval.is_some()
+            || self.rotate_compress.is_some()
+    }
+
+    fn is_stdout(&self) -> bool {
+        !self.rotates() && is_stdio(&self.out)
+    }
+
+    /// Determines the entries already written to the output file if
+    /// `--resume` is given and truncates the file to its complete entries.
+    /// Must be called before [Destination::open].
+    fn resume_point(&self) -> io::Result<ResumePoint> {
+        if !self.resume {
+            return Ok(ResumePoint::default());
+        }
+        if is_stdio(&self.out) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot resume output to stdout",
+            ));
+        }
+        if !self.out.exists() {
+            return Ok(ResumePoint::default());
+        }
+        let point = ResumePoint::from_export(&self.out)?;
+        OpenOptions::new()
+            .write(true)
+            .open(&self.out)?
+            .set_len(point.valid_len)?;
+        Ok(point)
+    }
+
+    fn from_config(config: &OutputConfig) -> Self {
+        Self {
+            out: config.path.clone(),
+            rotate_size: config.rotate_size,
+            rotate_interval: config.rotate_interval.map(Duration::from_secs),
+            rotate_compress: config.compress.map(|c| match c {
+                CompressionConfig::Gz => Compress::Gz,
+                CompressionConfig::Zst => Compress::Zst,
+            }),
+            append: config.append,
+            resume: false,
+            queue: config.queue.as_ref().map(|q| q.capacity),
+            overflow: config
+                .queue
+                .as_ref()
+                .map_or(Overflow::Block, |q| match q.overflow {
+                    OverflowConfig::Block => Overflow::Block,
+                    OverflowConfig::DropOldest => Overflow::DropOldest,
+                    OverflowConfig::DropNewest => Overflow::DropNewest,
+                    OverflowConfig::Spill => Overflow::Spill,
+                }),
+            spill_dir: config.queue.as_ref().and_then(|q| q.spill_dir.clone()),
+            #[cfg(feature = "tls")]
+            out_tls_ca: None,
+            #[cfg(feature = "tls")]
+            out_tls_cert: None,
+            #[cfg(feature = "tls")]
+            out_tls_key: None,
+            #[cfg(feature = "tls")]
+            spool: None,
+        }
+    }
+
+    fn open(&self) -> io::Result<Box<dyn EntrySink>> {
+        let sink = self.open_sink()?;
+        let Some(capacity) = self.queue else {
+            return Ok(sink);
+        };
+        let policy = match self.overflow {
+            Overflow::Block => OverflowPolicy::Block,
+            Overflow::DropOldest => OverflowPolicy::DropOldest,
+            Overflow::DropNewest => OverflowPolicy::DropNewest,
+            Overflow::Spill => {
+                OverflowPolicy::Spill(self.spill_dir.clone().unwrap_or_else(std::env::temp_dir))
+            }
+        };
+        Ok(Box::new(ReportingQueue(QueueSink::new(
+            sink, capacity, policy,
+        ))))
+    }
+
+    fn open_sink(&self) -> io::Result<Box<dyn EntrySink + Send>> {
+        if !self.rotates() && self.out == Path::new("journal:") {
+            #[cfg(all(target_os = "linux", feature = "local"))]
+            return Ok(Box::new(JournalSend::new()?));
+            #[cfg(not(all(target_os = "linux", feature = "local")))]
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "writing to the local journal is not supported",
+            ));
+        }
+        if let Some(addr) = self.out.to_str().and_then(|o| o.strip_prefix("tls://")) {
+            #[cfg(feature = "tls")]
+            {
+                let ca = self.out_tls_ca.as_deref().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "tls:// requires --out-tls-ca")
+                })?;
+                let identity = self
+                    .out_tls_cert
+                    .as_deref()
+                    .zip(self.out_tls_key.as_deref());
+                let config = tls::client_config(ca, identity)?;
+                let Some(dir) = &self.spool else {
+                    return Ok(Box::new(TlsSender::connect(addr, config)?));
+                };
+                let addr = addr.to_string();
+                let sink = SpoolSink::new(Spool::open(dir)?, move || {
+                    Ok(Box::new(TlsSender::connect(
+                        &addr,
+                        std::sync::Arc::clone(&config),
+                    )?))
+                });
+                return Ok(Box::new(ReportingSpool(sink)));
+            }
+            #[cfg(not(feature = "tls"))]
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("sending over TLS is not supported: tls://{}", addr),
+            ));
+        }
+        if !self.rotates() {
+            return Ok(Box::new(create_sink(&self.out, self.append)?));
+        }
+        let mut writer = RotatingExportWriter::new(&self.out);
+        if let Some(size) = self.rotate_size {
+            writer = writer.with_max_size(size);
+        }
+        if let Some(interval) = self.rotate_interval {
+            writer = writer.with_max_age(interval);
+        }
+        if let Some(c) = self.rotate_compress {
+            writer = writer.with_compression(c.into());
+        }
+        Ok(Box::new(writer))
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Redact {
+    None,
+    Default,
+    Strict,
+}
+
+impl Redact {
+    fn redaction(self) -> Option<Redaction> {
+        match self {
+            Redact::None => None,
+            Redact::Default => Some(Redaction::Default),
+            Redact::Strict => Some(Redaction::Strict),
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Compress {
+    Gz,
+    Zst,
+}
+
+impl From<Compress> for Compression {
+    fn from(value: Compress) -> Self {
+        match value {
+            Compress::Gz => Compression::Gzip,
+            Compress::Zst => Compression::Zstd,
+        }
+    }
+}
+
+#[derive(Args)]
+struct Formatting {
+    #[arg(long, value_enum, default_value_t = Format::Short)]
+    format: Format,
+    /// How to print values that are not valid UTF-8.
+    #[arg(long, value_enum, default_value_t = Binary::HexEscape)]
+    binary: Binary,
+    /// Show times in UTC instead of local time.
+    #[arg(long, conflicts_with = "timezone")]
+    utc: bool,
+    /// Show times in a time zone, e.g. `Europe/Berlin` or `+02:00`. With
+    /// this, `--utc` or `--time-format`, JSON and logfmt get a field
+    /// `__TIME`.
+    #[arg(long, value_name = "TZ")]
+    timezone: Option<Zone>,
+    /// Show times in a strftime format, e.g. `%Y-%m-%d %H:%M:%S%.6f`.
+    #[arg(long, value_name = "FORMAT", value_parser = parse_time_format)]
+    time_format: Option<TimeRendering>,
+    /// The columns of `--format table`: time, host, unit, identifier, pid,
+    /// priority, message or the names of fields.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "time,unit,priority,message"
+    )]
+    columns: Vec<Column>,
+    /// Truncate table lines to this width; the width of the terminal by
+    /// default.
+    #[arg(long, value_name = "COLUMNS")]
+    max_width: Option<usize>,
+    /// Show long values of tables in full.
+    #[arg(long)]
+    no_truncate: bool,
+    /// Highlight entries by priority; `auto` does so on terminals unless
+    /// `NO_COLOR` is set.
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// The highlighting: `journalctl` or `mono`, followed by changes such as
+    /// `notice=blue,debug=` separated by commas.
+    #[arg(long, default_value = "journalctl")]
+    theme: Theme,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                console::Term::stdout().is_term()
+                    && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && std::env::var_os("TERM").is_none_or(|t| t != "dumb")
+            }
+        }
+    }
+}
+
+impl Formatting {
+    fn formatter(&self) -> EntryFormatter {
+        let mut table = TableLayout::new(self.columns.clone()).with_truncate(!self.no_truncate);
+        let terminal = console::Term::stdout();
+        let width = match terminal.is_term() {
+            true => terminal.size_checked().map(|(_, width)| width as usize),
+            false => None,
+        };
+        if let Some(width) = self.max_width.or(width) {
+            table = table.with_max_width(width);
+        }
+        let mut formatter = EntryFormatter::new(self.format.into())
+            .with_binary(self.binary.into())
+            .with_table(table);
+        if self.color.enabled() {
+            formatter = formatter.with_theme(self.theme.clone());
+        }
+        let zone = match self.utc {
+            true => Some(Zone::Utc),
+            false => self.timezone,
+        };
+        match (zone, self.time_format.clone()) {
+            (None, None) => formatter,
+            (zone, time) => {
+                formatter.with_time(time.unwrap_or_default().with_zone(zone.unwrap_or_default()))
+            }
+        }
+    }
+}
+
+fn parse_time_format(format: &str) -> Result<TimeRendering, String> {
+    TimeRendering::default().with_format(format)
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Export,
+    Json,
+    Logfmt,
+    /// Like `journalctl -o short`.
+    Short,
+    /// Aligned columns, see `--columns`.
+    Table,
+}
+
+impl From<Format> for EntryFormat {
+    fn from(value: Format) -> Self {
+        match value {
+            Format::Export => EntryFormat::Export,
+            Format::Json => EntryFormat::Json,
+            Format::Logfmt => EntryFormat::Logfmt,
+            Format::Short => EntryFormat::Short,
+            Format::Table => EntryFormat::Table,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Binary {
+    /// Base64; in JSON, an object `{"base64": "..."}`.
+    Base64,
+    /// Invalid bytes as `\xNN`, backslashes as `\\`.
+    HexEscape,
+    /// Replace invalid bytes by U+FFFD.
+    Lossy,
+    /// Leave out the field.
+    Skip,
+}
+
+impl From<Binary> for BinaryRendering {
+    fn from(value: Binary) -> Self {
+        match value {
+            Binary::Base64 => BinaryRendering::Base64,
+            Binary::HexEscape => BinaryRendering::HexEscape,
+            Binary::Lossy => BinaryRendering::Lossy,
+            Binary::Skip => BinaryRendering::Skip,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Overflow {
+    Block,
+    DropOldest,
+    DropNewest,
+    Spill,
+}
+
+/// Prints the counters of the queue once it is finished.
+struct ReportingQueue(QueueSink);
+
+impl EntrySink for ReportingQueue {
+    fn write_entry(&mut self, entry: &[u8]) -> io::Result<()> {
+        self.0.write_entry(entry)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let result = self.0.finish();
+        let metrics = self.0.metrics();
+        if metrics.dropped() > 0 || metrics.spilled() > 0 {
+            eprintln!(
+                "queue: {} entries written, {} dropped, {} spilled, at most {} queued",
+                metrics.written(),
+                metrics.dropped(),
+                metrics.spilled(),
+                metrics.max_depth()
+            );
+        }
+        result
+    }
+}
+
+/// Prints how many entries were spooled once the sink is finished.
+#[cfg(feature = "tls")]
+struct ReportingSpool(SpoolSink);
+
+#[cfg(feature = "tls")]
+impl EntrySink for ReportingSpool {
+    fn write_entry(&mut self, entry: &[u8]) -> io::Result<()> {
+        self.0.write_entry(entry)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let result = self.0.finish();
+        if self.0.spooled() > 0 || self.0.replayed() > 0 {
+            eprintln!(
+                "spool: {} entries spooled, {} replayed",
+                self.0.spooled(),
+                self.0.replayed()
+            );
+        }
+        if !self.0.spool().is_empty() {
+            eprintln!(
+                "spool: undelivered entries remain in {}{}",
+                self.0.spool().dir().display(),
+                self.0
+                    .last_error()
+                    .map(|e| format!(" ({})", e))
+                    .unwrap_or_default()
+            );
+        }
+        result
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Output {
+    Text,
+    Json,
 }
 
 #[derive(Subcommand)]
 enum Command {
     Merge {
-        #[arg(short, long)]
-        out: PathBuf,
-        srcs: Vec<PathBuf>,
+        /// Record how far every source was read in this file, and skip what
+        /// was read according to it, e.g. to continue after a shutdown.
+        #[arg(long, value_hint = ValueHint::FilePath)]
+        state: Option<PathBuf>,
+        /// Estimate how far the clocks of the sources are off from each other
+        /// using the boots they have in common, and interleave the entries
+        /// by corrected timestamps. The sources are read twice.
+        #[arg(long)]
+        clock_skew_adjust: bool,
+        /// Interleave the entries of SOURCE as if their timestamps were later
+        /// by OFFSET, e.g. `b.export=-90s`, instead of an estimate; can be
+        /// given multiple times.
+        #[arg(long, value_name = "SOURCE=OFFSET", value_parser = parse_clock_offset)]
+        clock_offset: Vec<(PathBuf, i64)>,
+        /// Keep merging entries as the sources are written, until SIGINT or
+        /// SIGTERM. Files are followed like with `tail --follow`, directories
+        /// by their newest file; `tcp:HOST:PORT` and `unix:PATH` connect to a
+        /// socket that sends an export stream.
+        #[arg(short, long, conflicts_with_all = ["state", "clock_skew_adjust", "clock_offset"])]
+        follow: bool,
+        /// With `--follow`, hold entries back at most this long to wait for
+        /// earlier entries of sources that lag behind.
+        #[arg(long, value_parser = parse_duration, default_value = "1s", requires = "follow")]
+        watermark_delay: Duration,
+        /// Do not tag entries with their source in LOGINUS_SOURCE and with
+        /// when they were read in LOGINUS_INGEST_TIMESTAMP.
+        #[arg(long)]
+        no_provenance: bool,
+        #[command(flatten)]
+        out: Destination,
+        #[command(flatten)]
+        fields: FieldSelection,
+        #[command(flatten)]
+        srcs: Sources,
     },
     Sample {
-        #[arg(short, long)]
-        sample_rate: f64,
-        #[arg(short, long)]
+        #[arg(short, long, required_unless_present = "count")]
+        sample_rate: Option<f64>,
+        /// Keep all entries of this priority or a more important one, given
+        /// as a number or name, e.g. `err`; only the others are sampled.
+        #[arg(long, value_parser = parse_priority)]
+        keep_priority: Option<u8>,
+        /// Write exactly this many entries, chosen uniformly from all selected
+        /// entries, in their original order. They are kept in memory.
+        #[arg(short = 'n', long, conflicts_with_all = ["sample_rate", "keep_priority"])]
+        count: Option<usize>,
+        #[command(flatten)]
+        out: Destination,
+        #[command(flatten)]
+        fields: FieldSelection,
+        /// Interleave the entries of all sources by timestamp.
+        #[arg(long)]
+        merge: bool,
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// Package the selected entries of the sources into an archive for a
+    /// support request: the redacted entries as `journal.export`, their
+    /// statistics as `stats.json` and a `manifest.json` describing the
+    /// sources, options and files.
+    Bundle {
+        /// The archive to write, ending in `.tar`, `.tar.gz` or `.tar.zst`.
+        #[arg(short, long, value_hint = ValueHint::FilePath)]
         out: PathBuf,
-        src: PathBuf,
+        /// How to remove personal data and secrets from the entries:
+        /// `default` masks e-mail and IP addresses, credentials, passwords and
+        /// tokens; `strict` also masks host names, machine IDs and command
+        /// lines.
+        #[arg(long, value_enum, default_value_t = Redact::Default)]
+        redact: Redact,
+        #[command(flatten)]
+        fields: FieldSelection,
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// Run the pipeline described by a TOML file.
+    Run {
+        #[arg(value_hint = ValueHint::FilePath)]
+        config: PathBuf,
+    },
+    /// Evaluate alert rules described by a TOML file and print the alerts as
+    /// JSON lines. Use `journal:follow` as source to watch the local journal.
+    Alert {
+        #[arg(long, value_hint = ValueHint::FilePath)]
+        rules: PathBuf,
+        /// Also post every alert to this URL, e.g. `http://localhost:8080/hook`.
+        #[arg(long, value_hint = ValueHint::Url)]
+        webhook: Option<String>,
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// Receive export streams over TCP or Unix sockets. Entries are tagged
+    /// with the fields LOGINUS_PEER, LOGINUS_CONNECTION, LOGINUS_SOURCE and
+    /// LOGINUS_INGEST_TIMESTAMP.
+    #[cfg(feature = "listen")]
+    Listen {
+        /// Accept TCP connections on this address, e.g. `0.0.0.0:19531`.
+        #[arg(long, required_unless_present = "unix")]
+        tcp: Vec<std::net::SocketAddr>,
+        /// Accept connections on a Unix socket at this path.
+        #[arg(long, value_hint = ValueHint::FilePath)]
+        unix: Vec<PathBuf>,
+        /// Hold entries back this long to order them across connections.
+        #[arg(long, value_parser = parse_duration, default_value = "1s")]
+        reorder_window: Duration,
+        /// Exit after this many connections were closed.
+        #[arg(long)]
+        max_connections: Option<u64>,
+        /// Close connections on which no complete entry arrives this long.
+        #[arg(long, value_parser = parse_duration)]
+        idle_timeout: Option<Duration>,
+        /// Accept only TLS on TCP sockets, presenting this certificate chain.
+        #[cfg(feature = "tls")]
+        #[arg(long, requires = "tls_key", value_hint = ValueHint::FilePath)]
+        tls_cert: Option<PathBuf>,
+        /// Private key of `--tls-cert`.
+        #[cfg(feature = "tls")]
+        #[arg(long, requires = "tls_cert", value_hint = ValueHint::FilePath)]
+        tls_key: Option<PathBuf>,
+        /// Require clients to present a certificate issued by this CA.
+        #[cfg(feature = "tls")]
+        #[arg(long, requires = "tls_cert", value_hint = ValueHint::FilePath)]
+        tls_client_ca: Option<PathBuf>,
+        #[command(flatten)]
+        out: Destination,
     },
     Split {
-        #[arg(short, long)]
+        #[arg(short, long, value_hint = ValueHint::DirPath)]
         out_dir: PathBuf,
-        src: PathBuf,
+        #[command(flatten)]
+        srcs: Sources,
     },
     Count {
+        /// Parse a single uncompressed file in N chunks on as many threads.
+        #[arg(short, long, value_name = "N", default_value_t = 1)]
+        jobs: usize,
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// List the boots of the sources with their time ranges and entry counts.
+    Boots {
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// List the units of the sources with their entry counts and error rates.
+    Units {
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// Report fields that likely contain credentials: values of `password=`
+    /// and similar keys, AWS access keys, JSON Web Tokens, private keys and
+    /// other random-looking strings. Exits with 1 if any are found.
+    ScanSecrets {
+        /// Also write the entries with the secrets found masked as
+        /// `<secret>` to FILE, `-` for stdout. The sources are read twice.
+        #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+        redact: Option<PathBuf>,
+        /// The entropy in bits per character from which strings of 20 or
+        /// more characters count as secrets.
+        #[arg(long, default_value_t = 4.0)]
+        min_entropy: f64,
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// Report how many bytes of the sources each unit and each field name
+    /// takes, to find what makes the journal grow.
+    Usage {
+        /// Attribute entries to the value of this field instead of
+        /// `_SYSTEMD_UNIT`, or `SYSLOG_IDENTIFIER` for entries without a unit.
+        #[arg(long)]
+        key: Option<String>,
+        /// The number of keys and fields to list, largest first.
+        #[arg(short = 'n', long, default_value_t = 20)]
+        top: usize,
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// List the field names of the sources with the number of entries and
+    /// values, their bytes and an example value, to see which fields
+    /// applications add.
+    Fields {
+        /// Order by name, or by entries or bytes, most first.
+        #[arg(long, value_enum, default_value_t = FieldOrder::Name)]
+        sort: FieldOrder,
+        /// Only list fields that systemd does not document.
+        #[arg(long)]
+        custom: bool,
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// Count the distinct values of a field, e.g. to list the hosts or units
+    /// of an archive.
+    Values {
+        field: String,
+        /// The number of values to list, most frequent first; all by default,
+        /// 20 with `--approx`.
+        #[arg(short = 'n', long)]
+        top: Option<usize>,
+        /// Count in bounded memory, for fields with very many values. Counts
+        /// may be too large by the reported error.
+        #[arg(long)]
+        approx: bool,
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// List the login sessions of the sources with their users, time ranges
+    /// and commands.
+    Sessions {
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// List the crashes recorded by systemd-coredump.
+    Coredumps {
+        /// Write the cores that are embedded in the entries or stored on this
+        /// machine to DIR.
+        #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+        extract: Option<PathBuf>,
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// List the OOM kills, segfaults and hardware errors logged by the
+    /// kernel.
+    Incidents {
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// List the times when a unit logged far more than usual, with the rate
+    /// and sample messages, to find what flooded the journal.
+    Bursts {
+        /// The number of bursts to list, those with the most entries above
+        /// the usual rate first.
+        #[arg(short = 'n', long, default_value_t = 10)]
+        top: usize,
+        /// Group entries by this field instead of `_SYSTEMD_UNIT`, or
+        /// `SYSLOG_IDENTIFIER` for entries without a unit.
+        #[arg(long)]
+        key: Option<String>,
+        /// Report rates this many times above the average rate of the key.
+        #[arg(long, default_value_t = 5.0)]
+        factor: f64,
+        /// How long a key may exceed the rate before it bursts; also the
+        /// maximum gap within a burst.
+        #[arg(long, value_parser = parse_duration, default_value = "10s")]
+        window: Duration,
+        /// Leave out bursts of fewer entries.
+        #[arg(long, default_value_t = 100)]
+        min_entries: u64,
+        #[command(flatten)]
+        fields: FieldSelection,
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// Summarize the logins, sudo commands and new users logged by sshd,
+    /// PAM, sudo and useradd: failed and successful logins per user and
+    /// address, and brute-force bursts of failures from one address.
+    AuthReport {
+        /// Report bursts of at least this many failed logins from one address.
+        #[arg(long, default_value_t = 10)]
+        burst_threshold: u64,
+        /// The maximum time between two failures of a burst.
+        #[arg(long, value_parser = parse_duration, default_value = "1m")]
+        burst_window: Duration,
+        #[command(flatten)]
+        fields: FieldSelection,
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// List the jobs and state changes of units in order, with the time
+    /// spent in each state.
+    Timeline {
+        /// Only list the events of this unit; `.service` is appended to names
+        /// without a suffix.
+        #[arg(short, long)]
+        unit: Option<String>,
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// Print entries as text, e.g. as JSON lines.
+    Cat {
+        /// Print the newest entries first: the sources are read from their
+        /// end, starting with the last one. They must be uncompressed files.
+        #[arg(short, long)]
+        reverse: bool,
+        #[command(flatten)]
+        formatting: Formatting,
+        #[command(flatten)]
+        fields: FieldSelection,
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// Print the last entries and, with `--follow`, new entries as they are
+    /// appended, like `journalctl -f`.
+    Tail {
+        /// The number of entries printed before following.
+        #[arg(short = 'n', long, default_value_t = 10)]
+        lines: usize,
+        /// Wait for new entries; the source must be a single uncompressed
+        /// export file or a directory, whose newest file is followed.
+        #[arg(short, long)]
+        follow: bool,
+        #[command(flatten)]
+        formatting: Formatting,
+        #[command(flatten)]
+        fields: FieldSelection,
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// Browse entries interactively, interleaving the sources by timestamp.
+    #[cfg(feature = "tui")]
+    View {
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// Print the number of entries, their total size and time range.
+    Stats {
+        /// Parse a single uncompressed file in N chunks on as many threads.
+        #[arg(short, long, value_name = "N", default_value_t = 1)]
+        jobs: usize,
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// Describe every source: its format, size, number of entries, time
+    /// range, hosts, boots and anomalies. Only the start and end of
+    /// uncompressed files are read.
+    Info {
+        /// Read all entries, to count them exactly and find all hosts and
+        /// boots, and time going backwards.
+        #[arg(long)]
+        scan: bool,
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// Check that a file is a well-formed journal export.
+    Verify {
+        #[arg(value_hint = ValueHint::FilePath)]
         src: PathBuf,
     },
     ShowEntry {
+        #[arg(value_hint = ValueHint::FilePath)]
+        src: PathBuf,
+        /// The position of the entry in the file, starting at 0.
+        #[arg(required_unless_present_any = ["cursor", "at"], conflicts_with_all = ["cursor", "at"])]
+        n: Option<usize>,
+        /// Show the entry with this `__CURSOR`.
+        #[arg(long, conflicts_with = "at")]
+        cursor: Option<String>,
+        /// Show the first entry received at or after TIME, given as for
+        /// `--since`.
+        #[arg(long, value_name = "TIME", value_parser = parse_time)]
+        at: Option<u64>,
+        /// Print the explanation of the message from systemd's message
+        /// catalog.
+        #[arg(short = 'x', long)]
+        explain: bool,
+        /// Load the catalog files in this directory instead of the catalog
+        /// of the local system; can be given multiple times.
+        #[arg(long, value_name = "DIR", requires = "explain", value_hint = ValueHint::DirPath)]
+        catalog: Vec<PathBuf>,
+        /// How to print values that are not valid UTF-8.
+        #[arg(long, value_enum, default_value_t = Binary::HexEscape)]
+        binary: Binary,
+    },
+    /// Sort entries; inputs larger than the memory limit are sorted using
+    /// temporary files.
+    Sort {
+        #[arg(short, long, value_enum, default_value_t = Key::Realtime)]
+        key: Key,
+        /// Spill sorted runs to disk once the buffered entries exceed this
+        /// size (e.g. 512M).
+        #[arg(short, long, value_parser = parse_size, default_value = "256M")]
+        memory: u64,
+        /// Directory for temporary files; defaults to the system's.
+        #[arg(long, value_hint = ValueHint::DirPath)]
+        tmp_dir: Option<PathBuf>,
+        #[command(flatten)]
+        out: Destination,
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// Remove duplicate entries.
+    Dedup {
+        /// Compare entries by cursor or by their complete content.
+        #[arg(short, long, value_enum, default_value_t = DedupBy::Cursor)]
+        key: DedupBy,
+        /// Only find duplicates within this many distinct preceding entries.
+        #[arg(short, long, value_parser = parse_count, default_value = "1M")]
+        window: usize,
+        /// Find all duplicates, using a hash set in a temporary file instead of
+        /// a window.
+        #[arg(long, conflicts_with = "window")]
+        exact: bool,
+        /// Directory for temporary files; defaults to the system's.
+        #[arg(long, requires = "exact", value_hint = ValueHint::DirPath)]
+        tmp_dir: Option<PathBuf>,
+        #[command(flatten)]
+        out: Destination,
+        #[arg(value_hint = ValueHint::FilePath)]
         src: PathBuf,
-        n: usize,
+    },
+    /// Report entries contained in only one of two exports. Both exports
+    /// must be ordered by timestamp. Exits with 1 if they differ.
+    Diff {
+        #[arg(value_hint = ValueHint::FilePath)]
+        left: PathBuf,
+        #[arg(value_hint = ValueHint::FilePath)]
+        right: PathBuf,
+        /// Report the fields that differ between matched entries.
+        #[arg(long)]
+        fields: bool,
+    },
+    /// Report entries whose timestamp moves backwards.
+    CheckOrder {
+        /// Ignore timestamps that move backwards by at most this much
+        /// (e.g. 500ms).
+        #[arg(short, long, value_parser = parse_duration, default_value = "0s")]
+        tolerance: Duration,
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// Check entries against a schema described by a TOML file, i.e. the
+    /// fields they must have and the types and values these may have.
+    Validate {
+        #[arg(long, value_hint = ValueHint::FilePath)]
+        schema: PathBuf,
+        #[command(flatten)]
+        srcs: Sources,
+    },
+    /// Remove the oldest export files of a directory until the retention
+    /// constraints are met.
+    Vacuum {
+        #[arg(short, long, value_hint = ValueHint::DirPath)]
+        dir: PathBuf,
+        /// Remove files whose entries are all older than this (e.g. 30d).
+        #[arg(short, long, value_parser = parse_duration)]
+        keep: Option<Duration>,
+        /// Remove the oldest files until the total size is at most this
+        /// (e.g. 50G).
+        #[arg(short, long, value_parser = parse_size)]
+        max_total: Option<u64>,
+        /// Only report which files would be removed.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write a synthetic journal export file.
+    Generate {
+        /// Number of entries; accepts the suffixes K, M and G.
+        #[arg(short, long, value_parser = parse_count, default_value = "1K")]
+        entries: usize,
+        /// Number of distinct units the entries are attributed to.
+        #[arg(short, long, default_value_t = 8)]
+        units: usize,
+        #[arg(short, long, value_enum, default_value_t = Rate::Steady)]
+        rate_profile: Rate,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        #[command(flatten)]
+        out: Destination,
+    },
+    /// Print a script that completes the arguments of loginus in SHELL.
+    Completions { shell: Shell },
+    /// Print the manual page in roff, e.g. for `man -l -`.
+    Man {
+        /// Write a page for every subcommand to this directory instead,
+        /// `loginus.1` and e.g. `loginus-cat.1`.
+        #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+        out_dir: Option<PathBuf>,
     },
 }
 
-fn main() -> io::Result<()> {
-    let cli = Cli::parse();
+#[derive(Clone, Copy, ValueEnum)]
+enum Key {
+    /// The realtime timestamp.
+    Realtime,
+    /// The boot ID and monotonic timestamp.
+    Monotonic,
+    /// The sequence number ID and sequence number.
+    Seqnum,
+}
 
-    match cli.command {
-        Command::Merge { out, srcs } => merge_journals(out, srcs)?,
-        Command::Sample {
-            sample_rate,
-            out,
-            src,
-        } => sample_journal(out, sample_rate, src)?,
-        Command::Split { out_dir, src } => split(out_dir, src)?,
-        Command::Count { src } => {
-            let c = count(src)?;
-            println!("{}", c);
+impl From<Key> for SortKey {
+    fn from(value: Key) -> Self {
+        match value {
+            Key::Realtime => SortKey::Realtime,
+            Key::Monotonic => SortKey::Monotonic,
+            Key::Seqnum => SortKey::Seqnum,
         }
-        Command::ShowEntry { src, n } => show_entry(src, n)?,
     }
-
-    Ok(())
 }
 
-fn merge_journals(out: PathBuf, srcs: Vec<PathBuf>) -> std::io::Result<()> {
-    let mut jreaders = vec![];
-    srcs.iter().try_for_each(|p| {
-        jreaders.push(JournalExportRead::new(
-            OpenOptions::new().read(true).open(p)?,
-        ));
-        Ok::<_, std::io::Error>(())
-    })?;
-    let mut outfile = OpenOptions::new().create(true).write(true).open(out)?;
+#[derive(Clone, Copy, ValueEnum)]
+enum DedupBy {
+    Cursor,
+    Content,
+}
 
-    let mut counts = vec![];
-    for idx in 0..jreaders.len() {
-        if let Ok(None) = jreaders[idx].parse_next() {
-            jreaders.remove(idx);
-        } else {
-            counts.push(0);
+impl From<DedupBy> for DedupKey {
+    fn from(value: DedupBy) -> Self {
+        match value {
+            DedupBy::Cursor => DedupKey::Cursor,
+            DedupBy::Content => DedupKey::Content,
         }
     }
-    println!("jreaders.len(): {}", jreaders.len());
-    while !jreaders.is_empty() {
-        let mut min_idx = 0;
-        let mut min_val = u64::MAX - 1;
-        for (idx, _) in jreaders.iter().enumerate() {
-            let val = get_time_stamp(jreaders[idx].get_entry());
-            if val < min_val {
-                min_val = val;
-                min_idx = idx;
-                counts[idx] += 1;
-            }
-        }
-        outfile.write_all(jreaders[min_idx].get_entry().as_bytes())?;
+}
 
-        match jreaders[min_idx].parse_next() {
-            Ok(None) => {
-                jreaders.remove(min_idx);
-                println!("count at {}: {}", min_idx, counts[min_idx]);
-                counts.remove(min_idx);
-            }
-            Err(JournalExportReadError::IoError(e)) => return Err(e),
-            Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e)),
-            Ok(_) => (),
+#[derive(Clone, Copy, ValueEnum)]
+enum Rate {
+    Steady,
+    Bursty,
+}
+
+impl From<Rate> for RateProfile {
+    fn from(value: Rate) -> Self {
+        match value {
+            Rate::Steady => RateProfile::Steady,
+            Rate::Bursty => RateProfile::Bursty,
         }
     }
-    outfile.flush()?;
-    Ok(())
 }
 
-fn sample_journal(dst: PathBuf, sample_rate: f64, src: PathBuf) -> io::Result<()> {
-    let mut jreader = JournalExportRead::new(OpenOptions::new().read(true).open(src)?);
-    let mut outfile = OpenOptions::new().create(true).write(true).open(dst)?;
+fn parse_count(s: &str) -> Result<usize, String> {
+    let (digits, factor) = match s.as_bytes().last() {
+        Some(b'K' | b'k') => (&s[..s.len() - 1], 1_000),
+        Some(b'M' | b'm') => (&s[..s.len() - 1], 1_000_000),
+        Some(b'G' | b'g') => (&s[..s.len() - 1], 1_000_000_000),
+        _ => (s, 1),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(factor))
+        .ok_or_else(|| format!("invalid count: {}", s))
+}
 
-    let mut rng = rand::thread_rng();
-    loop {
-        match jreader.parse_next() {
-            Ok(None) => return Ok(()),
-            Ok(_) => (),
-            Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e)),
-        }
+/// Parses a size in bytes; accepts the binary suffixes K, M, G and T.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let (digits, shift) = match s.as_bytes().last() {
+        Some(b'K' | b'k') => (&s[..s.len() - 1], 10),
+        Some(b'M' | b'm') => (&s[..s.len() - 1], 20),
+        Some(b'G' | b'g') => (&s[..s.len() - 1], 30),
+        Some(b'T' | b't') => (&s[..s.len() - 1], 40),
+        _ => (s, 0),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| format!("invalid size: {}", s))
+}
 
-        if rng.gen_bool(sample_rate) {
-            outfile.write_all(jreader.get_entry().as_bytes())?;
-        }
+/// Parses a MESSAGE_ID given in hex or by one of the names of
+/// [message_ids::NAMED].
+fn parse_message_id(s: &str) -> Result<MessageId, String> {
+    message_ids::lookup(s).map_err(|_| {
+        let names: Vec<_> = message_ids::NAMED.iter().map(|(n, _)| *n).collect();
+        format!(
+            "expected a 128-bit ID in hex or one of {}: {}",
+            names.join(", "),
+            s
+        )
+    })
+}
+
+/// Parses a syslog priority given as a number from 0 to 7 or by name.
+fn parse_priority(s: &str) -> Result<u8, String> {
+    match PRIORITIES.iter().position(|p| *p == s) {
+        Some(p) => Ok(p as u8),
+        None => s
+            .parse()
+            .ok()
+            .filter(|p| *p < 8)
+            .ok_or_else(|| format!("expected 0-7 or one of {}: {}", PRIORITIES.join(", "), s)),
     }
 }
 
-fn split(out_dir: PathBuf, src: PathBuf) -> io::Result<()> {
-    let mut jreader = JournalExportRead::new(OpenOptions::new().read(true).open(src)?);
+/// Parses a point in time for `--since` into microseconds since the epoch.
+fn parse_time(s: &str) -> Result<u64, String> {
+    let invalid = || {
+        format!(
+            "expected YYYY-MM-DD[ HH:MM[:SS]], RFC 3339 or @SECONDS: {}",
+            s
+        )
+    };
+    let time = if let Some(secs) = s.strip_prefix('@') {
+        let secs: i64 = secs.parse().map_err(|_| invalid())?;
+        chrono::DateTime::from_timestamp(secs, 0).ok_or_else(invalid)?
+    } else if let Ok(time) = chrono::DateTime::parse_from_rfc3339(s) {
+        time.to_utc()
+    } else {
+        let local = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+            .iter()
+            .find_map(|f| chrono::NaiveDateTime::parse_from_str(s, f).ok())
+            .or_else(|| {
+                let date = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?;
+                date.and_hms_opt(0, 0, 0)
+            })
+            .ok_or_else(invalid)?;
+        local
+            .and_local_timezone(chrono::Local)
+            .earliest()
+            .ok_or_else(invalid)?
+            .to_utc()
+    };
+    u64::try_from(time.timestamp_micros()).map_err(|_| invalid())
+}
 
-    loop {
-        match jreader.parse_next() {
-            Ok(None) => return Ok(()),
-            Ok(_) => (),
-            Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e)),
-        }
+/// Parses `N/DURATION`, e.g. `1000/30s`.
+fn parse_rate(s: &str) -> Result<(u64, Duration), String> {
+    let (n, interval) = s
+        .split_once('/')
+        .ok_or_else(|| format!("expected N/DURATION: {}", s))?;
+    let n = n
+        .parse()
+        .map_err(|e| format!("invalid count {}: {}", n, e))?;
+    Ok((n, parse_duration(interval)?))
+}
 
-        let e = jreader.get_entry();
-        let digest: [u8; 32] = sha2::Sha256::digest(e.as_bytes()).into();
-        let digest = digest.iter().fold(String::new(), |mut s, b| {
-            s.push_str(&format!("{:02x}", b));
-            s
-        });
-        let target = out_dir.join(&digest);
-        std::fs::write(target, e.as_bytes())?;
-    }
+/// Parses `SOURCE=OFFSET`, where OFFSET is a duration with an optional sign,
+/// into the source and the offset in microseconds.
+fn parse_clock_offset(s: &str) -> Result<(PathBuf, i64), String> {
+    let Some((source, offset)) = s.rsplit_once('=') else {
+        return Err(format!("expected SOURCE=OFFSET: {}", s));
+    };
+    Ok((PathBuf::from(source), parse_offset(offset)?))
 }
 
-fn get_time_stamp(entry: RefEntry<'_>) -> u64 {
-    for (name, content, _) in entry.iter() {
-        if name == b"__REALTIME_TIMESTAMP" {
-            return String::from_utf8_lossy(content)
-                .parse::<u64>()
-                .unwrap_or(u64::MAX);
-        }
+/// Parses `[SOURCE=]OFFSET` for `--shift-time`.
+fn parse_shift(s: &str) -> Result<(Option<PathBuf>, i64), String> {
+    match s.contains('=') {
+        true => parse_clock_offset(s).map(|(source, offset)| (Some(source), offset)),
+        false => Ok((None, parse_offset(s)?)),
     }
-    u64::MAX
 }
 
-fn count(src: PathBuf) -> io::Result<usize> {
-    let mut jreader = JournalExportRead::new(OpenOptions::new().read(true).open(src)?);
-
-    let mut count = 0;
-    loop {
-        match jreader.parse_next() {
-            Ok(None) => return Ok(count),
-            Ok(_) => (),
-            Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e)),
-        }
+/// Parses a duration with an optional sign into microseconds.
+fn parse_offset(s: &str) -> Result<i64, String> {
+    let (sign, duration) = match s.strip_prefix('-') {
+        Some(d) => (-1, d),
+        None => (1, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let micros = i64::try_from(parse_duration(duration)?.as_micros())
+        .map_err(|_| format!("invalid duration: {}", s))?;
+    Ok(sign * micros)
+}
 
-        count += 1;
+/// Parses a duration; accepts the suffixes ms, s, m, h and d.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    if let Some(ms) = s.strip_suffix("ms") {
+        return ms
+            .parse::<u64>()
+            .map(Duration::from_millis)
+            .map_err(|_| format!("invalid duration: {}", s));
     }
+    let (digits, factor) = match s.as_bytes().last() {
+        Some(b's') => (&s[..s.len() - 1], 1),
+        Some(b'm') => (&s[..s.len() - 1], 60),
+        Some(b'h') => (&s[..s.len() - 1], 60 * 60),
+        Some(b'd') => (&s[..s.len() - 1], 24 * 60 * 60),
+        _ => (s, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(factor))
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("invalid duration: {}", s))
 }
 
-fn show_entry(src: PathBuf, n: usize) -> io::Result<()> {
-    let mut jreader = JournalExportRead::new(OpenOptions::new().read(true).open(src)?);
+/// Defaults of options from the configuration file and `LOGINUS_*`
+/// environment variables; options on the command line take precedence.
+///
+/// The configuration file is `$LOGINUS_CONFIG` or `loginus/config.toml` in
+/// `$XDG_CONFIG_HOME` (`~/.config`). Its keys are the long names of options,
+/// either at the top level, where they apply to every subcommand with that
+/// option, or in a table named after a subcommand:
+///
+/// ```toml
+/// output = "json"
+/// limit = 1000
+///
+/// [cat]
+/// format = "json"
+/// fields = ["MESSAGE", "_PID"]
+/// ```
+///
+/// The environment variables are named after the options, e.g.
+/// `LOGINUS_FORMAT=json` or `LOGINUS_DROP_FIELDS=_CMDLINE,_EXE`, and take
+/// precedence over the configuration file.
+#[derive(Default)]
+struct Defaults {
+    options: BTreeMap<String, Vec<String>>,
+    commands: BTreeMap<String, BTreeMap<String, Vec<String>>>,
+    env: BTreeMap<String, Vec<String>>,
+}
 
-    let mut count = 0;
-    loop {
-        match jreader.parse_next() {
-            Ok(None) => return Ok(()),
-            Ok(_) => (),
-            Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e)),
+impl Defaults {
+    fn load() -> io::Result<Self> {
+        let mut defaults = Self::default();
+        let explicit = std::env::var_os("LOGINUS_CONFIG").map(PathBuf::from);
+        let path = explicit.clone().or_else(|| {
+            let config = std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| std::env::var_os("HOME").map(|h| Path::new(&h).join(".config")))?;
+            Some(config.join("loginus").join("config.toml"))
+        });
+        if let Some(path) = path {
+            match fs::read_to_string(&path) {
+                Ok(text) => defaults.parse(&text).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}: {}", path.display(), e),
+                    )
+                })?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound && explicit.is_none() => {}
+                Err(e) => {
+                    return Err(io::Error::new(
+                        e.kind(),
+                        format!("{}: {}", path.display(), e),
+                    ))
+                }
+            }
+        }
+        for (name, value) in std::env::vars() {
+            if let Some(option) = name.strip_prefix("LOGINUS_") {
+                if option != "CONFIG" {
+                    let option = option.to_lowercase().replace('_', "-");
+                    defaults.env.insert(option, vec![value]);
+                }
+            }
         }
+        Ok(defaults)
+    }
 
-        if count == n {
-            for (name, content, _) in jreader.get_entry().iter() {
-                let name = String::from_utf8_lossy(name);
-                let content = String::from_utf8_lossy(content);
-                println!("{}={}", name, content);
+    fn parse(&mut self, text: &str) -> Result<(), String> {
+        let table: toml::Table = text.parse().map_err(|e: toml::de::Error| e.to_string())?;
+        for (key, value) in table {
+            match value {
+                toml::Value::Table(options) => {
+                    let command = self.commands.entry(key).or_default();
+                    for (key, value) in options {
+                        command.insert(key.clone(), option_values(&key, value)?);
+                    }
+                }
+                value => {
+                    self.options
+                        .insert(key.clone(), option_values(&key, value)?);
+                }
             }
-            return Ok(());
         }
-        count += 1;
+        Ok(())
+    }
+
+    /// Sets the defaults as the default values of the options of `command`,
+    /// which must have been built.
+    fn apply(&self, command: clap::Command) -> Result<clap::Command, String> {
+        let mut known = BTreeSet::new();
+        let mut names = BTreeSet::new();
+        for command in iter::once(&command).chain(command.get_subcommands()) {
+            known.extend(command.get_arguments().filter_map(|a| a.get_long()));
+            names.insert(command.get_name());
+        }
+        let unknown_option = self
+            .options
+            .keys()
+            .chain(self.commands.values().flat_map(|o| o.keys()));
+        if let Some(option) = unknown_option
+            .into_iter()
+            .find(|o| !known.contains(o.as_str()))
+        {
+            return Err(format!("unknown option: {}", option));
+        }
+        if let Some(name) = self.commands.keys().find(|c| !names.contains(c.as_str())) {
+            return Err(format!("unknown command: {}", name));
+        }
+        let command = with_defaults(command, &[&self.options, &self.env]);
+        Ok(command.mut_subcommands(|command| {
+            let layers: Vec<_> = iter::once(&self.options)
+                .chain(self.commands.get(command.get_name()))
+                .chain(iter::once(&self.env))
+                .collect();
+            with_defaults(command, &layers)
+        }))
+    }
+}
+
+/// The values of the option `key` in the configuration file.
+fn option_values(key: &str, value: toml::Value) -> Result<Vec<String>, String> {
+    match value {
+        toml::Value::String(s) => Ok(vec![s]),
+        toml::Value::Array(values) => values
+            .into_iter()
+            .map(|v| match v {
+                toml::Value::Array(_) | toml::Value::Table(_) => {
+                    Err(format!("invalid value of {}: {}", key, v))
+                }
+                v => option_values(key, v).map(|mut v| v.remove(0)),
+            })
+            .collect(),
+        toml::Value::Table(_) => Err(format!("invalid value of {}: a table", key)),
+        v => Ok(vec![v.to_string()]),
+    }
+}
+
+/// Sets the default values of the options of `command` (but not those of
+/// its subcommands) from the last of `layers` that has one. The arguments
+/// keep their order, such that this works on a built command.
+fn with_defaults(
+    command: clap::Command,
+    layers: &[&BTreeMap<String, Vec<String>>],
+) -> clap::Command {
+    command.mut_args(|a| {
+        let Some(values) = a
+            .get_long()
+            .and_then(|long| layers.iter().rev().find_map(|l| l.get(long)))
+        else {
+            return a;
+        };
+        let values: Vec<_> = match (values.as_slice(), a.get_value_delimiter()) {
+            ([value], Some(delimiter)) => value.split(delimiter).map(String::from).collect(),
+            _ => values.clone(),
+        };
+        a.default_values(values)
+    })
+}
+
+fn main() -> ExitCode {
+    let mut command = Cli::command();
+    command.build();
+    let command = match Defaults::load().and_then(|d| {
+        d.apply(command)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let cli = Cli::from_arg_matches(&command.get_matches()).unwrap_or_else(|e| e.exit());
+    let output = cli.output;
+    match run(cli) {
+        Ok(0) => ExitCode::SUCCESS,
+        Ok(_) => ExitCode::from(2),
+        Err(e) => {
+            report_error(output, &e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Prints `e` on stderr. If a source failed to parse, the message is
+/// followed by the bytes around the position of the error.
+fn report_error(output: Output, e: &io::Error) {
+    let report = e
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<SourceError>())
+        .map(ErrorReport::new);
+    match output {
+        Output::Text => {
+            eprintln!("error: {}", e);
+            if let Some(report) = report {
+                eprint!("{}", report.hexdump);
+            }
+        }
+        Output::Json => {
+            let mut json = serde_json::json!({ "error": e.to_string() });
+            if let Some(report) = report {
+                json["position"] = serde_json::json!(report);
+            }
+            eprintln!("{}", json);
+        }
+    }
+}
+
+/// Describes where `reader`, which reads `path`, failed with `error`.
+fn located<R: Read>(
+    reader: &JournalExportRead<R>,
+    path: &Path,
+    error: JournalExportReadError,
+) -> SourceError {
+    SourceError {
+        index: 0,
+        source_name: Some(path.display().to_string()),
+        context: reader.error_context(),
+        error,
+    }
+}
+
+/// Runs the command of `cli` and returns the number of sources that were
+/// skipped because of `--skip-errors`.
+fn run(cli: Cli) -> io::Result<usize> {
+    let mut skipped_sources = 0;
+    match cli.command {
+        Command::Merge {
+            follow: true,
+            watermark_delay,
+            no_provenance,
+            out,
+            fields,
+            srcs,
+            ..
+        } => {
+            shutdown().install()?;
+            let to_stderr = out.is_stdout();
+            let pipeline = fields.pipeline(&srcs.srcs)?;
+            let summary = merge_live(
+                out,
+                pipeline,
+                srcs.srcs,
+                &cli.errors,
+                watermark_delay,
+                !no_provenance,
+            )?;
+            print_summary(cli.output, &summary, to_stderr)?;
+            skipped_sources = skipped(summary.sources.iter().map(|s| &s.report));
+        }
+        Command::Merge {
+            state,
+            clock_skew_adjust,
+            clock_offset,
+            no_provenance,
+            out,
+            fields,
+            srcs,
+            ..
+        } => {
+            shutdown().install()?;
+            let to_stderr = out.is_stdout();
+            let srcs = srcs.expand()?;
+            let pipeline = fields.pipeline(&srcs)?;
+            let state = SourceState::load(state, &srcs)?;
+            let offsets = clock_offsets(&srcs, &cli.errors, clock_skew_adjust, clock_offset)?;
+            let summary = merge_journals(
+                out,
+                pipeline,
+                srcs,
+                &cli.range,
+                &cli.errors,
+                state,
+                offsets,
+                !no_provenance,
+                cli.progress,
+            )?;
+            print_summary(cli.output, &summary, to_stderr)?;
+            skipped_sources = skipped(summary.sources.iter().map(|s| &s.report));
+        }
+        Command::Sample {
+            sample_rate,
+            keep_priority,
+            count,
+            out,
+            fields,
+            merge,
+            srcs,
+        } => {
+            let srcs = srcs.expand()?;
+            let pipeline = fields.pipeline(&srcs)?;
+            let sampling = match (count, sample_rate) {
+                (Some(n), _) => Sampling::Count(n),
+                (None, rate) => Sampling::Rate {
+                    rate: rate.unwrap_or(1.0),
+                    keep_priority,
+                },
+            };
+            skipped_sources = sample_journal(
+                out,
+                pipeline,
+                sampling,
+                srcs,
+                &cli.range,
+                &cli.errors,
+                merge,
+                cli.progress,
+            )?;
+        }
+        Command::Bundle {
+            out,
+            redact,
+            fields,
+            srcs,
+        } => {
+            shutdown().install()?;
+            let srcs = srcs.expand()?;
+            let mut pipeline = fields.pipeline(&srcs)?;
+            if let Some(redaction) = redact.redaction() {
+                pipeline = pipeline.with_transform(redaction.substitute());
+            }
+            let summary = bundle(
+                &out,
+                redact,
+                pipeline,
+                srcs,
+                &cli.range,
+                &cli.errors,
+                cli.progress,
+            )?;
+            print_summary(cli.output, &summary, false)?;
+            skipped_sources = skipped(summary.sources.iter().map(|s| &s.report));
+        }
+        Command::Run { config } => {
+            shutdown().install()?;
+            let config = PipelineConfig::from_toml(&std::fs::read_to_string(config)?)?;
+            let out = Destination::from_config(&config.output);
+            let to_stderr = out.is_stdout();
+            let (summary, n) = run_pipeline(&config, out, cli.progress, &cli.range, &cli.errors)?;
+            print_summary(cli.output, &summary, to_stderr)?;
+            skipped_sources = n;
+        }
+        Command::Alert {
+            rules,
+            webhook,
+            srcs,
+        } => {
+            shutdown().install()?;
+            let rules = AlertRules::from_toml(&std::fs::read_to_string(rules)?)?;
+            let webhook = webhook.as_deref().map(Webhook::new).transpose()?;
+            let (summary, n) = alert(rules, webhook, srcs.expand()?, &cli.range, &cli.errors)?;
+            print_summary(cli.output, &summary, true)?;
+            skipped_sources = n;
+        }
+        #[cfg(feature = "listen")]
+        Command::Listen {
+            tcp,
+            unix,
+            reorder_window,
+            max_connections,
+            idle_timeout,
+            #[cfg(feature = "tls")]
+            tls_cert,
+            #[cfg(feature = "tls")]
+            tls_key,
+            #[cfg(feature = "tls")]
+            tls_client_ca,
+            out,
+        } => {
+            let addrs = tcp
+                .into_iter()
+                .map(ListenAddr::Tcp)
+                .chain(unix.into_iter().map(ListenAddr::Unix))
+                .collect();
+            shutdown().install()?;
+            let mut listener = Listener::new(addrs)
+                .with_reorder_window(reorder_window)
+                .with_shutdown(shutdown().clone());
+            if let Some(n) = max_connections {
+                listener = listener.with_max_connections(n);
+            }
+            if let Some(timeout) = idle_timeout {
+                listener = listener.with_idle_timeout(timeout);
+            }
+            #[cfg(feature = "tls")]
+            if let Some((cert, key)) = tls_cert.zip(tls_key) {
+                listener =
+                    listener.with_tls(tls::server_config(&cert, &key, tls_client_ca.as_deref())?);
+            }
+            let mut outfile = out.open()?;
+            listener.run(&mut *outfile, |r| match &r.error {
+                Some(e) => eprintln!(
+                    "connection {} from {} failed after {} entries: {}",
+                    r.id, r.peer, r.entries, e
+                ),
+                None => eprintln!(
+                    "connection {} from {} closed after {} entries",
+                    r.id, r.peer, r.entries
+                ),
+            })?;
+            outfile.finish()?;
+        }
+        Command::Split { out_dir, srcs } => {
+            skipped_sources = split(out_dir, srcs.expand()?, &cli.range, &cli.errors)?;
+        }
+        Command::Count { jobs, srcs } => {
+            let summary = count(srcs.expand()?, &cli.range, &cli.errors, jobs, cli.progress)?;
+            print_summary(cli.output, &summary, false)?;
+            skipped_sources = summary.sources.iter().filter(|s| s.errors > 0).count();
+        }
+        Command::Boots { srcs } => {
+            let (boots, n) = list_boots(srcs.expand()?, &cli.range, &cli.errors, cli.progress)?;
+            print_summary(cli.output, &BootsSummary { boots }, false)?;
+            skipped_sources = n;
+        }
+        Command::Units { srcs } => {
+            let (summary, n) = units(srcs.expand()?, &cli.range, &cli.errors, cli.progress)?;
+            print_summary(cli.output, &summary, false)?;
+            skipped_sources = n;
+        }
+        Command::ScanSecrets {
+            redact,
+            min_entropy,
+            srcs,
+        } => {
+            let to_stderr = redact.as_deref().is_some_and(is_stdio);
+            let scanner = SecretScanner::new().with_min_entropy(min_entropy);
+            let (summary, n) = scan_secrets(
+                scanner,
+                redact.as_deref(),
+                srcs.expand()?,
+                &cli.range,
+                &cli.errors,
+                cli.progress,
+            )?;
+            print_summary(cli.output, &summary, to_stderr)?;
+            skipped_sources = n;
+            if !summary.findings.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Command::Usage { key, top, srcs } => {
+            let usage = match key {
+                Some(key) => DiskUsage::by(&key),
+                None => DiskUsage::new(),
+            };
+            let (summary, n) = usage_report(
+                usage,
+                top,
+                srcs.expand()?,
+                &cli.range,
+                &cli.errors,
+                cli.progress,
+            )?;
+            print_summary(cli.output, &summary, false)?;
+            skipped_sources = n;
+        }
+        Command::Fields { sort, custom, srcs } => {
+            let (summary, n) = fields(
+                sort,
+                custom,
+                srcs.expand()?,
+                &cli.range,
+                &cli.errors,
+                cli.progress,
+            )?;
+            print_summary(cli.output, &summary, false)?;
+            skipped_sources = n;
+        }
+        Command::Values {
+            field,
+            top,
+            approx,
+            srcs,
+        } => {
+            let mut counter = ValueCounter::new(field);
+            let top = match (top, approx) {
+                (top, false) => top.unwrap_or(usize::MAX),
+                (top, true) => {
+                    let top = top.unwrap_or(20);
+                    counter = counter.with_capacity(top.saturating_mul(50).max(1000));
+                    top
+                }
+            };
+            let (summary, n) = values(
+                counter,
+                top,
+                srcs.expand()?,
+                &cli.range,
+                &cli.errors,
+                cli.progress,
+            )?;
+            print_summary(cli.output, &summary, false)?;
+            skipped_sources = n;
+        }
+        Command::Sessions { srcs } => {
+            let (summary, n) = sessions(srcs.expand()?, &cli.range, &cli.errors, cli.progress)?;
+            print_summary(cli.output, &summary, false)?;
+            skipped_sources = n;
+        }
+        Command::Coredumps { extract, srcs } => {
+            let (summary, n) = coredumps(
+                srcs.expand()?,
+                &cli.range,
+                &cli.errors,
+                extract.as_deref(),
+                cli.progress,
+            )?;
+            print_summary(cli.output, &summary, false)?;
+            skipped_sources = n;
+        }
+        Command::Incidents { srcs } => {
+            let (summary, n) = incidents(srcs.expand()?, &cli.range, &cli.errors, cli.progress)?;
+            print_summary(cli.output, &summary, false)?;
+            skipped_sources = n;
+        }
+        Command::Bursts {
+            top,
+            key,
+            factor,
+            window,
+            min_entries,
+            fields,
+            srcs,
+        } => {
+            let srcs = srcs.expand()?;
+            let pipeline = fields.pipeline(&srcs)?;
+            let mut detector = BurstDetector::new()
+                .with_factor(factor)
+                .with_window(window)
+                .with_min_entries(min_entries);
+            if let Some(key) = key {
+                detector = detector.with_key(key);
+            }
+            let (summary, n) = bursts(
+                detector,
+                top,
+                pipeline,
+                srcs,
+                &cli.range,
+                &cli.errors,
+                cli.progress,
+            )?;
+            print_summary(cli.output, &summary, false)?;
+            skipped_sources = n;
+        }
+        Command::AuthReport {
+            burst_threshold,
+            burst_window,
+            fields,
+            srcs,
+        } => {
+            let srcs = srcs.expand()?;
+            let pipeline = fields.pipeline(&srcs)?;
+            let analyzer = AuthAnalyzer::new().with_burst(burst_threshold, burst_window);
+            let (summary, n) = auth_report(
+                analyzer,
+                pipeline,
+                srcs,
+                &cli.range,
+                &cli.errors,
+                cli.progress,
+            )?;
+            print_summary(cli.output, &summary, false)?;
+            skipped_sources = n;
+        }
+        Command::Timeline { unit, srcs } => {
+            let (summary, n) =
+                timeline(srcs.expand()?, &cli.range, &cli.errors, unit, cli.progress)?;
+            print_summary(cli.output, &summary, false)?;
+            skipped_sources = n;
+        }
+        Command::Cat {
+            reverse,
+            formatting,
+            fields,
+            srcs,
+        } => {
+            let srcs = srcs.expand()?;
+            let pipeline = fields.pipeline(&srcs)?;
+            match reverse {
+                true => cat_reverse(formatting.formatter(), pipeline, srcs, &cli.range)?,
+                false => {
+                    skipped_sources = cat(
+                        formatting.formatter(),
+                        pipeline,
+                        srcs,
+                        &cli.range,
+                        &cli.errors,
+                        cli.progress,
+                    )?
+                }
+            }
+        }
+        Command::Tail {
+            lines,
+            follow,
+            formatting,
+            fields,
+            srcs,
+        } => {
+            let srcs = match follow {
+                true if srcs.srcs.len() != 1 => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "--follow requires a single file or directory",
+                    ))
+                }
+                true => srcs.srcs,
+                false => srcs.expand()?,
+            };
+            let pipeline = fields.pipeline(&srcs)?;
+            skipped_sources = tail(
+                formatting.formatter(),
+                pipeline,
+                srcs,
+                &cli.range,
+                &cli.errors,
+                lines,
+                follow,
+            )?;
+        }
+        #[cfg(feature = "tui")]
+        Command::View { srcs } => {
+            let mut reader = open_sources(&srcs.expand()?, true, &cli.range, &cli.errors)?;
+            view::run(&mut reader)?;
+            skipped_sources = skipped(&reader.report().sources);
+        }
+        Command::Stats { jobs, srcs } => {
+            let (summary, n) = stats(srcs.expand()?, &cli.range, &cli.errors, jobs, cli.progress)?;
+            print_summary(cli.output, &summary, false)?;
+            skipped_sources = n;
+        }
+        Command::Info { scan, srcs } => {
+            let summary = info(srcs.expand()?, scan)?;
+            print_summary(cli.output, &summary, false)?;
+        }
+        Command::Verify { src } => {
+            let summary = verify(src, cli.progress)?;
+            print_summary(cli.output, &summary, false)?;
+            if summary.error.is_some() {
+                std::process::exit(1);
+            }
+        }
+        Command::ShowEntry {
+            src,
+            n,
+            cursor,
+            at,
+            explain,
+            catalog,
+            binary,
+        } => {
+            let which = match (n, cursor, at) {
+                (Some(n), _, _) => EntryAt::Index(n),
+                (_, Some(cursor), _) => EntryAt::Cursor(cursor),
+                (_, _, Some(ts)) => EntryAt::Time(ts),
+                (None, None, None) => unreachable!("required by clap"),
+            };
+            let catalog = explain.then(|| load_catalog(&catalog)).transpose()?;
+            show_entry(src, which, catalog.as_ref(), binary.into())?
+        }
+        Command::Sort {
+            key,
+            memory,
+            tmp_dir,
+            out,
+            srcs,
+        } => {
+            let mut sorter = ExternalSort::new(key.into()).with_run_size(memory as usize);
+            if let Some(dir) = tmp_dir {
+                sorter = sorter.with_tmp_dir(dir);
+            }
+            skipped_sources = sort(
+                sorter,
+                out,
+                srcs.expand()?,
+                &cli.range,
+                &cli.errors,
+                cli.progress,
+            )?;
+        }
+        Command::Dedup {
+            key,
+            window,
+            exact,
+            tmp_dir,
+            out,
+            src,
+        } => {
+            let to_stderr = out.is_stdout();
+            let summary = if exact {
+                let tmp_dir = tmp_dir.unwrap_or_else(std::env::temp_dir);
+                let dedup = Dedup::new(key.into(), DiskHashSet::new(&tmp_dir)?);
+                dedup_journal(dedup, out, src, cli.progress)?
+            } else {
+                let dedup = Dedup::new(key.into(), WindowSet::new(window.max(1)));
+                dedup_journal(dedup, out, src, cli.progress)?
+            };
+            print_summary(cli.output, &summary, to_stderr)?;
+        }
+        Command::Diff {
+            left,
+            right,
+            fields,
+        } => {
+            let summary = diff(left, right, fields)?;
+            print_summary(cli.output, &summary, false)?;
+            if !summary.differences.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Command::CheckOrder { tolerance, srcs } => {
+            let summary = check_order(srcs.expand()?, tolerance, cli.progress)?;
+            print_summary(cli.output, &summary, false)?;
+            if summary.sources.iter().any(|s| !s.violations.is_empty()) {
+                std::process::exit(1);
+            }
+        }
+        Command::Validate { schema, srcs } => {
+            let schema = Schema::from_toml(&std::fs::read_to_string(schema)?)?;
+            let summary = validate(&schema, srcs.expand()?, cli.progress)?;
+            print_summary(cli.output, &summary, false)?;
+            if summary.sources.iter().any(|s| !s.invalid.is_empty()) {
+                std::process::exit(1);
+            }
+        }
+        Command::Vacuum {
+            dir,
+            keep,
+            max_total,
+            dry_run,
+        } => {
+            let policy = RetentionPolicy { keep, max_total };
+            let removed = retention::vacuum(&dir, &policy, dry_run)?;
+            let summary = VacuumSummary {
+                dry_run,
+                bytes: removed.iter().map(|f| f.size).sum(),
+                removed: removed.into_iter().map(|f| f.path).collect(),
+            };
+            print_summary(cli.output, &summary, false)?;
+        }
+        Command::Generate {
+            entries,
+            units,
+            rate_profile,
+            seed,
+            out,
+        } => generate(out, entries, units, rate_profile.into(), seed)?,
+        Command::Completions { shell } => {
+            let mut script = vec![];
+            clap_complete::generate(shell, &mut Cli::command(), "loginus", &mut script);
+            io::stdout().write_all(&script)?;
+        }
+        Command::Man { out_dir } => match out_dir {
+            Some(dir) => clap_mangen::generate_to(Cli::command(), dir)?,
+            None => clap_mangen::Man::new(Cli::command()).render(&mut io::stdout())?,
+        },
+    }
+
+    Ok(skipped_sources)
+}
+
+/// Prints `summary` on stdout, or on stderr if stdout is used for data.
+fn print_summary<T: Serialize + Display>(
+    output: Output,
+    summary: &T,
+    to_stderr: bool,
+) -> io::Result<()> {
+    let s = match output {
+        Output::Text => summary.to_string(),
+        Output::Json => serde_json::to_string(summary)?,
+    };
+    if to_stderr {
+        eprintln!("{}", s);
+    } else {
+        println!("{}", s);
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct CountSummary {
+    entries: usize,
+    sources: Vec<SourceSummary>,
+}
+
+impl Display for CountSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.entries)?;
+        if self.sources.len() > 1 {
+            for s in self.sources.iter() {
+                write!(f, "\n{}", s)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct SourceSummary {
+    path: PathBuf,
+    entries: usize,
+    first_timestamp: Option<u64>,
+    last_timestamp: Option<u64>,
+    errors: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl SourceSummary {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            entries: 0,
+            first_timestamp: None,
+            last_timestamp: None,
+            errors: 0,
+            error: None,
+        }
+    }
+
+    fn record(&mut self, timestamp: Option<u64>) {
+        self.entries += 1;
+        if let Some(ts) = timestamp {
+            self.first_timestamp = Some(self.first_timestamp.map_or(ts, |t| t.min(ts)));
+            self.last_timestamp = Some(self.last_timestamp.map_or(ts, |t| t.max(ts)));
+        }
+    }
+
+    /// Adds the entries recorded by `other`.
+    fn absorb(&mut self, other: SourceSummary) {
+        self.entries += other.entries;
+        for ts in [other.first_timestamp, other.last_timestamp] {
+            if ts.is_some() {
+                self.entries -= 1;
+                self.record(ts);
+            }
+        }
+    }
+}
+
+impl Display for SourceSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} entries", self.path.display(), self.entries)?;
+        if let (Some(first), Some(last)) = (self.first_timestamp, self.last_timestamp) {
+            write!(f, " ({} - {})", first, last)?;
+        }
+        if let Some(error) = &self.error {
+            write!(f, ", skipped after error: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Clone)]
+struct MergeSourceSummary {
+    path: PathBuf,
+    #[serde(flatten)]
+    report: SourceReport,
+    /// The microseconds added to the timestamps of the source to order its
+    /// entries, see `--clock-skew-adjust`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clock_offset: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct MergeSummary {
+    /// The number of entries written to the output.
+    entries: usize,
+    /// The number of entries skipped because they were written by a
+    /// previous run (see `--resume`).
+    skipped: usize,
+    sources: Vec<MergeSourceSummary>,
+}
+
+impl Display for MergeSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "merged {} entries", self.entries)?;
+        if self.skipped > 0 {
+            write!(f, " ({} skipped)", self.skipped)?;
+        }
+        let paths: Vec<_> = self
+            .sources
+            .iter()
+            .map(|s| s.path.display().to_string())
+            .collect();
+        let width = paths.iter().map(|p| p.len()).max().unwrap_or(0).max(6);
+        let ts = |t: Option<u64>| t.map_or("-".to_string(), |t| t.to_string());
+        write!(
+            f,
+            "\n{:<width$} {:>10} {:>12} {:>12} {:>16} {:>16} {:>6}",
+            "SOURCE", "ENTRIES", "BYTES", "OUT-OF-ORDER", "FIRST", "LAST", "ERRORS"
+        )?;
+        for (path, s) in paths.iter().zip(self.sources.iter()) {
+            let r = &s.report;
+            write!(
+                f,
+                "\n{:<width$} {:>10} {:>12} {:>12} {:>16} {:>16} {:>6}",
+                path,
+                r.entries,
+                r.bytes,
+                r.out_of_order,
+                ts(r.first_timestamp),
+                ts(r.last_timestamp),
+                r.errors
+            )?;
+        }
+        for (path, s) in paths.iter().zip(self.sources.iter()) {
+            if let Some(offset) = s.clock_offset.filter(|&o| o != 0) {
+                let offset = Duration::from_micros(offset.unsigned_abs()).as_secs_f64();
+                let sign = if s.clock_offset < Some(0) { '-' } else { '+' };
+                write!(f, "\n{}: clock adjusted by {}{:.3}s", path, sign, offset)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct StatsSummary {
+    entries: usize,
+    bytes: usize,
+    fields: usize,
+    first_timestamp: Option<u64>,
+    last_timestamp: Option<u64>,
+}
+
+impl Display for StatsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "entries: {}", self.entries)?;
+        writeln!(f, "bytes: {}", self.bytes)?;
+        write!(f, "fields: {}", self.fields)?;
+        if let (Some(first), Some(last)) = (self.first_timestamp, self.last_timestamp) {
+            write!(f, "\nfirst: {}\nlast: {}", first, last)?;
+        }
+        Ok(())
+    }
+}
+
+fn info(srcs: Vec<PathBuf>, scan: bool) -> io::Result<InfoSummary> {
+    let mut sources = vec![];
+    for src in srcs {
+        if is_stdio(&src) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "info requires files",
+            ));
+        }
+        let info = inspect(&src, scan)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", src.display(), e)))?;
+        sources.push(SourceInfo { path: src, info });
+    }
+    Ok(InfoSummary { sources })
+}
+
+#[derive(Serialize)]
+struct SourceInfo {
+    path: PathBuf,
+    #[serde(flatten)]
+    info: FileInfo,
+}
+
+#[derive(Serialize)]
+struct InfoSummary {
+    sources: Vec<SourceInfo>,
+}
+
+impl Display for InfoSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, source) in self.sources.iter().enumerate() {
+            if i > 0 {
+                write!(f, "\n\n")?;
+            }
+            let info = &source.info;
+            write!(f, "{}:", source.path.display())?;
+            write!(f, "\nformat: {}", info.format.name())?;
+            write!(f, "\nbytes: {}", info.bytes)?;
+            if let Some(b) = &info.bounds {
+                match b.exact {
+                    true => write!(f, "\nentries: {}", b.entries)?,
+                    false => write!(f, "\nentries: about {}", b.entries)?,
+                }
+                for (name, meta) in [("first", &b.first), ("last", &b.last)] {
+                    let time = meta.realtime.map(|t| t.to_string());
+                    write!(f, "\n{}: {}", name, time.as_deref().unwrap_or("-"))?;
+                }
+                if let Some(span) = b.span() {
+                    write!(f, "\nspan: {:?}", Duration::from_micros(span))?;
+                }
+            } else if info.anomalies.is_empty() {
+                write!(f, "\nentries: 0")?;
+            }
+            let partial = match info.scanned {
+                true => "",
+                false => " (of the first and last entry)",
+            };
+            for (name, values) in [("hosts", &info.hosts), ("boots", &info.boots)] {
+                match values.is_empty() {
+                    true => write!(f, "\n{}: -", name)?,
+                    false => write!(f, "\n{}{}: {}", name, partial, values.join(", "))?,
+                }
+            }
+            for anomaly in &info.anomalies {
+                write!(f, "\nwarning: {}", anomaly)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct VerifySummary {
+    entries: usize,
+    bytes: usize,
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    position: Option<ErrorReport>,
+}
+
+impl Display for VerifySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.error, &self.position) {
+            (Some(e), Some(p)) => write!(
+                f,
+                "error after {} entries: {}\n{}",
+                self.entries,
+                e,
+                p.hexdump.trim_end()
+            ),
+            (Some(e), None) => write!(f, "error after {} entries: {}", self.entries, e),
+            (None, _) => write!(f, "ok: {} entries", self.entries),
+        }
+    }
+}
+
+/// Where a source failed to parse, for the error message of the CLI.
+#[derive(Serialize)]
+struct ErrorReport {
+    source: String,
+    offset: Option<u64>,
+    entry: Option<usize>,
+    field: Option<String>,
+    context_offset: u64,
+    /// The bytes around `offset` in hex.
+    context: String,
+    #[serde(skip)]
+    hexdump: String,
+}
+
+impl ErrorReport {
+    fn new(e: &SourceError) -> Self {
+        Self {
+            source: e.name(),
+            offset: e.error.offset(),
+            entry: e.error.entry(),
+            field: e.error.field_name().map(lossy),
+            context_offset: e.context.offset,
+            context: e
+                .context
+                .bytes
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+            hexdump: e.context.hexdump(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DedupSummary {
+    entries: usize,
+    duplicates: usize,
+}
+
+impl Display for DedupSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "wrote {} entries, removed {} duplicates",
+            self.entries, self.duplicates
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct FieldDiff {
+    name: String,
+    left: Vec<String>,
+    right: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct EntryDiff {
+    /// `<` if the entry is only contained in the left export, `>` if it is
+    /// only contained in the right one and `~` if it differs.
+    kind: char,
+    timestamp: Option<u64>,
+    cursor: Option<String>,
+    fields: Vec<FieldDiff>,
+}
+
+#[derive(Serialize)]
+struct DiffSummary {
+    matched: usize,
+    differences: Vec<EntryDiff>,
+}
+
+impl Display for DiffSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = |kind| self.differences.iter().filter(|d| d.kind == kind).count();
+        for d in self.differences.iter() {
+            let ts = d.timestamp.map_or("-".to_string(), |t| t.to_string());
+            writeln!(
+                f,
+                "{} {} {}",
+                d.kind,
+                ts,
+                d.cursor.as_deref().unwrap_or("-")
+            )?;
+            for field in d.fields.iter() {
+                writeln!(f, "    {}", field.name)?;
+                for v in field.left.iter() {
+                    writeln!(f, "    < {}", v)?;
+                }
+                for v in field.right.iter() {
+                    writeln!(f, "    > {}", v)?;
+                }
+            }
+        }
+        write!(
+            f,
+            "{} matched, {} only left, {} only right, {} changed",
+            self.matched,
+            count('<'),
+            count('>'),
+            count('~')
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct SourceOrderSummary {
+    path: PathBuf,
+    entries: usize,
+    violations: Vec<OrderViolation>,
+}
+
+#[derive(Serialize)]
+struct CheckOrderSummary {
+    sources: Vec<SourceOrderSummary>,
+}
+
+impl Display for CheckOrderSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, s) in self.sources.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            let path = s.path.display();
+            for v in s.violations.iter() {
+                writeln!(
+                    f,
+                    "{}: entry {} at offset {}: {} is {}us before {}",
+                    path,
+                    v.index,
+                    v.offset,
+                    v.timestamp,
+                    v.delta(),
+                    v.previous
+                )?;
+            }
+            write!(
+                f,
+                "{}: {} entries, {} out of order",
+                path,
+                s.entries,
+                s.violations.len()
+            )?;
+            if let Some(max) = s.violations.iter().map(|v| v.delta()).max() {
+                write!(f, " (max {}us)", max)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct ValidateSummary {
+    sources: Vec<SourceValidateSummary>,
+}
+
+#[derive(Serialize)]
+struct SourceValidateSummary {
+    path: PathBuf,
+    entries: usize,
+    invalid: Vec<InvalidEntry>,
+}
+
+#[derive(Serialize)]
+struct InvalidEntry {
+    index: usize,
+    offset: usize,
+    violations: Vec<Violation>,
+}
+
+impl Display for ValidateSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, s) in self.sources.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            let path = s.path.display();
+            for e in s.invalid.iter() {
+                write!(f, "{}: entry {} at offset {}: ", path, e.index, e.offset)?;
+                for (j, v) in e.violations.iter().enumerate() {
+                    if j > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                writeln!(f)?;
+            }
+            write!(
+                f,
+                "{}: {} entries, {} invalid",
+                path,
+                s.entries,
+                s.invalid.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `path` can be read more than once, which is not the case for stdin
+/// and followed journals.
+fn rereadable(path: &Path) -> bool {
+    let follows =
+        local_journal_spec(path).is_some_and(|spec| spec.split(',').any(|o| o == "follow"));
+    !is_stdio(path) && !follows
+}
+
+/// Whether `path` denotes stdin or stdout.
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// The options of a `journal:` source or `None` if `path` is a file.
+fn local_journal_spec(path: &Path) -> Option<&str> {
+    path.to_str()?.strip_prefix("journal:")
+}
+
+#[cfg(all(target_os = "linux", feature = "local"))]
+fn parse_local_journal(spec: &str) -> io::Result<LocalJournal> {
+    let mut journal = LocalJournal::new();
+    for option in spec.split(',').filter(|o| !o.is_empty()) {
+        journal = match option.split_once('=') {
+            None if option == "follow" => journal.with_follow(true),
+            Some(("unit", unit)) => journal.with_unit(unit),
+            Some(("dir", dir)) => journal.with_directory(dir),
+            Some(("after", cursor)) => journal.with_after_cursor(cursor),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid journal option: {}", option),
+                ))
+            }
+        };
+    }
+    Ok(journal)
+}
+
+/// Opens `path` for reading; `-` denotes stdin and `journal:` the local
+/// journal. Compressed files are decompressed transparently.
+fn open_source(path: &Path) -> io::Result<Box<dyn Read>> {
+    if is_stdio(path) {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+    if let Some(spec) = local_journal_spec(path) {
+        #[cfg(all(target_os = "linux", feature = "local"))]
+        {
+            let read = parse_local_journal(spec)?.spawn()?;
+            let stopper = read.stopper();
+            shutdown().on_request(move || stopper.stop());
+            return Ok(Box::new(read));
+        }
+        #[cfg(not(all(target_os = "linux", feature = "local")))]
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "reading the local journal is not supported: journal:{}",
+                spec
+            ),
+        ));
+    }
+    source::open(path)
+}
+
+/// Opens `path` for writing, truncating existing files unless `append` is
+/// set; `-` denotes stdout.
+fn create_sink(path: &Path, append: bool) -> io::Result<Box<dyn Write + Send>> {
+    if is_stdio(path) {
+        return Ok(Box::new(BufWriter::new(io::stdout())));
+    }
+    let f = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)?;
+    Ok(Box::new(BufWriter::new(f)))
+}
+
+/// The size of the source at `path` or `None` if it is read from stdin, the
+/// local journal or compressed.
+fn source_len(path: &Path) -> io::Result<Option<u64>> {
+    if is_stdio(path) || local_journal_spec(path).is_some() || !source::is_verbatim(path) {
+        return Ok(None);
+    }
+    Ok(Some(std::fs::metadata(path)?.len()))
+}
+
+#[derive(Serialize)]
+struct VacuumSummary {
+    dry_run: bool,
+    bytes: u64,
+    removed: Vec<PathBuf>,
+}
+
+impl Display for VacuumSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = if self.dry_run {
+            "would remove"
+        } else {
+            "removed"
+        };
+        write!(
+            f,
+            "{} {} files ({} bytes)",
+            verb,
+            self.removed.len(),
+            self.bytes
+        )?;
+        for p in self.removed.iter() {
+            write!(f, "\n{}", p.display())?;
+        }
+        Ok(())
+    }
+}
+
+fn progress_bar(enabled: bool, total: Option<u64>) -> ProgressBar {
+    if !enabled {
+        return ProgressBar::hidden();
+    }
+    let (pb, template) = match total {
+        Some(total) => (
+            ProgressBar::new(total),
+            "{wide_bar} {bytes}/{total_bytes} {binary_bytes_per_sec} ETA {eta}",
+        ),
+        None => (
+            ProgressBar::new_spinner(),
+            "{spinner} {bytes} {binary_bytes_per_sec}",
+        ),
+    };
+    pb.set_style(ProgressStyle::with_template(template).unwrap());
+    pb
+}
+
+/// Advances `pb` by an entry of `len` bytes that one of the threads of
+/// [chunk::fold] parsed; `read` counts the bytes of all threads.
+fn advance(pb: &ProgressBar, read: &AtomicU64, len: usize) {
+    let len = len as u64;
+    let before = read.fetch_add(len, Ordering::Relaxed);
+    // Only once per MiB, as every update takes the lock of the bar.
+    if before >> 20 != (before + len) >> 20 {
+        pb.set_position(before + len);
+    }
+}
+
+/// Expands glob patterns and directories among `srcs`. Directories are
+/// replaced by the export files they contain, ordered by time. Other paths are
+/// passed on unchanged.
+fn expand_sources(srcs: Vec<PathBuf>) -> io::Result<Vec<PathBuf>> {
+    let mut expanded = vec![];
+    for src in srcs {
+        if src.is_dir() {
+            let discovered = source::discover(&src)?;
+            for p in discovered.skipped {
+                eprintln!("skipping binary journal file {}", p.display());
+            }
+            for (a, b) in discovered.overlapping {
+                eprintln!(
+                    "warning: {} starts before {} ends, entries are not in order",
+                    b.display(),
+                    a.display()
+                );
+            }
+            expanded.extend(discovered.exports);
+            continue;
+        }
+        let pattern = src.to_string_lossy();
+        if !pattern.contains(['*', '?', '[']) {
+            expanded.push(src);
+            continue;
+        }
+        let paths = glob::glob(&pattern)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(io::Error::from)?;
+        if paths.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no files match {}", pattern),
+            ));
+        }
+        expanded.extend(paths);
+    }
+    if expanded.iter().filter(|p| is_stdio(p)).count() > 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "stdin can only be used as a single source",
+        ));
+    }
+    Ok(expanded)
+}
+
+/// The shutdown requested by SIGTERM or SIGINT in the modes that install a
+/// handler for them.
+fn shutdown() -> &'static Shutdown {
+    static SHUTDOWN: OnceLock<Shutdown> = OnceLock::new();
+    SHUTDOWN.get_or_init(Shutdown::new)
+}
+
+/// The positions of the sources of a run, loaded from and saved to a
+/// `--state` file.
+struct SourceState {
+    path: Option<PathBuf>,
+    keys: Vec<String>,
+    /// The number of entries to skip per source.
+    skip: Vec<u64>,
+    checkpoint: Checkpoint,
+}
+
+impl SourceState {
+    fn load(path: Option<PathBuf>, srcs: &[PathBuf]) -> io::Result<Self> {
+        let checkpoint = match &path {
+            Some(path) => Checkpoint::load(path)?,
+            None => Checkpoint::default(),
+        };
+        let keys: Vec<_> = srcs
+            .iter()
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect();
+        let skip = srcs
+            .iter()
+            .zip(keys.iter())
+            .map(|(src, key)| match checkpoint.source(key) {
+                // The local journal resumes after the cursor instead.
+                Some(_) if local_journal_spec(src).is_some() => 0,
+                Some(source) => source.entries,
+                None => 0,
+            })
+            .collect();
+        Ok(Self {
+            path,
+            keys,
+            skip,
+            checkpoint,
+        })
+    }
+
+    /// The sources to open, with the local journal starting after the last
+    /// recorded cursor.
+    fn resumed(&self, srcs: &[PathBuf]) -> Vec<PathBuf> {
+        srcs.iter()
+            .zip(self.keys.iter())
+            .map(|(src, key)| {
+                let cursor = self.checkpoint.source(key).and_then(|s| s.cursor.as_ref());
+                match (local_journal_spec(src), cursor) {
+                    (Some(spec), Some(cursor)) if !spec.is_empty() => {
+                        format!("journal:{},after={}", spec, cursor).into()
+                    }
+                    (Some(_), Some(cursor)) => format!("journal:after={}", cursor).into(),
+                    _ => src.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// Whether the current entry of `source` was read by a previous run.
+    fn skips(&mut self, source: usize) -> bool {
+        match &mut self.skip[source] {
+            0 => false,
+            n => {
+                *n -= 1;
+                true
+            }
+        }
+    }
+
+    fn record(&mut self, source: usize, entry: &impl Entry) {
+        if self.path.is_some() {
+            self.checkpoint.record(&self.keys[source], entry);
+        }
+    }
+
+    fn save(&self) -> io::Result<()> {
+        match &self.path {
+            Some(path) => self.checkpoint.save(path),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Opens `srcs` as one stream of entries, selected according to `range`. If
+/// `merge` is set, the entries of all sources are interleaved by timestamp.
+fn open_sources(
+    srcs: &[PathBuf],
+    merge: bool,
+    range: &Range,
+    errors: &Errors,
+) -> io::Result<MultiRead<Box<dyn Read>>> {
+    open_tagged_sources(srcs, merge, None, range, errors)
+}
+
+/// Like [open_sources], but tags the entries of every source with the name
+/// of the corresponding source of `provenance`; see [TaggedRead].
+fn open_tagged_sources(
+    srcs: &[PathBuf],
+    merge: bool,
+    provenance: Option<&[PathBuf]>,
+    range: &Range,
+    errors: &Errors,
+) -> io::Result<MultiRead<Box<dyn Read>>> {
+    let tail = range.tail;
+    let since = range.since;
+    let order = if merge {
+        Order::Timestamp
+    } else {
+        Order::Sequential
+    };
+    let skip_errors = errors.skip();
+    // Only warn about errors while reading the entries that are processed,
+    // not while counting them for `--tail`.
+    let open = |warn: bool| -> io::Result<MultiRead<Box<dyn Read>>> {
+        let mut readers = vec![];
+        for (i, p) in srcs.iter().enumerate() {
+            let read = match open_tail(p, tail, since) {
+                Err(e) if skip_errors => Box::new(Unopened(Some(e))),
+                Err(e) => return Err(io::Error::new(e.kind(), format!("{}: {}", p.display(), e))),
+                Ok(read) => match provenance {
+                    Some(names) => {
+                        let name = names[i].display().to_string();
+                        Box::new(TaggedRead::new(read, name)) as Box<dyn Read>
+                    }
+                    None => read,
+                },
+            };
+            readers.push(JournalExportRead::new(read));
+        }
+        let names = srcs.iter().map(|p| p.display().to_string()).collect();
+        let mut reader = MultiRead::new(readers, order).with_source_names(names);
+        if skip_errors {
+            reader = reader.with_skip_errors(move |e| {
+                if warn {
+                    eprintln!("warning: skipping the rest of {}", e);
+                }
+            });
+        }
+        Ok(match since {
+            Some(ts) => reader.with_since(ts),
+            None => reader,
+        })
+    };
+    let mut skip = range.skip.unwrap_or(0);
+    if let Some(n) = tail.filter(|_| srcs.len() > 1 || !srcs.iter().all(|p| seekable(p))) {
+        // Only a single uncompressed file is known to start at its last
+        // entries; otherwise, count the entries to skip.
+        if let Some(src) = srcs.iter().find(|s| !rereadable(s)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("--tail cannot read {} twice", src.display()),
+            ));
+        }
+        let mut reader = open(false)?;
+        let mut count = 0usize;
+        while reader.parse_next()?.is_some() {
+            count += 1;
+        }
+        skip += count.saturating_sub(n);
+    }
+    let mut reader = open(true)?.with_skip(skip);
+    if let Some(limit) = range.limit {
+        reader = reader.with_limit(limit);
+    }
+    Ok(reader)
+}
+
+/// Opens `path`, positioned at its last `tail` entries or shortly before the
+/// entries `since` if it is an uncompressed file.
+fn open_tail(path: &Path, tail: Option<usize>, since: Option<u64>) -> io::Result<Box<dyn Read>> {
+    if !seekable(path) {
+        return open_source(path);
+    }
+    let mut f = File::open(path)?;
+    match (tail, since) {
+        (Some(n), _) => {
+            let offset = source::tail_offset(&mut f, n)?;
+            f.seek(SeekFrom::Start(offset))?;
+        }
+        (None, Some(ts)) => {
+            source::seek_to_time(&mut f, ts)?;
+        }
+        (None, None) => {}
+    }
+    Ok(Box::new(f))
+}
+
+/// The chunks to parse `srcs` in on `jobs` threads, if it is a single
+/// uncompressed file that is read completely; see [chunk::split]. With the
+/// `uring` feature, also a single chunk, which is read with io_uring.
+fn parallel_chunks(
+    srcs: &[PathBuf],
+    jobs: usize,
+    range: &Range,
+    errors: &Errors,
+) -> io::Result<Option<Vec<ops::Range<u64>>>> {
+    let complete = range.since.is_none()
+        && range.tail.is_none()
+        && range.skip.is_none()
+        && range.limit.is_none();
+    // Errors are reported per source, not per chunk.
+    let skip_errors = errors.skip();
+    match srcs {
+        [src]
+            if (jobs > 1 || cfg!(all(target_os = "linux", feature = "uring")))
+                && complete
+                && !skip_errors
+                && seekable(src) =>
+        {
+            chunk::split(src, jobs).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Whether `path` is an uncompressed file.
+fn seekable(path: &Path) -> bool {
+    !is_stdio(path)
+        && local_journal_spec(path).is_none()
+        && path.is_file()
+        && source::is_verbatim(path)
+}
+
+/// The total size of `srcs` or `None` if any of them is read from stdin.
+fn total_len(srcs: &[PathBuf], errors: &Errors) -> io::Result<Option<u64>> {
+    let mut total = Some(0);
+    for p in srcs {
+        let len = match source_len(p) {
+            Ok(len) => len,
+            // Reported once the source is read.
+            Err(_) if errors.skip() => Some(0),
+            Err(e) => return Err(io::Error::new(e.kind(), format!("{}: {}", p.display(), e))),
+        };
+        total = total.zip(len).map(|(a, b)| a + b);
+    }
+    Ok(total)
+}
+
+/// The number of sources that were skipped because of `--skip-errors`, by
+/// their reports.
+fn skipped<'a>(reports: impl IntoIterator<Item = &'a SourceReport>) -> usize {
+    reports.into_iter().filter(|r| r.errors > 0).count()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn merge_journals(
+    out: Destination,
+    mut pipeline: Pipeline,
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    mut state: SourceState,
+    offsets: Option<Vec<i64>>,
+    provenance: bool,
+    progress: bool,
+) -> io::Result<MergeSummary> {
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    let provenance = provenance.then_some(&srcs[..]);
+    let mut reader = open_tagged_sources(&state.resumed(&srcs), true, provenance, range, errors)?;
+    if let Some(offsets) = &offsets {
+        reader = reader.with_clock_offsets(offsets);
+    }
+    let resume = out.resume_point()?;
+    let mut outfile = out.open()?;
+
+    let mut entries = 0;
+    let mut skipped = 0;
+    while !shutdown().is_requested() && reader.parse_next()?.is_some() {
+        let e = reader.get_entry();
+        let source = reader.source_index().unwrap();
+        pb.set_position(reader.bytes_read() as u64);
+        if state.skips(source) {
+            skipped += 1;
+            continue;
+        }
+        state.record(source, &e);
+        if resume.skips(&e) {
+            skipped += 1;
+            continue;
+        }
+        pipeline.process(source, &e, &mut *outfile)?;
+        entries += 1;
+    }
+    pb.finish_and_clear();
+    pipeline.finish(&mut *outfile)?;
+    outfile.finish()?;
+    state.save()?;
+    let sources = srcs
+        .into_iter()
+        .zip(reader.report().sources)
+        .enumerate()
+        .map(|(i, (path, report))| MergeSourceSummary {
+            path,
+            report,
+            clock_offset: offsets.as_ref().map(|o| o[i]),
+        })
+        .collect();
+    Ok(MergeSummary {
+        entries,
+        skipped,
+        sources,
+    })
+}
+
+/// Merges `srcs` as they are written until a shutdown is requested or all
+/// of them ended; see [open_live].
+fn merge_live(
+    out: Destination,
+    mut pipeline: Pipeline,
+    srcs: Vec<PathBuf>,
+    errors: &Errors,
+    delay: Duration,
+    provenance: bool,
+) -> io::Result<MergeSummary> {
+    let mut merge = LiveMerge::new()
+        .with_delay(delay)
+        .with_shutdown(shutdown().clone());
+    for src in srcs.iter() {
+        let path = src.clone();
+        let name = src.display().to_string();
+        merge = merge.with_source(name.clone(), move || {
+            let read = open_live(&path)?;
+            Ok(match provenance {
+                true => Box::new(TaggedRead::new(read, name.as_str())),
+                false => read,
+            })
+        });
+    }
+    let mut reader = merge.start();
+    if errors.skip() {
+        reader = reader.with_skip_errors(|e| {
+            eprintln!("warning: skipping the rest of {}", e);
+        });
+    }
+    let mut outfile = out.open()?;
+    let mut entries = 0;
+    while reader.parse_next()?.is_some() {
+        pipeline.process(
+            reader.source_index().unwrap(),
+            reader.get_entry(),
+            &mut *outfile,
+        )?;
+        entries += 1;
+    }
+    pipeline.finish(&mut *outfile)?;
+    outfile.finish()?;
+    let sources = srcs
+        .into_iter()
+        .zip(reader.report().sources)
+        .map(|(path, report)| MergeSourceSummary {
+            path,
+            report,
+            clock_offset: None,
+        })
+        .collect();
+    Ok(MergeSummary {
+        entries,
+        skipped: 0,
+        sources,
+    })
+}
+
+/// Opens `path` as a source of [merge_live]: files and directories are
+/// followed, `tcp:HOST:PORT` and `unix:PATH` are connected to and other
+/// sources are opened by [open_source]. Compressed files are read once.
+fn open_live(path: &Path) -> io::Result<Box<dyn Read>> {
+    let spec = path.to_str().unwrap_or_default();
+    if let Some(addr) = spec.strip_prefix("tcp:") {
+        return Ok(Box::new(std::net::TcpStream::connect(addr)?));
+    }
+    #[cfg(unix)]
+    if let Some(socket) = spec.strip_prefix("unix:") {
+        return Ok(Box::new(std::os::unix::net::UnixStream::connect(socket)?));
+    }
+    if path.is_dir() || seekable(path) {
+        return Ok(Box::new(
+            Follow::new(path)?.with_shutdown(shutdown().clone()),
+        ));
+    }
+    open_source(path)
+}
+
+/// The offsets to add to the timestamps of `srcs` for ordering their entries:
+/// those `given` by `--clock-offset` and, if `estimate`, estimated by
+/// [ClockSkew] for the others. `None` if neither applies.
+fn clock_offsets(
+    srcs: &[PathBuf],
+    errors: &Errors,
+    estimate: bool,
+    given: Vec<(PathBuf, i64)>,
+) -> io::Result<Option<Vec<i64>>> {
+    if !estimate && given.is_empty() {
+        return Ok(None);
+    }
+    let mut offsets = vec![None; srcs.len()];
+    if estimate {
+        if let Some(src) = srcs.iter().find(|s| !rereadable(s)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("--clock-skew-adjust cannot read {} twice", src.display()),
+            ));
+        }
+        let mut skew = ClockSkew::new(srcs.len());
+        for (i, path) in srcs.iter().enumerate() {
+            let read = match open_source(path) {
+                Ok(read) => read,
+                // Reported when the sources are merged.
+                Err(_) if errors.skip() => continue,
+                Err(e) => return Err(e),
+            };
+            let mut jreader = JournalExportRead::new(read);
+            loop {
+                match jreader.parse_next() {
+                    Ok(Some(())) => skew.record(i, &jreader.get_entry()),
+                    Ok(None) => break,
+                    Err(_) if errors.skip() => break,
+                    Err(e) => return Err(located(&jreader, path, e).into()),
+                }
+            }
+        }
+        offsets = skew.offsets();
+        if srcs.len() > 1 {
+            for (path, _) in srcs.iter().zip(&offsets).filter(|(_, o)| o.is_none()) {
+                eprintln!(
+                    "warning: {} has no boot in common with other sources; its clock is not adjusted",
+                    path.display()
+                );
+            }
+        }
+    }
+    for (source, offset) in given {
+        let i = srcs.iter().position(|s| *s == source).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("--clock-offset: {} is not a source", source.display()),
+            )
+        })?;
+        offsets[i] = Some(offset);
+    }
+    Ok(Some(offsets.into_iter().map(|o| o.unwrap_or(0)).collect()))
+}
+
+/// Which entries `sample` keeps.
+enum Sampling {
+    /// Each entry with probability `rate`, and those of `keep_priority` or a
+    /// more important one always.
+    Rate {
+        rate: f64,
+        keep_priority: Option<u8>,
+    },
+    /// Exactly `n` of the entries the pipeline selects.
+    Count(usize),
+}
+
+/// Fails if the input of a resumed run did not contain the last entry of the
+/// output, e.g. because the input differs from that of the previous run.
+fn resumed(resume: &ResumePoint) -> io::Result<()> {
+    if resume.is_passed() {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "cannot resume: the last entry of the output is not in the input",
+    ))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn sample_journal(
+    dst: Destination,
+    mut pipeline: Pipeline,
+    sampling: Sampling,
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    merge: bool,
+    progress: bool,
+) -> io::Result<usize> {
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    let mut reader = open_sources(&srcs, merge, range, errors)?;
+    let mut resume = dst.resume_point()?;
+    let mut outfile = dst.open()?;
+
+    let (rate, keep_priority, mut reservoir) = match sampling {
+        Sampling::Rate {
+            rate,
+            keep_priority,
+        } => (rate, keep_priority, None),
+        Sampling::Count(n) => (1.0, None, Some(Reservoir::new(n))),
+    };
+    let mut rng = rand::thread_rng();
+    while reader.parse_next()?.is_some() {
+        pb.set_position(reader.bytes_read() as u64);
+        let e = reader.get_entry();
+        if resume.skips_in_sequence(&e) {
+            continue;
+        }
+        let important = keep_priority
+            .zip(e.get_u64(b"PRIORITY"))
+            .is_some_and(|(keep, priority)| priority <= keep as u64);
+        if !important && !rng.gen_bool(rate) {
+            continue;
+        }
+        let source = reader.source_index().unwrap();
+        match reservoir.as_mut() {
+            Some(reservoir) => pipeline.process(source, &e, reservoir)?,
+            None => pipeline.process(source, &e, &mut *outfile)?,
+        }
+    }
+    pb.finish_and_clear();
+    resumed(&resume)?;
+    if let Some(mut reservoir) = reservoir {
+        pipeline.finish(&mut reservoir)?;
+        for entry in reservoir.into_entries() {
+            outfile.write_entry(&entry)?;
+        }
+    } else {
+        pipeline.finish(&mut *outfile)?;
+    }
+    outfile.finish()?;
+    Ok(skipped(&reader.report().sources))
+}
+
+/// The compression of an archive and the name of its top directory, by the
+/// name of the archive.
+fn archive_kind(out: &Path) -> io::Result<(Option<Compression>, String)> {
+    let name = out
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let kinds = [
+        (".tar", None),
+        (".tar.gz", Some(Compression::Gzip)),
+        (".tgz", Some(Compression::Gzip)),
+        (".tar.zst", Some(Compression::Zstd)),
+        (".tzst", Some(Compression::Zstd)),
+    ];
+    kinds
+        .into_iter()
+        .find_map(|(suffix, compression)| {
+            let dir = name.strip_suffix(suffix).filter(|d| !d.is_empty())?;
+            Some((compression, dir.to_string()))
+        })
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{}: expected an archive ending in .tar, .tar.gz or .tar.zst",
+                    out.display()
+                ),
+            )
+        })
+}
+
+#[derive(Serialize)]
+struct BundleManifest {
+    created: String,
+    version: &'static str,
+    /// The command line the bundle was created with.
+    command: Vec<String>,
+    redaction: &'static str,
+    sources: Vec<MergeSourceSummary>,
+    files: Vec<BundleFile>,
+}
+
+#[derive(Serialize)]
+struct BundleFile {
+    name: &'static str,
+    bytes: u64,
+    sha256: String,
+}
+
+#[derive(Serialize)]
+struct BundleSummary {
+    path: PathBuf,
+    entries: usize,
+    /// The size of the archive.
+    bytes: u64,
+    sources: Vec<MergeSourceSummary>,
+}
+
+impl Display for BundleSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "bundled {} entries of {} sources into {} ({} bytes)",
+            self.entries,
+            self.sources.len(),
+            self.path.display(),
+            self.bytes
+        )
+    }
+}
+
+/// Writes the entries of `srcs` that pass `pipeline` to the archive `out`,
+/// with their statistics and a manifest.
+fn bundle(
+    out: &Path,
+    redact: Redact,
+    mut pipeline: Pipeline,
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    progress: bool,
+) -> io::Result<BundleSummary> {
+    let (compression, dir) = archive_kind(out)?;
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    let mut reader = open_sources(&srcs, true, range, errors)?;
+    let mut export = BufWriter::new(tempfile::tempfile()?);
+    while !shutdown().is_requested() && reader.parse_next()?.is_some() {
+        pb.set_position(reader.bytes_read() as u64);
+        pipeline.process(
+            reader.source_index().unwrap(),
+            &reader.get_entry(),
+            &mut export,
+        )?;
+    }
+    pb.finish_and_clear();
+    pipeline.finish(&mut export)?;
+    let mut export = export.into_inner().map_err(|e| e.into_error())?;
+
+    // The digest and statistics of what was written.
+    export.rewind()?;
+    let mut digest = sha2::Sha256::new();
+    let export_len = io::copy(&mut export, &mut digest)?;
+    export.rewind()?;
+    // Transforms such as `--reassemble` may have written entries beyond the
+    // default limits.
+    let limits = JournalExportLimits::bounded_by(export_len as usize);
+    let mut read_back = JournalExportRead::new_with_limits(limits, io::BufReader::new(&export));
+    let mut written = SourceSummary::new(PathBuf::new());
+    let mut bytes = 0;
+    let mut fields = 0;
+    while read_back.parse_next()?.is_some() {
+        let e = read_back.get_entry();
+        written.record(e.realtime_timestamp());
+        bytes += e.len_bytes();
+        fields += e.field_count();
+    }
+    let stats = serde_json::to_vec_pretty(&StatsSummary {
+        entries: written.entries,
+        bytes,
+        fields,
+        first_timestamp: written.first_timestamp,
+        last_timestamp: written.last_timestamp,
+    })?;
+    export.rewind()?;
+
+    let sources: Vec<_> = srcs
+        .into_iter()
+        .zip(reader.report().sources)
+        .map(|(path, report)| MergeSourceSummary {
+            path,
+            report,
+            clock_offset: None,
+        })
+        .collect();
+    let manifest = serde_json::to_vec_pretty(&BundleManifest {
+        created: chrono::Utc::now().to_rfc3339(),
+        version: env!("CARGO_PKG_VERSION"),
+        command: std::env::args().collect(),
+        redaction: redact.redaction().map_or("none", |r| r.name()),
+        sources: sources.clone(),
+        files: vec![
+            BundleFile {
+                name: "journal.export",
+                bytes: export_len,
+                sha256: hex(&digest.finalize()),
+            },
+            BundleFile {
+                name: "stats.json",
+                bytes: stats.len() as u64,
+                sha256: hex(&sha2::Sha256::digest(&stats)),
+            },
+        ],
+    })?;
+
+    let files: Vec<(&str, u64, Box<dyn Read + '_>)> = vec![
+        ("journal.export", export_len, Box::new(&export)),
+        ("stats.json", stats.len() as u64, Box::new(&stats[..])),
+        (
+            "manifest.json",
+            manifest.len() as u64,
+            Box::new(&manifest[..]),
+        ),
+    ];
+    let file = BufWriter::new(File::create(out)?);
+    match compression {
+        None => write_archive(file, &dir, files)?.flush()?,
+        Some(Compression::Gzip) => {
+            let enc = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+            write_archive(enc, &dir, files)?.finish()?.flush()?
+        }
+        #[cfg(feature = "zstd")]
+        Some(Compression::Zstd) => {
+            let enc = zstd::Encoder::new(file, 0)?;
+            write_archive(enc, &dir, files)?.finish()?.flush()?
+        }
+        #[cfg(not(feature = "zstd"))]
+        Some(Compression::Zstd) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "zstd compression is not supported by this build",
+            ))
+        }
+    }
+    Ok(BundleSummary {
+        path: out.to_path_buf(),
+        entries: written.entries,
+        bytes: fs::metadata(out)?.len(),
+        sources,
+    })
+}
+
+/// Writes `files`, given by name, size and content, into the directory `dir`
+/// of a tar archive.
+fn write_archive<W: Write>(
+    w: W,
+    dir: &str,
+    files: Vec<(&str, u64, Box<dyn Read + '_>)>,
+) -> io::Result<W> {
+    let mtime = chrono::Utc::now().timestamp() as u64;
+    let mut builder = tar::Builder::new(w);
+    for (name, size, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(size);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        header.set_cksum();
+        builder.append_data(&mut header, format!("{}/{}", dir, name), content)?;
+    }
+    builder.into_inner()
+}
+
+fn cat(
+    formatter: EntryFormatter,
+    mut pipeline: Pipeline,
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    progress: bool,
+) -> io::Result<usize> {
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    let mut reader = open_sources(&srcs, false, range, errors)?;
+    let mut sink = FormattingSink::new(formatter, BufWriter::new(io::stdout().lock()));
+    while reader.parse_next()?.is_some() {
+        pb.set_position(reader.bytes_read() as u64);
+        pipeline.process(
+            reader.source_index().unwrap(),
+            &reader.get_entry(),
+            &mut sink,
+        )?;
+    }
+    pb.finish_and_clear();
+    pipeline.finish(&mut sink)?;
+    sink.finish()?;
+    Ok(skipped(&reader.report().sources))
+}
+
+/// Like [cat], but reads the sources backwards. `--skip` and `--limit` apply
+/// to the reversed stream.
+fn cat_reverse(
+    formatter: EntryFormatter,
+    mut pipeline: Pipeline,
+    srcs: Vec<PathBuf>,
+    range: &Range,
+) -> io::Result<()> {
+    if range.tail.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--tail cannot be combined with --reverse; use --limit",
+        ));
+    }
+    if let Some(src) = srcs.iter().find(|s| !seekable(s)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("--reverse requires uncompressed files: {}", src.display()),
+        ));
+    }
+    let mut skip = range.skip.unwrap_or(0);
+    let mut limit = range.limit.unwrap_or(usize::MAX);
+    let mut sink = FormattingSink::new(formatter, BufWriter::new(io::stdout().lock()));
+    'sources: for (i, src) in srcs.iter().enumerate().rev() {
+        let mut reader = JournalExportReverseRead::new(File::open(src)?)?;
+        while reader.parse_next()?.is_some() {
+            if skip > 0 {
+                skip -= 1;
+                continue;
+            }
+            if limit == 0 {
+                break 'sources;
+            }
+            limit -= 1;
+            pipeline.process(i, reader.get_entry(), &mut sink)?;
+        }
+    }
+    pipeline.finish(&mut sink)?;
+    sink.finish()
+}
+
+/// Keeps the last entries written to it.
+struct LastEntries {
+    n: usize,
+    entries: VecDeque<Vec<u8>>,
+}
+
+impl EntrySink for LastEntries {
+    fn write_entry(&mut self, entry: &[u8]) -> io::Result<()> {
+        if self.entries.len() == self.n {
+            self.entries.pop_front();
+        }
+        if self.n > 0 {
+            self.entries.push_back(entry.to_vec());
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn tail(
+    formatter: EntryFormatter,
+    mut pipeline: Pipeline,
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    n: usize,
+    follow: bool,
+) -> io::Result<usize> {
+    let mut last = LastEntries {
+        n,
+        entries: VecDeque::new(),
+    };
+    // When following a directory, the last entries are those of the file
+    // that is followed.
+    let (follow, skipped_sources) = match follow {
+        true => {
+            let follow = Follow::new(&srcs[0])?;
+            let mut reader = JournalExportRead::new(source::open(follow.path())?);
+            while (reader.parse_next())
+                .map_err(|e| located(&reader, follow.path(), e))?
+                .is_some()
+            {
+                pipeline.process(0, &reader.get_entry(), &mut last)?;
+            }
+            // Continue after the last complete entry.
+            (Some(follow.with_offset(reader.position() as u64)?), 0)
+        }
+        false => {
+            let mut reader = open_sources(&srcs, true, range, errors)?;
+            while reader.parse_next()?.is_some() {
+                pipeline.process(
+                    reader.source_index().unwrap(),
+                    &reader.get_entry(),
+                    &mut last,
+                )?;
+            }
+            (None, skipped(&reader.report().sources))
+        }
+    };
+    let mut sink = FormattingSink::new(formatter, io::stdout().lock());
+    for entry in last.entries.iter() {
+        sink.write_entry(entry)?;
+    }
+    if let Some(follow) = follow {
+        shutdown().install()?;
+        let mut reader = JournalExportRead::new(follow.with_shutdown(shutdown().clone()));
+        while reader.parse_next()?.is_some() {
+            pipeline.process(0, &reader.get_entry(), &mut sink)?;
+        }
+    }
+    pipeline.finish(&mut sink)?;
+    sink.finish()?;
+    Ok(skipped_sources)
+}
+
+fn run_pipeline(
+    config: &PipelineConfig,
+    out: Destination,
+    progress: bool,
+    range: &Range,
+    errors: &Errors,
+) -> io::Result<(RunSummary, usize)> {
+    let srcs = expand_sources(config.sources.clone())?;
+    let mut pipeline = config.pipeline(&srcs, &transform_registry())?;
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    let mut state = SourceState::load(config.state.clone(), &srcs)?;
+    let mut reader = open_sources(&state.resumed(&srcs), config.merge, range, errors)?;
+    let mut outfile = out.open()?;
+
+    let mut entries = 0;
+    while !shutdown().is_requested() && reader.parse_next()?.is_some() {
+        pb.set_position(reader.bytes_read() as u64);
+        let source = reader.source_index().unwrap();
+        if state.skips(source) {
+            continue;
+        }
+        let e = reader.get_entry();
+        state.record(source, &e);
+        pipeline.process(source, &e, &mut *outfile)?;
+        entries += 1;
+    }
+    pb.finish_and_clear();
+    pipeline.finish(&mut *outfile)?;
+    outfile.finish()?;
+    state.save()?;
+    Ok((
+        RunSummary {
+            entries,
+            written: pipeline.written(),
+        },
+        skipped(&reader.report().sources),
+    ))
+}
+
+/// The transforms available to `plugin` stages of pipeline configurations.
+/// Transforms of optional features are registered here.
+fn transform_registry() -> TransformRegistry {
+    let registry = TransformRegistry::new();
+    #[cfg(feature = "geoip")]
+    let registry = registry.with("geoip", |options| {
+        #[derive(serde::Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Options {
+            databases: Vec<PathBuf>,
+            #[serde(default)]
+            fields: Vec<String>,
+        }
+        let options: Options = options.clone().try_into()?;
+        let mut geoip = GeoIp::new();
+        for path in options.databases.iter() {
+            geoip = geoip.with_database(GeoIp::open(path)?);
+        }
+        for field in options.fields.iter() {
+            geoip = geoip.with_field(FieldPattern::new(field.as_str()));
+        }
+        Ok(Box::new(geoip) as Box<dyn Transform>)
+    });
+    registry
+}
+
+#[derive(Serialize)]
+struct RunSummary {
+    /// The number of entries read from the sources.
+    entries: usize,
+    written: usize,
+}
+
+impl Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "read {} entries, wrote {}", self.entries, self.written)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        s.push_str(&format!("{:02x}", b));
+        s
+    })
+}
+
+fn split(
+    out_dir: PathBuf,
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+) -> io::Result<usize> {
+    let mut reader = open_sources(&srcs, false, range, errors)?;
+
+    while reader.parse_next()?.is_some() {
+        let e = reader.get_entry();
+        let digest = hex(&sha2::Sha256::digest(e.as_bytes()));
+        let target = out_dir.join(&digest);
+        std::fs::write(target, e.as_bytes())?;
+    }
+    Ok(skipped(&reader.report().sources))
+}
+
+fn count(
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    jobs: usize,
+    progress: bool,
+) -> io::Result<CountSummary> {
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    if let Some(chunks) = parallel_chunks(&srcs, jobs, range, errors)? {
+        let read = AtomicU64::new(0);
+        let parts = chunk::fold(
+            &srcs[0],
+            &chunks,
+            || SourceSummary::new(srcs[0].clone()),
+            |s, e| {
+                s.record(e.realtime_timestamp());
+                advance(&pb, &read, e.len_bytes());
+            },
+        )?;
+        pb.finish_and_clear();
+        let mut source = SourceSummary::new(srcs[0].clone());
+        for part in parts {
+            source.absorb(part);
+        }
+        return Ok(CountSummary {
+            entries: source.entries,
+            sources: vec![source],
+        });
+    }
+    let mut reader = open_sources(&srcs, false, range, errors)?;
+    let mut sources: Vec<_> = srcs.into_iter().map(SourceSummary::new).collect();
+
+    let mut entries = 0;
+    while reader.parse_next()?.is_some() {
+        let e = reader.get_entry();
+        sources[reader.source_index().unwrap()].record(e.realtime_timestamp());
+        entries += 1;
+        pb.set_position(reader.bytes_read() as u64);
+    }
+    pb.finish_and_clear();
+    for (s, report) in sources.iter_mut().zip(reader.report().sources) {
+        s.errors = report.errors;
+        s.error = report.error;
+    }
+    Ok(CountSummary { entries, sources })
+}
+
+fn list_boots(
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    progress: bool,
+) -> io::Result<(Vec<Boot>, usize)> {
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    let mut reader = open_sources(&srcs, false, range, errors)?;
+    let mut boots = BootList::new();
+    while reader.parse_next()?.is_some() {
+        pb.set_position(reader.bytes_read() as u64);
+        boots.push(&reader.get_entry());
+    }
+    pb.finish_and_clear();
+    Ok((boots.into_boots(), skipped(&reader.report().sources)))
+}
+
+#[derive(Serialize)]
+struct BootsSummary {
+    boots: Vec<Boot>,
+}
+
+impl Display for BootsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ts = |t: Option<u64>| t.map_or("-".to_string(), |t| t.to_string());
+        write!(
+            f,
+            "{:>4} {:<32} {:>16} {:>16} {:>10}",
+            "IDX", "BOOT ID", "FIRST", "LAST", "ENTRIES"
+        )?;
+        let n = self.boots.len() as i64;
+        for (i, b) in self.boots.iter().enumerate() {
+            write!(
+                f,
+                "\n{:>4} {:<32} {:>16} {:>16} {:>10}",
+                i as i64 + 1 - n,
+                b.id,
+                ts(b.first_timestamp),
+                ts(b.last_timestamp),
+                b.entries
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn alert(
+    mut rules: AlertRules,
+    webhook: Option<Webhook>,
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+) -> io::Result<(AlertSummary, usize)> {
+    let mut reader = open_sources(&srcs, true, range, errors)?;
+    let mut stdout = io::stdout();
+    let mut summary = AlertSummary {
+        entries: 0,
+        alerts: 0,
+        webhook_errors: 0,
+    };
+    while !shutdown().is_requested() && reader.parse_next()?.is_some() {
+        summary.entries += 1;
+        for alert in rules.check(&reader.get_entry()) {
+            summary.alerts += 1;
+            serde_json::to_writer(&mut stdout, &alert)?;
+            writeln!(stdout)?;
+            stdout.flush()?;
+            // A webhook that is down must not stop the watcher.
+            if let Err(e) = webhook.as_ref().map_or(Ok(()), |w| w.send(&alert)) {
+                summary.webhook_errors += 1;
+                eprintln!("Failed to post alert: {}", e);
+            }
+        }
+    }
+    Ok((summary, skipped(&reader.report().sources)))
+}
+
+#[derive(Serialize)]
+struct AlertSummary {
+    entries: u64,
+    alerts: u64,
+    webhook_errors: u64,
+}
+
+impl Display for AlertSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Checked {} entries, raised {} alerts",
+            self.entries, self.alerts
+        )?;
+        if self.webhook_errors > 0 {
+            write!(f, " ({} failed to post)", self.webhook_errors)?;
+        }
+        Ok(())
+    }
+}
+
+fn units(
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    progress: bool,
+) -> io::Result<(UnitsSummary, usize)> {
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    let mut reader = open_sources(&srcs, false, range, errors)?;
+    // systemd logs about a unit with UNIT rather than _SYSTEMD_UNIT.
+    let mut groups = GroupBy::new(group::UNIT, |_| Ok(GroupStats::default())).with_fallback("UNIT");
+    while reader.parse_next()?.is_some() {
+        pb.set_position(reader.bytes_read() as u64);
+        groups.push(&reader.get_entry())?;
+    }
+    pb.finish_and_clear();
+    let mut units: Vec<_> = groups
+        .into_groups()
+        .into_iter()
+        .map(|(unit, stats)| UnitSummary {
+            unit: unit.map(|u| String::from_utf8_lossy(&u).into_owned()),
+            error_rate: stats.error_rate(),
+            stats,
+        })
+        .collect();
+    units.sort_by_key(|u| std::cmp::Reverse(u.stats.entries));
+    Ok((UnitsSummary { units }, skipped(&reader.report().sources)))
+}
+
+#[derive(Serialize)]
+struct UnitSummary {
+    unit: Option<String>,
+    #[serde(flatten)]
+    stats: GroupStats,
+    error_rate: f64,
+}
+
+#[derive(Serialize)]
+struct UnitsSummary {
+    units: Vec<UnitSummary>,
+}
+
+impl Display for UnitsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ts = |t: Option<u64>| t.map_or("-".to_string(), |t| t.to_string());
+        write!(
+            f,
+            "{:<40} {:>10} {:>10} {:>10} {:>16} {:>16}",
+            "UNIT", "ENTRIES", "ERRORS", "ERROR-RATE", "FIRST", "LAST"
+        )?;
+        for u in &self.units {
+            write!(
+                f,
+                "\n{:<40} {:>10} {:>10} {:>9.2}% {:>16} {:>16}",
+                u.unit.as_deref().unwrap_or("-"),
+                u.stats.entries,
+                u.stats.errors,
+                u.error_rate * 100.0,
+                ts(u.stats.first_timestamp),
+                ts(u.stats.last_timestamp)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn scan_secrets(
+    mut scanner: SecretScanner,
+    redact: Option<&Path>,
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    progress: bool,
+) -> io::Result<(SecretsSummary, usize)> {
+    if redact.is_some() {
+        if let Some(src) = srcs.iter().find(|s| !rereadable(s)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("--redact cannot read {} twice", src.display()),
+            ));
+        }
+    }
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    let mut reader = open_sources(&srcs, false, range, errors)?;
+    let mut findings = vec![];
+    while reader.parse_next()?.is_some() {
+        pb.set_position(reader.bytes_read() as u64);
+        findings.extend(scanner.scan(&reader.get_entry()));
+    }
+    pb.finish_and_clear();
+
+    let mut redacted = None;
+    if let Some(out) = redact {
+        let mut pipeline = Pipeline::new().with_transform(secrets::redaction(&findings));
+        let mut writer: Box<dyn Write> = match is_stdio(out) {
+            true => Box::new(io::stdout().lock()),
+            false => Box::new(File::create(out)?),
+        };
+        let mut writer = BufWriter::new(&mut writer);
+        let mut reader = open_sources(&srcs, false, range, errors)?;
+        while reader.parse_next()?.is_some() {
+            pipeline.process(
+                reader.source_index().unwrap(),
+                &reader.get_entry(),
+                &mut writer,
+            )?;
+        }
+        pipeline.finish(&mut writer)?;
+        writer.flush()?;
+        redacted = Some(pipeline.written());
+    }
+    Ok((
+        SecretsSummary { findings, redacted },
+        skipped(&reader.report().sources),
+    ))
+}
+
+#[derive(Serialize)]
+struct SecretsSummary {
+    findings: Vec<Finding>,
+    /// The number of entries written to the redacted output.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redacted: Option<usize>,
+}
+
+impl Display for SecretsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_dash = |s: Option<String>| s.unwrap_or_else(|| "-".to_string());
+        write!(
+            f,
+            "{:>8} {:>16} {:<14} {:<24} {:<24} CURSOR",
+            "ENTRY", "TIME", "KIND", "FIELD", "EXCERPT"
+        )?;
+        for s in &self.findings {
+            write!(
+                f,
+                "\n{:>8} {:>16} {:<14} {:<24} {:<24} {}",
+                s.entry,
+                or_dash(s.timestamp.map(|t| t.to_string())),
+                s.kind.name(),
+                s.field,
+                s.excerpt,
+                or_dash(s.cursor.clone())
+            )?;
+        }
+        if let Some(redacted) = self.redacted {
+            write!(f, "\nwrote {} redacted entries", redacted)?;
+        }
+        Ok(())
+    }
+}
+
+fn usage_report(
+    mut usage: DiskUsage,
+    top: usize,
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    progress: bool,
+) -> io::Result<(UsageSummary, usize)> {
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    let mut reader = open_sources(&srcs, false, range, errors)?;
+    while reader.parse_next()?.is_some() {
+        pb.set_position(reader.bytes_read() as u64);
+        usage.push(&reader.get_entry())?;
+    }
+    pb.finish_and_clear();
+    let mut report = usage.into_report();
+    report.keys.truncate(top);
+    report.fields.truncate(top);
+    Ok((UsageSummary { report }, skipped(&reader.report().sources)))
+}
+
+#[derive(Serialize)]
+struct UsageSummary {
+    #[serde(flatten)]
+    report: UsageReport,
+}
+
+impl Display for UsageSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let r = &self.report;
+        let share = |bytes: u64| match r.total.bytes {
+            0 => 0.0,
+            total => bytes as f64 * 100.0 / total as f64,
+        };
+        write!(
+            f,
+            "{:<40} {:>10} {:>14} {:>7} {:>14}",
+            "KEY", "ENTRIES", "BYTES", "SHARE", "BINARY-BYTES"
+        )?;
+        for k in &r.keys {
+            write!(
+                f,
+                "\n{:<40} {:>10} {:>14} {:>6.2}% {:>14}",
+                k.key.as_deref().unwrap_or("-"),
+                k.usage.entries,
+                k.usage.bytes,
+                share(k.usage.bytes),
+                k.usage.binary_bytes
+            )?;
+        }
+        write!(
+            f,
+            "\n{:<40} {:>10} {:>14} {:>7} {:>14}",
+            "TOTAL", r.total.entries, r.total.bytes, "", r.total.binary_bytes
+        )?;
+        write!(
+            f,
+            "\n\n{:<40} {:>10} {:>14} {:>7} {:>14}",
+            "FIELD", "ENTRIES", "BYTES", "SHARE", "BINARY"
+        )?;
+        for field in &r.fields {
+            write!(
+                f,
+                "\n{:<40} {:>10} {:>14} {:>6.2}% {:>14}",
+                field.name,
+                field.entries,
+                field.bytes,
+                share(field.bytes),
+                field.binary
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FieldOrder {
+    Name,
+    Entries,
+    Bytes,
+}
+
+fn fields(
+    sort: FieldOrder,
+    custom: bool,
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    progress: bool,
+) -> io::Result<(FieldsSummary, usize)> {
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    let mut reader = open_sources(&srcs, false, range, errors)?;
+    let mut catalog = FieldCatalog::new();
+    while reader.parse_next()?.is_some() {
+        pb.set_position(reader.bytes_read() as u64);
+        catalog.push(&reader.get_entry());
+    }
+    pb.finish_and_clear();
+    let entries = catalog.entries();
+    let mut fields = catalog.into_fields();
+    fields.retain(|f| !custom || !f.known);
+    match sort {
+        FieldOrder::Name => {}
+        FieldOrder::Entries => fields.sort_by_key(|f| std::cmp::Reverse(f.entries)),
+        FieldOrder::Bytes => fields.sort_by_key(|f| std::cmp::Reverse(f.bytes)),
+    }
+    Ok((
+        FieldsSummary { entries, fields },
+        skipped(&reader.report().sources),
+    ))
+}
+
+#[derive(Serialize)]
+struct FieldsSummary {
+    entries: u64,
+    fields: Vec<FieldInfo>,
+}
+
+impl Display for FieldsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<32} {:<7} {:>10} {:>10} {:>14}  EXAMPLE",
+            "FIELD", "KIND", "ENTRIES", "VALUES", "BYTES"
+        )?;
+        for field in &self.fields {
+            write!(
+                f,
+                "\n{:<32} {:<7} {:>10} {:>10} {:>14}  {}",
+                field.name,
+                match field.known {
+                    true => "systemd",
+                    false => "custom",
+                },
+                field.entries,
+                field.values,
+                field.bytes,
+                field.example.as_deref().unwrap_or("-")
+            )?;
+        }
+        write!(
+            f,
+            "\n\n{} fields in {} entries",
+            self.fields.len(),
+            self.entries
+        )
+    }
+}
+
+fn values(
+    mut counter: ValueCounter,
+    top: usize,
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    progress: bool,
+) -> io::Result<(ValuesSummary, usize)> {
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    let mut reader = open_sources(&srcs, false, range, errors)?;
+    while reader.parse_next()?.is_some() {
+        pb.set_position(reader.bytes_read() as u64);
+        counter.push(&reader.get_entry());
+    }
+    pb.finish_and_clear();
+    Ok((
+        ValuesSummary {
+            report: counter.into_report(top),
+        },
+        skipped(&reader.report().sources),
+    ))
+}
+
+#[derive(Serialize)]
+struct ValuesSummary {
+    #[serde(flatten)]
+    report: ValuesReport,
+}
+
+impl Display for ValuesSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let r = &self.report;
+        match r.approximate {
+            true => write!(f, "{:>10} {:>10}  {}", "COUNT", "ERROR", r.field)?,
+            false => write!(f, "{:>10}  {}", "COUNT", r.field)?,
+        }
+        for v in &r.values {
+            match r.approximate {
+                true => write!(f, "\n{:>10} {:>10}  {}", v.count, v.error, v.value)?,
+                false => write!(f, "\n{:>10}  {}", v.count, v.value)?,
+            }
+        }
+        write!(f, "\n\n")?;
+        if let Some(distinct) = r.distinct {
+            write!(f, "{} distinct values, ", distinct)?;
+        }
+        write!(
+            f,
+            "{} entries, {} without {}",
+            r.entries, r.missing, r.field
+        )
+    }
+}
+
+fn sessions(
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    progress: bool,
+) -> io::Result<(SessionsSummary, usize)> {
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    let mut reader = open_sources(&srcs, false, range, errors)?;
+    let mut sessions = Sessions::new();
+    while reader.parse_next()?.is_some() {
+        pb.set_position(reader.bytes_read() as u64);
+        sessions.push(&reader.get_entry())?;
+    }
+    pb.finish_and_clear();
+    Ok((
+        SessionsSummary {
+            sessions: sessions.into_sessions(),
+        },
+        skipped(&reader.report().sources),
+    ))
+}
+
+#[derive(Serialize)]
+struct SessionsSummary {
+    sessions: Vec<Session>,
+}
+
+impl Display for SessionsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ts = |t: Option<u64>| t.map_or("-".to_string(), |t| t.to_string());
+        write!(
+            f,
+            "{:<10} {:<16} {:>16} {:>16} {:>10}",
+            "SESSION", "USER", "START", "END", "ENTRIES"
+        )?;
+        for s in &self.sessions {
+            let user = match (&s.user, s.login_uid) {
+                (Some(user), _) => user.clone(),
+                (None, Some(uid)) => format!("uid {}", uid),
+                (None, None) => "-".to_string(),
+            };
+            write!(
+                f,
+                "\n{:<10} {:<16} {:>16} {:>16} {:>10}",
+                s.id,
+                user,
+                ts(s.start),
+                ts(s.end),
+                s.entries
+            )?;
+            for command in &s.commands {
+                write!(f, "\n    {}", command)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn coredumps(
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    extract: Option<&Path>,
+    progress: bool,
+) -> io::Result<(CoredumpsSummary, usize)> {
+    if let Some(dir) = extract {
+        std::fs::create_dir_all(dir)?;
+    }
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    let mut reader = open_sources(&srcs, false, range, errors)?;
+    let mut coredumps = vec![];
+    while reader.parse_next()?.is_some() {
+        pb.set_position(reader.bytes_read() as u64);
+        let e = reader.get_entry();
+        let Some(coredump) = Coredump::from_entry(&e) else {
+            continue;
+        };
+        let extracted = match extract {
+            Some(dir) => coredump.extract(&e, dir)?,
+            None => None,
+        };
+        coredumps.push(CoredumpSummary {
+            coredump,
+            extracted,
+        });
+    }
+    pb.finish_and_clear();
+    Ok((
+        CoredumpsSummary { coredumps },
+        skipped(&reader.report().sources),
+    ))
+}
+
+#[derive(Serialize)]
+struct CoredumpSummary {
+    #[serde(flatten)]
+    coredump: Coredump,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extracted: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct CoredumpsSummary {
+    coredumps: Vec<CoredumpSummary>,
+}
+
+impl Display for CoredumpsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_dash = |s: Option<String>| s.unwrap_or_else(|| "-".to_string());
+        write!(
+            f,
+            "{:>16} {:>8} {:<8} {:<32} {:<32} CORE",
+            "TIME", "PID", "SIGNAL", "EXE", "UNIT"
+        )?;
+        for c in &self.coredumps {
+            let d = &c.coredump;
+            let core = match (&c.extracted, d.core_size, &d.filename) {
+                (Some(path), _, _) => path.display().to_string(),
+                (None, Some(size), _) => format!("embedded ({} bytes)", size),
+                (None, None, Some(filename)) => filename.clone(),
+                (None, None, None) => "-".to_string(),
+            };
+            write!(
+                f,
+                "\n{:>16} {:>8} {:<8} {:<32} {:<32} {}",
+                or_dash(d.timestamp.map(|t| t.to_string())),
+                or_dash(d.pid.map(|p| p.to_string())),
+                or_dash(d.signal_name.clone().or(d.signal.map(|s| s.to_string()))),
+                or_dash(d.exe.clone().or(d.comm.clone())),
+                or_dash(d.unit.clone()),
+                core
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn bursts(
+    mut detector: BurstDetector,
+    top: usize,
+    mut pipeline: Pipeline,
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    progress: bool,
+) -> io::Result<(BurstsSummary, usize)> {
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    let mut reader = open_sources(&srcs, false, range, errors)?;
+    while reader.parse_next()?.is_some() {
+        pb.set_position(reader.bytes_read() as u64);
+        pipeline.process(
+            reader.source_index().unwrap(),
+            &reader.get_entry(),
+            &mut detector,
+        )?;
+    }
+    pb.finish_and_clear();
+    pipeline.finish(&mut detector)?;
+    Ok((
+        BurstsSummary {
+            bursts: detector.into_bursts(top),
+        },
+        skipped(&reader.report().sources),
+    ))
+}
+
+#[derive(Serialize)]
+struct BurstsSummary {
+    bursts: Vec<KeyBurst>,
+}
+
+impl Display for BurstsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<32} {:>16} {:>16} {:>10} {:>10} {:>10}",
+            "KEY", "START", "END", "ENTRIES", "RATE/S", "USUAL/S"
+        )?;
+        for b in &self.bursts {
+            write!(
+                f,
+                "\n{:<32} {:>16} {:>16} {:>10} {:>10.1} {:>10.3}",
+                b.key, b.start, b.end, b.entries, b.rate, b.baseline
+            )?;
+            for sample in &b.samples {
+                write!(f, "\n    {}", sample)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn auth_report(
+    mut analyzer: AuthAnalyzer,
+    mut pipeline: Pipeline,
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    progress: bool,
+) -> io::Result<(AuthSummary, usize)> {
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    let mut reader = open_sources(&srcs, false, range, errors)?;
+    while reader.parse_next()?.is_some() {
+        pb.set_position(reader.bytes_read() as u64);
+        pipeline.process(
+            reader.source_index().unwrap(),
+            &reader.get_entry(),
+            &mut analyzer,
+        )?;
+    }
+    pb.finish_and_clear();
+    pipeline.finish(&mut analyzer)?;
+    Ok((
+        AuthSummary {
+            report: analyzer.into_report(),
+        },
+        skipped(&reader.report().sources),
+    ))
+}
+
+#[derive(Serialize)]
+struct AuthSummary {
+    #[serde(flatten)]
+    report: AuthReport,
+}
+
+impl Display for AuthSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_dash = |s: Option<String>| s.unwrap_or_else(|| "-".to_string());
+        let ts = |t: Option<u64>| or_dash(t.map(|t| t.to_string()));
+        let r = &self.report;
+        write!(
+            f,
+            "{:<16} {:<40} {:>8} {:>8} {:>16} {:>16}",
+            "USER", "ADDRESS", "FAILED", "OK", "FIRST", "LAST"
+        )?;
+        for l in &r.logins {
+            write!(
+                f,
+                "\n{:<16} {:<40} {:>8} {:>8} {:>16} {:>16}",
+                l.user,
+                or_dash(l.address.clone()),
+                l.failed,
+                l.succeeded,
+                ts(l.first_timestamp),
+                ts(l.last_timestamp)
+            )?;
+        }
+        if !r.bursts.is_empty() {
+            write!(
+                f,
+                "\n\n{:<40} {:>16} {:>16} {:>8} USERS",
+                "BURST FROM", "START", "END", "FAILED"
+            )?;
+            for b in &r.bursts {
+                let users: Vec<_> = b.users.iter().map(String::as_str).collect();
+                write!(
+                    f,
+                    "\n{:<40} {:>16} {:>16} {:>8} {}",
+                    b.address,
+                    b.start,
+                    b.end,
+                    b.failures,
+                    users.join(",")
+                )?;
+            }
+        }
+        if !r.sudo.is_empty() {
+            write!(f, "\n\n{:>16} {:<16} {:<16} COMMAND", "SUDO", "USER", "AS")?;
+            for s in &r.sudo {
+                let command = or_dash(s.command.clone());
+                write!(
+                    f,
+                    "\n{:>16} {:<16} {:<16} {}{}",
+                    ts(s.timestamp),
+                    s.user,
+                    or_dash(s.target.clone()),
+                    command,
+                    if s.denied { " (denied)" } else { "" }
+                )?;
+            }
+        }
+        if !r.users.is_empty() {
+            write!(f, "\n\n{:>16} {:<8} {:<16} UID", "USERS", "EVENT", "USER")?;
+            for u in &r.users {
+                let kind = match u.kind {
+                    UserEventKind::Added => "added",
+                    UserEventKind::Deleted => "deleted",
+                };
+                write!(
+                    f,
+                    "\n{:>16} {:<8} {:<16} {}",
+                    ts(u.timestamp),
+                    kind,
+                    u.user,
+                    or_dash(u.uid.map(|u| u.to_string()))
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn incidents(
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    progress: bool,
+) -> io::Result<(IncidentsSummary, usize)> {
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    let mut reader = open_sources(&srcs, false, range, errors)?;
+    let mut detector = KernelDetector::new();
+    while reader.parse_next()?.is_some() {
+        pb.set_position(reader.bytes_read() as u64);
+        detector.push(&reader.get_entry());
+    }
+    pb.finish_and_clear();
+    Ok((
+        IncidentsSummary {
+            incidents: detector.into_incidents(),
+        },
+        skipped(&reader.report().sources),
+    ))
+}
+
+#[derive(Serialize)]
+struct IncidentsSummary {
+    incidents: Vec<Incident>,
+}
+
+impl Display for IncidentsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_dash = |s: Option<String>| s.unwrap_or_else(|| "-".to_string());
+        write!(
+            f,
+            "{:>16} {:<14} {:>8} {:<16} MESSAGE",
+            "TIME", "KIND", "PID", "PROCESS"
+        )?;
+        for i in &self.incidents {
+            let kind = match i.kind {
+                IncidentKind::Oom => "oom",
+                IncidentKind::Segfault => "segfault",
+                IncidentKind::HardwareError => "hardware-error",
+            };
+            write!(
+                f,
+                "\n{:>16} {:<14} {:>8} {:<16} {}",
+                or_dash(i.timestamp.map(|t| t.to_string())),
+                kind,
+                or_dash(i.pid.map(|p| p.to_string())),
+                or_dash(i.process.clone()),
+                i.message
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn timeline(
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    unit: Option<String>,
+    progress: bool,
+) -> io::Result<(TimelineSummary, usize)> {
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    let mut reader = open_sources(&srcs, false, range, errors)?;
+    let mut timeline = Timeline::new();
+    if let Some(unit) = unit {
+        timeline = match unit.contains('.') {
+            true => timeline.with_unit(unit),
+            false => timeline.with_unit(format!("{}.service", unit)),
+        };
+    }
+    while reader.parse_next()?.is_some() {
+        pb.set_position(reader.bytes_read() as u64);
+        timeline.push(&reader.get_entry());
+    }
+    pb.finish_and_clear();
+    Ok((
+        TimelineSummary {
+            events: timeline.into_events(),
+        },
+        skipped(&reader.report().sources),
+    ))
+}
+
+#[derive(Serialize)]
+struct TimelineSummary {
+    events: Vec<UnitEvent>,
+}
+
+impl Display for TimelineSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>16} {:<32} {:<17} {:>12} MESSAGE",
+            "TIME", "UNIT", "EVENT", "DURATION"
+        )?;
+        for e in &self.events {
+            write!(
+                f,
+                "\n{:>16} {:<32} {:<17} {:>12} {}",
+                e.timestamp.map_or("-".to_string(), |t| t.to_string()),
+                e.unit,
+                e.event.as_str(),
+                e.duration
+                    .map_or("-".to_string(), |d| format!("{:.3}s", d as f64 / 1e6)),
+                e.message.as_deref().unwrap_or("")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn stats(
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    jobs: usize,
+    progress: bool,
+) -> io::Result<(StatsSummary, usize)> {
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    let record = |(source, bytes, fields): &mut (SourceSummary, usize, usize), e: &RefEntry| {
+        source.record(e.realtime_timestamp());
+        *bytes += e.len_bytes();
+        *fields += e.field_count();
+    };
+    let mut totals = (SourceSummary::new(PathBuf::new()), 0, 0);
+    let mut skipped_sources = 0;
+    if let Some(chunks) = parallel_chunks(&srcs, jobs, range, errors)? {
+        let read = AtomicU64::new(0);
+        let parts = chunk::fold(
+            &srcs[0],
+            &chunks,
+            || (SourceSummary::new(PathBuf::new()), 0, 0),
+            |totals, e| {
+                record(totals, e);
+                advance(&pb, &read, e.len_bytes());
+            },
+        )?;
+        for (source, bytes, fields) in parts {
+            totals.0.absorb(source);
+            totals.1 += bytes;
+            totals.2 += fields;
+        }
+    } else {
+        let mut reader = open_sources(&srcs, false, range, errors)?;
+        while reader.parse_next()?.is_some() {
+            record(&mut totals, &reader.get_entry());
+            pb.set_position(reader.bytes_read() as u64);
+        }
+        skipped_sources = skipped(&reader.report().sources);
+    }
+    pb.finish_and_clear();
+    let (source, bytes, fields) = totals;
+    let summary = StatsSummary {
+        entries: source.entries,
+        bytes,
+        fields,
+        first_timestamp: source.first_timestamp,
+        last_timestamp: source.last_timestamp,
+    };
+    Ok((summary, skipped_sources))
+}
+
+fn verify(src: PathBuf, progress: bool) -> io::Result<VerifySummary> {
+    let pb = progress_bar(progress, source_len(&src)?);
+    let mut jreader = JournalExportRead::new(open_source(&src)?);
+
+    let mut entries = 0;
+    let mut bytes = 0;
+    let error = loop {
+        match jreader.parse_next() {
+            Ok(None) => break None,
+            Ok(_) => (),
+            Err(e) if matches!(e.kind(), ErrorKind::IoError(_)) => return Err(e.into()),
+            Err(e) => break Some(located(&jreader, &src, e)),
+        }
+        entries += 1;
+        bytes += jreader.get_entry().len_bytes();
+        pb.set_position(jreader.bytes_read() as u64);
+    };
+    pb.finish_and_clear();
+    Ok(VerifySummary {
+        entries,
+        bytes,
+        error: error.as_ref().map(|e| e.error.to_string()),
+        position: error.as_ref().map(ErrorReport::new),
+    })
+}
+
+fn sort(
+    mut sorter: ExternalSort,
+    dst: Destination,
+    srcs: Vec<PathBuf>,
+    range: &Range,
+    errors: &Errors,
+    progress: bool,
+) -> io::Result<usize> {
+    let pb = progress_bar(progress, total_len(&srcs, errors)?);
+    let mut reader = open_sources(&srcs, false, range, errors)?;
+    while reader.parse_next()?.is_some() {
+        sorter.push(&reader.get_entry())?;
+        pb.set_position(reader.bytes_read() as u64);
+    }
+    pb.finish_and_clear();
+
+    let mut sorted = sorter.finish()?;
+    let resume = dst.resume_point()?;
+    let mut outfile = dst.open()?;
+    while sorted.parse_next()?.is_some() {
+        let e = sorted.get_entry();
+        if !resume.skips(&e) {
+            outfile.write_entry(e.as_bytes())?;
+        }
+    }
+    outfile.finish()?;
+    Ok(skipped(&reader.report().sources))
+}
+
+fn dedup_journal<S: KeySet>(
+    mut dedup: Dedup<S>,
+    dst: Destination,
+    src: PathBuf,
+    progress: bool,
+) -> io::Result<DedupSummary> {
+    let pb = progress_bar(progress, source_len(&src)?);
+    let mut jreader = JournalExportRead::new(open_source(&src)?);
+    let mut resume = dst.resume_point()?;
+    let mut outfile = dst.open()?;
+
+    let mut entries = 0;
+    while (jreader.parse_next())
+        .map_err(|e| located(&jreader, &src, e))?
+        .is_some()
+    {
+        let e = jreader.get_entry();
+        pb.set_position(jreader.bytes_read() as u64);
+        if dedup.is_duplicate(&e)? || resume.skips_in_sequence(&e) {
+            continue;
+        }
+        outfile.write_entry(e.as_bytes())?;
+        entries += 1;
+    }
+    pb.finish_and_clear();
+    resumed(&resume)?;
+    outfile.finish()?;
+    Ok(DedupSummary {
+        entries,
+        duplicates: dedup.duplicates(),
+    })
+}
+
+fn lossy(v: &[u8]) -> String {
+    String::from_utf8_lossy(v).into_owned()
+}
+
+impl EntryDiff {
+    fn new(kind: char, e: &impl Entry) -> Self {
+        Self {
+            kind,
+            timestamp: e.realtime_timestamp(),
+            cursor: e.get(b"__CURSOR").map(lossy),
+            fields: vec![],
+        }
+    }
+}
+
+fn diff(left: PathBuf, right: PathBuf, fields: bool) -> io::Result<DiffSummary> {
+    let mut diff = ExportDiff::new(
+        JournalExportRead::new(open_source(&left)?),
+        JournalExportRead::new(open_source(&right)?),
+    );
+    let mut differences = vec![];
+    while let Some(d) = diff.next_difference()? {
+        differences.push(match d {
+            Difference::Left(e) => EntryDiff::new('<', &e),
+            Difference::Right(e) => EntryDiff::new('>', &e),
+            Difference::Changed(l, r) => {
+                let mut d = EntryDiff::new('~', &l);
+                if fields {
+                    d.fields = field_changes(&l, &r)
+                        .into_iter()
+                        .map(|c| FieldDiff {
+                            name: lossy(&c.name),
+                            left: c.left.iter().map(|v| lossy(v)).collect(),
+                            right: c.right.iter().map(|v| lossy(v)).collect(),
+                        })
+                        .collect();
+                }
+                d
+            }
+        });
+    }
+    Ok(DiffSummary {
+        matched: diff.matched(),
+        differences,
+    })
+}
+
+fn check_order(
+    srcs: Vec<PathBuf>,
+    tolerance: Duration,
+    progress: bool,
+) -> io::Result<CheckOrderSummary> {
+    let pb = progress_bar(progress, total_len(&srcs, &Errors::default())?);
+    let mut sources = vec![];
+    let mut done = 0;
+    for path in srcs {
+        let mut jreader = JournalExportRead::new(open_source(&path)?);
+        let mut checker = OrderChecker::new().with_tolerance(tolerance.as_micros() as u64);
+        let mut violations = vec![];
+        while (jreader.parse_next())
+            .map_err(|e| located(&jreader, &path, e))?
+            .is_some()
+        {
+            violations.extend(checker.check(&jreader.get_entry()));
+            pb.set_position((done + jreader.bytes_read()) as u64);
+        }
+        done += jreader.bytes_read();
+        sources.push(SourceOrderSummary {
+            path,
+            entries: checker.entries(),
+            violations,
+        });
+    }
+    pb.finish_and_clear();
+    Ok(CheckOrderSummary { sources })
+}
+
+fn validate(schema: &Schema, srcs: Vec<PathBuf>, progress: bool) -> io::Result<ValidateSummary> {
+    let pb = progress_bar(progress, total_len(&srcs, &Errors::default())?);
+    let mut sources = vec![];
+    let mut done = 0;
+    for path in srcs {
+        let mut jreader = JournalExportRead::new(open_source(&path)?);
+        let mut entries = 0;
+        let mut invalid = vec![];
+        while (jreader.parse_next())
+            .map_err(|e| located(&jreader, &path, e))?
+            .is_some()
+        {
+            let entry = jreader.get_entry();
+            let violations = schema.validate(&entry);
+            if !violations.is_empty() {
+                invalid.push(InvalidEntry {
+                    index: entries,
+                    offset: jreader.position() - entry.len_bytes(),
+                    violations,
+                });
+            }
+            entries += 1;
+            pb.set_position((done + jreader.bytes_read()) as u64);
+        }
+        done += jreader.bytes_read();
+        sources.push(SourceValidateSummary {
+            path,
+            entries,
+            invalid,
+        });
+    }
+    pb.finish_and_clear();
+    Ok(ValidateSummary { sources })
+}
+
+fn load_catalog(dirs: &[PathBuf]) -> io::Result<Catalog> {
+    let mut catalog = Catalog::new();
+    if dirs.is_empty() {
+        catalog.load_dir(Path::new(catalog::DEFAULT_DIR))?;
+    }
+    for dir in dirs {
+        catalog.load_dir(dir)?;
+    }
+    Ok(catalog)
+}
+
+/// How [show_entry] selects the entry.
+enum EntryAt {
+    Index(usize),
+    Cursor(String),
+    /// The first entry with a timestamp at or after this one.
+    Time(u64),
+}
+
+fn show_entry(
+    src: PathBuf,
+    which: EntryAt,
+    catalog: Option<&Catalog>,
+    binary: BinaryRendering,
+) -> io::Result<()> {
+    let read = match which {
+        EntryAt::Time(ts) if seekable(&src) => {
+            let mut f = File::open(&src)?;
+            source::seek_to_time(&mut f, ts)?;
+            Box::new(f)
+        }
+        _ => open_source(&src)?,
+    };
+    let mut jreader = JournalExportRead::new(read);
+
+    let mut count = 0;
+    loop {
+        match jreader.parse_next() {
+            Ok(None) => break,
+            Ok(_) => (),
+            Err(e) => return Err(located(&jreader, &src, e).into()),
+        }
+
+        let entry = jreader.get_entry();
+        let found = match &which {
+            EntryAt::Index(n) => count == *n,
+            EntryAt::Cursor(cursor) => entry.get(b"__CURSOR") == Some(cursor.as_bytes()),
+            EntryAt::Time(ts) => entry.realtime_timestamp().is_some_and(|t| t >= *ts),
+        };
+        if found {
+            for (name, content, _) in entry.iter() {
+                let name = String::from_utf8_lossy(name);
+                if let Some(content) = binary.render(content) {
+                    println!("{}={}", name, content);
+                }
+            }
+            if let Some(explanation) = catalog.and_then(|c| c.explain(&entry)) {
+                println!();
+                for line in explanation.lines() {
+                    println!("-- {}", line);
+                }
+            }
+            return Ok(());
+        }
+        count += 1;
+    }
+    match which {
+        EntryAt::Index(_) => Ok(()),
+        EntryAt::Cursor(cursor) => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no entry with cursor {}", cursor),
+        )),
+        EntryAt::Time(_) => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no entry at or after the given time",
+        )),
+    }
+}
+
+fn generate(
+    dst: Destination,
+    entries: usize,
+    units: usize,
+    rate_profile: RateProfile,
+    seed: u64,
+) -> io::Result<()> {
+    let mut outfile = dst.open()?;
+    let mut generator = EntryGenerator::new(seed)
+        .with_units(units.max(1))
+        .with_rate_profile(rate_profile);
+
+    let mut buf = vec![];
+    for _ in 0..entries {
+        buf.clear();
+        generator.write_entry(&mut buf);
+        outfile.write_entry(&buf)?;
+    }
+    outfile.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, io::Read};
+
+    use clap::{Arg, ArgAction, ArgMatches, Command};
+    use loginus::{
+        generate::EntryGenerator, pipeline::Pipeline, reassemble::Reassemble,
+        transform::read_emitted,
+    };
+
+    use super::{bundle, open_sources, skipped, Defaults, Errors, Range, Redact};
+
+    fn command() -> Command {
+        let format = Arg::new("format").long("format");
+        let fields = Arg::new("fields")
+            .long("fields")
+            .value_delimiter(',')
+            .action(ArgAction::Append);
+        let mut command = Command::new("loginus")
+            .arg(Arg::new("limit").long("limit"))
+            .subcommand(Command::new("cat").arg(format.clone()).arg(fields))
+            .subcommand(Command::new("count").arg(format));
+        command.build();
+        command
+    }
+
+    fn matches(defaults: &Defaults, args: &[&str]) -> ArgMatches {
+        let command = defaults.apply(command()).unwrap();
+        let args = ["loginus"].iter().chain(args);
+        command.try_get_matches_from(args).unwrap()
+    }
+
+    fn values_of(matches: &ArgMatches, command: &str, option: &str) -> Vec<String> {
+        let matches = matches.subcommand_matches(command).unwrap();
+        matches
+            .get_many::<String>(option)
+            .map_or(vec![], |v| v.cloned().collect())
+    }
+
+    #[test]
+    fn defaults_are_layered() {
+        let mut defaults = Defaults::default();
+        defaults
+            .parse("format = \"file\"\nlimit = 10\n\n[cat]\nformat = \"cat\"\n")
+            .unwrap();
+        let format = |d: &Defaults, args: &[&str]| {
+            let command = args[0];
+            values_of(&matches(d, args), command, "format")
+        };
+        assert_eq!(format(&defaults, &["count"]), ["file"]);
+        assert_eq!(format(&defaults, &["cat"]), ["cat"]);
+        assert_eq!(
+            matches(&defaults, &["cat"])
+                .get_one::<String>("limit")
+                .unwrap(),
+            "10"
+        );
+
+        defaults.env.insert("format".into(), vec!["env".into()]);
+        assert_eq!(format(&defaults, &["count"]), ["env"]);
+        assert_eq!(format(&defaults, &["cat"]), ["env"]);
+        assert_eq!(format(&defaults, &["cat", "--format", "cli"]), ["cli"]);
+    }
+
+    #[test]
+    fn unknown_options_and_commands_are_rejected() {
+        let apply = |text: &str| {
+            let mut defaults = Defaults::default();
+            defaults.parse(text).unwrap();
+            defaults.apply(command()).err()
+        };
+        assert_eq!(apply("nope = 1").unwrap(), "unknown option: nope");
+        assert_eq!(apply("[cat]\nnope = 1").unwrap(), "unknown option: nope");
+        assert_eq!(
+            apply("[nope]\nformat = \"json\"").unwrap(),
+            "unknown command: nope"
+        );
+        assert_eq!(apply("[count]\nformat = \"json\""), None);
+
+        let mut defaults = Defaults::default();
+        assert!(defaults.parse("[cat]\nformat = {}").is_err());
+        assert!(defaults.parse("fields = [[\"A\"]]").is_err());
+    }
+
+    #[test]
+    fn delimited_values_are_split() {
+        let mut defaults = Defaults::default();
+        defaults.parse("fields = [\"A\", \"B\"]").unwrap();
+        assert_eq!(
+            values_of(&matches(&defaults, &["cat"]), "cat", "fields"),
+            ["A", "B"]
+        );
+
+        defaults.env.insert("fields".into(), vec!["D,E".into()]);
+        assert_eq!(
+            values_of(&matches(&defaults, &["cat"]), "cat", "fields"),
+            ["D", "E"]
+        );
+        assert_eq!(
+            values_of(
+                &matches(&defaults, &["cat", "--fields", "F,G"]),
+                "cat",
+                "fields"
+            ),
+            ["F", "G"]
+        );
+    }
+
+    #[test]
+    fn bundle_stats_count_entries_beyond_the_default_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut stream = vec![];
+        for i in 0..300 {
+            let message = match i {
+                0 => "Exception in thread main".to_owned(),
+                i => format!(
+                    "    at frame {} of the stack trace of a request that failed",
+                    i
+                ),
+            };
+            let entry = format!(
+                "__REALTIME_TIMESTAMP=1700000000000000\n_PID=1\nMESSAGE={}\n\n",
+                message
+            );
+            stream.extend_from_slice(entry.as_bytes());
+        }
+        let src = dir.path().join("trace.export");
+        std::fs::write(&src, &stream).unwrap();
+
+        let out = dir.path().join("bundle.tar");
+        let pipeline = Pipeline::new().with_transform(Reassemble::new());
+        let summary = bundle(
+            &out,
+            Redact::None,
+            pipeline,
+            vec![src],
+            &Range::default(),
+            &Errors::default(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(summary.entries, 1);
+
+        let mut files = BTreeMap::new();
+        let mut archive = tar::Archive::new(std::fs::File::open(&out).unwrap());
+        for file in archive.entries().unwrap() {
+            let mut file = file.unwrap();
+            let name = file.path().unwrap().to_string_lossy().into_owned();
+            let mut content = vec![];
+            file.read_to_end(&mut content).unwrap();
+            files.insert(name, content);
+        }
+        let export = &files["bundle/journal.export"];
+        assert!(export.len() > 12 * 1024);
+        let mut reader = read_emitted(export);
+        let mut entries = 0;
+        while reader.parse_next().unwrap().is_some() {
+            entries += 1;
+        }
+        let stats: serde_json::Value = serde_json::from_slice(&files["bundle/stats.json"]).unwrap();
+        assert_eq!(stats["entries"], entries);
+        assert_eq!(stats["bytes"], export.len());
+    }
+
+    #[test]
+    fn open_sources_applies_the_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("a.export");
+        std::fs::write(&src, EntryGenerator::new(0).generate(10)).unwrap();
+        let count = |range: &Range| {
+            let mut reader =
+                open_sources(std::slice::from_ref(&src), false, range, &Errors::default()).unwrap();
+            let mut entries = 0;
+            while reader.parse_next().unwrap().is_some() {
+                entries += 1;
+            }
+            entries
+        };
+        assert_eq!(count(&Range::default()), 10);
+        let range = Range {
+            skip: Some(2),
+            limit: Some(3),
+            ..Range::default()
+        };
+        assert_eq!(count(&range), 3);
+        let range = Range {
+            tail: Some(4),
+            skip: Some(1),
+            ..Range::default()
+        };
+        assert_eq!(count(&range), 3);
+    }
+
+    #[test]
+    fn skipped_sources_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let good = dir.path().join("good.export");
+        std::fs::write(&good, EntryGenerator::new(0).generate(10)).unwrap();
+        let bad = dir.path().join("bad.export");
+        std::fs::write(&bad, "MESSAGE=unterminated").unwrap();
+        let srcs = [good, dir.path().join("missing.export"), bad];
+        let errors = Errors {
+            fail_fast: false,
+            skip_errors: true,
+        };
+        let mut reader = open_sources(&srcs, false, &Range::default(), &errors).unwrap();
+        let mut entries = 0;
+        while reader.parse_next().unwrap().is_some() {
+            entries += 1;
+        }
+        assert_eq!(entries, 10);
+        assert_eq!(skipped(&reader.report().sources), 2);
     }
 }
@@ -0,0 +1,516 @@
+//! Read several journal export streams as one.
+//!
+//! [MultiRead] combines a number of [JournalExportRead]s by either reading them
+//! one after another ([Order::Sequential]) or by interleaving their entries
+//! according to their `__REALTIME_TIMESTAMP` ([Order::Timestamp]). The latter
+//! yields a sorted stream if each of the sources is sorted. Entries without a
+//! timestamp are emitted as soon as they are at the head of their source.
+//!
+//! Like [JournalExportRead], [MultiRead] is a stateful object: the entry that
+//! was parsed last can be accessed using [MultiRead::get_entry] and
+//! [MultiRead::source_index] tells which source it originates from.
+//! [MultiRead::report] summarizes what was read from each source so far.
+//! Errors are reported as [SourceError]s, which tell the source and the
+//! position in it at which parsing failed. With [MultiRead::with_skip_errors],
+//! a failed source is dropped and the others are read to their end.
+
+use std::io::{self, Read};
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{
+    journald::{
+        Entry, ErrorContext, ErrorKind, JournalExportRead, JournalExportReadError, RefEntry,
+    },
+    trace,
+};
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum Order {
+    /// Read the sources one after another.
+    #[default]
+    Sequential,
+    /// Interleave the entries of all sources by their timestamp.
+    Timestamp,
+}
+
+/// Statistics about the entries read from one source of a [MultiRead].
+#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize)]
+pub struct SourceReport {
+    pub entries: usize,
+    pub bytes: usize,
+    pub first_timestamp: Option<u64>,
+    pub last_timestamp: Option<u64>,
+    /// The number of entries whose timestamp is lower than that of the
+    /// preceding entry of the same source.
+    pub out_of_order: usize,
+    /// The number of errors that were skipped (see
+    /// [MultiRead::with_skip_errors]) and the message of the last one.
+    pub errors: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SourceReport {
+    pub(crate) fn record(&mut self, timestamp: Option<u64>, previous: Option<u64>) {
+        self.entries += 1;
+        if let Some(ts) = timestamp {
+            self.first_timestamp = Some(self.first_timestamp.map_or(ts, |t| t.min(ts)));
+            self.last_timestamp = Some(self.last_timestamp.map_or(ts, |t| t.max(ts)));
+            if previous.is_some_and(|p| ts < p) {
+                self.out_of_order += 1;
+            }
+        }
+    }
+}
+
+/// An error of one of the sources of a [MultiRead].
+#[derive(Debug, Error)]
+#[error("{}: {error}", self.name())]
+pub struct SourceError {
+    /// The index of the source, in the order passed to [MultiRead::new].
+    pub index: usize,
+    /// The name set with [MultiRead::with_source_names], if any.
+    pub source_name: Option<String>,
+    pub context: ErrorContext,
+    #[source]
+    pub error: JournalExportReadError,
+}
+
+impl SourceError {
+    /// The name of the source or, without one, its index.
+    pub fn name(&self) -> String {
+        match &self.source_name {
+            Some(name) => name.clone(),
+            None => format!("source {}", self.index),
+        }
+    }
+}
+
+impl From<SourceError> for io::Error {
+    fn from(e: SourceError) -> Self {
+        let kind = match e.error.kind() {
+            ErrorKind::IoError(e) => e.kind(),
+            ErrorKind::Timeout(_) => io::ErrorKind::TimedOut,
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize)]
+pub struct MergeReport {
+    pub entries: usize,
+    /// One report per source, in the order passed to [MultiRead::new].
+    pub sources: Vec<SourceReport>,
+}
+
+struct Source<R> {
+    index: usize,
+    reader: JournalExportRead<R>,
+    timestamp: Option<u64>,
+    /// Added to `timestamp` to order the entries, see
+    /// [MultiRead::with_clock_offsets].
+    offset: i64,
+}
+
+type ErrorHandler = Box<dyn FnMut(&SourceError)>;
+
+pub struct MultiRead<R> {
+    sources: Vec<Source<R>>,
+    order: Order,
+    primed: bool,
+    current: Option<usize>,
+    // Bytes read by sources that were already exhausted.
+    done_bytes: usize,
+    reports: Vec<SourceReport>,
+    names: Vec<String>,
+    since: Option<u64>,
+    skip: usize,
+    limit: Option<usize>,
+    yielded: usize,
+    on_error: Option<ErrorHandler>,
+}
+
+impl<R: Read> MultiRead<R> {
+    pub fn new(readers: Vec<JournalExportRead<R>>, order: Order) -> Self {
+        let reports = vec![SourceReport::default(); readers.len()];
+        let sources = readers
+            .into_iter()
+            .enumerate()
+            .map(|(index, reader)| Source {
+                index,
+                reader,
+                timestamp: None,
+                offset: 0,
+            })
+            .collect();
+        Self {
+            sources,
+            order,
+            primed: false,
+            current: None,
+            done_bytes: 0,
+            reports,
+            names: vec![],
+            since: None,
+            skip: 0,
+            limit: None,
+            yielded: 0,
+            on_error: None,
+        }
+    }
+
+    /// Names the sources in errors, e.g. by their paths; in the order passed
+    /// to [MultiRead::new].
+    pub fn with_source_names(self, names: Vec<String>) -> Self {
+        Self { names, ..self }
+    }
+
+    /// Drops the entries with a `__REALTIME_TIMESTAMP` before `ts`; entries
+    /// without one are kept. [Self::with_skip] and [Self::with_limit] count
+    /// the remaining entries only.
+    pub fn with_since(self, ts: u64) -> Self {
+        Self {
+            since: Some(ts),
+            ..self
+        }
+    }
+
+    /// Orders the entries of each source as if their timestamps were later by
+    /// the microseconds at its index in `offsets`, e.g. to correct a clock
+    /// that is off (see [ClockSkew](crate::skew::ClockSkew)). The entries
+    /// themselves are not changed. Only applies to [Order::Timestamp].
+    pub fn with_clock_offsets(mut self, offsets: &[i64]) -> Self {
+        for source in self.sources.iter_mut() {
+            source.offset = offsets.get(source.index).copied().unwrap_or(0);
+        }
+        self
+    }
+
+    /// Skips the first `n` entries of the combined stream.
+    pub fn with_skip(self, n: usize) -> Self {
+        Self { skip: n, ..self }
+    }
+
+    /// Ends the combined stream after `n` entries, not counting skipped ones.
+    pub fn with_limit(self, n: usize) -> Self {
+        Self {
+            limit: Some(n),
+            ..self
+        }
+    }
+
+    /// Continues with the other sources if one fails instead of returning
+    /// the error: the rest of the failed source is skipped, and the error is
+    /// counted in its [SourceReport] and passed to `handler`.
+    pub fn with_skip_errors(self, handler: impl FnMut(&SourceError) + 'static) -> Self {
+        Self {
+            on_error: Some(Box::new(handler)),
+            ..self
+        }
+    }
+
+    pub fn parse_next(&mut self) -> Result<Option<()>, SourceError> {
+        let _span = trace::trace_span!("merge_next");
+        if self.limit.is_some_and(|l| self.yielded >= l) {
+            self.current = None;
+            return Ok(None);
+        }
+        while self.skip > 0 {
+            if self.next_recent_entry()?.is_none() {
+                return Ok(None);
+            }
+            self.skip -= 1;
+        }
+        let next = self.next_recent_entry()?;
+        self.yielded += next.is_some() as usize;
+        Ok(next)
+    }
+
+    /// Like [Self::next_entry], but skips the entries before `since`.
+    fn next_recent_entry(&mut self) -> Result<Option<()>, SourceError> {
+        while self.next_entry()?.is_some() {
+            let ts = self.get_entry().realtime_timestamp();
+            if self.since.is_none_or(|s| ts.is_none_or(|t| t >= s)) {
+                return Ok(Some(()));
+            }
+        }
+        Ok(None)
+    }
+
+    fn next_entry(&mut self) -> Result<Option<()>, SourceError> {
+        match self.order {
+            Order::Sequential => {
+                self.current = None;
+                while !self.sources.is_empty() {
+                    if self.advance(0)? {
+                        self.current = Some(0);
+                        return Ok(Some(()));
+                    }
+                }
+                Ok(None)
+            }
+            Order::Timestamp => {
+                if !self.primed {
+                    let mut i = 0;
+                    while i < self.sources.len() {
+                        if self.advance(i)? {
+                            i += 1;
+                        }
+                    }
+                    self.primed = true;
+                } else if let Some(i) = self.current.take() {
+                    self.advance(i)?;
+                }
+                // min_by_key returns the first of several minimal sources,
+                // which keeps the order stable for equal timestamps.
+                self.current = self
+                    .sources
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, s)| s.timestamp.map(|t| t.saturating_add_signed(s.offset)))
+                    .map(|(i, _)| i);
+                Ok(self.current.map(|_| ()))
+            }
+        }
+    }
+
+    /// Returns the entry that was parsed last.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the last call to [MultiRead::parse_next] did not yield an
+    /// entry.
+    pub fn get_entry(&self) -> RefEntry<'_> {
+        let i = self.current.expect("no current entry");
+        self.sources[i].reader.get_entry()
+    }
+
+    /// The index (in the order passed to [MultiRead::new]) of the source the
+    /// current entry originates from.
+    pub fn source_index(&self) -> Option<usize> {
+        self.current.map(|i| self.sources[i].index)
+    }
+
+    /// The total number of bytes read from all sources.
+    pub fn bytes_read(&self) -> usize {
+        self.done_bytes
+            + self
+                .sources
+                .iter()
+                .map(|s| s.reader.bytes_read())
+                .sum::<usize>()
+    }
+
+    /// Statistics about the entries parsed so far. In [Order::Timestamp], the
+    /// head of each source is parsed before it is yielded; these entries are
+    /// included.
+    pub fn report(&self) -> MergeReport {
+        MergeReport {
+            entries: self.reports.iter().map(|r| r.entries).sum(),
+            sources: self.reports.clone(),
+        }
+    }
+
+    /// Parses the next entry of the source at position `i`. If the source is
+    /// exhausted, it is removed and `false` is returned.
+    fn advance(&mut self, i: usize) -> Result<bool, SourceError> {
+        let source = &mut self.sources[i];
+        let parsed = source.reader.parse_next().map_err(|error| SourceError {
+            index: source.index,
+            source_name: self.names.get(source.index).cloned(),
+            context: source.reader.error_context(),
+            error,
+        });
+        let report = &mut self.reports[source.index];
+        report.bytes = source.reader.bytes_read();
+        let parsed = match (parsed, &mut self.on_error) {
+            (Err(e), Some(handler)) => {
+                trace::debug!(source = source.index, error = %e, "source skipped");
+                report.errors += 1;
+                report.error = Some(e.error.to_string());
+                handler(&e);
+                None
+            }
+            (parsed, _) => parsed?,
+        };
+        match parsed {
+            Some(()) => {
+                let previous = source.timestamp;
+                source.timestamp = source.reader.get_entry().realtime_timestamp();
+                report.record(source.timestamp, previous);
+                Ok(true)
+            }
+            None => {
+                trace::debug!(
+                    source = source.index,
+                    entries = report.entries,
+                    bytes = report.bytes,
+                    "source exhausted"
+                );
+                self.done_bytes += source.reader.bytes_read();
+                self.sources.remove(i);
+                Ok(false)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{
+        generate::EntryGenerator,
+        journald::{Entry, JournalExportRead},
+    };
+
+    use super::{MultiRead, Order};
+
+    fn read_all(streams: &[Vec<u8>], order: Order) -> Vec<(usize, u64)> {
+        let readers = streams
+            .iter()
+            .map(|s| JournalExportRead::new(&s[..]))
+            .collect();
+        let mut multi_read = MultiRead::new(readers, order);
+        let mut entries = vec![];
+        while multi_read.parse_next().unwrap().is_some() {
+            let ts = multi_read.get_entry().realtime_timestamp().unwrap();
+            entries.push((multi_read.source_index().unwrap(), ts));
+        }
+        assert_eq!(
+            multi_read.bytes_read(),
+            streams.iter().map(|s| s.len()).sum::<usize>()
+        );
+        entries
+    }
+
+    #[test]
+    fn sequential_reads_sources_in_order() {
+        let streams = vec![
+            EntryGenerator::new(1).generate(3),
+            vec![],
+            EntryGenerator::new(2).generate(2),
+        ];
+        let sources: Vec<_> = read_all(&streams, Order::Sequential)
+            .into_iter()
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(sources, vec![0, 0, 0, 2, 2]);
+    }
+
+    #[test]
+    fn timestamp_order_interleaves_sources() {
+        let streams = vec![
+            EntryGenerator::new(1).generate(50),
+            vec![],
+            EntryGenerator::new(2).generate(70),
+            EntryGenerator::new(3).generate(1),
+        ];
+        let entries = read_all(&streams, Order::Timestamp);
+        assert_eq!(entries.len(), 121);
+        assert!(entries.windows(2).all(|w| w[0].1 <= w[1].1));
+        for (i, n) in [(0, 50), (2, 70), (3, 1)] {
+            assert_eq!(entries.iter().filter(|(s, _)| *s == i).count(), n);
+        }
+    }
+
+    #[test]
+    fn orders_by_corrected_timestamps() {
+        let streams = [
+            EntryGenerator::new(1).generate(20),
+            EntryGenerator::new(2).generate(20),
+        ];
+        let readers = streams
+            .iter()
+            .map(|s| JournalExportRead::new(&s[..]))
+            .collect();
+        let mut multi_read =
+            MultiRead::new(readers, Order::Timestamp).with_clock_offsets(&[0, i64::MAX / 2]);
+        let mut sources = vec![];
+        while multi_read.parse_next().unwrap().is_some() {
+            sources.push(multi_read.source_index().unwrap());
+        }
+        assert_eq!(sources, [[0; 20], [1; 20]].concat());
+    }
+
+    #[test]
+    fn skips_and_limits_entries() {
+        let streams = [
+            EntryGenerator::new(1).generate(3),
+            EntryGenerator::new(2).generate(4),
+        ];
+        let readers = streams
+            .iter()
+            .map(|s| JournalExportRead::new(&s[..]))
+            .collect();
+        let mut multi_read = MultiRead::new(readers, Order::Sequential)
+            .with_skip(2)
+            .with_limit(3);
+        let mut sources = vec![];
+        while multi_read.parse_next().unwrap().is_some() {
+            sources.push(multi_read.source_index().unwrap());
+        }
+        assert_eq!(sources, [0, 1, 1]);
+        assert!(multi_read.parse_next().unwrap().is_none());
+    }
+
+    #[test]
+    fn skips_failed_sources() {
+        let mut broken = EntryGenerator::new(2).generate(2);
+        broken.extend_from_slice(b"MESSAGE\n\x05\0\0\0\0\0\0\0ab");
+        let streams = [
+            EntryGenerator::new(1).generate(3),
+            broken,
+            EntryGenerator::new(3).generate(4),
+        ];
+        let readers = streams
+            .iter()
+            .map(|s| JournalExportRead::new(&s[..]))
+            .collect();
+        let failed = Rc::new(RefCell::new(vec![]));
+        let mut multi_read = MultiRead::new(readers, Order::Timestamp).with_skip_errors({
+            let failed = failed.clone();
+            move |e| failed.borrow_mut().push(e.index)
+        });
+        let mut entries = 0;
+        while multi_read.parse_next().unwrap().is_some() {
+            entries += 1;
+        }
+        assert_eq!(entries, 9);
+        assert_eq!(*failed.borrow(), [1]);
+        let errors: Vec<_> = multi_read
+            .report()
+            .sources
+            .iter()
+            .map(|r| r.errors)
+            .collect();
+        assert_eq!(errors, [0, 1, 0]);
+        assert!(multi_read.report().sources[1].error.is_some());
+    }
+
+    #[test]
+    fn report_counts_per_source() {
+        let mut unordered = EntryGenerator::new(1).generate(3);
+        unordered.extend(EntryGenerator::new(1).generate(2));
+        let streams = [unordered, EntryGenerator::new(2).generate(4)];
+        let readers = streams
+            .iter()
+            .map(|s| JournalExportRead::new(&s[..]))
+            .collect();
+        let mut multi_read = MultiRead::new(readers, Order::Timestamp);
+        while multi_read.parse_next().unwrap().is_some() {}
+
+        let report = multi_read.report();
+        assert_eq!(report.entries, 9);
+        assert_eq!(report.sources[0].entries, 5);
+        assert_eq!(report.sources[0].out_of_order, 1);
+        assert_eq!(report.sources[1].out_of_order, 0);
+        for (r, s) in report.sources.iter().zip(streams.iter()) {
+            assert_eq!(r.bytes, s.len());
+            assert!(r.first_timestamp <= r.last_timestamp);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! The documented `MESSAGE_ID`s of systemd.
+//!
+//! The constants correspond to the `SD_MESSAGE_*` definitions of
+//! `<systemd/sd-messages.h>`; [NAMED] gives them kebab-case names for use on
+//! the command line, e.g. `unit-failed`.
+//! See: [systemd catalog](https://www.freedesktop.org/wiki/Software/systemd/catalog/)
+
+use std::{fmt, str::FromStr};
+
+use thiserror::Error;
+
+use crate::journald::Entry;
+
+/// A 128-bit message ID, written as 32 lowercase hex digits.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+pub struct MessageId(u128);
+
+impl MessageId {
+    pub const fn from_u128(id: u128) -> Self {
+        Self(id)
+    }
+
+    pub const fn as_u128(self) -> u128 {
+        self.0
+    }
+
+    /// The `MESSAGE_ID` of `entry`, if it has a valid one.
+    pub fn of(entry: &impl Entry) -> Option<Self> {
+        std::str::from_utf8(entry.get(b"MESSAGE_ID")?)
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    /// Whether `entry` has this `MESSAGE_ID`.
+    pub fn matches(self, entry: &impl Entry) -> bool {
+        Self::of(entry) == Some(self)
+    }
+
+    /// The name of this ID in [NAMED].
+    pub fn name(self) -> Option<&'static str> {
+        NAMED.iter().find(|(_, id)| *id == self).map(|(n, _)| *n)
+    }
+}
+
+impl fmt::Display for MessageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("expected a 128-bit message ID in hex: {0}")]
+pub struct InvalidMessageId(String);
+
+impl FromStr for MessageId {
+    type Err = InvalidMessageId;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 32 || !s.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return Err(InvalidMessageId(s.to_string()));
+        }
+        u128::from_str_radix(s, 16)
+            .map(Self)
+            .map_err(|_| InvalidMessageId(s.to_string()))
+    }
+}
+
+/// The ID named `s` in [NAMED], or the ID `s` in hex.
+pub fn lookup(s: &str) -> Result<MessageId, InvalidMessageId> {
+    match NAMED.iter().find(|(name, _)| *name == s) {
+        Some(&(_, id)) => Ok(id),
+        None => s.parse(),
+    }
+}
+
+macro_rules! message_ids {
+    ($($(#[$doc:meta])* $name:ident = $id:literal, $kebab:literal;)*) => {
+        $(
+            $(#[$doc])*
+            pub const $name: MessageId = MessageId($id);
+        )*
+
+        /// All IDs of this module by name.
+        pub const NAMED: &[(&str, MessageId)] = &[$(($kebab, $name)),*];
+    };
+}
+
+message_ids! {
+    /// The journal has been started.
+    JOURNAL_START = 0xf77379a8490b408bbe5f6940505a777b, "journal-start";
+    /// The journal has been stopped.
+    JOURNAL_STOP = 0xd93fb3c9c24d451a97cea615ce59c00b, "journal-stop";
+    /// Messages from a service have been suppressed by rate limiting.
+    JOURNAL_DROPPED = 0xa596d6fe7bfa4994828e72309e95d61e, "journal-dropped";
+    /// Journal messages have been missed.
+    JOURNAL_MISSED = 0xe9bf28e6e834481bb6f48f548ad13606, "journal-missed";
+    /// Disk space used by the journal.
+    JOURNAL_USAGE = 0xec387f577b844b8fa948f33cad9a75e6, "journal-usage";
+    /// A process dumped core.
+    COREDUMP = 0xfc2e22bc6ee647b6b90729ab34a250b1, "coredump";
+    /// A core file was truncated.
+    TRUNCATED_CORE = 0x5aadd8e954dc4b1a8c954d63fd9e1137, "truncated-core";
+    /// A login session has been created.
+    SESSION_START = 0x8d45620c1a4348dbb17410da57c60c66, "session-start";
+    /// A login session has been terminated.
+    SESSION_STOP = 0x3354939424b4456d9802ca8333ed424a, "session-stop";
+    /// A seat is now available.
+    SEAT_START = 0xfcbefc5da23d428093f97c82a9290f7b, "seat-start";
+    /// A seat has been removed.
+    SEAT_STOP = 0xe7852bfe46784ed0accde04bc864c2d5, "seat-stop";
+    /// The system clock has been changed.
+    TIME_CHANGE = 0xc7a787079b354eaaa9e77b371893cd27, "time-change";
+    /// The time zone has been changed.
+    TIMEZONE_CHANGE = 0x45f82f4aef7a4bbf942ce861d1f20990, "timezone-change";
+    /// The system is configured in a way that might cause problems.
+    TAINTED = 0x50876a9db00f4c40bde1a2ad381c3a1b, "tainted";
+    /// System start-up is complete.
+    STARTUP_FINISHED = 0xb07a249cd024414a82dd00cd181378ff, "startup-finished";
+    /// The start-up of a user manager is complete.
+    USER_STARTUP_FINISHED = 0xeed00a68ffd84e31882105fd973abdd1, "user-startup-finished";
+    /// A sleep state has been entered.
+    SLEEP_START = 0x6bbd95ee977941e497c48be27c254128, "sleep-start";
+    /// A sleep state has been left.
+    SLEEP_STOP = 0x8811e6df2a8e40f58a94cea26f8ebf14, "sleep-stop";
+    /// The system is shutting down.
+    SHUTDOWN = 0x98268866d1d54a499c4e98921d93bc40, "shutdown";
+    /// A factory reset has been initiated.
+    FACTORY_RESET = 0xc14aaf76ec284a5fa1f105f88dfb061c, "factory-reset";
+    /// A start job for a unit has begun.
+    UNIT_STARTING = 0x7d4958e842da4a758f6c1cdc7b36dcc5, "unit-starting";
+    /// A start job for a unit has finished successfully.
+    UNIT_STARTED = 0x39f53479d3a045ac8e11786248231fbf, "unit-started";
+    /// A start job for a unit has failed.
+    UNIT_START_FAILED = 0xbe02cf6855d2428ba40df7e9d022f03d, "unit-start-failed";
+    /// A stop job for a unit has begun.
+    UNIT_STOPPING = 0xde5b426a63be47a7b6ac3eaac82e2f6f, "unit-stopping";
+    /// A stop job for a unit has finished.
+    UNIT_STOPPED = 0x9d1aaa27d60140bd96365438aad20286, "unit-stopped";
+    /// A reload job for a unit has begun.
+    UNIT_RELOADING = 0xd34d037fff1847e6ae669a370e694725, "unit-reloading";
+    /// A reload job for a unit has finished.
+    UNIT_RELOADED = 0x7b05ebc668384222baa8881179cfda54, "unit-reloaded";
+    /// The automatic restart of a unit has been scheduled.
+    UNIT_RESTART_SCHEDULED = 0x5eb03494b6584870a536b337290809b3, "unit-restart-scheduled";
+    /// The resources consumed by a unit.
+    UNIT_RESOURCES = 0xae8f7b866b0347b9af31fe1c80b127c0, "unit-resources";
+    /// A unit has entered the dead state successfully.
+    UNIT_SUCCESS = 0x7ad2d189f7e94e70a38c781354912448, "unit-success";
+    /// A unit has been skipped.
+    UNIT_SKIPPED = 0x0e4284a0caca4bfc81c0bb6786972673, "unit-skipped";
+    /// A unit has entered the failed state.
+    UNIT_FAILED = 0xd9b373ed55a64feb8242e02dbe79a49c, "unit-failed";
+    /// A process of a unit has exited.
+    UNIT_PROCESS_EXIT = 0x98e322203f7a4ed290d09fe03c09fe15, "unit-process-exit";
+    /// A process of a unit has been killed by the OOM killer.
+    UNIT_OUT_OF_MEMORY = 0xfe6faa94e7774663a0da52717891d8ef, "unit-out-of-memory";
+    /// A process could not be executed.
+    SPAWN_FAILED = 0x641257651c1b4ec9a8624d7a40a9e1e7, "spawn-failed";
+    /// Messages could not be forwarded to syslog.
+    FORWARD_SYSLOG_MISSED = 0x0027229ca0644181a76c4e92458afa2e, "forward-syslog-missed";
+    /// A mount point is not empty.
+    OVERMOUNTING = 0x1dee0369c7fc4736b7099b38ecb46ee7, "overmounting";
+    /// A virtual machine or container has been started.
+    MACHINE_START = 0x24d8d4452573402496068381a6312df2, "machine-start";
+    /// A virtual machine or container has been terminated.
+    MACHINE_STOP = 0x58432bd3bace477cb514b56381b8a758, "machine-stop";
+    /// DNSSEC validation failed.
+    DNSSEC_FAILURE = 0x1675d7f172174098b1108bf8c7dc8f5d, "dnssec-failure";
+    /// The clock has been synchronized for the first time.
+    TIME_SYNC = 0x7c8a41f37b764941a0e1780b1be2f037, "time-sync";
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::journald::JournalExportRead;
+
+    use super::{lookup, MessageId, COREDUMP, NAMED, UNIT_FAILED};
+
+    #[test]
+    fn parses_and_names_ids() {
+        assert_eq!(COREDUMP.to_string(), "fc2e22bc6ee647b6b90729ab34a250b1");
+        assert_eq!(
+            "FC2E22BC6EE647B6B90729AB34A250B1"
+                .parse::<MessageId>()
+                .unwrap(),
+            COREDUMP
+        );
+        assert!("fc2e22bc".parse::<MessageId>().is_err());
+        assert_eq!(lookup("unit-failed").unwrap(), UNIT_FAILED);
+        assert_eq!(UNIT_FAILED.name(), Some("unit-failed"));
+        assert!(lookup("unit-exploded").is_err());
+
+        let input = b"MESSAGE_ID=fc2e22bc6ee647b6b90729ab34a250b1\n\n";
+        let entry = JournalExportRead::new(&input[..]).next().unwrap();
+        assert!(COREDUMP.matches(&entry));
+        assert!(!UNIT_FAILED.matches(&entry));
+
+        let mut ids: Vec<_> = NAMED.iter().map(|(_, id)| *id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), NAMED.len());
+    }
+}
@@ -0,0 +1,123 @@
+//! Detect entries that are out of order.
+//!
+//! Journal export streams are expected to be ordered by their
+//! `__REALTIME_TIMESTAMP`. Broken shippers or clock adjustments produce
+//! entries whose timestamp moves backwards; [OrderChecker] flags every entry
+//! whose timestamp is more than a tolerance below that of the preceding entry.
+//! A single backwards jump is thus reported once, not for every entry until
+//! the stream catches up again.
+
+use serde::Serialize;
+
+use crate::journald::Entry;
+
+/// An entry whose timestamp precedes that of the preceding entry.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize)]
+pub struct OrderViolation {
+    /// The index of the entry in the stream.
+    pub index: usize,
+    /// The byte offset of the entry in the stream.
+    pub offset: usize,
+    pub timestamp: u64,
+    /// The timestamp of the preceding entry.
+    pub previous: u64,
+}
+
+impl OrderViolation {
+    /// How far (in microseconds) the timestamp moved backwards.
+    pub fn delta(&self) -> u64 {
+        self.previous - self.timestamp
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OrderChecker {
+    tolerance: u64,
+    index: usize,
+    offset: usize,
+    previous: Option<u64>,
+}
+
+impl OrderChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Timestamps may move backwards by up to `tolerance` microseconds
+    /// without being flagged.
+    pub fn with_tolerance(self, tolerance: u64) -> Self {
+        Self { tolerance, ..self }
+    }
+
+    /// Checks the next entry of the stream. Entries must be passed in stream
+    /// order; entries without a timestamp are neither flagged nor compared
+    /// against.
+    pub fn check(&mut self, entry: &impl Entry) -> Option<OrderViolation> {
+        let index = self.index;
+        let offset = self.offset;
+        self.index += 1;
+        self.offset += entry.len_bytes();
+
+        let ts = entry.realtime_timestamp()?;
+        let previous = self.previous.replace(ts)?;
+        (ts < previous && previous - ts > self.tolerance).then_some(OrderViolation {
+            index,
+            offset,
+            timestamp: ts,
+            previous,
+        })
+    }
+
+    /// The number of entries checked so far.
+    pub fn entries(&self) -> usize {
+        self.index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{generate::EntryGenerator, journald::JournalExportRead, testutil::write_string};
+
+    use super::OrderChecker;
+
+    fn entry(ts: u64) -> Vec<u8> {
+        let mut e = vec![];
+        write_string(&mut e, "__REALTIME_TIMESTAMP", ts.to_string());
+        e.push(b'\n');
+        e
+    }
+
+    fn check_all(stream: &[u8], tolerance: u64) -> Vec<(usize, usize, u64)> {
+        let mut checker = OrderChecker::new().with_tolerance(tolerance);
+        let mut jreader = JournalExportRead::new(stream);
+        let mut violations = vec![];
+        while jreader.parse_next().unwrap().is_some() {
+            if let Some(v) = checker.check(&jreader.get_entry()) {
+                violations.push((v.index, v.offset, v.delta()));
+            }
+        }
+        violations
+    }
+
+    #[test]
+    fn flags_timestamps_moving_backwards() {
+        let timestamps = [100, 200, 150, 160, 155, 300, 90];
+        let entries: Vec<_> = timestamps.iter().map(|&ts| entry(ts)).collect();
+        let offset = |i: usize| entries[..i].iter().map(|e| e.len()).sum::<usize>();
+        let stream = entries.concat();
+        assert_eq!(
+            check_all(&stream, 0),
+            vec![(2, offset(2), 50), (4, offset(4), 5), (6, offset(6), 210)]
+        );
+        assert_eq!(
+            check_all(&stream, 10),
+            vec![(2, offset(2), 50), (6, offset(6), 210)]
+        );
+    }
+
+    #[test]
+    fn generated_streams_are_ordered() {
+        let stream = EntryGenerator::new(5).generate(100);
+        assert!(check_all(&stream, 0).is_empty());
+    }
+}
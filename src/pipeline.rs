@@ -0,0 +1,612 @@
+//! Chain transforms and describe processing pipelines declaratively.
+//!
+//! A [Pipeline] passes every entry through a [Chain] of [Transform]s before it
+//! is written to an [EntrySink].
+//!
+//! [PipelineConfig] describes the sources, stages and output of a pipeline in
+//! TOML; stages of type `plugin` refer to the transforms of a
+//! [TransformRegistry]:
+//!
+//! ```toml
+//! sources = ["/var/log/exports", "remote-*.export.zst"]
+//! merge = true
+//!
+//! [[stages]]
+//! type = "filter"
+//! field = "PRIORITY"
+//! regex = "^[0-4]$"
+//!
+//! [[stages]]
+//! type = "inject"
+//! name = "SOURCE_FILE"
+//! value = "{source}"
+//!
+//! [[stages]]
+//! type = "drop"
+//! fields = ["_CMDLINE", "_SELINUX_CONTEXT"]
+//!
+//! [output]
+//! path = "archive/host"
+//! rotate_size = 104857600
+//! compress = "zst"
+//!
+//! [output.queue]
+//! capacity = 10000
+//! overflow = "spill"
+//! ```
+
+use std::{collections::HashMap, io, path::PathBuf};
+
+use regex::bytes::Regex;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    journald::Entry,
+    queue::OverflowPolicy,
+    ratelimit::RateLimit,
+    reassemble::Reassemble,
+    sink::{Compression, EntrySink},
+    transform::{
+        read_emitted, Chain, EntryView, FieldPattern, Filter, PerSource, Projection, Rewrite,
+        Substitute, Substitution, Transform, TransformResult,
+    },
+};
+
+/// Passes entries through a [Chain] of transforms and writes the result to an
+/// [EntrySink].
+#[derive(Default)]
+pub struct Pipeline {
+    chain: Chain,
+    buf: Vec<u8>,
+    written: usize,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_transform(self, transform: impl Transform + 'static) -> Self {
+        Self {
+            chain: self.chain.with(transform),
+            ..self
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chain.is_empty()
+    }
+
+    /// The number of entries written to a sink so far.
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Passes `entry`, which originates from the source at index `source`,
+    /// through the pipeline and writes the result to `sink`.
+    pub fn process(
+        &mut self,
+        source: usize,
+        entry: &impl Entry,
+        sink: &mut dyn EntrySink,
+    ) -> io::Result<()> {
+        self.buf.clear();
+        match self
+            .chain
+            .apply(EntryView::new(entry, source, &mut self.buf))?
+        {
+            TransformResult::Keep => {
+                self.written += 1;
+                sink.write_entry(entry.as_bytes())
+            }
+            TransformResult::Drop => Ok(()),
+            TransformResult::Replace => {
+                self.written += write_all(&self.buf, sink)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes the entries held back by the transforms. Must be called once
+    /// the input is exhausted.
+    pub fn finish(&mut self, sink: &mut dyn EntrySink) -> io::Result<()> {
+        self.buf.clear();
+        self.chain.finish(&mut self.buf)?;
+        self.written += write_all(&self.buf, sink)?;
+        Ok(())
+    }
+}
+
+/// Writes the entries of the export stream `entries` to `sink` one by one.
+fn write_all(entries: &[u8], sink: &mut dyn EntrySink) -> io::Result<usize> {
+    let mut jreader = read_emitted(entries);
+    let mut written = 0;
+    while jreader.parse_next()?.is_some() {
+        sink.write_entry(jreader.get_entry().as_bytes())?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+type Factory = Box<dyn Fn(&toml::Table) -> Result<Box<dyn Transform>, PipelineError>>;
+
+/// Transforms that can be referred to by name in a [PipelineConfig], in
+/// addition to the built-in ones:
+///
+/// ```toml
+/// [[stages]]
+/// type = "plugin"
+/// name = "anonymize"
+/// options = { salt = "..." }
+/// ```
+#[derive(Default)]
+pub struct TransformRegistry {
+    factories: HashMap<String, Factory>,
+}
+
+impl TransformRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `factory`, which creates the transform `name` from its
+    /// options. Options can be deserialized using [toml::Table::try_into].
+    pub fn with<F>(mut self, name: impl Into<String>, factory: F) -> Self
+    where
+        F: Fn(&toml::Table) -> Result<Box<dyn Transform>, PipelineError> + 'static,
+    {
+        self.factories.insert(name.into(), Box::new(factory));
+        self
+    }
+
+    pub fn create(
+        &self,
+        name: &str,
+        options: &toml::Table,
+    ) -> Result<Box<dyn Transform>, PipelineError> {
+        let factory = self
+            .factories
+            .get(name)
+            .ok_or_else(|| PipelineError::UnknownTransform(name.to_string()))?;
+        factory(options)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum PipelineError {
+    #[error("invalid pipeline configuration: {0}")]
+    Config(#[from] toml::de::Error),
+    #[error("invalid regular expression: {0}")]
+    Regex(#[from] regex::Error),
+    #[error("unknown transform: {0}")]
+    UnknownTransform(String),
+    #[cfg(feature = "expr")]
+    #[error("invalid expression: {0}")]
+    Expr(#[from] crate::expr::ExprError),
+    #[cfg(feature = "geoip")]
+    #[error("invalid GeoIP database: {0}")]
+    GeoIp(#[from] maxminddb::MaxMindDbError),
+}
+
+impl From<PipelineError> for io::Error {
+    fn from(value: PipelineError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, value)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineConfig {
+    /// Export files, directories or glob patterns.
+    pub sources: Vec<PathBuf>,
+    /// A file recording how far every source was read; a run continues where
+    /// the previous one stopped.
+    pub state: Option<PathBuf>,
+    /// Interleave the entries of all sources by timestamp.
+    #[serde(default)]
+    pub merge: bool,
+    #[serde(default)]
+    pub stages: Vec<StageConfig>,
+    pub output: OutputConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum StageConfig {
+    Filter {
+        field: String,
+        regex: String,
+        #[serde(default)]
+        invert: bool,
+    },
+    Keep {
+        fields: Vec<String>,
+    },
+    Drop {
+        fields: Vec<String>,
+    },
+    Rename {
+        from: String,
+        to: String,
+    },
+    /// `{source}` in the value is replaced by the path of the source.
+    Inject {
+        name: String,
+        value: String,
+    },
+    Substitute {
+        field: String,
+        regex: String,
+        #[serde(default)]
+        template: String,
+    },
+    Reassemble {
+        /// The maximum time in milliseconds between two lines.
+        #[serde(default = "default_reassemble_window")]
+        window_ms: u64,
+        continuation: Option<String>,
+        /// The maximum size of a merged message in bytes.
+        max_message_size: Option<usize>,
+    },
+    /// Lets through `burst` entries per interval; see [RateLimit].
+    RateLimit {
+        burst: u64,
+        /// Defaults to one second.
+        #[serde(default = "default_rate_limit_interval")]
+        interval_ms: u64,
+        /// Limit every value of this field separately.
+        key: Option<String>,
+    },
+    /// Selects entries by an expression; see [crate::expr].
+    #[cfg(feature = "expr")]
+    Where {
+        expr: String,
+    },
+    /// Assigns fields using expressions; see [crate::expr].
+    #[cfg(feature = "expr")]
+    Map {
+        expr: String,
+    },
+    /// A transform of a [TransformRegistry].
+    Plugin {
+        name: String,
+        #[serde(default)]
+        options: toml::Table,
+    },
+}
+
+fn default_reassemble_window() -> u64 {
+    1000
+}
+
+fn default_rate_limit_interval() -> u64 {
+    1000
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    /// The output file, `-` for stdout or, if the output is rotated, the
+    /// prefix of the output files.
+    pub path: PathBuf,
+    #[serde(default)]
+    pub append: bool,
+    /// Rotate the output once it exceeds this many bytes.
+    pub rotate_size: Option<u64>,
+    /// Rotate the output after this many seconds.
+    pub rotate_interval: Option<u64>,
+    /// Compress rotated files.
+    pub compress: Option<CompressionConfig>,
+    /// Write the output on a separate thread through a bounded queue.
+    pub queue: Option<QueueConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct QueueConfig {
+    /// The number of entries held in memory.
+    pub capacity: usize,
+    #[serde(default)]
+    pub overflow: OverflowConfig,
+    /// Where `spill` puts its temporary file; defaults to the system's
+    /// temporary directory.
+    pub spill_dir: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverflowConfig {
+    #[default]
+    Block,
+    DropOldest,
+    DropNewest,
+    Spill,
+}
+
+impl QueueConfig {
+    pub fn policy(&self) -> OverflowPolicy {
+        match self.overflow {
+            OverflowConfig::Block => OverflowPolicy::Block,
+            OverflowConfig::DropOldest => OverflowPolicy::DropOldest,
+            OverflowConfig::DropNewest => OverflowPolicy::DropNewest,
+            OverflowConfig::Spill => {
+                OverflowPolicy::Spill(self.spill_dir.clone().unwrap_or_else(std::env::temp_dir))
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompressionConfig {
+    Gz,
+    Zst,
+}
+
+impl From<CompressionConfig> for Compression {
+    fn from(value: CompressionConfig) -> Self {
+        match value {
+            CompressionConfig::Gz => Compression::Gzip,
+            CompressionConfig::Zst => Compression::Zstd,
+        }
+    }
+}
+
+impl PipelineConfig {
+    pub fn from_toml(s: &str) -> Result<Self, PipelineError> {
+        Ok(toml::from_str(s)?)
+    }
+
+    /// Builds the stages of the pipeline for the given (expanded) sources.
+    /// Plugin stages are looked up in `registry`.
+    pub fn pipeline(
+        &self,
+        sources: &[PathBuf],
+        registry: &TransformRegistry,
+    ) -> Result<Pipeline, PipelineError> {
+        let mut pipeline = Pipeline::new();
+        for stage in self.stages.iter() {
+            pipeline = pipeline.with_transform(stage.build(sources, registry)?);
+        }
+        Ok(pipeline)
+    }
+}
+
+impl StageConfig {
+    fn build(
+        &self,
+        sources: &[PathBuf],
+        registry: &TransformRegistry,
+    ) -> Result<Box<dyn Transform>, PipelineError> {
+        let patterns = |fields: &[String]| {
+            fields
+                .iter()
+                .map(|f| FieldPattern::new(f.as_str()))
+                .collect()
+        };
+        Ok(match self {
+            StageConfig::Filter {
+                field,
+                regex,
+                invert,
+            } => Box::new(Filter {
+                field: FieldPattern::new(field.as_str()),
+                regex: Regex::new(regex)?,
+                invert: *invert,
+            }),
+            StageConfig::Keep { fields } => Box::new(Projection::Keep(patterns(fields))),
+            StageConfig::Drop { fields } => Box::new(Projection::Drop(patterns(fields))),
+            StageConfig::Rename { from, to } => {
+                Box::new(Rewrite::new().with_rename(from.as_str(), to.as_str()))
+            }
+            StageConfig::Inject { name, value } if value.contains("{source}") => {
+                Box::new(PerSource(
+                    sources
+                        .iter()
+                        .map(|src| {
+                            let value = value.replace("{source}", &src.to_string_lossy());
+                            Rewrite::new().with_field(name.as_str(), value)
+                        })
+                        .collect(),
+                ))
+            }
+            StageConfig::Inject { name, value } => {
+                Box::new(Rewrite::new().with_field(name.as_str(), value.as_str()))
+            }
+            StageConfig::Substitute {
+                field,
+                regex,
+                template,
+            } => Box::new(Substitute::new().with(Substitution {
+                field: FieldPattern::new(field.as_str()),
+                regex: Regex::new(regex)?,
+                template: template.as_bytes().to_vec(),
+            })),
+            StageConfig::Reassemble {
+                window_ms,
+                continuation,
+                max_message_size,
+            } => {
+                let mut r = Reassemble::new().with_window(window_ms * 1000);
+                if let Some(size) = max_message_size {
+                    r = r.with_max_message_size(*size);
+                }
+                Box::new(match continuation {
+                    Some(c) => r.with_continuation(Regex::new(c)?),
+                    None => r,
+                })
+            }
+            StageConfig::RateLimit {
+                burst,
+                interval_ms,
+                key,
+            } => {
+                let limit = RateLimit::new(*burst, interval_ms * 1000);
+                Box::new(match key {
+                    Some(key) => limit.with_key(key.as_str()),
+                    None => limit,
+                })
+            }
+            #[cfg(feature = "expr")]
+            StageConfig::Where { expr } => Box::new(crate::expr::Predicate::new(expr)?),
+            #[cfg(feature = "expr")]
+            StageConfig::Map { expr } => Box::new(crate::expr::Map::new(expr)?),
+            StageConfig::Plugin { name, options } => registry.create(name, options)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, path::PathBuf};
+
+    use serde::Deserialize;
+
+    use crate::{
+        generate::EntryGenerator,
+        journald::{Entry, JournalExportRead},
+        queue::OverflowPolicy,
+        transform::{EntryView, Transform, TransformResult},
+    };
+
+    use super::{PipelineConfig, PipelineError, TransformRegistry};
+
+    const CONFIG: &str = r#"
+        sources = ["a.export", "b.export"]
+
+        [[stages]]
+        type = "filter"
+        field = "PRIORITY"
+        regex = "^[2-4]$"
+
+        [[stages]]
+        type = "rename"
+        from = "_HOSTNAME"
+        to = "HOST"
+
+        [[stages]]
+        type = "inject"
+        name = "SOURCE_FILE"
+        value = "{source}"
+
+        [[stages]]
+        type = "keep"
+        fields = ["PRIORITY", "HOST", "SOURCE_FILE"]
+
+        [output]
+        path = "-"
+
+        [output.queue]
+        capacity = 100
+        overflow = "drop-oldest"
+    "#;
+
+    #[test]
+    fn runs_configured_stages() {
+        let config = PipelineConfig::from_toml(CONFIG).unwrap();
+        assert!(!config.merge);
+        assert_eq!(
+            config
+                .output
+                .queue
+                .as_ref()
+                .map(|q| (q.capacity, q.policy())),
+            Some((100, OverflowPolicy::DropOldest))
+        );
+        let sources: Vec<_> = config.sources.iter().map(PathBuf::from).collect();
+        let mut pipeline = config
+            .pipeline(&sources, &TransformRegistry::new())
+            .unwrap();
+
+        let stream = EntryGenerator::new(3).generate(200);
+        let mut jreader = JournalExportRead::new(&stream[..]);
+        let mut out = vec![];
+        let mut selected = 0;
+        while jreader.parse_next().unwrap().is_some() {
+            let e = jreader.get_entry();
+            if matches!(e.get(b"PRIORITY"), Some(b"2" | b"3" | b"4")) {
+                selected += 1;
+            }
+            pipeline.process(1, &e, &mut out).unwrap();
+        }
+        pipeline.finish(&mut out).unwrap();
+        assert_eq!(pipeline.written(), selected);
+
+        let mut jreader = JournalExportRead::new(&out[..]);
+        while jreader.parse_next().unwrap().is_some() {
+            let e = jreader.get_entry();
+            let names: Vec<_> = e
+                .iter()
+                .map(|(n, _, _)| n)
+                .filter(|n| !n.starts_with(b"__"))
+                .collect();
+            assert_eq!(names, [&b"PRIORITY"[..], b"HOST", b"SOURCE_FILE"]);
+            assert_eq!(e.get(b"SOURCE_FILE"), Some(&b"b.export"[..]));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_configs() {
+        assert!(PipelineConfig::from_toml("sources = []").is_err());
+        let invalid_regex = CONFIG.replace("^[2-4]$", "(");
+        let config = PipelineConfig::from_toml(&invalid_regex).unwrap();
+        assert!(config.pipeline(&[], &TransformRegistry::new()).is_err());
+    }
+
+    /// Keeps every n-th entry.
+    struct Thin {
+        every: usize,
+        seen: usize,
+    }
+
+    impl Transform for Thin {
+        fn apply(&mut self, _: EntryView<'_>) -> io::Result<TransformResult> {
+            self.seen += 1;
+            Ok(match self.seen % self.every {
+                0 => TransformResult::Keep,
+                _ => TransformResult::Drop,
+            })
+        }
+    }
+
+    #[test]
+    fn creates_plugin_stages() {
+        #[derive(Deserialize)]
+        struct Options {
+            every: usize,
+        }
+        let registry = TransformRegistry::new().with("thin", |options| {
+            let options: Options = options.clone().try_into()?;
+            Ok(Box::new(Thin {
+                every: options.every,
+                seen: 0,
+            }))
+        });
+        let config = PipelineConfig::from_toml(
+            r#"
+            sources = ["-"]
+
+            [[stages]]
+            type = "plugin"
+            name = "thin"
+            options = { every = 3 }
+
+            [output]
+            path = "-"
+            "#,
+        )
+        .unwrap();
+        let mut pipeline = config.pipeline(&[], &registry).unwrap();
+        let stream = EntryGenerator::new(5).generate(30);
+        let mut jreader = JournalExportRead::new(&stream[..]);
+        let mut out = vec![];
+        while jreader.parse_next().unwrap().is_some() {
+            pipeline.process(0, &jreader.get_entry(), &mut out).unwrap();
+        }
+        assert_eq!(pipeline.written(), 10);
+
+        let unknown = config.pipeline(&[], &TransformRegistry::new());
+        assert!(matches!(unknown, Err(PipelineError::UnknownTransform(_))));
+    }
+}
@@ -0,0 +1,163 @@
+//! Recycle the allocations of owned entries.
+//!
+//! Pipelines that turn every entry into an [OwnedEntry] and drop it shortly
+//! after allocate and free two buffers per entry. An [EntryPool] keeps the
+//! buffers of dropped [PooledEntry]s and reuses them for the next entries.
+//! The pool is shared by cloning it; entries may be dropped on any thread.
+
+use std::{
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
+
+use crate::journald::{parser::FieldIter, Entry, OwnedEntry, RefEntry};
+
+#[derive(Clone)]
+pub struct EntryPool {
+    free: Arc<Mutex<Vec<OwnedEntry>>>,
+    max_entries: usize,
+    max_entry_capacity: usize,
+}
+
+impl Default for EntryPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EntryPool {
+    pub fn new() -> Self {
+        Self {
+            free: Arc::default(),
+            max_entries: 1024,
+            max_entry_capacity: 64 * 1024,
+        }
+    }
+
+    /// Keeps at most `n` unused entries; defaults to 1024.
+    pub fn with_max_entries(self, n: usize) -> Self {
+        Self {
+            max_entries: n,
+            ..self
+        }
+    }
+
+    /// Does not keep entries that allocated more than `capacity` bytes, such
+    /// that a few large entries do not hold on to memory; defaults to 64 KiB.
+    pub fn with_max_entry_capacity(self, capacity: usize) -> Self {
+        Self {
+            max_entry_capacity: capacity,
+            ..self
+        }
+    }
+
+    /// The number of unused entries in the pool.
+    pub fn len(&self) -> usize {
+        self.free.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copies `entry` into a recycled entry, if there is one.
+    pub fn to_owned(&self, entry: &RefEntry<'_>) -> PooledEntry {
+        let mut owned = self.free.lock().unwrap().pop().unwrap_or_default();
+        entry.clone_into(&mut owned);
+        PooledEntry {
+            entry: Some(owned),
+            pool: self.clone(),
+        }
+    }
+
+    fn recycle(&self, entry: OwnedEntry) {
+        if entry.capacity() > self.max_entry_capacity {
+            return;
+        }
+        let mut free = self.free.lock().unwrap();
+        if free.len() < self.max_entries {
+            free.push(entry);
+        }
+    }
+}
+
+/// An [OwnedEntry] that returns to its pool when dropped.
+pub struct PooledEntry {
+    entry: Option<OwnedEntry>,
+    pool: EntryPool,
+}
+
+impl PooledEntry {
+    /// Detaches the entry from the pool.
+    pub fn into_inner(mut self) -> OwnedEntry {
+        self.entry.take().unwrap()
+    }
+}
+
+impl Deref for PooledEntry {
+    type Target = OwnedEntry;
+
+    fn deref(&self) -> &Self::Target {
+        self.entry.as_ref().unwrap()
+    }
+}
+
+impl Entry for PooledEntry {
+    fn as_bytes(&self) -> &[u8] {
+        (**self).as_bytes()
+    }
+
+    fn iter(&self) -> FieldIter<'_> {
+        (**self).iter()
+    }
+}
+
+impl Drop for PooledEntry {
+    fn drop(&mut self) {
+        if let Some(entry) = self.entry.take() {
+            self.pool.recycle(entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        journald::{Entry, JournalExportRead},
+        testutil::write_string,
+    };
+
+    use super::EntryPool;
+
+    #[test]
+    fn recycles_entries() {
+        let mut stream = vec![];
+        for i in 0..10 {
+            write_string(&mut stream, "MESSAGE", format!("message {}", i));
+            stream.push(b'\n');
+        }
+        write_string(&mut stream, "MESSAGE", "x".repeat(1000));
+        stream.push(b'\n');
+
+        let pool = EntryPool::new().with_max_entry_capacity(512);
+        let mut reader = JournalExportRead::new(&stream[..]);
+        let mut messages = vec![];
+        while reader.parse_next().unwrap().is_some() {
+            let entry = pool.to_owned(&reader.get_entry());
+            messages.push(entry.get(b"MESSAGE").unwrap().len());
+            assert!(pool.is_empty());
+            drop(entry);
+            assert!(pool.len() <= 1);
+        }
+        assert_eq!(messages[..2], [9, 9]);
+        assert_eq!(messages[10], 1000);
+        // The large entry was not kept.
+        assert!(pool.is_empty());
+
+        let mut reader = JournalExportRead::new(&stream[..]);
+        reader.parse_next().unwrap();
+        let owned = pool.to_owned(&reader.get_entry()).into_inner();
+        assert_eq!(owned.get(b"MESSAGE"), Some(&b"message 0"[..]));
+        assert!(pool.is_empty());
+    }
+}
@@ -0,0 +1,121 @@
+//! Record where entries come from.
+//!
+//! [TaggedRead] adds the fields `LOGINUS_SOURCE`, naming the source of an
+//! entry, such as its path or the address of a peer, and
+//! `LOGINUS_INGEST_TIMESTAMP`, the microseconds since the epoch when the entry
+//! was read, to every entry of an export stream. Thus, the entries of merged
+//! or received streams can still be traced back to their sources. Entries
+//! that have a `LOGINUS_SOURCE` already, e.g. from an earlier merge, keep
+//! their provenance.
+
+use std::{
+    io::{self, Read},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::journald::{parser::FieldType, write_field, Entry, JournalExportRead};
+
+pub const SOURCE_FIELD: &str = "LOGINUS_SOURCE";
+pub const INGEST_TIMESTAMP_FIELD: &str = "LOGINUS_INGEST_TIMESTAMP";
+
+/// The current time in microseconds since the epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_micros() as u64)
+}
+
+/// Appends the provenance fields to `out`.
+pub fn write_fields(out: &mut Vec<u8>, source: &[u8], ingested: u64) {
+    let string = &FieldType::String;
+    write_field(out, SOURCE_FIELD.as_bytes(), source, string);
+    let ingested = ingested.to_string();
+    write_field(
+        out,
+        INGEST_TIMESTAMP_FIELD.as_bytes(),
+        ingested.as_bytes(),
+        string,
+    );
+}
+
+/// Appends `entry` to `out` with the provenance fields.
+pub fn tag(entry: &impl Entry, source: &[u8], ingested: u64, out: &mut Vec<u8>) {
+    let bytes = entry.as_bytes();
+    // Without the empty line that ends the entry.
+    out.extend_from_slice(&bytes[..bytes.len() - 1]);
+    if entry.get(SOURCE_FIELD.as_bytes()).is_none() {
+        write_fields(out, source, ingested);
+    }
+    out.push(b'\n');
+}
+
+/// Adds the provenance fields to the entries of an export stream.
+pub struct TaggedRead<R> {
+    reader: JournalExportRead<R>,
+    source: Vec<u8>,
+    out: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> TaggedRead<R> {
+    /// Tags the entries of `inner` with `source`.
+    pub fn new(inner: R, source: impl Into<Vec<u8>>) -> Self {
+        Self {
+            reader: JournalExportRead::new(inner),
+            source: source.into(),
+            out: vec![],
+            pos: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for TaggedRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.out.len() {
+            self.out.clear();
+            self.pos = 0;
+            if self.reader.parse_next()?.is_none() {
+                return Ok(0);
+            }
+            tag(&self.reader.get_entry(), &self.source, now(), &mut self.out);
+        }
+        let n = buf.len().min(self.out.len() - self.pos);
+        buf[..n].copy_from_slice(&self.out[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        journald::{Entry, JournalExportRead},
+        testutil::{write_binary, write_string},
+    };
+
+    use super::{now, TaggedRead};
+
+    #[test]
+    fn tags_entries() {
+        let mut stream = vec![];
+        write_string(&mut stream, "MESSAGE", "first");
+        write_binary(&mut stream, "DATA", b"a\nb");
+        stream.push(b'\n');
+        write_string(&mut stream, "MESSAGE", "merged before");
+        write_string(&mut stream, "LOGINUS_SOURCE", "origin");
+        stream.push(b'\n');
+
+        let before = now();
+        let mut tagged = vec![];
+        std::io::copy(&mut TaggedRead::new(&stream[..], "a.export"), &mut tagged).unwrap();
+        let entries: Vec<_> = JournalExportRead::new(&tagged[..]).collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].get(b"DATA"), Some(&b"a\nb"[..]));
+        assert_eq!(entries[0].get(b"LOGINUS_SOURCE"), Some(&b"a.export"[..]));
+        let ingested = entries[0].get(b"LOGINUS_INGEST_TIMESTAMP").unwrap();
+        let ingested: u64 = std::str::from_utf8(ingested).unwrap().parse().unwrap();
+        assert!((before..=now()).contains(&ingested));
+        assert_eq!(entries[1].get(b"LOGINUS_SOURCE"), Some(&b"origin"[..]));
+        assert_eq!(entries[1].get(b"LOGINUS_INGEST_TIMESTAMP"), None);
+    }
+}
@@ -0,0 +1,387 @@
+//! Decouple producers of entries from slow sinks.
+//!
+//! [QueueSink] is an [EntrySink] that hands entries to a background thread
+//! writing them to another sink. At most `capacity` entries are held in
+//! memory; what happens to further entries while the queue is full is
+//! decided by the [OverflowPolicy]. [QueueMetrics] counts the entries that
+//! were written, dropped and spilled to disk.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread::JoinHandle,
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::FileExt;
+
+#[cfg(not(unix))]
+use crate::fileext::FileExt;
+use crate::sink::EntrySink;
+
+/// What to do with an entry while the queue is full.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub enum OverflowPolicy {
+    /// Wait until the sink has caught up.
+    #[default]
+    Block,
+    /// Discard the oldest queued entry to make room.
+    DropOldest,
+    /// Discard the entry.
+    DropNewest,
+    /// Append the entry to a temporary file in the given directory. Entries
+    /// are written in order, i.e. once the queue spilled, further entries are
+    /// spilled as well until the sink has caught up with the file.
+    Spill(PathBuf),
+}
+
+/// Counters of a [QueueSink], updated while it runs.
+#[derive(Debug, Default)]
+pub struct QueueMetrics {
+    written: AtomicU64,
+    dropped: AtomicU64,
+    spilled: AtomicU64,
+    max_depth: AtomicU64,
+}
+
+impl QueueMetrics {
+    /// Entries passed to the sink.
+    pub fn written(&self) -> u64 {
+        self.written.load(Ordering::Relaxed)
+    }
+
+    /// Entries discarded because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Entries written to the spill file because the queue was full.
+    pub fn spilled(&self) -> u64 {
+        self.spilled.load(Ordering::Relaxed)
+    }
+
+    /// The largest number of entries held in memory at once.
+    pub fn max_depth(&self) -> u64 {
+        self.max_depth.load(Ordering::Relaxed)
+    }
+}
+
+/// Entries spilled to a temporary file, each prefixed by its length as a
+/// little-endian u64.
+struct Spill {
+    file: File,
+    read_pos: u64,
+    write_pos: u64,
+}
+
+impl Spill {
+    fn is_empty(&self) -> bool {
+        self.read_pos == self.write_pos
+    }
+
+    fn push(&mut self, entry: &[u8]) -> io::Result<()> {
+        self.file
+            .write_all_at(&(entry.len() as u64).to_le_bytes(), self.write_pos)?;
+        self.file.write_all_at(entry, self.write_pos + 8)?;
+        self.write_pos += 8 + entry.len() as u64;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> io::Result<Vec<u8>> {
+        let mut len = [0; 8];
+        self.file.read_exact_at(&mut len, self.read_pos)?;
+        let mut entry = vec![0; u64::from_le_bytes(len) as usize];
+        self.file.read_exact_at(&mut entry, self.read_pos + 8)?;
+        self.read_pos += 8 + entry.len() as u64;
+        if self.is_empty() {
+            self.file.set_len(0)?;
+            self.read_pos = 0;
+            self.write_pos = 0;
+        }
+        Ok(entry)
+    }
+}
+
+#[derive(Default)]
+struct State {
+    queue: VecDeque<Vec<u8>>,
+    spill: Option<Spill>,
+    closed: bool,
+    /// Set once the sink failed; the error is returned by the next call.
+    failed: bool,
+    error: Option<io::Error>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Signalled when entries were queued or the queue was closed.
+    not_empty: Condvar,
+    /// Signalled when entries were taken from the queue or the sink failed.
+    not_full: Condvar,
+    metrics: Arc<QueueMetrics>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Writes entries to a sink on a background thread.
+pub struct QueueSink {
+    shared: Arc<Shared>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl QueueSink {
+    /// Holds up to `capacity` entries (at least one) in memory.
+    pub fn new(sink: Box<dyn EntrySink + Send>, capacity: usize, policy: OverflowPolicy) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::default(),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            metrics: Arc::default(),
+        });
+        let writer = {
+            let shared = Arc::clone(&shared);
+            std::thread::spawn(move || write(&shared, sink))
+        };
+        Self {
+            shared,
+            capacity: capacity.max(1),
+            policy,
+            writer: Some(writer),
+        }
+    }
+
+    /// A handle to the counters of this queue, which remains valid after the
+    /// queue was finished.
+    pub fn metrics(&self) -> Arc<QueueMetrics> {
+        Arc::clone(&self.shared.metrics)
+    }
+
+    fn enqueue(&self, state: &mut State, entry: &[u8]) -> io::Result<()> {
+        let metrics = &self.shared.metrics;
+        if let Some(spill) = state.spill.as_mut().filter(|s| !s.is_empty()) {
+            spill.push(entry)?;
+            metrics.spilled.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+        if state.queue.len() >= self.capacity {
+            match &self.policy {
+                OverflowPolicy::Block => unreachable!(),
+                OverflowPolicy::DropOldest => {
+                    state.queue.pop_front();
+                    metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                OverflowPolicy::DropNewest => {
+                    metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                    return Ok(());
+                }
+                OverflowPolicy::Spill(dir) => {
+                    let spill = match &mut state.spill {
+                        Some(spill) => spill,
+                        None => state.spill.insert(Spill {
+                            file: tempfile::tempfile_in(dir)?,
+                            read_pos: 0,
+                            write_pos: 0,
+                        }),
+                    };
+                    spill.push(entry)?;
+                    metrics.spilled.fetch_add(1, Ordering::Relaxed);
+                    return Ok(());
+                }
+            }
+        }
+        state.queue.push_back(entry.to_vec());
+        metrics
+            .max_depth
+            .fetch_max(state.queue.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+fn failure(state: &mut State) -> io::Error {
+    state
+        .error
+        .take()
+        .unwrap_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "the queued sink failed"))
+}
+
+/// The body of the writer thread.
+fn write(shared: &Shared, mut sink: Box<dyn EntrySink + Send>) {
+    let result = (|| loop {
+        let mut state = shared.lock();
+        let entry = loop {
+            if let Some(entry) = state.queue.pop_front() {
+                break entry;
+            }
+            if let Some(spill) = state.spill.as_mut().filter(|s| !s.is_empty()) {
+                break spill.pop()?;
+            }
+            if state.closed {
+                drop(state);
+                return sink.finish();
+            }
+            state = shared
+                .not_empty
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        };
+        drop(state);
+        shared.not_full.notify_all();
+        sink.write_entry(&entry)?;
+        shared.metrics.written.fetch_add(1, Ordering::Relaxed);
+    })();
+    if let Err(e) = result {
+        let mut state = shared.lock();
+        state.failed = true;
+        state.error = Some(e);
+        state.queue.clear();
+        drop(state);
+        shared.not_full.notify_all();
+    }
+}
+
+impl EntrySink for QueueSink {
+    fn write_entry(&mut self, entry: &[u8]) -> io::Result<()> {
+        let mut state = self.shared.lock();
+        if self.policy == OverflowPolicy::Block {
+            while state.queue.len() >= self.capacity && !state.failed {
+                state = self
+                    .shared
+                    .not_full
+                    .wait(state)
+                    .unwrap_or_else(|e| e.into_inner());
+            }
+        }
+        if state.failed {
+            return Err(failure(&mut state));
+        }
+        self.enqueue(&mut state, entry)?;
+        drop(state);
+        self.shared.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Waits until all queued entries were written and finishes the sink.
+    fn finish(&mut self) -> io::Result<()> {
+        let Some(writer) = self.writer.take() else {
+            return Ok(());
+        };
+        self.shared.lock().closed = true;
+        self.shared.not_empty.notify_one();
+        writer
+            .join()
+            .map_err(|_| io::Error::other("the queue writer panicked"))?;
+        // Unless a previous call already returned it.
+        self.shared.lock().error.take().map_or(Ok(()), Err)
+    }
+}
+
+impl Drop for QueueSink {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Barrier},
+    };
+
+    use super::{OverflowPolicy, QueueSink};
+    use crate::sink::EntrySink;
+
+    /// Waits at a barrier before writing the first entry, such that the
+    /// queue fills up deterministically.
+    struct Gated {
+        gate: Arc<Barrier>,
+        opened: bool,
+        out: Arc<std::sync::Mutex<Vec<u8>>>,
+    }
+
+    impl EntrySink for Gated {
+        fn write_entry(&mut self, entry: &[u8]) -> io::Result<()> {
+            if !self.opened {
+                self.gate.wait();
+                self.opened = true;
+            }
+            self.out.lock().unwrap().extend_from_slice(entry);
+            Ok(())
+        }
+
+        fn finish(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run(policy: OverflowPolicy) -> (String, u64, u64) {
+        let gate = Arc::new(Barrier::new(2));
+        let out = Arc::default();
+        let sink = Gated {
+            gate: Arc::clone(&gate),
+            opened: false,
+            out: Arc::clone(&out),
+        };
+        let mut queue = QueueSink::new(Box::new(sink), 2, policy);
+        let metrics = queue.metrics();
+        // The writer takes the first entry and blocks at the gate; the next
+        // two fill the queue.
+        queue.write_entry(b"A=0\n\n").unwrap();
+        while queue.shared.lock().queue.len() == 1 {
+            std::thread::yield_now();
+        }
+        for i in 1..6 {
+            queue
+                .write_entry(format!("A={}\n\n", i).as_bytes())
+                .unwrap();
+        }
+        gate.wait();
+        queue.finish().unwrap();
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        let values: String = out.split_terminator("\n\n").map(|e| &e[2..]).collect();
+        (values, metrics.dropped(), metrics.spilled())
+    }
+
+    #[test]
+    fn applies_overflow_policies() {
+        assert_eq!(run(OverflowPolicy::DropNewest), ("012".to_string(), 3, 0));
+        assert_eq!(run(OverflowPolicy::DropOldest), ("045".to_string(), 3, 0));
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            run(OverflowPolicy::Spill(dir.path().to_path_buf())),
+            ("012345".to_string(), 0, 3)
+        );
+    }
+
+    #[test]
+    fn reports_sink_errors() {
+        struct Failing;
+        impl EntrySink for Failing {
+            fn write_entry(&mut self, _: &[u8]) -> io::Result<()> {
+                Err(io::Error::other("unreachable"))
+            }
+            fn finish(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut queue = QueueSink::new(Box::new(Failing), 1, OverflowPolicy::Block);
+        let mut result = Ok(());
+        for _ in 0..3 {
+            result = result.and_then(|_| queue.write_entry(b"A=1\n\n"));
+        }
+        let error = result.and_then(|_| queue.finish()).unwrap_err();
+        assert_eq!(error.to_string(), "unreachable");
+    }
+}
@@ -0,0 +1,226 @@
+//! Throttle entries with token buckets.
+//!
+//! [RateLimit] lets through at most `burst` entries at once and refills at
+//! `burst` entries per interval, measured by the `__REALTIME_TIMESTAMP` of
+//! the entries (entries without timestamp are counted at the time of the
+//! previous entry). With a key field, e.g. `_SYSTEMD_UNIT`, every value of
+//! the field has its own bucket.
+//!
+//! Like journald's own rate limiting, suppressed entries are summarized by a
+//! synthetic entry with the message `Suppressed N messages from KEY`, written
+//! before the next entry that passes and when the input ends. Synthetic
+//! entries carry the number of suppressed entries in `LOGINUS_SUPPRESSED`.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+};
+
+use crate::{
+    journald::{parser::FieldType, write_field, Entry},
+    transform::{EntryView, Transform, TransformResult},
+};
+
+struct Bucket {
+    tokens: f64,
+    /// The time of the last refill, in microseconds.
+    refilled: u64,
+    suppressed: u64,
+    /// The time of the last suppressed entry.
+    last_suppressed: u64,
+}
+
+pub struct RateLimit {
+    burst: f64,
+    /// Tokens per microsecond.
+    rate: f64,
+    key: Option<Vec<u8>>,
+    buckets: HashMap<Vec<u8>, Bucket>,
+    now: u64,
+    suppressed: u64,
+}
+
+impl RateLimit {
+    /// Lets through `burst` entries per `interval` microseconds.
+    pub fn new(burst: u64, interval: u64) -> Self {
+        Self {
+            burst: burst as f64,
+            rate: burst as f64 / interval.max(1) as f64,
+            key: None,
+            buckets: HashMap::new(),
+            now: 0,
+            suppressed: 0,
+        }
+    }
+
+    /// Limits every value of the field `name` separately; entries without
+    /// the field share a bucket.
+    pub fn with_key(self, name: impl Into<Vec<u8>>) -> Self {
+        Self {
+            key: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// The number of entries suppressed so far.
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed
+    }
+
+    /// Decides whether `entry` passes. If it does and entries of its bucket
+    /// were suppressed before, their summary is appended to `out`.
+    pub fn check(&mut self, entry: &impl Entry, out: &mut Vec<u8>) -> bool {
+        self.now = entry.realtime_timestamp().unwrap_or(self.now);
+        let value = match &self.key {
+            Some(key) => entry.get(key).unwrap_or_default(),
+            None => b"",
+        };
+        let (burst, rate, now) = (self.burst, self.rate, self.now);
+        let bucket = self.buckets.entry(value.to_vec()).or_insert(Bucket {
+            tokens: burst,
+            refilled: now,
+            suppressed: 0,
+            last_suppressed: 0,
+        });
+        let elapsed = now.saturating_sub(bucket.refilled);
+        bucket.tokens = (bucket.tokens + elapsed as f64 * rate).min(burst);
+        bucket.refilled = bucket.refilled.max(now);
+        if bucket.tokens < 1.0 {
+            bucket.suppressed += 1;
+            bucket.last_suppressed = now;
+            self.suppressed += 1;
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        if bucket.suppressed > 0 {
+            let n = std::mem::take(&mut bucket.suppressed);
+            self.write_summary(value, n, now, out);
+        }
+        true
+    }
+
+    /// Appends the summaries of all buckets with suppressed entries to
+    /// `out`, ordered by time. Must be called once the input is exhausted.
+    pub fn flush(&mut self, out: &mut Vec<u8>) {
+        let mut pending = BTreeMap::new();
+        for (value, bucket) in self.buckets.iter_mut() {
+            if bucket.suppressed > 0 {
+                let n = std::mem::take(&mut bucket.suppressed);
+                pending.insert((bucket.last_suppressed, value.clone()), n);
+            }
+        }
+        for ((ts, value), n) in pending {
+            self.write_summary(&value, n, ts, out);
+        }
+    }
+
+    fn write_summary(&self, value: &[u8], n: u64, ts: u64, out: &mut Vec<u8>) {
+        let mut message = format!("Suppressed {} messages", n).into_bytes();
+        if self.key.is_some() && !value.is_empty() {
+            message.extend_from_slice(b" from ");
+            message.extend_from_slice(value);
+        }
+        let string = |out: &mut Vec<u8>, name: &[u8], value: &[u8]| {
+            write_field(out, name, value, &FieldType::String)
+        };
+        string(out, b"__REALTIME_TIMESTAMP", ts.to_string().as_bytes());
+        string(out, b"MESSAGE", &message);
+        string(out, b"PRIORITY", b"5");
+        string(out, b"SYSLOG_IDENTIFIER", b"loginus");
+        string(out, b"LOGINUS_SUPPRESSED", n.to_string().as_bytes());
+        if let Some(key) = self.key.as_ref().filter(|_| !value.is_empty()) {
+            string(out, key, value);
+        }
+        out.push(b'\n');
+    }
+}
+
+impl Transform for RateLimit {
+    fn apply(&mut self, entry: EntryView<'_>) -> io::Result<TransformResult> {
+        let start = entry.out.len();
+        Ok(match self.check(&entry.entry, entry.out) {
+            false => TransformResult::Drop,
+            true if entry.out.len() == start => TransformResult::Keep,
+            true => {
+                entry.out.extend_from_slice(entry.entry.as_bytes());
+                TransformResult::Replace
+            }
+        })
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        self.flush(out);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        journald::{Entry, JournalExportRead},
+        testutil::write_string,
+    };
+
+    use super::RateLimit;
+
+    fn limit(stream: &[u8], limit: &mut RateLimit) -> Vec<String> {
+        let mut out = vec![];
+        let mut jreader = JournalExportRead::new(stream);
+        while jreader.parse_next().unwrap().is_some() {
+            let e = jreader.get_entry();
+            if limit.check(&e, &mut out) {
+                out.extend_from_slice(e.as_bytes());
+            }
+        }
+        limit.flush(&mut out);
+        JournalExportRead::new(&out[..])
+            .map(|e| String::from_utf8(e.get(b"MESSAGE").unwrap().to_vec()).unwrap())
+            .collect()
+    }
+
+    /// Entries of units at times in milliseconds.
+    fn stream(entries: &[(u64, &str)]) -> Vec<u8> {
+        let mut stream = vec![];
+        for (ts, unit) in entries {
+            write_string(&mut stream, "__REALTIME_TIMESTAMP", (ts * 1000).to_string());
+            write_string(&mut stream, "_SYSTEMD_UNIT", format!("{}.service", unit));
+            write_string(&mut stream, "MESSAGE", format!("{} {}", unit, ts));
+            stream.push(b'\n');
+        }
+        stream
+    }
+
+    #[test]
+    fn suppresses_bursts_per_key() {
+        // Five entries of a.service within 40ms, one of b.service, then one
+        // more of a.service after the bucket was refilled.
+        let entries = [
+            (0, "a"),
+            (10, "a"),
+            (20, "b"),
+            (20, "a"),
+            (30, "a"),
+            (40, "a"),
+            (2_000_000, "a"),
+        ];
+
+        let mut keyed = RateLimit::new(2, 1_000_000).with_key("_SYSTEMD_UNIT");
+        assert_eq!(
+            limit(&stream(&entries), &mut keyed),
+            [
+                "a 0",
+                "a 10",
+                "b 20",
+                "Suppressed 3 messages from a.service",
+                "a 2000000"
+            ]
+        );
+        assert_eq!(keyed.suppressed(), 3);
+
+        let mut global = RateLimit::new(2, 1_000_000);
+        assert_eq!(
+            limit(&stream(&entries[..4]), &mut global),
+            ["a 0", "a 10", "Suppressed 2 messages"]
+        );
+    }
+}
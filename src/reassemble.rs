@@ -0,0 +1,296 @@
+//! Reassemble messages that were split across several entries.
+//!
+//! Programs that write multi-line messages (e.g. Java or Python stack traces)
+//! to stdout end up with one journal entry per line. Moreover, journald splits
+//! lines that exceed its line length limit into several entries and marks all
+//! but the last one with `_LINE_BREAK=line-max`.
+//!
+//! [Reassemble] merges consecutive entries that belong to one logical message
+//! into a single entry whose `MESSAGE` is the concatenation of the messages.
+//! Entries belong to the same message if they originate from the same stream
+//! (`_STREAM_ID` or, lacking that, `_PID` and `_BOOT_ID`), are at most a time
+//! window apart and either the previous entry was split by journald or the
+//! message of the entry looks like a continuation line: it starts with
+//! whitespace, `Caused by:` or `...`, or matches a configurable expression.
+//! The other fields of a merged entry are taken from its first entry. A
+//! message is not continued beyond a maximum size, 1 MiB by default.
+
+use std::io;
+
+use regex::bytes::Regex;
+
+use crate::{
+    journald::{parser::FieldType, write_field, Entry},
+    transform::{EntryView, Transform, TransformResult},
+};
+
+struct Pending {
+    /// The fields of the first entry.
+    fields: Vec<(Vec<u8>, Vec<u8>, FieldType)>,
+    key: Vec<u8>,
+    timestamp: Option<u64>,
+    message: Vec<u8>,
+    // Whether the last entry of the group was split by journald.
+    line_max: bool,
+}
+
+pub struct Reassemble {
+    window: u64,
+    continuation: Option<Regex>,
+    max_message_size: usize,
+    pending: Option<Pending>,
+    merged: usize,
+}
+
+impl Default for Reassemble {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Reassemble {
+    pub fn new() -> Self {
+        Self {
+            window: 1_000_000,
+            continuation: None,
+            max_message_size: 1 << 20,
+            pending: None,
+            merged: 0,
+        }
+    }
+
+    /// The maximum time in microseconds between two consecutive entries of
+    /// one message; defaults to one second.
+    pub fn with_window(self, window: u64) -> Self {
+        Self { window, ..self }
+    }
+
+    /// Additionally treats messages matching `continuation` as continuation
+    /// lines.
+    pub fn with_continuation(self, continuation: Regex) -> Self {
+        Self {
+            continuation: Some(continuation),
+            ..self
+        }
+    }
+
+    /// The maximum size of a merged message; an entry that would exceed it
+    /// starts a new message.
+    pub fn with_max_message_size(self, max_message_size: usize) -> Self {
+        Self {
+            max_message_size,
+            ..self
+        }
+    }
+
+    /// The number of entries that were merged into their predecessors so far.
+    pub fn merged(&self) -> usize {
+        self.merged
+    }
+
+    /// Feeds the next entry. Entries that are complete (i.e. cannot be
+    /// continued anymore) are appended to `out`.
+    pub fn push(&mut self, entry: &impl Entry, out: &mut Vec<u8>) {
+        let Some(message) = entry.get(b"MESSAGE") else {
+            self.flush(out);
+            out.extend_from_slice(entry.as_bytes());
+            return;
+        };
+        let key = stream_key(entry);
+        let timestamp = entry.realtime_timestamp();
+        let line_max = entry.get(b"_LINE_BREAK") == Some(b"line-max");
+        let continuation = self.is_continuation(message);
+        if let Some(p) = self.pending.as_mut() {
+            let within = match (p.timestamp, timestamp) {
+                (Some(prev), Some(ts)) => ts.saturating_sub(prev) <= self.window,
+                _ => true,
+            };
+            let fits = p.message.len() + 1 + message.len() <= self.max_message_size;
+            if p.key == key && within && fits {
+                if p.line_max {
+                    p.message.extend_from_slice(message);
+                } else if continuation {
+                    p.message.push(b'\n');
+                    p.message.extend_from_slice(message);
+                } else {
+                    self.flush(out);
+                    self.start(entry, key, timestamp, message, line_max);
+                    return;
+                }
+                p.timestamp = timestamp.or(p.timestamp);
+                p.line_max = line_max;
+                self.merged += 1;
+                return;
+            }
+        }
+        self.flush(out);
+        self.start(entry, key, timestamp, message, line_max);
+    }
+
+    /// Appends the pending entry, if any, to `out`. Must be called once the
+    /// input is exhausted.
+    pub fn flush(&mut self, out: &mut Vec<u8>) {
+        let Some(p) = self.pending.take() else {
+            return;
+        };
+        let mut message = Some(p.message);
+        for (name, value, typ) in p.fields.iter() {
+            if name == b"MESSAGE" {
+                if let Some(m) = message.take() {
+                    write_field(out, name, &m, typ);
+                    continue;
+                }
+            }
+            write_field(out, name, value, typ);
+        }
+        out.push(b'\n');
+    }
+
+    fn start(
+        &mut self,
+        entry: &impl Entry,
+        key: Vec<u8>,
+        timestamp: Option<u64>,
+        message: &[u8],
+        line_max: bool,
+    ) {
+        self.pending = Some(Pending {
+            fields: entry
+                .iter()
+                .map(|(name, value, typ)| (name.to_vec(), value.to_vec(), typ))
+                .collect(),
+            key,
+            timestamp,
+            message: message.to_vec(),
+            line_max,
+        });
+    }
+
+    fn is_continuation(&self, message: &[u8]) -> bool {
+        message.first().is_some_and(|c| c.is_ascii_whitespace())
+            || message.starts_with(b"Caused by:")
+            || message.starts_with(b"...")
+            || self
+                .continuation
+                .as_ref()
+                .is_some_and(|r| r.is_match(message))
+    }
+}
+
+impl Transform for Reassemble {
+    fn apply(&mut self, entry: EntryView<'_>) -> io::Result<TransformResult> {
+        self.push(&entry.entry, entry.out);
+        Ok(TransformResult::Replace)
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        self.flush(out);
+        Ok(())
+    }
+}
+
+fn stream_key(entry: &impl Entry) -> Vec<u8> {
+    if let Some(id) = entry.get(b"_STREAM_ID") {
+        return [b"s", id].concat();
+    }
+    let pid = entry.get(b"_PID").unwrap_or_default();
+    let boot = entry.get(b"_BOOT_ID").unwrap_or_default();
+    [b"p", pid, b";", boot].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::bytes::Regex;
+
+    use crate::{
+        config::JournalExportLimits,
+        journald::{Entry, JournalExportRead},
+        testutil::write_string,
+    };
+
+    use super::Reassemble;
+
+    fn entry(out: &mut Vec<u8>, ts: u64, pid: &str, message: &str, line_break: bool) {
+        write_string(out, "__REALTIME_TIMESTAMP", ts.to_string());
+        write_string(out, "_PID", pid);
+        write_string(out, "MESSAGE", message);
+        if line_break {
+            write_string(out, "_LINE_BREAK", "line-max");
+        }
+        out.push(b'\n');
+    }
+
+    fn reassemble(stream: &[u8], reassemble: &mut Reassemble) -> Vec<Vec<u8>> {
+        let mut out = vec![];
+        let limits = JournalExportLimits::bounded_by(stream.len());
+        let mut jreader = JournalExportRead::new_with_limits(limits, stream);
+        while jreader.parse_next().unwrap().is_some() {
+            reassemble.push(&jreader.get_entry(), &mut out);
+        }
+        reassemble.flush(&mut out);
+        let limits = JournalExportLimits::bounded_by(out.len());
+        let mut jreader = JournalExportRead::new_with_limits(limits, &out[..]);
+        let mut messages = vec![];
+        while jreader.parse_next().unwrap().is_some() {
+            messages.push(jreader.get_entry().get(b"MESSAGE").unwrap().to_vec());
+        }
+        messages
+    }
+
+    #[test]
+    fn merges_stack_traces_and_split_lines() {
+        let mut stream = vec![];
+        entry(&mut stream, 100, "1", "java.lang.Exception: boom", false);
+        entry(&mut stream, 101, "1", "\tat Main.main(Main.java:3)", false);
+        entry(&mut stream, 102, "2", "other process", false);
+        entry(&mut stream, 103, "1", "\tat Main.run(Main.java:7)", false);
+        entry(
+            &mut stream,
+            104,
+            "1",
+            "Caused by: java.io.IOException",
+            false,
+        );
+        entry(&mut stream, 105, "1", "aaaa", true);
+        entry(&mut stream, 106, "1", "bbbb", false);
+        entry(&mut stream, 3_000_000, "1", "  late indentation", false);
+
+        let mut r = Reassemble::new();
+        let messages = reassemble(&stream, &mut r);
+        assert_eq!(
+            messages,
+            [
+                &b"java.lang.Exception: boom\n\tat Main.main(Main.java:3)"[..],
+                b"other process",
+                b"\tat Main.run(Main.java:7)\nCaused by: java.io.IOException",
+                b"aaaabbbb",
+                b"  late indentation",
+            ]
+        );
+        assert_eq!(r.merged(), 3);
+
+        let mut r = Reassemble::new()
+            .with_window(10_000_000)
+            .with_continuation(Regex::new("^aaaa$").unwrap());
+        assert_eq!(reassemble(&stream, &mut r).len(), 3);
+    }
+
+    #[test]
+    fn merges_large_messages_up_to_the_maximum_size() {
+        let first = "x".repeat(13_000);
+        let mut stream = vec![];
+        entry(&mut stream, 100, "1", &first, false);
+        entry(&mut stream, 101, "1", "\tat Main.main(Main.java:3)", false);
+        entry(&mut stream, 102, "1", "\tat Main.run(Main.java:7)", false);
+
+        let messages = reassemble(&stream, &mut Reassemble::new());
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with(first.as_bytes()));
+
+        let mut r = Reassemble::new().with_max_message_size(13_030);
+        let messages = reassemble(&stream, &mut r);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1], b"\tat Main.run(Main.java:7)");
+        assert_eq!(r.merged(), 1);
+    }
+}
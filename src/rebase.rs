@@ -0,0 +1,215 @@
+//! Shift the timestamps of entries.
+//!
+//! [Rebase] adds an offset, fixed or per source, to the times of entries, e.g.
+//! to correct a clock that is known to be off before merging, or to hide when
+//! the entries of a reproduction were logged with [Rebase::starting_at]. The
+//! fields that carry the time are rewritten consistently:
+//! `__REALTIME_TIMESTAMP`, `_SOURCE_REALTIME_TIMESTAMP`, the time in
+//! `__CURSOR` and `SYSLOG_TIMESTAMP`, either in the traditional syslog format
+//! (`Jan  5 14:03:07`) or in RFC 3339. Monotonic timestamps are relative to
+//! the boot and stay as they are.
+
+use std::io;
+
+use chrono::{DateTime, Datelike, NaiveDateTime, SecondsFormat, TimeDelta};
+
+use crate::{
+    journald::{write_field, Entry},
+    transform::{EntryView, Transform, TransformResult},
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Rebase {
+    offset: i64,
+    /// The offsets of the sources that differ from `offset`, by index.
+    sources: Vec<Option<i64>>,
+    /// Where the first entry is moved to, for [Rebase::starting_at].
+    start: Option<u64>,
+}
+
+impl Rebase {
+    /// Shifts all entries by `offset` microseconds.
+    pub fn new(offset: i64) -> Self {
+        Self {
+            offset,
+            ..Self::default()
+        }
+    }
+
+    /// Shifts all entries such that the first one is at `start`.
+    pub fn starting_at(start: u64) -> Self {
+        Self {
+            start: Some(start),
+            ..Self::default()
+        }
+    }
+
+    /// Shifts the entries of the source at index `source` by `offset`
+    /// microseconds instead.
+    pub fn with_source_offset(mut self, source: usize, offset: i64) -> Self {
+        if self.sources.len() <= source {
+            self.sources.resize(source + 1, None);
+        }
+        self.sources[source] = Some(offset);
+        self
+    }
+
+    fn offset(&mut self, source: usize, timestamp: Option<u64>) -> i64 {
+        if let (Some(start), Some(ts)) = (self.start, timestamp) {
+            self.offset = start as i64 - ts as i64;
+            self.start = None;
+        }
+        self.sources
+            .get(source)
+            .copied()
+            .flatten()
+            .unwrap_or(self.offset)
+    }
+
+    /// Appends `entry`, which originates from the source at index `source`,
+    /// with shifted timestamps to `out`. Returns `false` and leaves `out`
+    /// untouched if the offset is 0.
+    pub fn apply(&mut self, entry: &impl Entry, source: usize, out: &mut Vec<u8>) -> bool {
+        let realtime = entry.realtime_timestamp();
+        let offset = self.offset(source, realtime);
+        if offset == 0 {
+            return false;
+        }
+        let shift = |ts: u64| ts.saturating_add_signed(offset);
+        for (name, value, typ) in entry.iter() {
+            let shifted = match name {
+                b"__REALTIME_TIMESTAMP" | b"_SOURCE_REALTIME_TIMESTAMP" => {
+                    std::str::from_utf8(value)
+                        .ok()
+                        .and_then(|v| v.parse().ok())
+                        .map(|ts| shift(ts).to_string().into_bytes())
+                }
+                b"__CURSOR" => Some(shift_cursor(value, offset)),
+                b"SYSLOG_TIMESTAMP" => shift_syslog_timestamp(value, realtime, offset),
+                _ => None,
+            };
+            write_field(out, name, shifted.as_deref().unwrap_or(value), &typ);
+        }
+        out.push(b'\n');
+        true
+    }
+}
+
+impl Transform for Rebase {
+    fn apply(&mut self, entry: EntryView<'_>) -> io::Result<TransformResult> {
+        Ok(
+            match Rebase::apply(self, &entry.entry, entry.source, entry.out) {
+                true => TransformResult::Replace,
+                false => TransformResult::Keep,
+            },
+        )
+    }
+}
+
+/// Shifts the realtime timestamp `t=` in a cursor, given in hex.
+fn shift_cursor(cursor: &[u8], offset: i64) -> Vec<u8> {
+    let parts: Vec<Vec<u8>> = cursor
+        .split(|&b| b == b';')
+        .map(|part| {
+            let ts = part
+                .strip_prefix(b"t=")
+                .and_then(|t| std::str::from_utf8(t).ok())
+                .and_then(|t| u64::from_str_radix(t, 16).ok());
+            match ts {
+                Some(ts) => format!("t={:x}", ts.saturating_add_signed(offset)).into_bytes(),
+                None => part.to_vec(),
+            }
+        })
+        .collect();
+    parts.join(&b';')
+}
+
+/// Shifts a syslog timestamp in RFC 3339 or as `Mmm dd hh:mm:ss`, which
+/// lacks the year; it is taken from the realtime timestamp of the entry.
+fn shift_syslog_timestamp(value: &[u8], realtime: Option<u64>, offset: i64) -> Option<Vec<u8>> {
+    let value = std::str::from_utf8(value).ok()?;
+    let time = value.trim_end();
+    let trailer = &value[time.len()..];
+    let delta = TimeDelta::microseconds(offset);
+    if let Ok(t) = DateTime::parse_from_rfc3339(time) {
+        let utc = time.ends_with('Z') || time.ends_with('z');
+        let shifted = (t + delta).to_rfc3339_opts(SecondsFormat::AutoSi, utc);
+        return Some(format!("{}{}", shifted, trailer).into_bytes());
+    }
+    let reference = realtime
+        .and_then(|ts| DateTime::from_timestamp_micros(ts as i64))
+        .map_or_else(|| chrono::Utc::now().naive_utc(), |t| t.naive_utc());
+    // Of the adjacent years, the one that puts the time closest to the
+    // realtime timestamp, for entries logged around New Year.
+    let t = [-1, 0, 1]
+        .into_iter()
+        .filter_map(|y| {
+            let s = format!("{} {}", reference.year() + y, time);
+            NaiveDateTime::parse_from_str(&s, "%Y %b %e %H:%M:%S").ok()
+        })
+        .min_by_key(|t| (*t - reference).abs())?;
+    let shifted = (t + delta).format("%b %e %H:%M:%S");
+    Some(format!("{}{}", shifted, trailer).into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        journald::{Entry, JournalExportRead},
+        testutil::write_string,
+    };
+
+    use super::Rebase;
+
+    fn shift(rebase: &mut Rebase, source: usize, fields: &[(&str, &str)]) -> Vec<String> {
+        let mut input = vec![];
+        for (name, value) in fields {
+            write_string(&mut input, name, value);
+        }
+        input.push(b'\n');
+        let entry = JournalExportRead::new(&input[..]).next().unwrap();
+        let mut out = vec![];
+        if !rebase.apply(&entry, source, &mut out) {
+            return vec![];
+        }
+        let entry = JournalExportRead::new(&out[..]).next().unwrap();
+        entry
+            .iter()
+            .map(|(_, v, _)| String::from_utf8_lossy(v).into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn shifts_timestamps() {
+        // 2023-12-31T23:59:30Z
+        let fields = [
+            ("__CURSOR", "s=ab;i=1;t=60dd70e575c80;x=cd"),
+            ("__REALTIME_TIMESTAMP", "1704067170000000"),
+            ("SYSLOG_TIMESTAMP", "Dec 31 23:59:30 "),
+            ("MESSAGE", "tick"),
+        ];
+        let mut rebase = Rebase::new(90_000_000).with_source_offset(1, 0);
+        assert_eq!(
+            shift(&mut rebase, 0, &fields),
+            [
+                "s=ab;i=1;t=60dd713b4a700;x=cd",
+                "1704067260000000",
+                "Jan  1 00:01:00 ",
+                "tick"
+            ]
+        );
+        assert_eq!(shift(&mut rebase, 1, &fields), [] as [String; 0]);
+
+        let mut rebase = Rebase::starting_at(0);
+        let fields = [
+            ("__REALTIME_TIMESTAMP", "1704067170000000"),
+            ("SYSLOG_TIMESTAMP", "2023-12-31T23:59:30.250+01:00"),
+        ];
+        assert_eq!(
+            shift(&mut rebase, 0, &fields),
+            ["0", "1970-01-01T00:00:00.250+01:00"]
+        );
+        let fields = [("__REALTIME_TIMESTAMP", "1704067180000000")];
+        assert_eq!(shift(&mut rebase, 1, &fields), ["10000000"]);
+    }
+}
@@ -0,0 +1,115 @@
+//! Order entries that arrive out of step by their timestamps.
+
+use std::{
+    collections::BinaryHeap,
+    time::{Duration, Instant},
+};
+
+struct Held<T> {
+    timestamp: Option<u64>,
+    seq: u64,
+    arrival: Instant,
+    entry: T,
+}
+
+impl<T> PartialEq for Held<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.seq == other.seq
+    }
+}
+
+impl<T> Eq for Held<T> {}
+
+impl<T> PartialOrd for Held<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Held<T> {
+    // Reversed, such that the heap pops the earliest entry first.
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (other.timestamp, other.seq).cmp(&(self.timestamp, self.seq))
+    }
+}
+
+/// Holds entries back for a window after their arrival and releases them
+/// ordered by timestamp. Entries with equal timestamps are released in the
+/// order of their arrival.
+pub(crate) struct Reorder<T> {
+    window: Duration,
+    heap: BinaryHeap<Held<T>>,
+    seq: u64,
+}
+
+impl<T> Reorder<T> {
+    pub(crate) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            heap: BinaryHeap::new(),
+            seq: 0,
+        }
+    }
+
+    pub(crate) fn push(&mut self, timestamp: Option<u64>, entry: T, now: Instant) {
+        self.seq += 1;
+        self.heap.push(Held {
+            timestamp,
+            seq: self.seq,
+            arrival: now,
+            entry,
+        });
+    }
+
+    /// When the next entry can be released.
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.heap.peek().map(|h| h.arrival + self.window)
+    }
+
+    pub(crate) fn pop(&mut self, now: Instant) -> Option<T> {
+        if self.deadline()? > now {
+            return None;
+        }
+        self.pop_any()
+    }
+
+    /// Releases the next entry before the window has passed if its timestamp
+    /// is at most `watermark`, i.e. no earlier entries are expected.
+    pub(crate) fn pop_until(&mut self, watermark: u64) -> Option<T> {
+        if self.heap.peek()?.timestamp > Some(watermark) {
+            return None;
+        }
+        self.pop_any()
+    }
+
+    pub(crate) fn pop_any(&mut self) -> Option<T> {
+        self.heap.pop().map(|h| h.entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::Reorder;
+
+    #[test]
+    fn reorders_within_window() {
+        let mut reorder = Reorder::new(Duration::from_secs(1));
+        let start = Instant::now();
+        reorder.push(Some(20), b"b".to_vec(), start);
+        reorder.push(Some(10), b"a".to_vec(), start + Duration::from_millis(500));
+        reorder.push(Some(20), b"c".to_vec(), start + Duration::from_millis(600));
+        assert_eq!(reorder.pop(start + Duration::from_millis(900)), None);
+        assert_eq!(
+            reorder.deadline(),
+            Some(start + Duration::from_millis(1500))
+        );
+        assert_eq!(reorder.pop_until(15), Some(b"a".to_vec()));
+        assert_eq!(reorder.pop_until(15), None);
+        let late = start + Duration::from_secs(2);
+        assert_eq!(reorder.pop(late), Some(b"b".to_vec()));
+        assert_eq!(reorder.pop(late), Some(b"c".to_vec()));
+        assert_eq!(reorder.pop(late), None);
+    }
+}
@@ -0,0 +1,100 @@
+//! Choose a fixed number of entries uniformly at random.
+//!
+//! [Reservoir] keeps `n` of the entries written to it such that every subset
+//! of `n` entries is equally likely, however many entries there are, using
+//! Algorithm R. The kept entries are handed out in their original order.
+
+use std::io;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::sink::EntrySink;
+
+pub struct Reservoir {
+    n: usize,
+    seen: u64,
+    /// The kept entries with their index in the input.
+    kept: Vec<(u64, Vec<u8>)>,
+    rng: StdRng,
+}
+
+impl Reservoir {
+    pub fn new(n: usize) -> Self {
+        Self {
+            n,
+            seen: 0,
+            kept: Vec::with_capacity(n.min(1 << 16)),
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Chooses reproducibly.
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            ..self
+        }
+    }
+
+    /// The number of entries written so far.
+    pub fn seen(&self) -> u64 {
+        self.seen
+    }
+
+    /// The kept entries, in the order they were written.
+    pub fn into_entries(mut self) -> Vec<Vec<u8>> {
+        self.kept.sort_unstable_by_key(|(index, _)| *index);
+        self.kept.into_iter().map(|(_, entry)| entry).collect()
+    }
+}
+
+impl EntrySink for Reservoir {
+    fn write_entry(&mut self, entry: &[u8]) -> io::Result<()> {
+        let index = self.seen;
+        self.seen += 1;
+        if self.kept.len() < self.n {
+            self.kept.push((index, entry.to_vec()));
+        } else if self.n > 0 {
+            let j = self.rng.gen_range(0..=index);
+            if j < self.n as u64 {
+                self.kept[j as usize] = (index, entry.to_vec());
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sink::EntrySink;
+
+    use super::Reservoir;
+
+    #[test]
+    fn keeps_n_entries_uniformly_in_order() {
+        let mut hits = [0u32; 10];
+        for seed in 0..2000 {
+            let mut reservoir = Reservoir::new(3).with_seed(seed);
+            for i in 0..10u8 {
+                reservoir.write_entry(&[i]).unwrap();
+            }
+            assert_eq!(reservoir.seen(), 10);
+            let kept = reservoir.into_entries();
+            assert_eq!(kept.len(), 3);
+            assert!(kept.windows(2).all(|w| w[0] < w[1]));
+            for entry in kept {
+                hits[entry[0] as usize] += 1;
+            }
+        }
+        // Each entry is kept with probability 3/10, 600 times on average.
+        assert!(hits.iter().all(|&h| (500..700).contains(&h)), "{:?}", hits);
+
+        let mut reservoir = Reservoir::new(5);
+        reservoir.write_entry(b"only").unwrap();
+        assert_eq!(reservoir.into_entries(), [b"only".to_vec()]);
+    }
+}
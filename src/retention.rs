@@ -0,0 +1,130 @@
+//! Enforce retention constraints on a directory of (rotated) export files.
+//!
+//! The files of a directory are ordered as by [crate::source::discover], i.e.
+//! by the timestamps of their first and last entry. [plan] then selects the
+//! oldest files for removal until the [RetentionPolicy] is met. Files are only
+//! removed as a whole; since a file is never modified after it has been
+//! rotated, its modification time bounds the timestamps of all of its entries.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::source;
+
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Remove files whose entries are all older than this.
+    pub keep: Option<Duration>,
+    /// Remove the oldest files until the total size is at most this many
+    /// bytes.
+    pub max_total: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+/// Lists the export files in `dir`, oldest first.
+pub fn scan(dir: &Path) -> io::Result<Vec<ArchiveFile>> {
+    let mut files = vec![];
+    for path in source::discover(dir)?.exports {
+        let metadata = std::fs::metadata(&path)?;
+        files.push(ArchiveFile {
+            path,
+            size: metadata.len(),
+            modified: metadata.modified()?,
+        });
+    }
+    Ok(files)
+}
+
+/// Selects the files that have to be removed to satisfy `policy`. `files` must
+/// be ordered oldest first; the selected files are a prefix of `files`.
+pub fn plan(files: &[ArchiveFile], policy: &RetentionPolicy, now: SystemTime) -> usize {
+    let mut remove = 0;
+    if let Some(cutoff) = policy.keep.and_then(|keep| now.checked_sub(keep)) {
+        while remove < files.len() && files[remove].modified < cutoff {
+            remove += 1;
+        }
+    }
+    if let Some(max_total) = policy.max_total {
+        let mut total: u64 = files[remove..].iter().map(|f| f.size).sum();
+        while total > max_total && remove < files.len() {
+            total -= files[remove].size;
+            remove += 1;
+        }
+    }
+    remove
+}
+
+/// Removes the oldest export files in `dir` until `policy` is met and returns
+/// the removed files. If `dry_run` is set, nothing is removed.
+pub fn vacuum(dir: &Path, policy: &RetentionPolicy, dry_run: bool) -> io::Result<Vec<ArchiveFile>> {
+    let mut files = scan(dir)?;
+    let n = plan(&files, policy, SystemTime::now());
+    files.truncate(n);
+    if !dry_run {
+        for f in files.iter() {
+            std::fs::remove_file(&f.path)?;
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        time::{Duration, SystemTime},
+    };
+
+    use super::{plan, ArchiveFile, RetentionPolicy};
+
+    fn files(now: SystemTime) -> Vec<ArchiveFile> {
+        // Five files of 10 bytes each, written 5, 4, ..., 1 days ago.
+        (0..5)
+            .map(|i| ArchiveFile {
+                path: PathBuf::from(format!("{}.export", i)),
+                size: 10,
+                modified: now - Duration::from_secs((5 - i) * 24 * 3600),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn plan_by_age() {
+        let now = SystemTime::now();
+        let policy = RetentionPolicy {
+            keep: Some(Duration::from_secs(3 * 24 * 3600 - 1)),
+            max_total: None,
+        };
+        assert_eq!(plan(&files(now), &policy, now), 3);
+    }
+
+    #[test]
+    fn plan_by_size() {
+        let now = SystemTime::now();
+        let policy = RetentionPolicy {
+            keep: None,
+            max_total: Some(25),
+        };
+        assert_eq!(plan(&files(now), &policy, now), 3);
+    }
+
+    #[test]
+    fn plan_by_age_and_size() {
+        let now = SystemTime::now();
+        let policy = RetentionPolicy {
+            keep: Some(Duration::from_secs(30 * 24 * 3600)),
+            max_total: Some(40),
+        };
+        assert_eq!(plan(&files(now), &policy, now), 1);
+        assert_eq!(plan(&files(now), &RetentionPolicy::default(), now), 0);
+    }
+}
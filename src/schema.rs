@@ -0,0 +1,274 @@
+//! Check entries against a schema, e.g. the logging contract of a service.
+//!
+//! A [Schema] is described in TOML. It lists the fields every entry must
+//! have and, per field name or prefix (ending in `*`), the types its values
+//! may have and a regex they must match:
+//!
+//! ```toml
+//! required = ["MESSAGE", "PRIORITY", "SYSLOG_IDENTIFIER"]
+//!
+//! [fields.PRIORITY]
+//! types = ["integer"]
+//! regex = "^[0-7]$"
+//!
+//! [fields."MYAPP_*"]
+//! types = ["string"]
+//! ```
+//!
+//! A value has one of the listed types if it is written as a `string` or
+//! `binary` field, or if it is an `integer` or valid `utf8`. Every field
+//! with a matching name is checked, including repeated ones; fields without
+//! a rule are not.
+
+use std::{collections::BTreeMap, fmt, io};
+
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    journald::{parser::FieldType, Entry},
+    transform::FieldPattern,
+};
+
+#[derive(Error, Debug)]
+pub enum SchemaError {
+    #[error("invalid schema: {0}")]
+    Config(#[from] toml::de::Error),
+    #[error("invalid regular expression: {0}")]
+    Regex(#[from] regex::Error),
+}
+
+impl From<SchemaError> for io::Error {
+    fn from(value: SchemaError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, value)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SchemaConfig {
+    #[serde(default)]
+    pub required: Vec<String>,
+    /// The rules by field name or prefix.
+    #[serde(default)]
+    pub fields: BTreeMap<String, FieldConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct FieldConfig {
+    /// The value must have one of these types; any if empty.
+    #[serde(default)]
+    pub types: Vec<ValueType>,
+    pub regex: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueType {
+    /// Written as a string field.
+    String,
+    /// Written as a binary field.
+    Binary,
+    /// A decimal integer, possibly negative.
+    Integer,
+    Utf8,
+}
+
+impl ValueType {
+    fn admits(&self, value: &[u8], typ: &FieldType) -> bool {
+        match self {
+            ValueType::String => matches!(typ, FieldType::String),
+            ValueType::Binary => matches!(typ, FieldType::Binary),
+            ValueType::Integer => {
+                std::str::from_utf8(value).is_ok_and(|v| v.parse::<i64>().is_ok())
+            }
+            ValueType::Utf8 => std::str::from_utf8(value).is_ok(),
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ValueType::String => "string",
+            ValueType::Binary => "binary",
+            ValueType::Integer => "integer",
+            ValueType::Utf8 => "utf8",
+        })
+    }
+}
+
+/// Why an entry does not conform to a [Schema].
+#[derive(Serialize, PartialEq, Eq, Debug, Clone)]
+#[serde(tag = "violation", rename_all = "snake_case")]
+pub enum Violation {
+    Missing {
+        field: String,
+    },
+    Type {
+        field: String,
+        expected: Vec<ValueType>,
+    },
+    Mismatch {
+        field: String,
+        regex: String,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Missing { field } => write!(f, "missing field {}", field),
+            Violation::Type { field, expected } => {
+                write!(f, "{} is not ", field)?;
+                for (i, t) in expected.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" or ")?;
+                    }
+                    write!(f, "{}", t)?;
+                }
+                Ok(())
+            }
+            Violation::Mismatch { field, regex } => {
+                write!(f, "{} does not match {}", field, regex)
+            }
+        }
+    }
+}
+
+struct FieldRule {
+    pattern: FieldPattern,
+    types: Vec<ValueType>,
+    regex: Option<Regex>,
+}
+
+pub struct Schema {
+    required: Vec<String>,
+    fields: Vec<FieldRule>,
+}
+
+impl Schema {
+    pub fn new(config: &SchemaConfig) -> Result<Self, SchemaError> {
+        let fields = config
+            .fields
+            .iter()
+            .map(|(name, field)| {
+                Ok(FieldRule {
+                    pattern: FieldPattern::new(name.as_str()),
+                    types: field.types.clone(),
+                    regex: field.regex.as_deref().map(Regex::new).transpose()?,
+                })
+            })
+            .collect::<Result<_, SchemaError>>()?;
+        Ok(Self {
+            required: config.required.clone(),
+            fields,
+        })
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self, SchemaError> {
+        Self::new(&toml::from_str(s)?)
+    }
+
+    /// The ways in which `entry` violates the schema; none if it conforms.
+    pub fn validate(&self, entry: &impl Entry) -> Vec<Violation> {
+        let mut violations: Vec<_> = self
+            .required
+            .iter()
+            .filter(|name| entry.get(name.as_bytes()).is_none())
+            .map(|name| Violation::Missing {
+                field: name.clone(),
+            })
+            .collect();
+        for (name, value, typ) in entry.iter() {
+            for rule in self.fields.iter().filter(|r| r.pattern.matches(name)) {
+                let field = || String::from_utf8_lossy(name).into_owned();
+                if !rule.types.is_empty() && !rule.types.iter().any(|t| t.admits(value, &typ)) {
+                    violations.push(Violation::Type {
+                        field: field(),
+                        expected: rule.types.clone(),
+                    });
+                }
+                if let Some(regex) = rule.regex.as_ref().filter(|r| !r.is_match(value)) {
+                    violations.push(Violation::Mismatch {
+                        field: field(),
+                        regex: regex.as_str().to_owned(),
+                    });
+                }
+            }
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        journald::JournalExportRead,
+        testutil::{write_binary, write_string},
+    };
+
+    use super::{Schema, ValueType, Violation};
+
+    #[test]
+    fn validates_entries() {
+        let schema = Schema::from_toml(
+            r#"
+            required = ["MESSAGE", "PRIORITY"]
+
+            [fields.PRIORITY]
+            types = ["integer"]
+            regex = "^[0-7]$"
+
+            [fields."APP_*"]
+            types = ["string", "utf8"]
+            "#,
+        )
+        .unwrap();
+        let mut stream = vec![];
+        write_string(&mut stream, "MESSAGE", "ok");
+        write_string(&mut stream, "PRIORITY", "6");
+        write_binary(&mut stream, "APP_TRACE", "a\nb");
+        write_string(&mut stream, "OTHER", "anything");
+        stream.push(b'\n');
+        write_string(&mut stream, "PRIORITY", "debug");
+        write_binary(&mut stream, "APP_BLOB", b"\xff\n");
+        write_string(&mut stream, "PRIORITY", "9");
+        stream.push(b'\n');
+        let entries: Vec<_> = JournalExportRead::new(&stream[..]).collect();
+
+        assert_eq!(schema.validate(&entries[0]), []);
+        let violations = schema.validate(&entries[1]);
+        let field = |f: &str| f.to_owned();
+        assert_eq!(
+            violations,
+            [
+                Violation::Missing {
+                    field: field("MESSAGE")
+                },
+                Violation::Type {
+                    field: field("PRIORITY"),
+                    expected: vec![ValueType::Integer],
+                },
+                Violation::Mismatch {
+                    field: field("PRIORITY"),
+                    regex: field("^[0-7]$"),
+                },
+                Violation::Type {
+                    field: field("APP_BLOB"),
+                    expected: vec![ValueType::String, ValueType::Utf8],
+                },
+                Violation::Mismatch {
+                    field: field("PRIORITY"),
+                    regex: field("^[0-7]$"),
+                },
+            ]
+        );
+        assert_eq!(violations[3].to_string(), "APP_BLOB is not string or utf8");
+
+        assert!(Schema::from_toml("[fields.X]\nregex = \"(\"").is_err());
+        assert!(Schema::from_toml("[fields.X]\ntypes = [\"float\"]").is_err());
+    }
+}
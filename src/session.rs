@@ -0,0 +1,169 @@
+//! Reconstruct login sessions.
+//!
+//! Entries are assigned to a session by `_AUDIT_SESSION`, falling back to
+//! `_SYSTEMD_SESSION` and the `SESSION_ID` that systemd-logind attaches to
+//! its messages about a session. A [Session] records who was logged in, when
+//! the session was active and which commands ran in it.
+
+use std::io;
+
+use serde::Serialize;
+
+use crate::{
+    group::{Aggregate, GroupBy},
+    journald::Entry,
+};
+
+/// The fields identifying the session of an entry, by preference.
+pub const SESSION_FIELDS: [&str; 3] = ["_AUDIT_SESSION", "_SYSTEMD_SESSION", "SESSION_ID"];
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct Session {
+    pub id: String,
+    /// The user name, as logged by systemd-logind.
+    pub user: Option<String>,
+    /// The audit login UID of the session's processes.
+    pub login_uid: Option<u64>,
+    pub entries: u64,
+    pub start: Option<u64>,
+    pub end: Option<u64>,
+    /// The distinct `_CMDLINE`s of the session, in order of appearance.
+    pub commands: Vec<String>,
+}
+
+impl Session {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            ..Self::default()
+        }
+    }
+}
+
+impl Aggregate for Session {
+    fn push(&mut self, entry: &impl Entry) -> io::Result<()> {
+        self.entries += 1;
+        if let Some(ts) = entry.realtime_timestamp() {
+            self.start = Some(self.start.map_or(ts, |t| t.min(ts)));
+            self.end = Some(self.end.map_or(ts, |t| t.max(ts)));
+        }
+        if self.user.is_none() {
+            self.user = entry
+                .get(b"USER_ID")
+                .map(|u| String::from_utf8_lossy(u).into_owned());
+        }
+        if self.login_uid.is_none() {
+            // Processes outside of a login have the login UID -1.
+            self.login_uid = entry
+                .get_u64(b"_AUDIT_LOGINUID")
+                .filter(|&uid| uid != u32::MAX as u64);
+        }
+        if let Some(cmdline) = entry.get(b"_CMDLINE") {
+            let cmdline = String::from_utf8_lossy(cmdline);
+            if !self.commands.iter().any(|c| *c == cmdline) {
+                self.commands.push(cmdline.into_owned());
+            }
+        }
+        Ok(())
+    }
+}
+
+type NewSession = fn(Option<&[u8]>) -> io::Result<Session>;
+
+/// Collects the sessions of a stream.
+pub struct Sessions {
+    groups: GroupBy<Session, NewSession>,
+}
+
+impl Default for Sessions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sessions {
+    pub fn new() -> Self {
+        let create: NewSession = |id| {
+            Ok(Session::new(String::from_utf8_lossy(
+                id.unwrap_or_default(),
+            )))
+        };
+        let [session, systemd, logind] = SESSION_FIELDS;
+        let groups = GroupBy::new(session, create)
+            .with_fallback(systemd)
+            .with_fallback(logind);
+        Self { groups }
+    }
+
+    pub fn push(&mut self, entry: &impl Entry) -> io::Result<()> {
+        self.groups.push(entry)
+    }
+
+    /// The sessions ordered by their start; entries without a session are
+    /// left out.
+    pub fn into_sessions(self) -> Vec<Session> {
+        let mut sessions: Vec<_> = self
+            .groups
+            .into_groups()
+            .into_iter()
+            .filter_map(|(id, session)| id.map(|_| session))
+            .collect();
+        sessions.sort_by_key(|s| (s.start.is_none(), s.start));
+        sessions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{journald::JournalExportRead, testutil::write_string};
+
+    use super::Sessions;
+
+    #[test]
+    fn reconstructs_sessions() {
+        let mut stream = vec![];
+        let entries: [(u64, &[(&str, &str)]); 5] = [
+            (10, &[("SESSION_ID", "3"), ("USER_ID", "alice")]),
+            (
+                20,
+                &[
+                    ("_AUDIT_SESSION", "3"),
+                    ("_AUDIT_LOGINUID", "1000"),
+                    ("_CMDLINE", "bash"),
+                ],
+            ),
+            (30, &[("_AUDIT_SESSION", "3"), ("_CMDLINE", "sudo -i")]),
+            (5, &[("_SYSTEMD_SESSION", "2"), ("_CMDLINE", "sshd: bob")]),
+            (40, &[("_AUDIT_SESSION", "3"), ("_CMDLINE", "bash")]),
+        ];
+        for (ts, fields) in entries {
+            write_string(&mut stream, "__REALTIME_TIMESTAMP", ts.to_string());
+            for (name, value) in fields {
+                write_string(&mut stream, name, value);
+            }
+            stream.push(b'\n');
+        }
+        write_string(&mut stream, "MESSAGE", "no session");
+        stream.push(b'\n');
+
+        let mut sessions = Sessions::new();
+        for e in JournalExportRead::new(&stream[..]) {
+            sessions.push(&e).unwrap();
+        }
+        let sessions = sessions.into_sessions();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!((sessions[0].id.as_str(), sessions[0].entries), ("2", 1));
+        let s = &sessions[1];
+        assert_eq!(
+            (
+                s.id.as_str(),
+                s.user.as_deref(),
+                s.login_uid,
+                s.start,
+                s.end
+            ),
+            ("3", Some("alice"), Some(1000), Some(10), Some(40))
+        );
+        assert_eq!(s.commands, ["bash", "sudo -i"]);
+    }
+}
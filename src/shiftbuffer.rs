@@ -44,10 +44,16 @@
 //!
 //! In a typical scenario, one would call [ShiftBuffer::make_room] whenever more
 //! data needs to be read into the buffer. This method either shifts the window
-//! or doubles the buffer size, depending on whether the window currently covers
-//! the entire buffer or not.
+//! or grows the buffer, depending on whether the window currently covers the
+//! entire buffer or not. How the buffer grows is determined by the
+//! [GrowthStrategy]; the growth can be capped using
+//! [ShiftBuffer::with_max_size].
 
-use std::ops::{Add, AddAssign, Index, IndexMut, Range, Sub, SubAssign};
+use alloc::vec::Vec;
+use core::{
+    fmt,
+    ops::{Add, AddAssign, Index, IndexMut, Range, Sub, SubAssign},
+};
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Default)]
 pub struct Pointer(usize);
@@ -96,6 +102,33 @@ impl Sub<Pointer> for Pointer {
     }
 }
 
+/// Determines by how much the buffer grows if the window covers the entire
+/// buffer.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum GrowthStrategy {
+    /// Double the size of the buffer.
+    #[default]
+    Double,
+    /// Increase the size of the buffer by its initial size.
+    Linear,
+}
+
+#[derive(Debug)]
+pub struct MaxSizeExceeded(pub usize);
+
+impl fmt::Display for MaxSizeExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Buffer cannot grow beyond its maximum size of {} elements.",
+            self.0
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MaxSizeExceeded {}
+
 pub struct ShiftBuffer<T> {
     buf: Vec<T>,
     // The absolute position of the lower end of the window in the overall byte
@@ -103,6 +136,9 @@ pub struct ShiftBuffer<T> {
     offset: Pointer,
     lower: Pointer,
     upper: Pointer,
+    init_size: usize,
+    max_size: usize,
+    growth: GrowthStrategy,
 }
 
 impl<T: Default + Copy> ShiftBuffer<T> {
@@ -113,12 +149,35 @@ impl<T: Default + Copy> ShiftBuffer<T> {
             offset: Pointer::default(),
             lower: Pointer::default(),
             upper: Pointer::default(),
+            init_size,
+            max_size: usize::MAX,
+            growth: GrowthStrategy::default(),
         }
     }
 
+    /// Limits the size of the internal buffer to `max_size` elements. If the
+    /// initial size exceeds `max_size`, the buffer retains its initial size but
+    /// never grows.
+    pub fn with_max_size(self, max_size: usize) -> Self {
+        assert!(max_size > 0);
+        Self { max_size, ..self }
+    }
+
+    pub fn with_growth(self, growth: GrowthStrategy) -> Self {
+        Self { growth, ..self }
+    }
+
+    /// Discards the content of the buffer while retaining its allocation. All
+    /// pointers obtained before are invalidated.
+    pub fn reset(&mut self) {
+        self.offset = Pointer::default();
+        self.lower = Pointer::default();
+        self.upper = Pointer::default();
+    }
+
     /// Moves the lower end of the window by `n`.
     pub fn shrink(&mut self, n: usize) -> Pointer {
-        assert!(self.lower + n < self.upper);
+        assert!(self.lower + n <= self.upper);
         self.lower += n;
         self.lower
     }
@@ -137,31 +196,59 @@ impl<T: Default + Copy> ShiftBuffer<T> {
     ///
     /// Otherwise, it performs either of two operations: if the lower end is at
     /// the beginning of the buffer (the window covers the entire buffer), the
-    /// buffer is extended. Otherwise, the buffer is shifted; i.e., all entries
-    /// prior to the lower end are discarded and the content is moved to the
-    /// beginning of the buffer.
+    /// buffer is grown according to the [GrowthStrategy]. Otherwise, the buffer
+    /// is shifted; i.e., all entries prior to the lower end are discarded and
+    /// the content is moved to the beginning of the buffer.
     ///
-    /// In all cases, the return value of this method is the same as for
-    /// [ShiftBuffer::free].
-    pub fn make_room(&mut self) -> &mut [T] {
+    /// If the buffer would have to grow beyond its maximum size, an error is
+    /// returned and the state of the buffer remains unchanged. Otherwise, the
+    /// return value of this method is the same as for [ShiftBuffer::free].
+    pub fn make_room(&mut self) -> Result<&mut [T], MaxSizeExceeded> {
         if self.relative_pos(self.upper) == self.buf.len() {
             if self.lower == self.offset {
-                self.buf.extend((0..self.buf.len()).map(|_| T::default()))
+                self.grow()?;
             } else {
                 self.shift();
             }
         }
-        self.free()
+        Ok(self.free())
     }
 
-    pub fn shift(&mut self) {
-        let d = self.upper.abs() - self.lower.abs();
-        for p in 0..d {
-            self.buf[p] = self.buf[p + d]
+    fn grow(&mut self) -> Result<(), MaxSizeExceeded> {
+        let len = self.buf.len();
+        if len >= self.max_size {
+            return Err(MaxSizeExceeded(self.max_size));
         }
+        let increment = match self.growth {
+            GrowthStrategy::Double => len,
+            GrowthStrategy::Linear => self.init_size,
+        };
+        let new_len = len.saturating_add(increment.max(1)).min(self.max_size);
+        self.buf.resize(new_len, T::default());
+        Ok(())
+    }
+
+    /// Moves the content of the window to the beginning of the buffer,
+    /// discarding all entries prior to the lower end.
+    pub fn shift(&mut self) {
+        let (l, u) = (self.relative_pos(self.lower), self.relative_pos(self.upper));
+        self.buf.copy_within(l..u, 0);
         self.offset = self.lower;
     }
 
+    /// Copies the elements of `src` to `dest`; the ranges may overlap.
+    pub fn copy_within(&mut self, src: Range<Pointer>, dest: Pointer) {
+        let (l, u) = (self.relative_pos(src.start), self.relative_pos(src.end));
+        let d = self.relative_pos(dest);
+        self.buf.copy_within(l..u, d);
+    }
+
+    /// The entire internal buffer, also outside of the window. It moves when
+    /// the buffer grows.
+    pub fn allocation_mut(&mut self) -> &mut [T] {
+        &mut self.buf
+    }
+
     pub fn free(&mut self) -> &mut [T] {
         let r = self.relative_pos(self.upper);
         &mut self.buf[r..]
@@ -182,12 +269,21 @@ impl<T: Default + Copy> ShiftBuffer<T> {
 
     /// Create a shift buffer that contains a copy of the current window.
     pub fn clone_window(&self) -> ShiftBuffer<T> {
-        let (l, u) = (self.lower, self.upper);
+        self.clone_range(self.lower, self.upper)
+    }
+
+    /// Like [ShiftBuffer::clone_window], but only retains the part `l..u` of
+    /// the window.
+    pub fn clone_range(&self, l: Pointer, u: Pointer) -> ShiftBuffer<T> {
+        assert!(self.lower <= l && l <= u && u <= self.upper);
         ShiftBuffer {
             buf: self[l..u].to_vec(),
             offset: l,
             lower: l,
             upper: u,
+            init_size: self.init_size,
+            max_size: self.max_size,
+            growth: self.growth,
         }
     }
 }
@@ -222,9 +318,12 @@ impl<T: Default + Copy> Index<Range<Pointer>> for ShiftBuffer<T> {
 
 #[cfg(test)]
 mod tests {
-    use super::ShiftBuffer;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{GrowthStrategy, ShiftBuffer};
 
     #[test]
+    #[allow(clippy::explicit_counter_loop)]
     fn store_simple_string() {
         let input_string = "ABC";
         let mut sbuf = ShiftBuffer::<u8>::new(1 << 10);
@@ -238,4 +337,74 @@ mod tests {
 
         assert_eq!(&sbuf[lower..upper], input_string.as_bytes());
     }
+
+    #[test]
+    fn shift_retains_window_content() {
+        let mut sbuf = ShiftBuffer::<u8>::new(8);
+        let lower = sbuf.lower();
+        let upper = sbuf.extend(8);
+        for (i, b) in b"01234567".iter().enumerate() {
+            sbuf[lower + i] = *b;
+        }
+        // Window [3, 8) is not located at offset `upper - lower`.
+        let lower = sbuf.shrink(3);
+        sbuf.shift();
+
+        assert_eq!(&sbuf[lower..upper], b"34567");
+        assert_eq!(sbuf.free().len(), 3);
+    }
+
+    #[test]
+    fn random_extend_shrink_shift_cycles() {
+        for seed in 0..64 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut sbuf = ShiftBuffer::<u8>::new(rng.gen_range(1..64));
+            // Mirror of the data stream that is expected inside the window.
+            let mut expected: Vec<u8> = vec![];
+            let mut next_byte = 0u8;
+
+            for _ in 0..256 {
+                match rng.gen_range(0..3) {
+                    0 => {
+                        let free = sbuf.make_room().unwrap();
+                        let n = rng.gen_range(0..=free.len());
+                        for b in free[..n].iter_mut() {
+                            *b = next_byte;
+                            expected.push(next_byte);
+                            next_byte = next_byte.wrapping_add(1);
+                        }
+                        sbuf.extend(n);
+                    }
+                    1 => {
+                        let n = rng.gen_range(0..=expected.len());
+                        sbuf.shrink(n);
+                        expected.drain(..n);
+                    }
+                    _ => sbuf.shift(),
+                }
+                assert_eq!(&sbuf[sbuf.lower()..sbuf.upper()], &expected[..]);
+            }
+        }
+    }
+
+    #[test]
+    fn growth_respects_strategy_and_cap() {
+        fn free_sizes(mut sbuf: ShiftBuffer<u8>) -> Vec<usize> {
+            let mut sizes = vec![];
+            while let Ok(free) = sbuf.make_room() {
+                let n = free.len();
+                sbuf.extend(n);
+                sizes.push(n);
+            }
+            sizes
+        }
+
+        let double = ShiftBuffer::<u8>::new(4).with_max_size(20);
+        let linear = ShiftBuffer::<u8>::new(4)
+            .with_max_size(20)
+            .with_growth(GrowthStrategy::Linear);
+
+        assert_eq!(free_sizes(double), vec![4, 4, 8, 4]);
+        assert_eq!(free_sizes(linear), vec![4, 4, 4, 4, 4]);
+    }
 }
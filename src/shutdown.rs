@@ -0,0 +1,188 @@
+//! Stop long-running modes gracefully.
+//!
+//! A [Shutdown] is a flag that is raised once, e.g. by SIGTERM or SIGINT
+//! (see [Shutdown::install]). Loops poll [Shutdown::is_requested] between
+//! entries; components that block, such as sockets or child processes,
+//! register a hook with [Shutdown::on_request] that unblocks them.
+//!
+//! [Checkpoint] records how far every source was read, such that a mode that
+//! was stopped can resume where it left off.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+#[cfg(unix)]
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
+};
+
+use crate::journald::Entry;
+
+type Hook = Box<dyn FnOnce() + Send>;
+
+/// A request to shut down; clones share the request.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+    hooks: Arc<Mutex<Vec<Hook>>>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests a shutdown on the first SIGTERM or SIGINT. A second signal
+    /// exits the process immediately with status 130. Does nothing on
+    /// platforms without signals.
+    #[cfg(unix)]
+    pub fn install(&self) -> io::Result<()> {
+        let mut signals = Signals::new([SIGTERM, SIGINT])?;
+        let shutdown = self.clone();
+        std::thread::spawn(move || {
+            for _ in signals.forever() {
+                if shutdown.is_requested() {
+                    std::process::exit(130);
+                }
+                shutdown.request();
+            }
+        });
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn install(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Raises the flag and runs the registered hooks; does nothing if the
+    /// flag is raised already.
+    pub fn request(&self) {
+        if self.requested.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let hooks = std::mem::take(&mut *self.hooks.lock().unwrap_or_else(|e| e.into_inner()));
+        for hook in hooks {
+            hook();
+        }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Runs `hook` once a shutdown is requested, or right away if it was
+    /// requested already.
+    pub fn on_request(&self, hook: impl FnOnce() + Send + 'static) {
+        let mut hooks = self.hooks.lock().unwrap_or_else(|e| e.into_inner());
+        if self.is_requested() {
+            drop(hooks);
+            hook();
+        } else {
+            hooks.push(Box::new(hook));
+        }
+    }
+}
+
+/// How far a source was read.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct SourceCheckpoint {
+    /// The number of entries read from the source.
+    pub entries: u64,
+    /// The `__CURSOR` of the last entry read, if it had one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+/// The positions of a set of sources, stored as JSON.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct Checkpoint {
+    pub sources: BTreeMap<String, SourceCheckpoint>,
+}
+
+impl Checkpoint {
+    /// Reads a checkpoint; a missing file yields an empty checkpoint.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read(path) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Replaces the file at `path` atomically.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(tmp, path)
+    }
+
+    pub fn source(&self, key: &str) -> Option<&SourceCheckpoint> {
+        self.sources.get(key)
+    }
+
+    /// Records that `entry` was read from the source `key`.
+    pub fn record(&mut self, key: &str, entry: &impl Entry) {
+        let source = self.sources.entry(key.to_string()).or_default();
+        source.entries += 1;
+        if let Some(cursor) = entry.get(b"__CURSOR") {
+            source.cursor = Some(String::from_utf8_lossy(cursor).into_owned());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::{Checkpoint, Shutdown};
+    use crate::journald::JournalExportRead;
+
+    #[test]
+    fn runs_hooks_once() {
+        let shutdown = Shutdown::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let hook = |calls: &Arc<AtomicUsize>| {
+            let calls = Arc::clone(calls);
+            move || {
+                calls.fetch_add(1, Ordering::Relaxed);
+            }
+        };
+        shutdown.on_request(hook(&calls));
+        shutdown.clone().request();
+        shutdown.request();
+        assert!(shutdown.is_requested());
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        shutdown.on_request(hook(&calls));
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn saves_checkpoints() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        assert_eq!(Checkpoint::load(&path).unwrap(), Checkpoint::default());
+        let mut checkpoint = Checkpoint::default();
+        let input = b"__CURSOR=s=1\nA=1\n\nA=2\n\n".as_slice();
+        for entry in JournalExportRead::new(input) {
+            checkpoint.record("journal:", &entry);
+        }
+        checkpoint.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        let source = loaded.source("journal:").unwrap();
+        assert_eq!((source.entries, source.cursor.as_deref()), (2, Some("s=1")));
+    }
+}
@@ -0,0 +1,450 @@
+//! Write journal entries to files.
+//!
+//! [EntrySink] abstracts over destinations that accept whole entries. Any
+//! [Write] is an [EntrySink]; [RotatingExportWriter] distributes the entries
+//! over a series of files, starting a new file whenever the current one
+//! exceeds a maximum size or age. Files are only rotated between entries, i.e.
+//! every file is a valid export stream on its own.
+//!
+//! [ResumePoint] supports appending to the output of an interrupted run
+//! without duplicating entries.
+
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    journald::{Entry, ErrorKind, JournalExportRead},
+    trace,
+};
+
+/// A destination for journal entries in the Journal Export Format.
+pub trait EntrySink {
+    /// Writes one entry, including the terminating empty line.
+    fn write_entry(&mut self, entry: &[u8]) -> io::Result<()>;
+    /// Flushes all entries and closes the destination, if applicable.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+impl<W: Write + ?Sized> EntrySink for W {
+    fn write_entry(&mut self, entry: &[u8]) -> io::Result<()> {
+        self.write_all(entry)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+/// Compression applied to files once they are closed.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+}
+
+struct CurrentFile {
+    path: PathBuf,
+    file: BufWriter<File>,
+    written: u64,
+    opened: Instant,
+}
+
+/// Writes entries to files named `<prefix>-YYYYMMDD-HHMM-N.export`, where the
+/// time is the (UTC) time at which the file was opened and `N` distinguishes
+/// files opened within the same minute.
+pub struct RotatingExportWriter {
+    prefix: PathBuf,
+    max_size: Option<u64>,
+    max_age: Option<Duration>,
+    compression: Option<Compression>,
+    current: Option<CurrentFile>,
+    closed: Vec<PathBuf>,
+}
+
+impl RotatingExportWriter {
+    /// `prefix` may contain directories, e.g. `archive/host`; these must
+    /// exist.
+    pub fn new(prefix: impl Into<PathBuf>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            max_size: None,
+            max_age: None,
+            compression: None,
+            current: None,
+            closed: vec![],
+        }
+    }
+
+    /// Starts a new file before an entry would push the current file beyond
+    /// `max_size` bytes. A single entry larger than `max_size` still ends up
+    /// in a file of its own.
+    pub fn with_max_size(self, max_size: u64) -> Self {
+        assert!(max_size > 0);
+        Self {
+            max_size: Some(max_size),
+            ..self
+        }
+    }
+
+    /// Starts a new file once the current one has been open for `max_age`.
+    pub fn with_max_age(self, max_age: Duration) -> Self {
+        Self {
+            max_age: Some(max_age),
+            ..self
+        }
+    }
+
+    pub fn with_compression(self, compression: Compression) -> Self {
+        Self {
+            compression: Some(compression),
+            ..self
+        }
+    }
+
+    /// Closes the current file (if any) such that the next entry is written
+    /// to a new file.
+    pub fn rotate(&mut self) -> io::Result<()> {
+        let Some(mut current) = self.current.take() else {
+            return Ok(());
+        };
+        let _span = trace::debug_span!("rotate", path = %current.path.display());
+        current.file.flush()?;
+        drop(current.file);
+        let path = match self.compression {
+            Some(c) => compress(&current.path, c)?,
+            None => current.path,
+        };
+        trace::debug!(bytes = current.written, "closed file");
+        self.closed.push(path);
+        Ok(())
+    }
+
+    /// The files that were closed so far, in the order they were written.
+    pub fn closed_files(&self) -> &[PathBuf] {
+        &self.closed
+    }
+
+    fn needs_rotation(&self, len: usize) -> bool {
+        let Some(current) = &self.current else {
+            return false;
+        };
+        if current.written == 0 {
+            return false;
+        }
+        let too_large = self
+            .max_size
+            .is_some_and(|max| current.written + len as u64 > max);
+        let too_old = self
+            .max_age
+            .is_some_and(|max| current.opened.elapsed() >= max);
+        too_large || too_old
+    }
+
+    fn open_next(&self) -> io::Result<CurrentFile> {
+        let stamp = chrono::Utc::now().format("%Y%m%d-%H%M");
+        let prefix = self.prefix.to_string_lossy();
+        for n in 0.. {
+            let path = PathBuf::from(format!("{}-{}-{}.export", prefix, stamp, n));
+            if self
+                .compression
+                .is_some_and(|c| compressed_path(&path, c).exists())
+            {
+                continue;
+            }
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(f) => {
+                    return Ok(CurrentFile {
+                        path,
+                        file: BufWriter::new(f),
+                        written: 0,
+                        opened: Instant::now(),
+                    })
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        unreachable!()
+    }
+}
+
+impl EntrySink for RotatingExportWriter {
+    fn write_entry(&mut self, entry: &[u8]) -> io::Result<()> {
+        if self.needs_rotation(entry.len()) {
+            self.rotate()?;
+        }
+        if self.current.is_none() {
+            self.current = Some(self.open_next()?);
+        }
+        let current = self.current.as_mut().unwrap();
+        current.file.write_all(entry)?;
+        current.written += entry.len() as u64;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.rotate()
+    }
+}
+
+/// Describes how far an existing export file was written.
+///
+/// The resume point records the timestamp of the last entry of the file and
+/// the cursors of all entries with that timestamp. When the output is ordered
+/// by time, [ResumePoint::skips] tells which entries have already been
+/// written. Otherwise, when the inputs are read in the same order again,
+/// [ResumePoint::skips_in_sequence] skips the entries up to the last entry
+/// of the file.
+#[derive(Debug, Clone, Default)]
+pub struct ResumePoint {
+    timestamp: Option<u64>,
+    cursors: HashSet<Vec<u8>>,
+    /// The last entry of the file and its cursor.
+    last: Option<(Vec<u8>, Option<Vec<u8>>)>,
+    passed: bool,
+    /// The length of the prefix of the file that consists of complete
+    /// entries.
+    pub valid_len: u64,
+}
+
+impl ResumePoint {
+    /// Reads the export file at `path`. A trailing incomplete entry (e.g. of
+    /// an interrupted write) is not considered part of the file; see
+    /// [ResumePoint::valid_len]. Other parse errors are returned.
+    pub fn from_export(path: &Path) -> io::Result<Self> {
+        let mut jreader = JournalExportRead::new(File::open(path)?);
+        let mut point = ResumePoint::default();
+        loop {
+            match jreader.parse_next() {
+                Ok(Some(())) => (),
+                Ok(None) => return Ok(point),
+                Err(e) if matches!(e.kind(), ErrorKind::UnexpectedEof) => return Ok(point),
+                Err(e) => return Err(e.into()),
+            }
+            let e = jreader.get_entry();
+            point.valid_len = jreader.position() as u64;
+            let ts = e.realtime_timestamp();
+            if ts != point.timestamp {
+                point.timestamp = ts;
+                point.cursors.clear();
+            }
+            if let Some(cursor) = e.get(b"__CURSOR") {
+                point.cursors.insert(cursor.to_vec());
+            }
+            let cursor = e.get(b"__CURSOR").map(|c| c.to_vec());
+            point.last = Some((e.as_bytes().to_vec(), cursor));
+        }
+    }
+
+    /// Whether `entry` precedes the resume point, i.e. has been written
+    /// already. Entries without a timestamp are never skipped.
+    pub fn skips(&self, entry: &impl Entry) -> bool {
+        let (Some(last), Some(ts)) = (self.timestamp, entry.realtime_timestamp()) else {
+            return false;
+        };
+        ts < last
+            || (ts == last
+                && entry
+                    .get(b"__CURSOR")
+                    .is_some_and(|c| self.cursors.contains(c)))
+    }
+
+    /// Whether `entry` precedes the last entry of the file in the input or is
+    /// that entry, regardless of timestamps. The last entry is recognized by
+    /// its cursor or, lacking one, by its bytes.
+    pub fn skips_in_sequence(&mut self, entry: &impl Entry) -> bool {
+        let Some((bytes, cursor)) = self.last.as_ref().filter(|_| !self.passed) else {
+            return false;
+        };
+        self.passed = match (cursor, entry.get(b"__CURSOR")) {
+            (Some(cursor), Some(c)) => cursor == c,
+            _ => bytes == entry.as_bytes(),
+        };
+        true
+    }
+
+    /// Whether [ResumePoint::skips_in_sequence] found the last entry of the
+    /// file, or the file is empty. Otherwise, all entries were skipped.
+    pub fn is_passed(&self) -> bool {
+        self.passed || self.last.is_none()
+    }
+}
+
+fn compressed_path(path: &Path, compression: Compression) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(compression.extension());
+    PathBuf::from(name)
+}
+
+/// Compresses the file at `path` and removes the uncompressed file. Returns
+/// the path of the compressed file.
+fn compress(path: &Path, compression: Compression) -> io::Result<PathBuf> {
+    let target = compressed_path(path, compression);
+    let mut src = File::open(path)?;
+    let dst = BufWriter::new(File::create(&target)?);
+    match compression {
+        Compression::Gzip => {
+            let mut enc = flate2::write::GzEncoder::new(dst, flate2::Compression::default());
+            io::copy(&mut src, &mut enc)?;
+            enc.finish()?.flush()?;
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => {
+            let mut enc = zstd::Encoder::new(dst, 0)?;
+            io::copy(&mut src, &mut enc)?;
+            enc.finish()?.flush()?;
+        }
+        #[cfg(not(feature = "zstd"))]
+        Compression::Zstd => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "zstd compression is not supported by this build",
+            ))
+        }
+    }
+    std::fs::remove_file(path)?;
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use crate::{generate::EntryGenerator, journald::JournalExportRead, source};
+
+    use super::{Compression, EntrySink, ResumePoint, RotatingExportWriter};
+
+    fn entries(n: usize) -> Vec<Vec<u8>> {
+        let mut generator = EntryGenerator::new(0);
+        (0..n)
+            .map(|_| {
+                let mut e = vec![];
+                generator.write_entry(&mut e);
+                e
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rotates_by_size_between_entries() -> std::io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let entries = entries(20);
+        let max_size = entries.iter().map(|e| e.len()).max().unwrap() as u64 * 3;
+        let mut writer = RotatingExportWriter::new(dir.path().join("host")).with_max_size(max_size);
+        for e in entries.iter() {
+            writer.write_entry(e)?;
+        }
+        writer.finish()?;
+
+        let files = writer.closed_files();
+        assert!(files.len() > 1);
+        let mut all = vec![];
+        for f in files {
+            let content = std::fs::read(f)?;
+            assert!(content.len() as u64 <= max_size);
+            let mut jreader = JournalExportRead::new(&content[..]);
+            while jreader.parse_next().unwrap().is_some() {}
+            all.extend(content);
+        }
+        assert_eq!(all, entries.concat());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn closed_files_are_compressed() -> std::io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let entries = entries(3);
+        let mut writer =
+            RotatingExportWriter::new(dir.path().join("host")).with_compression(Compression::Zstd);
+        for e in entries.iter() {
+            writer.write_entry(e)?;
+        }
+        writer.rotate()?;
+        writer.write_entry(&entries[0])?;
+        writer.finish()?;
+
+        let files = writer.closed_files().to_vec();
+        assert_eq!(files.len(), 2);
+        assert_ne!(files[0], files[1]);
+        assert!(files
+            .iter()
+            .all(|f| f.to_string_lossy().ends_with(".export.zst")));
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 2);
+
+        let mut content = vec![];
+        source::open(&files[0])?.read_to_end(&mut content)?;
+        assert_eq!(content, entries.concat());
+        Ok(())
+    }
+
+    #[test]
+    fn resume_point_skips_written_entries() -> std::io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("out.export");
+        let entries = entries(10);
+        let mut written = entries[..6].concat();
+        let valid_len = written.len() as u64;
+        // An interrupted write leaves an incomplete entry behind.
+        written.extend_from_slice(&entries[6][..20]);
+        std::fs::write(&path, &written)?;
+
+        let point = ResumePoint::from_export(&path)?;
+        assert_eq!(point.valid_len, valid_len);
+        let stream = entries.concat();
+        let mut jreader = JournalExportRead::new(&stream[..]);
+        let mut skipped = 0;
+        while jreader.parse_next().unwrap().is_some() {
+            if point.skips(&jreader.get_entry()) {
+                skipped += 1;
+            }
+        }
+        assert_eq!(skipped, 6);
+        Ok(())
+    }
+
+    #[test]
+    fn resume_point_skips_in_sequence() -> std::io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("out.export");
+        // The second half of the input is older than the first one.
+        let entries = entries(10);
+        let input = [entries[5..].concat(), entries[..5].concat()].concat();
+        std::fs::write(&path, [&entries[5][..], &entries[7]].concat())?;
+
+        let mut point = ResumePoint::from_export(&path)?;
+        let mut jreader = JournalExportRead::new(&input[..]);
+        let mut kept = 0;
+        while jreader.parse_next().unwrap().is_some() {
+            if !point.skips_in_sequence(&jreader.get_entry()) {
+                kept += 1;
+            }
+        }
+        assert_eq!(kept, 7);
+        assert!(point.is_passed());
+
+        let mut point = ResumePoint::from_export(&path)?;
+        let older = entries[..5].concat();
+        let mut jreader = JournalExportRead::new(&older[..]);
+        while jreader.parse_next().unwrap().is_some() {
+            assert!(point.skips_in_sequence(&jreader.get_entry()));
+        }
+        assert!(!point.is_passed());
+        Ok(())
+    }
+}
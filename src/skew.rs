@@ -0,0 +1,136 @@
+//! Estimate the offsets between the clocks of several sources.
+//!
+//! Entries are stamped by the clock of the machine that received them; when
+//! the journals of machines with wrong clocks are merged by timestamp, their
+//! entries interleave in the wrong order. [ClockSkew] estimates by how much
+//! the clocks of the sources are off from each other using the boots that
+//! several sources contain: as `__MONOTONIC_TIMESTAMP` counts from the start
+//! of the boot, `__REALTIME_TIMESTAMP` minus `__MONOTONIC_TIMESTAMP` is the
+//! time at which the boot started according to the clock of the source. The
+//! offset between two sources is the median of the differences between the
+//! start times of their common boots.
+//!
+//! The offsets can be applied to the order of a merge with
+//! [MultiRead::with_clock_offsets](crate::merge::MultiRead::with_clock_offsets).
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::journald::Entry;
+
+pub struct ClockSkew {
+    sources: usize,
+    /// The earliest start time of every boot in each source.
+    starts: HashMap<Vec<u8>, Vec<Option<i64>>>,
+}
+
+impl ClockSkew {
+    pub fn new(sources: usize) -> Self {
+        Self {
+            sources,
+            starts: HashMap::new(),
+        }
+    }
+
+    /// Records the start time of the boot of `entry`, which was read from
+    /// the source at index `source`. Entries without `_BOOT_ID` or either
+    /// timestamp are ignored.
+    pub fn record(&mut self, source: usize, entry: &impl Entry) {
+        let (Some(boot), Some(realtime), Some(monotonic)) = (
+            entry.get(b"_BOOT_ID"),
+            entry.realtime_timestamp(),
+            entry.get_u64(b"__MONOTONIC_TIMESTAMP"),
+        ) else {
+            return;
+        };
+        let start = realtime as i64 - monotonic as i64;
+        let starts = match self.starts.get_mut(boot) {
+            Some(starts) => starts,
+            None => self
+                .starts
+                .entry(boot.to_vec())
+                .or_insert_with(|| vec![None; self.sources]),
+        };
+        let s = &mut starts[source];
+        *s = Some(s.map_or(start, |s| s.min(start)));
+    }
+
+    /// The microseconds to add to the timestamps of each source such that
+    /// they match the clock of the first source it has a boot in common with,
+    /// directly or through other sources. Sources without a boot in common
+    /// with any other source are `None`.
+    pub fn offsets(&self) -> Vec<Option<i64>> {
+        // The offset of source j relative to source i for every pair of
+        // sources with common boots.
+        let mut pairs: HashMap<(usize, usize), Vec<i64>> = HashMap::new();
+        for starts in self.starts.values() {
+            for (i, a) in starts.iter().enumerate() {
+                for (j, b) in starts.iter().enumerate().skip(i + 1) {
+                    if let (Some(a), Some(b)) = (a, b) {
+                        pairs.entry((i, j)).or_default().push(a - b);
+                    }
+                }
+            }
+        }
+        let mut edges = vec![vec![]; self.sources];
+        for ((i, j), mut diffs) in pairs {
+            diffs.sort_unstable();
+            let median = diffs[diffs.len() / 2];
+            edges[i].push((j, median));
+            edges[j].push((i, -median));
+        }
+
+        let mut offsets = vec![None; self.sources];
+        for root in 0..self.sources {
+            if offsets[root].is_some() || edges[root].is_empty() {
+                continue;
+            }
+            offsets[root] = Some(0);
+            let mut queue = VecDeque::from([root]);
+            while let Some(i) = queue.pop_front() {
+                let base = offsets[i].unwrap_or(0);
+                for &(j, offset) in edges[i].iter() {
+                    if offsets[j].is_none() {
+                        offsets[j] = Some(base + offset);
+                        queue.push_back(j);
+                    }
+                }
+            }
+        }
+        offsets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{journald::JournalExportRead, testutil::write_string};
+
+    use super::ClockSkew;
+
+    fn entry(boot: &str, realtime: u64, monotonic: u64) -> Vec<u8> {
+        let mut e = vec![];
+        write_string(&mut e, "_BOOT_ID", boot);
+        write_string(&mut e, "__REALTIME_TIMESTAMP", realtime.to_string());
+        write_string(&mut e, "__MONOTONIC_TIMESTAMP", monotonic.to_string());
+        e.push(b'\n');
+        e
+    }
+
+    #[test]
+    fn estimates_offsets_from_common_boots() {
+        let streams = [
+            [entry("a", 1_000, 100), entry("b", 5_000, 10)].concat(),
+            // 300µs ahead of the first source.
+            [entry("a", 1_350, 150), entry("c", 9_000, 10)].concat(),
+            // 50µs behind the second source, only sharing boot c.
+            [entry("c", 8_950, 10)].concat(),
+            [entry("d", 1_000, 10)].concat(),
+        ];
+        let mut skew = ClockSkew::new(streams.len());
+        for (i, s) in streams.iter().enumerate() {
+            for e in JournalExportRead::new(&s[..]) {
+                skew.record(i, &e);
+            }
+        }
+        assert_eq!(skew.offsets(), [Some(0), Some(-300), Some(-250), None]);
+    }
+}
@@ -0,0 +1,271 @@
+//! Sort journal export streams that may not fit into memory.
+//!
+//! [ExternalSort] collects entries in memory until their size exceeds the run
+//! size, sorts them and spills them as a run to a temporary file. Once all
+//! entries were pushed, [ExternalSort::finish] returns a [SortedRead] that
+//! merges the runs. The sort is stable: entries with equal keys retain their
+//! input order.
+
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Cursor, Read, Seek, Write},
+    path::PathBuf,
+};
+
+use crate::journald::{Entry, JournalExportRead, JournalExportReadError, RefEntry};
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum SortKey {
+    /// `__REALTIME_TIMESTAMP`.
+    #[default]
+    Realtime,
+    /// `_BOOT_ID`, then `__MONOTONIC_TIMESTAMP`. Entries are grouped by boot;
+    /// the boots are ordered by their ID.
+    Monotonic,
+    /// `__SEQNUM_ID`, then `__SEQNUM`.
+    Seqnum,
+}
+
+// Entries lacking the (numeric part of the) key sort first.
+type KeyValue = (Vec<u8>, Option<u64>);
+
+impl SortKey {
+    fn value(&self, entry: &impl Entry) -> KeyValue {
+        let (group, number): (&[u8], &[u8]) = match self {
+            SortKey::Realtime => (b"", b"__REALTIME_TIMESTAMP"),
+            SortKey::Monotonic => (b"_BOOT_ID", b"__MONOTONIC_TIMESTAMP"),
+            SortKey::Seqnum => (b"__SEQNUM_ID", b"__SEQNUM"),
+        };
+        let group = match group {
+            b"" => vec![],
+            name => entry.get(name).unwrap_or_default().to_vec(),
+        };
+        (group, entry.get_u64(number))
+    }
+}
+
+pub struct ExternalSort {
+    key: SortKey,
+    run_size: usize,
+    tmp_dir: PathBuf,
+    buf: Vec<u8>,
+    entries: Vec<(KeyValue, usize, usize)>,
+    runs: Vec<File>,
+}
+
+impl ExternalSort {
+    pub fn new(key: SortKey) -> Self {
+        Self {
+            key,
+            run_size: 1 << 28,
+            tmp_dir: std::env::temp_dir(),
+            buf: vec![],
+            entries: vec![],
+            runs: vec![],
+        }
+    }
+
+    /// Spills the collected entries once their total size exceeds
+    /// `run_size` bytes. The memory used is roughly `run_size` plus the keys
+    /// of the collected entries.
+    pub fn with_run_size(self, run_size: usize) -> Self {
+        assert!(run_size > 0);
+        Self { run_size, ..self }
+    }
+
+    /// The directory where runs are spilled to; defaults to
+    /// [std::env::temp_dir]. The files are removed once they are closed.
+    pub fn with_tmp_dir(self, tmp_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            tmp_dir: tmp_dir.into(),
+            ..self
+        }
+    }
+
+    pub fn push(&mut self, entry: &impl Entry) -> io::Result<()> {
+        let start = self.buf.len();
+        self.buf.extend_from_slice(entry.as_bytes());
+        self.entries
+            .push((self.key.value(entry), start, self.buf.len()));
+        if self.buf.len() >= self.run_size {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// The number of runs that were spilled to disk so far.
+    pub fn runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    pub fn finish(mut self) -> io::Result<SortedRead> {
+        let mut runs: Vec<JournalExportRead<Box<dyn Read>>> = vec![];
+        if self.runs.is_empty() {
+            runs.push(JournalExportRead::new(Box::new(Cursor::new(
+                self.sorted_run(),
+            ))));
+        } else {
+            self.spill()?;
+            for f in self.runs {
+                runs.push(JournalExportRead::new(Box::new(BufReader::new(f))));
+            }
+        }
+        Ok(SortedRead {
+            key: self.key,
+            runs,
+            heads: BinaryHeap::new(),
+            primed: false,
+            current: None,
+        })
+    }
+
+    fn sorted_run(&mut self) -> Vec<u8> {
+        self.entries.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut run = Vec::with_capacity(self.buf.len());
+        for (_, start, end) in self.entries.drain(..) {
+            run.extend_from_slice(&self.buf[start..end]);
+        }
+        self.buf.clear();
+        run
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        if self.entries.is_empty() {
+            return Ok(());
+        }
+        let run = self.sorted_run();
+        let mut f = BufWriter::new(tempfile::tempfile_in(&self.tmp_dir)?);
+        f.write_all(&run)?;
+        let mut f = f.into_inner().map_err(|e| e.into_error())?;
+        f.rewind()?;
+        self.runs.push(f);
+        Ok(())
+    }
+}
+
+/// Yields the entries pushed to an [ExternalSort] in sorted order.
+///
+/// Like [JournalExportRead], [SortedRead] is a stateful object: the entry that
+/// was parsed last can be accessed using [SortedRead::get_entry].
+pub struct SortedRead {
+    key: SortKey,
+    runs: Vec<JournalExportRead<Box<dyn Read>>>,
+    // The keys of the current entries of all runs that are not exhausted.
+    // Ties are broken by the run index, which keeps the sort stable.
+    heads: BinaryHeap<Reverse<(KeyValue, usize)>>,
+    primed: bool,
+    current: Option<usize>,
+}
+
+impl SortedRead {
+    pub fn parse_next(&mut self) -> Result<Option<()>, JournalExportReadError> {
+        if !self.primed {
+            for i in 0..self.runs.len() {
+                self.advance(i)?;
+            }
+            self.primed = true;
+        } else if let Some(i) = self.current.take() {
+            self.advance(i)?;
+        }
+        self.current = self.heads.pop().map(|Reverse((_, i))| i);
+        Ok(self.current.map(|_| ()))
+    }
+
+    /// Returns the entry that was parsed last.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the last call to [SortedRead::parse_next] did not yield an
+    /// entry.
+    pub fn get_entry(&self) -> RefEntry<'_> {
+        let i = self.current.expect("no current entry");
+        self.runs[i].get_entry()
+    }
+
+    fn advance(&mut self, i: usize) -> Result<(), JournalExportReadError> {
+        if self.runs[i].parse_next()?.is_some() {
+            let key = self.key.value(&self.runs[i].get_entry());
+            self.heads.push(Reverse((key, i)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    use crate::{
+        generate::EntryGenerator,
+        journald::{Entry, JournalExportRead},
+    };
+
+    use super::{ExternalSort, SortKey};
+
+    fn shuffled(n: usize) -> Vec<Vec<u8>> {
+        let mut generator = EntryGenerator::new(9);
+        let mut entries: Vec<_> = (0..n)
+            .map(|_| {
+                let mut e = vec![];
+                generator.write_entry(&mut e);
+                e
+            })
+            .collect();
+        entries.shuffle(&mut StdRng::seed_from_u64(9));
+        entries
+    }
+
+    fn get_u64(entry: &[u8], name: &[u8]) -> Option<u64> {
+        let mut jreader = JournalExportRead::new(entry);
+        jreader.parse_next().unwrap();
+        jreader.get_entry().get_u64(name)
+    }
+
+    fn sort(stream: &[u8], key: SortKey, run_size: usize) -> (Vec<Vec<u8>>, usize) {
+        let mut sorter = ExternalSort::new(key).with_run_size(run_size);
+        let mut jreader = JournalExportRead::new(stream);
+        while jreader.parse_next().unwrap().is_some() {
+            sorter.push(&jreader.get_entry()).unwrap();
+        }
+        let runs = sorter.runs();
+        let mut sorted = sorter.finish().unwrap();
+        let mut entries = vec![];
+        while sorted.parse_next().unwrap().is_some() {
+            entries.push(sorted.get_entry().as_bytes().to_vec());
+        }
+        (entries, runs)
+    }
+
+    #[test]
+    fn sorts_in_memory_and_with_runs() {
+        let entries = shuffled(200);
+        let stream = entries.concat();
+        let mut expected = entries.clone();
+        expected.sort_by_key(|e| get_u64(e, b"__REALTIME_TIMESTAMP"));
+
+        let (in_memory, runs) = sort(&stream, SortKey::Realtime, 1 << 30);
+        assert_eq!(runs, 0);
+        assert_eq!(in_memory, expected);
+        let (spilled, runs) = sort(&stream, SortKey::Realtime, 4096);
+        assert!(runs > 1);
+        assert_eq!(spilled, expected);
+    }
+
+    #[test]
+    fn sorts_by_seqnum() {
+        let stream = shuffled(50).concat();
+        let (entries, _) = sort(&stream, SortKey::Seqnum, 2048);
+        let seqnums: Vec<_> = entries
+            .iter()
+            .map(|e| get_u64(e, b"__SEQNUM").unwrap())
+            .collect();
+        assert_eq!(seqnums, (1..=50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn empty_input() {
+        assert!(sort(&[], SortKey::Monotonic, 16).0.is_empty());
+    }
+}
@@ -0,0 +1,374 @@
+//! Locate and open journal export files.
+//!
+//! [open] transparently decompresses files based on their extension
+//! (`.gz`, `.zst`) and converts the logs of containers (`.log`, see
+//! [crate::container]) and, with the `evtx` feature, Windows event logs
+//! (`.evtx`, `.xml`) using [crate::winevt]. [discover] scans a directory for export files and orders
+//! them by the timestamps of their first and last entry, such that rotated
+//! files can be read as one logical stream. The timestamps are read by
+//! [crate::inspect::peek_bounds], i.e. from both ends of uncompressed files.
+//!
+//! Binary journal files (`*.journal`) as written by journald are not in the
+//! Journal Export Format and cannot be read; [discover] reports them
+//! separately.
+//!
+//! [tail_offset] finds the last entries of an uncompressed file by scanning
+//! it backwards, such that `--tail` does not read the whole file.
+//! [seek_to_time] searches a time-ordered file for a timestamp, such that
+//! `--since` does not scan it from the start.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    container::{self, ContainerInfo, ContainerLogRead},
+    inspect,
+    journald::{sync::first_entry, Entry, ErrorKind, JournalExportRead},
+};
+
+const EXPORT_SUFFIXES: &[&str] = &[".export", ".export.gz", ".export.zst"];
+
+/// Opens the export file at `path`, decompressing it if its extension is
+/// `.gz` or `.zst` and converting it if it is a container or Windows event
+/// log. Files ending in `.log` are converted if their first line is a line
+/// of a Docker or CRI log, and read as they are otherwise.
+pub fn open(path: &Path) -> io::Result<Box<dyn Read>> {
+    let f = File::open(path)?;
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("log") => {
+            // Large enough for the first line of a Docker log, which is split
+            // at 16K.
+            let mut f = BufReader::with_capacity(64 * 1024, f);
+            if !container::is_container_log(f.fill_buf()?) {
+                return Ok(Box::new(f));
+            }
+            let info = ContainerInfo::from_path(path);
+            Ok(Box::new(ContainerLogRead::new(f).with_info(info)))
+        }
+        #[cfg(feature = "evtx")]
+        Some("evtx") => Ok(Box::new(crate::winevt::EvtxRead::new(f)?)),
+        #[cfg(not(feature = "evtx"))]
+        Some("evtx") => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "importing Windows event logs is not supported by this build",
+        )),
+        #[cfg(feature = "evtx")]
+        Some("xml") => Ok(Box::new(crate::winevt::XmlEventRead::new(BufReader::new(
+            f,
+        )))),
+        Some("gz") => Ok(Box::new(flate2::read::MultiGzDecoder::new(BufReader::new(
+            f,
+        )))),
+        #[cfg(feature = "zstd")]
+        Some("zst") => Ok(Box::new(zstd::Decoder::new(f)?)),
+        #[cfg(not(feature = "zstd"))]
+        Some("zst") => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "zstd compression is not supported by this build",
+        )),
+        _ => Ok(Box::new(f)),
+    }
+}
+
+/// Whether [open] reads the file at `path` as it is, i.e. offsets in the
+/// stream are offsets in the file, rather than decompressing or converting
+/// it.
+pub fn is_verbatim(path: &Path) -> bool {
+    let converted: &[&str] = match cfg!(feature = "evtx") {
+        true => &["gz", "zst", "log", "evtx", "xml"],
+        false => &["gz", "zst", "log", "evtx"],
+    };
+    !path
+        .extension()
+        .is_some_and(|e| converted.iter().any(|c| e == *c))
+}
+
+/// Whether the name of `path` ends in one of the extensions of (compressed)
+/// export files.
+pub fn is_export_file(path: &Path) -> bool {
+    let name = path.file_name().map(|n| n.to_string_lossy());
+    name.is_some_and(|n| EXPORT_SUFFIXES.iter().any(|s| n.ends_with(s)))
+}
+
+/// The result of scanning a directory.
+pub struct Discovered {
+    /// Export files, ordered by the timestamps of their first and last
+    /// entry.
+    pub exports: Vec<PathBuf>,
+    /// Binary journal files that were skipped.
+    pub skipped: Vec<PathBuf>,
+    /// Pairs of export files whose time ranges overlap, i.e. the second one
+    /// starts before the first one ends. Their entries are not in order when
+    /// the files are read one after the other.
+    pub overlapping: Vec<(PathBuf, PathBuf)>,
+}
+
+/// Scans `dir` (non-recursively) for export files and orders them by the
+/// `__REALTIME_TIMESTAMP` of their first entry, then by that of their last
+/// entry. Files without entries or timestamps come first; ties are broken by
+/// the file name. Files whose time ranges overlap are reported in
+/// [Discovered::overlapping].
+pub fn discover(dir: &Path) -> io::Result<Discovered> {
+    let mut exports = vec![];
+    let mut skipped = vec![];
+    for dirent in std::fs::read_dir(dir)? {
+        let path = dirent?.path();
+        if !path.is_file() {
+            continue;
+        }
+        if is_export_file(&path) {
+            let bounds = inspect::peek_bounds(&path)?;
+            let range = bounds.map(|b| (b.first.realtime, b.last.realtime));
+            exports.push((range.unwrap_or_default(), path));
+        } else if path.extension().is_some_and(|e| e == "journal") {
+            skipped.push(path);
+        }
+    }
+    exports.sort();
+    skipped.sort();
+    // The file that ends last among those before the current one.
+    let mut latest: Option<(u64, &PathBuf)> = None;
+    let mut overlapping = vec![];
+    for ((first, last), path) in exports.iter() {
+        if let (Some((end, prev)), Some(first)) = (latest, first) {
+            if *first < end {
+                overlapping.push((prev.clone(), path.clone()));
+            }
+        }
+        if let Some(last) = *last {
+            if latest.is_none_or(|(end, _)| last > end) {
+                latest = Some((last, path));
+            }
+        }
+    }
+    Ok(Discovered {
+        exports: exports.into_iter().map(|(_, p)| p).collect(),
+        skipped,
+        overlapping,
+    })
+}
+
+/// Returns the offset at which the last `n` entries of the export stream `r`
+/// start, or 0 if it has at most `n` entries. Blocks of growing size are read
+/// backwards from the end, like [crate::journald::JournalExportReverseRead]
+/// does.
+pub fn tail_offset<R: Read + Seek>(r: &mut R, n: usize) -> io::Result<u64> {
+    let len = r.seek(SeekFrom::End(0))?;
+    if n == 0 {
+        return Ok(len);
+    }
+    let mut block = 64 * 1024;
+    loop {
+        let start = len.saturating_sub(block);
+        r.seek(SeekFrom::Start(start))?;
+        let mut buf = Vec::with_capacity((len - start) as usize);
+        r.by_ref().take(len - start).read_to_end(&mut buf)?;
+        // The first offset that parses has the most entries in the block.
+        if let Some((c, ends)) = first_entry(&buf, start == 0) {
+            if ends.len() > n {
+                return Ok(start + (c + ends[ends.len() - n - 1]) as u64);
+            }
+            if ends.len() == n {
+                return Ok(start + c as u64);
+            }
+        }
+        if start == 0 {
+            return Ok(0);
+        }
+        block *= 4;
+    }
+}
+
+/// The number of bytes that [seek_to_time] reads at every probe; once the
+/// search is narrowed down to this size, the rest is left to the caller.
+const SEARCH_BLOCK_SIZE: u64 = 64 * 1024;
+
+/// Seeks the export stream `r`, whose entries are ordered by their
+/// `__REALTIME_TIMESTAMP`, to an entry at or before the first one with a
+/// timestamp of at least `ts` (in microseconds) and returns its offset.
+///
+/// The entries are located by interpolation search, which falls back to
+/// bisection when the timestamps are not evenly distributed over the
+/// stream. The caller still has to skip the earlier entries that follow
+/// the offset.
+pub fn seek_to_time<R: Read + Seek>(r: &mut R, ts: u64) -> io::Result<u64> {
+    let len = r.seek(SeekFrom::End(0))?;
+    // `lo` is 0 or an entry older than `ts`, `hi` the end of the stream or
+    // an entry at least as new as `ts`.
+    let (mut lo, mut lo_ts) = (0, None);
+    let (mut hi, mut hi_ts) = (len, None);
+    let mut bisect = false;
+    while hi - lo > SEARCH_BLOCK_SIZE {
+        let span = hi - lo;
+        let probe = match (lo_ts, hi_ts) {
+            (Some(a), Some(b)) if !bisect && b > a => {
+                lo + (span as u128 * (ts - a) as u128 / (b - a) as u128) as u64
+            }
+            _ => lo + span / 2,
+        };
+        let probe = probe.clamp(lo + span / 16, hi - span / 16);
+        match timestamped_entry(r, probe, hi)? {
+            Some((offset, t)) if t < ts => (lo, lo_ts) = (offset, Some(t)),
+            Some((offset, t)) => (hi, hi_ts) = (offset, Some(t)),
+            // No entry starts in the block; it is part of one at or before
+            // `probe`.
+            None => (hi, hi_ts) = (probe, None),
+        }
+        // Interpolation that fails to halve the range is followed by a
+        // bisection step.
+        bisect = !bisect && hi - lo > span / 2;
+    }
+    r.seek(SeekFrom::Start(lo))
+}
+
+/// Returns the offset and timestamp of the first entry with a timestamp that
+/// starts in the block at `probe` and ends before `end`.
+fn timestamped_entry<R: Read + Seek>(
+    r: &mut R,
+    probe: u64,
+    end: u64,
+) -> io::Result<Option<(u64, u64)>> {
+    let size = SEARCH_BLOCK_SIZE.min(end - probe);
+    r.seek(SeekFrom::Start(probe))?;
+    let mut buf = Vec::with_capacity(size as usize);
+    r.by_ref().take(size).read_to_end(&mut buf)?;
+    // As for `first_entry`, binary values may contain `\n\n`: the entries
+    // start at the first offset from which the block parses up to the entry
+    // that it cuts off.
+    let candidates = (probe == 0).then_some(0).into_iter().chain(
+        buf.windows(2)
+            .enumerate()
+            .filter(|(_, w)| *w == b"\n\n")
+            .map(|(i, _)| i + 2),
+    );
+    for c in candidates.filter(|&c| c < buf.len()) {
+        let mut reader = JournalExportRead::new(&buf[c..]);
+        let mut start = 0;
+        let mut found = None;
+        let valid = loop {
+            match reader.parse_next() {
+                Ok(Some(())) => {
+                    if found.is_none() {
+                        let t = reader.get_entry().realtime_timestamp();
+                        found = t.map(|t| (probe + (c + start) as u64, t));
+                    }
+                    start = reader.position();
+                }
+                Ok(None) => break true,
+                Err(e) if matches!(e.kind(), ErrorKind::UnexpectedEof) => break probe + size < end,
+                Err(_) => break false,
+            }
+        };
+        if valid {
+            return Ok(found);
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Write};
+
+    use crate::{
+        generate::{EntryGenerator, RateProfile},
+        journald::{Entry, JournalExportRead},
+        testutil::{write_binary, write_string},
+    };
+
+    use super::{discover, open, seek_to_time, tail_offset};
+
+    #[test]
+    fn tail_offset_finds_last_entries() {
+        let mut stream = EntryGenerator::new(0).generate(2000);
+        // A binary value that looks like the end of an entry.
+        write_binary(&mut stream, "DATA", b"x\n\nMESSAGE=fake\n\n");
+        stream.push(b'\n');
+        write_string(&mut stream, "MESSAGE", "last");
+        stream.push(b'\n');
+
+        let tail = |n| {
+            let offset = tail_offset(&mut Cursor::new(&stream), n).unwrap() as usize;
+            JournalExportRead::new(&stream[offset..]).collect::<Vec<_>>()
+        };
+        let last = tail(3);
+        assert_eq!(last.len(), 3);
+        assert_eq!(last[1].get(b"DATA"), Some(&b"x\n\nMESSAGE=fake\n\n"[..]));
+        assert_eq!(last[2].get(b"MESSAGE"), Some(&b"last"[..]));
+        // More than fit into the first block.
+        assert_eq!(tail(1500).len(), 1500);
+        assert_eq!(tail(5000).len(), 2002);
+        assert!(tail(0).is_empty());
+    }
+
+    #[test]
+    fn seek_to_time_finds_entries_by_timestamp() {
+        let stream = EntryGenerator::new(0)
+            .with_rate_profile(RateProfile::Bursty)
+            .generate(20_000);
+        let timestamps: Vec<_> = JournalExportRead::new(&stream[..])
+            .map(|e| e.realtime_timestamp().unwrap())
+            .collect();
+        let first = timestamps[0];
+        let last = timestamps[timestamps.len() - 1];
+        for ts in [0, first, timestamps[7_000], last - 1, last, last + 1] {
+            let mut cursor = Cursor::new(&stream);
+            let offset = seek_to_time(&mut cursor, ts).unwrap();
+            assert_eq!(cursor.position(), offset);
+            let entries: Vec<_> = JournalExportRead::new(&stream[offset as usize..])
+                .map(|e| e.realtime_timestamp().unwrap())
+                .collect();
+            // Starts before the first entry at `ts`, but not far before.
+            assert!(offset == 0 || entries[0] < ts);
+            let recent = timestamps.iter().filter(|&&t| t >= ts).count();
+            assert_eq!(entries.iter().filter(|&&t| t >= ts).count(), recent);
+            assert!(entries.len() - recent < 1_000);
+        }
+    }
+
+    #[test]
+    fn discover_orders_by_time() -> std::io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut generator = EntryGenerator::new(0);
+        let early = generator.generate(10);
+        let late = generator.generate(10);
+        let later = generator.generate(10);
+
+        // Starts with `late` as well, but ends after it.
+        std::fs::write(dir.path().join("a.export"), [&late[..], &later].concat())?;
+        std::fs::write(dir.path().join("c.export"), &late)?;
+        let mut gz = flate2::write::GzEncoder::new(
+            std::fs::File::create(dir.path().join("b.export.gz"))?,
+            flate2::Compression::default(),
+        );
+        gz.write_all(&early)?;
+        gz.finish()?;
+        std::fs::write(dir.path().join("system.journal"), b"LPKSHHRH")?;
+        std::fs::write(dir.path().join("notes.txt"), b"")?;
+
+        let discovered = discover(dir.path())?;
+        let names: Vec<_> = discovered
+            .exports
+            .iter()
+            .map(|p| p.file_name().unwrap().to_owned())
+            .collect();
+        assert_eq!(names, vec!["b.export.gz", "c.export", "a.export"]);
+        let overlapping = discovered
+            .overlapping
+            .iter()
+            .map(|(a, b)| (a.file_name().unwrap(), b.file_name().unwrap()));
+        assert_eq!(
+            overlapping.collect::<Vec<_>>(),
+            vec![("c.export".as_ref(), "a.export".as_ref())]
+        );
+        assert_eq!(discovered.skipped.len(), 1);
+
+        let mut decompressed = vec![];
+        open(&discovered.exports[0])?.read_to_end(&mut decompressed)?;
+        assert_eq!(decompressed, early);
+        Ok(())
+    }
+}
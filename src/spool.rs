@@ -0,0 +1,459 @@
+//! Buffer entries on disk while a destination is unreachable.
+//!
+//! A [Spool] is a directory of append-only segments in the Journal Export
+//! Format, named `<N>.export` with increasing `N`, and an `index` file that
+//! records how far the oldest segment was replayed. Segments are deleted once
+//! they were replayed completely. The spool survives restarts: entries that
+//! could not be delivered by one run are replayed by the next.
+//!
+//! [SpoolSink] writes to a destination that is reached through a connection,
+//! e.g. a [TlsSender](crate::tls::TlsSender). While the connection is down,
+//! entries are appended to the spool; once it can be reestablished, the
+//! spooled entries are replayed in order before any new entry. Entries are
+//! delivered at least once: the spool does not know which entries a
+//! destination received before its connection broke, so entries written
+//! right before a failure may be lost in its buffers, and entries replayed
+//! right before a failure may be replayed again.
+
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    journald::{Entry, ErrorKind, JournalExportRead},
+    sink::EntrySink,
+    trace,
+};
+
+/// A directory of spooled entries.
+pub struct Spool {
+    dir: PathBuf,
+    max_segment_size: u64,
+    /// The numbers of the segments on disk, oldest first.
+    segments: VecDeque<u64>,
+    /// The position up to which the oldest segment was replayed.
+    offset: u64,
+    /// The newest segment if it was created by this spool, and its size.
+    writer: Option<(BufWriter<File>, u64)>,
+}
+
+impl Spool {
+    /// Opens the spool in `dir`, creating the directory if necessary.
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let mut segments = vec![];
+        for entry in fs::read_dir(&dir)? {
+            let name = entry?.file_name();
+            let n = name
+                .to_str()
+                .and_then(|n| n.strip_suffix(".export"))
+                .and_then(|n| n.parse::<u64>().ok());
+            if let Some(n) = n {
+                segments.push(n);
+            }
+        }
+        segments.sort_unstable();
+        let mut spool = Self {
+            dir,
+            max_segment_size: 64 << 20,
+            segments: segments.into(),
+            offset: 0,
+            writer: None,
+        };
+        if let Some((segment, offset)) = spool.read_index()? {
+            // Segments before the indexed one were replayed, but the spool
+            // stopped before it could delete them.
+            while spool.segments.front().is_some_and(|&n| n < segment) {
+                spool.remove_oldest()?;
+            }
+            if spool.segments.front() == Some(&segment) {
+                spool.offset = offset;
+            }
+        }
+        Ok(spool)
+    }
+
+    /// Starts a new segment once the current one exceeds `size` bytes.
+    /// Defaults to 64MiB.
+    pub fn with_max_segment_size(self, size: u64) -> Self {
+        Self {
+            max_segment_size: size,
+            ..self
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Whether all spooled entries were replayed.
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    fn segment_path(&self, n: u64) -> PathBuf {
+        self.dir.join(format!("{:016}.export", n))
+    }
+
+    fn read_index(&self) -> io::Result<Option<(u64, u64)>> {
+        let index = match fs::read_to_string(self.dir.join("index")) {
+            Ok(index) => index,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let parsed = index
+            .trim_end()
+            .split_once(' ')
+            .and_then(|(s, o)| Some((s.parse().ok()?, o.parse().ok()?)));
+        match parsed {
+            Some(position) => Ok(Some(position)),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid spool index in {}", self.dir.display()),
+            )),
+        }
+    }
+
+    fn write_index(&self) -> io::Result<()> {
+        let tmp = self.dir.join("index.tmp");
+        let segment = self.segments.front().copied().unwrap_or(0);
+        fs::write(&tmp, format!("{} {}\n", segment, self.offset))?;
+        fs::rename(tmp, self.dir.join("index"))
+    }
+
+    fn remove_oldest(&mut self) -> io::Result<()> {
+        if let Some(n) = self.segments.pop_front() {
+            if self.segments.is_empty() {
+                self.writer = None;
+            }
+            fs::remove_file(self.segment_path(n))?;
+        }
+        self.offset = 0;
+        Ok(())
+    }
+
+    /// Appends an entry, including its terminating empty line.
+    pub fn push(&mut self, entry: &[u8]) -> io::Result<()> {
+        let full = self
+            .writer
+            .as_ref()
+            .is_none_or(|(_, size)| *size >= self.max_segment_size);
+        if full {
+            // Existing segments are never appended to, as they may end with
+            // an entry that was torn by a crash.
+            let n = self.segments.back().map_or(0, |n| n + 1);
+            let file = OpenOptions::new()
+                .create_new(true)
+                .write(true)
+                .open(self.segment_path(n))?;
+            if let Some((mut old, _)) = self.writer.replace((BufWriter::new(file), 0)) {
+                old.flush()?;
+            }
+            self.segments.push_back(n);
+        }
+        let (writer, size) = self.writer.as_mut().unwrap();
+        writer.write_all(entry)?;
+        *size += entry.len() as u64;
+        Ok(())
+    }
+
+    /// Writes buffered entries to disk.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let _span = trace::debug_span!("spool_flush");
+        match &mut self.writer {
+            Some((writer, _)) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes all spooled entries to `sink`, oldest first, and returns their
+    /// number. If the sink fails, the replay stops and the next replay starts
+    /// with the entry that failed.
+    pub fn replay(&mut self, sink: &mut dyn EntrySink) -> io::Result<u64> {
+        self.flush()?;
+        let mut replayed = 0;
+        while let Some(&n) = self.segments.front() {
+            let mut file = File::open(self.segment_path(n))?;
+            file.seek(SeekFrom::Start(self.offset))?;
+            let mut jreader = JournalExportRead::new(file);
+            let start = self.offset;
+            loop {
+                match jreader.parse_next() {
+                    Ok(Some(())) => (),
+                    // A torn entry at the end of a segment is skipped.
+                    Ok(None) => break,
+                    Err(e) if matches!(e.kind(), ErrorKind::UnexpectedEof) => break,
+                    Err(e) => return Err(e.into()),
+                }
+                if let Err(e) = sink.write_entry(jreader.get_entry().as_bytes()) {
+                    self.write_index()?;
+                    return Err(e);
+                }
+                self.offset = start + jreader.position() as u64;
+                replayed += 1;
+            }
+            self.remove_oldest()?;
+            self.write_index()?;
+        }
+        Ok(replayed)
+    }
+}
+
+/// Opens a connection to the destination of a [SpoolSink].
+pub type Connect = Box<dyn FnMut() -> io::Result<Box<dyn EntrySink + Send>> + Send>;
+
+/// Writes entries through a connection, spooling them while it is down.
+pub struct SpoolSink {
+    spool: Spool,
+    connect: Connect,
+    sink: Option<Box<dyn EntrySink + Send>>,
+    retry_interval: Duration,
+    next_attempt: Instant,
+    spooled: u64,
+    replayed: u64,
+    last_error: Option<io::Error>,
+}
+
+impl SpoolSink {
+    /// The connection is opened on the first write. Entries left in `spool`
+    /// by a previous run are replayed first.
+    pub fn new(
+        spool: Spool,
+        connect: impl FnMut() -> io::Result<Box<dyn EntrySink + Send>> + Send + 'static,
+    ) -> Self {
+        Self {
+            spool,
+            connect: Box::new(connect),
+            sink: None,
+            retry_interval: Duration::from_secs(5),
+            next_attempt: Instant::now(),
+            spooled: 0,
+            replayed: 0,
+            last_error: None,
+        }
+    }
+
+    /// Waits this long after a failure before connecting again. Defaults to
+    /// 5s.
+    pub fn with_retry_interval(self, interval: Duration) -> Self {
+        Self {
+            retry_interval: interval,
+            ..self
+        }
+    }
+
+    pub fn spool(&self) -> &Spool {
+        &self.spool
+    }
+
+    /// Entries appended to the spool by this sink.
+    pub fn spooled(&self) -> u64 {
+        self.spooled
+    }
+
+    /// Entries replayed from the spool by this sink.
+    pub fn replayed(&self) -> u64 {
+        self.replayed
+    }
+
+    /// The error that caused the latest failure of the connection.
+    pub fn last_error(&self) -> Option<&io::Error> {
+        self.last_error.as_ref()
+    }
+
+    fn fail(&mut self, error: io::Error) {
+        trace::debug!(%error, spooled = self.spooled, "connection failed");
+        self.sink = None;
+        self.last_error = Some(error);
+        self.next_attempt = Instant::now() + self.retry_interval;
+    }
+
+    /// Connects and replays the spool, unless the last attempt was too
+    /// recent.
+    fn reconnect(&mut self) {
+        if self.sink.is_some() || Instant::now() < self.next_attempt {
+            return;
+        }
+        let _span = trace::debug_span!("reconnect");
+        let mut sink = match (self.connect)() {
+            Ok(sink) => sink,
+            Err(e) => return self.fail(e),
+        };
+        let mut counted = Counted(&mut *sink, 0);
+        let result = self.spool.replay(&mut counted);
+        trace::debug!(replayed = counted.1, "connected");
+        self.replayed += counted.1;
+        match result {
+            Ok(_) => self.sink = Some(sink),
+            Err(e) => self.fail(e),
+        }
+    }
+}
+
+/// Counts the entries written successfully.
+struct Counted<'a>(&'a mut dyn EntrySink, u64);
+
+impl EntrySink for Counted<'_> {
+    fn write_entry(&mut self, entry: &[u8]) -> io::Result<()> {
+        self.0.write_entry(entry)?;
+        self.1 += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.0.finish()
+    }
+}
+
+impl EntrySink for SpoolSink {
+    fn write_entry(&mut self, entry: &[u8]) -> io::Result<()> {
+        self.reconnect();
+        if let Some(sink) = &mut self.sink {
+            match sink.write_entry(entry) {
+                Ok(()) => return Ok(()),
+                Err(e) => self.fail(e),
+            }
+        }
+        self.spool.push(entry)?;
+        self.spooled += 1;
+        Ok(())
+    }
+
+    /// Makes a last attempt to deliver the spooled entries. Entries that
+    /// remain in the spool are not an error; they are replayed by the next
+    /// [SpoolSink] using the spool.
+    fn finish(&mut self) -> io::Result<()> {
+        self.spool.flush()?;
+        if self.sink.is_none() {
+            self.next_attempt = Instant::now();
+            self.reconnect();
+        }
+        match &mut self.sink {
+            Some(sink) => sink.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use super::{Spool, SpoolSink};
+    use crate::sink::EntrySink;
+
+    /// Writes to a shared buffer while `up` is set.
+    struct Remote {
+        up: Arc<AtomicBool>,
+        out: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl EntrySink for Remote {
+        fn write_entry(&mut self, entry: &[u8]) -> io::Result<()> {
+            if !self.up.load(Ordering::Relaxed) {
+                return Err(io::ErrorKind::ConnectionReset.into());
+            }
+            self.out.lock().unwrap().extend_from_slice(entry);
+            Ok(())
+        }
+
+        fn finish(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn sink(dir: &std::path::Path, up: &Arc<AtomicBool>, out: &Arc<Mutex<Vec<u8>>>) -> SpoolSink {
+        let spool = Spool::open(dir).unwrap().with_max_segment_size(20);
+        let (up, out) = (Arc::clone(up), Arc::clone(out));
+        SpoolSink::new(spool, move || match up.load(Ordering::Relaxed) {
+            true => Ok(Box::new(Remote {
+                up: Arc::clone(&up),
+                out: Arc::clone(&out),
+            })),
+            false => Err(io::ErrorKind::ConnectionRefused.into()),
+        })
+        .with_retry_interval(Duration::ZERO)
+    }
+
+    fn entry(i: usize) -> Vec<u8> {
+        format!("MESSAGE={}\n\n", i).into_bytes()
+    }
+
+    #[test]
+    fn replays_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let up = Arc::new(AtomicBool::new(true));
+        let out = Arc::default();
+        let mut sink = sink(dir.path(), &up, &out);
+        let mut expected = vec![];
+        for i in 0..30 {
+            // Down for entries 5..15.
+            up.store(!(5..15).contains(&i), Ordering::Relaxed);
+            sink.write_entry(&entry(i)).unwrap();
+            expected.extend(entry(i));
+        }
+        sink.finish().unwrap();
+        assert_eq!((sink.spooled(), sink.replayed()), (10, 10));
+        assert!(sink.spool().is_empty());
+        assert_eq!(*out.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn persists_across_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let up = Arc::new(AtomicBool::new(false));
+        let out = Arc::default();
+        let mut sink1 = sink(dir.path(), &up, &out);
+        for i in 0..10 {
+            sink1.write_entry(&entry(i)).unwrap();
+        }
+        sink1.finish().unwrap();
+        assert_eq!(sink1.spooled(), 10);
+        assert!(sink1.last_error().is_some());
+        drop(sink1);
+
+        // A partial replay stops at the entry that failed.
+        let mut spool = Spool::open(dir.path()).unwrap();
+        let mut partial = vec![];
+        struct Limited<'a>(&'a mut Vec<u8>);
+        impl EntrySink for Limited<'_> {
+            fn write_entry(&mut self, entry: &[u8]) -> io::Result<()> {
+                if self.0.len() >= 3 * entry.len() {
+                    return Err(io::ErrorKind::ConnectionReset.into());
+                }
+                self.0.extend_from_slice(entry);
+                Ok(())
+            }
+            fn finish(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        assert!(spool.replay(&mut Limited(&mut partial)).is_err());
+        drop(spool);
+
+        up.store(true, Ordering::Relaxed);
+        let mut sink2 = sink(dir.path(), &up, &out);
+        sink2.write_entry(&entry(10)).unwrap();
+        sink2.finish().unwrap();
+        assert_eq!(sink2.replayed(), 7);
+        let expected: Vec<u8> = (3..11).flat_map(entry).collect();
+        assert_eq!(*out.lock().unwrap(), expected);
+        assert_eq!(
+            std::fs::read_dir(dir.path()).unwrap().count(),
+            1,
+            "only the index remains"
+        );
+    }
+}
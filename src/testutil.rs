@@ -0,0 +1,352 @@
+//! Helpers for tests: writing fields and reading in small chunks. Only
+//! compiled in tests and with the `testutil` feature.
+//!
+//! [ChunkedRead] hands out its data in small chunks, which helps to exercise
+//! the buffer management of the readers.
+//!
+//! [TestEntry] describes an entry field by field; [assert_round_trip] checks
+//! that entries written to a stream parse back into the same entries. The
+//! [FIXTURES] are golden export files in `testdata/` with binary values, huge
+//! values and edge-case field names, and [strategy] provides proptest
+//! strategies for entries, such that downstream crates can run the same
+//! property tests.
+//!
+//! Synthetic streams of realistic entries are produced by [EntryGenerator],
+//! which is re-exported from [crate::generate].
+
+use std::{
+    io::{self, Read},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::AsyncRead;
+
+pub use crate::generate::{EntryGenerator, RateProfile, Workload};
+use crate::{
+    config::{JournalExportLimits, JournalExportLimitsBuilder},
+    journald::{
+        parser::FieldType, write_field, Entry, JournalExportAsyncRead, JournalExportRead,
+        JournalExportReadError,
+    },
+};
+
+/// Appends a string field `name=value\n` to `out`.
+pub fn write_string(out: &mut Vec<u8>, name: &str, value: impl AsRef<[u8]>) {
+    out.extend_from_slice(name.as_bytes());
+    out.push(b'=');
+    out.extend_from_slice(value.as_ref());
+    out.push(b'\n');
+}
+
+/// Appends a binary field to `out`; i.e. the field name, a newline, the value
+/// length as 64bit little endian integer, the value and a newline.
+pub fn write_binary(out: &mut Vec<u8>, name: &str, value: impl AsRef<[u8]>) {
+    let value = value.as_ref();
+    out.extend_from_slice(name.as_bytes());
+    out.push(b'\n');
+    out.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    out.extend_from_slice(value);
+    out.push(b'\n');
+}
+
+/// A reader over a byte slice that returns at most `chunk_size` bytes per
+/// read. Implements both [Read] and [AsyncRead].
+pub struct ChunkedRead<'a> {
+    data: &'a [u8],
+    chunk_size: usize,
+}
+
+impl<'a> ChunkedRead<'a> {
+    pub fn new(data: &'a [u8], chunk_size: usize) -> Self {
+        assert!(chunk_size > 0);
+        Self { data, chunk_size }
+    }
+}
+
+impl<'a> Read for ChunkedRead<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.chunk_size).min(self.data.len());
+        buf[..n].copy_from_slice(&self.data[..n]);
+        self.data = &self.data[n..];
+        Ok(n)
+    }
+}
+
+impl<'a> AsyncRead for ChunkedRead<'a> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.get_mut().read(buf))
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct TestField {
+    pub name: String,
+    pub value: Vec<u8>,
+    /// Whether the field is written in binary form, which it must be if the
+    /// value contains a newline.
+    pub binary: bool,
+}
+
+impl TestField {
+    pub fn string(name: impl Into<String>, value: impl Into<Vec<u8>>) -> Self {
+        let value = value.into();
+        Self {
+            name: name.into(),
+            binary: value.contains(&b'\n'),
+            value,
+        }
+    }
+
+    pub fn binary(name: impl Into<String>, value: impl Into<Vec<u8>>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            binary: true,
+        }
+    }
+}
+
+/// An entry as a list of fields, for building streams and comparing parsed
+/// entries.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct TestEntry(pub Vec<TestField>);
+
+impl TestEntry {
+    pub fn from_entry(entry: &impl Entry) -> Self {
+        Self(
+            entry
+                .iter()
+                .map(|(name, value, typ)| TestField {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    value: value.to_vec(),
+                    binary: matches!(typ, FieldType::Binary),
+                })
+                .collect(),
+        )
+    }
+
+    /// Appends the entry to `out` in the Journal Export Format.
+    pub fn write(&self, out: &mut Vec<u8>) {
+        for f in self.0.iter() {
+            let typ = match f.binary {
+                true => FieldType::Binary,
+                false => FieldType::String,
+            };
+            write_field(out, f.name.as_bytes(), &f.value, &typ);
+        }
+        out.push(b'\n');
+    }
+}
+
+pub fn write_entries(entries: &[TestEntry]) -> Vec<u8> {
+    let mut out = vec![];
+    for e in entries {
+        e.write(&mut out);
+    }
+    out
+}
+
+/// Limits that admit the [FIXTURES], i.e. values of up to 1 MiB.
+pub fn test_limits() -> JournalExportLimits {
+    JournalExportLimitsBuilder::new()
+        .with_max_field_value_size(1 << 20)
+        .build()
+}
+
+/// Parses `stream` with [test_limits].
+pub fn parse_entries(stream: impl Read) -> Result<Vec<TestEntry>, JournalExportReadError> {
+    let mut reader = JournalExportRead::new_with_limits(test_limits(), stream);
+    let mut entries = vec![];
+    while reader.parse_next()?.is_some() {
+        entries.push(TestEntry::from_entry(&reader.get_entry()));
+    }
+    Ok(entries)
+}
+
+/// Writes `entries` and checks that both the sync and the async reader,
+/// reading chunks of `chunk_size` bytes, parse them back, and that the
+/// parsed entries serialize to the same stream.
+pub fn assert_round_trip(entries: &[TestEntry], chunk_size: usize) {
+    let stream = write_entries(entries);
+    let parsed = parse_entries(ChunkedRead::new(&stream, chunk_size)).unwrap();
+    assert_eq!(parsed, entries);
+
+    let chunked = ChunkedRead::new(&stream, chunk_size);
+    let mut reader = JournalExportAsyncRead::new(test_limits(), chunked);
+    let mut rewritten = vec![];
+    while futures::executor::block_on(reader.parse_next())
+        .unwrap()
+        .is_some()
+    {
+        rewritten.extend_from_slice(reader.get_entry().as_bytes());
+    }
+    assert_eq!(rewritten, stream);
+}
+
+/// A golden export file in `testdata/` and the entries it contains.
+pub struct Fixture {
+    pub name: &'static str,
+    pub bytes: &'static [u8],
+    build: fn() -> Vec<TestEntry>,
+}
+
+impl Fixture {
+    pub fn entries(&self) -> Vec<TestEntry> {
+        (self.build)()
+    }
+}
+
+pub const FIXTURES: &[Fixture] = &[
+    Fixture {
+        name: "binary-fields",
+        bytes: include_bytes!("../testdata/binary-fields.export"),
+        build: binary_fields,
+    },
+    Fixture {
+        name: "edge-case-names",
+        bytes: include_bytes!("../testdata/edge-case-names.export"),
+        build: edge_case_names,
+    },
+    Fixture {
+        name: "huge-values",
+        bytes: include_bytes!("../testdata/huge-values.export"),
+        build: huge_values,
+    },
+];
+
+fn binary_fields() -> Vec<TestEntry> {
+    vec![
+        TestEntry(vec![
+            TestField::string("MESSAGE", "binary values"),
+            TestField::binary("DATA", "line 1\nline 2"),
+            TestField::binary("EMPTY", ""),
+            TestField::binary("NUL", [0, 0]),
+            TestField::binary("NEWLINE", "\n"),
+        ]),
+        TestEntry(vec![
+            TestField::binary("FAKE_ENTRY", "MESSAGE=inside\n\nMESSAGE=still inside\n"),
+            TestField::binary("HIGH", [0x80, 0xfe, 0xff]),
+            TestField::binary("LENGTH_LIKE", 8u64.to_le_bytes()),
+            TestField::string("MESSAGE", "after"),
+        ]),
+        TestEntry(vec![TestField::binary("MESSAGE", "only binary\n")]),
+    ]
+}
+
+fn edge_case_names() -> Vec<TestEntry> {
+    let s = TestField::string;
+    vec![
+        TestEntry(vec![
+            s("A", "single letter"),
+            s("_", "underscore"),
+            s("__CURSOR", "s=0;i=1"),
+            s("9LEADING_DIGIT", "not the first field"),
+            s("EMPTY", ""),
+            s("EQUALS", "a=b=c"),
+            s(&"N".repeat(64), "the longest name journald writes"),
+            s(
+                &"L".repeat(128),
+                "the longest name the parser accepts by default",
+            ),
+            s("REPEATED", "1"),
+            s("REPEATED", "2"),
+            s("lowercase", "accepted by the parser"),
+        ]),
+        TestEntry(vec![s("_SINGLE", "x")]),
+    ]
+}
+
+fn huge_values() -> Vec<TestEntry> {
+    let text: Vec<u8> = (0..70_000).map(|i| b'a' + (i % 26) as u8).collect();
+    let blob: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+    vec![TestEntry(vec![
+        TestField::string("MESSAGE", text),
+        TestField::binary("COREDUMP", blob),
+        TestField::string("AFTER", "small"),
+    ])]
+}
+
+/// Proptest strategies for fields and entries.
+pub mod strategy {
+    use proptest::{collection::vec, prelude::*};
+
+    use super::{TestEntry, TestField};
+
+    /// Names as journald writes them: up to 64 uppercase letters, digits and
+    /// underscores, not starting with a digit.
+    pub fn field_name() -> impl Strategy<Value = String> {
+        "[A-Z_][A-Z0-9_]{0,63}"
+    }
+
+    /// Mostly text, sometimes arbitrary bytes, including newlines.
+    pub fn field_value() -> impl Strategy<Value = Vec<u8>> {
+        prop_oneof![
+            3 => "[ -~]{0,64}".prop_map(String::into_bytes),
+            1 => vec(any::<u8>(), 0..256),
+        ]
+    }
+
+    pub fn field() -> impl Strategy<Value = TestField> {
+        (field_name(), field_value(), any::<bool>()).prop_map(|(name, value, binary)| TestField {
+            binary: binary || value.contains(&b'\n'),
+            name,
+            value,
+        })
+    }
+
+    pub fn entry() -> impl Strategy<Value = TestEntry> {
+        vec(field(), 1..16).prop_map(TestEntry)
+    }
+
+    pub fn entries() -> impl Strategy<Value = Vec<TestEntry>> {
+        vec(entry(), 0..16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use crate::journald::JournalExportRead;
+
+    use super::{
+        assert_round_trip, parse_entries, strategy, write_entries, ChunkedRead, EntryGenerator,
+        FIXTURES,
+    };
+
+    #[test]
+    fn chunked_reads_are_parsable() {
+        let stream = EntryGenerator::new(3).generate(50);
+        for chunk_size in [1, 7, 64] {
+            let mut export_read =
+                JournalExportRead::with_capacity(8, ChunkedRead::new(&stream, chunk_size));
+            let mut count = 0;
+            while export_read.parse_next().unwrap().is_some() {
+                count += 1;
+            }
+            assert_eq!(count, 50);
+        }
+    }
+
+    #[test]
+    fn fixtures_match_their_entries() {
+        for fixture in FIXTURES {
+            let entries = fixture.entries();
+            assert_eq!(write_entries(&entries), fixture.bytes, "{}", fixture.name);
+            assert_eq!(parse_entries(fixture.bytes).unwrap(), entries);
+            assert_round_trip(&entries, 4096);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn entries_round_trip(entries in strategy::entries(), chunk_size in 1..512usize) {
+            assert_round_trip(&entries, chunk_size);
+        }
+    }
+}
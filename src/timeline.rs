@@ -0,0 +1,189 @@
+//! Reconstruct the state changes of units.
+//!
+//! systemd logs the jobs and state changes of units with well-known
+//! `MESSAGE_ID`s (see [crate::message_ids]) and the unit in `UNIT`, or
+//! `USER_UNIT` for the units of user managers. [Timeline] turns these
+//! messages into [UnitEvent]s and measures how long each unit stayed in the
+//! state reached by an event.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{
+    journald::Entry,
+    message_ids::{self, MessageId},
+};
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnitEventKind {
+    Starting,
+    Started,
+    StartFailed,
+    Reloading,
+    Reloaded,
+    Stopping,
+    Stopped,
+    ProcessExited,
+    Succeeded,
+    Failed,
+    OomKilled,
+    RestartScheduled,
+}
+
+impl UnitEventKind {
+    pub fn from_message_id(id: MessageId) -> Option<Self> {
+        use UnitEventKind::*;
+        Some(match id {
+            message_ids::UNIT_STARTING => Starting,
+            message_ids::UNIT_STARTED => Started,
+            message_ids::UNIT_START_FAILED => StartFailed,
+            message_ids::UNIT_RELOADING => Reloading,
+            message_ids::UNIT_RELOADED => Reloaded,
+            message_ids::UNIT_STOPPING => Stopping,
+            message_ids::UNIT_STOPPED => Stopped,
+            message_ids::UNIT_PROCESS_EXIT => ProcessExited,
+            message_ids::UNIT_SUCCESS => Succeeded,
+            message_ids::UNIT_FAILED => Failed,
+            message_ids::UNIT_OUT_OF_MEMORY => OomKilled,
+            message_ids::UNIT_RESTART_SCHEDULED => RestartScheduled,
+            _ => return None,
+        })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        use UnitEventKind::*;
+        match self {
+            Starting => "starting",
+            Started => "started",
+            StartFailed => "start-failed",
+            Reloading => "reloading",
+            Reloaded => "reloaded",
+            Stopping => "stopping",
+            Stopped => "stopped",
+            ProcessExited => "process-exited",
+            Succeeded => "succeeded",
+            Failed => "failed",
+            OomKilled => "oom-killed",
+            RestartScheduled => "restart-scheduled",
+        }
+    }
+}
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct UnitEvent {
+    pub timestamp: Option<u64>,
+    pub unit: String,
+    pub event: UnitEventKind,
+    pub invocation_id: Option<String>,
+    pub message: Option<String>,
+    /// The time in microseconds until the next event of the unit; `None` for
+    /// its last event.
+    pub duration: Option<u64>,
+}
+
+/// Collects the events of all units, or of a single one.
+#[derive(Default)]
+pub struct Timeline {
+    unit: Option<String>,
+    events: Vec<UnitEvent>,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only collects the events of `unit`.
+    pub fn with_unit(self, unit: impl Into<String>) -> Self {
+        Self {
+            unit: Some(unit.into()),
+            ..self
+        }
+    }
+
+    pub fn push(&mut self, entry: &impl Entry) {
+        let Some(event) = MessageId::of(entry).and_then(UnitEventKind::from_message_id) else {
+            return;
+        };
+        let Some(unit) = entry.get(b"UNIT").or_else(|| entry.get(b"USER_UNIT")) else {
+            return;
+        };
+        if self.unit.as_ref().is_some_and(|u| u.as_bytes() != unit) {
+            return;
+        }
+        let string = |v: &[u8]| String::from_utf8_lossy(v).into_owned();
+        self.events.push(UnitEvent {
+            timestamp: entry.realtime_timestamp(),
+            unit: string(unit),
+            event,
+            invocation_id: entry
+                .get(b"INVOCATION_ID")
+                .or_else(|| entry.get(b"USER_INVOCATION_ID"))
+                .map(string),
+            message: entry.get(b"MESSAGE").map(string),
+            duration: None,
+        });
+    }
+
+    /// The events ordered by time, with their durations.
+    pub fn into_events(self) -> Vec<UnitEvent> {
+        let mut events = self.events;
+        events.sort_by_key(|e| (e.timestamp.is_none(), e.timestamp));
+        let mut last: HashMap<String, usize> = HashMap::new();
+        for i in 0..events.len() {
+            let Some(ts) = events[i].timestamp else {
+                continue;
+            };
+            if let Some(prev) = last.insert(events[i].unit.clone(), i) {
+                events[prev].duration = events[prev].timestamp.map(|t| ts.saturating_sub(t));
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{journald::JournalExportRead, message_ids, testutil::write_string};
+
+    use super::{Timeline, UnitEventKind};
+
+    #[test]
+    fn orders_unit_events() {
+        let mut stream = vec![];
+        for (ts, unit, id) in [
+            (10, "a.service", message_ids::UNIT_STARTING),
+            (12, "a.service", message_ids::UNIT_STARTED),
+            (15, "b.service", message_ids::UNIT_STARTED),
+            (40, "a.service", message_ids::UNIT_FAILED),
+            (41, "a.service", message_ids::UNIT_RESTART_SCHEDULED),
+            (20, "a.service", message_ids::JOURNAL_START),
+        ] {
+            write_string(&mut stream, "__REALTIME_TIMESTAMP", ts.to_string());
+            write_string(&mut stream, "UNIT", unit);
+            write_string(&mut stream, "MESSAGE_ID", id.to_string());
+            stream.push(b'\n');
+        }
+
+        let mut timeline = Timeline::new().with_unit("a.service");
+        for e in JournalExportRead::new(&stream[..]) {
+            timeline.push(&e);
+        }
+        let events: Vec<_> = timeline
+            .into_events()
+            .into_iter()
+            .map(|e| (e.timestamp, e.event, e.duration))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (Some(10), UnitEventKind::Starting, Some(2)),
+                (Some(12), UnitEventKind::Started, Some(28)),
+                (Some(40), UnitEventKind::Failed, Some(1)),
+                (Some(41), UnitEventKind::RestartScheduled, None),
+            ]
+        );
+    }
+}
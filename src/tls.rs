@@ -0,0 +1,213 @@
+//! TLS for sending and receiving export streams.
+//!
+//! [server_config] and [client_config] build rustls configurations from PEM
+//! files. Both sides can authenticate each other: the server verifies client
+//! certificates against a CA if one is given, and the client always verifies
+//! the server against a CA. [TlsSender] writes entries to a TLS connection.
+
+use std::{
+    io::{self, BufWriter, Write},
+    net::TcpStream,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use rustls::{
+    client::WebPkiServerVerifier,
+    crypto::{ring, CryptoProvider},
+    server::WebPkiClientVerifier,
+    ClientConfig, ClientConnection, RootCertStore, ServerConfig, StreamOwned,
+};
+use rustls_pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName};
+use thiserror::Error;
+
+use crate::{sink::EntrySink, trace};
+
+#[derive(Error, Debug)]
+pub enum TlsError {
+    #[error("cannot read {path}: {error}")]
+    Pem {
+        path: PathBuf,
+        error: rustls_pki_types::pem::Error,
+    },
+    #[error("invalid certificate or key: {0}")]
+    Rustls(#[from] rustls::Error),
+    #[error("invalid CA: {0}")]
+    Verifier(String),
+    #[error("invalid server name: {0}")]
+    ServerName(String),
+}
+
+impl From<TlsError> for io::Error {
+    fn from(value: TlsError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, value)
+    }
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+/// Reads all certificates of a PEM file.
+pub fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect())
+        .map_err(|error| TlsError::Pem {
+            path: path.to_path_buf(),
+            error,
+        })
+}
+
+/// Reads the first private key of a PEM file.
+pub fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, TlsError> {
+    PrivateKeyDer::from_pem_file(path).map_err(|error| TlsError::Pem {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+fn root_store(ca: &Path) -> Result<Arc<RootCertStore>, TlsError> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(ca)? {
+        roots.add(cert)?;
+    }
+    Ok(Arc::new(roots))
+}
+
+/// A server presenting the certificate chain `cert` with the private key
+/// `key`. If `client_ca` is given, clients must present a certificate issued
+/// by it.
+pub fn server_config(
+    cert: &Path,
+    key: &Path,
+    client_ca: Option<&Path>,
+) -> Result<Arc<ServerConfig>, TlsError> {
+    let builder =
+        ServerConfig::builder_with_provider(provider()).with_safe_default_protocol_versions()?;
+    let builder = match client_ca {
+        Some(ca) => {
+            let verifier = WebPkiClientVerifier::builder_with_provider(root_store(ca)?, provider())
+                .build()
+                .map_err(|e| TlsError::Verifier(e.to_string()))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Arc::new(
+        builder.with_single_cert(load_certs(cert)?, load_key(key)?)?,
+    ))
+}
+
+/// A client trusting the servers certified by `ca`. If `identity` is given,
+/// the client authenticates itself with the certificate chain and private
+/// key in the given files.
+pub fn client_config(
+    ca: &Path,
+    identity: Option<(&Path, &Path)>,
+) -> Result<Arc<ClientConfig>, TlsError> {
+    let verifier = WebPkiServerVerifier::builder_with_provider(root_store(ca)?, provider())
+        .build()
+        .map_err(|e| TlsError::Verifier(e.to_string()))?;
+    let builder = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()?
+        .with_webpki_verifier(verifier);
+    Ok(Arc::new(match identity {
+        Some((cert, key)) => builder.with_client_auth_cert(load_certs(cert)?, load_key(key)?)?,
+        None => builder.with_no_client_auth(),
+    }))
+}
+
+/// Writes entries to a TLS connection. The connection is closed with a
+/// `close_notify` alert on [EntrySink::finish], such that the receiver can
+/// tell a complete stream from a truncated one.
+pub struct TlsSender {
+    stream: BufWriter<StreamOwned<ClientConnection, TcpStream>>,
+}
+
+impl TlsSender {
+    /// Connects to `addr`, a `host:port` pair, and verifies that the server
+    /// is `host`.
+    pub fn connect(addr: &str, config: Arc<ClientConfig>) -> io::Result<Self> {
+        let _span = trace::debug_span!("tls_connect", addr);
+        let (host, _) = addr
+            .rsplit_once(':')
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "expected HOST:PORT"))?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let name = ServerName::try_from(host.to_string())
+            .map_err(|_| TlsError::ServerName(host.to_string()))?;
+        let tcp = TcpStream::connect(addr)?;
+        let conn = ClientConnection::new(config, name).map_err(TlsError::from)?;
+        Ok(Self {
+            stream: BufWriter::new(StreamOwned::new(conn, tcp)),
+        })
+    }
+}
+
+impl EntrySink for TlsSender {
+    fn write_entry(&mut self, entry: &[u8]) -> io::Result<()> {
+        self.stream.write_all(entry)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let _span = trace::debug_span!("tls_finish");
+        self.stream.flush()?;
+        let stream = self.stream.get_mut();
+        stream.conn.send_close_notify();
+        stream.flush()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testutil {
+    use std::path::Path;
+
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+
+    /// Writes a CA (`ca.pem`), a server certificate for `localhost`
+    /// (`server.pem`, `server.key`) and a client certificate (`client.pem`,
+    /// `client.key`) to `dir`.
+    pub fn write_pki(dir: &Path) {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(vec![]).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+        for (name, san) in [("server", "localhost"), ("client", "client.example")] {
+            let key = KeyPair::generate().unwrap();
+            let cert = CertificateParams::new(vec![san.to_string()])
+                .unwrap()
+                .signed_by(&key, &ca, &ca_key)
+                .unwrap();
+            std::fs::write(dir.join(format!("{}.pem", name)), cert.pem()).unwrap();
+            std::fs::write(dir.join(format!("{}.key", name)), key.serialize_pem()).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{client_config, server_config, testutil::write_pki, TlsError};
+
+    #[test]
+    fn loads_pem_files() {
+        let dir = tempfile::tempdir().unwrap();
+        write_pki(dir.path());
+        let path = |name: &str| dir.path().join(name);
+        server_config(
+            &path("server.pem"),
+            &path("server.key"),
+            Some(&path("ca.pem")),
+        )
+        .unwrap();
+        client_config(
+            &path("ca.pem"),
+            Some((&path("client.pem"), &path("client.key"))),
+        )
+        .unwrap();
+        assert!(matches!(
+            server_config(&path("server.pem"), &path("ca.pem"), None),
+            Err(TlsError::Pem { .. })
+        ));
+        assert!(client_config(&path("missing.pem"), None).is_err());
+    }
+}
@@ -0,0 +1,55 @@
+//! Optional instrumentation with `tracing`.
+//!
+//! With the `tracing` feature, the macros of this module forward to those of
+//! the `tracing` crate; without it, they expand to nothing, such that the
+//! instrumented code need not be conditional. Values that are only passed to
+//! these macros must therefore not require computation of their own.
+//!
+//! Spans are entered right away and left when the returned guard is dropped:
+//!
+//! ```ignore
+//! let _span = trace::debug_span!("rotate", path = %path.display());
+//! trace::debug!(bytes = written, "closed file");
+//! ```
+
+#[cfg(feature = "tracing")]
+macro_rules! trace {
+    ($($arg:tt)*) => { tracing::trace!($($arg)*) };
+}
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace {
+    ($($arg:tt)*) => {};
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! debug {
+    ($($arg:tt)*) => { tracing::debug!($($arg)*) };
+}
+#[cfg(not(feature = "tracing"))]
+macro_rules! debug {
+    ($($arg:tt)*) => {};
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! trace_span {
+    ($($arg:tt)*) => { tracing::trace_span!($($arg)*).entered() };
+}
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_span {
+    ($($arg:tt)*) => {
+        ()
+    };
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! debug_span {
+    ($($arg:tt)*) => { tracing::debug_span!($($arg)*).entered() };
+}
+#[cfg(not(feature = "tracing"))]
+macro_rules! debug_span {
+    ($($arg:tt)*) => {
+        ()
+    };
+}
+
+pub(crate) use {debug, debug_span, trace, trace_span};
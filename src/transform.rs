@@ -0,0 +1,643 @@
+//! Transform journal entries while streaming.
+//!
+//! A [Transform] inspects an [Entry] and keeps it, drops it or replaces it by
+//! appending entries in the Journal Export Format to a buffer, which can then
+//! be written to an [crate::sink::EntrySink]. [Chain] applies a sequence of
+//! transforms; downstream crates can implement [Transform] to plug their own
+//! steps into a [crate::pipeline::Pipeline].
+//!
+//! [Projection] keeps or drops fields; [Rewrite] renames fields and injects
+//! constant fields, e.g. to normalize logs from heterogeneous producers.
+//! [Substitute] rewrites field values using regular expressions and [Filter]
+//! selects entries by the values of their fields.
+
+use std::{borrow::Cow, io};
+
+use regex::bytes::Regex;
+
+use crate::{
+    config::JournalExportLimits,
+    journald::{parser::FieldType, write_field, Entry, JournalExportRead},
+};
+
+/// An entry passed to a [Transform].
+pub struct EntryView<'a> {
+    pub entry: &'a dyn Entry,
+    /// The index of the source the entry originates from.
+    pub source: usize,
+    /// Receives the replacement entries; empty when the view is created.
+    pub out: &'a mut Vec<u8>,
+}
+
+impl<'a> EntryView<'a> {
+    pub fn new(entry: &'a dyn Entry, source: usize, out: &'a mut Vec<u8>) -> Self {
+        Self { entry, source, out }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum TransformResult {
+    /// Pass the entry on unchanged.
+    Keep,
+    /// Discard the entry.
+    Drop,
+    /// Pass on the entries appended to [EntryView::out] instead, which may be
+    /// none, one or several.
+    Replace,
+}
+
+pub trait Transform {
+    fn apply(&mut self, entry: EntryView<'_>) -> io::Result<TransformResult>;
+
+    /// Appends the entries held back by the transform to `out`. Called once
+    /// the input is exhausted.
+    fn finish(&mut self, _out: &mut Vec<u8>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<T: Transform + ?Sized> Transform for Box<T> {
+    fn apply(&mut self, entry: EntryView<'_>) -> io::Result<TransformResult> {
+        (**self).apply(entry)
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        (**self).finish(out)
+    }
+}
+
+/// Reads the entries in `entries`, which transforms emitted. Such entries are
+/// only bound by their size, e.g. a reassembled message can exceed the
+/// default limits, hence the limits are derived from the size.
+pub fn read_emitted(entries: &[u8]) -> JournalExportRead<&[u8]> {
+    JournalExportRead::new_with_limits(JournalExportLimits::bounded_by(entries.len()), entries)
+}
+
+/// Applies a sequence of transforms. An entry is only copied by the
+/// transforms that replace it.
+#[derive(Default)]
+pub struct Chain {
+    transforms: Vec<Box<dyn Transform>>,
+    bufs: Vec<Vec<u8>>,
+}
+
+impl Chain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, transform: impl Transform + 'static) -> Self {
+        self.transforms.push(Box::new(transform));
+        self.bufs.push(vec![]);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+
+    /// Applies the transforms from index `i` on. Entries that pass all of
+    /// them are appended to `out`, unless `entry` itself passes unchanged and
+    /// `top` is set.
+    fn run(
+        &mut self,
+        i: usize,
+        source: usize,
+        entry: &dyn Entry,
+        out: &mut Vec<u8>,
+        top: bool,
+    ) -> io::Result<TransformResult> {
+        if i == self.transforms.len() {
+            if top {
+                return Ok(TransformResult::Keep);
+            }
+            out.extend_from_slice(entry.as_bytes());
+            return Ok(TransformResult::Replace);
+        }
+        let mut buf = std::mem::take(&mut self.bufs[i]);
+        buf.clear();
+        let result = match self.transforms[i].apply(EntryView::new(entry, source, &mut buf)) {
+            Ok(TransformResult::Keep) => self.run(i + 1, source, entry, out, top),
+            Ok(TransformResult::Drop) => Ok(TransformResult::Drop),
+            Ok(TransformResult::Replace) => self
+                .run_all(i + 1, source, &buf, out)
+                .map(|()| TransformResult::Replace),
+            Err(e) => Err(e),
+        };
+        self.bufs[i] = buf;
+        result
+    }
+
+    fn run_all(
+        &mut self,
+        i: usize,
+        source: usize,
+        entries: &[u8],
+        out: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        let mut jreader = read_emitted(entries);
+        while jreader.parse_next()?.is_some() {
+            self.run(i, source, &jreader.get_entry(), out, false)?;
+        }
+        Ok(())
+    }
+}
+
+impl Transform for Chain {
+    fn apply(&mut self, entry: EntryView<'_>) -> io::Result<TransformResult> {
+        self.run(0, entry.source, entry.entry, entry.out, true)
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        for i in 0..self.transforms.len() {
+            let mut buf = std::mem::take(&mut self.bufs[i]);
+            buf.clear();
+            // Entries held back are not attributed to a source anymore.
+            let result = self.transforms[i]
+                .finish(&mut buf)
+                .and_then(|()| self.run_all(i + 1, 0, &buf, out));
+            self.bufs[i] = buf;
+            result?;
+        }
+        Ok(())
+    }
+}
+
+/// Applies a transform per source, e.g. to inject the name of the source.
+/// Must precede transforms that hold entries back, which lose track of their
+/// sources.
+pub struct PerSource<T>(pub Vec<T>);
+
+impl<T: Transform> Transform for PerSource<T> {
+    fn apply(&mut self, entry: EntryView<'_>) -> io::Result<TransformResult> {
+        self.0[entry.source].apply(entry)
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        for t in self.0.iter_mut() {
+            t.finish(out)?;
+        }
+        Ok(())
+    }
+}
+
+fn replaced(written: bool) -> io::Result<TransformResult> {
+    Ok(match written {
+        true => TransformResult::Replace,
+        false => TransformResult::Drop,
+    })
+}
+
+/// A field name or, if it ends with `*`, a prefix of field names.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct FieldPattern(Vec<u8>);
+
+impl FieldPattern {
+    pub fn new(pattern: impl Into<Vec<u8>>) -> Self {
+        Self(pattern.into())
+    }
+
+    pub fn matches(&self, name: &[u8]) -> bool {
+        match self.0.strip_suffix(b"*") {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == self.0,
+        }
+    }
+}
+
+/// Keeps or drops fields by name.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Projection {
+    /// Keep only the matching fields and the address fields (`__CURSOR`,
+    /// `__REALTIME_TIMESTAMP` etc.), which are needed to merge, resume or
+    /// diff the output.
+    Keep(Vec<FieldPattern>),
+    /// Drop the matching fields.
+    Drop(Vec<FieldPattern>),
+}
+
+impl Projection {
+    fn retains(&self, name: &[u8]) -> bool {
+        match self {
+            Projection::Keep(patterns) => {
+                name.starts_with(b"__") || patterns.iter().any(|p| p.matches(name))
+            }
+            Projection::Drop(patterns) => !patterns.iter().any(|p| p.matches(name)),
+        }
+    }
+
+    /// Appends the projection of `entry` to `out`. Returns `false` and leaves
+    /// `out` untouched if no field remains.
+    pub fn apply(&self, entry: &impl Entry, out: &mut Vec<u8>) -> bool {
+        let start = out.len();
+        for (name, value, typ) in entry.iter() {
+            if self.retains(name) {
+                write_field(out, name, value, &typ);
+            }
+        }
+        if out.len() == start {
+            return false;
+        }
+        out.push(b'\n');
+        true
+    }
+}
+
+/// Renames fields and injects constant fields.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Rewrite {
+    renames: Vec<(Vec<u8>, Vec<u8>)>,
+    fields: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Rewrite {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Renames all fields called `from` to `to`.
+    pub fn with_rename(mut self, from: impl Into<Vec<u8>>, to: impl Into<Vec<u8>>) -> Self {
+        self.renames.push((from.into(), to.into()));
+        self
+    }
+
+    /// Adds the field `name=value` to every entry. Fields of the entry with
+    /// the same name (after renaming) are replaced.
+    pub fn with_field(mut self, name: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) -> Self {
+        self.fields.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.renames.is_empty() && self.fields.is_empty()
+    }
+
+    /// Appends the rewritten `entry` to `out`. Returns `false` and leaves
+    /// `out` untouched if the result has no fields.
+    pub fn apply(&self, entry: &impl Entry, out: &mut Vec<u8>) -> bool {
+        let start = out.len();
+        for (name, value, typ) in entry.iter() {
+            let name = self
+                .renames
+                .iter()
+                .find(|(from, _)| from == name)
+                .map_or(name, |(_, to)| to);
+            if self.fields.iter().any(|(n, _)| n == name) {
+                continue;
+            }
+            write_field(out, name, value, &typ);
+        }
+        for (name, value) in self.fields.iter() {
+            write_field(out, name, value, &FieldType::String);
+        }
+        if out.len() == start {
+            return false;
+        }
+        out.push(b'\n');
+        true
+    }
+}
+
+/// Replaces all matches of `regex` in the values of the fields matching
+/// `field` by `template`. The template may refer to capture groups (`$1`,
+/// `${name}`); see [Regex::replace_all].
+#[derive(Debug, Clone)]
+pub struct Substitution {
+    pub field: FieldPattern,
+    pub regex: Regex,
+    pub template: Vec<u8>,
+}
+
+/// Applies [Substitution]s to field values. Entries without matches are not
+/// copied.
+#[derive(Debug, Clone, Default)]
+pub struct Substitute {
+    substitutions: Vec<Substitution>,
+}
+
+impl Substitute {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, substitution: Substitution) -> Self {
+        self.substitutions.push(substitution);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.substitutions.is_empty()
+    }
+
+    fn affects(&self, name: &[u8], value: &[u8]) -> bool {
+        self.substitutions
+            .iter()
+            .any(|s| s.field.matches(name) && s.regex.is_match(value))
+    }
+
+    /// If any substitution matches, appends the rewritten `entry` to `out` and
+    /// returns `true`. Otherwise, `out` is left untouched and the entry can be
+    /// used as is. Fields without matches are copied verbatim.
+    pub fn apply(&self, entry: &impl Entry, out: &mut Vec<u8>) -> bool {
+        if !entry
+            .iter()
+            .any(|(name, value, _)| self.affects(name, value))
+        {
+            return false;
+        }
+        for (name, value, typ) in entry.iter() {
+            let mut value = Cow::Borrowed(value);
+            for s in self.substitutions.iter().filter(|s| s.field.matches(name)) {
+                if let Cow::Owned(v) = s.regex.replace_all(&value, &s.template[..]) {
+                    value = Cow::Owned(v);
+                }
+            }
+            write_field(out, name, &value, &typ);
+        }
+        out.push(b'\n');
+        true
+    }
+}
+
+/// Selects entries with a field whose value matches a regular expression.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    pub field: FieldPattern,
+    pub regex: Regex,
+    /// Select the entries that do not match instead.
+    pub invert: bool,
+}
+
+impl Filter {
+    pub fn selects(&self, entry: &impl Entry) -> bool {
+        let matches = entry
+            .iter()
+            .any(|(name, value, _)| self.field.matches(name) && self.regex.is_match(value));
+        matches != self.invert
+    }
+}
+
+impl Transform for Projection {
+    fn apply(&mut self, entry: EntryView<'_>) -> io::Result<TransformResult> {
+        replaced(Projection::apply(self, &entry.entry, entry.out))
+    }
+}
+
+impl Transform for Rewrite {
+    fn apply(&mut self, entry: EntryView<'_>) -> io::Result<TransformResult> {
+        replaced(Rewrite::apply(self, &entry.entry, entry.out))
+    }
+}
+
+impl Transform for Substitute {
+    fn apply(&mut self, entry: EntryView<'_>) -> io::Result<TransformResult> {
+        Ok(match Substitute::apply(self, &entry.entry, entry.out) {
+            true => TransformResult::Replace,
+            false => TransformResult::Keep,
+        })
+    }
+}
+
+impl Transform for Filter {
+    fn apply(&mut self, entry: EntryView<'_>) -> io::Result<TransformResult> {
+        Ok(match self.selects(&entry.entry) {
+            true => TransformResult::Keep,
+            false => TransformResult::Drop,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        generate::EntryGenerator,
+        journald::{Entry, JournalExportRead},
+        testutil::write_string,
+    };
+
+    use regex::bytes::Regex;
+
+    use super::{
+        read_emitted, Chain, EntryView, FieldPattern, Filter, Projection, Rewrite, Substitute,
+        Substitution, Transform, TransformResult,
+    };
+
+    fn project(stream: &[u8], projection: &Projection) -> Vec<u8> {
+        let mut jreader = JournalExportRead::new(stream);
+        let mut out = vec![];
+        while jreader.parse_next().unwrap().is_some() {
+            projection.apply(&jreader.get_entry(), &mut out);
+        }
+        out
+    }
+
+    fn names(stream: &[u8]) -> Vec<Vec<u8>> {
+        let mut jreader = JournalExportRead::new(stream);
+        jreader.parse_next().unwrap();
+        let e = jreader.get_entry();
+        e.iter().map(|(name, _, _)| name.to_vec()).collect()
+    }
+
+    #[test]
+    fn keeps_and_drops_fields() {
+        let stream = EntryGenerator::new(1).generate(20);
+        let keep = Projection::Keep(vec![
+            FieldPattern::new("MESSAGE"),
+            FieldPattern::new("_SYSTEMD_*"),
+        ]);
+        let kept = project(&stream, &keep);
+        assert_eq!(
+            names(&kept),
+            [
+                &b"__CURSOR"[..],
+                b"__REALTIME_TIMESTAMP",
+                b"__MONOTONIC_TIMESTAMP",
+                b"__SEQNUM",
+                b"__SEQNUM_ID",
+                b"_SYSTEMD_UNIT",
+                b"MESSAGE"
+            ]
+        );
+
+        let drop = Projection::Drop(vec![FieldPattern::new("_*"), FieldPattern::new("PRIORITY")]);
+        let dropped = project(&stream, &drop);
+        assert!(names(&dropped)
+            .iter()
+            .all(|n| !n.starts_with(b"_") && n != b"PRIORITY"));
+
+        // Dropping everything but the message field retains its content,
+        // including multi-line (binary) messages.
+        let messages = |s: &[u8]| {
+            let mut jreader = JournalExportRead::new(s);
+            let mut messages = vec![];
+            while jreader.parse_next().unwrap().is_some() {
+                messages.push(jreader.get_entry().get(b"MESSAGE").unwrap().to_vec());
+            }
+            messages
+        };
+        let only_message = Projection::Drop(vec![
+            FieldPattern::new("_*"),
+            FieldPattern::new("P*"),
+            FieldPattern::new("S*"),
+        ]);
+        assert_eq!(
+            messages(&project(&stream, &only_message)),
+            messages(&stream)
+        );
+        assert!(project(&stream, &Projection::Keep(vec![])).len() < stream.len());
+        assert!(project(&stream, &Projection::Drop(vec![FieldPattern::new("*")])).is_empty());
+    }
+
+    #[test]
+    fn chains_transforms() {
+        let mut stream = vec![];
+        for (priority, message) in [("3", "disk full"), ("6", "ok"), ("3", "fine")] {
+            write_string(&mut stream, "PRIORITY", priority);
+            write_string(&mut stream, "MESSAGE", message);
+            stream.push(b'\n');
+        }
+        let mut chain = Chain::new()
+            .with(Filter {
+                field: FieldPattern::new("PRIORITY"),
+                regex: Regex::new("^[0-3]$").unwrap(),
+                invert: false,
+            })
+            .with(Substitute::new().with(Substitution {
+                field: FieldPattern::new("MESSAGE"),
+                regex: Regex::new("full").unwrap(),
+                template: b"FULL".to_vec(),
+            }));
+        let mut jreader = JournalExportRead::new(&stream[..]);
+        let mut results = vec![];
+        let mut out = vec![];
+        while jreader.parse_next().unwrap().is_some() {
+            out.clear();
+            let result = chain
+                .apply(EntryView::new(&jreader.get_entry(), 0, &mut out))
+                .unwrap();
+            results.push((result, out.clone()));
+        }
+        assert_eq!(
+            results,
+            [
+                (
+                    TransformResult::Replace,
+                    b"PRIORITY=3\nMESSAGE=disk FULL\n\n".to_vec()
+                ),
+                (TransformResult::Drop, vec![]),
+                (TransformResult::Keep, vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn chains_entries_over_the_default_limits() {
+        let mut stream = vec![];
+        for i in 0..600 {
+            let message = match i {
+                0 => "java.lang.Exception: boom".to_owned(),
+                i => format!("\tat com.example.Frame{}.run(Frame.java:{})", i, i),
+            };
+            write_string(&mut stream, "__REALTIME_TIMESTAMP", (100 + i).to_string());
+            write_string(&mut stream, "_PID", "1");
+            write_string(&mut stream, "MESSAGE", message);
+            stream.push(b'\n');
+        }
+        let mut chain = Chain::new()
+            .with(crate::reassemble::Reassemble::new())
+            .with(Substitute::new().with(Substitution {
+                field: FieldPattern::new("MESSAGE"),
+                regex: Regex::new("boom").unwrap(),
+                template: b"BOOM".to_vec(),
+            }));
+        let mut jreader = JournalExportRead::new(&stream[..]);
+        let mut out = vec![];
+        while jreader.parse_next().unwrap().is_some() {
+            let result = chain.apply(EntryView::new(&jreader.get_entry(), 0, &mut out));
+            assert_eq!(result.unwrap(), TransformResult::Replace);
+        }
+        chain.finish(&mut out).unwrap();
+
+        let mut jreader = read_emitted(&out);
+        jreader.parse_next().unwrap().unwrap();
+        let message = jreader.get_entry().get(b"MESSAGE").unwrap().to_vec();
+        assert!(message.len() > 12 * 1024);
+        assert!(message.starts_with(b"java.lang.Exception: BOOM\n\tat com.example.Frame1."));
+        assert!(jreader.parse_next().unwrap().is_none());
+    }
+
+    #[test]
+    fn renames_and_injects_fields() {
+        let stream = EntryGenerator::new(2).generate(5);
+        let rewrite = Rewrite::new()
+            .with_rename("_HOSTNAME", "HOST")
+            .with_rename("SYSLOG_IDENTIFIER", "_COMM")
+            .with_field("ENVIRONMENT", "prod")
+            .with_field("SOURCE_FILE", "a\nb");
+        let mut out = vec![];
+        let mut jreader = JournalExportRead::new(&stream[..]);
+        while jreader.parse_next().unwrap().is_some() {
+            assert!(rewrite.apply(&jreader.get_entry(), &mut out));
+        }
+
+        let mut jreader = JournalExportRead::new(&out[..]);
+        let mut count = 0;
+        while jreader.parse_next().unwrap().is_some() {
+            let e = jreader.get_entry();
+            assert_eq!(e.get(b"HOST"), Some(&b"localhost"[..]));
+            assert_eq!(e.get(b"_HOSTNAME"), None);
+            assert_eq!(e.iter().filter(|(n, _, _)| *n == b"_COMM").count(), 2);
+            assert_eq!(e.get(b"ENVIRONMENT"), Some(&b"prod"[..]));
+            assert_eq!(e.get(b"SOURCE_FILE"), Some(&b"a\nb"[..]));
+            count += 1;
+        }
+        assert_eq!(count, 5);
+
+        let replace = Rewrite::new().with_field("_HOSTNAME", "other");
+        out.clear();
+        let mut jreader = JournalExportRead::new(&stream[..]);
+        jreader.parse_next().unwrap();
+        replace.apply(&jreader.get_entry(), &mut out);
+        let mut jreader = JournalExportRead::new(&out[..]);
+        jreader.parse_next().unwrap();
+        let e = jreader.get_entry();
+        assert_eq!(e.iter().filter(|(n, _, _)| *n == b"_HOSTNAME").count(), 1);
+        assert_eq!(e.get(b"_HOSTNAME"), Some(&b"other"[..]));
+    }
+
+    #[test]
+    fn substitutes_values() {
+        let mut stream = vec![];
+        write_string(&mut stream, "MESSAGE", "\x1b[1;31merror\x1b[0m: disk full");
+        write_string(&mut stream, "SYSLOG_TIMESTAMP", "2024-01-02T03:04:05Z");
+        write_string(&mut stream, "OTHER", "\x1b[0m");
+        stream.push(b'\n');
+        write_string(&mut stream, "MESSAGE", "plain");
+        stream.push(b'\n');
+
+        let substitute = Substitute::new()
+            .with(Substitution {
+                field: FieldPattern::new("MESSAGE"),
+                regex: Regex::new(r"\x1b\[[0-9;]*m").unwrap(),
+                template: b"".to_vec(),
+            })
+            .with(Substitution {
+                field: FieldPattern::new("SYSLOG_*"),
+                regex: Regex::new(r"^(\d{4})-(\d{2})-(\d{2})T([0-9:]+)Z$").unwrap(),
+                template: b"$1$2$3 $4".to_vec(),
+            });
+        let mut jreader = JournalExportRead::new(&stream[..]);
+        let mut out = vec![];
+        jreader.parse_next().unwrap();
+        assert!(substitute.apply(&jreader.get_entry(), &mut out));
+        jreader.parse_next().unwrap();
+        assert!(!substitute.apply(&jreader.get_entry(), &mut out));
+
+        let mut expected = vec![];
+        write_string(&mut expected, "MESSAGE", "error: disk full");
+        write_string(&mut expected, "SYSLOG_TIMESTAMP", "20240102 03:04:05");
+        write_string(&mut expected, "OTHER", "\x1b[0m");
+        expected.push(b'\n');
+        assert_eq!(out, expected);
+    }
+}
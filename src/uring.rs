@@ -0,0 +1,195 @@
+//! Read uncompressed export files with io_uring.
+//!
+//! [JournalExportUringRead] parses like [JournalExportRead], but reads the
+//! file with io_uring straight into the buffer of the parser, which is
+//! registered with the kernel such that the pages of the buffer are not
+//! mapped anew for every read. The buffer is registered again whenever it
+//! grows. If registering fails, e.g. because of `RLIMIT_MEMLOCK`, the reads
+//! are submitted without registered buffers.
+//!
+//! [JournalExportRead]: crate::journald::JournalExportRead
+
+use std::{fs::File, io, os::fd::AsRawFd, path::Path};
+
+use io_uring::{opcode, types, IoUring};
+
+use crate::{
+    config::JournalExportLimits,
+    journald::{
+        parser::{JournalExportParser, ParseResult},
+        JournalExportReadError, RefEntry,
+    },
+};
+
+pub struct JournalExportUringRead {
+    ring: IoUring,
+    file: File,
+    /// The offset in the file to read from next.
+    offset: u64,
+    /// The address and length of the registered buffer.
+    registered: Option<(usize, usize)>,
+    /// Whether registering failed before.
+    unregistrable: bool,
+    parse_state: JournalExportParser,
+}
+
+impl JournalExportUringRead {
+    /// Reads `file` from `offset`. Fails if io_uring is not available, e.g.
+    /// because it is disabled by a seccomp filter.
+    pub fn new(file: File, offset: u64) -> io::Result<Self> {
+        Self::new_with_limits(JournalExportLimits::default(), file, offset)
+    }
+
+    pub fn new_with_limits(
+        limits: JournalExportLimits,
+        file: File,
+        offset: u64,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            ring: IoUring::new(1)?,
+            file,
+            offset,
+            registered: None,
+            unregistrable: false,
+            parse_state: JournalExportParser::new(limits),
+        })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(File::open(path)?, 0)
+    }
+
+    pub fn parse_next(&mut self) -> Result<Option<()>, JournalExportReadError> {
+        self.parse_state.clear_entry();
+        loop {
+            let (buf, len) = match self.parse_state.parse() {
+                ParseResult::Ok(()) => return Ok(Some(())),
+                ParseResult::Eof => return Ok(None),
+                ParseResult::Err(e) => return Err(e),
+                ParseResult::Underfilled(b) => (b.as_mut_ptr(), b.len()),
+            };
+            match self.read(buf, len) {
+                Ok(n) => self.parse_state.extend(n),
+                Err(e) => return Err(self.parse_state.locate(e.into())),
+            }
+        }
+    }
+
+    /// Reads into the `len` bytes at `buf`, which lie within the buffer of
+    /// the parser.
+    fn read(&mut self, buf: *mut u8, len: usize) -> io::Result<usize> {
+        let within = |(start, n): (usize, usize)| {
+            (start..=start + n).contains(&(buf as usize)) && buf as usize + len <= start + n
+        };
+        if !self.registered.is_some_and(within) && !self.unregistrable {
+            self.register()?;
+        }
+        // Registered buffers are limited to 1 GiB.
+        let len = len.min(1 << 30) as u32;
+        let fd = types::Fd(self.file.as_raw_fd());
+        let read = match self.registered {
+            Some(_) => opcode::ReadFixed::new(fd, buf, len, 0)
+                .offset(self.offset)
+                .build(),
+            None => opcode::Read::new(fd, buf, len).offset(self.offset).build(),
+        };
+        // SAFETY: `buf` points to `len` bytes of the buffer of the parser,
+        // which is neither moved nor accessed until the read completes, and
+        // which is registered if the read is fixed.
+        unsafe { self.ring.submission().push(&read) }
+            .map_err(|_| io::Error::other("io_uring submission queue is full"))?;
+        loop {
+            match self.ring.submit_and_wait(1) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => result?,
+            };
+            if let Some(completion) = self.ring.completion().next() {
+                let n = match completion.result() {
+                    n if n < 0 => return Err(io::Error::from_raw_os_error(-n)),
+                    n => n as usize,
+                };
+                self.offset += n as u64;
+                return Ok(n);
+            }
+        }
+    }
+
+    /// Registers the (moved) buffer of the parser in place of the previous
+    /// one.
+    fn register(&mut self) -> io::Result<()> {
+        let submitter = self.ring.submitter();
+        if self.registered.take().is_some() {
+            submitter.unregister_buffers()?;
+        }
+        let buf = self.parse_state.buffer_mut();
+        let iovec = libc::iovec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
+        };
+        // SAFETY: the buffer outlives the ring, which is dropped first, and
+        // is unregistered before it moves.
+        match unsafe { submitter.register_buffers(&[iovec]) } {
+            Ok(()) => self.registered = Some((iovec.iov_base as usize, iovec.iov_len)),
+            Err(_) => self.unregistrable = true,
+        }
+        Ok(())
+    }
+
+    pub fn get_entry(&self) -> RefEntry<'_> {
+        self.parse_state.get_entry()
+    }
+
+    /// See [JournalExportParser::position].
+    pub fn position(&self) -> usize {
+        self.parse_state.position()
+    }
+
+    /// The total number of bytes that were read from the file.
+    pub fn bytes_read(&self) -> usize {
+        self.parse_state.bytes_read()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::{
+        config::JournalExportLimitsBuilder,
+        journald::{Entry, JournalExportRead},
+        testutil::{write_binary, write_string},
+    };
+
+    use super::JournalExportUringRead;
+
+    #[test]
+    fn reads_like_read() {
+        let mut stream = vec![];
+        for i in 0..2000 {
+            write_string(&mut stream, "MESSAGE", format!("entry {}", i));
+            write_binary(&mut stream, "DATA", vec![i as u8; i % 300]);
+            stream.push(b'\n');
+        }
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&stream).unwrap();
+
+        // A small buffer that grows, such that it is registered again.
+        let limits = JournalExportLimitsBuilder::new()
+            .with_initial_buf_size(64)
+            .build();
+        let mut reader =
+            match JournalExportUringRead::new_with_limits(limits, file.reopen().unwrap(), 0) {
+                Ok(reader) => reader,
+                // Not available in this environment.
+                Err(_) => return,
+            };
+        let mut expected = JournalExportRead::new(&stream[..]);
+        while let Some(()) = expected.parse_next().unwrap() {
+            assert_eq!(reader.parse_next().unwrap(), Some(()));
+            let (a, b) = (reader.get_entry(), expected.get_entry());
+            assert_eq!(a.as_bytes(), b.as_bytes());
+        }
+        assert_eq!(reader.parse_next().unwrap(), None);
+        assert_eq!(reader.bytes_read(), stream.len());
+    }
+}
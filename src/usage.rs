@@ -0,0 +1,210 @@
+//! Attribute the size of a journal to units and fields.
+//!
+//! [DiskUsage] sums up the bytes the entries take in the Journal Export
+//! Format per key, by default the unit or else the syslog identifier, and per
+//! field name, counting binary values separately. This shows which service
+//! and which fields make a journal grow.
+
+use std::{collections::BTreeMap, io};
+
+use serde::Serialize;
+
+use crate::{
+    group::{Aggregate, GroupBy, UNIT},
+    journald::{parser::FieldType, Entry},
+};
+
+/// The size of a set of entries.
+#[derive(Serialize, PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Usage {
+    pub entries: u64,
+    pub bytes: u64,
+    /// The bytes of the fields with binary values.
+    pub binary_bytes: u64,
+}
+
+/// The size of a field in the Journal Export Format and whether it is
+/// written as binary.
+fn field_size(name: &[u8], value: &[u8], typ: &FieldType) -> (u64, bool) {
+    let binary = matches!(typ, FieldType::Binary) || value.contains(&b'\n');
+    let framing = match binary {
+        true => 1 + 8 + 1,
+        false => 1 + 1,
+    };
+    ((name.len() + framing + value.len()) as u64, binary)
+}
+
+impl Aggregate for Usage {
+    fn push(&mut self, entry: &impl Entry) -> io::Result<()> {
+        self.entries += 1;
+        self.bytes += entry.len_bytes() as u64;
+        for (name, value, typ) in entry.iter() {
+            if let (size, true) = field_size(name, value, &typ) {
+                self.binary_bytes += size;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct FieldUsage {
+    pub name: String,
+    /// The number of entries with the field.
+    pub entries: u64,
+    pub bytes: u64,
+    /// The number of binary values of the field.
+    pub binary: u64,
+}
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct KeyUsage {
+    /// `None` for the entries without the key.
+    pub key: Option<String>,
+    #[serde(flatten)]
+    pub usage: Usage,
+}
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct UsageReport {
+    pub total: Usage,
+    /// Ordered by size, largest first.
+    pub keys: Vec<KeyUsage>,
+    /// Ordered by size, largest first.
+    pub fields: Vec<FieldUsage>,
+}
+
+type NewUsage = fn(Option<&[u8]>) -> io::Result<Usage>;
+
+pub struct DiskUsage {
+    total: Usage,
+    keys: GroupBy<Usage, NewUsage>,
+    fields: BTreeMap<Vec<u8>, FieldUsage>,
+}
+
+impl Default for DiskUsage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DiskUsage {
+    /// Attributes entries to their unit, or else to their syslog identifier.
+    pub fn new() -> Self {
+        Self::by(UNIT).with_fallback("SYSLOG_IDENTIFIER")
+    }
+
+    /// Attributes entries to the value of `field`.
+    pub fn by(field: &str) -> Self {
+        let create: NewUsage = |_| Ok(Usage::default());
+        Self {
+            total: Usage::default(),
+            keys: GroupBy::new(field, create),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    /// Attributes entries without the fields given before to the value of
+    /// `field`.
+    pub fn with_fallback(self, field: &str) -> Self {
+        Self {
+            keys: self.keys.with_fallback(field),
+            ..self
+        }
+    }
+
+    pub fn push(&mut self, entry: &impl Entry) -> io::Result<()> {
+        self.total.push(entry)?;
+        self.keys.push(entry)?;
+        for (name, value, typ) in entry.iter() {
+            let (size, binary) = field_size(name, value, &typ);
+            let field = match self.fields.get_mut(name) {
+                Some(field) => field,
+                None => self
+                    .fields
+                    .entry(name.to_vec())
+                    .or_insert_with(|| FieldUsage {
+                        name: String::from_utf8_lossy(name).into_owned(),
+                        ..FieldUsage::default()
+                    }),
+            };
+            field.entries += 1;
+            field.bytes += size;
+            field.binary += binary as u64;
+        }
+        Ok(())
+    }
+
+    pub fn into_report(self) -> UsageReport {
+        let mut keys: Vec<_> = self
+            .keys
+            .into_groups()
+            .into_iter()
+            .map(|(key, usage)| KeyUsage {
+                key: key.map(|k| String::from_utf8_lossy(&k).into_owned()),
+                usage,
+            })
+            .collect();
+        keys.sort_by_key(|k| std::cmp::Reverse(k.usage.bytes));
+        let mut fields: Vec<_> = self.fields.into_values().collect();
+        fields.sort_by_key(|f| std::cmp::Reverse(f.bytes));
+        UsageReport {
+            total: self.total,
+            keys,
+            fields,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        journald::JournalExportRead,
+        testutil::{write_binary, write_string},
+    };
+
+    use super::DiskUsage;
+
+    #[test]
+    fn attributes_bytes() {
+        let mut stream = vec![];
+        write_string(&mut stream, "_SYSTEMD_UNIT", "a.service");
+        write_string(&mut stream, "MESSAGE", "hello");
+        stream.push(b'\n');
+        write_string(&mut stream, "SYSLOG_IDENTIFIER", "kernel");
+        write_binary(&mut stream, "MESSAGE", "two\nlines");
+        stream.push(b'\n');
+        write_string(&mut stream, "MESSAGE", "orphan");
+        stream.push(b'\n');
+
+        let mut usage = DiskUsage::new();
+        for e in JournalExportRead::new(&stream[..]) {
+            usage.push(&e).unwrap();
+        }
+        let report = usage.into_report();
+        assert_eq!(report.total.entries, 3);
+        assert_eq!(report.total.bytes, stream.len() as u64);
+        // MESSAGE\n, the length, two\nlines\n
+        assert_eq!(report.total.binary_bytes, 8 + 8 + 10);
+
+        let keys: Vec<_> = report
+            .keys
+            .iter()
+            .map(|k| (k.key.as_deref(), k.usage.bytes))
+            .collect();
+        assert_eq!(
+            keys,
+            [(Some("kernel"), 52), (Some("a.service"), 39), (None, 16)]
+        );
+        let message = &report.fields[0];
+        assert_eq!(
+            (
+                message.name.as_str(),
+                message.entries,
+                message.bytes,
+                message.binary
+            ),
+            ("MESSAGE", 3, 14 + 26 + 15, 1)
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//! Count the distinct values of a field.
+//!
+//! [ValueCounter] counts every value of a field exactly, or, for fields with
+//! many values such as message IDs of a large archive, approximately in
+//! bounded memory with the Space-Saving algorithm: it keeps `capacity`
+//! counters, and a value that is not counted yet takes over the smallest
+//! counter, inheriting its count as the possible overestimation. Every value
+//! that makes up more than `1 / capacity` of all values is guaranteed to be
+//! among the counters.
+
+use std::collections::{BTreeSet, HashMap};
+
+use serde::Serialize;
+
+use crate::{format::BinaryRendering, journald::Entry};
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct ValueCount {
+    /// Bytes that are not valid UTF-8 as `\xNN`.
+    pub value: String,
+    pub count: u64,
+    /// By how much `count` may be too large; 0 if counted exactly.
+    pub error: u64,
+}
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct ValuesReport {
+    pub field: String,
+    pub entries: u64,
+    /// The number of entries without the field.
+    pub missing: u64,
+    /// The number of distinct values, unless counted approximately.
+    pub distinct: Option<u64>,
+    pub approximate: bool,
+    /// Ordered by count, most first.
+    pub values: Vec<ValueCount>,
+}
+
+/// The counters of the Space-Saving algorithm.
+struct SpaceSaving {
+    capacity: usize,
+    /// `(value, count, error)`.
+    slots: Vec<(Vec<u8>, u64, u64)>,
+    index: HashMap<Vec<u8>, usize>,
+    /// `(count, slot)`, to find the smallest counter.
+    order: BTreeSet<(u64, usize)>,
+}
+
+impl SpaceSaving {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            slots: vec![],
+            index: HashMap::new(),
+            order: BTreeSet::new(),
+        }
+    }
+
+    fn push(&mut self, value: &[u8]) {
+        let slot = match self.index.get(value) {
+            Some(&slot) => slot,
+            None if self.slots.len() < self.capacity => {
+                self.slots.push((value.to_vec(), 0, 0));
+                self.index.insert(value.to_vec(), self.slots.len() - 1);
+                self.order.insert((0, self.slots.len() - 1));
+                self.slots.len() - 1
+            }
+            None => {
+                let &(min, slot) = self.order.first().unwrap();
+                let (old, _, _) =
+                    std::mem::replace(&mut self.slots[slot], (value.to_vec(), min, min));
+                self.index.remove(&old);
+                self.index.insert(value.to_vec(), slot);
+                slot
+            }
+        };
+        let count = &mut self.slots[slot].1;
+        self.order.remove(&(*count, slot));
+        *count += 1;
+        self.order.insert((*count, slot));
+    }
+}
+
+enum Counts {
+    Exact(HashMap<Vec<u8>, u64>),
+    Approximate(SpaceSaving),
+}
+
+pub struct ValueCounter {
+    field: String,
+    counts: Counts,
+    entries: u64,
+    missing: u64,
+}
+
+impl ValueCounter {
+    /// Counts the values of `field` exactly.
+    pub fn new(field: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            counts: Counts::Exact(HashMap::new()),
+            entries: 0,
+            missing: 0,
+        }
+    }
+
+    /// Counts approximately with `capacity` counters.
+    pub fn with_capacity(self, capacity: usize) -> Self {
+        Self {
+            counts: Counts::Approximate(SpaceSaving::new(capacity)),
+            ..self
+        }
+    }
+
+    /// Counts every value of the field in `entry`, also repeated ones.
+    pub fn push(&mut self, entry: &impl Entry) {
+        self.entries += 1;
+        let mut found = false;
+        for (name, value, _) in entry.iter() {
+            if name != self.field.as_bytes() {
+                continue;
+            }
+            found = true;
+            match &mut self.counts {
+                Counts::Exact(counts) => match counts.get_mut(value) {
+                    Some(count) => *count += 1,
+                    None => {
+                        counts.insert(value.to_vec(), 1);
+                    }
+                },
+                Counts::Approximate(sketch) => sketch.push(value),
+            }
+        }
+        self.missing += !found as u64;
+    }
+
+    /// The `top` most frequent values.
+    pub fn into_report(self, top: usize) -> ValuesReport {
+        let (distinct, approximate, values): (_, _, Vec<_>) = match self.counts {
+            Counts::Exact(counts) => (
+                Some(counts.len() as u64),
+                false,
+                counts.into_iter().map(|(v, c)| (v, c, 0)).collect(),
+            ),
+            Counts::Approximate(sketch) => (None, true, sketch.slots),
+        };
+        let mut values: Vec<_> = values
+            .into_iter()
+            .map(|(value, count, error)| ValueCount {
+                value: BinaryRendering::HexEscape
+                    .render(&value)
+                    .unwrap_or_default()
+                    .into_owned(),
+                count,
+                error,
+            })
+            .collect();
+        values.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+        values.truncate(top);
+        ValuesReport {
+            field: self.field,
+            entries: self.entries,
+            missing: self.missing,
+            distinct,
+            approximate,
+            values,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{journald::JournalExportRead, testutil::write_string};
+
+    use super::ValueCounter;
+
+    #[test]
+    fn counts_values() {
+        let mut stream = vec![];
+        for i in 0..1000 {
+            let host = match i % 10 {
+                0..=5 => "a".to_string(),
+                6..=7 => "b".to_string(),
+                _ => format!("rare{}", i),
+            };
+            write_string(&mut stream, "_HOSTNAME", host);
+            stream.push(b'\n');
+        }
+        write_string(&mut stream, "MESSAGE", "no host");
+        stream.push(b'\n');
+
+        let count = |counter: ValueCounter| {
+            let mut counter = counter;
+            for e in JournalExportRead::new(&stream[..]) {
+                counter.push(&e);
+            }
+            counter.into_report(2)
+        };
+        let exact = count(ValueCounter::new("_HOSTNAME"));
+        assert_eq!((exact.entries, exact.missing), (1001, 1));
+        assert_eq!(exact.distinct, Some(202));
+        let top: Vec<_> = exact
+            .values
+            .iter()
+            .map(|v| (v.value.as_str(), v.count, v.error))
+            .collect();
+        assert_eq!(top, [("a", 600, 0), ("b", 200, 0)]);
+
+        let approximate = count(ValueCounter::new("_HOSTNAME").with_capacity(10));
+        assert!(approximate.approximate);
+        assert_eq!(approximate.distinct, None);
+        let top: Vec<_> = approximate.values.iter().map(|v| &v.value).collect();
+        assert_eq!(top, ["a", "b"]);
+        for v in approximate.values {
+            let exact = match v.value.as_str() {
+                "a" => 600,
+                _ => 200,
+            };
+            assert!(v.count >= exact && v.count - v.error <= exact);
+        }
+    }
+}
@@ -0,0 +1,537 @@
+//! Explore export files interactively in the terminal.
+//!
+//! [run] shows the entries of a [MultiRead] in the short format of
+//! `journalctl`. Entries are loaded in batches between key presses, such that
+//! the first screen appears immediately and the viewer stays responsive while
+//! a large export is read. [ViewState] holds the loaded entries and shows
+//! those matching a [ViewFilter]. The keys are:
+//!
+//! - `/`: filter by `NAME=VALUE` terms and words of the message, as typed,
+//! - `p`: lower the maximum priority shown; `P` shows all priorities again,
+//! - `[` and `]`: only show entries since or until the selected one,
+//! - `c`: clear all filters,
+//! - `Enter`: show the fields of the selected entry,
+//! - `Space`: mark the selected entry,
+//! - `w`: write the marked entries, or all shown if none are marked, to a
+//!   file in the Journal Export Format,
+//! - `q`: quit.
+
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    time::Duration,
+};
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Paragraph, Wrap},
+    DefaultTerminal, Frame,
+};
+
+use crate::{
+    format::{BinaryRendering, EntryFormat, EntryFormatter},
+    journald::{Entry, OwnedEntry},
+    merge::MultiRead,
+};
+
+/// The number of entries loaded between two screen updates.
+const BATCH: usize = 5000;
+
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct ViewFilter {
+    /// Whitespace-separated terms that must all match: `NAME=VALUE` matches
+    /// fields, other terms match words of the message, ignoring case.
+    pub query: String,
+    /// Only show entries up to this `PRIORITY`; entries without a priority
+    /// are shown.
+    pub max_priority: Option<u8>,
+    /// Only show entries with a `__REALTIME_TIMESTAMP` at or after this.
+    pub since: Option<u64>,
+    /// Only show entries with a `__REALTIME_TIMESTAMP` at or before this.
+    pub until: Option<u64>,
+}
+
+impl ViewFilter {
+    pub fn matches(&self, entry: &impl Entry) -> bool {
+        if let Some(max) = self.max_priority {
+            if entry.get_u64(b"PRIORITY").is_some_and(|p| p > max as u64) {
+                return false;
+            }
+        }
+        if self.since.is_some() || self.until.is_some() {
+            let Some(ts) = entry.realtime_timestamp() else {
+                return false;
+            };
+            if self.since.is_some_and(|s| ts < s) || self.until.is_some_and(|u| ts > u) {
+                return false;
+            }
+        }
+        let message = entry
+            .get(b"MESSAGE")
+            .map(|m| String::from_utf8_lossy(m).to_lowercase());
+        self.query
+            .split_whitespace()
+            .all(|term| match term.split_once('=') {
+                Some((name, value)) => entry
+                    .iter()
+                    .any(|(n, v, _)| n == name.as_bytes() && v == value.as_bytes()),
+                None => message
+                    .as_ref()
+                    .is_some_and(|m| m.contains(&term.to_lowercase())),
+            })
+    }
+}
+
+/// The loaded entries, the ones shown and the selection.
+#[derive(Default)]
+pub struct ViewState {
+    entries: Vec<OwnedEntry>,
+    filter: ViewFilter,
+    /// The indices of the entries matching the filter.
+    shown: Vec<usize>,
+    /// The position of the selected entry in `shown`.
+    position: usize,
+    marked: BTreeSet<usize>,
+}
+
+impl ViewState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, entry: OwnedEntry) {
+        if self.filter.matches(&entry) {
+            self.shown.push(self.entries.len());
+        }
+        self.entries.push(entry);
+    }
+
+    /// The number of loaded entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entry(&self, index: usize) -> &OwnedEntry {
+        &self.entries[index]
+    }
+
+    /// The indices of the shown entries.
+    pub fn shown(&self) -> &[usize] {
+        &self.shown
+    }
+
+    pub fn filter(&self) -> &ViewFilter {
+        &self.filter
+    }
+
+    /// Replaces the filter; the selection moves to the first shown entry at
+    /// or after the one selected before.
+    pub fn set_filter(&mut self, filter: ViewFilter) {
+        let selected = self.selected();
+        self.filter = filter;
+        self.shown = (0..self.entries.len())
+            .filter(|&i| self.filter.matches(&self.entries[i]))
+            .collect();
+        let position = selected.map_or(0, |s| self.shown.partition_point(|&i| i < s));
+        self.select(position);
+    }
+
+    /// The position of the selected entry among the shown ones.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The index of the selected entry.
+    pub fn selected(&self) -> Option<usize> {
+        self.shown.get(self.position).copied()
+    }
+
+    /// Selects the shown entry at `position`, or the last one.
+    pub fn select(&mut self, position: usize) {
+        self.position = position.min(self.shown.len().saturating_sub(1));
+    }
+
+    pub fn move_by(&mut self, delta: isize) {
+        self.select(self.position.saturating_add_signed(delta));
+    }
+
+    pub fn toggle_mark(&mut self) {
+        if let Some(index) = self.selected() {
+            if !self.marked.remove(&index) {
+                self.marked.insert(index);
+            }
+        }
+    }
+
+    pub fn is_marked(&self, index: usize) -> bool {
+        self.marked.contains(&index)
+    }
+
+    /// The number of marked entries.
+    pub fn marked(&self) -> usize {
+        self.marked.len()
+    }
+
+    /// Writes the marked entries, or all shown ones if none are marked, in
+    /// the Journal Export Format. Returns the number of entries written.
+    pub fn write_selection(&self, out: &mut impl Write) -> io::Result<usize> {
+        let indices: Vec<usize> = if self.marked.is_empty() {
+            self.shown.clone()
+        } else {
+            self.marked.iter().copied().collect()
+        };
+        for &i in indices.iter() {
+            out.write_all(self.entries[i].as_bytes())?;
+        }
+        Ok(indices.len())
+    }
+}
+
+enum Mode {
+    Normal,
+    Filter,
+    Save(String),
+}
+
+struct App {
+    state: ViewState,
+    mode: Mode,
+    details: bool,
+    loading: bool,
+    /// The position of the first shown entry on screen.
+    offset: usize,
+    /// The number of entries on screen.
+    page: usize,
+    message: Option<String>,
+    formatter: EntryFormatter,
+}
+
+/// Shows the entries of `reader` until the user quits.
+pub fn run<R: Read>(reader: &mut MultiRead<R>) -> io::Result<()> {
+    let mut terminal = ratatui::try_init()?;
+    let mut app = App {
+        state: ViewState::new(),
+        mode: Mode::Normal,
+        details: false,
+        loading: true,
+        offset: 0,
+        page: 1,
+        message: None,
+        formatter: EntryFormatter::new(EntryFormat::Short),
+    };
+    let result = app.run(&mut terminal, reader);
+    ratatui::restore();
+    result
+}
+
+impl App {
+    fn run<R: Read>(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        reader: &mut MultiRead<R>,
+    ) -> io::Result<()> {
+        loop {
+            if self.loading {
+                self.load(reader);
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+            let timeout = if self.loading {
+                Duration::ZERO
+            } else {
+                Duration::from_millis(250)
+            };
+            if event::poll(timeout)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press && !self.handle_key(key) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    fn load<R: Read>(&mut self, reader: &mut MultiRead<R>) {
+        for _ in 0..BATCH {
+            match reader.parse_next() {
+                Ok(Some(())) => self.state.push(reader.get_entry().to_owned()),
+                Ok(None) => {
+                    self.loading = false;
+                    return;
+                }
+                Err(e) => {
+                    self.loading = false;
+                    self.message = Some(format!("stopped loading: {}", e));
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Returns false if the user quits.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        match &mut self.mode {
+            Mode::Filter => {
+                let mut filter = self.state.filter().clone();
+                match key.code {
+                    KeyCode::Enter | KeyCode::Esc => self.mode = Mode::Normal,
+                    KeyCode::Backspace => {
+                        filter.query.pop();
+                    }
+                    KeyCode::Char(c) => filter.query.push(c),
+                    _ => (),
+                }
+                if filter != *self.state.filter() {
+                    self.state.set_filter(filter);
+                }
+            }
+            Mode::Save(path) => match key.code {
+                KeyCode::Enter => {
+                    let path = std::mem::take(path);
+                    self.message = Some(match self.save(&path) {
+                        Ok(n) => format!("wrote {} entries to {}", n, path),
+                        Err(e) => format!("{}: {}", path, e),
+                    });
+                    self.mode = Mode::Normal;
+                }
+                KeyCode::Esc => self.mode = Mode::Normal,
+                KeyCode::Backspace => {
+                    path.pop();
+                }
+                KeyCode::Char(c) => path.push(c),
+                _ => (),
+            },
+            Mode::Normal => {
+                self.message = None;
+                let page = self.page as isize;
+                let mut filter = self.state.filter().clone();
+                let selected_ts = self
+                    .state
+                    .selected()
+                    .and_then(|i| self.state.entry(i).realtime_timestamp());
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return false,
+                    KeyCode::Down | KeyCode::Char('j') => self.state.move_by(1),
+                    KeyCode::Up | KeyCode::Char('k') => self.state.move_by(-1),
+                    KeyCode::PageDown => self.state.move_by(page),
+                    KeyCode::PageUp => self.state.move_by(-page),
+                    KeyCode::Home | KeyCode::Char('g') => self.state.select(0),
+                    KeyCode::End | KeyCode::Char('G') => self.state.select(usize::MAX),
+                    KeyCode::Enter => self.details = !self.details,
+                    KeyCode::Char('/') => self.mode = Mode::Filter,
+                    KeyCode::Char('w') => self.mode = Mode::Save(String::new()),
+                    KeyCode::Char(' ') => {
+                        self.state.toggle_mark();
+                        self.state.move_by(1);
+                    }
+                    KeyCode::Char('p') => {
+                        filter.max_priority =
+                            Some(filter.max_priority.unwrap_or(8).saturating_sub(1))
+                    }
+                    KeyCode::Char('P') => filter.max_priority = None,
+                    KeyCode::Char('[') => filter.since = selected_ts,
+                    KeyCode::Char(']') => filter.until = selected_ts,
+                    KeyCode::Char('c') => filter = ViewFilter::default(),
+                    _ => (),
+                }
+                if filter != *self.state.filter() {
+                    self.state.set_filter(filter);
+                }
+            }
+        }
+        true
+    }
+
+    fn save(&self, path: &str) -> io::Result<usize> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let n = self.state.write_selection(&mut out)?;
+        out.flush()?;
+        Ok(n)
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [list, details] = if self.details {
+            Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(main)
+        } else {
+            [main, Default::default()]
+        };
+
+        self.page = (list.height as usize).max(1);
+        let position = self.state.position();
+        if position < self.offset {
+            self.offset = position;
+        } else if position >= self.offset + self.page {
+            self.offset = position + 1 - self.page;
+        }
+        let shown = self.state.shown();
+        let end = shown.len().min(self.offset + self.page);
+        let lines: Vec<Line> = (self.offset.min(end)..end)
+            .map(|p| {
+                let index = shown[p];
+                let entry = self.state.entry(index);
+                let mut text = vec![];
+                let _ = self.formatter.write(&mut text, entry);
+                text.pop();
+                let mark = if self.state.is_marked(index) {
+                    "* "
+                } else {
+                    "  "
+                };
+                let mut style = priority_style(entry);
+                if p == position {
+                    style = style.add_modifier(Modifier::REVERSED);
+                }
+                Line::styled(format!("{}{}", mark, String::from_utf8_lossy(&text)), style)
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(lines), list);
+
+        if self.details {
+            let lines: Vec<Line> = self
+                .state
+                .selected()
+                .map(|i| self.state.entry(i))
+                .into_iter()
+                .flat_map(|e| e.iter())
+                .filter_map(|(name, value, _)| {
+                    let value = BinaryRendering::HexEscape.render(value)?;
+                    Some(Line::from(format!(
+                        "{}={}",
+                        String::from_utf8_lossy(name),
+                        value
+                    )))
+                })
+                .collect();
+            let block = Block::bordered().title("Fields");
+            frame.render_widget(
+                Paragraph::new(lines)
+                    .block(block)
+                    .wrap(Wrap { trim: false }),
+                details,
+            );
+        }
+
+        let line = match &self.mode {
+            Mode::Filter => format!("/{}", self.state.filter().query),
+            Mode::Save(path) => format!("write to: {}", path),
+            Mode::Normal => match &self.message {
+                Some(message) => message.clone(),
+                None => self.summary(),
+            },
+        };
+        frame.render_widget(
+            Paragraph::new(line).style(Style::new().add_modifier(Modifier::REVERSED)),
+            status,
+        );
+    }
+
+    fn summary(&self) -> String {
+        let mut s = format!("{}/{} entries", self.state.shown().len(), self.state.len());
+        if self.loading {
+            s.push_str(" (loading)");
+        }
+        if self.state.marked() > 0 {
+            s.push_str(&format!(", {} marked", self.state.marked()));
+        }
+        let filter = self.state.filter();
+        if !filter.query.is_empty() {
+            s.push_str(&format!(" | /{}", filter.query));
+        }
+        if let Some(p) = filter.max_priority {
+            s.push_str(&format!(" | priority <= {}", p));
+        }
+        if filter.since.is_some() || filter.until.is_some() {
+            s.push_str(" | time range");
+        }
+        s.push_str(
+            " | q quit, / filter, p priority, [ ] time, c clear, Enter fields, Space mark, w write",
+        );
+        s
+    }
+}
+
+fn priority_style(entry: &impl Entry) -> Style {
+    match entry.get_u64(b"PRIORITY") {
+        Some(0..=2) => Style::new().fg(Color::Red).add_modifier(Modifier::BOLD),
+        Some(3) => Style::new().fg(Color::Red),
+        Some(4) => Style::new().fg(Color::Yellow),
+        Some(5) => Style::new().add_modifier(Modifier::BOLD),
+        _ => Style::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        journald::{Entry, JournalExportRead},
+        testutil::write_string,
+    };
+
+    use super::{ViewFilter, ViewState};
+
+    #[test]
+    fn filters_and_writes_selection() {
+        let mut stream = vec![];
+        for (ts, priority, unit, message) in [
+            (1, "6", "a.service", "Started"),
+            (2, "3", "b.service", "Disk full"),
+            (3, "4", "a.service", "disk almost full"),
+            (4, "6", "b.service", "Stopped"),
+        ] {
+            write_string(&mut stream, "__REALTIME_TIMESTAMP", ts.to_string());
+            write_string(&mut stream, "PRIORITY", priority);
+            write_string(&mut stream, "_SYSTEMD_UNIT", unit);
+            write_string(&mut stream, "MESSAGE", message);
+            stream.push(b'\n');
+        }
+        let mut state = ViewState::new();
+        for e in JournalExportRead::new(&stream[..]) {
+            state.push(e);
+        }
+
+        state.select(3);
+        state.set_filter(ViewFilter {
+            query: "DISK".into(),
+            ..Default::default()
+        });
+        assert_eq!(state.shown(), [1, 2]);
+        // The selected entry is not shown; the selection stays close to it.
+        assert_eq!(state.selected(), Some(2));
+        state.set_filter(ViewFilter {
+            query: "disk _SYSTEMD_UNIT=a.service".into(),
+            ..Default::default()
+        });
+        assert_eq!(state.shown(), [2]);
+        state.set_filter(ViewFilter {
+            max_priority: Some(4),
+            since: Some(3),
+            ..Default::default()
+        });
+        assert_eq!(state.shown(), [2]);
+        state.set_filter(ViewFilter::default());
+        assert_eq!(state.shown(), [0, 1, 2, 3]);
+
+        let mut out = vec![];
+        assert_eq!(state.write_selection(&mut out).unwrap(), 4);
+        assert_eq!(out, stream);
+        state.select(1);
+        state.toggle_mark();
+        state.move_by(2);
+        state.toggle_mark();
+        let mut out = vec![];
+        assert_eq!(state.write_selection(&mut out).unwrap(), 2);
+        let written: Vec<_> = JournalExportRead::new(&out[..])
+            .map(|e| e.realtime_timestamp().unwrap())
+            .collect();
+        assert_eq!(written, [2, 4]);
+    }
+}
@@ -0,0 +1,140 @@
+//! JavaScript bindings for a journal viewer in the browser.
+//!
+//! Built for `wasm32-unknown-unknown` with the `wasm` feature as a `cdylib`,
+//! which is then processed by `wasm-bindgen`:
+//!
+//! ```sh
+//! cargo rustc --lib --crate-type cdylib --release --target wasm32-unknown-unknown \
+//!     --no-default-features --features wasm
+//! wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/loginus.wasm
+//! ```
+//!
+//! A file that the user dropped into the page is passed in as a `Uint8Array`:
+//!
+//! ```js
+//! import init, { Journal, convert } from "./pkg/loginus.js";
+//! await init();
+//! const bytes = new Uint8Array(await file.arrayBuffer());
+//! const journal = new Journal(bytes);
+//! const first = JSON.parse(journal.entry(0));
+//! const text = convert(bytes, "short", "hex-escape");
+//! ```
+//!
+//! Entries are handed out as JSON in the format of [EntryFormat::Json], such
+//! that no values have to be copied field by field across the boundary.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    format::{BinaryRendering, EntryFormat, EntryFormatter},
+    journald::{JournalExportRead, JournalExportReadError, OwnedEntry},
+};
+
+/// The entries of an export file, kept in wasm memory.
+#[wasm_bindgen]
+pub struct Journal {
+    entries: Vec<OwnedEntry>,
+}
+
+#[wasm_bindgen]
+impl Journal {
+    /// Parses all entries of `data`.
+    #[wasm_bindgen(constructor)]
+    pub fn parse(data: &[u8]) -> Result<Journal, JsError> {
+        Ok(Self {
+            entries: read_entries(data)?,
+        })
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The entry at `index` as a JSON object; values that are not valid UTF-8
+    /// are written as `{"base64": "..."}`.
+    pub fn entry(&self, index: usize) -> Option<String> {
+        let entry = self.entries.get(index)?;
+        let mut out = vec![];
+        EntryFormatter::new(EntryFormat::Json)
+            .with_binary(BinaryRendering::Base64)
+            .write(&mut out, entry)
+            .ok()?;
+        out.pop();
+        String::from_utf8(out).ok()
+    }
+}
+
+/// Converts the export file `data` to `format` (`json`, `logfmt`, `short`
+/// or `table`), rendering binary values as `binary` (`base64`, `hex-escape`,
+/// `lossy` or `skip`).
+#[wasm_bindgen]
+pub fn convert(data: &[u8], format: &str, binary: &str) -> Result<String, JsError> {
+    let format = match format {
+        "json" => EntryFormat::Json,
+        "logfmt" => EntryFormat::Logfmt,
+        "short" => EntryFormat::Short,
+        "table" => EntryFormat::Table,
+        _ => return Err(JsError::new(&format!("unknown format: {}", format))),
+    };
+    let binary = match binary {
+        "base64" => BinaryRendering::Base64,
+        "hex-escape" => BinaryRendering::HexEscape,
+        "lossy" => BinaryRendering::Lossy,
+        "skip" => BinaryRendering::Skip,
+        _ => {
+            return Err(JsError::new(&format!(
+                "unknown binary rendering: {}",
+                binary
+            )))
+        }
+    };
+    let formatter = EntryFormatter::new(format).with_binary(binary);
+    let mut out = vec![];
+    if let Some(header) = formatter.header() {
+        out.extend_from_slice(header.as_bytes());
+        out.push(b'\n');
+    }
+    for entry in read_entries(data)? {
+        formatter.write(&mut out, &entry)?;
+    }
+    Ok(String::from_utf8(out)?)
+}
+
+fn read_entries(data: &[u8]) -> Result<Vec<OwnedEntry>, JournalExportReadError> {
+    let mut reader = JournalExportRead::new(data);
+    let mut entries = vec![];
+    while reader.parse_next()?.is_some() {
+        entries.push(reader.get_entry().to_owned());
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testutil::{write_binary, write_string};
+
+    use super::{convert, Journal};
+
+    #[test]
+    fn parses_and_converts() {
+        let mut stream = vec![];
+        write_string(&mut stream, "MESSAGE", "hello");
+        write_binary(&mut stream, "DATA", b"\xff");
+        stream.push(b'\n');
+        write_string(&mut stream, "MESSAGE", "world");
+        stream.push(b'\n');
+
+        let journal = Journal::parse(&stream).unwrap();
+        assert_eq!(journal.length(), 2);
+        assert_eq!(
+            journal.entry(0).unwrap(),
+            r#"{"DATA":{"base64":"/w=="},"MESSAGE":"hello"}"#
+        );
+        assert_eq!(journal.entry(2), None);
+        assert_eq!(
+            convert(&stream, "logfmt", "hex-escape").unwrap(),
+            "MESSAGE=hello DATA=\\xff\nMESSAGE=world\n"
+        );
+    }
+}
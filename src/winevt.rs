@@ -0,0 +1,468 @@
+//! Import Windows event logs.
+//!
+//! [XmlEventRead] converts events in XML, as exported by the Event Viewer
+//! ("Save as XML") or `wevtutil qe /f:xml`, to entries in the Journal Export
+//! Format; [EvtxRead] does the same for binary `.evtx` files. Both implement
+//! [Read], such that [crate::source::open] can hand them to the readers of
+//! [crate::journald] like any export file.
+//!
+//! The fields of the `System` element are mapped as follows:
+//!
+//! | Event                   | Entry                                        |
+//! |-------------------------|----------------------------------------------|
+//! | `TimeCreated`           | `__REALTIME_TIMESTAMP`                       |
+//! | `Computer`              | `_HOSTNAME`                                  |
+//! | `Execution/@ProcessID`  | `_PID`                                       |
+//! | `Level`                 | `PRIORITY` and `WINEVT_LEVEL`                |
+//! | `Provider/@Name`        | `SYSLOG_IDENTIFIER` and `WINEVT_PROVIDER`    |
+//! | `EventID`               | `WINEVT_EVENT_ID`                            |
+//! | `Channel`, `Task`, ...  | `WINEVT_CHANNEL`, `WINEVT_TASK`, ...         |
+//!
+//! The items of `EventData` and `UserData` become `WINEVT_DATA_NAME` fields,
+//! named by their `Name` or element, or numbered if they are unnamed. The
+//! rendered message of the event, if the export includes it, becomes
+//! `MESSAGE`; otherwise, `MESSAGE` names the provider and the event ID,
+//! followed by the data.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, Read},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use quick_xml::{
+    events::{BytesStart, Event},
+    XmlVersion,
+};
+
+use crate::journald::{parser::FieldType, write_field};
+
+/// The `PRIORITY` of an event `Level`. Level 0 (`LogAlways`) is used by
+/// events without a level, e.g. those of the security log.
+fn priority(level: &str) -> Option<u8> {
+    match level {
+        "0" | "4" => Some(6),
+        "1" => Some(2),
+        "2" => Some(3),
+        "3" => Some(4),
+        "5" => Some(7),
+        _ => None,
+    }
+}
+
+/// An event while it is parsed.
+#[derive(Default)]
+struct WinEvent {
+    timestamp: Option<u64>,
+    provider: Option<String>,
+    event_id: Option<String>,
+    level: Option<String>,
+    computer: Option<String>,
+    pid: Option<String>,
+    message: Option<String>,
+    /// The other fields of `System`, already named.
+    system: Vec<(String, String)>,
+    data: Vec<(Option<String>, String)>,
+}
+
+impl WinEvent {
+    fn write(&self, out: &mut Vec<u8>) {
+        let mut field = |name: &str, value: &str| {
+            write_field(out, name.as_bytes(), value.as_bytes(), &FieldType::String)
+        };
+        if let Some(ts) = self.timestamp {
+            field("__REALTIME_TIMESTAMP", &ts.to_string());
+        }
+        if let Some(computer) = &self.computer {
+            field("_HOSTNAME", computer);
+        }
+        if let Some(pid) = &self.pid {
+            field("_PID", pid);
+        }
+        if let Some(priority) = self.level.as_deref().and_then(priority) {
+            field("PRIORITY", &priority.to_string());
+        }
+        if let Some(provider) = &self.provider {
+            field("SYSLOG_IDENTIFIER", provider);
+        }
+        field("MESSAGE", &self.message());
+        if let Some(provider) = &self.provider {
+            field("WINEVT_PROVIDER", provider);
+        }
+        if let Some(id) = &self.event_id {
+            field("WINEVT_EVENT_ID", id);
+        }
+        if let Some(level) = &self.level {
+            field("WINEVT_LEVEL", level);
+        }
+        for (name, value) in self.system.iter() {
+            field(name, value);
+        }
+        for (i, (name, value)) in self.data.iter().enumerate() {
+            let name = match name {
+                Some(name) => format!("WINEVT_DATA_{}", field_name(name)),
+                None => format!("WINEVT_DATA_{}", i + 1),
+            };
+            field(&name, value);
+        }
+        out.push(b'\n');
+    }
+
+    fn message(&self) -> String {
+        if let Some(message) = &self.message {
+            return message.clone();
+        }
+        let mut message = format!(
+            "{} event {}",
+            self.provider.as_deref().unwrap_or("unknown provider"),
+            self.event_id.as_deref().unwrap_or("without ID")
+        );
+        for (i, (name, value)) in self.data.iter().enumerate() {
+            message.push_str(if i == 0 { ": " } else { ", " });
+            if let Some(name) = name {
+                message.push_str(name);
+                message.push('=');
+            }
+            message.push_str(value);
+        }
+        message
+    }
+}
+
+/// Turns the name of an element or data item into the suffix of a field
+/// name, e.g. `TargetUserName` into `TARGETUSERNAME`.
+fn field_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_uppercase(),
+            false => '_',
+        })
+        .collect()
+}
+
+/// Parses `2024-05-01T10:00:00.1234567Z` into microseconds since the epoch.
+fn parse_system_time(s: &str) -> Option<u64> {
+    let time = chrono::DateTime::parse_from_rfc3339(s).ok()?;
+    u64::try_from(time.timestamp_micros()).ok()
+}
+
+fn invalid(e: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// Converts Windows events in XML to entries in the Journal Export Format.
+/// The `Event` elements may be wrapped in another element, such as `Events`.
+pub struct XmlEventRead<R> {
+    reader: quick_xml::Reader<R>,
+    xml: Vec<u8>,
+    /// The elements enclosing the current one within the event and whether
+    /// they contain other elements.
+    stack: Vec<(String, bool)>,
+    text: String,
+    data_name: Option<String>,
+    event: Option<WinEvent>,
+    /// Converted entries that were not read yet.
+    out: Vec<u8>,
+    pos: usize,
+    events: usize,
+}
+
+impl<R: BufRead> XmlEventRead<R> {
+    pub fn new(read: R) -> Self {
+        Self {
+            reader: quick_xml::Reader::from_reader(read),
+            xml: vec![],
+            stack: vec![],
+            text: String::new(),
+            data_name: None,
+            event: None,
+            out: vec![],
+            pos: 0,
+            events: 0,
+        }
+    }
+
+    /// The number of events converted so far.
+    pub fn events(&self) -> usize {
+        self.events
+    }
+
+    /// Converts the next event into `self.out`. Returns `false` at the end
+    /// of the input.
+    fn convert_next(&mut self) -> io::Result<bool> {
+        loop {
+            self.xml.clear();
+            let mut ended = false;
+            let event = self
+                .reader
+                .read_event_into(&mut self.xml)
+                .map_err(|e| invalid(format!("at {}: {}", self.reader.error_position(), e)))?;
+            match event {
+                Event::Start(e) => {
+                    let name = e.local_name().as_ref().to_string();
+                    if name == "Event" {
+                        self.event = Some(WinEvent::default());
+                        self.stack.clear();
+                    }
+                    if self.event.is_none() {
+                        continue;
+                    }
+                    if let Some(parent) = self.stack.last_mut() {
+                        parent.1 = true;
+                    }
+                    Self::start(self.event.as_mut().unwrap(), &mut self.data_name, &e)?;
+                    self.stack.push((name, false));
+                    self.text.clear();
+                }
+                Event::Empty(e) => {
+                    let Some(event) = self.event.as_mut() else {
+                        continue;
+                    };
+                    if let Some(parent) = self.stack.last_mut() {
+                        parent.1 = true;
+                    }
+                    Self::start(event, &mut self.data_name, &e)?;
+                    if e.local_name().as_ref() == "Data" {
+                        event.data.push((self.data_name.take(), String::new()));
+                    }
+                }
+                Event::Text(t) => self.text.push_str(&t.xml10_content()),
+                Event::CData(t) => self.text.push_str(&t),
+                Event::GeneralRef(r) => match r.resolve_char_ref().map_err(invalid)? {
+                    Some(c) => self.text.push(c),
+                    None => self.text.push_str(
+                        quick_xml::escape::resolve_predefined_entity(&r).unwrap_or_default(),
+                    ),
+                },
+                Event::End(_) if self.event.is_some() => ended = true,
+                Event::Eof => return Ok(false),
+                _ => {}
+            }
+            if ended && self.end() {
+                self.event.take().unwrap().write(&mut self.out);
+                self.events += 1;
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Handles the attributes of an element.
+    fn start(
+        event: &mut WinEvent,
+        data_name: &mut Option<String>,
+        e: &BytesStart,
+    ) -> io::Result<()> {
+        let element = e.local_name().as_ref().to_string();
+        for attr in e.attributes() {
+            let attr = attr.map_err(invalid)?;
+            let value = attr
+                .normalized_value(XmlVersion::Implicit1_0)
+                .map_err(invalid)?
+                .into_owned();
+            match (element.as_str(), attr.key.local_name().as_ref()) {
+                ("Provider", "Name") => event.provider = Some(value),
+                ("TimeCreated", "SystemTime") => {
+                    event.timestamp = Some(
+                        parse_system_time(&value)
+                            .ok_or_else(|| invalid(format!("invalid SystemTime: {}", value)))?,
+                    )
+                }
+                ("Execution", "ProcessID") => event.pid = Some(value),
+                ("Execution", "ThreadID") => event.system.push(("WINEVT_THREAD_ID".into(), value)),
+                ("Security", "UserID") => event.system.push(("WINEVT_USER_ID".into(), value)),
+                ("Correlation", "ActivityID") => {
+                    event.system.push(("WINEVT_ACTIVITY_ID".into(), value))
+                }
+                ("Data", "Name") => *data_name = Some(value),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles the end of the current element. Returns whether it ends the
+    /// event.
+    fn end(&mut self) -> bool {
+        let Some((name, has_children)) = self.stack.pop() else {
+            return false;
+        };
+        let event = self.event.as_mut().unwrap();
+        let value = self.text.trim().to_string();
+        self.text.clear();
+        let parent = self.stack.last().map(|(n, _)| n.as_str());
+        let in_user_data = self.stack.iter().any(|(n, _)| n == "UserData");
+        match (parent, name.as_str()) {
+            (None, "Event") => return true,
+            (Some("System"), "EventID") => event.event_id = Some(value),
+            (Some("System"), "Level") => event.level = Some(value),
+            (Some("System"), "Computer") => event.computer = Some(value),
+            (Some("System"), "Channel" | "Task" | "Opcode" | "Keywords" | "EventRecordID") => {
+                let name = match name.as_str() {
+                    "EventRecordID" => "RECORD_ID".to_string(),
+                    n => field_name(n),
+                };
+                event.system.push((format!("WINEVT_{}", name), value));
+            }
+            (Some("EventData"), "Data") => event.data.push((self.data_name.take(), value)),
+            (Some("EventData"), "Binary") => event.data.push((Some(name), value)),
+            (Some("RenderingInfo"), "Message") if !value.is_empty() => event.message = Some(value),
+            (Some(_), _) if in_user_data && !has_children => event.data.push((Some(name), value)),
+            _ => {}
+        }
+        false
+    }
+}
+
+impl<R: BufRead> Read for XmlEventRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.out.len() {
+            self.out.clear();
+            self.pos = 0;
+            if !self.convert_next()? {
+                return Ok(0);
+            }
+        }
+        let n = buf.len().min(self.out.len() - self.pos);
+        buf[..n].copy_from_slice(&self.out[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Converts the records of an `.evtx` file to entries in the Journal Export
+/// Format. The records are parsed on a separate thread.
+pub struct EvtxRead {
+    rx: Receiver<io::Result<Vec<u8>>>,
+    entry: Vec<u8>,
+    pos: usize,
+}
+
+impl EvtxRead {
+    /// Starts converting `file`; fails if it is not an EVTX file.
+    pub fn new(file: File) -> io::Result<Self> {
+        let mut parser = evtx::EvtxParser::from_read_seek(file)
+            .map_err(|e| invalid(format!("not an EVTX file: {}", e)))?;
+        let (tx, rx) = mpsc::sync_channel(64);
+        thread::spawn(move || {
+            for record in parser.records() {
+                let entry = record.map_err(invalid).and_then(|record| {
+                    let mut entry = vec![];
+                    XmlEventRead::new(record.data.as_bytes()).read_to_end(&mut entry)?;
+                    Ok(entry)
+                });
+                let failed = entry.is_err();
+                if tx.send(entry).is_err() || failed {
+                    return;
+                }
+            }
+        });
+        Ok(Self {
+            rx,
+            entry: vec![],
+            pos: 0,
+        })
+    }
+}
+
+impl Read for EvtxRead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.entry.len() {
+            match self.rx.recv() {
+                Ok(entry) => {
+                    self.entry = entry?;
+                    self.pos = 0;
+                }
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.entry.len() - self.pos);
+        buf[..n].copy_from_slice(&self.entry[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::journald::{Entry, JournalExportRead};
+
+    use super::XmlEventRead;
+
+    const EVENTS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Events>
+<Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+  <System>
+    <Provider Name="Microsoft-Windows-Security-Auditing" Guid="{54849625-5478-4994-a5ba-3e3b0328c30d}"/>
+    <EventID>4625</EventID>
+    <Level>0</Level>
+    <Task>12544</Task>
+    <TimeCreated SystemTime="2024-05-01T10:00:00.1234567Z"/>
+    <EventRecordID>1042</EventRecordID>
+    <Execution ProcessID="684" ThreadID="1220"/>
+    <Channel>Security</Channel>
+    <Computer>dc01.example.com</Computer>
+  </System>
+  <EventData>
+    <Data Name="TargetUserName">alice</Data>
+    <Data Name="IpAddress">10.0.0.7 &amp; more</Data>
+    <Data>unnamed</Data>
+  </EventData>
+</Event>
+<Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+  <System>
+    <Provider Name="Service Control Manager"/>
+    <EventID Qualifiers="49152">7031</EventID>
+    <Level>2</Level>
+    <TimeCreated SystemTime="2024-05-01T10:00:01Z"/>
+    <Computer>dc01.example.com</Computer>
+  </System>
+  <UserData><Crash xmlns="urn:x"><Service>Spooler</Service></Crash></UserData>
+  <RenderingInfo Culture="en-US">
+    <Message>The Print Spooler service terminated unexpectedly.</Message>
+  </RenderingInfo>
+</Event>
+</Events>"#;
+
+    #[test]
+    fn converts_xml_events() {
+        let mut read = XmlEventRead::new(EVENTS.as_bytes());
+        let mut export = vec![];
+        std::io::copy(&mut read, &mut export).unwrap();
+        assert_eq!(read.events(), 2);
+
+        let entries: Vec<_> = JournalExportRead::new(&export[..]).collect();
+        assert_eq!(entries.len(), 2);
+        let get = |i: usize, name: &str| {
+            entries[i]
+                .get(name.as_bytes())
+                .map(|v| String::from_utf8_lossy(v).into_owned())
+        };
+        assert_eq!(entries[0].realtime_timestamp(), Some(1714557600123456));
+        assert_eq!(get(0, "PRIORITY").as_deref(), Some("6"));
+        assert_eq!(get(0, "_PID").as_deref(), Some("684"));
+        assert_eq!(get(0, "WINEVT_EVENT_ID").as_deref(), Some("4625"));
+        assert_eq!(get(0, "WINEVT_RECORD_ID").as_deref(), Some("1042"));
+        assert_eq!(get(0, "WINEVT_CHANNEL").as_deref(), Some("Security"));
+        assert_eq!(
+            get(0, "WINEVT_DATA_IPADDRESS").as_deref(),
+            Some("10.0.0.7 & more")
+        );
+        assert_eq!(
+            get(0, "MESSAGE").as_deref(),
+            Some(
+                "Microsoft-Windows-Security-Auditing event 4625: \
+                 TargetUserName=alice, IpAddress=10.0.0.7 & more, unnamed"
+            )
+        );
+        assert_eq!(get(0, "WINEVT_DATA_3").as_deref(), Some("unnamed"));
+
+        assert_eq!(get(1, "PRIORITY").as_deref(), Some("3"));
+        assert_eq!(get(1, "WINEVT_EVENT_ID").as_deref(), Some("7031"));
+        assert_eq!(get(1, "WINEVT_DATA_SERVICE").as_deref(), Some("Spooler"));
+        assert_eq!(
+            get(1, "MESSAGE").as_deref(),
+            Some("The Print Spooler service terminated unexpectedly.")
+        );
+    }
+}